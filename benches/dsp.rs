@@ -0,0 +1,319 @@
+//! Benchmarks of the DSP core, run with `cargo bench`. Each benchmark is timed in batches
+//! until enough batches are taken, and the median time an iteration took is reported with
+//! how much audio a second that is. `cargo bench -- goertzel` runs those whose names contain
+//! `goertzel`; `cargo bench --bench dsp -- --save <file>` keeps the medians and `--baseline
+//! <file>` compares with ones kept earlier, to see what a change costs. `--precision` compares
+//! decoding in double and single precision instead.
+
+use bell103_demodulator::assembly::Assembler;
+use bell103_demodulator::backend::{Backend, Goertzel, Goertzel32};
+use bell103_demodulator::demodulator::Demodulator;
+use bell103_demodulator::framing::{self, Framing, StreamReceiver};
+use bell103_demodulator::goertzel::{GoertzelBank, GoertzelFilter, GoertzelPair};
+use bell103_demodulator::modulator::{FskModulator, AMPLITUDE};
+use bell103_demodulator::standard::{Channel, Standard};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Batches timed for each benchmark, the median of which is reported
+const BATCHES: usize = 21;
+/// How long each batch should take, its iterations counted to make it so
+const BATCH_TIME: Duration = Duration::from_millis(25);
+/// Sampling rates the end to end decodes are run at
+const SAMPLING_RATES: [f64; 4] = [8_000.0, 22_050.0, 48_000.0, 96_000.0];
+/// Characters in the message each end to end decode is of
+const MESSAGE: &[u8] = b"The quick brown fox jumps over the lazy dog. 0123456789\r\n";
+const REPEATS: usize = 8;
+/// Frequencies the filter bank searches, 3 Hz apart across the voice band
+const BANK_BINS: usize = 1_000;
+
+struct Bench {
+    filter: Vec<String>,
+    baseline: HashMap<String, f64>,
+    results: Vec<(String, f64)>,
+}
+
+impl Bench {
+    /// Times `run`, counting `samples` samples of audio an iteration at `sampling_rate`, and
+    /// returns the median unless the benchmark's filtered out
+    fn run<F: FnMut()>(
+        &mut self,
+        name: &str,
+        samples: usize,
+        sampling_rate: f64,
+        mut run: F,
+    ) -> Option<f64> {
+        if !self.filter.is_empty() && !self.filter.iter().any(|f| name.contains(f.as_str())) {
+            return None;
+        }
+        // Find how many iterations fill a batch, warming up as it goes
+        let mut iterations = 1;
+        loop {
+            let start = Instant::now();
+            for _ in 0..iterations {
+                run();
+            }
+            if start.elapsed() >= BATCH_TIME / 2 {
+                break;
+            }
+            iterations *= 2;
+        }
+        let mut batches: Vec<f64> = (0..BATCHES)
+            .map(|_| {
+                let start = Instant::now();
+                for _ in 0..iterations {
+                    run();
+                }
+                start.elapsed().as_secs_f64() / iterations as f64
+            })
+            .collect();
+        batches.sort_by(f64::total_cmp);
+        let median = batches[BATCHES / 2];
+        let spread = (batches[BATCHES * 3 / 4] - batches[BATCHES / 4]) / median * 100.0;
+        let realtime = samples as f64 / sampling_rate / median;
+        let mut line = format!(
+            "{:<36} {:>12} ±{:>4.1}%  {:>10.1} Msamples/s  {:>9.0}x real time",
+            name,
+            format_time(median),
+            spread,
+            samples as f64 / median / 1e6,
+            realtime
+        );
+        if let Some(&before) = self.baseline.get(name) {
+            line += &format!("  {:+.1}% on the baseline", (median / before - 1.0) * 100.0);
+        }
+        println!("{}", line);
+        self.results.push((name.to_string(), median));
+        Some(median)
+    }
+}
+
+fn format_time(seconds: f64) -> String {
+    match seconds {
+        s if s < 1e-6 => format!("{:.1} ns", s * 1e9),
+        s if s < 1e-3 => format!("{:.2} µs", s * 1e6),
+        s => format!("{:.2} ms", s * 1e3),
+    }
+}
+
+/// A recording of [`MESSAGE`] repeated on `channel`, between a little idle mark
+fn fixture(channel: Channel, sampling_rate: f64) -> Vec<i16> {
+    let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+    let mut samples = Vec::new();
+    modulator.hold(1, 30.0, &mut samples);
+    for _ in 0..REPEATS {
+        modulator.modulate(&framing::frame(MESSAGE), &mut samples);
+    }
+    modulator.hold(1, 30.0, &mut samples);
+    samples
+}
+
+/// `samples` quietened to a quarter, for room to add roughly Gaussian white noise across the
+/// band, its deviation about `noise` times the signal's peak
+fn noisy(samples: &[i16], noise: f64) -> Vec<i16> {
+    let mut state = 1u32;
+    samples
+        .iter()
+        .map(|&sample| {
+            let white: f64 = (0..4)
+                .map(|_| {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    f64::from(state >> 16) / 32768.0 - 1.0
+                })
+                .sum();
+            (f64::from(sample) / 4.0 + AMPLITUDE / 4.0 * noise * white).round() as i16
+        })
+        .collect()
+}
+
+/// Decodes as the main command does, a bit a block deframed as it's decided
+fn decode_blocks(samples: &[i16], channel: Channel, sampling_rate: f64) -> Vec<u8> {
+    let block_size = (sampling_rate / channel.baud).round() as usize;
+    let demodulator = Demodulator::new(block_size, channel.mark, channel.space, sampling_rate);
+    let mut assembler = Assembler::new(demodulator).unwrap();
+    let mut bytes = Vec::with_capacity(samples.len() / block_size / 10);
+    assembler.push(samples, &mut bytes);
+    assembler.finish(&mut bytes);
+    bytes
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut bench = Bench {
+        filter: Vec::new(),
+        baseline: HashMap::new(),
+        results: Vec::new(),
+    };
+    let mut save = None;
+    let mut precision = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--save" => save = args.next(),
+            "--precision" => precision = true,
+            "--baseline" => {
+                let path = args.next().expect("--baseline needs a file");
+                let kept = fs::read_to_string(&path).expect("couldn't read the baseline");
+                bench.baseline = kept
+                    .lines()
+                    .filter_map(|line| line.split_once('\t'))
+                    .filter_map(|(name, seconds)| Some((name.to_string(), seconds.parse().ok()?)))
+                    .collect();
+            }
+            // Passed by cargo bench
+            "--bench" => {}
+            _ => bench.filter.push(arg),
+        }
+    }
+
+    if precision {
+        compare_precision(&mut bench);
+    } else {
+        dsp(&mut bench);
+    }
+
+    if let Some(path) = save {
+        let kept: String = bench
+            .results
+            .iter()
+            .map(|(name, seconds)| format!("{}\t{}\n", name, seconds))
+            .collect();
+        fs::write(&path, kept).expect("couldn't save the results");
+    }
+}
+
+fn dsp(bench: &mut Bench) {
+    let sampling_rate = 48_000.0;
+    let channel = Standard::Bell103.answer();
+    let block_size = (sampling_rate / channel.baud) as usize;
+    let samples = fixture(channel, sampling_rate);
+    let block = &samples[block_size * 40..block_size * 41];
+
+    let mut filter = GoertzelFilter::new(block_size, channel.mark, sampling_rate);
+    bench.run("goertzel/filter", block_size, sampling_rate, || {
+        filter.process(black_box(block));
+        black_box(filter.get_mag_sq());
+        filter.reset();
+    });
+    let pair = GoertzelPair::new(block_size, [channel.mark, channel.space], sampling_rate);
+    bench.run("goertzel/pair", block_size, sampling_rate, || {
+        black_box(pair.energies(black_box(block)));
+    });
+    // A wideband search: every frequency over a second of audio, a filter at a time and as a bank
+    let second = &samples[..sampling_rate as usize];
+    let frequencies: Vec<f64> = (0..BANK_BINS).map(|i| 300.0 + i as f64 * 3.0).collect();
+    bench.run("goertzel/search", second.len(), sampling_rate, || {
+        for &frequency in &frequencies {
+            let mut filter = GoertzelFilter::new(second.len(), frequency, sampling_rate);
+            filter.process(black_box(second));
+            black_box(filter.get_mag_sq());
+        }
+    });
+    let bank = GoertzelBank::new(frequencies, sampling_rate);
+    bench.run("goertzel/bank", second.len(), sampling_rate, || {
+        black_box(bank.energies(black_box(second), &[(0, second.len())]));
+    });
+    let mut demodulator = Demodulator::new(block_size, channel.mark, channel.space, sampling_rate);
+    bench.run("decision/bit", block_size, sampling_rate, || {
+        black_box(demodulator.bit(black_box(block)));
+    });
+
+    for &rate in &SAMPLING_RATES {
+        for &standard in &[Standard::Bell103, Standard::Bell202] {
+            let channel = standard.answer();
+            let samples = fixture(channel, rate);
+            let expected = MESSAGE.repeat(REPEATS);
+            let name = |kind| format!("decode/{}/{:?}/{}", kind, standard, rate).to_lowercase();
+            // Blocks only stay in step with bits a whole number of samples long
+            if rate % channel.baud == 0.0 {
+                assert_eq!(decode_blocks(&samples, channel, rate), expected);
+            }
+            let (received, _) = framing::receive(&samples, channel, rate, 8);
+            assert_eq!(
+                received.iter().map(|r| r.byte).collect::<Vec<_>>(),
+                expected
+            );
+            bench.run(&name("blocks"), samples.len(), rate, || {
+                black_box(decode_blocks(black_box(&samples), channel, rate));
+            });
+            bench.run(&name("uart"), samples.len(), rate, || {
+                black_box(framing::receive(black_box(&samples), channel, rate, 8));
+            });
+        }
+    }
+}
+
+/// Decodes each fixture, clean and noisy, with the [`Goertzel`] filters in double and single
+/// precision, reporting how much faster single precision is and whatever it decided otherwise
+fn compare_precision(bench: &mut Bench) {
+    for &rate in &SAMPLING_RATES {
+        for &standard in &[Standard::Bell103, Standard::Bell202] {
+            let channel = standard.answer();
+            let clean = fixture(channel, rate);
+            for (noise, samples) in [("clean", noisy(&clean, 0.0)), ("noisy", noisy(&clean, 1.0))] {
+                let name = format!("{:?}/{}/{}", standard, rate, noise).to_lowercase();
+                let block_size = (rate / channel.baud).round() as usize;
+                let decide = |backend: &dyn Backend| {
+                    let detector = backend.detector(channel, block_size, rate).unwrap();
+                    Demodulator::with_detector(block_size, detector).soft_bits(&samples)
+                };
+                let receive = |backend: &dyn Backend| {
+                    let framing = Framing::new(8);
+                    let mut stream =
+                        StreamReceiver::with_backend(channel, rate, framing, backend).unwrap();
+                    let mut bytes = Vec::new();
+                    stream.push_to(&samples, &mut bytes);
+                    bytes
+                };
+                let double = bench.run(
+                    &format!("precision/f64/{}", name),
+                    samples.len(),
+                    rate,
+                    || {
+                        black_box(receive(&Goertzel));
+                    },
+                );
+                let single = bench.run(
+                    &format!("precision/f32/{}", name),
+                    samples.len(),
+                    rate,
+                    || {
+                        black_box(receive(&Goertzel32));
+                    },
+                );
+                let (double, single) = match (double, single) {
+                    (Some(double), Some(single)) => (double, single),
+                    _ => continue,
+                };
+
+                let (soft, soft32) = (decide(&Goertzel), decide(&Goertzel32));
+                let flipped = soft
+                    .iter()
+                    .zip(&soft32)
+                    .filter(|(a, b)| (**a >= 0.0) != (**b >= 0.0))
+                    .count();
+                let largest = soft
+                    .iter()
+                    .zip(&soft32)
+                    .map(|(a, b)| (a - b).abs())
+                    .fold(0.0, f64::max);
+                let (bytes, bytes32) = (receive(&Goertzel), receive(&Goertzel32));
+                let differing = bytes.iter().zip(&bytes32).filter(|(a, b)| a != b).count()
+                    + bytes.len().abs_diff(bytes32.len());
+                println!(
+                    "{:<36} f32 {:.2}x the speed of f64, {} of {} decisions flipped (largest \
+                     difference {:.1e}), {} of {} characters differ",
+                    name,
+                    double / single,
+                    flipped,
+                    soft.len(),
+                    largest,
+                    differing,
+                    bytes.len()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#ifndef BELL103_H
+#define BELL103_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Standards to pass to `bell103_create`
+#define BELL103_STANDARD_BELL103 0
+
+#define BELL103_STANDARD_BELL202 1
+
+#define BELL103_STANDARD_V21 2
+
+#define BELL103_STANDARD_V23 3
+
+// A streaming decoder, opaque to C
+typedef struct Bell103Decoder Bell103Decoder;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a decoder for samples at `sampling_rate`, on the originating channel of
+// `standard` when `origin` is true and the answering one otherwise. Returns null for an
+// unknown standard or a sampling rate outside 8 to 384 kHz.
+Bell103Decoder *bell103_create(double sampling_rate, uint32_t standard, bool origin);
+
+// Demodulates the next `len` samples, returning how many decoded bytes are waiting to be
+// read
+//
+// # Safety
+//
+// `decoder` must come from `bell103_create` and not have been destroyed, and `samples`
+// must point to `len` samples, or be null if `len` is 0.
+size_t bell103_feed(Bell103Decoder *decoder, const int16_t *samples, size_t len);
+
+// Demodulates the next `len` samples between -1.0 and 1.0, as WebAudio and many SDR
+// libraries produce them, returning how many decoded bytes are waiting to be read
+//
+// # Safety
+//
+// `decoder` must come from `bell103_create` and not have been destroyed, and `samples`
+// must point to `len` samples, or be null if `len` is 0.
+size_t bell103_feed_f32(Bell103Decoder *decoder, const float *samples, size_t len);
+
+// Moves up to `capacity` decoded bytes into `buffer`, returning how many it took
+//
+// # Safety
+//
+// `decoder` must come from `bell103_create` and not have been destroyed, and `buffer`
+// must have room for `capacity` bytes.
+size_t bell103_read(Bell103Decoder *decoder, uint8_t *buffer, size_t capacity);
+
+// Frees a decoder, doing nothing for null
+//
+// # Safety
+//
+// `decoder` must come from `bell103_create` and not have been destroyed already.
+void bell103_destroy(Bell103Decoder *decoder);
+
+#if defined(BELL103_WASM)
+// Allocates `len` bytes of the module's memory, aligned for samples, for JavaScript to
+// write samples to and read decoded bytes from. Returns null if there's no room.
+uint8_t *bell103_alloc(size_t len);
+#endif
+
+#if defined(BELL103_WASM)
+// Frees memory from `bell103_alloc`
+//
+// # Safety
+//
+// `buffer` must come from `bell103_alloc(len)` and not have been freed already.
+void bell103_free(uint8_t *buffer, size_t len);
+#endif
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BELL103_H */
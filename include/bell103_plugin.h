@@ -0,0 +1,52 @@
+#ifndef BELL103_PLUGIN_H
+#define BELL103_PLUGIN_H
+
+/* Implemented by demodulator backend plugins, which src/backend.rs loads with --plugin */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// The version of this header, which a plugin's backends give as `abi`
+#define BELL103_PLUGIN_ABI 1
+
+// Tone frequencies in Hz and signalling rate in baud for one direction of a link
+typedef struct Bell103Channel {
+  double mark;
+  double space;
+  double baud;
+} Bell103Channel;
+
+// A way of demodulating. Everything it points to has to stay valid while the plugin is
+// loaded, which is until the program exits.
+typedef struct Bell103Backend {
+  // BELL103_PLUGIN_ABI
+  uint32_t abi;
+  // What --backend chooses it by, which no other backend may have
+  const char *name;
+  const char *description;
+  // The channels of the backend's own standard, which replace --standard and --origin, or
+  // null to decode whichever those choose
+  const Bell103Channel *originate;
+  const Bell103Channel *answer;
+  // Makes a detector deciding windows of `window` samples on `channel`, or returns null if
+  // it can't
+  void *(*create)(const Bell103Channel *channel, size_t window, double sampling_rate);
+  // Returns how much a window of `len` samples, usually `window` of them but fewer at the
+  // end of a recording, sounds like mark (1.0) or space (-1.0)
+  double (*decide)(void *detector, const int16_t *window, size_t len);
+  // Frees a detector from `create`
+  void (*destroy)(void *detector);
+} Bell103Backend;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Exported by a plugin: its backends, `*count` of them
+const Bell103Backend *bell103_backends(size_t *count);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BELL103_PLUGIN_H */
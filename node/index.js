@@ -0,0 +1,36 @@
+// Decodes FSK modem audio in Node.js with the native addon, built with
+// `cargo build --release --features node` and copied here as bell103_demodulator.node.
+"use strict";
+
+const { EventEmitter } = require("events");
+const path = require("path");
+
+const addon = require(process.env.BELL103_ADDON || path.join(__dirname, "bell103_demodulator.node"));
+
+/**
+ * A streaming decoder. Samples are pushed as they arrive, an Int16Array, a Float32Array
+ * or a Buffer of 16-bit little endian PCM, and each push that decodes something emits
+ * `data` with a Buffer of the bytes.
+ */
+class Decoder extends EventEmitter {
+  constructor({ sampleRate, standard = "bell103", origin = false } = {}) {
+    super();
+    this.decoder = addon.create(sampleRate, standard, origin);
+  }
+
+  /** Demodulates the next samples, returning how many bytes they decoded */
+  push(samples) {
+    const data = addon.push(this.decoder, samples);
+    if (data.length > 0) {
+      this.emit("data", data);
+    }
+    return data.length;
+  }
+
+  /** Calls `listener` with each Buffer of bytes decoded */
+  onData(listener) {
+    return this.on("data", listener);
+  }
+}
+
+module.exports = { Decoder };
@@ -0,0 +1,467 @@
+use crate::demodulator::Demodulator;
+use crate::detect::{self, Identified};
+use crate::filter::Biquad;
+use crate::framing;
+use crate::goertzel::GoertzelBank;
+use crate::spectrum;
+use crate::standard::{Channel, Standard};
+use std::fmt;
+
+/// Nearer tones than this can't be told apart over the short decision windows
+const MIN_SHIFT: f64 = 50.0;
+/// The other tone is looked for no further than this from the strongest one
+const MAX_SHIFT: f64 = 1500.0;
+/// The signal's band ends where its power falls to this multiple of the noise floor, or to
+/// this fraction of the peak in a clean recording
+const FLOOR_MARGIN: f64 = 4.0;
+const BAND_EDGE: f64 = 1e-3;
+/// The band-pass filter is this much wider than the band so both tones pass evenly
+const BAND_WIDENING: f64 = 1.5;
+/// Frequencies tried around each rough tone when measuring it
+const SEARCH_STEPS: usize = 40;
+/// Fewer transitions than this don't say enough about the bit timing
+const MIN_TRANSITIONS: usize = 20;
+/// Runs longer than this many bits are left out when refining the bit period
+const MAX_RUN_BITS: f64 = 12.0;
+/// A steady tone at least this many bits long at either end of a recording is the idle mark
+const IDLE_BITS: f64 = 20.0;
+/// Signalling rates a measurement is rounded to when within a few percent
+const COMMON_BAUDS: [f64; 11] = [
+    45.45, 50.0, 75.0, 100.0, 110.0, 150.0, 200.0, 300.0, 600.0, 1200.0, 2400.0,
+];
+const BAUD_TOLERANCE: f64 = 0.03;
+/// Rates up to this are taken to be 5-bit Baudot rather than ASCII
+pub const MAX_BAUDOT_BAUD: f64 = 75.0;
+/// Tones within this distance of a standard's count as matching it
+const STANDARD_TOLERANCE: f64 = 30.0;
+
+/// FSK parameters estimated from a recording of an unknown signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// The tone the line idles on, which asynchronous serial calls mark
+    pub mark: f64,
+    pub space: f64,
+    /// The bit rate measured from the spacing of tone transitions
+    pub measured_baud: f64,
+    /// The common rate nearest the measurement, or the measurement itself when none is close
+    pub baud: f64,
+    pub transitions: usize,
+}
+
+impl Estimate {
+    pub fn shift(&self) -> f64 {
+        (self.space - self.mark).abs()
+    }
+
+    pub fn channel(&self) -> Channel {
+        Channel {
+            mark: self.mark,
+            space: self.space,
+            baud: self.baud,
+        }
+    }
+
+    /// The known standard channel these settings match, if any
+    pub fn standard(&self) -> Option<Identified> {
+        let matches = |channel: Channel| {
+            (channel.mark - self.mark).abs() <= STANDARD_TOLERANCE
+                && (channel.space - self.space).abs() <= STANDARD_TOLERANCE
+                && channel.baud == self.baud
+        };
+        Standard::ALL.iter().find_map(|&standard| {
+            [false, true]
+                .iter()
+                .find(|&&origin| matches(standard.channel(origin)))
+                .map(|&origin| Identified::Modem { standard, origin })
+        })
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Tones: {:.0} Hz mark, {:.0} Hz space, {:.0} Hz shift",
+            self.mark,
+            self.space,
+            self.shift()
+        )?;
+        write!(
+            f,
+            "Baud: {} (measured {:.2} from {} transitions)",
+            self.baud, self.measured_baud, self.transitions
+        )
+    }
+}
+
+/// Finds the band the signal occupies as `(center, bandwidth)`, from the furthest bins either
+/// side of the strongest peak in the spectrum that stand out from the noise floor
+fn find_band(samples: &[i16], sampling_rate: f64) -> Option<(f64, f64)> {
+    let (spectrum, bin_hz) = detect::average_spectrum(samples, sampling_rate);
+    let start = (detect::MIN_TONE / bin_hz) as usize;
+    let end = ((detect::MAX_TONE / bin_hz) as usize).min(spectrum.len());
+    let peak = (start..end).max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())?;
+    if spectrum[peak] == 0.0 {
+        return None;
+    }
+    // The signal covers only a small part of the range, so the median is the noise floor
+    let mut sorted = spectrum[start..end].to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let threshold = (FLOOR_MARGIN * sorted[sorted.len() / 2]).max(spectrum[peak] * BAND_EDGE);
+    let span = (MAX_SHIFT / bin_hz) as usize;
+    let mut above = (peak.saturating_sub(span).max(start)..(peak + span).min(end))
+        .filter(|&i| spectrum[i] > threshold);
+    let low = above.next().unwrap_or(peak) as f64 * bin_hz;
+    let high = above.next_back().unwrap_or(peak) as f64 * bin_hz;
+    Some(((low + high) / 2.0, (high - low).max(MIN_SHIFT)))
+}
+
+/// Times each cycle of the signal from the spacing of its zero crossings, returning the
+/// `(start, period)` of each in samples
+fn frequency_track(samples: &[i16], band: (f64, f64), sampling_rate: f64) -> Vec<(f64, f64)> {
+    // Two sections for steeper skirts, each a little wider than the band
+    let (center, bandwidth) = band;
+    let q = center / (BAND_WIDENING * bandwidth);
+    let mut filters = [
+        Biquad::band_pass(center, q, sampling_rate),
+        Biquad::band_pass(center, q, sampling_rate),
+    ];
+    let filtered: Vec<f64> = samples
+        .iter()
+        .map(|&x| {
+            filters
+                .iter_mut()
+                .fold(f64::from(x), |x, filter| filter.process(x))
+        })
+        .collect();
+    let mut crossings = Vec::new();
+    for (i, pair) in filtered.windows(2).enumerate() {
+        if (pair[0] < 0.0) != (pair[1] < 0.0) {
+            crossings.push(i as f64 + pair[0] / (pair[0] - pair[1]));
+        }
+    }
+    crossings
+        .windows(3)
+        .map(|cycle| (cycle[0], cycle[2] - cycle[0]))
+        .filter(|&(_, period)| {
+            let frequency = sampling_rate / period;
+            (detect::MIN_TONE..=detect::MAX_TONE).contains(&frequency)
+        })
+        .collect()
+}
+
+/// Splits the cycles into the two tones they alternate between, as the mean frequency of
+/// each side of a threshold that settles halfway between them
+fn rough_tones(track: &[(f64, f64)], sampling_rate: f64) -> Option<(f64, f64)> {
+    if track.is_empty() {
+        return None;
+    }
+    let frequency = |period: f64| sampling_rate / period;
+    let mut sorted: Vec<f64> = track.iter().map(|&(_, period)| frequency(period)).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let (mut low, mut high) = (sorted[sorted.len() / 10], sorted[sorted.len() * 9 / 10]);
+    for _ in 0..10 {
+        let threshold = (low + high) / 2.0;
+        let mut sums = [(0.0, 0.0); 2];
+        for &(_, period) in track {
+            let sum = &mut sums[(frequency(period) >= threshold) as usize];
+            sum.0 += frequency(period) * period;
+            sum.1 += period;
+        }
+        if sums[0].1 == 0.0 || sums[1].1 == 0.0 {
+            return None;
+        }
+        low = sums[0].0 / sums[0].1;
+        high = sums[1].0 / sums[1].1;
+    }
+    if high - low < MIN_SHIFT {
+        return None;
+    }
+    Some((low, high))
+}
+
+/// Measures a tone as the frequency with the most energy summed over `segments` of the
+/// recording, searching `span` either side of a rough estimate
+fn measure_tone(
+    samples: &[i16],
+    segments: &[(usize, usize)],
+    rough: f64,
+    span: f64,
+    sampling_rate: f64,
+) -> f64 {
+    if segments.is_empty() {
+        return rough;
+    }
+    let step = 2.0 * span / SEARCH_STEPS as f64;
+    let frequencies = (0..=SEARCH_STEPS).map(|i| rough - span + i as f64 * step);
+    let energies =
+        GoertzelBank::new(frequencies.collect(), sampling_rate).energies(samples, segments);
+    let best = (0..energies.len())
+        .max_by(|&a, &b| energies[a].partial_cmp(&energies[b]).unwrap())
+        .unwrap();
+    rough - span + spectrum::interpolate_peak(&energies, best) * step
+}
+
+/// Splits the recording into the runs between transitions, trimmed by `margin` samples each
+/// end, as `(start, end)` sample ranges of the low and high tones
+fn run_segments(crossings: &[(f64, bool)], margin: f64) -> [Vec<(usize, usize)>; 2] {
+    let mut segments = [Vec::new(), Vec::new()];
+    for run in crossings.windows(2) {
+        let (start, end) = (run[0].0 + margin, run[1].0 - margin);
+        if end - start >= 2.0 {
+            segments[run[0].1 as usize].push((start.round() as usize, end.round() as usize));
+        }
+    }
+    segments
+}
+
+/// Whether the high tone is the one the line idles on at the start or end of the recording,
+/// if either holds a steady tone for a character or more
+fn idle_tone(crossings: &[(f64, bool)], end: f64, period: f64) -> Option<bool> {
+    let (first, last) = (crossings[0], crossings[crossings.len() - 1]);
+    if first.0 >= IDLE_BITS * period {
+        Some(!first.1)
+    } else if end - last.0 >= IDLE_BITS * period {
+        Some(last.1)
+    } else {
+        None
+    }
+}
+
+/// Whether the high tone is mark going by which way round decodes to more printable ASCII.
+/// Text sent without idle time between characters frames as 8N1 either way round, since
+/// inverting it turns each clear top bit and stop bit into another valid frame.
+fn ascii_polarity(
+    samples: &[i16],
+    tones: (f64, f64),
+    baud: f64,
+    sampling_rate: f64,
+) -> Option<bool> {
+    let printable = |mark: f64, space: f64| {
+        let channel = Channel { mark, space, baud };
+        let (received, _) = framing::receive(samples, channel, sampling_rate, 8);
+        received
+            .iter()
+            .filter(|received| matches!(received.byte, b' '..=b'~' | b'\r' | b'\n' | b'\t'))
+            .count()
+    };
+    let (low, high) = tones;
+    let (high_mark, low_mark) = (printable(high, low), printable(low, high));
+    if high_mark == low_mark {
+        None
+    } else {
+        Some(high_mark > low_mark)
+    }
+}
+
+/// Whether the high tone is held for the longest run, for a signal with no idle time to go by
+fn longest_run(crossings: &[(f64, bool)], end: f64) -> bool {
+    let (first, last) = (crossings[0], crossings[crossings.len() - 1]);
+    let mut longest = (first.0, !first.1);
+    for (length, high) in crossings
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0, pair[0].1))
+        .chain(Some((end - last.0, last.1)))
+    {
+        if length > longest.0 {
+            longest = (length, high);
+        }
+    }
+    longest.1
+}
+
+/// Refines a rough bit period to the one that best fits every run as a whole number of bits
+fn bit_period(runs: &[f64]) -> f64 {
+    let mut sorted = runs.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    // Single bit runs are the most common, so this lands among them
+    let mut period = sorted[sorted.len() / 4];
+    for _ in 0..3 {
+        let (mut length, mut bits) = (0.0, 0.0);
+        for &run in runs {
+            let count = (run / period).round();
+            if (1.0..=MAX_RUN_BITS).contains(&count) && (run / period - count).abs() < 0.3 {
+                length += run;
+                bits += count;
+            }
+        }
+        if bits > 0.0 {
+            period = length / bits;
+        }
+    }
+    period
+}
+
+/// Estimates the tones and baud rate of an unknown FSK signal. The signal's band is found in
+/// the spectrum and the frequency of each cycle within it gives a first estimate of the two
+/// tones. A demodulator with windows short enough to follow single bits then finds where the
+/// signal switches between them. Runs between transitions are whole numbers of bits, which
+/// gives the bit period, and the tone held longest is taken as the idle mark.
+pub fn estimate(samples: &[i16], sampling_rate: f64) -> Option<Estimate> {
+    let band = find_band(samples, sampling_rate)?;
+    let track = frequency_track(samples, band, sampling_rate);
+    let (low, high) = rough_tones(&track, sampling_rate)?;
+    // Three quarters of the window that puts each tone in the other's null still separates
+    // them well while following bits faster than the shift
+    let block_size = ((0.75 * sampling_rate / (high - low)).round() as usize).max(2);
+    let step = (block_size / 4).max(1);
+    let mut demodulator = Demodulator::new(block_size, high, low, sampling_rate);
+    let soft = demodulator.sliding_soft_bits(samples, step);
+
+    // Interpolated zero crossings in samples from the middle of each window, positive when
+    // switching to the high tone
+    let mut crossings = Vec::new();
+    for (i, pair) in soft.windows(2).enumerate() {
+        if (pair[0] >= 0.0) != (pair[1] >= 0.0) {
+            let offset = pair[0] / (pair[0] - pair[1]);
+            let time = (i as f64 + offset) * step as f64 + block_size as f64 / 2.0;
+            crossings.push((time, pair[1] >= 0.0));
+        }
+    }
+    if crossings.len() < MIN_TRANSITIONS {
+        return None;
+    }
+
+    // Glitches shorter than a decision step are noise rather than bits
+    let runs: Vec<f64> = crossings
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|&run| run >= step as f64)
+        .collect();
+    if runs.is_empty() {
+        return None;
+    }
+    let period = bit_period(&runs);
+    let measured_baud = sampling_rate / period;
+    let baud = COMMON_BAUDS
+        .iter()
+        .copied()
+        .find(|&baud| (measured_baud / baud - 1.0).abs() <= BAUD_TOLERANCE)
+        .unwrap_or(measured_baud);
+
+    // Cycles spanning a change of tone pulled the first estimates inwards, so measure the
+    // tones again away from the transitions
+    let [low_runs, high_runs] = run_segments(&crossings, period / 4.0);
+    let span = (high - low) / 2.0;
+    let low = measure_tone(samples, &low_runs, low, span, sampling_rate);
+    let high = measure_tone(samples, &high_runs, high, span, sampling_rate);
+    let high_is_mark = idle_tone(&crossings, samples.len() as f64, period)
+        .or_else(|| {
+            if baud > MAX_BAUDOT_BAUD {
+                ascii_polarity(samples, (low, high), baud, sampling_rate)
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| longest_run(&crossings, samples.len() as f64));
+    let (mark, space) = if high_is_mark {
+        (high, low)
+    } else {
+        (low, high)
+    };
+
+    Some(Estimate {
+        mark,
+        space,
+        measured_baud,
+        baud,
+        transitions: crossings.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::FskModulator;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    /// Idles on mark, then sends some text as 8N1
+    fn serial_signal(channel: Channel) -> Vec<i16> {
+        let mut bits = vec![1; channel.baud as usize / 4];
+        bits.extend(framing::frame(
+            b"The quick brown fox jumps over the lazy dog 0123456789",
+        ));
+        bits.extend(vec![1; 10]);
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+        let mut samples = Vec::new();
+        modulator.modulate(&bits, &mut samples);
+        samples
+    }
+
+    #[test]
+    fn test_estimate_standards() {
+        for &standard in &Standard::ALL {
+            for &origin in &[false, true] {
+                let channel = standard.channel(origin);
+                let estimate = estimate(&serial_signal(channel), SAMPLING_RATE).unwrap();
+                assert_eq!(estimate.baud, channel.baud, "{:?}", estimate);
+                assert!(
+                    (estimate.mark - channel.mark).abs() < 20.0,
+                    "{:?}",
+                    estimate
+                );
+                assert!(
+                    (estimate.space - channel.space).abs() < 20.0,
+                    "{:?}",
+                    estimate
+                );
+                // Half duplex standards are reported as their only channel
+                let origin = origin && standard.originate() != standard.answer();
+                let identified = Identified::Modem { standard, origin };
+                assert_eq!(estimate.standard(), Some(identified));
+            }
+        }
+    }
+
+    #[test]
+    fn test_estimate_in_noise() {
+        let channel = Standard::Bell103.answer();
+        let mut state = 1u32;
+        let noisy: Vec<i16> = serial_signal(channel)
+            .iter()
+            .map(|&sample| {
+                // Roughly Gaussian white noise stronger than the signal across the full band
+                let noise: f64 = (0..4)
+                    .map(|_| {
+                        state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                        f64::from(state >> 16) / 32768.0 - 1.0
+                    })
+                    .sum();
+                (f64::from(sample) / 2.0 + 6000.0 * noise) as i16
+            })
+            .collect();
+        let estimate = estimate(&noisy, SAMPLING_RATE).unwrap();
+        assert_eq!(estimate.baud, 300.0);
+        assert!(
+            (estimate.mark - channel.mark).abs() < 10.0,
+            "{:?}",
+            estimate
+        );
+        assert!(
+            (estimate.space - channel.space).abs() < 10.0,
+            "{:?}",
+            estimate
+        );
+    }
+
+    #[test]
+    fn test_estimate_unknown_signal() {
+        let channel = Channel {
+            mark: 1615.0,
+            space: 1785.0,
+            baud: 45.45,
+        };
+        let estimate = estimate(&serial_signal(channel), SAMPLING_RATE).unwrap();
+        assert_eq!(estimate.baud, 45.45);
+        assert!((estimate.mark - 1615.0).abs() < 10.0, "{:?}", estimate);
+        assert!((estimate.shift() - 170.0).abs() < 10.0, "{:?}", estimate);
+        assert_eq!(estimate.standard(), None);
+    }
+
+    #[test]
+    fn test_estimate_silence() {
+        assert_eq!(estimate(&[0; 48_000], SAMPLING_RATE), None);
+    }
+}
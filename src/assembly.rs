@@ -0,0 +1,365 @@
+use crate::demodulator::Demodulator;
+use crate::framing::Deframer;
+use std::ops::Range;
+
+/// Takes the characters of a message as they're decoded, so a receiver can hand them to
+/// whatever collects them without building a message of its own
+pub trait MessageWriter {
+    fn write_byte(&mut self, byte: u8);
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+}
+
+/// Keeps the bytes as they were received
+impl MessageWriter for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) {
+        self.push(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// Reads the bytes as 7-bit ASCII
+impl MessageWriter for String {
+    fn write_byte(&mut self, byte: u8) {
+        self.push(char::from(byte & 0x7f));
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.reserve(bytes.len());
+        for &byte in bytes {
+            self.push(char::from(byte & 0x7f));
+        }
+    }
+}
+
+impl<W: MessageWriter + ?Sized> MessageWriter for &mut W {
+    fn write_byte(&mut self, byte: u8) {
+        (**self).write_byte(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        (**self).write_bytes(bytes);
+    }
+}
+
+/// A message of at most `N` bytes kept without allocating, for receivers with no heap to
+/// spare or that mustn't wait on one. Bytes past the end are counted and dropped.
+#[derive(Debug, Clone)]
+pub struct MessageBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    dropped: usize,
+}
+
+impl<const N: usize> Default for MessageBuffer<N> {
+    fn default() -> Self {
+        Self {
+            bytes: [0; N],
+            len: 0,
+            dropped: 0,
+        }
+    }
+}
+
+impl<const N: usize> MessageBuffer<N> {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many bytes didn't fit since the buffer was last cleared
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Empties the buffer for the next message
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+}
+
+impl<const N: usize> MessageWriter for MessageBuffer<N> {
+    fn write_byte(&mut self, byte: u8) {
+        match self.bytes.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.dropped += 1,
+        }
+    }
+}
+
+/// Samples an [`Assembler`] takes in at a time, beyond the window it keeps
+const PIECE: usize = 4096;
+
+/// The samples a bit starting at `start` is decided on, a `window` long centred on the
+/// bit as near as the start of the recording allows. A window longer than the bit overlaps
+/// its neighbours, and one shorter leaves a gap between them.
+pub fn centred(start: usize, bit_length: usize, window: usize) -> Range<usize> {
+    let from = (start + bit_length / 2).saturating_sub(window / 2);
+    from..from + window
+}
+
+/// Decides a bit a block and deframes it as audio arrives in chunks of any size, writing each
+/// character as its frame ends. Only the samples a bit still to be decided needs are kept,
+/// in a buffer allocated once, so decoding allocates nothing of its own once it's made.
+#[derive(Debug)]
+pub struct Assembler {
+    demodulator: Demodulator,
+    deframer: Deframer,
+    bit_length: usize,
+    /// Samples from `kept` on, taken in a piece at a time so it never grows
+    pending: Vec<i16>,
+    capacity: usize,
+    kept: usize,
+    /// Where the next bit to be decided starts
+    next: usize,
+}
+
+impl Assembler {
+    /// Decides each bit over a block of exactly its own samples
+    pub fn new(demodulator: Demodulator) -> Result<Self, String> {
+        let bit_length = demodulator.block_size();
+        Self::with_bit_length(demodulator, bit_length)
+    }
+
+    /// Decides bits `bit_length` samples long over windows of `demodulator`'s block size
+    /// centred on each, longer or shorter than the bits themselves, such as
+    /// [`crate::calibrate`] picks. Neither may be empty, or decoding would never move on
+    /// from the first bit.
+    pub fn with_bit_length(demodulator: Demodulator, bit_length: usize) -> Result<Self, String> {
+        if demodulator.block_size() == 0 {
+            return Err("A bit can't be decided over an empty window".to_string());
+        }
+        if bit_length == 0 {
+            return Err("A bit must be at least one sample long".to_string());
+        }
+        let capacity = demodulator.block_size() + bit_length + PIECE;
+        Ok(Self {
+            demodulator,
+            deframer: Deframer::default(),
+            bit_length,
+            pending: Vec::with_capacity(capacity),
+            capacity,
+            kept: 0,
+            next: 0,
+        })
+    }
+
+    /// Feeds the next samples, writing the characters they complete and returning how many
+    /// there were
+    pub fn push<W: MessageWriter>(&mut self, mut samples: &[i16], writer: &mut W) -> usize {
+        let window = self.demodulator.block_size();
+        let mut written = 0;
+        while !samples.is_empty() {
+            let room = self.capacity - self.pending.len();
+            let (piece, rest) = samples.split_at(room.min(samples.len()));
+            self.pending.extend_from_slice(piece);
+            samples = rest;
+            let end = self.kept + self.pending.len();
+            loop {
+                let decided = centred(self.next, self.bit_length, window);
+                if decided.end > end {
+                    break;
+                }
+                written += self.decide(decided, writer);
+            }
+            let needed = centred(self.next, self.bit_length, window).start - self.kept;
+            let done = needed.min(self.pending.len());
+            self.pending.drain(..done);
+            self.kept += done;
+        }
+        written
+    }
+
+    /// Ends the stream, bits the recording ends partway through deciding on what's left
+    pub fn finish<W: MessageWriter>(&mut self, writer: &mut W) -> usize {
+        let window = self.demodulator.block_size();
+        let end = self.kept + self.pending.len();
+        let mut written = 0;
+        while self.next < end {
+            let decided = centred(self.next, self.bit_length, window);
+            written += self.decide(decided.start.min(end)..decided.end.min(end), writer);
+        }
+        self.pending.clear();
+        self.kept = end;
+        written
+    }
+
+    fn decide<W: MessageWriter>(&mut self, samples: Range<usize>, writer: &mut W) -> usize {
+        let block = &self.pending[samples.start - self.kept..samples.end - self.kept];
+        let bit = self.demodulator.bit(block);
+        self.next += self.bit_length;
+        match self.deframer.push(bit) {
+            Some(byte) => {
+                writer.write_byte(byte);
+                1
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Goertzel;
+    use crate::framing::{frame, Framing, StreamReceiver};
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    /// Counts the allocations each test thread makes, so a decode can be checked for them
+    /// without other tests running alongside getting in the way
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    fn count() {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count();
+            System.alloc(layout)
+        }
+
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            count();
+            System.alloc_zeroed(layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count();
+            System.realloc(ptr, layout, new_size)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations<F: FnOnce()>(run: F) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        run();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    fn recording(message: &[u8]) -> Vec<i16> {
+        let channel = Standard::Bell103.answer();
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+        let mut samples = Vec::new();
+        modulator.hold(1, 20.0, &mut samples);
+        modulator.modulate(&frame(message), &mut samples);
+        modulator.hold(1, 20.0, &mut samples);
+        samples
+    }
+
+    fn bell103() -> Assembler {
+        let channel = Standard::Bell103.answer();
+        Assembler::new(Demodulator::new(
+            160,
+            channel.mark,
+            channel.space,
+            SAMPLING_RATE,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_assembler_refuses_empty_bits() {
+        let channel = Standard::Bell103.answer();
+        let demodulator = |window| Demodulator::new(window, channel.mark, channel.space, 100.0);
+        assert!(Assembler::new(demodulator(0)).is_err());
+        assert!(Assembler::with_bit_length(demodulator(1), 0).is_err());
+        assert!(Assembler::with_bit_length(demodulator(1), 1).is_ok());
+    }
+
+    #[test]
+    fn test_assembler_takes_chunks_of_any_size() {
+        let samples = recording(b"Welcome, login:");
+        let mut whole = Vec::new();
+        let mut assembler = bell103();
+        assembler.push(&samples, &mut whole);
+        assembler.finish(&mut whole);
+        assert_eq!(whole, b"Welcome, login:");
+
+        let mut chunked = String::new();
+        let mut assembler = bell103();
+        let written: usize = samples
+            .chunks(97)
+            .map(|chunk| assembler.push(chunk, &mut chunked))
+            .sum();
+        assert_eq!(written + assembler.finish(&mut chunked), 15);
+        assert_eq!(chunked, "Welcome, login:");
+    }
+
+    #[test]
+    fn test_message_buffer_drops_what_doesnt_fit() {
+        let mut buffer = MessageBuffer::<4>::default();
+        buffer.write_bytes(b"login:");
+        assert_eq!(buffer.as_bytes(), b"logi");
+        assert_eq!(buffer.dropped(), 2);
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.dropped(), 0);
+    }
+
+    #[test]
+    fn test_decoding_doesnt_allocate() {
+        assert_eq!(allocations(|| drop(std::hint::black_box(vec![0u8; 16]))), 1);
+        let samples = recording(b"Welcome, login:");
+        let mut assembler = bell103();
+        let mut message = MessageBuffer::<64>::default();
+        let decoding = allocations(|| {
+            for chunk in samples.chunks(97) {
+                assembler.push(chunk, &mut message);
+            }
+            assembler.finish(&mut message);
+        });
+        assert_eq!(message.as_bytes(), b"Welcome, login:");
+        assert_eq!(decoding, 0);
+
+        // Nor does the UART receiver, its window sized when it's made, whatever the chunks: those
+        // it hasn't room for are taken in pieces
+        let channel = Standard::Bell103.answer();
+        let framing = Framing::new(8);
+        let mut receiver =
+            StreamReceiver::with_capacity(channel, SAMPLING_RATE, framing, &Goertzel, 480).unwrap();
+        let mut text = String::with_capacity(64);
+        let decoding = allocations(|| {
+            for chunk in samples.chunks(480) {
+                receiver.push_to(chunk, &mut text);
+            }
+            receiver.push_to(&samples, &mut text);
+        });
+        assert_eq!(text, "Welcome, login:Welcome, login:");
+        assert_eq!(decoding, 0);
+    }
+}
@@ -0,0 +1,1816 @@
+use crate::detect;
+use crate::ptt;
+use crate::resample::Resampler;
+use crate::standard::Channel;
+use memmap2::Mmap;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, Cursor, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Once;
+
+/// Container and sample encoding used when writing generated audio or reading raw captures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PcmFormat {
+    /// 16-bit mono Microsoft PCM WAV
+    Wav,
+    /// Headerless signed 16-bit little-endian samples
+    S16le,
+    /// Headerless unsigned 8-bit samples centred on 128
+    U8,
+    /// Headerless 32-bit little-endian IEEE float samples in [-1.0, 1.0]
+    F32le,
+}
+
+impl FromStr for PcmFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wav" => Ok(PcmFormat::Wav),
+            "s16le" => Ok(PcmFormat::S16le),
+            "u8" => Ok(PcmFormat::U8),
+            "f32le" => Ok(PcmFormat::F32le),
+            _ => Err(format!(
+                "unknown format `{}`, expected wav, s16le, u8 or f32le",
+                s
+            )),
+        }
+    }
+}
+
+/// Frames read at a time by [`read_wav`], about 1.4 s at 48 kHz
+pub const CHUNK_FRAMES: usize = 1 << 16;
+
+/// Files starting with this are FLAC rather than WAV
+const FLAC_MAGIC: &[u8] = b"fLaC";
+
+/// Files starting with this are in an Ogg container, holding Vorbis or Opus
+const OGG_MAGIC: &[u8] = b"OggS";
+
+/// Bytes peeked to tell containers apart, enough for the form type after a RIFF or IFF size
+const MAGIC_LENGTH: u64 = 12;
+
+/// What the first bytes of a file say it holds
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Container {
+    Wav,
+    Aiff,
+    Flac,
+    Mp3,
+    Ogg,
+    /// Recognised but not readable, named for the error
+    Unsupported(&'static str),
+    /// No known header, so taken to be a headerless capture
+    Raw,
+}
+
+fn detect(magic: &[u8]) -> Container {
+    let id = magic.get(..4).unwrap_or(magic);
+    let form = magic.get(8..12).unwrap_or_default();
+    let unsupported = Container::Unsupported;
+    match id {
+        b"RIFF" if form == b"WAVE" => Container::Wav,
+        b"RIFF" => unsupported("a RIFF file other than WAV, such as AVI"),
+        b"RF64" | b"BW64" => unsupported("a 64-bit RF64 WAV file"),
+        b"FORM" if form == b"AIFF" || form == b"AIFC" => Container::Aiff,
+        b"FORM" => unsupported("an IFF file other than AIFF, such as 8SVX"),
+        _ if id == FLAC_MAGIC => Container::Flac,
+        _ if id == OGG_MAGIC => Container::Ogg,
+        b"caff" => unsupported("a Core Audio (CAF) file"),
+        b"wvpk" => unsupported("a WavPack file"),
+        b"MAC " => unsupported("a Monkey's Audio file"),
+        b".snd" => unsupported("a Sun AU file"),
+        b"MThd" => unsupported("a MIDI file, which holds notes rather than sound"),
+        [0x30, 0x26, 0xb2, 0x75] => unsupported("a WMA file"),
+        [0x1a, 0x45, 0xdf, 0xa3] => unsupported("a Matroska or WebM file"),
+        _ if magic.get(4..8) == Some(b"ftyp") => unsupported("an MP4 or M4A file"),
+        _ if magic.starts_with(b"#!AMR") => unsupported("an AMR file"),
+        _ if is_mp3(magic) => Container::Mp3,
+        _ => Container::Raw,
+    }
+}
+
+/// Reads a WAV file of 8-bit unsigned, 16, 24 or 32-bit signed or 32-bit float samples, scaled
+/// to 16 bits, mixing multi-channel recordings down to mono. Duplex recordings with a
+/// direction on each channel keep both since the directions use separate bands. AIFF files are
+/// read the same way, as are FLAC, MP3 and Ogg Vorbis files when built with the `flac`, `mp3`
+/// and `ogg` features. Recordings made at a rate other than `sampling_rate` are resampled to
+/// it, see [`WavChunks::resample_to`].
+pub fn read_wav<R: Read>(reader: R, sampling_rate: f64) -> io::Result<Vec<i16>> {
+    let mut samples = Vec::new();
+    for chunk in read_wav_chunks(reader, CHUNK_FRAMES)?.resample_to(sampling_rate) {
+        samples.extend(chunk?);
+    }
+    Ok(samples)
+}
+
+/// Like [`read_wav`], but yields the mono samples `frames` at a time so a long recording
+/// never has to fit in memory
+pub fn read_wav_chunks<R: Read>(mut reader: R, frames: usize) -> io::Result<WavChunks<R>> {
+    // Peek at the start to tell the format, then read on as if it hadn't been taken
+    let mut magic = Vec::new();
+    (&mut reader).take(MAGIC_LENGTH).read_to_end(&mut magic)?;
+    let container = detect(&magic);
+    let reader = Cursor::new(magic).chain(reader);
+    match container {
+        Container::Wav => {
+            let reader = hound::WavReader::new(reader).map_err(to_io)?;
+            let spec = reader.spec();
+            let channels = usize::from(spec.channels);
+            let source =
+                if (spec.sample_format, spec.bits_per_sample) == (hound::SampleFormat::Int, 16) {
+                    // hound leaves the reader at the start of the samples
+                    let length = u64::from(reader.len()) * 2;
+                    Source::Pcm16(Pcm16 {
+                        reader: reader.into_inner().take(length),
+                        frame: channels * 2,
+                        bytes: Vec::new(),
+                    })
+                } else {
+                    Source::Wav(reader)
+                };
+            Ok(WavChunks::new(
+                source,
+                channels,
+                Some(spec.sample_rate),
+                frames,
+            ))
+        }
+        Container::Aiff => read_aiff(reader, frames),
+        Container::Flac => read_flac(reader, frames),
+        Container::Mp3 => read_mp3(reader, frames),
+        Container::Ogg => read_ogg(reader, frames),
+        Container::Unsupported(name) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "The input looks like {}, which can't be read, convert it to WAV first",
+                name
+            ),
+        )),
+        Container::Raw => {
+            eprintln!(
+                "Warning: no audio header found, so reading headerless 16-bit mono samples, \
+                 see --raw"
+            );
+            let spec = RawSpec {
+                format: PcmFormat::S16le,
+                endian: Endian::Little,
+                channels: 1,
+                layout: Layout::Interleaved,
+            };
+            let source = Source::Raw(read_raw_chunks(reader, spec, frames)?);
+            Ok(WavChunks::new(source, 1, None, frames))
+        }
+    }
+}
+
+/// Reads AIFF chunks up to the sound data. The COMM chunk has to come first, as every
+/// recorder writes it, since the samples are streamed rather than buffered until it turns up.
+fn read_aiff<R: Read>(mut reader: Peeked<R>, frames: usize) -> io::Result<WavChunks<R>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut header = [0; 12];
+    reader.read_exact(&mut header)?;
+    let compressed = &header[8..] == b"AIFC";
+    let mut format = None;
+    loop {
+        let mut chunk = [0; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = u32::from_be_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        // Chunks are padded to an even length
+        let mut body = (&mut reader).take(u64::from(size) + u64::from(size % 2));
+        match &chunk[..4] {
+            b"COMM" => {
+                let mut comm = Vec::new();
+                body.read_to_end(&mut comm)?;
+                if comm.len() < 18 {
+                    return Err(invalid("the AIFF COMM chunk is cut short".into()));
+                }
+                let channels = usize::from(u16::from_be_bytes([comm[0], comm[1]]));
+                let bits = u16::from_be_bytes([comm[6], comm[7]]);
+                let rate = extended_to_u32(&comm[8..18]);
+                // Plain AIFF is always big-endian integers, AIFF-C names its encoding
+                let encoding = match comm.get(18..22) {
+                    Some(encoding) if compressed => encoding,
+                    _ => b"NONE",
+                };
+                let width = usize::from(bits).div_ceil(8);
+                let sample = match (encoding, bits) {
+                    (b"NONE" | b"twos", 1..=32) => AiffSample::Int {
+                        width,
+                        little: false,
+                    },
+                    (b"sowt", 1..=32) => AiffSample::Int {
+                        width,
+                        little: true,
+                    },
+                    (b"raw ", 8) => AiffSample::Unsigned,
+                    (b"fl32" | b"FL32", _) => AiffSample::Float,
+                    _ => {
+                        return Err(invalid(format!(
+                            "AIFF-C `{}` samples of {} bits aren't supported, expected \
+                             uncompressed PCM",
+                            String::from_utf8_lossy(encoding),
+                            bits
+                        )))
+                    }
+                };
+                if channels == 0 {
+                    return Err(invalid("the AIFF file has no channels".into()));
+                }
+                format = Some((channels, rate, sample));
+            }
+            b"SSND" => {
+                let (channels, rate, sample) = format.ok_or_else(|| {
+                    invalid("the AIFF sound data comes before its COMM chunk".into())
+                })?;
+                let mut offsets = [0; 8];
+                body.read_exact(&mut offsets)?;
+                let offset = u32::from_be_bytes([offsets[0], offsets[1], offsets[2], offsets[3]]);
+                io::copy(&mut (&mut reader).take(u64::from(offset)), &mut io::sink())?;
+                let length = u64::from(size.saturating_sub(8).saturating_sub(offset));
+                let source = Source::Aiff(AiffSource {
+                    reader: reader.take(length),
+                    sample,
+                });
+                return Ok(WavChunks::new(source, channels, Some(rate), frames));
+            }
+            _ => {
+                io::copy(&mut body, &mut io::sink())?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "flac")]
+fn read_flac<R: Read>(reader: Peeked<R>, frames: usize) -> io::Result<WavChunks<R>> {
+    let reader = claxon::FlacReader::new(reader).map_err(flac_to_io)?;
+    let info = reader.streaminfo();
+    let (channels, rate) = (info.channels as usize, info.sample_rate);
+    let source = Source::Flac(FlacSource {
+        reader,
+        bits: info.bits_per_sample,
+        buffer: Vec::new(),
+        pending: Vec::new(),
+    });
+    Ok(WavChunks::new(source, channels, Some(rate), frames))
+}
+
+#[cfg(not(feature = "flac"))]
+fn read_flac<R: Read>(_: Peeked<R>, _: usize) -> io::Result<WavChunks<R>> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "This build can't read FLAC, rebuild with `cargo build --features flac`",
+    ))
+}
+
+/// MP3 files start with an ID3 tag or straight away with the header of an MPEG audio frame
+fn is_mp3(magic: &[u8]) -> bool {
+    match magic {
+        [b'I', b'D', b'3', ..] => true,
+        // The sync bits, then a valid layer, bit rate and sampling rate so that quiet headerless
+        // samples of -1 aren't taken for a frame
+        [0xff, second, third, ..] => {
+            second & 0xe0 == 0xe0 && second & 0x06 != 0 && third >> 4 != 0xf && third & 0x0c != 0x0c
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "mp3")]
+fn read_mp3<R: Read>(reader: Peeked<R>, frames: usize) -> io::Result<WavChunks<R>> {
+    read_compressed(reader, frames, "MP3")
+}
+
+#[cfg(not(feature = "mp3"))]
+fn read_mp3<R: Read>(_: Peeked<R>, _: usize) -> io::Result<WavChunks<R>> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "This build can't read MP3, rebuild with `cargo build --features mp3`",
+    ))
+}
+
+#[cfg(feature = "ogg")]
+fn read_ogg<R: Read>(reader: Peeked<R>, frames: usize) -> io::Result<WavChunks<R>> {
+    read_compressed(reader, frames, "Ogg")
+}
+
+#[cfg(not(feature = "ogg"))]
+fn read_ogg<R: Read>(_: Peeked<R>, _: usize) -> io::Result<WavChunks<R>> {
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "This build can't read Ogg, rebuild with `cargo build --features ogg`",
+    ))
+}
+
+/// Reads a lossy `container` file, MP3 or Ogg, through symphonia
+#[cfg(feature = "symphonia")]
+fn read_compressed<R: Read>(
+    mut reader: Peeked<R>,
+    frames: usize,
+    container: &str,
+) -> io::Result<WavChunks<R>> {
+    use symphonia::core::codecs::{CODEC_TYPE_OPUS, CODEC_TYPE_VORBIS};
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::probe::Hint;
+
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    // The demuxer has to own its input, and a lossy file is small next to the audio it
+    // decodes to
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(data)), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension(&container.to_lowercase());
+    let format = symphonia::default::get_probe()
+        .format(&hint, stream, &Default::default(), &Default::default())
+        .map_err(compressed_to_io)?
+        .format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| invalid(format!("the {} file has no audio", container)))?;
+    let (id, params) = (track.id, track.codec_params.clone());
+    let codec = match params.codec {
+        CODEC_TYPE_VORBIS => "Vorbis",
+        CODEC_TYPE_OPUS => {
+            return Err(invalid(
+                "Opus can't be decoded yet, convert the recording to FLAC or WAV first".into(),
+            ))
+        }
+        _ => container,
+    };
+    let decoder = symphonia::default::get_codecs()
+        .make(&params, &Default::default())
+        .map_err(compressed_to_io)?;
+    eprintln!(
+        "Warning: {} is lossy, so tones may be smeared and bits lost (encoded at {} Hz)",
+        codec,
+        params.sample_rate.unwrap_or_default()
+    );
+    let channels = params.channels.map_or(1, |channels| channels.count());
+    let source = Source::Compressed(CompressedSource {
+        format,
+        decoder,
+        track: id,
+        pending: Vec::new(),
+    });
+    Ok(WavChunks::new(source, channels, params.sample_rate, frames))
+}
+
+/// A reader with the bytes peeked from its start put back
+type Peeked<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+enum Source<R: Read> {
+    Wav(hound::WavReader<Peeked<R>>),
+    Pcm16(Pcm16<R>),
+    Aiff(AiffSource<R>),
+    Raw(RawChunks<Peeked<R>>),
+    #[cfg(feature = "flac")]
+    Flac(FlacSource<R>),
+    #[cfg(feature = "symphonia")]
+    Compressed(CompressedSource),
+}
+
+/// The 16-bit samples of a WAV file read a chunk of bytes at a time, straight from its data
+/// chunk, and reinterpreted as samples, rather than a sample at a time through hound
+struct Pcm16<R: Read> {
+    reader: io::Take<Peeked<R>>,
+    /// Bytes per frame, one sample for each channel
+    frame: usize,
+    bytes: Vec<u8>,
+}
+
+impl<R: Read> Pcm16<R> {
+    /// Reads up to `count` samples, ending at the last whole frame of a file cut short
+    fn read(&mut self, count: usize) -> io::Result<Vec<i16>> {
+        self.bytes.resize(count * 2, 0);
+        let mut filled = 0;
+        while filled < self.bytes.len() {
+            match self.reader.read(&mut self.bytes[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let whole = filled / self.frame * self.frame;
+        Ok(self.bytes[..whole]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect())
+    }
+}
+
+/// Mono samples from a WAV or FLAC file a chunk at a time, see [`read_wav_chunks`]
+pub struct WavChunks<R: Read> {
+    source: Source<R>,
+    channels: usize,
+    /// What the header says, unknown for headerless samples
+    sampling_rate: Option<u32>,
+    frames: usize,
+    mixdown: Mixdown,
+    resampler: Option<Resampler>,
+}
+
+impl<R: Read> WavChunks<R> {
+    fn new(source: Source<R>, channels: usize, sampling_rate: Option<u32>, frames: usize) -> Self {
+        Self {
+            source,
+            channels,
+            sampling_rate,
+            frames,
+            mixdown: Mixdown::Mix,
+            resampler: None,
+        }
+    }
+
+    /// Resamples a recording whose header gives a rate other than `rate`, which is what
+    /// the tones and bit periods are worked out at, saying so on stderr
+    pub fn resample_to(mut self, rate: f64) -> Self {
+        self.resampler = resampler(self.sampling_rate, rate);
+        self
+    }
+
+    /// Makes mono samples from a multi-channel recording as `mixdown` says, rather than
+    /// averaging the channels
+    pub fn mixdown(mut self, mixdown: Mixdown) -> Self {
+        self.mixdown = mixdown;
+        self
+    }
+}
+
+impl<R: Read> Iterator for WavChunks<R> {
+    type Item = io::Result<Vec<i16>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let count = self.frames * self.channels;
+        let samples = match &mut self.source {
+            Source::Wav(reader) => read_samples(reader, count).map_err(to_io),
+            Source::Pcm16(source) => source.read(count),
+            Source::Aiff(source) => source.read(count),
+            // Already mono and a chunk at a time
+            Source::Raw(chunks) => chunks.next().unwrap_or_else(|| Ok(Vec::new())),
+            #[cfg(feature = "flac")]
+            Source::Flac(source) => source.read(count),
+            #[cfg(feature = "symphonia")]
+            Source::Compressed(source) => source.read(count),
+        };
+        match samples {
+            Ok(samples) if samples.is_empty() => None,
+            Ok(samples) => {
+                let mono = select(&mut self.mixdown, samples, self.channels);
+                Some(match &mut self.resampler {
+                    Some(resampler) => mono.map(|mono| resampler.process(&mono)),
+                    None => mono,
+                })
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// A resampler from the rate a header gives to `to`, when they differ. The note is only
+/// printed once, however many times the recording is read.
+fn resampler(from: Option<u32>, to: f64) -> Option<Resampler> {
+    static NOTED: Once = Once::new();
+    let from = f64::from(from?);
+    if (from - to).abs() < f64::EPSILON {
+        return None;
+    }
+    NOTED.call_once(|| {
+        eprintln!(
+            "Resampling the {} Hz recording to {} Hz, the rate it's decoded at (see -s)",
+            from, to
+        )
+    });
+    Some(Resampler::new(from, to))
+}
+
+/// Reads the 80-bit IEEE extended float AIFF gives its sampling rate as
+fn extended_to_u32(bytes: &[u8]) -> u32 {
+    let exponent = i32::from(u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff);
+    let mut mantissa = [0; 8];
+    mantissa.copy_from_slice(&bytes[2..10]);
+    let mantissa = u64::from_be_bytes(mantissa) as f64;
+    (mantissa * 2f64.powi(exponent - 16_383 - 63)).round() as u32
+}
+
+/// How AIFF stores each sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AiffSample {
+    /// Signed integers left-justified in `width` bytes, big-endian unless AIFF-C says `sowt`
+    Int { width: usize, little: bool },
+    /// AIFF-C `raw `, 8-bit samples centred on 128
+    Unsigned,
+    /// AIFF-C `fl32`, big-endian 32-bit IEEE float
+    Float,
+}
+
+/// The sound data of an AIFF file, read straight through
+struct AiffSource<R: Read> {
+    reader: io::Take<Peeked<R>>,
+    sample: AiffSample,
+}
+
+impl<R: Read> AiffSource<R> {
+    /// Reads up to `count` interleaved samples scaled to 16 bits
+    fn read(&mut self, count: usize) -> io::Result<Vec<i16>> {
+        let width = match self.sample {
+            AiffSample::Int { width, .. } => width,
+            AiffSample::Unsigned => 1,
+            AiffSample::Float => 4,
+        };
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take((count * width) as u64)
+            .read_to_end(&mut bytes)?;
+        let samples = bytes.chunks_exact(width).map(|bytes| match self.sample {
+            AiffSample::Int { little, .. } => {
+                let fold = |value: i32, &byte: &u8| value << 8 | i32::from(byte);
+                let value = if little {
+                    bytes.iter().rev().fold(0, fold)
+                } else {
+                    bytes.iter().fold(0, fold)
+                };
+                // Sign extend from the top of the width
+                let bits = 8 * width as u32;
+                scale_int(value << (32 - bits) >> (32 - bits), bits)
+            }
+            AiffSample::Unsigned => (i16::from(bytes[0]) - 128) << 8,
+            AiffSample::Float => {
+                from_float(f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+        });
+        Ok(samples.collect())
+    }
+}
+
+/// FLAC decodes a block of a few thousand frames at a time, so samples beyond a chunk wait
+/// for the next
+#[cfg(feature = "flac")]
+struct FlacSource<R: Read> {
+    reader: claxon::FlacReader<Peeked<R>>,
+    bits: u32,
+    /// Reused for every block
+    buffer: Vec<i32>,
+    /// Interleaved samples decoded but not yet returned
+    pending: Vec<i16>,
+}
+
+#[cfg(feature = "flac")]
+impl<R: Read> FlacSource<R> {
+    /// Reads up to `count` interleaved samples scaled to 16 bits
+    fn read(&mut self, count: usize) -> io::Result<Vec<i16>> {
+        while self.pending.len() < count {
+            let buffer = std::mem::take(&mut self.buffer);
+            let block = match self.reader.blocks().read_next_or_eof(buffer) {
+                Ok(Some(block)) => block,
+                Ok(None) => break,
+                Err(err) => return Err(flac_to_io(err)),
+            };
+            for i in 0..block.duration() {
+                for channel in 0..block.channels() {
+                    let sample = block.sample(channel, i);
+                    self.pending.push(scale_int(sample, self.bits));
+                }
+            }
+            self.buffer = block.into_buffer();
+        }
+        Ok(take_pending(&mut self.pending, count))
+    }
+}
+
+/// MP3 and Vorbis decode a frame of up to a few thousand samples at a time, so samples beyond
+/// a chunk wait for the next
+#[cfg(feature = "symphonia")]
+struct CompressedSource {
+    format: Box<dyn symphonia::core::formats::FormatReader>,
+    decoder: Box<dyn symphonia::core::codecs::Decoder>,
+    track: u32,
+    /// Interleaved samples decoded but not yet returned
+    pending: Vec<i16>,
+}
+
+#[cfg(feature = "symphonia")]
+impl CompressedSource {
+    /// Reads up to `count` interleaved samples
+    fn read(&mut self, count: usize) -> io::Result<Vec<i16>> {
+        use symphonia::core::audio::SampleBuffer;
+        use symphonia::core::errors::Error;
+
+        while self.pending.len() < count {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                // The stream simply ends
+                Err(Error::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(compressed_to_io(err)),
+            };
+            if packet.track_id() != self.track {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let mut buffer =
+                        SampleBuffer::<i16>::new(decoded.capacity() as u64, *decoded.spec());
+                    buffer.copy_interleaved_ref(decoded);
+                    self.pending.extend_from_slice(buffer.samples());
+                }
+                // A corrupt frame only costs its own samples
+                Err(Error::DecodeError(_)) => continue,
+                Err(err) => return Err(compressed_to_io(err)),
+            }
+        }
+        Ok(take_pending(&mut self.pending, count))
+    }
+}
+
+/// Takes up to `count` samples from the front of those decoded ahead
+#[cfg(any(feature = "flac", feature = "symphonia"))]
+fn take_pending(pending: &mut Vec<i16>, count: usize) -> Vec<i16> {
+    let rest = pending.split_off(count.min(pending.len()));
+    std::mem::replace(pending, rest)
+}
+
+/// Scales a signed integer sample of `bits` bits to 16 bits
+fn scale_int(sample: i32, bits: u32) -> i16 {
+    if bits < 16 {
+        (sample << (16 - bits)) as i16
+    } else {
+        (sample >> (bits - 16)) as i16
+    }
+}
+
+/// Reads up to `count` samples, scaling them to 16 bits whatever their format
+fn read_samples<R: Read>(
+    reader: &mut hound::WavReader<R>,
+    count: usize,
+) -> Result<Vec<i16>, hound::Error> {
+    let spec = reader.spec();
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader.samples::<i16>().take(count).collect(),
+        // hound makes 8-bit samples signed, so every width only needs shifting
+        (hound::SampleFormat::Int, bits) => reader
+            .samples::<i32>()
+            .take(count)
+            .map(|sample| sample.map(|sample| scale_int(sample, u32::from(bits))))
+            .collect(),
+        (hound::SampleFormat::Float, _) => reader
+            .samples::<f32>()
+            .take(count)
+            .map(|sample| sample.map(from_float))
+            .collect(),
+    }
+}
+
+/// Scales a float sample in [-1.0, 1.0] to 16 bits, clipping anything beyond
+fn from_float(sample: f32) -> i16 {
+    (sample * 32_767.0) as i16
+}
+
+/// Byte order of headerless samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "little" => Ok(Endian::Little),
+            "big" => Ok(Endian::Big),
+            _ => Err(format!(
+                "unknown byte order `{}`, expected little or big",
+                s
+            )),
+        }
+    }
+}
+
+/// How the channels of a headerless capture are arranged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// One sample of each channel in turn
+    Interleaved,
+    /// All of the first channel, then all of the next, as DSP tools often dump buffers
+    Planar,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interleaved" => Ok(Layout::Interleaved),
+            "planar" => Ok(Layout::Planar),
+            _ => Err(format!(
+                "unknown channel layout `{}`, expected interleaved or planar",
+                s
+            )),
+        }
+    }
+}
+
+/// Everything needed to read a headerless capture, since nothing in the data says
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawSpec {
+    pub format: PcmFormat,
+    pub endian: Endian,
+    pub channels: usize,
+    pub layout: Layout,
+}
+
+/// Reads headerless samples, `frames` at a time, mixing the channels down to mono. A partial
+/// frame at the end is dropped. Planar captures only say where each channel starts once all
+/// of the data is in, so they're read as a single chunk.
+pub fn read_raw_chunks<R: Read>(
+    reader: R,
+    spec: RawSpec,
+    frames: usize,
+) -> io::Result<RawChunks<R>> {
+    let width = match spec.format {
+        PcmFormat::S16le => 2,
+        PcmFormat::U8 => 1,
+        PcmFormat::F32le => 4,
+        PcmFormat::Wav => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WAV files have a header, expected s16le, u8 or f32le",
+            ))
+        }
+    };
+    Ok(RawChunks {
+        reader,
+        spec,
+        width,
+        frames,
+        mixdown: Mixdown::Mix,
+        done: false,
+    })
+}
+
+/// Mono samples from a headerless capture a chunk at a time, see [`read_raw_chunks`]
+pub struct RawChunks<R: Read> {
+    reader: R,
+    spec: RawSpec,
+    /// Bytes per sample
+    width: usize,
+    frames: usize,
+    done: bool,
+    mixdown: Mixdown,
+}
+
+impl<R: Read> RawChunks<R> {
+    /// Like [`WavChunks::mixdown`]
+    pub fn mixdown(mut self, mixdown: Mixdown) -> Self {
+        self.mixdown = mixdown;
+        self
+    }
+}
+
+impl<R: Read> RawChunks<R> {
+    fn sample(&self, bytes: &[u8]) -> i16 {
+        let big = self.spec.endian == Endian::Big;
+        match self.spec.format {
+            PcmFormat::U8 => (i16::from(bytes[0]) - 128) << 8,
+            PcmFormat::F32le => {
+                let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+                from_float(if big {
+                    f32::from_be_bytes(bytes)
+                } else {
+                    f32::from_le_bytes(bytes)
+                })
+            }
+            _ if big => i16::from_be_bytes([bytes[0], bytes[1]]),
+            _ => i16::from_le_bytes([bytes[0], bytes[1]]),
+        }
+    }
+}
+
+impl<R: Read> Iterator for RawChunks<R> {
+    type Item = io::Result<Vec<i16>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let channels = self.spec.channels;
+        let frame = self.width * channels;
+        let mut bytes = Vec::new();
+        let read = match self.spec.layout {
+            Layout::Interleaved => (&mut self.reader)
+                .take((self.frames * frame) as u64)
+                .read_to_end(&mut bytes),
+            Layout::Planar => {
+                self.done = true;
+                self.reader.read_to_end(&mut bytes)
+            }
+        };
+        if let Err(err) = read {
+            return Some(Err(err));
+        }
+        bytes.truncate(bytes.len() / frame * frame);
+        if bytes.is_empty() {
+            return None;
+        }
+        let samples: Vec<i16> = bytes
+            .chunks_exact(self.width)
+            .map(|sample| self.sample(sample))
+            .collect();
+        let samples = match self.spec.layout {
+            Layout::Interleaved => samples,
+            // Interleave the channels to share the mixdown
+            Layout::Planar => {
+                let length = samples.len() / channels;
+                (0..samples.len())
+                    .map(|i| samples[i % channels * length + i / channels])
+                    .collect()
+            }
+        };
+        Some(select(&mut self.mixdown, samples, channels))
+    }
+}
+
+/// How a multi-channel recording becomes the mono samples decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mixdown {
+    /// Average the channels, which keeps both directions of a duplex recording
+    Mix,
+    /// Only the channel numbered from 0
+    Only(usize),
+    /// The channel with the strongest signal in any of `bands` over the first chunk
+    Best {
+        bands: Vec<Channel>,
+        sampling_rate: f64,
+    },
+}
+
+/// Which channel of a multi-channel recording to decode, as given on the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelPick {
+    Mix,
+    Best,
+    /// Numbered from 1
+    Number(usize),
+}
+
+impl FromStr for ChannelPick {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mix" => Ok(ChannelPick::Mix),
+            "best" => Ok(ChannelPick::Best),
+            _ => match s.parse() {
+                Ok(number) if number > 0 => Ok(ChannelPick::Number(number)),
+                _ => Err(format!(
+                    "unknown channel `{}`, expected mix, best or a number from 1",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// Makes interleaved frames mono as `mixdown` says, settling on a channel for
+/// [`Mixdown::Best`] with the first chunk
+fn select(mixdown: &mut Mixdown, samples: Vec<i16>, channels: usize) -> io::Result<Vec<i16>> {
+    if let Mixdown::Best {
+        bands,
+        sampling_rate,
+    } = mixdown
+    {
+        let scores: Vec<f64> = (0..channels)
+            .map(|channel| {
+                let samples = only(&samples, channel, channels);
+                bands
+                    .iter()
+                    .map(|&band| detect::band_snr(&samples, band, *sampling_rate))
+                    .fold(f64::NEG_INFINITY, f64::max)
+            })
+            .collect();
+        let best = (0..channels)
+            .max_by(|&a, &b| scores[a].total_cmp(&scores[b]))
+            .unwrap_or_default();
+        if channels > 1 {
+            eprintln!(
+                "Decoding channel {} of {}, the strongest at {:.1} dB in band",
+                best + 1,
+                channels,
+                scores[best]
+            );
+        }
+        *mixdown = Mixdown::Only(best);
+    }
+    match *mixdown {
+        Mixdown::Only(channel) if channel >= channels => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The recording has {} channel{}, so there's no channel {}",
+                channels,
+                if channels == 1 { "" } else { "s" },
+                channel + 1
+            ),
+        )),
+        Mixdown::Only(channel) => Ok(only(&samples, channel, channels)),
+        _ => Ok(mix_down(samples, channels)),
+    }
+}
+
+/// One channel of interleaved frames
+fn only(samples: &[i16], channel: usize, channels: usize) -> Vec<i16> {
+    samples
+        .iter()
+        .skip(channel)
+        .step_by(channels)
+        .copied()
+        .collect()
+}
+
+/// Averages each frame of interleaved samples into one
+fn mix_down(samples: Vec<i16>, channels: usize) -> Vec<i16> {
+    if channels == 1 {
+        return samples;
+    }
+    samples
+        .chunks(channels)
+        .map(|frame| {
+            let sum: i32 = frame.iter().map(|&s| i32::from(s)).sum();
+            (sum / frame.len() as i32) as i16
+        })
+        .collect()
+}
+
+/// A WAV, AIFF, FLAC, MP3 or Ogg file mapped into memory, so samples are decoded straight from the page cache
+/// rather than read into buffers first. 16-bit WAV samples are read in place, other formats
+/// are converted as they're read.
+pub struct MappedWav {
+    map: Mmap,
+    data: Option<InPlace>,
+    mixdown: Mixdown,
+    /// The rate to resample to, see [`WavChunks::resample_to`]
+    resample: Option<f64>,
+}
+
+/// 16-bit samples that can be read straight from the mapping
+struct InPlace {
+    /// Byte range of the whole frames in the data chunk
+    bytes: Range<usize>,
+    channels: usize,
+    sampling_rate: u32,
+}
+
+impl MappedWav {
+    pub fn open(file: &File) -> io::Result<Self> {
+        // The mapping is only sound while nothing else truncates or rewrites the file, which
+        // is the caller's promise in asking for it
+        let map = unsafe { Mmap::map(file)? };
+        if detect(&map[..map.len().min(MAGIC_LENGTH as usize)]) != Container::Wav {
+            // Decoded from the mapping like any other format that can't be read in place
+            return Ok(Self {
+                map,
+                data: None,
+                mixdown: Mixdown::Mix,
+                resample: None,
+            });
+        }
+        let reader = hound::WavReader::new(Cursor::new(&map[..])).map_err(to_io)?;
+        let spec = reader.spec();
+        let channels = usize::from(spec.channels);
+        let data = if (spec.sample_format, spec.bits_per_sample) == (hound::SampleFormat::Int, 16) {
+            let length = reader.len() as usize * 2;
+            // hound leaves the cursor at the start of the samples
+            let start = reader.into_inner().position() as usize;
+            let frame = channels * 2;
+            let length = length.min(map.len() - start) / frame * frame;
+            Some(InPlace {
+                bytes: start..start + length,
+                channels,
+                sampling_rate: spec.sample_rate,
+            })
+        } else {
+            None
+        };
+        Ok(Self {
+            map,
+            data,
+            mixdown: Mixdown::Mix,
+            resample: None,
+        })
+    }
+
+    /// Like [`WavChunks::mixdown`]
+    pub fn mixdown(mut self, mixdown: Mixdown) -> Self {
+        self.mixdown = mixdown;
+        self
+    }
+
+    /// Like [`WavChunks::resample_to`], which has samples copied out of the mapping when the
+    /// rates differ
+    pub fn resample_to(mut self, rate: f64) -> Self {
+        self.resample = Some(rate);
+        self
+    }
+
+    /// Mono samples `frames` at a time from formats that can't be read in place
+    fn converted(&self, frames: usize) -> io::Result<WavChunks<Cursor<&[u8]>>> {
+        let chunks = read_wav_chunks(Cursor::new(&self.map[..]), frames)?;
+        let chunks = chunks.mixdown(self.mixdown.clone());
+        Ok(match self.resample {
+            Some(rate) => chunks.resample_to(rate),
+            None => chunks,
+        })
+    }
+
+    /// All of the recording in mono, borrowed from the mapping when it's already mono 16-bit
+    pub fn samples(&self) -> io::Result<Cow<'_, [i16]>> {
+        match &self.data {
+            Some(data) => {
+                let mut mixdown = self.mixdown.clone();
+                let samples = mono(&self.map[data.bytes.clone()], data.channels, &mut mixdown)?;
+                let resampler = self
+                    .resample
+                    .and_then(|rate| resampler(Some(data.sampling_rate), rate));
+                Ok(match resampler {
+                    Some(mut resampler) => Cow::Owned(resampler.process(&samples)),
+                    None => samples,
+                })
+            }
+            None => {
+                let mut samples = Vec::new();
+                for chunk in self.converted(CHUNK_FRAMES)? {
+                    samples.extend(chunk?);
+                }
+                Ok(Cow::Owned(samples))
+            }
+        }
+    }
+
+    /// Mono samples `frames` at a time, borrowed from the mapping when it's already mono
+    /// 16-bit
+    pub fn chunks(
+        &self,
+        frames: usize,
+    ) -> Box<dyn Iterator<Item = io::Result<Cow<'_, [i16]>>> + '_> {
+        match &self.data {
+            Some(data) => {
+                let mut mixdown = self.mixdown.clone();
+                let mut resampler = self
+                    .resample
+                    .and_then(|rate| resampler(Some(data.sampling_rate), rate));
+                Box::new(
+                    self.map[data.bytes.clone()]
+                        .chunks(frames * data.channels * 2)
+                        .map(move |bytes| {
+                            let samples = mono(bytes, data.channels, &mut mixdown)?;
+                            Ok(match &mut resampler {
+                                Some(resampler) => Cow::Owned(resampler.process(&samples)),
+                                None => samples,
+                            })
+                        }),
+                )
+            }
+            None => match self.converted(frames) {
+                Ok(chunks) => Box::new(chunks.map(|chunk| chunk.map(Cow::Owned))),
+                Err(err) => Box::new(std::iter::once(Err(err))),
+            },
+        }
+    }
+}
+
+/// Mono samples from 16-bit little-endian frames, borrowed when they're mono already
+fn mono<'a>(bytes: &'a [u8], channels: usize, mixdown: &mut Mixdown) -> io::Result<Cow<'a, [i16]>> {
+    // Every pair of bytes is a valid i16, so only alignment and byte order matter
+    let (head, samples, tail) = unsafe { bytes.align_to::<i16>() };
+    let in_place = head.is_empty() && tail.is_empty() && cfg!(target_endian = "little");
+    // Picking a channel other than the first still has to fail through `select`
+    let first = !matches!(*mixdown, Mixdown::Only(channel) if channel > 0);
+    if channels == 1 && in_place && first {
+        return Ok(Cow::Borrowed(samples));
+    }
+    let samples = bytes
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    select(mixdown, samples, channels).map(Cow::Owned)
+}
+
+/// A 16-bit mono WAV file written a block at a time as audio arrives, with the header kept
+/// up to date after every block so the file can be read even if the capture is killed
+pub struct WavRecorder {
+    writer: hound::WavWriter<io::BufWriter<File>>,
+}
+
+impl WavRecorder {
+    pub fn create(path: &Path, sampling_rate: u32) -> io::Result<Self> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: sampling_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path, spec).map_err(to_io)?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut block = self.writer.get_i16_writer(samples.len() as u32);
+        for &sample in samples {
+            block.write_sample(sample);
+        }
+        block.flush().map_err(to_io)?;
+        self.writer.flush().map_err(to_io)
+    }
+}
+
+/// Writes mono samples to `writer` in the given format
+pub fn write_samples<W: Write>(
+    writer: &mut W,
+    samples: &[i16],
+    format: PcmFormat,
+    sampling_rate: u32,
+) -> io::Result<()> {
+    match format {
+        PcmFormat::Wav => {
+            // hound needs to seek back to fill in the header, so build the file in memory
+            // which also lets WAV go to pipes like stdout
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: sampling_rate,
+                bits_per_sample: 16,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let mut buffer = Cursor::new(Vec::with_capacity(44 + samples.len() * 2));
+            {
+                let mut wav = hound::WavWriter::new(&mut buffer, spec).map_err(to_io)?;
+                for &sample in samples {
+                    wav.write_sample(sample).map_err(to_io)?;
+                }
+                wav.finalize().map_err(to_io)?;
+            }
+            writer.write_all(buffer.get_ref())
+        }
+        PcmFormat::S16le => {
+            let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+            writer.write_all(&bytes)
+        }
+        PcmFormat::U8 => {
+            let bytes: Vec<u8> = samples.iter().map(|&s| ((s >> 8) + 128) as u8).collect();
+            writer.write_all(&bytes)
+        }
+        PcmFormat::F32le => {
+            let bytes: Vec<u8> = samples
+                .iter()
+                .flat_map(|&s| (f32::from(s) / 32_768.0).to_le_bytes())
+                .collect();
+            writer.write_all(&bytes)
+        }
+    }
+}
+
+/// Scales audio by a gain in dB, clipping at full scale
+pub fn apply_gain(samples: &mut [i16], db: f64) {
+    let gain = 10f64.powf(db / 20.0);
+    for sample in samples {
+        *sample = (f64::from(*sample) * gain)
+            .round()
+            .clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16;
+    }
+}
+
+/// `--gain` on incoming samples, warning the first time they clip since flattened peaks
+/// smear the tones
+pub struct InputGain {
+    db: f64,
+    warned: bool,
+}
+
+impl InputGain {
+    pub fn new(db: f64) -> Self {
+        Self { db, warned: false }
+    }
+
+    /// Applies the gain in place, returning how many samples ended up at full scale
+    pub fn process(&mut self, samples: &mut [i16]) -> usize {
+        apply_gain(samples, self.db);
+        let clipped = samples
+            .iter()
+            .filter(|&&sample| sample == i16::MAX || sample == i16::MIN)
+            .count();
+        if clipped > 0 && !self.warned {
+            self.warned = true;
+            eprintln!(
+                "Warning: {} of {} samples clipped with {} dB of --gain, try less",
+                clipped,
+                samples.len(),
+                self.db
+            );
+        }
+        clipped
+    }
+}
+
+/// Plays audio by piping it as WAV into a player command such as `aplay -q`
+pub fn play_with_command(command: &str, samples: &[i16], sampling_rate: u32) -> io::Result<()> {
+    let mut player = ptt::shell(command).stdin(Stdio::piped()).spawn()?;
+    let result = write_samples(
+        player.stdin.as_mut().unwrap(),
+        samples,
+        PcmFormat::Wav,
+        sampling_rate,
+    );
+    // Close stdin so the player sees the end of the file before we wait on it
+    drop(player.stdin.take());
+    let status = player.wait()?;
+    result?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{}` failed with {}",
+            command, status
+        )))
+    }
+}
+
+fn to_io(err: hound::Error) -> io::Error {
+    match err {
+        hound::Error::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[cfg(feature = "symphonia")]
+fn compressed_to_io(err: symphonia::core::errors::Error) -> io::Error {
+    match err {
+        symphonia::core::errors::Error::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[cfg(feature = "flac")]
+fn flac_to_io(err: claxon::Error) -> io::Error {
+    match err {
+        claxon::Error::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_raw() {
+        let mut s16 = Vec::new();
+        write_samples(&mut s16, &[1, -2], PcmFormat::S16le, 48_000).unwrap();
+        assert_eq!(s16, [0x01, 0x00, 0xfe, 0xff]);
+
+        let mut u8 = Vec::new();
+        write_samples(&mut u8, &[-32_768, 0, 32_767], PcmFormat::U8, 48_000).unwrap();
+        assert_eq!(u8, [0, 128, 255]);
+
+        let mut f32 = Vec::new();
+        write_samples(&mut f32, &[-32_768], PcmFormat::F32le, 48_000).unwrap();
+        assert_eq!(f32, (-1.0f32).to_le_bytes());
+    }
+
+    #[test]
+    fn test_read_raw() {
+        let read = |bytes: Vec<u8>, format, channels, frames| {
+            let spec = RawSpec {
+                format,
+                endian: Endian::Little,
+                channels,
+                layout: Layout::Interleaved,
+            };
+            read_raw_chunks(Cursor::new(bytes), spec, frames)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect::<Vec<Vec<i16>>>()
+        };
+        let samples: Vec<i16> = (0..1000).map(|i| i * 30 - 15_000).collect();
+        let mut s16 = Vec::new();
+        write_samples(&mut s16, &samples, PcmFormat::S16le, 8_000).unwrap();
+        let chunks = read(s16, PcmFormat::S16le, 1, 300);
+        let lengths: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [300, 300, 300, 100]);
+        assert_eq!(chunks.concat(), samples);
+
+        // Stereo 8-bit with a stray byte at the end
+        let chunks = read(vec![0, 64, 128, 128, 255, 0, 7], PcmFormat::U8, 2, 100);
+        assert_eq!(chunks, [vec![-24_576, 0, -128]]);
+
+        let f32: Vec<u8> = [0.5f32, -1.0]
+            .iter()
+            .flat_map(|s| s.to_le_bytes())
+            .collect();
+        assert_eq!(read(f32, PcmFormat::F32le, 1, 100), [vec![16_383, -32_767]]);
+        let spec = RawSpec {
+            format: PcmFormat::Wav,
+            endian: Endian::Little,
+            channels: 1,
+            layout: Layout::Interleaved,
+        };
+        assert!(read_raw_chunks(io::empty(), spec, 100).is_err());
+    }
+
+    #[test]
+    fn test_read_raw_layouts() {
+        let read = |bytes: &[u8], format, endian, layout| {
+            let spec = RawSpec {
+                format,
+                endian,
+                channels: 2,
+                layout,
+            };
+            read_raw_chunks(Cursor::new(bytes.to_vec()), spec, 2)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect::<Vec<Vec<i16>>>()
+        };
+        // Left 100, 200, 300 and right 300, 400, 500, big-endian
+        let interleaved = [0, 100, 1, 44, 0, 200, 1, 144, 1, 44, 1, 244];
+        let planar = [0, 100, 0, 200, 1, 44, 1, 44, 1, 144, 1, 244];
+        let (s16, big) = (PcmFormat::S16le, Endian::Big);
+        let mixed = [vec![200, 300], vec![400]];
+        assert_eq!(read(&interleaved, s16, big, Layout::Interleaved), mixed);
+        assert_eq!(
+            read(&planar, s16, big, Layout::Planar),
+            [vec![200, 300, 400]]
+        );
+
+        let f32: Vec<u8> = [0.5f32, 0.25]
+            .iter()
+            .flat_map(|s| s.to_be_bytes())
+            .collect();
+        let chunks = read(&f32, PcmFormat::F32le, big, Layout::Planar);
+        assert_eq!(chunks, [vec![12_287]]);
+    }
+
+    #[test]
+    fn test_apply_gain() {
+        let mut samples = [1000, -16_384, 30_000];
+        apply_gain(&mut samples, -20.0);
+        assert_eq!(samples, [100, -1638, 3000]);
+        apply_gain(&mut samples, 20.0 * 20f64.log10());
+        assert_eq!(samples, [2000, -32_760, 32_767]);
+    }
+
+    #[test]
+    fn test_input_gain() {
+        let mut gain = InputGain::new(6.0);
+        let mut samples = [1000, -1000, 20_000];
+        assert_eq!(gain.process(&mut samples), 1);
+        assert_eq!(samples, [1995, -1995, 32_767]);
+        let mut samples = [-20_000, 0];
+        assert_eq!(gain.process(&mut samples), 1);
+        assert_eq!(samples, [-32_768, 0]);
+    }
+
+    #[test]
+    fn test_read_stereo_wav() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut wav = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+            for &sample in &[100i16, 300, -50, -150, 7, 7] {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        wav.set_position(0);
+        assert_eq!(read_wav(wav, 8_000.0).unwrap(), [200, -100, 7]);
+    }
+
+    #[test]
+    fn test_mixdown() {
+        use crate::modulator::Oscillator;
+        use crate::standard::Standard;
+
+        // Hum on the first channel and a Bell 103 mark tone on the second
+        let channel = Standard::Bell103.answer();
+        let (mut hum, mut mark) = (
+            Oscillator::new(60.0, 8_000.0),
+            Oscillator::new(channel.mark, 8_000.0),
+        );
+        let mut bytes = Vec::new();
+        for _ in 0..8_000 {
+            for oscillator in [&mut hum, &mut mark] {
+                let sample = (oscillator.next_sample() * 8_000.0) as i16;
+                bytes.extend(sample.to_le_bytes());
+            }
+        }
+        let spec = RawSpec {
+            format: PcmFormat::S16le,
+            endian: Endian::Little,
+            channels: 2,
+            layout: Layout::Interleaved,
+        };
+        let read = |mixdown: Mixdown| -> io::Result<Vec<i16>> {
+            let chunks = read_raw_chunks(Cursor::new(&bytes), spec, 1_000).unwrap();
+            chunks
+                .mixdown(mixdown)
+                .collect::<io::Result<Vec<_>>>()
+                .map(|c| c.concat())
+        };
+        let second: Vec<i16> = bytes
+            .chunks(4)
+            .map(|frame| i16::from_le_bytes([frame[2], frame[3]]))
+            .collect();
+        assert_eq!(read(Mixdown::Only(1)).unwrap(), second);
+        let best = Mixdown::Best {
+            bands: vec![channel],
+            sampling_rate: 8_000.0,
+        };
+        assert_eq!(read(best).unwrap(), second);
+        assert_eq!(read(Mixdown::Mix).unwrap().len(), second.len());
+        let err = read(Mixdown::Only(2)).unwrap_err();
+        assert!(err.to_string().contains("no channel 3"));
+
+        assert_eq!("best".parse(), Ok(ChannelPick::Best));
+        assert_eq!("2".parse(), Ok(ChannelPick::Number(2)));
+        assert!("0".parse::<ChannelPick>().is_err());
+    }
+
+    #[test]
+    fn test_read_sample_formats() {
+        fn wav<S: hound::Sample + Copy>(bits: u16, int: bool, samples: &[S]) -> Vec<i16> {
+            let spec = hound::WavSpec {
+                channels: 1,
+                sample_rate: 8_000,
+                bits_per_sample: bits,
+                sample_format: if int {
+                    hound::SampleFormat::Int
+                } else {
+                    hound::SampleFormat::Float
+                },
+            };
+            let mut wav = Cursor::new(Vec::new());
+            {
+                let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+                for &sample in samples {
+                    writer.write_sample(sample).unwrap();
+                }
+                writer.finalize().unwrap();
+            }
+            wav.set_position(0);
+            read_wav(wav, 8_000.0).unwrap()
+        }
+        assert_eq!(
+            wav(8, true, &[-128i8, 0, 64, 127]),
+            [-32_768, 0, 16_384, 32_512]
+        );
+        assert_eq!(
+            wav(24, true, &[-8_388_608, -256, 0x12_3456, 8_388_607]),
+            [-32_768, -1, 0x1234, 32_767]
+        );
+        assert_eq!(
+            wav(32, true, &[i32::MIN, 0x4000_0000, i32::MAX]),
+            [-32_768, 16_384, 32_767]
+        );
+        assert_eq!(
+            wav(32, false, &[-1.0f32, 0.5, 1.0, 2.0]),
+            [-32_767, 16_383, 32_767, 32_767]
+        );
+    }
+
+    /// Builds a FLAC stream of 16-bit samples stored verbatim, `block` frames to a block
+    #[cfg(feature = "flac")]
+    fn flac(samples: &[i16], channels: usize, block: usize) -> Vec<u8> {
+        fn crc8(bytes: &[u8]) -> u8 {
+            bytes.iter().fold(0, |crc, &byte| {
+                (0..8).fold(crc ^ byte, |crc, _| match crc & 0x80 {
+                    0 => crc << 1,
+                    _ => crc << 1 ^ 0x07,
+                })
+            })
+        }
+        fn crc16(bytes: &[u8]) -> u16 {
+            bytes.iter().fold(0, |crc, &byte| {
+                (0..8).fold(crc ^ u16::from(byte) << 8, |crc, _| match crc & 0x8000 {
+                    0 => crc << 1,
+                    _ => crc << 1 ^ 0x8005,
+                })
+            })
+        }
+        let mut flac = FLAC_MAGIC.to_vec();
+        // STREAMINFO as the last metadata block, with unknown frame sizes and MD5
+        flac.extend([0x80, 0, 0, 34]);
+        flac.extend((block as u16).to_be_bytes());
+        flac.extend((block as u16).to_be_bytes());
+        flac.extend([0; 6]);
+        let frames = (samples.len() / channels) as u64;
+        let info = 8_000 << 44 | (channels as u64 - 1) << 41 | 15 << 36 | frames;
+        flac.extend(info.to_be_bytes());
+        flac.extend([0; 16]);
+        for (number, chunk) in samples.chunks(block * channels).enumerate() {
+            let length = chunk.len() / channels;
+            // Sync, a 16-bit block size at the end of the header, the STREAMINFO rate,
+            // independent channels and 16-bit samples
+            let mut frame = vec![0xff, 0xf8, 0x70, ((channels - 1) << 4 | 0b1000) as u8];
+            frame.push(number as u8);
+            frame.extend((length as u16 - 1).to_be_bytes());
+            frame.push(crc8(&frame));
+            for channel in 0..channels {
+                frame.push(0x02);
+                for i in 0..length {
+                    frame.extend(chunk[i * channels + channel].to_be_bytes());
+                }
+            }
+            let crc = crc16(&frame);
+            frame.extend(crc.to_be_bytes());
+            flac.extend(frame);
+        }
+        flac
+    }
+
+    #[test]
+    #[cfg(feature = "flac")]
+    fn test_read_flac() {
+        let samples: Vec<i16> = (0..1000).map(|i| i * 30 - 15_000).collect();
+        let chunks: Vec<Vec<i16>> = read_wav_chunks(Cursor::new(flac(&samples, 1, 256)), 300)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let lengths: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [300, 300, 300, 100]);
+        assert_eq!(chunks.concat(), samples);
+
+        let stereo = flac(&[100, 300, -50, -150, 7, 7], 2, 16);
+        assert_eq!(
+            read_wav(Cursor::new(stereo), 8_000.0).unwrap(),
+            [200, -100, 7]
+        );
+    }
+
+    #[test]
+    fn test_read_aiff() {
+        fn aiff(form: &[u8], encoding: &[u8], bits: u16, data: &[u8]) -> Vec<u8> {
+            let chunk = |id: &[u8], body: &[u8]| {
+                let mut chunk = id.to_vec();
+                chunk.extend((body.len() as u32).to_be_bytes());
+                chunk.extend(body);
+                if body.len() % 2 == 1 {
+                    chunk.push(0);
+                }
+                chunk
+            };
+            // Two channels, the frame count, bits and 8 kHz as an 80-bit float
+            let mut comm = vec![0, 2, 0, 0, 0, 0];
+            comm.extend(bits.to_be_bytes());
+            comm.extend([0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]);
+            comm.extend(encoding);
+            let mut ssnd = vec![0; 8];
+            ssnd.extend(data);
+            let mut body = form.to_vec();
+            // An odd-length chunk the reader has to step over
+            body.extend(chunk(b"NAME", b"tap"));
+            body.extend(chunk(b"COMM", &comm));
+            body.extend(chunk(b"SSND", &ssnd));
+            let mut file = b"FORM".to_vec();
+            file.extend((body.len() as u32).to_be_bytes());
+            file.extend(body);
+            file
+        }
+        let read = |file: Vec<u8>| read_wav(Cursor::new(file), 8_000.0);
+
+        let data = [0x10, 0x00, 0x30, 0x00, 0xff, 0x00, 0xfd, 0x00];
+        assert_eq!(
+            read(aiff(b"AIFF", b"", 16, &data)).unwrap(),
+            [0x2000, -0x200]
+        );
+        assert_eq!(
+            read(aiff(b"AIFC", b"sowt", 16, &[0x00, 0x10, 0x00, 0x30])).unwrap(),
+            [0x2000]
+        );
+        let data = [0x12, 0x34, 0x56, 0x12, 0x34, 0x56];
+        assert_eq!(read(aiff(b"AIFF", b"", 24, &data)).unwrap(), [0x1234]);
+        let mut data = 0.5f32.to_be_bytes().to_vec();
+        data.extend((-0.5f32).to_be_bytes());
+        assert_eq!(read(aiff(b"AIFC", b"fl32", 32, &data)).unwrap(), [0]);
+
+        let err = read(aiff(b"AIFC", b"ulaw", 8, &[0, 0])).unwrap_err();
+        assert!(err.to_string().contains("`ulaw`"));
+        let err = read(aiff(b"8SVX", b"", 8, &[0, 0])).unwrap_err();
+        assert!(err.to_string().contains("other than AIFF"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "flac"))]
+    fn test_flac_needs_feature() {
+        let err = read_wav(Cursor::new(b"fLaC\0\0\0\x22".to_vec()), 8_000.0).unwrap_err();
+        assert!(err.to_string().contains("--features flac"));
+    }
+
+    #[test]
+    fn test_is_mp3() {
+        assert!(is_mp3(b"ID3\x04"));
+        assert!(is_mp3(&[0xff, 0xfb, 0x90, 0x64]));
+        assert!(!is_mp3(b"RIFF"));
+        assert!(!is_mp3(FLAC_MAGIC));
+        assert!(!is_mp3(&[0xff]));
+        assert!(!is_mp3(&[0xff, 0xff, 0xff, 0xff]));
+        #[cfg(not(feature = "mp3"))]
+        {
+            let err = read_wav(Cursor::new(b"ID3\x04\0".to_vec()), 8_000.0).unwrap_err();
+            assert!(err.to_string().contains("--features mp3"));
+        }
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"RIFF\0\0\0\0WAVEfmt "), Container::Wav);
+        assert_eq!(detect(b"FORM\0\0\0\0AIFC"), Container::Aiff);
+        assert_eq!(detect(b"fLaC\0\0\0\x22"), Container::Flac);
+        assert_eq!(detect(b"OggS\0\x02"), Container::Ogg);
+        assert_eq!(detect(b"ID3\x04"), Container::Mp3);
+        assert_eq!(
+            detect(b"\0\0\0\x20ftypM4A "),
+            Container::Unsupported("an MP4 or M4A file")
+        );
+        assert_eq!(detect(b"\x01\0\xff\xff\0\x10"), Container::Raw);
+        assert_eq!(detect(b""), Container::Raw);
+
+        let err = read_wav(Cursor::new(b"RIFF\0\0\0\0AVI LIST".to_vec()), 8_000.0).unwrap_err();
+        assert!(err.to_string().contains("such as AVI"));
+        // Anything without a header is read as 16-bit mono samples
+        let raw = [0x01, 0x00, 0xff, 0xff, 0x00, 0x10];
+        assert_eq!(
+            read_wav(Cursor::new(raw), 8_000.0).unwrap(),
+            [1, -1, 0x1000]
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "ogg"))]
+    fn test_ogg_needs_feature() {
+        let err = read_wav(Cursor::new(b"OggS\0\x02".to_vec()), 8_000.0).unwrap_err();
+        assert!(err.to_string().contains("--features ogg"));
+    }
+
+    #[test]
+    fn test_read_wav_chunks() {
+        let mut wav = Vec::new();
+        let samples: Vec<i16> = (0..1000).collect();
+        write_samples(&mut wav, &samples, PcmFormat::Wav, 8_000).unwrap();
+        let chunks: Vec<Vec<i16>> = read_wav_chunks(Cursor::new(wav), 300)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let lengths: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [300, 300, 300, 100]);
+        assert_eq!(chunks.concat(), samples);
+    }
+
+    #[test]
+    fn test_read_wav_cut_short() {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let samples: Vec<i16> = (-600..600).map(|i| i * 50).collect();
+        let mut wav = Cursor::new(Vec::new());
+        {
+            let mut writer = hound::WavWriter::new(&mut wav, spec).unwrap();
+            for &sample in &samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        // Half a frame past the 500th, as a recording still being copied might be
+        let mut wav = wav.into_inner();
+        let header = wav.len() - samples.len() * 2;
+        wav.truncate(header + 1_001 * 2);
+        let chunks: Vec<Vec<i16>> = read_wav_chunks(Cursor::new(wav), 128)
+            .unwrap()
+            .mixdown(Mixdown::Only(1))
+            .map(Result::unwrap)
+            .collect();
+        let lengths: Vec<usize> = chunks.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [128, 128, 128, 116]);
+        let right: Vec<i16> = samples[..1_000]
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .copied()
+            .collect();
+        assert_eq!(chunks.concat(), right);
+    }
+
+    #[test]
+    fn test_resample_to() {
+        let mut wav = Vec::new();
+        let samples: Vec<i16> = (0..1000).map(|i| i * 2).collect();
+        write_samples(&mut wav, &samples, PcmFormat::Wav, 8_000).unwrap();
+        assert_eq!(read_wav(Cursor::new(&wav), 8_000.0).unwrap(), samples);
+        let resampled = read_wav(Cursor::new(&wav), 16_000.0).unwrap();
+        assert_eq!(resampled.len(), 1999);
+        assert_eq!(resampled[..5], [0, 1, 2, 3, 4]);
+
+        // Headerless samples have no rate to go by
+        let raw = [0x01, 0x00, 0xff, 0xff, 0x00, 0x10];
+        assert_eq!(
+            read_wav(Cursor::new(raw), 16_000.0).unwrap(),
+            [1, -1, 0x1000]
+        );
+        assert_eq!(
+            extended_to_u32(&[0x40, 0x0e, 0xbb, 0x80, 0, 0, 0, 0, 0, 0]),
+            48_000
+        );
+    }
+
+    #[test]
+    fn test_mapped_wav() {
+        let path = std::env::temp_dir().join(format!("mapped-{}.wav", std::process::id()));
+        let samples: Vec<i16> = (0..1000).collect();
+        write_samples(
+            &mut File::create(&path).unwrap(),
+            &samples,
+            PcmFormat::Wav,
+            8_000,
+        )
+        .unwrap();
+        let mapped = MappedWav::open(&File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(mapped.samples().unwrap(), Cow::Borrowed(s) if s == &samples[..]));
+        let lengths: Vec<usize> = mapped
+            .chunks(300)
+            .map(|chunk| chunk.unwrap().len())
+            .collect();
+        assert_eq!(lengths, [300, 300, 300, 100]);
+
+        // Stereo is mixed down a chunk at a time
+        let path = std::env::temp_dir().join(format!("mapped-stereo-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &sample in &[100i16, 300, -50, -150, 7, 7] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        let mapped = MappedWav::open(&File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*mapped.samples().unwrap(), [200, -100, 7]);
+        let chunks: Vec<Vec<i16>> = mapped.chunks(2).map(|c| c.unwrap().into_owned()).collect();
+        assert_eq!(chunks, [vec![200, -100], vec![7]]);
+
+        // Other formats are converted from the mapping
+        let path = std::env::temp_dir().join(format!("mapped-float-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_format: hound::SampleFormat::Float,
+            bits_per_sample: 32,
+            ..spec
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &sample in &[0.5f32, -0.5, 0.25] {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        let mapped = MappedWav::open(&File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*mapped.samples().unwrap(), [16_383, -16_383, 8_191]);
+        let lengths: Vec<usize> = mapped.chunks(2).map(|chunk| chunk.unwrap().len()).collect();
+        assert_eq!(lengths, [2, 1]);
+    }
+
+    #[test]
+    fn test_wav_recorder() {
+        let path = std::env::temp_dir().join(format!("recorded-{}.wav", std::process::id()));
+        let mut recorder = WavRecorder::create(&path, 8_000).unwrap();
+        recorder.write(&[1, 2, 3]).unwrap();
+        recorder.write(&[-4, 5]).unwrap();
+        // Readable before it's closed
+        let samples = read_wav(File::open(&path).unwrap(), 8_000.0).unwrap();
+        drop(recorder);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(samples, [1, 2, 3, -4, 5]);
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let mut wav = Vec::new();
+        write_samples(&mut wav, &[0, 100, -100], PcmFormat::Wav, 8_000).unwrap();
+        let reader = hound::WavReader::new(Cursor::new(wav)).unwrap();
+        assert_eq!(reader.spec().sample_rate, 8_000);
+        let samples: Vec<i16> = reader.into_samples().map(Result::unwrap).collect();
+        assert_eq!(samples, [0, 100, -100]);
+    }
+}
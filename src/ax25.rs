@@ -0,0 +1,227 @@
+use crate::clock::ClockRecovery;
+use crate::demodulator::{Demodulator, TICKS_PER_BIT};
+use crate::hdlc::{self, HdlcDecoder};
+use crate::modulator::FskModulator;
+use crate::standard::Standard;
+use std::fmt;
+
+const ADDRESS_LEN: usize = 7;
+const MAX_DIGIPEATERS: usize = 8;
+/// Unnumbered information frames, used by APRS
+const UI: u8 = 0x03;
+
+/// A station address. Callsigns are sent as shifted ASCII padded with spaces, followed by a
+/// byte holding the SSID, the has-been-repeated bit and the end of address bit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub callsign: String,
+    pub ssid: u8,
+    pub repeated: bool,
+}
+
+impl Address {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let callsign: String = bytes[..6]
+            .iter()
+            .map(|&b| char::from(b >> 1))
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        if callsign.is_empty() || !callsign.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        Some(Self {
+            callsign,
+            ssid: (bytes[6] >> 1) & 0x0f,
+            repeated: bytes[6] & 0x80 != 0,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.callsign)?;
+        if self.ssid != 0 {
+            write!(f, "-{}", self.ssid)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub destination: Address,
+    pub source: Address,
+    pub digipeaters: Vec<Address>,
+    pub control: u8,
+    pub pid: Option<u8>,
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    /// Parses a frame's contents, as returned by the HDLC decoder
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        // Addresses run until one has the end of address bit set
+        let count = bytes
+            .chunks_exact(ADDRESS_LEN)
+            .position(|address| address[6] & 1 == 1)?
+            + 1;
+        if !(2..=2 + MAX_DIGIPEATERS).contains(&count) {
+            return None;
+        }
+        let addresses = bytes[..count * ADDRESS_LEN]
+            .chunks_exact(ADDRESS_LEN)
+            .map(Address::parse)
+            .collect::<Option<Vec<_>>>()?;
+        let rest = &bytes[count * ADDRESS_LEN..];
+        let control = *rest.first()?;
+        // Only I and UI frames carry a PID
+        let (pid, info) = if control & 1 == 0 || control & 0xef == UI {
+            (Some(*rest.get(1)?), &rest[2..])
+        } else {
+            (None, &rest[1..])
+        };
+        Some(Self {
+            destination: addresses[0].clone(),
+            source: addresses[1].clone(),
+            digipeaters: addresses[2..].to_vec(),
+            control,
+            pid,
+            info: info.to_vec(),
+        })
+    }
+}
+
+/// TNC2 monitor format, e.g. `N0CALL-9>APRS,WIDE1-1*:!4903.50N/07201.75W-`. The last
+/// digipeater that has repeated the frame is marked with `*`.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+        let last_repeated = self.digipeaters.iter().rposition(|d| d.repeated);
+        for (i, digipeater) in self.digipeaters.iter().enumerate() {
+            write!(f, ",{}", digipeater)?;
+            if Some(i) == last_repeated {
+                write!(f, "*")?;
+            }
+        }
+        write!(f, ":{}", String::from_utf8_lossy(&self.info).trim_end())
+    }
+}
+
+/// Decodes AFSK1200 packets: Bell 202 tones carrying NRZI coded HDLC frames
+pub fn decode(samples: &[i16], sampling_rate: f64) -> Vec<Frame> {
+    StreamDecoder::new(sampling_rate)
+        .push(samples)
+        .iter()
+        .filter_map(|frame| Frame::parse(frame))
+        .collect()
+}
+
+/// Decodes AFSK1200 packets from audio as it arrives
+pub struct StreamDecoder {
+    demodulator: Demodulator,
+    clock: ClockRecovery,
+    hdlc: HdlcDecoder,
+    step: usize,
+    pending: Vec<i16>,
+}
+
+impl StreamDecoder {
+    pub fn new(sampling_rate: f64) -> Self {
+        let channel = Standard::Bell202.answer();
+        let samples_per_bit = sampling_rate / channel.baud;
+        let block_size = samples_per_bit.round() as usize;
+        let step = (block_size / TICKS_PER_BIT).max(1);
+        Self {
+            demodulator: Demodulator::new(block_size, channel.mark, channel.space, sampling_rate),
+            clock: ClockRecovery::new(samples_per_bit / step as f64),
+            hdlc: HdlcDecoder::new(),
+            step,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feeds the next samples, returning the contents of the frames they complete that
+    /// hold AX.25 addresses, without their FCS
+    pub fn push(&mut self, samples: &[i16]) -> Vec<Vec<u8>> {
+        self.pending.extend_from_slice(samples);
+        let decisions = self.demodulator.sliding_soft_bits(&self.pending, self.step);
+        self.pending.drain(..decisions.len() * self.step);
+        let (clock, hdlc) = (&mut self.clock, &mut self.hdlc);
+        decisions
+            .into_iter()
+            .filter_map(|soft| clock.push(soft))
+            .filter_map(|soft| hdlc.push(u8::from(soft > 0.0)))
+            .filter(|frame| Frame::parse(frame).is_some())
+            .collect()
+    }
+}
+
+/// Modulates a frame's contents as AFSK1200 with `flags` flags either side, the ones
+/// before it giving a transmitter time to key up
+#[cfg_attr(not(feature = "live"), allow(dead_code))]
+pub fn modulate(frame: &[u8], flags: usize, sampling_rate: f64) -> Vec<i16> {
+    let channel = Standard::Bell202.answer();
+    let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+    let mut samples = Vec::new();
+    modulator.modulate(&hdlc::encode(frame, flags), &mut samples);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(callsign: &str, ssid: u8, flags: u8) -> Vec<u8> {
+        let mut bytes: Vec<u8> = format!("{:6}", callsign).bytes().map(|b| b << 1).collect();
+        bytes.push(0x60 | ssid << 1 | flags);
+        bytes
+    }
+
+    fn aprs_frame() -> Vec<u8> {
+        let mut bytes = address("APRS", 0, 0);
+        bytes.extend(address("N0CALL", 9, 0));
+        bytes.extend(address("WIDE1", 1, 0x80));
+        bytes.extend(address("WIDE2", 1, 1));
+        bytes.extend(&[UI, 0xf0]);
+        bytes.extend(b"!4903.50N/07201.75W-Test\r");
+        bytes
+    }
+
+    #[test]
+    fn test_tnc2_format() {
+        let frame = Frame::parse(&aprs_frame()).unwrap();
+        assert_eq!(frame.pid, Some(0xf0));
+        assert_eq!(
+            frame.to_string(),
+            "N0CALL-9>APRS,WIDE1-1*,WIDE2-1:!4903.50N/07201.75W-Test"
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let channel = Standard::Bell202.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 48_000.0);
+        let mut samples = vec![0; 1234];
+        modulator.modulate(&hdlc::encode(&aprs_frame(), 20), &mut samples);
+        modulator.modulate(&hdlc::encode(b"too short", 4), &mut samples);
+        modulator.modulate(&hdlc::encode(&aprs_frame(), 4), &mut samples);
+
+        let frames = decode(&samples, 48_000.0);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], Frame::parse(&aprs_frame()).unwrap());
+    }
+
+    #[test]
+    fn test_stream_decoder() {
+        let mut samples = vec![0; 777];
+        samples.extend(modulate(&aprs_frame(), 10, 44_100.0));
+        samples.extend(modulate(&aprs_frame(), 10, 44_100.0));
+        let mut decoder = StreamDecoder::new(44_100.0);
+        let frames: Vec<_> = samples
+            .chunks(441)
+            .flat_map(|block| decoder.push(block))
+            .collect();
+        assert_eq!(frames, [aprs_frame(), aprs_frame()]);
+    }
+}
@@ -0,0 +1,412 @@
+//! Demodulator backends, the algorithms that judge each window of audio mark or space for
+//! the framing and I/O around them. Programs embedding the crate add their own to a
+//! [`Registry`] at compile time; anyone else builds a plugin, a shared library implementing
+//! `include/bell103_plugin.h`, which [`Registry::load`] opens at runtime. A backend can
+//! bring its own tones and baud too, for a standard this crate doesn't know.
+
+use crate::demodulator::{Detector, GoertzelDetector, GoertzelDetector32};
+use crate::standard::Channel;
+use std::ffi::{c_void, CStr};
+use std::io;
+use std::os::raw::c_char;
+use std::path::Path;
+
+/// The version of `include/bell103_plugin.h` that plugins are checked against
+pub const BELL103_PLUGIN_ABI: u32 = 1;
+
+/// A way of demodulating, which makes a [`Detector`] for each channel decoded
+pub trait Backend: Send + Sync {
+    /// The name it's chosen by, e.g. with `--backend`
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// The channel to decode in place of the one the standard and origin choose, for a
+    /// backend that brings its own standard
+    fn channel(&self, _origin: bool) -> Option<Channel> {
+        None
+    }
+
+    /// A detector deciding windows of `window` samples on `channel`
+    fn detector(
+        &self,
+        channel: Channel,
+        window: usize,
+        sampling_rate: f64,
+    ) -> Result<Box<dyn Detector>, String>;
+}
+
+/// Mark and space Goertzel filter energy, as [`crate::demodulator::Demodulator::new`] compares
+#[derive(Debug)]
+pub struct Goertzel;
+
+impl Backend for Goertzel {
+    fn name(&self) -> &str {
+        "goertzel"
+    }
+
+    fn description(&self) -> &str {
+        "Goertzel filters at the mark and space tones"
+    }
+
+    fn detector(
+        &self,
+        channel: Channel,
+        window: usize,
+        sampling_rate: f64,
+    ) -> Result<Box<dyn Detector>, String> {
+        let detector = GoertzelDetector::new(window, channel.mark, channel.space, sampling_rate);
+        Ok(Box::new(detector))
+    }
+}
+
+/// [`Goertzel`] in single precision, for processors faster at it; `cargo bench --bench dsp --
+/// --precision` compares the two
+#[derive(Debug)]
+pub struct Goertzel32;
+
+impl Backend for Goertzel32 {
+    fn name(&self) -> &str {
+        "goertzel32"
+    }
+
+    fn description(&self) -> &str {
+        "Goertzel filters at the mark and space tones in single precision"
+    }
+
+    fn detector(
+        &self,
+        channel: Channel,
+        window: usize,
+        sampling_rate: f64,
+    ) -> Result<Box<dyn Detector>, String> {
+        let detector = GoertzelDetector32::new(window, channel.mark, channel.space, sampling_rate);
+        Ok(Box::new(detector))
+    }
+}
+
+/// The backends to choose from by name, starting with the built-in [`Goertzel`] and
+/// [`Goertzel32`]
+pub struct Registry {
+    backends: Vec<Box<dyn Backend>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            backends: vec![Box::new(Goertzel), Box::new(Goertzel32)],
+        }
+    }
+}
+
+impl Registry {
+    /// Adds a backend, failing if one already has its name
+    pub fn register(&mut self, backend: Box<dyn Backend>) -> Result<(), String> {
+        if self.find(backend.name()).is_some() {
+            return Err(format!(
+                "there's already a backend named `{}`",
+                backend.name()
+            ));
+        }
+        self.backends.push(backend);
+        Ok(())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn Backend> {
+        self.backends
+            .iter()
+            .find(|backend| backend.name() == name)
+            .map(AsRef::as_ref)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Backend> {
+        self.backends.iter().map(AsRef::as_ref)
+    }
+
+    /// Registers the backends in the plugin at `path`, returning how many it had. The
+    /// library stays loaded for the rest of the program.
+    #[cfg(unix)]
+    pub fn load(&mut self, path: &Path) -> io::Result<usize> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let file = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| invalid("the path has a NUL in it".to_string()))?;
+        let dlerror = || {
+            let error = unsafe { libc::dlerror() };
+            if error.is_null() {
+                "unknown error".to_string()
+            } else {
+                unsafe { CStr::from_ptr(error) }
+                    .to_string_lossy()
+                    .into_owned()
+            }
+        };
+        let library = unsafe { libc::dlopen(file.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if library.is_null() {
+            return Err(io::Error::other(dlerror()));
+        }
+        let symbol =
+            unsafe { libc::dlsym(library, b"bell103_backends\0".as_ptr() as *const c_char) };
+        if symbol.is_null() {
+            return Err(invalid(dlerror()));
+        }
+        let backends: PluginEntry = unsafe { std::mem::transmute(symbol) };
+        let mut count = 0;
+        let first = unsafe { backends(&mut count) };
+        if first.is_null() && count > 0 {
+            return Err(invalid("bell103_backends returned null".to_string()));
+        }
+        for i in 0..count {
+            let backend = unsafe { &*first.add(i) };
+            self.register(Box::new(unsafe { Plugin::new(backend) }.map_err(invalid)?))
+                .map_err(invalid)?;
+        }
+        Ok(count)
+    }
+
+    #[cfg(not(unix))]
+    pub fn load(&mut self, _path: &Path) -> io::Result<usize> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "plugins can only be loaded on Unix",
+        ))
+    }
+}
+
+/// `bell103_backends`, which a plugin exports
+#[cfg(unix)]
+type PluginEntry = unsafe extern "C" fn(count: *mut usize) -> *const Bell103Backend;
+
+/// A backend as a plugin describes it to C, see `include/bell103_plugin.h`
+#[repr(C)]
+#[derive(Debug)]
+pub struct Bell103Backend {
+    pub abi: u32,
+    pub name: *const c_char,
+    pub description: *const c_char,
+    /// The channels of the backend's own standard, or null to decode the one chosen
+    pub originate: *const Channel,
+    pub answer: *const Channel,
+    pub create: Option<
+        unsafe extern "C" fn(
+            channel: *const Channel,
+            window: usize,
+            sampling_rate: f64,
+        ) -> *mut c_void,
+    >,
+    pub decide:
+        Option<unsafe extern "C" fn(detector: *mut c_void, window: *const i16, len: usize) -> f64>,
+    pub destroy: Option<unsafe extern "C" fn(detector: *mut c_void)>,
+}
+
+// Plugins describe their backends in static data, which nothing changes, and their
+// functions are only ever called with a detector of their own
+unsafe impl Sync for Bell103Backend {}
+
+/// A plugin's backend, which has to outlive the program
+#[derive(Debug)]
+struct Plugin {
+    backend: &'static Bell103Backend,
+    name: String,
+    description: String,
+}
+
+impl Plugin {
+    /// # Safety
+    ///
+    /// Every pointer in `backend` must be valid for `'static`, its strings NUL terminated.
+    unsafe fn new(backend: &'static Bell103Backend) -> Result<Self, String> {
+        if backend.abi != BELL103_PLUGIN_ABI {
+            return Err(format!(
+                "a backend is for plugin ABI {}, not {}",
+                backend.abi, BELL103_PLUGIN_ABI
+            ));
+        }
+        if backend.name.is_null()
+            || backend.create.is_none()
+            || backend.decide.is_none()
+            || backend.destroy.is_none()
+        {
+            return Err("a backend is missing its name or functions".to_string());
+        }
+        let text = |text: *const c_char| {
+            if text.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(text).to_string_lossy().into_owned()
+            }
+        };
+        Ok(Self {
+            backend,
+            name: text(backend.name),
+            description: text(backend.description),
+        })
+    }
+}
+
+impl Backend for Plugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn channel(&self, origin: bool) -> Option<Channel> {
+        let channel = if origin {
+            self.backend.originate
+        } else {
+            self.backend.answer
+        };
+        unsafe { channel.as_ref() }.copied()
+    }
+
+    fn detector(
+        &self,
+        channel: Channel,
+        window: usize,
+        sampling_rate: f64,
+    ) -> Result<Box<dyn Detector>, String> {
+        let create = self.backend.create.unwrap();
+        let state = unsafe { create(&channel, window, sampling_rate) };
+        if state.is_null() {
+            return Err(format!(
+                "the {} backend can't decode {}/{} Hz at {} baud",
+                self.name, channel.mark, channel.space, channel.baud
+            ));
+        }
+        Ok(Box::new(PluginDetector {
+            backend: self.backend,
+            state,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct PluginDetector {
+    backend: &'static Bell103Backend,
+    state: *mut c_void,
+}
+
+// A detector's state belongs to it alone, and is only used from one thread at a time
+unsafe impl Send for PluginDetector {}
+
+impl Detector for PluginDetector {
+    fn decide(&mut self, window: &[i16]) -> f64 {
+        let decide = self.backend.decide.unwrap();
+        let soft = unsafe { decide(self.state, window.as_ptr(), window.len()) };
+        // Treat a plugin's NaN as no decision either way
+        if soft.is_nan() {
+            0.0
+        } else {
+            soft.clamp(-1.0, 1.0)
+        }
+    }
+}
+
+impl Drop for PluginDetector {
+    fn drop(&mut self) {
+        unsafe { (self.backend.destroy.unwrap())(self.state) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demodulator::Demodulator;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+    static TONES: Channel = Channel {
+        mark: 1_000.0,
+        space: 2_000.0,
+        baud: 100.0,
+    };
+
+    /// Decides by whether a window crosses zero above or below the halfway rate, as a plugin
+    /// written in C might
+    unsafe extern "C" fn create(channel: *const Channel, _: usize, rate: f64) -> *mut c_void {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        let channel = &*channel;
+        Box::into_raw(Box::new((channel.mark + channel.space) / rate)) as *mut c_void
+    }
+
+    unsafe extern "C" fn decide(detector: *mut c_void, window: *const i16, len: usize) -> f64 {
+        let threshold = *(detector as *const f64);
+        let window = std::slice::from_raw_parts(window, len);
+        let crossings = window
+            .windows(2)
+            .filter(|pair| (pair[0] < 0) != (pair[1] < 0))
+            .count();
+        if (crossings as f64) < threshold * len as f64 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    unsafe extern "C" fn destroy(detector: *mut c_void) {
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        drop(Box::from_raw(detector as *mut f64));
+    }
+
+    static CROSSINGS: Bell103Backend = Bell103Backend {
+        abi: BELL103_PLUGIN_ABI,
+        name: b"crossings\0".as_ptr() as *const c_char,
+        description: b"Zero crossings\0".as_ptr() as *const c_char,
+        originate: &TONES,
+        answer: std::ptr::null(),
+        create: Some(create),
+        decide: Some(decide),
+        destroy: Some(destroy),
+    };
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::default();
+        let plugin = unsafe { Plugin::new(&CROSSINGS) }.unwrap();
+        registry.register(Box::new(plugin)).unwrap();
+        assert!(registry.register(Box::new(Goertzel)).is_err());
+        let names: Vec<_> = registry.iter().map(Backend::name).collect();
+        assert_eq!(names, ["goertzel", "goertzel32", "crossings"]);
+
+        let bits = [0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1];
+        for name in &["goertzel", "goertzel32", "crossings"] {
+            let backend = registry.find(name).unwrap();
+            let channel = backend
+                .channel(true)
+                .unwrap_or_else(|| Standard::Bell103.answer());
+            let mut samples = Vec::new();
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE)
+                .modulate(&bits, &mut samples);
+            let window = (SAMPLING_RATE / channel.baud) as usize;
+            let detector = backend.detector(channel, window, SAMPLING_RATE).unwrap();
+            let mut demodulator = Demodulator::with_detector(window, detector);
+            assert_eq!(demodulator.bits(&samples), bits, "{}", name);
+        }
+        assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+        assert_eq!(registry.find("crossings").unwrap().channel(false), None);
+        assert!(registry.find("nonesuch").is_none());
+
+        let future = Bell103Backend {
+            abi: BELL103_PLUGIN_ABI + 1,
+            ..CROSSINGS
+        };
+        let future: &'static _ = Box::leak(Box::new(future));
+        assert!(unsafe { Plugin::new(future) }.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load() {
+        let mut registry = Registry::default();
+        assert!(registry.load(Path::new("/nonexistent/plugin.so")).is_err());
+    }
+}
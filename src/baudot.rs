@@ -0,0 +1,130 @@
+/// Shift codes
+pub const LTRS: u8 = 0x1f;
+pub const FIGS: u8 = 0x1b;
+const SPACE: u8 = 0x04;
+const NUL: char = '\0';
+
+/// ITA2 letters case, indexed by code. Shift codes map to NUL.
+const LETTERS: [char; 32] = [
+    NUL, 'E', '\n', 'A', ' ', 'S', 'I', 'U', '\r', 'D', 'R', 'J', 'N', 'F', 'C', 'K', 'T', 'Z',
+    'L', 'W', 'H', 'Y', 'P', 'Q', 'O', 'B', 'G', NUL, 'M', 'X', 'V', NUL,
+];
+
+/// US TTY figures case, as used for amateur RTTY and TDDs
+const FIGURES: [char; 32] = [
+    NUL, '3', '\n', '-', ' ', '\x07', '8', '7', '\r', '$', '4', '\'', ',', '!', ':', '(', '5', '"',
+    ')', '2', '#', '6', '0', '1', '9', '?', '&', NUL, '.', '/', ';', NUL,
+];
+
+/// International ITA2 figures case, as used for NAVTEX. WHO ARE YOU and the unassigned codes
+/// map to NUL.
+const ITA2_FIGURES: [char; 32] = [
+    NUL, '3', '\n', '-', ' ', '\'', '8', '7', '\r', NUL, '4', '\x07', ',', NUL, ':', '(', '5', '+',
+    ')', '2', NUL, '6', '0', '1', '9', '?', NUL, NUL, '.', '/', '=', NUL,
+];
+
+/// Tracks the letters/figures shift while decoding 5-bit Baudot codes
+#[derive(Debug)]
+pub struct BaudotDecoder {
+    figures: bool,
+    unshift_on_space: bool,
+    figures_case: &'static [char; 32],
+}
+
+impl BaudotDecoder {
+    /// With `unshift_on_space` a space returns to letters, which limits the damage a missed
+    /// LTRS does on noisy links
+    pub fn new(unshift_on_space: bool) -> Self {
+        Self {
+            figures: false,
+            unshift_on_space,
+            figures_case: &FIGURES,
+        }
+    }
+
+    /// A decoder using the international figures case instead of the US one
+    pub fn international(unshift_on_space: bool) -> Self {
+        Self {
+            figures_case: &ITA2_FIGURES,
+            ..Self::new(unshift_on_space)
+        }
+    }
+
+    /// Decodes one code, returning `None` for shifts and NUL
+    pub fn decode(&mut self, code: u8) -> Option<char> {
+        let code = code & 0x1f;
+        match code {
+            LTRS => self.figures = false,
+            FIGS => self.figures = true,
+            SPACE if self.unshift_on_space => self.figures = false,
+            _ => (),
+        }
+        let table = if self.figures {
+            self.figures_case
+        } else {
+            &LETTERS
+        };
+        Some(table[usize::from(code)]).filter(|&c| c != NUL)
+    }
+}
+
+/// Encodes text as Baudot codes, starting in letters and inserting shifts as needed.
+/// Lowercase letters are sent as uppercase and characters without a code are skipped.
+pub fn encode(text: &str) -> Vec<u8> {
+    let mut codes = vec![LTRS];
+    let mut figures = false;
+    for c in text.chars().map(|c| c.to_ascii_uppercase()) {
+        let find = |table: &[char; 32]| table.iter().position(|&t| t == c && c != NUL);
+        // Characters in both cases don't need a shift
+        let code = match (find(&LETTERS), find(&FIGURES)) {
+            (Some(l), Some(f)) if l == f => l,
+            (Some(l), _) => {
+                if figures {
+                    codes.push(LTRS);
+                    figures = false;
+                }
+                l
+            }
+            (None, Some(f)) => {
+                if !figures {
+                    codes.push(FIGS);
+                    figures = true;
+                }
+                f
+            }
+            (None, None) => continue,
+        };
+        codes.push(code as u8);
+    }
+    codes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(codes: &[u8], unshift_on_space: bool) -> String {
+        let mut decoder = BaudotDecoder::new(unshift_on_space);
+        codes.iter().filter_map(|&c| decoder.decode(c)).collect()
+    }
+
+    #[test]
+    fn test_decode() {
+        // RY with figures and a space
+        let codes = [LTRS, 0x0a, 0x15, FIGS, 0x17, 0x13, SPACE, 0x17];
+        assert_eq!(decode(&codes, false), "RY12 1");
+        assert_eq!(decode(&codes, true), "RY12 Q");
+
+        let mut decoder = BaudotDecoder::international(false);
+        assert_eq!(decoder.decode(FIGS), None);
+        assert_eq!(decoder.decode(0x1e), Some('='));
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let text = "CQ CQ DE N0CALL 599 73.\r\n";
+        let codes = encode(&text.to_lowercase());
+        assert_eq!(codes[..3], [LTRS, 0x0e, 0x17]);
+        assert_eq!(decode(&codes, false), text);
+    }
+}
@@ -0,0 +1,115 @@
+//! A graphical front end for decoding recordings without the command line. It serves a page
+//! on this machine and opens it in the browser, where a WAV file is dropped to see its decoded
+//! text and a plot of the mark and space tones.
+
+use bell103_demodulator::{events, gui, http};
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::process::Command;
+use std::thread;
+use structopt::clap::{Error, ErrorKind};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "bell103_gui")]
+struct Opt {
+    #[structopt(
+        long = "listen",
+        default_value = "127.0.0.1:0",
+        help = "Address to serve the page on [default: a free port on this machine]"
+    )]
+    listen: String,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate to decode at, recordings made at another are resampled to it"
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "no_browser",
+        help = "Print the page's address instead of opening it"
+    )]
+    no_browser: bool,
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    let listener = TcpListener::bind(&opt.listen).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't serve on {}: {}", opt.listen, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    eprintln!("Serving the GUI on {}, press Ctrl-C to stop", url);
+    if !opt.no_browser {
+        if let Err(e) = open_browser(&url) {
+            eprintln!("Couldn't open a browser, open {} in one: {}", url, e);
+        }
+    }
+    let sampling_rate = opt.sampling_rate;
+    for client in listener.incoming().flatten() {
+        thread::spawn(move || serve_client(client, sampling_rate));
+    }
+}
+
+/// Opens `url` in the desktop's browser
+fn open_browser(url: &str) -> io::Result<()> {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", "", url]);
+        command
+    } else {
+        let mut command = Command::new(if cfg!(target_os = "macos") {
+            "open"
+        } else {
+            "xdg-open"
+        });
+        command.arg(url);
+        command
+    };
+    command.spawn().map(drop)
+}
+
+/// Answers one request: `GET /` for the page, or `POST /decode` with a recording dropped on it
+fn serve_client(mut client: TcpStream, sampling_rate: f64) {
+    let request = client
+        .try_clone()
+        .and_then(|reader| http::read_request(BufReader::new(reader)));
+    let (status, content_type, body) = match request {
+        Err(e) => (
+            "400 Bad Request",
+            "application/json",
+            json_error(&e.to_string()),
+        ),
+        Ok(request) => match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/") => ("200 OK", "text/html; charset=utf-8", gui::PAGE.to_string()),
+            ("POST", "/decode") => {
+                let origin = matches!(request.parameter("origin"), Some("" | "1" | "true"));
+                let decoded = request
+                    .parameter("standard")
+                    .unwrap_or("auto")
+                    .parse()
+                    .and_then(|selection| {
+                        gui::decode(&request.body, selection, origin, sampling_rate)
+                    });
+                match decoded {
+                    Ok(decoded) => ("200 OK", "application/json", decoded.json()),
+                    Err(e) => ("400 Bad Request", "application/json", json_error(&e)),
+                }
+            }
+            _ => (
+                "404 Not Found",
+                "application/json",
+                json_error("expected GET / or POST /decode"),
+            ),
+        },
+    };
+    let _ = http::respond(&mut client, status, content_type, body.as_bytes());
+}
+
+fn json_error(message: &str) -> String {
+    format!(r#"{{"error":{}}}"#, events::json_string(message))
+}
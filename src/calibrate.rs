@@ -0,0 +1,149 @@
+use crate::assembly::{centred, Assembler};
+use crate::demodulator::Demodulator;
+use crate::framing::frame;
+use crate::modulator::FskModulator;
+use crate::standard::Channel;
+
+/// Window lengths tried, in bit periods: shorter ones hear less of the bits either side, and
+/// longer ones tell two close tones apart better
+const WINDOWS: [f64; 9] = [0.5, 0.625, 0.75, 0.875, 1.0, 1.25, 1.5, 1.75, 2.0];
+/// How far the reference is put off the blocks, in bit periods: a recording's bits rarely
+/// start where a block does, and a window's judged by how it does however they fall
+const OFFSETS: [f64; 4] = [0.0, 0.125, 0.25, 0.375];
+/// Idle mark either side of the reference, a frame's worth so the blocks keep in step
+const IDLE_BITS: usize = 10;
+
+/// How well bits were told apart with one window length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+    /// The samples each bit is decided on, centred on the bit
+    pub window: usize,
+    pub bit_length: usize,
+    /// The least any bit of the reference was decided by, between -1.0 and 1.0: the soft
+    /// decision for a mark, or its negative for a space, so below zero a bit came out wrong
+    pub margin: f64,
+    /// Characters of the reference decoded wrong
+    pub errors: usize,
+}
+
+impl Calibration {
+    /// How much of each window overlaps the bits either side, as a share of a bit, negative
+    /// for a gap between windows
+    pub fn overlap(&self) -> f64 {
+        (self.window as f64 - self.bit_length as f64) / self.bit_length as f64
+    }
+}
+
+/// Every character sent in turn, so each bit is heard next to every pattern of neighbours
+fn reference() -> Vec<u8> {
+    (0..=255).collect()
+}
+
+/// Decodes a synthetic recording of `channel` at `sampling_rate` with each of a range of
+/// window lengths, made into demodulators by `demodulator`, and returns how each did, best
+/// first: those decoding the most characters right, then those with the widest margin. The
+/// recording's decoded with its bits put a little off the blocks too, and each window judged
+/// by the worst of them. `bit_length` must be at least a sample.
+pub fn calibrate<F>(
+    channel: Channel,
+    sampling_rate: f64,
+    bit_length: usize,
+    mut demodulator: F,
+) -> Vec<Calibration>
+where
+    F: FnMut(usize) -> Demodulator,
+{
+    let message = reference();
+    let mut bits = vec![1; IDLE_BITS];
+    bits.extend(frame(&message));
+    bits.extend(vec![1; IDLE_BITS]);
+    let recordings: Vec<(usize, Vec<i16>)> = OFFSETS
+        .iter()
+        .map(|share| {
+            let mut modulator =
+                FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+            let mut samples = Vec::new();
+            modulator.hold(1, *share, &mut samples);
+            let offset = samples.len();
+            modulator.modulate(&bits, &mut samples);
+            (offset, samples)
+        })
+        .collect();
+
+    let mut windows: Vec<usize> = WINDOWS
+        .iter()
+        .map(|share| ((bit_length as f64 * share).round() as usize).max(1))
+        .collect();
+    windows.dedup();
+    let mut calibrations: Vec<Calibration> = windows
+        .into_iter()
+        .map(|window| {
+            let mut calibration = Calibration {
+                window,
+                bit_length,
+                margin: 1.0,
+                errors: 0,
+            };
+            for (offset, samples) in &recordings {
+                let mut assembler = Assembler::with_bit_length(demodulator(window), bit_length)
+                    .expect("a bit and its windows are at least a sample long");
+                let mut decoded = Vec::new();
+                assembler.push(samples, &mut decoded);
+                assembler.finish(&mut decoded);
+                let errors = message
+                    .iter()
+                    .zip(&decoded)
+                    .filter(|(sent, received)| sent != received)
+                    .count()
+                    + message.len().abs_diff(decoded.len());
+                calibration.errors = calibration.errors.max(errors);
+
+                // Each bit's judged on the block it's decided in: the one its middle falls in
+                let mut demodulator = demodulator(window);
+                let margin = bits
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &bit)| {
+                        let middle = offset + i * bit_length + bit_length / 2;
+                        let block = middle / bit_length * bit_length;
+                        let decided = centred(block, bit_length, window);
+                        let end = decided.end.min(samples.len());
+                        let soft = demodulator.soft_bit(&samples[decided.start.min(end)..end]);
+                        if bit == 1 {
+                            soft
+                        } else {
+                            -soft
+                        }
+                    })
+                    .fold(1.0, f64::min);
+                calibration.margin = calibration.margin.min(margin);
+            }
+            calibration
+        })
+        .collect();
+    calibrations.sort_by(|a, b| a.errors.cmp(&b.errors).then(b.margin.total_cmp(&a.margin)));
+    calibrations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::standard::Standard;
+
+    #[test]
+    fn test_calibrate() {
+        let channel = Standard::Bell103.answer();
+        let calibrations = calibrate(channel, 48_000.0, 160, |window| {
+            Demodulator::new(window, channel.mark, channel.space, 48_000.0)
+        });
+        assert_eq!(calibrations.len(), WINDOWS.len());
+        let best = calibrations[0];
+        assert_eq!(best.errors, 0);
+        assert!(best.margin > 0.0);
+        // A bit's own samples decode cleanly too, but not with the best margin
+        let bit = calibrations.iter().find(|c| c.window == 160).unwrap();
+        assert_eq!(bit.errors, 0);
+        assert!(best.margin >= bit.margin);
+        assert_eq!(bit.overlap(), 0.0);
+    }
+}
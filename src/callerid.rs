@@ -0,0 +1,268 @@
+use crate::framing::{self, Received};
+use crate::standard::Standard;
+use std::fmt;
+
+/// Message types
+const SDMF: u8 = 0x04;
+const MDMF: u8 = 0x80;
+/// Channel seizure is a run of alternating bits, which frames as 0x55
+const SEIZURE: u8 = 0x55;
+/// Shortest run of mark accepted ahead of a message. On-hook caller ID sends 180 bits and
+/// off-hook (call waiting) sends about 80 ms, or 96 bits.
+const MIN_MARK_BITS: f64 = 50.0;
+
+/// Caller ID message formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Single Data Message Format: date, time and number only
+    Sdmf,
+    /// Multiple Data Message Format: a list of typed parameters
+    Mdmf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    /// Month, day, hour and minute as MMDDHHMM
+    DateTime(String),
+    Number(String),
+    /// Why the number was withheld, `O` for unavailable or `P` for private
+    NumberAbsent(String),
+    Name(String),
+    NameAbsent(String),
+    Unknown(u8, Vec<u8>),
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Field::DateTime(date) if date.len() == 8 && date.is_ascii() => write!(
+                f,
+                "Date: {}/{} {}:{}",
+                &date[0..2],
+                &date[2..4],
+                &date[4..6],
+                &date[6..8]
+            ),
+            Field::DateTime(date) => write!(f, "Date: {}", date),
+            Field::Number(number) => write!(f, "Number: {}", number),
+            Field::NumberAbsent(reason) => write!(f, "Number: {}", absence(reason)),
+            Field::Name(name) => write!(f, "Name: {}", name),
+            Field::NameAbsent(reason) => write!(f, "Name: {}", absence(reason)),
+            Field::Unknown(kind, data) => write!(f, "Parameter {:#04x}: {:02x?}", kind, data),
+        }
+    }
+}
+
+fn absence(reason: &str) -> &str {
+    match reason {
+        "O" => "unavailable",
+        "P" => "private",
+        reason => reason,
+    }
+}
+
+/// A caller ID message and the preamble found ahead of it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub format: Format,
+    pub fields: Vec<Field>,
+    pub checksum_ok: bool,
+    pub seizure_bytes: usize,
+    pub mark_bits: usize,
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Caller ID ({:?}), checksum {}, seizure {} bytes, mark {} bits",
+            self.format,
+            if self.checksum_ok { "OK" } else { "FAILED" },
+            self.seizure_bytes,
+            self.mark_bits
+        )?;
+        for field in &self.fields {
+            writeln!(f, "  {}", field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes caller ID messages from a Bell 202 phone line recording
+pub fn decode(samples: &[i16], sampling_rate: f64) -> Vec<Message> {
+    let (received, ticks_per_bit) =
+        framing::receive(samples, Standard::Bell202.answer(), sampling_rate, 8);
+    find_messages(&received, ticks_per_bit)
+}
+
+/// Finds messages in received characters. A message starts with an SDMF or MDMF type byte
+/// and is accepted when its checksum is valid, or when it follows a mark period so that a
+/// corrupted message is still reported.
+pub fn find_messages(received: &[Received], ticks_per_bit: f64) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let mut seizure_bytes = 0;
+    let mut i = 0;
+    while i < received.len() {
+        // Idle bits between the end of the previous character and the start of this one
+        let gap = match i {
+            0 => received[0].tick as f64 / ticks_per_bit,
+            _ => (received[i].tick - received[i - 1].tick) as f64 / ticks_per_bit - 10.0,
+        };
+        let bytes: Vec<u8> = received[i..].iter().map(|r| r.byte).collect();
+        if let Some((format, fields, checksum_ok, length)) = parse(&bytes) {
+            if checksum_ok || gap >= MIN_MARK_BITS {
+                messages.push(Message {
+                    format,
+                    fields,
+                    checksum_ok,
+                    seizure_bytes,
+                    mark_bits: gap.max(0.0).round() as usize,
+                });
+                seizure_bytes = 0;
+                i += length;
+                continue;
+            }
+        }
+        seizure_bytes = match (received[i].byte, seizure_bytes) {
+            (SEIZURE, 0) => 1,
+            (SEIZURE, count) if gap < 2.0 => count + 1,
+            _ => 0,
+        };
+        i += 1;
+    }
+    messages
+}
+
+/// Parses a message at the start of `bytes`, returning its fields, whether the checksum
+/// matched and how many bytes it used
+fn parse(bytes: &[u8]) -> Option<(Format, Vec<Field>, bool, usize)> {
+    let format = match *bytes.first()? {
+        SDMF => Format::Sdmf,
+        MDMF => Format::Mdmf,
+        _ => return None,
+    };
+    let length = 3 + usize::from(*bytes.get(1)?);
+    let message = bytes.get(..length)?;
+    // All bytes including the checksum sum to zero
+    let checksum_ok = message.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0;
+    let payload = &message[2..length - 1];
+    let fields = match format {
+        Format::Sdmf => parse_sdmf(payload),
+        Format::Mdmf => parse_mdmf(payload),
+    };
+    Some((format, fields, checksum_ok, length))
+}
+
+fn text(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b & 0x7f)).collect()
+}
+
+fn parse_sdmf(payload: &[u8]) -> Vec<Field> {
+    let split = payload.len().min(8);
+    let number = text(&payload[split..]);
+    let number = match number.as_str() {
+        "O" | "P" => Field::NumberAbsent(number),
+        _ => Field::Number(number),
+    };
+    vec![Field::DateTime(text(&payload[..split])), number]
+}
+
+fn parse_mdmf(mut payload: &[u8]) -> Vec<Field> {
+    let mut fields = Vec::new();
+    while payload.len() >= 2 {
+        let (kind, length) = (payload[0], usize::from(payload[1]));
+        let data = &payload[2..(2 + length).min(payload.len())];
+        fields.push(match kind {
+            0x01 => Field::DateTime(text(data)),
+            0x02 => Field::Number(text(data)),
+            0x04 => Field::NumberAbsent(text(data)),
+            0x07 => Field::Name(text(data)),
+            0x08 => Field::NameAbsent(text(data)),
+            kind => Field::Unknown(kind, data.to_vec()),
+        });
+        payload = &payload[2 + data.len()..];
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::frame;
+    use crate::modulator::FskModulator;
+
+    fn with_checksum(mut message: Vec<u8>) -> Vec<u8> {
+        let sum = message.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        message.push(sum.wrapping_neg());
+        message
+    }
+
+    #[test]
+    fn test_decode_mdmf() {
+        let mut message = vec![MDMF, 33, 0x01, 8];
+        message.extend(b"10141230");
+        message.extend(&[0x02, 10]);
+        message.extend(b"5035551234");
+        message.extend(&[0x07, 9]);
+        message.extend(b"LUKE PETH");
+        let message = with_checksum(message);
+
+        // Channel seizure, 180 bits of mark, then the message
+        let mut modulator = FskModulator::new(1200.0, 2200.0, 1200.0, 48_000.0);
+        let mut samples = vec![0; 123];
+        modulator.modulate(&frame(&[SEIZURE; 30]), &mut samples);
+        modulator.modulate(&[1; 180], &mut samples);
+        modulator.modulate(&frame(&message), &mut samples);
+        modulator.modulate(&[1; 20], &mut samples);
+
+        let messages = decode(&samples, 48_000.0);
+        assert_eq!(messages.len(), 1);
+        let decoded = &messages[0];
+        assert_eq!(decoded.format, Format::Mdmf);
+        assert!(decoded.checksum_ok);
+        assert!(decoded.seizure_bytes >= 28, "{}", decoded.seizure_bytes);
+        assert!(
+            (178..=182).contains(&decoded.mark_bits),
+            "{}",
+            decoded.mark_bits
+        );
+        assert_eq!(
+            decoded.fields,
+            [
+                Field::DateTime("10141230".to_string()),
+                Field::Number("5035551234".to_string()),
+                Field::Name("LUKE PETH".to_string()),
+            ]
+        );
+        assert_eq!(decoded.fields[0].to_string(), "Date: 10/14 12:30");
+    }
+
+    #[test]
+    fn test_find_messages_checks_checksum() {
+        let mut message = vec![SDMF, 9];
+        message.extend(b"01020304P");
+        let mut message = with_checksum(message);
+        let received = |bytes: &[u8], start: usize| -> Vec<Received> {
+            bytes
+                .iter()
+                .enumerate()
+                .map(|(i, &byte)| Received {
+                    byte,
+                    tick: (start + i * 10) * 8,
+                })
+                .collect()
+        };
+
+        let messages = find_messages(&received(&message, 0), 8.0);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].fields[1], Field::NumberAbsent("P".to_string()));
+        assert!(messages[0].checksum_ok);
+
+        // A corrupted message is only reported when it follows a mark period
+        message[5] ^= 0x01;
+        assert!(find_messages(&received(&message, 0), 8.0).is_empty());
+        let messages = find_messages(&received(&message, 120), 8.0);
+        assert_eq!(messages.len(), 1);
+        assert!(!messages[0].checksum_ok);
+    }
+}
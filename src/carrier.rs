@@ -0,0 +1,149 @@
+use crate::goertzel::GoertzelPair;
+use crate::standard::Channel;
+use std::ops::Range;
+
+/// A bit period carries the carrier when at least this share of its power is in the
+/// channel's tones. A steady tone puts about all of it there, white noise under 4 / N.
+const CARRIER_SHARE: f64 = 0.3;
+/// Carrier dropping out for fewer bits than this is a fade within a message, not its end
+const HANG_BITS: usize = 30;
+/// Bursts of carrier shorter than this, a couple of characters, are taken for noise
+const MIN_BITS: usize = 20;
+/// Bits of audio kept either side of a message when it's cut out
+const MARGIN_BITS: usize = 10;
+
+/// Finds where `channel`'s carrier is present in a recording, returning the sample range of
+/// each message it carries
+pub fn segments(samples: &[i16], channel: Channel, sampling_rate: f64) -> Vec<Range<usize>> {
+    let bit = (sampling_rate / channel.baud).round().max(1.0) as usize;
+    let filters = GoertzelPair::new(bit, [channel.mark, channel.space], sampling_rate);
+    let mut segments: Vec<Range<usize>> = Vec::new();
+    for (i, block) in samples.chunks_exact(bit).enumerate() {
+        if !carries(&filters, block) {
+            continue;
+        }
+        let start = i * bit;
+        match segments.last_mut() {
+            Some(last) if start - last.end < HANG_BITS * bit => last.end = start + bit,
+            _ => segments.push(start..start + bit),
+        }
+    }
+    segments.retain(|segment| segment.len() >= MIN_BITS * bit);
+    segments
+}
+
+/// Follows whether a channel's carrier is present in audio as it arrives, by the same test
+/// as [`segments`]: it's heard after [`MIN_BITS`] of carrier and lost after [`HANG_BITS`]
+/// without
+pub struct Detector {
+    filters: GoertzelPair,
+    bit: usize,
+    pending: Vec<i16>,
+    carrier_bits: usize,
+    quiet_bits: usize,
+    heard: bool,
+}
+
+impl Detector {
+    pub fn new(channel: Channel, sampling_rate: f64) -> Self {
+        let bit = (sampling_rate / channel.baud).round().max(1.0) as usize;
+        Self {
+            filters: GoertzelPair::new(bit, [channel.mark, channel.space], sampling_rate),
+            bit,
+            pending: Vec::new(),
+            carrier_bits: 0,
+            quiet_bits: 0,
+            heard: false,
+        }
+    }
+
+    /// Takes the next block of audio, returning whether the carrier is heard by its end
+    pub fn push(&mut self, samples: &[i16]) -> bool {
+        self.pending.extend_from_slice(samples);
+        let whole = self.pending.len() / self.bit * self.bit;
+        for block in self.pending[..whole].chunks_exact(self.bit) {
+            if carries(&self.filters, block) {
+                self.carrier_bits += 1;
+                self.quiet_bits = 0;
+                self.heard |= self.carrier_bits >= MIN_BITS;
+            } else {
+                self.quiet_bits += 1;
+                if self.quiet_bits >= HANG_BITS {
+                    self.carrier_bits = 0;
+                    self.heard = false;
+                }
+            }
+        }
+        self.pending.drain(..whole);
+        self.heard
+    }
+}
+
+/// Whether most of a bit period's power is in the channel's tones
+fn carries(filters: &GoertzelPair, block: &[i16]) -> bool {
+    let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+    let in_band: f64 = filters.energies(block).iter().sum();
+    // The tones' magnitudes squared scale with the block length over the plain energy
+    energy > 0.0 && in_band / (energy * block.len() as f64 / 2.0) >= CARRIER_SHARE
+}
+
+/// Widens a message's range by [`MARGIN_BITS`] on each side, within the recording
+pub fn with_margin(
+    segment: &Range<usize>,
+    channel: Channel,
+    sampling_rate: f64,
+    len: usize,
+) -> Range<usize> {
+    let margin = (MARGIN_BITS as f64 * sampling_rate / channel.baud) as usize;
+    segment.start.saturating_sub(margin)..(segment.end + margin).min(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+
+    #[test]
+    fn test_segments() {
+        let channel = Standard::Bell103.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        // Quiet noise, a message, a gap and another message with a short fade in it
+        let mut samples: Vec<i16> = (0..4_000).map(|i| (i * 7919 % 201) as i16 - 100).collect();
+        modulator.modulate(&framing::frame(b"first"), &mut samples);
+        let first = 4_000..samples.len();
+        samples.extend(vec![0; 8_000]);
+        let second = samples.len();
+        modulator.modulate(&framing::frame(b"sec"), &mut samples);
+        samples.extend(vec![0; 100]);
+        modulator.modulate(&framing::frame(b"ond"), &mut samples);
+        let second = second..samples.len();
+        samples.extend(vec![0; 1_000]);
+
+        let found = segments(&samples, channel, 8_000.0);
+        assert_eq!(found.len(), 2);
+        let close = |a: usize, b: usize| a.abs_diff(b) <= 27;
+        assert!(close(found[0].start, first.start) && close(found[0].end, first.end));
+        assert!(close(found[1].start, second.start) && close(found[1].end, second.end));
+        assert!(segments(&vec![0; 8_000], channel, 8_000.0).is_empty());
+
+        let widened = with_margin(&found[0], channel, 8_000.0, samples.len());
+        assert_eq!(widened.end - found[0].end, 266);
+        assert_eq!(with_margin(&(10..20), channel, 8_000.0, 30), 0..30);
+    }
+
+    #[test]
+    fn test_detector() {
+        let channel = Standard::Bell103.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        let mut detector = Detector::new(channel, 8_000.0);
+        let mut idle = Vec::new();
+        modulator.modulate(&[1; 30], &mut idle);
+        assert!(!detector.push(&idle[..idle.len() / 3]));
+        assert!(detector.push(&idle[idle.len() / 3..]));
+        // Fades are ridden out, but not the carrier going away
+        assert!(detector.push(&vec![0; 20 * 27]));
+        assert!(!detector.push(&vec![0; 20 * 27]));
+    }
+}
@@ -0,0 +1,279 @@
+use crate::clock::ClockRecovery;
+use crate::framing;
+use crate::standard::Channel;
+use std::fmt;
+use std::str::FromStr;
+
+/// Characters further apart than this many bit periods are in separate blocks, since blocks
+/// are separated by leader tone
+const BLOCK_GAP_BITS: f64 = 20.0;
+/// Tarbell blocks start with a run of preamble bytes and a sync byte
+const TARBELL_PREAMBLE: u8 = 0x3c;
+const TARBELL_SYNC: u8 = 0xe6;
+const TARBELL_PREAMBLE_MATCH: usize = 2;
+const MSX_ID_LEN: usize = 10;
+const MSX_NAME_LEN: usize = 6;
+/// File types marked by the ten bytes that start an MSX header block
+const MSX_FILE_TYPES: [(u8, &str); 3] = [(0xd3, "BASIC"), (0xea, "ASCII"), (0xd0, "binary")];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TapeFormat {
+    /// Kansas City standard, 300 baud with 1200 Hz for 0 and 2400 Hz for 1
+    Kcs,
+    /// Processor Technology CUTS at 1200 baud, one cycle of 1200 Hz for 0 and two of
+    /// 2400 Hz for 1
+    Cuts,
+    /// Tarbell, synchronous phase encoding at 1500 bits/s
+    Tarbell,
+    /// MSX, one cycle at the baud rate for 0 and two for 1, at 1200 or 2400 baud
+    Msx,
+}
+
+impl TapeFormat {
+    pub fn default_baud(self) -> f64 {
+        match self {
+            TapeFormat::Kcs => 300.0,
+            TapeFormat::Cuts | TapeFormat::Msx => 1200.0,
+            TapeFormat::Tarbell => 1500.0,
+        }
+    }
+
+    /// The tones the format's asynchronous characters are sent with at `baud`, which
+    /// Tarbell's synchronous recording has none of
+    pub fn channel(self, baud: f64) -> Option<Channel> {
+        let (space, mark) = match self {
+            TapeFormat::Kcs | TapeFormat::Cuts => (1200.0, 2400.0),
+            TapeFormat::Msx => (baud, 2.0 * baud),
+            TapeFormat::Tarbell => return None,
+        };
+        Some(Channel { mark, space, baud })
+    }
+}
+
+impl FromStr for TapeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "kcs" => Ok(TapeFormat::Kcs),
+            "cuts" => Ok(TapeFormat::Cuts),
+            "tarbell" => Ok(TapeFormat::Tarbell),
+            "msx" => Ok(TapeFormat::Msx),
+            _ => Err(format!(
+                "`{}` is not a tape format, expected kcs, cuts, tarbell or msx",
+                s
+            )),
+        }
+    }
+}
+
+/// A block of bytes recorded between leader tones, or after a sync byte for Tarbell
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    /// Seconds from the start of the recording
+    pub start: f64,
+    pub data: Vec<u8>,
+}
+
+impl Block {
+    /// The file type and name if this is an MSX header block
+    pub fn msx_header(&self) -> Option<(&'static str, String)> {
+        if self.data.len() != MSX_ID_LEN + MSX_NAME_LEN {
+            return None;
+        }
+        let (id, name) = self.data.split_at(MSX_ID_LEN);
+        let (_, file_type) = MSX_FILE_TYPES.iter().find(|&&(b, _)| b == id[0])?;
+        if id.iter().any(|&b| b != id[0]) {
+            return None;
+        }
+        let name = String::from_utf8_lossy(name).trim_end().to_string();
+        Some((file_type, name))
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Block at {:.3}s: ", self.start)?;
+        match self.msx_header() {
+            Some((file_type, name)) => write!(f, "MSX {} file \"{}\"", file_type, name),
+            None => write!(f, "{} bytes", self.data.len()),
+        }
+    }
+}
+
+pub fn decode(samples: &[i16], sampling_rate: f64, format: TapeFormat, baud: f64) -> Vec<Block> {
+    match format.channel(baud) {
+        Some(channel) => decode_fsk(samples, sampling_rate, channel),
+        None => decode_tarbell(samples, sampling_rate, baud),
+    }
+}
+
+/// Decodes asynchronous 8N2 FSK formats, splitting blocks at the leader tones
+fn decode_fsk(samples: &[i16], sampling_rate: f64, channel: Channel) -> Vec<Block> {
+    let (received, ticks_per_bit) = framing::receive(samples, channel, sampling_rate, 8);
+    let seconds_per_tick = 1.0 / (ticks_per_bit * channel.baud);
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut last_tick = None;
+    for r in received {
+        let gap = last_tick.map_or(f64::INFINITY, |last| (r.tick - last) as f64);
+        if gap > BLOCK_GAP_BITS * ticks_per_bit {
+            blocks.push(Block {
+                start: r.tick as f64 * seconds_per_tick,
+                data: Vec::new(),
+            });
+        }
+        blocks.last_mut().unwrap().data.push(r.byte);
+        last_tick = Some(r.tick);
+    }
+    blocks
+}
+
+/// Decodes Tarbell phase encoding, where every bit has a transition in its middle: high then
+/// low for 1. The signal is clocked as half bits, and the pairing and polarity that find the
+/// most data are kept since a tape may be inverted.
+fn decode_tarbell(samples: &[i16], sampling_rate: f64, baud: f64) -> Vec<Block> {
+    let mut clock = ClockRecovery::new(sampling_rate / (2.0 * baud));
+    let halves: Vec<(usize, u8)> = samples
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &s)| {
+            clock
+                .push(f64::from(s))
+                .map(|soft| (i, u8::from(soft > 0.0)))
+        })
+        .collect();
+
+    let mut best: Vec<Block> = Vec::new();
+    for offset in 0..2 {
+        for &invert in &[false, true] {
+            // Pairs without a transition aren't valid bits
+            let bits: Vec<(usize, Option<u8>)> = halves[offset..]
+                .chunks_exact(2)
+                .map(|pair| {
+                    let bit = Some(pair[0].1 ^ u8::from(invert)).filter(|_| pair[0].1 != pair[1].1);
+                    (pair[0].0, bit)
+                })
+                .collect();
+            let blocks = find_sync_blocks(&bits, sampling_rate);
+            let total = |blocks: &[Block]| blocks.iter().map(|b| b.data.len()).sum::<usize>();
+            if total(&blocks) > total(&best) {
+                best = blocks;
+            }
+        }
+    }
+    best
+}
+
+/// Finds blocks of synchronous data, most significant bit first, each following a preamble
+/// and sync byte and ending at the first invalid bit
+fn find_sync_blocks(bits: &[(usize, Option<u8>)], sampling_rate: f64) -> Vec<Block> {
+    let byte = |pos: usize| {
+        bits.get(pos..pos + 8)?
+            .iter()
+            .try_fold(0, |value, &(_, bit)| Some(value << 1 | bit?))
+    };
+    let mut blocks = Vec::new();
+    let mut pos = 8 * TARBELL_PREAMBLE_MATCH;
+    while pos + 8 <= bits.len() {
+        let preamble =
+            (1..=TARBELL_PREAMBLE_MATCH).all(|i| byte(pos - 8 * i) == Some(TARBELL_PREAMBLE));
+        if !preamble || byte(pos) != Some(TARBELL_SYNC) {
+            pos += 1;
+            continue;
+        }
+        pos += 8;
+        let start = bits[pos.min(bits.len() - 1)].0 as f64 / sampling_rate;
+        let mut data = Vec::new();
+        while let Some(b) = byte(pos) {
+            data.push(b);
+            pos += 8;
+        }
+        if !data.is_empty() {
+            blocks.push(Block { start, data });
+        }
+        pos += 8 * TARBELL_PREAMBLE_MATCH;
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{FskModulator, AMPLITUDE};
+
+    fn fsk_tape(blocks: &[&[u8]], space: f64, mark: f64, baud: f64) -> Vec<i16> {
+        let mut modulator = FskModulator::new(mark, space, baud, 48_000.0);
+        let mut samples = Vec::new();
+        for block in blocks {
+            modulator.hold(1, 100.0, &mut samples);
+            for &byte in block.iter() {
+                let bits: Vec<u8> = std::iter::once(0)
+                    .chain((0..8).map(|i| (byte >> i) & 1))
+                    .chain(vec![1, 1])
+                    .collect();
+                modulator.modulate(&bits, &mut samples);
+            }
+        }
+        modulator.hold(1, 20.0, &mut samples);
+        samples
+    }
+
+    #[test]
+    fn test_msx() {
+        let mut header = vec![0xd3; MSX_ID_LEN];
+        header.extend(b"HELLO ");
+        let program: Vec<u8> = (0..=255).collect();
+        let samples = fsk_tape(&[&header, &program], 2400.0, 4800.0, 2400.0);
+
+        let blocks = decode(&samples, 48_000.0, TapeFormat::Msx, 2400.0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].msx_header(), Some(("BASIC", "HELLO".to_string())));
+        assert_eq!(
+            blocks[0].to_string(),
+            "Block at 0.041s: MSX BASIC file \"HELLO\""
+        );
+        assert_eq!(blocks[1].data, program);
+    }
+
+    #[test]
+    fn test_kcs_and_cuts() {
+        let data = b"10 PRINT \"HELLO\"\r20 GOTO 10\r";
+        for &format in &[TapeFormat::Kcs, TapeFormat::Cuts] {
+            let baud = format.default_baud();
+            let samples = fsk_tape(&[data], 1200.0, 2400.0, baud);
+            let blocks = decode(&samples, 48_000.0, format, baud);
+            assert_eq!(blocks.len(), 1);
+            assert_eq!(blocks[0].data, data);
+        }
+    }
+
+    #[test]
+    fn test_tarbell() {
+        let data = b"TARBELL CASSETTE";
+        let bytes = std::iter::repeat_n(TARBELL_PREAMBLE, 8)
+            .chain(std::iter::once(TARBELL_SYNC))
+            .chain(data.iter().copied());
+        let mut halves = vec![0u8; 37];
+        for byte in bytes {
+            for i in (0..8).rev() {
+                let bit = (byte >> i) & 1;
+                halves.extend(&[bit, bit ^ 1]);
+            }
+        }
+        halves.extend(vec![0; 40]);
+
+        // 16 samples per half bit with slightly rounded edges, and with the tape inverted
+        for &polarity in &[1.0, -1.0] {
+            let samples: Vec<i16> = halves
+                .iter()
+                .flat_map(|&h| vec![if h == 1 { 1.0 } else { -1.0 }; 16])
+                .collect::<Vec<f64>>()
+                .windows(3)
+                .map(|w| (polarity * AMPLITUDE * w.iter().sum::<f64>() / 3.0) as i16)
+                .collect();
+            let blocks = decode(&samples, 48_000.0, TapeFormat::Tarbell, 1500.0);
+            assert_eq!(blocks.len(), 1);
+            assert_eq!(blocks[0].data, data);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+/// How far each transition pulls the bit clock towards it, as a fraction of the error
+const GAIN: f64 = 0.15;
+
+/// Recovers the bit clock of a synchronous signal from soft decisions. The clock is nudged
+/// towards each mark/space transition, and one decision from the middle of each bit is
+/// returned.
+#[derive(Debug)]
+pub struct ClockRecovery {
+    ticks_per_bit: f64,
+    /// Ticks since the predicted start of the current bit
+    phase: f64,
+    sampled: bool,
+    last: f64,
+}
+
+impl ClockRecovery {
+    pub fn new(ticks_per_bit: f64) -> Self {
+        Self {
+            ticks_per_bit,
+            phase: 0.0,
+            sampled: false,
+            last: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, soft: f64) -> Option<f64> {
+        self.phase += 1.0;
+        if (soft > 0.0) != (self.last > 0.0) && soft != self.last {
+            // Interpolate where the decision crossed zero between the two ticks
+            let edge = self.phase - 1.0 + self.last / (self.last - soft);
+            let error = if edge < self.ticks_per_bit / 2.0 {
+                edge
+            } else {
+                edge - self.ticks_per_bit
+            };
+            self.phase -= GAIN * error;
+        }
+        self.last = soft;
+        if self.phase >= self.ticks_per_bit {
+            self.phase -= self.ticks_per_bit;
+            self.sampled = false;
+        }
+        if !self.sampled && self.phase >= self.ticks_per_bit / 2.0 {
+            self.sampled = true;
+            return Some(soft);
+        }
+        None
+    }
+}
+
+/// Runs clock recovery over soft decisions, returning hard bits with mark as 1
+pub fn recover_bits(soft_bits: &[f64], ticks_per_bit: f64) -> Vec<u8> {
+    let mut clock = ClockRecovery::new(ticks_per_bit);
+    soft_bits
+        .iter()
+        .filter_map(|&soft| clock.push(soft))
+        .map(|soft| u8::from(soft > 0.0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovers_drifting_clock() {
+        // A pattern sent 2% slow and starting mid-bit
+        let pattern = [1u8, 0, 0, 1, 1, 1, 0, 1, 0, 0, 0, 1];
+        let bits: Vec<u8> = pattern.iter().cycle().take(600).copied().collect();
+        let ticks_per_bit = 8.16;
+        let soft: Vec<f64> = (0..(bits.len() as f64 * ticks_per_bit) as usize)
+            .map(|t| {
+                let bit = bits[((t as f64 + 3.0) / ticks_per_bit) as usize % bits.len()];
+                if bit == 1 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            })
+            .collect();
+
+        let recovered = recover_bits(&soft, 8.0);
+        let start = recovered
+            .windows(pattern.len())
+            .position(|w| w == pattern)
+            .unwrap();
+        let expected = bits.iter().cycle().skip(start % pattern.len());
+        let matching = recovered[start..]
+            .iter()
+            .zip(expected)
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert!(matching >= bits.len() - start - 2);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::events::{Event, Sink};
+use crate::standard::{Channel, Standard};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A subscriber that takes longer than this to read an event is dropped
+const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What a stream is decoding, which the control socket can change while it runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub running: bool,
+    pub standard: Standard,
+    pub origin: bool,
+}
+
+impl Settings {
+    /// The channel to decode, or none while stopped
+    pub fn tuning(&self) -> Option<Channel> {
+        Some(self.standard.channel(self.origin)).filter(|_| self.running)
+    }
+
+    /// Carries out a command, answering with `ok` or `error` and what went wrong
+    pub fn apply(&mut self, command: &Command) -> String {
+        match *command {
+            Command::Status => {
+                let standard = format!("{:?}", self.standard).to_lowercase();
+                format!(
+                    "ok {} {} {}",
+                    if self.running { "running" } else { "stopped" },
+                    standard,
+                    if self.origin { "origin" } else { "answer" }
+                )
+            }
+            Command::Start => {
+                self.running = true;
+                "ok".to_string()
+            }
+            Command::Stop => {
+                self.running = false;
+                "ok".to_string()
+            }
+            Command::Standard(standard) => {
+                self.standard = standard;
+                "ok".to_string()
+            }
+            Command::Origin(origin) => {
+                self.origin = origin;
+                "ok".to_string()
+            }
+            Command::Subscribe | Command::Unsubscribe | Command::Quit => "ok".to_string(),
+        }
+    }
+}
+
+/// A line of the control protocol
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// `status`, answered with `ok running|stopped <standard> origin|answer`
+    Status,
+    /// `start`, resuming decoding
+    Start,
+    /// `stop`, ignoring the audio until started again
+    Stop,
+    /// `set standard <standard>`
+    Standard(Standard),
+    /// `set origin on|off`
+    Origin(bool),
+    /// `subscribe`, after which each event follows on its own line as JSON
+    Subscribe,
+    /// `unsubscribe`
+    Unsubscribe,
+    /// `quit`, closing the connection
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<_> = s.split_whitespace().collect();
+        match words[..] {
+            ["status"] => Ok(Command::Status),
+            ["start"] => Ok(Command::Start),
+            ["stop"] => Ok(Command::Stop),
+            ["set", "standard", standard] => standard.parse().map(Command::Standard),
+            ["set", "origin", "on"] => Ok(Command::Origin(true)),
+            ["set", "origin", "off"] => Ok(Command::Origin(false)),
+            ["set", "origin", _] => Err("expected set origin on or off".to_string()),
+            ["subscribe"] => Ok(Command::Subscribe),
+            ["unsubscribe"] => Ok(Command::Unsubscribe),
+            ["quit"] => Ok(Command::Quit),
+            _ => Err(format!(
+                "unknown command `{}`, expected status, start, stop, set standard, set origin, \
+                 subscribe, unsubscribe or quit",
+                s.trim()
+            )),
+        }
+    }
+}
+
+/// Connections subscribed to the events, each with the number of the connection
+type Subscribers = Arc<Mutex<Vec<(usize, Box<dyn Write + Send>)>>>;
+
+/// A Unix socket taking control commands a line at a time, from any number of local
+/// connections, and sending events to the ones that subscribe
+#[derive(Clone)]
+pub struct Control {
+    settings: Arc<Mutex<Settings>>,
+    subscribers: Subscribers,
+    _socket: Arc<SocketFile>,
+}
+
+impl Control {
+    /// Listens at `path`, replacing a socket left behind there, accepting connections in the
+    /// background. The settings can be shared with anything else steering the stream.
+    #[cfg(unix)]
+    pub fn listen(path: &Path, settings: Arc<Mutex<Settings>>) -> io::Result<Self> {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::fs::FileTypeExt;
+        use std::os::unix::net::UnixListener;
+
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        let control = Self {
+            settings,
+            subscribers: Arc::default(),
+            _socket: Arc::new(SocketFile(path.to_path_buf())),
+        };
+        let (settings, subscribers) = (Arc::clone(&control.settings), control.subscribers.clone());
+        std::thread::spawn(move || {
+            for (id, connection) in listener.incoming().flatten().enumerate() {
+                let (settings, subscribers) = (Arc::clone(&settings), subscribers.clone());
+                std::thread::spawn(move || {
+                    let mut writer = connection.try_clone()?;
+                    writer.set_write_timeout(Some(SEND_TIMEOUT))?;
+                    for line in BufReader::new(connection).lines() {
+                        let line = line?;
+                        let command = line.parse::<Command>();
+                        let reply = match &command {
+                            Ok(command) => settings.lock().unwrap().apply(command),
+                            Err(e) => format!("error {}", e),
+                        };
+                        // Replies go out under the subscribers' lock, so events can't split them
+                        let mut subscribers = subscribers.lock().unwrap();
+                        writeln!(writer, "{}", reply)?;
+                        match command {
+                            Ok(Command::Subscribe) if subscribers.iter().all(|(i, _)| *i != id) => {
+                                subscribers.push((id, Box::new(writer.try_clone()?)));
+                            }
+                            Ok(Command::Unsubscribe) => subscribers.retain(|(i, _)| *i != id),
+                            Ok(Command::Quit) => break,
+                            _ => {}
+                        }
+                    }
+                    subscribers.lock().unwrap().retain(|(i, _)| *i != id);
+                    io::Result::Ok(())
+                });
+            }
+        });
+        Ok(control)
+    }
+
+    #[cfg(not(unix))]
+    pub fn listen(_: &Path, _: Arc<Mutex<Settings>>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "control sockets need a Unix system",
+        ))
+    }
+
+    /// The channel to decode now, or none while stopped
+    pub fn tuning(&self) -> Option<Channel> {
+        self.settings.lock().unwrap().tuning()
+    }
+}
+
+impl Sink for Control {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        let line = format!("{}\n", event.to_json());
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain_mut(|(_, subscriber)| subscriber.write_all(line.as_bytes()).is_ok());
+        Ok(())
+    }
+}
+
+/// Removes the socket once nothing's listening on it any more
+struct SocketFile(PathBuf);
+
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let mut settings = Settings {
+            running: true,
+            standard: Standard::Bell103,
+            origin: false,
+        };
+        let status = |settings: &mut Settings| settings.apply(&Command::Status);
+        assert_eq!(status(&mut settings), "ok running bell103 answer");
+        for line in ["stop", "set standard v21", "set origin on"] {
+            assert_eq!(settings.apply(&line.parse().unwrap()), "ok");
+        }
+        assert_eq!(status(&mut settings), "ok stopped v21 origin");
+        assert_eq!(settings.tuning(), None);
+        settings.apply(&Command::Start);
+        assert_eq!(settings.tuning(), Some(Standard::V21.originate()));
+
+        assert!("set standard auto".parse::<Command>().is_err());
+        assert!("set origin maybe".parse::<Command>().is_err());
+        assert!("restart".parse::<Command>().is_err());
+        assert_eq!(" subscribe \r".parse(), Ok(Command::Subscribe));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_control_socket() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("control-{}.sock", std::process::id()));
+        let settings = Settings {
+            running: true,
+            standard: Standard::Bell103,
+            origin: false,
+        };
+        let mut control = Control::listen(&path, Arc::new(Mutex::new(settings))).unwrap();
+        let mut client = UnixStream::connect(&path).unwrap();
+        let mut replies = BufReader::new(client.try_clone().unwrap()).lines();
+        client.write_all(b"set standard bell202\nbogus\n").unwrap();
+        assert_eq!(replies.next().unwrap().unwrap(), "ok");
+        assert!(replies
+            .next()
+            .unwrap()
+            .unwrap()
+            .starts_with("error unknown"));
+        assert_eq!(control.tuning(), Some(Standard::Bell202.answer()));
+
+        client.write_all(b"subscribe\n").unwrap();
+        assert_eq!(replies.next().unwrap().unwrap(), "ok");
+        control.take(&Event::Carrier(true)).unwrap();
+        let event = replies.next().unwrap().unwrap();
+        assert_eq!(event, r#"{"type":"carrier","present":true}"#);
+
+        drop(control);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::modulator::{Oscillator, AMPLITUDE};
+use std::f64::consts::PI;
+
+/// Rise and fall time of each element, which keeps the keying free of clicks
+const RAMP_MS: f64 = 5.0;
+
+const MORSE: [(char, &str); 54] = [
+    ('A', ".-"),
+    ('B', "-..."),
+    ('C', "-.-."),
+    ('D', "-.."),
+    ('E', "."),
+    ('F', "..-."),
+    ('G', "--."),
+    ('H', "...."),
+    ('I', ".."),
+    ('J', ".---"),
+    ('K', "-.-"),
+    ('L', ".-.."),
+    ('M', "--"),
+    ('N', "-."),
+    ('O', "---"),
+    ('P', ".--."),
+    ('Q', "--.-"),
+    ('R', ".-."),
+    ('S', "..."),
+    ('T', "-"),
+    ('U', "..-"),
+    ('V', "...-"),
+    ('W', ".--"),
+    ('X', "-..-"),
+    ('Y', "-.--"),
+    ('Z', "--.."),
+    ('0', "-----"),
+    ('1', ".----"),
+    ('2', "..---"),
+    ('3', "...--"),
+    ('4', "....-"),
+    ('5', "....."),
+    ('6', "-...."),
+    ('7', "--..."),
+    ('8', "---.."),
+    ('9', "----."),
+    ('.', ".-.-.-"),
+    (',', "--..--"),
+    ('?', "..--.."),
+    ('\'', ".----."),
+    ('!', "-.-.--"),
+    ('/', "-..-."),
+    ('(', "-.--."),
+    (')', "-.--.-"),
+    ('&', ".-..."),
+    (':', "---..."),
+    (';', "-.-.-."),
+    ('=', "-...-"),
+    ('+', ".-.-."),
+    ('-', "-....-"),
+    ('_', "..--.-"),
+    ('"', ".-..-."),
+    ('$', "...-..-"),
+    ('@', ".--.-."),
+];
+
+/// Returns the dots and dashes for a character
+pub fn morse(c: char) -> Option<&'static str> {
+    let c = c.to_ascii_uppercase();
+    MORSE
+        .iter()
+        .find(|(key, _)| *key == c)
+        .map(|(_, code)| *code)
+}
+
+/// Keys a tone on and off to send text as Morse code, timed using the PARIS standard where a
+/// dot lasts 1200 / WPM milliseconds
+#[derive(Debug)]
+pub struct CwGenerator {
+    sampling_rate: f64,
+    frequency: f64,
+    dot_samples: usize,
+    ramp_samples: usize,
+}
+
+impl CwGenerator {
+    pub fn new(wpm: f64, frequency: f64, sampling_rate: f64) -> Self {
+        let dot_samples = (sampling_rate * 1.2 / wpm) as usize;
+        Self {
+            sampling_rate,
+            frequency,
+            dot_samples,
+            ramp_samples: ((sampling_rate * RAMP_MS / 1000.0) as usize).min(dot_samples / 2),
+        }
+    }
+
+    /// Appends the audio for `text` to `samples`, failing on the first character without a
+    /// Morse representation
+    pub fn generate(&self, text: &str, samples: &mut Vec<i16>) -> Result<(), char> {
+        let mut oscillator = Oscillator::new(self.frequency, self.sampling_rate);
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                // Seven dots between words, less the trailing gap after the last character
+                self.silence(4, samples);
+            }
+            for c in word.chars() {
+                for element in morse(c).ok_or(c)?.chars() {
+                    let dots = if element == '-' { 3 } else { 1 };
+                    self.key(&mut oscillator, dots, samples);
+                    self.silence(1, samples);
+                }
+                self.silence(2, samples);
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&self, oscillator: &mut Oscillator, dots: usize, samples: &mut Vec<i16>) {
+        let length = dots * self.dot_samples;
+        for i in 0..length {
+            let edge = i.min(length - 1 - i);
+            let envelope = if edge < self.ramp_samples {
+                0.5 - 0.5 * (PI * edge as f64 / self.ramp_samples as f64).cos()
+            } else {
+                1.0
+            };
+            samples.push((AMPLITUDE * envelope * oscillator.next_sample()) as i16);
+        }
+    }
+
+    fn silence(&self, dots: usize, samples: &mut Vec<i16>) {
+        samples.extend(std::iter::repeat_n(0, dots * self.dot_samples));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_timing() {
+        // 12 WPM gives a 100 ms dot, or 800 samples at 8 kHz
+        let generator = CwGenerator::new(12.0, 700.0, 8_000.0);
+        let mut samples = Vec::new();
+        generator.generate("E T", &mut samples).unwrap();
+        // dot + 3 gap, 4 extra word gap, dash + 3 gap
+        assert_eq!(samples.len(), (1 + 3 + 4 + 3 + 3) * 800);
+
+        let keyed: Vec<bool> = samples
+            .chunks(800)
+            .map(|c| c.iter().any(|&s| s != 0))
+            .collect();
+        let expected = [1, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 0, 0, 0];
+        assert_eq!(keyed, expected.iter().map(|&k| k == 1).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unknown_character() {
+        let generator = CwGenerator::new(20.0, 700.0, 8_000.0);
+        assert_eq!(generator.generate("CQ #", &mut Vec::new()), Err('#'));
+        assert_eq!(morse('k'), Some("-.-"));
+    }
+}
@@ -0,0 +1,202 @@
+use crate::events::{Event, Messages, Sink};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Reads a configuration file into the command line options it stands for. Each line is a
+/// long option's name and its value, `listen = udp://0.0.0.0:7355`, or a flag's name alone.
+/// A flag can be written `= true` too, or left off with `= false`. Values can be quoted, and
+/// blank lines and ones starting with `#` are skipped.
+pub fn options(config: &str) -> Result<Vec<String>, String> {
+    let mut options = Vec::new();
+    for (number, line) in config.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (line, None),
+        };
+        let named = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() || !named {
+            return Err(format!(
+                "line {}: `{}` isn't an option's name and value, e.g. listen = udp://0.0.0.0:7355",
+                number + 1,
+                line
+            ));
+        }
+        match value {
+            Some("false") => {}
+            None | Some("true") => options.push(format!("--{}", name)),
+            Some(value) => {
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                options.push(format!("--{}", name));
+                options.push(value.to_string());
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// What a daemon has been asked to do by a signal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// SIGHUP, to read its configuration again
+    Reload,
+    /// SIGTERM or SIGINT, to finish what it's decoding and exit
+    Shutdown,
+}
+
+static RELOAD: AtomicBool = AtomicBool::new(false);
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Catches SIGHUP, SIGTERM and SIGINT from now on, for [`signal`] to pick up. Other platforms
+/// have no signals to reload with, and Ctrl-C ends the process as usual.
+#[cfg(unix)]
+pub fn catch_signals() -> io::Result<()> {
+    extern "C" fn caught(signal: libc::c_int) {
+        // Only an atomic store is safe in a signal handler
+        if signal == libc::SIGHUP {
+            RELOAD.store(true, Ordering::SeqCst);
+        } else {
+            SHUTDOWN.store(true, Ordering::SeqCst);
+        }
+    }
+    let handler: extern "C" fn(libc::c_int) = caught;
+    for &signal in &[libc::SIGHUP, libc::SIGTERM, libc::SIGINT] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = handler as libc::sighandler_t;
+        // Carry on with reads a signal interrupts, it's picked up between blocks
+        action.sa_flags = libc::SA_RESTART;
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn catch_signals() -> io::Result<()> {
+    Ok(())
+}
+
+/// The signal caught since this was last asked, if any, a shutdown before a reload
+pub fn signal() -> Option<Signal> {
+    if SHUTDOWN.swap(false, Ordering::SeqCst) {
+        Some(Signal::Shutdown)
+    } else if RELOAD.swap(false, Ordering::SeqCst) {
+        Some(Signal::Reload)
+    } else {
+        None
+    }
+}
+
+/// Logs a stream's state as it changes, the carrier coming and going and each message decoded,
+/// so a daemon's log shows what it's been hearing
+pub struct StateLog<W> {
+    log: W,
+    messages: Messages,
+    count: u64,
+    snr: Option<f64>,
+}
+
+impl<W: Write> StateLog<W> {
+    pub fn new(log: W) -> Self {
+        Self {
+            log,
+            messages: Messages::default(),
+            count: 0,
+            snr: None,
+        }
+    }
+}
+
+impl<W: Write> Sink for StateLog<W> {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        match event {
+            Event::Snr(db) => self.snr = Some(*db).filter(|db| db.is_finite()),
+            Event::Carrier(true) => writeln!(self.log, "Carrier heard")?,
+            Event::Carrier(false) => writeln!(self.log, "Carrier lost")?,
+            _ => {}
+        }
+        if let Some(message) = self.messages.take(event) {
+            self.count += 1;
+            write!(
+                self.log,
+                "Message {} decoded, {} characters",
+                self.count,
+                message.chars().count()
+            )?;
+            match self.snr {
+                Some(snr) => writeln!(self.log, " at {:.1} dB SNR", snr)?,
+                None => writeln!(self.log)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let config = "# Line 1\n\nlisten = udp://0.0.0.0:7355\nstandard=v21\norigin\nsegment = false\nmonitor = true\nexec = \"logger -t modem {}\"\n";
+        assert_eq!(
+            options(config).unwrap(),
+            [
+                "--listen",
+                "udp://0.0.0.0:7355",
+                "--standard",
+                "v21",
+                "--origin",
+                "--monitor",
+                "--exec",
+                "logger -t modem {}"
+            ]
+        );
+        let bad = options("live\n--standard v21\n").unwrap_err();
+        assert!(bad.starts_with("line 2: `--standard v21`"));
+        assert!(options("= v21").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signals() {
+        catch_signals().unwrap();
+        assert_eq!(signal(), None);
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert_eq!(signal(), Some(Signal::Reload));
+        unsafe { libc::raise(libc::SIGHUP) };
+        unsafe { libc::raise(libc::SIGTERM) };
+        assert_eq!(signal(), Some(Signal::Shutdown));
+        assert_eq!(signal(), Some(Signal::Reload));
+        assert_eq!(signal(), None);
+    }
+
+    #[test]
+    fn test_state_log() {
+        let mut log = StateLog::new(Vec::new());
+        for event in &[
+            Event::Carrier(true),
+            Event::Text("Welcome, "),
+            Event::Snr(16.94),
+            Event::Text("login:"),
+            Event::Carrier(false),
+            Event::Carrier(true),
+            Event::Snr(f64::NEG_INFINITY),
+            Event::Text("ok"),
+            Event::Carrier(false),
+        ] {
+            log.take(event).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(log.log).unwrap(),
+            "Carrier heard\nCarrier lost\nMessage 1 decoded, 15 characters at 16.9 dB SNR\nCarrier heard\nCarrier lost\nMessage 2 decoded, 2 characters\n"
+        );
+    }
+}
@@ -0,0 +1,962 @@
+//! A D-Bus service for controlling a decoder running a stream, so desktop applications
+//! and system services needn't speak `--control`. It owns `io.github.lukexor.Bell103Demodulator`
+//! on the session or system bus, with an object at `/io/github/lukexor/Bell103Demodulator`
+//! having `Start` and `Stop` methods, `Running`, `Standard` and `Origin` properties, and a
+//! `MessageDecoded` signal with each message's text. The wire protocol is written here, so
+//! only a Unix socket to the bus is needed.
+
+use crate::control::{Command, Settings};
+use crate::events::{Event, Messages, Sink};
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The name the service owns, which is also its interface's
+pub const NAME: &str = "io.github.lukexor.Bell103Demodulator";
+pub const PATH: &str = "/io/github/lukexor/Bell103Demodulator";
+const INTERFACE: &str = NAME;
+/// Standard interfaces the object has too
+const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+/// Message types
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const SIGNAL: u8 = 4;
+/// The flag on method calls that don't want a reply
+const NO_REPLY_EXPECTED: u8 = 0x1;
+/// Header fields
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+/// Longer messages are refused, well short of the 128 MiB D-Bus allows, as none sent here
+/// need much
+const MAX_MESSAGE: usize = 1 << 20;
+/// How deep containers can nest in a message, as D-Bus limits them
+const MAX_DEPTH: usize = 64;
+/// RequestName's flag for failing rather than queueing for a name, and its answer when the
+/// name's ours
+const DO_NOT_QUEUE: u32 = 4;
+const PRIMARY_OWNER: u32 = 1;
+/// Errors answering calls
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const UNKNOWN_OBJECT: &str = "org.freedesktop.DBus.Error.UnknownObject";
+const UNKNOWN_INTERFACE: &str = "org.freedesktop.DBus.Error.UnknownInterface";
+const UNKNOWN_PROPERTY: &str = "org.freedesktop.DBus.Error.UnknownProperty";
+const READ_ONLY: &str = "org.freedesktop.DBus.Error.PropertyReadOnly";
+const INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.lukexor.Bell103Demodulator">
+    <method name="Start"/>
+    <method name="Stop"/>
+    <property name="Running" type="b" access="read"/>
+    <property name="Standard" type="s" access="readwrite"/>
+    <property name="Origin" type="b" access="readwrite"/>
+    <signal name="MessageDecoded">
+      <arg name="text" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Properties">
+    <method name="Get">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="out"/>
+    </method>
+    <method name="GetAll">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="properties" type="a{sv}" direction="out"/>
+    </method>
+    <method name="Set">
+      <arg name="interface" type="s" direction="in"/>
+      <arg name="name" type="s" direction="in"/>
+      <arg name="value" type="v" direction="in"/>
+    </method>
+    <signal name="PropertiesChanged">
+      <arg name="interface" type="s"/>
+      <arg name="changed" type="a{sv}"/>
+      <arg name="invalidated" type="as"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+/// Which bus to offer the service on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bus {
+    /// The desktop session's, for applications the user runs
+    Session,
+    /// The system's, for services, which needs a policy letting the decoder own its name
+    System,
+}
+
+impl FromStr for Bus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "session" => Ok(Bus::Session),
+            "system" => Ok(Bus::System),
+            _ => Err(format!("unknown bus `{}`, expected session or system", s)),
+        }
+    }
+}
+
+impl Bus {
+    /// The bus's address, as its environment variable gives it or by default
+    fn address(self) -> io::Result<String> {
+        let (variable, default) = match self {
+            Bus::Session => (
+                "DBUS_SESSION_BUS_ADDRESS",
+                std::env::var("XDG_RUNTIME_DIR")
+                    .ok()
+                    .map(|directory| format!("unix:path={}/bus", directory)),
+            ),
+            Bus::System => (
+                "DBUS_SYSTEM_BUS_ADDRESS",
+                Some("unix:path=/var/run/dbus/system_bus_socket".to_string()),
+            ),
+        };
+        std::env::var(variable).ok().or(default).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} isn't set, is there a session bus?", variable),
+            )
+        })
+    }
+}
+
+/// A value in a message, each type of which has its own signature
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Byte(u8),
+    Bool(bool),
+    U32(u32),
+    Str(String),
+    Path(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// An array and the signature of its elements, as it may have none
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::U32(_) => "u".to_string(),
+            Value::Str(_) => "s".to_string(),
+            Value::Path(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Variant(_) => "v".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                let fields: String = fields.iter().map(Value::signature).collect();
+                format!("({})", fields)
+            }
+            Value::DictEntry(key, value) => format!("{{{}{}}}", key.signature(), value.signature()),
+        }
+    }
+
+    /// Appends the value to a message, little endian and aligned from the message's start
+    fn write(&self, message: &mut Vec<u8>) {
+        pad(message, alignment(self.signature().as_bytes()[0]));
+        match self {
+            Value::Byte(byte) => message.push(*byte),
+            Value::Bool(value) => message.extend_from_slice(&u32::from(*value).to_le_bytes()),
+            Value::U32(value) => message.extend_from_slice(&value.to_le_bytes()),
+            Value::Str(text) | Value::Path(text) => {
+                message.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                message.extend_from_slice(text.as_bytes());
+                message.push(0);
+            }
+            Value::Signature(signature) => {
+                message.push(signature.len() as u8);
+                message.extend_from_slice(signature.as_bytes());
+                message.push(0);
+            }
+            Value::Variant(value) => {
+                Value::Signature(value.signature()).write(message);
+                value.write(message);
+            }
+            Value::Array(element, values) => {
+                let length = message.len();
+                message.extend_from_slice(&[0; 4]);
+                // The length doesn't count the padding before the first element
+                pad(message, alignment(element.as_bytes()[0]));
+                let start = message.len();
+                for value in values {
+                    value.write(message);
+                }
+                let size = (message.len() - start) as u32;
+                message[length..length + 4].copy_from_slice(&size.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                for field in fields {
+                    field.write(message);
+                }
+            }
+            Value::DictEntry(key, value) => {
+                key.write(message);
+                value.write(message);
+            }
+        }
+    }
+}
+
+/// How a type's values are aligned, from the first character of its signature
+fn alignment(code: u8) -> usize {
+    match code {
+        b'(' | b'{' => 8,
+        b'y' | b'g' | b'v' => 1,
+        _ => 4,
+    }
+}
+
+fn pad(message: &mut Vec<u8>, alignment: usize) {
+    while !message.len().is_multiple_of(alignment) {
+        message.push(0);
+    }
+}
+
+/// Splits the first complete type from a signature
+fn split_type(signature: &str) -> Result<(&str, &str), String> {
+    let mut depth = 0;
+    for (i, code) in signature.bytes().enumerate() {
+        match code {
+            b'a' => continue,
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' if depth == 0 => break,
+            b')' | b'}' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Ok(signature.split_at(i + 1));
+        }
+    }
+    Err(format!("the signature `{}` is malformed", signature))
+}
+
+/// Reads values from a message, aligned from the start of `bytes`
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    big_endian: bool,
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], big_endian: bool) -> Self {
+        Self {
+            bytes,
+            at: 0,
+            big_endian,
+            depth: 0,
+        }
+    }
+
+    fn take(&mut self, length: usize, alignment: usize) -> Result<&'a [u8], String> {
+        let start = self.at.div_ceil(alignment) * alignment;
+        if start + length > self.bytes.len() {
+            return Err("a value runs past the message".to_string());
+        }
+        self.at = start + length;
+        Ok(&self.bytes[start..self.at])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4, 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn text(&mut self, length: usize) -> Result<String, String> {
+        let text = self.take(length, 1)?;
+        if self.take(1, 1)? != [0] {
+            return Err("a string isn't terminated".to_string());
+        }
+        String::from_utf8(text.to_vec()).map_err(|_| "a string isn't UTF-8".to_string())
+    }
+
+    /// Reads a value of a single complete type
+    fn value(&mut self, signature: &str) -> Result<Value, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("a message nests too deeply".to_string());
+        }
+        let inner = signature.get(1..signature.len() - 1).unwrap_or_default();
+        let value = match signature.as_bytes()[0] {
+            b'y' => Value::Byte(self.take(1, 1)?[0]),
+            b'b' => match self.u32()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err("a boolean isn't 0 or 1".to_string()),
+            },
+            b'u' => Value::U32(self.u32()?),
+            code @ (b's' | b'o') => {
+                let length = self.u32()? as usize;
+                let text = self.text(length)?;
+                match code {
+                    b's' => Value::Str(text),
+                    _ => Value::Path(text),
+                }
+            }
+            b'g' => {
+                let length = usize::from(self.take(1, 1)?[0]);
+                Value::Signature(self.text(length)?)
+            }
+            b'v' => {
+                let length = usize::from(self.take(1, 1)?[0]);
+                let signature = self.text(length)?;
+                match split_type(&signature)? {
+                    (single, "") => Value::Variant(Box::new(self.value(single)?)),
+                    _ => return Err("a variant holds more than one value".to_string()),
+                }
+            }
+            b'a' => {
+                let length = self.u32()? as usize;
+                let element = &signature[1..];
+                self.take(0, alignment(element.as_bytes()[0]))?;
+                let end = self.at + length;
+                if end > self.bytes.len() {
+                    return Err("an array runs past the message".to_string());
+                }
+                let mut values = Vec::new();
+                while self.at < end {
+                    values.push(self.value(element)?);
+                }
+                Value::Array(element.to_string(), values)
+            }
+            b'(' => {
+                self.take(0, 8)?;
+                let mut fields = Vec::new();
+                let mut rest = inner;
+                while !rest.is_empty() {
+                    let (field, after) = split_type(rest)?;
+                    fields.push(self.value(field)?);
+                    rest = after;
+                }
+                Value::Struct(fields)
+            }
+            b'{' => {
+                self.take(0, 8)?;
+                let (key, rest) = split_type(inner)?;
+                let (value, _) = split_type(rest)?;
+                Value::DictEntry(Box::new(self.value(key)?), Box::new(self.value(value)?))
+            }
+            code => {
+                return Err(format!(
+                    "values of type `{}` aren't supported",
+                    char::from(code)
+                ))
+            }
+        };
+        self.depth -= 1;
+        Ok(value)
+    }
+}
+
+/// A message's type, header fields and body
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Message {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    destination: Option<String>,
+    sender: Option<String>,
+    body: Vec<Value>,
+}
+
+impl Message {
+    /// A call to the bus itself
+    fn to_bus(member: &str, body: Vec<Value>) -> Self {
+        Self {
+            kind: METHOD_CALL,
+            path: Some("/org/freedesktop/DBus".to_string()),
+            interface: Some("org.freedesktop.DBus".to_string()),
+            member: Some(member.to_string()),
+            destination: Some("org.freedesktop.DBus".to_string()),
+            body,
+            ..Self::default()
+        }
+    }
+
+    /// A signal from the service's object
+    fn signal(interface: &str, member: &str, body: Vec<Value>) -> Self {
+        Self {
+            kind: SIGNAL,
+            path: Some(PATH.to_string()),
+            interface: Some(interface.to_string()),
+            member: Some(member.to_string()),
+            body,
+            ..Self::default()
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for value in &self.body {
+            value.write(&mut body);
+        }
+        let signature: String = self.body.iter().map(Value::signature).collect();
+        let fields = vec![
+            (FIELD_PATH, self.path.clone().map(Value::Path)),
+            (FIELD_INTERFACE, self.interface.clone().map(Value::Str)),
+            (FIELD_MEMBER, self.member.clone().map(Value::Str)),
+            (FIELD_ERROR_NAME, self.error_name.clone().map(Value::Str)),
+            (FIELD_REPLY_SERIAL, self.reply_serial.map(Value::U32)),
+            (FIELD_DESTINATION, self.destination.clone().map(Value::Str)),
+            (FIELD_SENDER, self.sender.clone().map(Value::Str)),
+            (
+                FIELD_SIGNATURE,
+                Some(Value::Signature(signature)).filter(|_| !body.is_empty()),
+            ),
+        ];
+        let fields = fields
+            .into_iter()
+            .filter_map(|(code, value)| {
+                let field = vec![Value::Byte(code), Value::Variant(Box::new(value?))];
+                Some(Value::Struct(field))
+            })
+            .collect();
+
+        let mut message = vec![b'l', self.kind, self.flags, 1];
+        message.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        message.extend_from_slice(&self.serial.to_le_bytes());
+        Value::Array("(yv)".to_string(), fields).write(&mut message);
+        pad(&mut message, 8);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut fixed = [0; 16];
+        reader.read_exact(&mut fixed)?;
+        let big_endian = match fixed[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("a message has an unknown byte order")),
+        };
+        let number = |at: usize| {
+            let bytes = [fixed[at], fixed[at + 1], fixed[at + 2], fixed[at + 3]];
+            match big_endian {
+                true => u32::from_be_bytes(bytes),
+                false => u32::from_le_bytes(bytes),
+            }
+        };
+        let (body_length, fields_length) = (number(4) as usize, number(12) as usize);
+        if body_length + fields_length > MAX_MESSAGE {
+            return Err(invalid("a message is too long"));
+        }
+        let header_length = (16 + fields_length).div_ceil(8) * 8;
+        let mut bytes = fixed.to_vec();
+        bytes.resize(header_length + body_length, 0);
+        reader.read_exact(&mut bytes[16..])?;
+
+        let mut message = Self {
+            kind: fixed[1],
+            flags: fixed[2],
+            serial: number(8),
+            ..Self::default()
+        };
+        let mut header = Reader::new(&bytes[..header_length], big_endian);
+        header.at = 12;
+        let mut signature = String::new();
+        let fields = header.value("a(yv)").map_err(|e| invalid(&e))?;
+        let Value::Array(_, fields) = fields else {
+            return Err(invalid("a message's header fields aren't an array"));
+        };
+        for field in fields {
+            let Value::Struct(field) = field else {
+                continue;
+            };
+            let [Value::Byte(code), Value::Variant(value)] = &field[..] else {
+                continue;
+            };
+            match (*code, &**value) {
+                (FIELD_PATH, Value::Path(path)) => message.path = Some(path.clone()),
+                (FIELD_INTERFACE, Value::Str(name)) => message.interface = Some(name.clone()),
+                (FIELD_MEMBER, Value::Str(name)) => message.member = Some(name.clone()),
+                (FIELD_ERROR_NAME, Value::Str(name)) => message.error_name = Some(name.clone()),
+                (FIELD_REPLY_SERIAL, Value::U32(serial)) => message.reply_serial = Some(*serial),
+                (FIELD_DESTINATION, Value::Str(name)) => message.destination = Some(name.clone()),
+                (FIELD_SENDER, Value::Str(name)) => message.sender = Some(name.clone()),
+                (FIELD_SIGNATURE, Value::Signature(types)) => signature = types.clone(),
+                _ => {}
+            }
+        }
+        let mut body = Reader::new(&bytes[header_length..], big_endian);
+        let mut rest = signature.as_str();
+        while !rest.is_empty() {
+            let (single, after) = split_type(rest).map_err(|e| invalid(&e))?;
+            message
+                .body
+                .push(body.value(single).map_err(|e| invalid(&e))?);
+            rest = after;
+        }
+        Ok(message)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// The writing half of a connection to the bus, numbering the messages sent
+struct Outgoing {
+    writer: Box<dyn Write + Send>,
+    serial: u32,
+}
+
+impl Outgoing {
+    fn send(&mut self, mut message: Message) -> io::Result<u32> {
+        self.serial += 1;
+        message.serial = self.serial;
+        self.writer.write_all(&message.encode())?;
+        Ok(self.serial)
+    }
+}
+
+/// The decoder's service on a bus, answering calls in the background and signalling each
+/// message decoded
+pub struct Service {
+    outgoing: Arc<Mutex<Outgoing>>,
+    messages: Messages,
+}
+
+impl Service {
+    /// Connects to `bus` and takes the service's name, failing if another decoder has it,
+    /// then serves calls changing `settings`
+    #[cfg(unix)]
+    pub fn connect(bus: Bus, settings: Arc<Mutex<Settings>>) -> io::Result<Self> {
+        let stream = connect(&bus.address()?)?;
+        let mut reader = io::BufReader::new(stream.try_clone()?);
+        authenticate(&mut reader, &mut &stream)?;
+        let mut outgoing = Outgoing {
+            writer: Box::new(stream),
+            serial: 0,
+        };
+        let hello = outgoing.send(Message::to_bus("Hello", Vec::new()))?;
+        reply(&mut reader, hello)?;
+        let body = vec![Value::Str(NAME.to_string()), Value::U32(DO_NOT_QUEUE)];
+        let request = outgoing.send(Message::to_bus("RequestName", body))?;
+        if reply(&mut reader, request)?.body != [Value::U32(PRIMARY_OWNER)] {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another decoder has the name".to_string(),
+            ));
+        }
+
+        let service = Self {
+            outgoing: Arc::new(Mutex::new(outgoing)),
+            messages: Messages::default(),
+        };
+        let outgoing = Arc::clone(&service.outgoing);
+        std::thread::spawn(move || -> io::Result<()> {
+            loop {
+                let message = Message::read(&mut reader)?;
+                if message.kind == METHOD_CALL {
+                    for answer in serve(&settings, &message) {
+                        outgoing.lock().unwrap().send(answer)?;
+                    }
+                }
+            }
+        });
+        Ok(service)
+    }
+
+    #[cfg(not(unix))]
+    pub fn connect(_: Bus, _: Arc<Mutex<Settings>>) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "D-Bus needs a Unix system",
+        ))
+    }
+}
+
+impl Sink for Service {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        if let Some(message) = self.messages.take(event) {
+            // Strings on the bus can't hold NUL
+            let text = Value::Str(message.replace('\0', ""));
+            let signal = Message::signal(INTERFACE, "MessageDecoded", vec![text]);
+            self.outgoing.lock().unwrap().send(signal)?;
+        }
+        Ok(())
+    }
+}
+
+/// Connects to the first Unix socket in a bus address
+#[cfg(unix)]
+fn connect(address: &str) -> io::Result<std::os::unix::net::UnixStream> {
+    use std::os::unix::net::UnixStream;
+
+    for address in address.split(';') {
+        let Some(options) = address.strip_prefix("unix:") else {
+            continue;
+        };
+        for option in options.split(',') {
+            match option.split_once('=') {
+                Some(("path", path)) => return UnixStream::connect(unescape(path)),
+                #[cfg(target_os = "linux")]
+                Some(("abstract", name)) => {
+                    use std::os::linux::net::SocketAddrExt;
+                    let name = unescape(name);
+                    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                    return UnixStream::connect_addr(&address);
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "can't connect to the bus at `{}`, only to Unix sockets",
+            address
+        ),
+    ))
+}
+
+/// Undoes the percent encoding of a value in an address
+fn unescape(value: &str) -> String {
+    let mut unescaped = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        let escaped = match byte {
+            b'%' => bytes.next().zip(bytes.next()).and_then(|(high, low)| {
+                let digits = [high, low];
+                let digits = std::str::from_utf8(&digits).ok()?;
+                u8::from_str_radix(digits, 16).ok()
+            }),
+            _ => None,
+        };
+        unescaped.push(escaped.unwrap_or(byte));
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+/// Authenticates as the user the decoder runs as, which the bus checks against the socket
+#[cfg(unix)]
+fn authenticate<R: io::BufRead, W: Write>(reader: &mut R, writer: &mut W) -> io::Result<()> {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let uid: String = uid.bytes().map(|digit| format!("{:02x}", digit)).collect();
+    write!(writer, "\0AUTH EXTERNAL {}\r\n", uid)?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("OK ") {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("the bus refused to authenticate: {}", line.trim()),
+        ));
+    }
+    writer.write_all(b"BEGIN\r\n")
+}
+
+/// Waits for the reply to a call, setting aside anything that comes before it
+fn reply<R: Read>(reader: &mut R, serial: u32) -> io::Result<Message> {
+    loop {
+        let message = Message::read(reader)?;
+        if message.reply_serial != Some(serial) {
+            continue;
+        }
+        match message.kind {
+            ERROR => {
+                let name = message.error_name.unwrap_or_default();
+                return Err(io::Error::other(match message.body.first() {
+                    Some(Value::Str(text)) => format!("{}: {}", name, text),
+                    _ => name,
+                }));
+            }
+            _ => return Ok(message),
+        }
+    }
+}
+
+/// Answers a method call, followed by a signal if it changed the properties
+fn serve(settings: &Mutex<Settings>, call: &Message) -> Vec<Message> {
+    let mut settings = settings.lock().unwrap();
+    let before = properties(&settings);
+    let answer = answer(&mut settings, call);
+    let mut sent = Vec::new();
+    if call.flags & NO_REPLY_EXPECTED == 0 {
+        let reply = Message {
+            reply_serial: Some(call.serial),
+            destination: call.sender.clone(),
+            ..Message::default()
+        };
+        sent.push(match answer {
+            Ok(body) => Message {
+                kind: METHOD_RETURN,
+                body,
+                ..reply
+            },
+            Err((name, text)) => Message {
+                kind: ERROR,
+                error_name: Some(name.to_string()),
+                body: vec![Value::Str(text)],
+                ..reply
+            },
+        });
+    }
+    let changed: Vec<Value> = properties(&settings)
+        .into_iter()
+        .zip(before)
+        .filter(|(after, before)| after != before)
+        .map(|((name, value), _)| property_entry(name, value))
+        .collect();
+    if !changed.is_empty() {
+        let body = vec![
+            Value::Str(INTERFACE.to_string()),
+            Value::Array("{sv}".to_string(), changed),
+            Value::Array("s".to_string(), Vec::new()),
+        ];
+        sent.push(Message::signal(PROPERTIES, "PropertiesChanged", body));
+    }
+    sent
+}
+
+/// The body of the reply to a call, or the name and text of the error it's answered with
+fn answer(settings: &mut Settings, call: &Message) -> Result<Vec<Value>, (&'static str, String)> {
+    let path = call.path.as_deref().unwrap_or_default();
+    if path != PATH {
+        return Err((UNKNOWN_OBJECT, format!("there's no object at {}", path)));
+    }
+    let member = call.member.as_deref().unwrap_or_default();
+    // The interface is optional on calls
+    match (call.interface.as_deref(), member, &call.body[..]) {
+        (Some(INTERFACE) | None, "Start", []) => {
+            settings.apply(&Command::Start);
+            Ok(Vec::new())
+        }
+        (Some(INTERFACE) | None, "Stop", []) => {
+            settings.apply(&Command::Stop);
+            Ok(Vec::new())
+        }
+        (Some(PROPERTIES) | None, "Get", [Value::Str(interface), Value::Str(name)]) => {
+            our_interface(interface)?;
+            let value = properties(settings)
+                .into_iter()
+                .find(|(property, _)| property == name)
+                .map(|(_, value)| Value::Variant(Box::new(value)));
+            value
+                .map(|value| vec![value])
+                .ok_or_else(|| (UNKNOWN_PROPERTY, format!("there's no property {}", name)))
+        }
+        (Some(PROPERTIES) | None, "GetAll", [Value::Str(interface)]) => {
+            our_interface(interface)?;
+            let entries = properties(settings)
+                .into_iter()
+                .map(|(name, value)| property_entry(name, value))
+                .collect();
+            Ok(vec![Value::Array("{sv}".to_string(), entries)])
+        }
+        (
+            Some(PROPERTIES) | None,
+            "Set",
+            [Value::Str(interface), Value::Str(name), Value::Variant(value)],
+        ) => {
+            our_interface(interface)?;
+            let command = match (name.as_str(), &**value) {
+                ("Standard", Value::Str(standard)) => {
+                    Command::Standard(standard.parse().map_err(|e| (INVALID_ARGS, e))?)
+                }
+                ("Origin", Value::Bool(origin)) => Command::Origin(*origin),
+                ("Standard", _) | ("Origin", _) => {
+                    return Err((
+                        INVALID_ARGS,
+                        format!("{} can't be {}", name, value.signature()),
+                    ))
+                }
+                ("Running", _) => {
+                    return Err((READ_ONLY, "Running changes with Start and Stop".to_string()))
+                }
+                _ => return Err((UNKNOWN_PROPERTY, format!("there's no property {}", name))),
+            };
+            settings.apply(&command);
+            Ok(Vec::new())
+        }
+        (Some(INTROSPECTABLE) | None, "Introspect", []) => {
+            Ok(vec![Value::Str(INTROSPECTION.to_string())])
+        }
+        (Some(PEER) | None, "Ping", []) => Ok(Vec::new()),
+        (interface, member, body) => {
+            let types: String = body.iter().map(Value::signature).collect();
+            let interface = interface.map_or(String::new(), |name| format!("{}.", name));
+            Err((
+                UNKNOWN_METHOD,
+                format!("there's no method {}{}({})", interface, member, types),
+            ))
+        }
+    }
+}
+
+fn our_interface(interface: &str) -> Result<(), (&'static str, String)> {
+    match interface {
+        INTERFACE | "" => Ok(()),
+        _ => Err((
+            UNKNOWN_INTERFACE,
+            format!("there's no interface {}", interface),
+        )),
+    }
+}
+
+fn properties(settings: &Settings) -> Vec<(&'static str, Value)> {
+    vec![
+        ("Running", Value::Bool(settings.running)),
+        (
+            "Standard",
+            Value::Str(format!("{:?}", settings.standard).to_lowercase()),
+        ),
+        ("Origin", Value::Bool(settings.origin)),
+    ]
+}
+
+fn property_entry(name: &str, value: Value) -> Value {
+    Value::DictEntry(
+        Box::new(Value::Str(name.to_string())),
+        Box::new(Value::Variant(Box::new(value))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::standard::Standard;
+
+    #[test]
+    fn test_messages() {
+        let hello = Message {
+            serial: 1,
+            ..Message::to_bus("Hello", Vec::new())
+        };
+        let encoded = hello.encode();
+        assert_eq!(&encoded[..16], b"l\x01\x00\x01\0\0\0\0\x01\0\0\0\x6d\0\0\0");
+        assert_eq!(encoded.len() % 8, 0);
+        assert_eq!(Message::read(&mut &encoded[..]).unwrap(), hello);
+
+        let changed = vec![
+            property_entry("Origin", Value::Bool(true)),
+            property_entry("Standard", Value::Str("v21".to_string())),
+        ];
+        let signal = Message {
+            serial: 7,
+            sender: Some(":1.42".to_string()),
+            ..Message::signal(
+                PROPERTIES,
+                "PropertiesChanged",
+                vec![
+                    Value::Str(INTERFACE.to_string()),
+                    Value::Array("{sv}".to_string(), changed),
+                    Value::Array("s".to_string(), Vec::new()),
+                ],
+            )
+        };
+        let encoded = signal.encode();
+        assert_eq!(Message::read(&mut &encoded[..]).unwrap(), signal);
+        assert!(Message::read(&mut &encoded[..encoded.len() - 1]).is_err());
+
+        assert_eq!(split_type("a{sv}as"), Ok(("a{sv}", "as")));
+        assert_eq!(split_type("(yv)"), Ok(("(yv)", "")));
+        assert!(split_type("a").is_err());
+        assert!(split_type(")").is_err());
+        assert_eq!(unescape("/tmp/dbus%2dtest"), "/tmp/dbus-test");
+        assert_eq!("system".parse(), Ok(Bus::System));
+    }
+
+    #[test]
+    fn test_calls() {
+        let settings = Mutex::new(Settings {
+            running: true,
+            standard: Standard::Bell103,
+            origin: false,
+        });
+        let call = |interface: &str, member: &str, body: Vec<Value>| Message {
+            kind: METHOD_CALL,
+            serial: 3,
+            path: Some(PATH.to_string()),
+            interface: Some(interface.to_string()).filter(|name| !name.is_empty()),
+            member: Some(member.to_string()),
+            sender: Some(":1.9".to_string()),
+            body,
+            ..Message::default()
+        };
+        let string = |text: &str| Value::Str(text.to_string());
+        let variant = |value: Value| Value::Variant(Box::new(value));
+
+        let sent = serve(&settings, &call("", "Stop", Vec::new()));
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[0].kind, METHOD_RETURN);
+        assert_eq!(sent[0].reply_serial, Some(3));
+        assert_eq!(sent[0].destination.as_deref(), Some(":1.9"));
+        assert_eq!(
+            sent[1].body[1],
+            Value::Array(
+                "{sv}".to_string(),
+                vec![property_entry("Running", Value::Bool(false))]
+            )
+        );
+        assert_eq!(settings.lock().unwrap().tuning(), None);
+
+        let set = vec![
+            string(INTERFACE),
+            string("Standard"),
+            variant(string("v23")),
+        ];
+        assert_eq!(serve(&settings, &call(PROPERTIES, "Set", set)).len(), 2);
+        let get = vec![string(INTERFACE), string("Standard")];
+        let sent = serve(&settings, &call(PROPERTIES, "Get", get));
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].body, [variant(string("v23"))]);
+        assert_eq!(settings.lock().unwrap().standard, Standard::V23);
+
+        let error = |body: Vec<Value>| {
+            let sent = serve(&settings, &call(PROPERTIES, "Set", body));
+            assert_eq!(sent[0].kind, ERROR);
+            sent[0].error_name.clone().unwrap()
+        };
+        let set = |name: &str, value: Value| vec![string(INTERFACE), string(name), variant(value)];
+        assert_eq!(error(set("Running", Value::Bool(true))), READ_ONLY);
+        assert_eq!(error(set("Origin", string("on"))), INVALID_ARGS);
+        assert_eq!(error(set("Standard", string("v99"))), INVALID_ARGS);
+        assert_eq!(error(set("Baud", Value::U32(300))), UNKNOWN_PROPERTY);
+
+        let mut quiet = call(INTERFACE, "Start", Vec::new());
+        quiet.flags = NO_REPLY_EXPECTED;
+        assert_eq!(serve(&settings, &quiet).len(), 1);
+        assert_eq!(
+            settings.lock().unwrap().tuning(),
+            Some(Standard::V23.answer())
+        );
+        let sent = serve(&settings, &call(INTERFACE, "Restart", Vec::new()));
+        assert_eq!(sent[0].error_name.as_deref(), Some(UNKNOWN_METHOD));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::goertzel::{GoertzelPair, GoertzelPair32};
+use std::fmt;
+
+/// Decisions per bit taken when timing asynchronous characters
+pub const TICKS_PER_BIT: usize = 8;
+
+/// A demodulation algorithm, judging how much a window of samples sounds like mark or space.
+/// The Goertzel filters of [`Demodulator::new`] are one, and [`crate::backend`] adds others.
+pub trait Detector: fmt::Debug + Send {
+    /// Returns a soft decision for one window, between -1.0 (space) and 1.0 (mark)
+    fn decide(&mut self, window: &[i16]) -> f64;
+}
+
+/// Compares mark and space filter energy over a block, filtering for both in one pass
+#[derive(Debug)]
+pub struct GoertzelDetector {
+    filters: GoertzelPair,
+}
+
+impl GoertzelDetector {
+    pub fn new(
+        block_size: usize,
+        mark_frequency: f64,
+        space_frequency: f64,
+        sampling_rate: f64,
+    ) -> Self {
+        Self {
+            filters: GoertzelPair::new(
+                block_size,
+                [mark_frequency, space_frequency],
+                sampling_rate,
+            ),
+        }
+    }
+}
+
+impl Detector for GoertzelDetector {
+    fn decide(&mut self, window: &[i16]) -> f64 {
+        let [mark, space] = self.filters.energies(window);
+        soft_decision(mark, space)
+    }
+}
+
+/// [`GoertzelDetector`] in single precision, see [`GoertzelPair32`]
+#[derive(Debug)]
+pub struct GoertzelDetector32 {
+    filters: GoertzelPair32,
+}
+
+impl GoertzelDetector32 {
+    pub fn new(
+        block_size: usize,
+        mark_frequency: f64,
+        space_frequency: f64,
+        sampling_rate: f64,
+    ) -> Self {
+        Self {
+            filters: GoertzelPair32::new(
+                block_size,
+                [mark_frequency, space_frequency],
+                sampling_rate,
+            ),
+        }
+    }
+}
+
+impl Detector for GoertzelDetector32 {
+    fn decide(&mut self, window: &[i16]) -> f64 {
+        let [mark, space] = self.filters.energies(window);
+        let total = mark + space;
+        if total > 0.0 {
+            f64::from((mark - space) / total)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Takes decisions over consecutive blocks of one bit period, by comparing mark and space
+/// filter energy unless given another [`Detector`]
+#[derive(Debug)]
+pub struct Demodulator {
+    detector: Box<dyn Detector>,
+    block_size: usize,
+}
+
+impl Demodulator {
+    pub fn new(
+        block_size: usize,
+        mark_frequency: f64,
+        space_frequency: f64,
+        sampling_rate: f64,
+    ) -> Self {
+        let detector =
+            GoertzelDetector::new(block_size, mark_frequency, space_frequency, sampling_rate);
+        Self::with_detector(block_size, Box::new(detector))
+    }
+
+    pub fn with_detector(block_size: usize, detector: Box<dyn Detector>) -> Self {
+        Self {
+            detector,
+            block_size,
+        }
+    }
+
+    /// Decides one bit per block, 1 (mark) when the mark tone is at least as strong as space
+    pub fn bits(&mut self, samples: &[i16]) -> Vec<u8> {
+        samples
+            .chunks(self.block_size)
+            .map(|block| self.bit(block))
+            .collect()
+    }
+
+    /// Decides the bit of a single block, as [`Demodulator::bits`] does each of its blocks
+    pub fn bit(&mut self, block: &[i16]) -> u8 {
+        u8::from(self.soft_bit(block) >= 0.0)
+    }
+
+    /// The soft decision of a single block or window, between -1.0 (space) and 1.0 (mark)
+    pub fn soft_bit(&mut self, block: &[i16]) -> f64 {
+        self.detector.decide(block)
+    }
+
+    /// The samples each decision is taken over
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Returns a soft decision per block between -1.0 (space) and 1.0 (mark)
+    pub fn soft_bits(&mut self, samples: &[i16]) -> Vec<f64> {
+        let mut soft = Vec::with_capacity(samples.len() / self.block_size);
+        for block in samples.chunks(self.block_size) {
+            soft.push(self.detector.decide(block));
+        }
+        soft
+    }
+
+    /// Returns soft decisions from a block-sized window slid along `step` samples at a time,
+    /// for receivers that need finer timing than one decision per bit. Decision `i` covers
+    /// the samples starting at `i * step`.
+    pub fn sliding_soft_bits(&mut self, samples: &[i16], step: usize) -> Vec<f64> {
+        if samples.len() < self.block_size {
+            return Vec::new();
+        }
+        let count = (samples.len() - self.block_size) / step + 1;
+        (0..count)
+            .map(|i| {
+                let start = i * step;
+                self.detector
+                    .decide(&samples[start..start + self.block_size])
+            })
+            .collect()
+    }
+}
+
+fn soft_decision(mark: f64, space: f64) -> f64 {
+    let total = mark + space;
+    if total > 0.0 {
+        (mark - space) / total
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,271 @@
+use crate::modulator::FskModulator;
+use crate::spectrum;
+use crate::standard::{Channel, Standard};
+use std::fmt;
+use std::ops::Range;
+
+/// How much of the start of a recording is analyzed
+pub const DETECT_SECS: f64 = 5.0;
+/// Spectra are averaged over blocks of about this long
+const BLOCK_SECS: f64 = 0.1;
+pub const MIN_TONE: f64 = 100.0;
+pub const MAX_TONE: f64 = 4000.0;
+/// Simulated signals are compared over this long
+const TEMPLATE_SECS: f64 = 2.0;
+/// Common RTTY shifts, matched within a few percent
+const RTTY_SHIFTS: [f64; 4] = [170.0, 425.0, 450.0, 850.0];
+const RTTY_SHIFT_TOLERANCE: f64 = 0.05;
+const RTTY_BAUD: f64 = 45.45;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Identified {
+    Modem {
+        standard: Standard,
+        origin: bool,
+    },
+    /// A two-tone signal at an RTTY shift that isn't a modem standard
+    Rtty {
+        low: f64,
+        shift: f64,
+    },
+}
+
+impl fmt::Display for Identified {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Identified::Modem { standard, origin } => {
+                let channel = standard.channel(origin);
+                let direction = if standard.originate() == standard.answer() {
+                    ""
+                } else if origin {
+                    " originating channel"
+                } else {
+                    " answering channel"
+                };
+                write!(
+                    f,
+                    "{:?}{} ({}/{} Hz, {} baud)",
+                    standard, direction, channel.mark, channel.space, channel.baud
+                )
+            }
+            Identified::Rtty { low, shift } => {
+                write!(f, "RTTY ({:.0} Hz shift from {:.0} Hz)", shift, low)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Detection {
+    pub identified: Identified,
+    /// How clearly the best match beat the runner up, from 0.5 for a tie towards 1
+    pub confidence: f64,
+}
+
+/// Averages the power spectrum over the first seconds of a recording
+pub fn average_spectrum(samples: &[i16], sampling_rate: f64) -> (Vec<f64>, f64) {
+    let size = ((BLOCK_SECS * sampling_rate) as usize).next_power_of_two();
+    let end = samples.len().min((DETECT_SECS * sampling_rate) as usize);
+    let mut average = vec![0.0; size / 2];
+    for block in samples[..end].chunks_exact(size) {
+        for (sum, power) in average.iter_mut().zip(spectrum::power_spectrum(block)) {
+            *sum += power;
+        }
+    }
+    (average, sampling_rate / size as f64)
+}
+
+/// Ratio in dB of the power between a channel's tones, widened by half the baud rate on each
+/// side, to the power everywhere else above DC, over the start of a recording
+pub fn band_snr(samples: &[i16], channel: Channel, sampling_rate: f64) -> f64 {
+    // A recording shorter than a block is padded out to one
+    let size = ((BLOCK_SECS * sampling_rate) as usize).next_power_of_two();
+    let mut padded = samples.to_vec();
+    padded.resize(size.max(samples.len()), 0);
+    let (spectrum, bin_hz) = average_spectrum(&padded, sampling_rate);
+    let low = channel.mark.min(channel.space) - channel.baud / 2.0;
+    let high = channel.mark.max(channel.space) + channel.baud / 2.0;
+    let (mut signal, mut noise) = (0.0, 0.0);
+    for (i, power) in spectrum.iter().enumerate().skip(1) {
+        if (low..=high).contains(&(i as f64 * bin_hz)) {
+            signal += power;
+        } else {
+            noise += power;
+        }
+    }
+    10.0 * (signal / f64::max(noise, f64::MIN_POSITIVE)).log10()
+}
+
+/// Pseudo-random bits for the simulated signals
+fn random_bits(count: usize) -> Vec<u8> {
+    let mut state = 12345u32;
+    (0..count)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8 & 1
+        })
+        .collect()
+}
+
+/// The spectrum a channel carrying random data would have
+fn template(channel: Channel, sampling_rate: f64) -> Vec<f64> {
+    let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+    let mut samples = Vec::new();
+    let bits = (TEMPLATE_SECS * channel.baud) as usize;
+    modulator.modulate(&random_bits(bits), &mut samples);
+    average_spectrum(&samples, sampling_rate).0
+}
+
+/// Cosine similarity of two amplitude spectra over the range tones are found in
+fn similarity(a: &[f64], b: &[f64], range: Range<usize>) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for i in range {
+        let (x, y) = (a[i].sqrt(), b[i].sqrt());
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b).sqrt()
+    }
+}
+
+/// Identifies the standard used at the start of a recording by comparing its spectrum with
+/// the spectrum each known channel would produce, and with an RTTY signal on any pair of
+/// peaks at an RTTY shift
+pub fn identify(samples: &[i16], sampling_rate: f64) -> Option<Detection> {
+    let (spectrum, bin_hz) = average_spectrum(samples, sampling_rate);
+    let range = (MIN_TONE / bin_hz) as usize..((MAX_TONE / bin_hz) as usize).min(spectrum.len());
+    if spectrum[range.clone()].iter().all(|&power| power == 0.0) {
+        return None;
+    }
+
+    let mut candidates: Vec<(Identified, Channel)> = Vec::new();
+    for &standard in &Standard::ALL {
+        for &origin in &[false, true] {
+            if !origin || standard.originate() != standard.answer() {
+                let identified = Identified::Modem { standard, origin };
+                candidates.push((identified, standard.channel(origin)));
+            }
+        }
+    }
+
+    // The two strongest peaks at least a shift apart
+    let strongest = |bins: &mut dyn Iterator<Item = usize>| {
+        bins.max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+    };
+    if let Some(first) = strongest(&mut range.clone()) {
+        let min_distance = RTTY_SHIFTS[0] * (1.0 - RTTY_SHIFT_TOLERANCE) / bin_hz;
+        let apart = |&b: &usize| (b as f64 - first as f64).abs() >= min_distance;
+        if let Some(second) = strongest(&mut range.clone().filter(apart)) {
+            let first = spectrum::interpolate_peak(&spectrum, first) * bin_hz;
+            let second = spectrum::interpolate_peak(&spectrum, second) * bin_hz;
+            let (low, high) = (first.min(second), first.max(second));
+            let shift = RTTY_SHIFTS
+                .iter()
+                .find(|&&shift| ((high - low) / shift - 1.0).abs() <= RTTY_SHIFT_TOLERANCE);
+            if let Some(&shift) = shift {
+                let channel = Channel {
+                    mark: low,
+                    space: high,
+                    baud: RTTY_BAUD,
+                };
+                candidates.push((Identified::Rtty { low, shift }, channel));
+            }
+        }
+    }
+
+    let mut scored: Vec<(Identified, f64)> = candidates
+        .into_iter()
+        .map(|(identified, channel)| {
+            let template = template(channel, sampling_rate);
+            (identified, similarity(&spectrum, &template, range.clone()))
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    let (identified, best) = scored[0];
+    let runner_up = scored.get(1).map_or(0.0, |c| c.1);
+    Some(Detection {
+        identified,
+        confidence: confidence(best, runner_up),
+    })
+}
+
+/// Compares the dissimilarity left by the best and runner up matches: 0.5 when they match
+/// equally well, approaching 1 as the best one becomes a much closer fit
+fn confidence(best: f64, runner_up: f64) -> f64 {
+    let (best, runner_up) = (1.0 - best, 1.0 - runner_up);
+    if best + runner_up == 0.0 {
+        0.5
+    } else {
+        runner_up / (best + runner_up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A different bit sequence from the templates
+    fn random_bits(count: usize) -> Vec<u8> {
+        let mut state = 987u32;
+        (0..count)
+            .map(|_| {
+                state = state.wrapping_mul(69_069).wrapping_add(1);
+                (state >> 20) as u8 & 1
+            })
+            .collect()
+    }
+
+    fn signal(mark: f64, space: f64, baud: f64) -> Vec<i16> {
+        let mut modulator = FskModulator::new(mark, space, baud, 8_000.0);
+        let mut samples = Vec::new();
+        modulator.modulate(&random_bits((2.0 * baud) as usize), &mut samples);
+        samples
+    }
+
+    #[test]
+    fn test_identify_standards() {
+        for &standard in &Standard::ALL {
+            for &origin in &[false, true] {
+                let channel = standard.channel(origin);
+                let samples = signal(channel.mark, channel.space, channel.baud);
+                let detection = identify(&samples, 8_000.0).unwrap();
+                let expected_origin = origin && standard.originate() != standard.answer();
+                let expected = Identified::Modem {
+                    standard,
+                    origin: expected_origin,
+                };
+                assert_eq!(detection.identified, expected);
+                assert!(detection.confidence > 0.6, "{:?}", detection);
+            }
+        }
+    }
+
+    #[test]
+    fn test_band_snr() {
+        let channel = Standard::Bell103.answer();
+        let samples = signal(channel.mark, channel.space, channel.baud);
+        assert!(band_snr(&samples, channel, 8_000.0) > 10.0);
+        // The other direction is out of band
+        assert!(band_snr(&samples, Standard::Bell103.originate(), 8_000.0) < -10.0);
+        assert!(band_snr(&samples[..200], channel, 8_000.0) > 10.0);
+        assert_eq!(band_snr(&[0; 8000], channel, 8_000.0), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_identify_rtty() {
+        let samples = signal(2125.0, 2295.0, 45.45);
+        let detection = identify(&samples, 8_000.0).unwrap();
+        match detection.identified {
+            Identified::Rtty { low, shift } => {
+                assert_eq!(shift, 170.0);
+                assert!((low - 2125.0).abs() < 5.0);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(identify(&[0; 8000], 8_000.0), None);
+    }
+}
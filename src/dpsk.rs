@@ -0,0 +1,253 @@
+use crate::demodulator::TICKS_PER_BIT;
+use crate::filter::Biquad;
+use crate::framing::UartReceiver;
+use std::f64::consts::PI;
+
+pub const SYMBOL_RATE: f64 = 600.0;
+pub const ORIGINATE_CARRIER: f64 = 1200.0;
+pub const ANSWER_CARRIER: f64 = 2400.0;
+/// Each dibit, first bit first, advances the carrier phase by a number of quarter turns
+const PHASE_CHANGES: [([u8; 2], usize); 4] = [([0, 0], 1), ([0, 1], 0), ([1, 1], 3), ([1, 0], 2)];
+/// Taps of the self-synchronizing 1 + x^-14 + x^-17 scrambler
+const SCRAMBLER_TAPS: [usize; 2] = [14, 17];
+/// How far each timing error moves the symbol clock, in symbols
+const TIMING_GAIN: f64 = 0.02;
+/// How quickly the phase correction follows the decision errors
+const PHASE_GAIN: f64 = 0.05;
+/// Symbols used to find the initial symbol timing
+const ACQUISITION_SYMBOLS: usize = 32;
+/// Symbols weaker than this fraction of the average power are treated as no carrier
+const MIN_POWER: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Iq {
+    re: f64,
+    im: f64,
+}
+
+impl Iq {
+    fn lerp(self, other: Iq, t: f64) -> Iq {
+        Iq {
+            re: self.re + t * (other.re - self.re),
+            im: self.im + t * (other.im - self.im),
+        }
+    }
+
+    fn power(self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The phase of `self` relative to `other`
+    fn angle_from(self, other: Iq) -> f64 {
+        let re = self.re * other.re + self.im * other.im;
+        let im = self.im * other.re - self.re * other.im;
+        im.atan2(re)
+    }
+}
+
+/// Mixes the channel around `carrier` down to baseband. Averaging over one symbol removes
+/// the mixing products at twice the carrier and the other channel's carrier, which all fall
+/// on multiples of the symbol rate.
+fn baseband(samples: &[i16], sampling_rate: f64, carrier: f64) -> Vec<Iq> {
+    let length = (sampling_rate / SYMBOL_RATE).round() as usize;
+    let mut filter = Biquad::band_pass(carrier, carrier / 1200.0, sampling_rate);
+    let mixed: Vec<Iq> = samples
+        .iter()
+        .enumerate()
+        .map(|(n, &s)| {
+            let x = filter.process(f64::from(s));
+            let phase = 2.0 * PI * carrier * n as f64 / sampling_rate;
+            Iq {
+                re: x * phase.cos(),
+                im: -x * phase.sin(),
+            }
+        })
+        .collect();
+    let mut sum = Iq::default();
+    (0..mixed.len())
+        .map(|n| {
+            sum.re += mixed[n].re;
+            sum.im += mixed[n].im;
+            if n >= length {
+                sum.re -= mixed[n - length].re;
+                sum.im -= mixed[n - length].im;
+            }
+            Iq {
+                re: sum.re / length as f64,
+                im: sum.im / length as f64,
+            }
+        })
+        .collect()
+}
+
+/// Recovers symbols with a Gardner timing detector, which compares the change across each
+/// symbol with the value halfway between and is independent of the carrier phase
+fn symbols(baseband: &[Iq], sampling_rate: f64) -> Vec<Iq> {
+    let samples_per_symbol = sampling_rate / SYMBOL_RATE;
+    let at = |t: f64| {
+        let i = t.floor() as usize;
+        baseband[i].lerp(baseband[(i + 1).min(baseband.len() - 1)], t - t.floor())
+    };
+    if baseband.len() < 2 * samples_per_symbol as usize {
+        return Vec::new();
+    }
+    // Start at the carrier, on the phase with the most power: the end of each symbol, where
+    // the average covers just that symbol
+    let average = baseband.iter().map(|z| z.power()).sum::<f64>() / baseband.len() as f64;
+    let start = baseband
+        .iter()
+        .position(|z| z.power() >= MIN_POWER * average)
+        .unwrap_or(0) as f64;
+    let power_at = |offset: f64| {
+        (1..=ACQUISITION_SYMBOLS)
+            .map(|k| start + offset + k as f64 * samples_per_symbol)
+            .take_while(|&t| t + 1.0 < baseband.len() as f64)
+            .map(|t| at(t).power())
+            .sum::<f64>()
+    };
+    let offset = (0..samples_per_symbol as usize)
+        .map(|offset| offset as f64)
+        .max_by(|&a, &b| power_at(a).partial_cmp(&power_at(b)).unwrap())
+        .unwrap();
+
+    let mut symbols = Vec::new();
+    let mut t = start + offset + samples_per_symbol;
+    let mut previous = at(t - samples_per_symbol);
+    while t + 1.0 < baseband.len() as f64 {
+        let current = at(t);
+        let middle = at(t - samples_per_symbol / 2.0);
+        let power = (current.power() + previous.power()) / 2.0;
+        let error = if power > 0.0 {
+            ((current.re - previous.re) * middle.re + (current.im - previous.im) * middle.im)
+                / power
+        } else {
+            0.0
+        };
+        symbols.push(current);
+        previous = current;
+        t += samples_per_symbol * (1.0 - TIMING_GAIN * error.clamp(-1.0, 1.0));
+    }
+    symbols
+}
+
+/// Decides the dibit carried by each change of phase. The average decision error is fed
+/// back as a phase correction, which removes any carrier frequency offset.
+fn dibits(symbols: &[Iq]) -> Vec<u8> {
+    let average = symbols.iter().map(|s| s.power()).sum::<f64>() / symbols.len().max(1) as f64;
+    let mut correction = 0.0;
+    let mut bits = Vec::new();
+    for pair in symbols.windows(2) {
+        if pair[0].power().min(pair[1].power()) < MIN_POWER * average {
+            continue;
+        }
+        let change = pair[1].angle_from(pair[0]) - correction;
+        let quarters = (change / (PI / 2.0)).round();
+        correction += PHASE_GAIN * (change - quarters * PI / 2.0);
+        let quarters = quarters.rem_euclid(4.0) as usize;
+        let (dibit, _) = PHASE_CHANGES.iter().find(|&&(_, q)| q == quarters).unwrap();
+        bits.extend(dibit);
+    }
+    bits
+}
+
+/// Descrambles received bits, dropping the first few that come out before the descrambler
+/// has filled with received bits
+pub fn descramble(bits: &[u8]) -> Vec<u8> {
+    let delay = SCRAMBLER_TAPS[1];
+    (delay.min(bits.len())..bits.len())
+        .map(|i| {
+            SCRAMBLER_TAPS
+                .iter()
+                .fold(bits[i], |bit, &tap| bit ^ bits[i - tap])
+        })
+        .collect()
+}
+
+/// Demodulates one direction of a Bell 212A or V.22 call to its descrambled bit stream
+pub fn demodulate(samples: &[i16], sampling_rate: f64, carrier: f64) -> Vec<u8> {
+    let baseband = baseband(samples, sampling_rate, carrier);
+    descramble(&dibits(&symbols(&baseband, sampling_rate)))
+}
+
+/// Decodes the asynchronous 8N1 characters carried by the synchronous bit stream
+pub fn decode(samples: &[i16], sampling_rate: f64, carrier: f64) -> Vec<u8> {
+    let mut receiver = UartReceiver::new(TICKS_PER_BIT as f64, 8);
+    demodulate(samples, sampling_rate, carrier)
+        .into_iter()
+        .flat_map(|bit| std::iter::repeat_n(if bit == 1 { 1.0 } else { -1.0 }, TICKS_PER_BIT))
+        .filter_map(|soft| receiver.push(soft))
+        .map(|r| r.byte)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing;
+    use crate::modulator::AMPLITUDE;
+
+    fn scramble(bits: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = Vec::with_capacity(bits.len());
+        for (i, &bit) in bits.iter().enumerate() {
+            let bit = SCRAMBLER_TAPS
+                .iter()
+                .filter(|&&tap| i >= tap)
+                .fold(bit, |bit, &tap| bit ^ out[i - tap]);
+            out.push(bit);
+        }
+        out
+    }
+
+    /// Modulates a message with the carrier and symbol clock both slightly off
+    fn modulate(message: &[u8], carrier: f64, sampling_rate: f64) -> Vec<i16> {
+        let mut bits = vec![1; 200];
+        bits.extend(framing::frame(message));
+        bits.extend(vec![1; 100]);
+        let bits = scramble(&bits);
+        let mut quarters = 0;
+        let phases: Vec<usize> = bits
+            .chunks_exact(2)
+            .map(|dibit| {
+                let (_, q) = PHASE_CHANGES.iter().find(|(d, _)| d == dibit).unwrap();
+                quarters = (quarters + q) % 4;
+                quarters
+            })
+            .collect();
+        let samples_per_symbol = sampling_rate / (SYMBOL_RATE * 1.0005);
+        let carrier = carrier + 3.0;
+        (0..(phases.len() as f64 * samples_per_symbol) as usize)
+            .map(|n| {
+                let symbol = ((n as f64 + 17.0) / samples_per_symbol) as usize;
+                let phase = phases[symbol.min(phases.len() - 1)] as f64 * PI / 2.0;
+                let t = n as f64 / sampling_rate;
+                (0.4 * AMPLITUDE * (2.0 * PI * carrier * t + phase).cos()) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_descramble_round_trip() {
+        let bits: Vec<u8> = (0..200).map(|i| ((i * 7) % 3 == 0) as u8).collect();
+        assert_eq!(descramble(&scramble(&bits)), bits[SCRAMBLER_TAPS[1]..]);
+    }
+
+    #[test]
+    fn test_decode_both_channels() {
+        let answer = modulate(b"Hello from the answering modem", ANSWER_CARRIER, 48_000.0);
+        let originate = modulate(
+            b"Hello from the originating modem",
+            ORIGINATE_CARRIER,
+            48_000.0,
+        );
+        let summed: Vec<i16> = answer
+            .iter()
+            .zip(&originate)
+            .map(|(&a, &o)| a / 2 + o / 2)
+            .collect();
+
+        let text = decode(&summed, 48_000.0, ANSWER_CARRIER);
+        assert_eq!(text, b"Hello from the answering modem");
+        let text = decode(&summed, 48_000.0, ORIGINATE_CARRIER);
+        assert_eq!(text, b"Hello from the originating modem");
+    }
+}
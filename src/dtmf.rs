@@ -0,0 +1,270 @@
+use crate::goertzel::{self, GoertzelFilter};
+use crate::modulator::{Oscillator, AMPLITUDE};
+use std::fmt;
+
+/// Low group frequencies, one per keypad row
+pub const ROW_FREQUENCIES: [f64; 4] = [697.0, 770.0, 852.0, 941.0];
+/// High group frequencies, one per keypad column
+pub const COLUMN_FREQUENCIES: [f64; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+/// Detection blocks are 25.6 ms, 205 samples at 8 kHz, advancing by half a block
+const BLOCK_SECS: f64 = 0.0256;
+/// The row and column tones must hold this much of a block's energy
+const MIN_TONE_ENERGY: f64 = 0.6;
+/// Each tone must be 6 dB stronger than the others in its group
+const MIN_GROUP_RATIO: f64 = 4.0;
+/// Row tones may be up to 8 dB stronger than column tones (normal twist) and column tones
+/// up to 4 dB stronger than row tones (reverse twist)
+const MAX_NORMAL_TWIST: f64 = 6.31;
+const MAX_REVERSE_TWIST: f64 = 2.51;
+pub const KEYPAD: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+/// Returns the row and column frequencies for a keypad digit
+pub fn tones(digit: char) -> Option<(f64, f64)> {
+    let digit = digit.to_ascii_uppercase();
+    KEYPAD.iter().enumerate().find_map(|(row, keys)| {
+        keys.iter()
+            .position(|&key| key == digit)
+            .map(|column| (ROW_FREQUENCIES[row], COLUMN_FREQUENCIES[column]))
+    })
+}
+
+/// Synthesizes DTMF digit sequences as tone bursts separated by silence
+#[derive(Debug)]
+pub struct DtmfGenerator {
+    sampling_rate: f64,
+    tone_samples: usize,
+    gap_samples: usize,
+}
+
+impl DtmfGenerator {
+    /// Tone and gap durations are in milliseconds
+    pub fn new(sampling_rate: f64, tone_ms: f64, gap_ms: f64) -> Self {
+        Self {
+            sampling_rate,
+            tone_samples: (sampling_rate * tone_ms / 1000.0) as usize,
+            gap_samples: (sampling_rate * gap_ms / 1000.0) as usize,
+        }
+    }
+
+    /// Appends the audio for `digits` to `samples`, failing on the first character that isn't
+    /// a keypad digit. A `,` inserts a pause the length of a digit.
+    pub fn generate(&self, digits: &str, samples: &mut Vec<i16>) -> Result<(), char> {
+        for digit in digits.chars() {
+            if digit == ',' {
+                let pause = self.tone_samples + self.gap_samples;
+                samples.extend(std::iter::repeat_n(0, pause));
+                continue;
+            }
+            let (row, column) = tones(digit).ok_or(digit)?;
+            let mut low = Oscillator::new(row, self.sampling_rate);
+            let mut high = Oscillator::new(column, self.sampling_rate);
+            for _ in 0..self.tone_samples {
+                let sample = 0.5 * (low.next_sample() + high.next_sample());
+                samples.push((AMPLITUDE * sample) as i16);
+            }
+            samples.extend(std::iter::repeat_n(0, self.gap_samples));
+        }
+        Ok(())
+    }
+}
+
+/// A digit found in a recording, timed in seconds
+#[derive(Debug, PartialEq)]
+pub struct Detection {
+    pub digit: char,
+    pub start: f64,
+    pub duration: f64,
+}
+
+impl fmt::Display for Detection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.3}s {} ({:.0} ms)",
+            self.start,
+            self.digit,
+            1000.0 * self.duration
+        )
+    }
+}
+
+/// Finds DTMF digits with a Goertzel filter for each of the eight tones
+#[derive(Debug)]
+pub struct DtmfDetector {
+    sampling_rate: f64,
+    block_size: usize,
+    hop: usize,
+    min_blocks: usize,
+}
+
+impl DtmfDetector {
+    /// Digits must last at least `min_ms` milliseconds, 40 under the ITU-T Q.24 limits
+    pub fn new(sampling_rate: f64, min_ms: f64) -> Self {
+        let block_size = (sampling_rate * BLOCK_SECS).round() as usize;
+        let hop = block_size / 2;
+        let min_blocks = ((sampling_rate * min_ms / 1000.0 - block_size as f64) / hop as f64)
+            .floor()
+            .max(0.0) as usize
+            + 1;
+        Self {
+            sampling_rate,
+            block_size,
+            hop,
+            min_blocks,
+        }
+    }
+
+    pub fn detect(&self, samples: &[i16]) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        let mut run: Option<(char, usize, usize)> = None;
+        let blocks = samples.len().saturating_sub(self.block_size) / self.hop + 1;
+        for i in 0..=blocks {
+            let digit = if i < blocks {
+                let start = i * self.hop;
+                self.classify(&samples[start..(start + self.block_size).min(samples.len())])
+            } else {
+                None
+            };
+            match run {
+                Some((current, _, ref mut count)) if Some(current) == digit => *count += 1,
+                _ => {
+                    if let Some((digit, first, count)) = run.take() {
+                        if count >= self.min_blocks {
+                            detections.push(Detection {
+                                digit,
+                                start: (first * self.hop) as f64 / self.sampling_rate,
+                                duration: ((count - 1) * self.hop + self.block_size) as f64
+                                    / self.sampling_rate,
+                            });
+                        }
+                    }
+                    run = digit.map(|digit| (digit, i, 1));
+                }
+            }
+        }
+        detections
+    }
+
+    /// Checks a block's level, tone purity and twist, returning its digit if it passes
+    fn classify(&self, block: &[i16]) -> Option<char> {
+        if goertzel::is_silent(block) {
+            return None;
+        }
+        let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+        // Scale Goertzel magnitudes to the energy of the tone in the block
+        let powers = |frequencies: &[f64; 4]| {
+            let mut powers = [0.0; 4];
+            for (power, &frequency) in powers.iter_mut().zip(frequencies) {
+                let mut filter = GoertzelFilter::new(block.len(), frequency, self.sampling_rate);
+                filter.process(block);
+                *power = 2.0 * filter.get_mag_sq() / block.len() as f64;
+            }
+            powers
+        };
+        let strongest = |powers: &[f64; 4]| {
+            let best = (0..4)
+                .max_by(|&a, &b| powers[a].partial_cmp(&powers[b]).unwrap())
+                .unwrap();
+            let clear = (0..4)
+                .filter(|&i| i != best)
+                .all(|i| powers[best] >= MIN_GROUP_RATIO * powers[i]);
+            Some(best).filter(|_| clear)
+        };
+        let rows = powers(&ROW_FREQUENCIES);
+        let columns = powers(&COLUMN_FREQUENCIES);
+        let row = strongest(&rows)?;
+        let column = strongest(&columns)?;
+        let (row_power, column_power) = (rows[row], columns[column]);
+        let twist_ok = row_power <= MAX_NORMAL_TWIST * column_power
+            && column_power <= MAX_REVERSE_TWIST * row_power;
+        let pure = row_power + column_power >= MIN_TONE_ENERGY * energy;
+        Some(KEYPAD[row][column]).filter(|_| twist_ok && pure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tones() {
+        assert_eq!(tones('5'), Some((770.0, 1336.0)));
+        assert_eq!(tones('#'), Some((941.0, 1477.0)));
+        assert_eq!(tones('d'), Some((941.0, 1633.0)));
+        assert_eq!(tones('x'), None);
+    }
+
+    #[test]
+    fn test_generate() {
+        let generator = DtmfGenerator::new(8_000.0, 50.0, 50.0);
+        let mut samples = Vec::new();
+        generator.generate("9,", &mut samples).unwrap();
+        assert_eq!(samples.len(), 4 * 400);
+        assert!(samples[400..].iter().all(|&s| s == 0));
+
+        // The strongest row and column should be those of the digit
+        let strongest = |frequencies: &[f64]| {
+            let energies: Vec<f64> = frequencies
+                .iter()
+                .map(|&frequency| {
+                    let mut filter = GoertzelFilter::new(400, frequency, 8_000.0);
+                    filter.process(&samples[..400]);
+                    filter.get_mag_sq()
+                })
+                .collect();
+            (0..energies.len())
+                .max_by(|&a, &b| energies[a].partial_cmp(&energies[b]).unwrap())
+                .unwrap()
+        };
+        assert_eq!(strongest(&ROW_FREQUENCIES), 2);
+        assert_eq!(strongest(&COLUMN_FREQUENCIES), 2);
+
+        assert_eq!(generator.generate("12x", &mut samples), Err('x'));
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut samples = vec![0; 1000];
+        DtmfGenerator::new(8_000.0, 100.0, 60.0)
+            .generate("555,12*#", &mut samples)
+            .unwrap();
+        // Too short to count as a digit
+        DtmfGenerator::new(8_000.0, 25.0, 50.0)
+            .generate("9", &mut samples)
+            .unwrap();
+        DtmfGenerator::new(8_000.0, 45.0, 50.0)
+            .generate("D", &mut samples)
+            .unwrap();
+
+        let detections = DtmfDetector::new(8_000.0, 40.0).detect(&samples);
+        let digits: String = detections.iter().map(|d| d.digit).collect();
+        assert_eq!(digits, "55512*#D");
+        assert!((detections[0].start - 0.125).abs() < 0.015);
+        assert!((detections[0].duration - 0.1).abs() < 0.015);
+        assert!((detections[3].start - 0.125 - 4.0 * 0.16).abs() < 0.015);
+    }
+
+    #[test]
+    fn test_rejects_twist_and_single_tones() {
+        let detector = DtmfDetector::new(8_000.0, 40.0);
+        let tone = |row: f64, column: f64, row_level: f64| -> Vec<i16> {
+            let mut low = Oscillator::new(row, 8_000.0);
+            let mut high = Oscillator::new(column, 8_000.0);
+            (0..800)
+                .map(|_| {
+                    let sample = row_level * low.next_sample() + 0.2 * high.next_sample();
+                    (AMPLITUDE * sample) as i16
+                })
+                .collect()
+        };
+        assert_eq!(detector.detect(&tone(770.0, 1336.0, 0.3)).len(), 1);
+        // Row 14 dB louder than column
+        assert!(detector.detect(&tone(770.0, 1336.0, 1.0)).is_empty());
+        assert!(detector.detect(&tone(770.0, 1336.0, 0.0)).is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::dtmf::{self, Detection};
+use std::fmt;
+use std::str::FromStr;
+
+/// Digits in nibble order for `--map`, so each pair of digits is one byte
+const DEFAULT_MAP: &str = "0123456789ABCD*#";
+
+/// Digits sent together, split from the next sequence by a pause
+#[derive(Debug, PartialEq)]
+pub struct Sequence {
+    pub digits: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Groups detections into sequences wherever the gap between digits exceeds `max_gap`
+/// seconds
+pub fn sequences(detections: &[Detection], max_gap: f64) -> Vec<Sequence> {
+    let mut sequences: Vec<Sequence> = Vec::new();
+    for detection in detections {
+        let end = detection.start + detection.duration;
+        match sequences.last_mut() {
+            Some(sequence) if detection.start - sequence.end <= max_gap => {
+                sequence.digits.push(detection.digit);
+                sequence.end = end;
+            }
+            _ => sequences.push(Sequence {
+                digits: detection.digit.to_string(),
+                start: detection.start,
+                end,
+            }),
+        }
+    }
+    sequences
+}
+
+/// How the digits of a sequence carry information
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Convention {
+    /// Automatic number identification, the ID a radio sends as PTT is pressed or released,
+    /// often wrapped in `*` or `#`
+    Ani,
+    /// A page to another station's code, `called*caller` or just the called code, optionally
+    /// ending in `#`
+    Page,
+    /// A selective call to a unit code, optionally followed by `#` and a status
+    Selcall,
+    /// Arbitrary bytes, two digits each
+    Data,
+}
+
+impl FromStr for Convention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ani" => Ok(Convention::Ani),
+            "page" => Ok(Convention::Page),
+            "selcall" => Ok(Convention::Selcall),
+            "data" => Ok(Convention::Data),
+            _ => Err(format!(
+                "unknown convention `{}`, expected ani, page, selcall or data",
+                s
+            )),
+        }
+    }
+}
+
+/// Which digit stands for each of the 16 nibble values
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigitMap {
+    digits: Vec<char>,
+}
+
+impl Default for DigitMap {
+    fn default() -> Self {
+        DEFAULT_MAP.parse().unwrap()
+    }
+}
+
+impl DigitMap {
+    fn nibble(&self, digit: char) -> u8 {
+        self.digits.iter().position(|&d| d == digit).unwrap() as u8
+    }
+
+    /// Encodes bytes as digit pairs, high nibble first
+    #[cfg(test)]
+    pub fn encode(&self, data: &[u8]) -> String {
+        data.iter()
+            .flat_map(|byte| vec![byte >> 4, byte & 15])
+            .map(|nibble| self.digits[usize::from(nibble)])
+            .collect()
+    }
+
+    /// Decodes digit pairs into bytes, dropping an unpaired final digit
+    pub fn decode(&self, digits: &str) -> Vec<u8> {
+        let digits: Vec<char> = digits.chars().collect();
+        digits
+            .chunks_exact(2)
+            .map(|pair| self.nibble(pair[0]) << 4 | self.nibble(pair[1]))
+            .collect()
+    }
+}
+
+impl FromStr for DigitMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits: Vec<char> = s.chars().map(|c| c.to_ascii_uppercase()).collect();
+        let valid = digits.len() == 16
+            && digits.iter().all(|&digit| dtmf::tones(digit).is_some())
+            && (1..16).all(|i| !digits[..i].contains(&digits[i]));
+        if valid {
+            Ok(Self { digits })
+        } else {
+            Err(format!(
+                "digit map `{}` must list all 16 DTMF digits once, e.g. {}",
+                s, DEFAULT_MAP
+            ))
+        }
+    }
+}
+
+/// What a sequence says under a convention
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    Ani(String),
+    Page {
+        called: String,
+        caller: Option<String>,
+    },
+    Selcall {
+        unit: String,
+        status: Option<String>,
+    },
+    Data(Vec<u8>),
+}
+
+impl Message {
+    pub fn new(digits: &str, convention: Convention, map: &DigitMap) -> Self {
+        match convention {
+            Convention::Ani => Message::Ani(digits.trim_matches(|c| c == '*' || c == '#').into()),
+            Convention::Page => {
+                // A leading `*` is often the radio's page prefix rather than a separator
+                let digits = digits.trim_start_matches('*').trim_end_matches('#');
+                let mut codes = digits.splitn(2, '*');
+                Message::Page {
+                    called: codes.next().unwrap_or_default().into(),
+                    caller: codes.next().map(Into::into),
+                }
+            }
+            Convention::Selcall => {
+                let mut parts = digits.splitn(2, '#');
+                Message::Selcall {
+                    unit: parts.next().unwrap_or_default().into(),
+                    status: parts.next().filter(|s| !s.is_empty()).map(Into::into),
+                }
+            }
+            Convention::Data => Message::Data(map.decode(digits)),
+        }
+    }
+}
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Message::Ani(id) => write!(f, "ANI {}", id),
+            Message::Page { called, caller } => {
+                write!(f, "Page to {}", called)?;
+                match caller {
+                    Some(caller) => write!(f, " from {}", caller),
+                    None => Ok(()),
+                }
+            }
+            Message::Selcall { unit, status } => {
+                write!(f, "Selcall {}", unit)?;
+                match status {
+                    Some(status) => write!(f, " status {}", status),
+                    None => Ok(()),
+                }
+            }
+            Message::Data(data) => {
+                write!(f, "Data {}", String::from_utf8_lossy(data).escape_debug())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detections(digits: &[(&str, f64)]) -> Vec<Detection> {
+        let mut detections = Vec::new();
+        for &(sequence, start) in digits {
+            for (i, digit) in sequence.chars().enumerate() {
+                detections.push(Detection {
+                    digit,
+                    start: start + 0.1 * i as f64,
+                    duration: 0.05,
+                });
+            }
+        }
+        detections
+    }
+
+    #[test]
+    fn test_sequences() {
+        let sequences = sequences(&detections(&[("*123#", 0.0), ("456", 2.0)]), 0.5);
+        let digits: Vec<&str> = sequences.iter().map(|s| s.digits.as_str()).collect();
+        assert_eq!(digits, vec!["*123#", "456"]);
+        assert_eq!(sequences[1].start, 2.0);
+        assert!((sequences[0].end - 0.45).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_conventions() {
+        let map = DigitMap::default();
+        let message = |digits, convention| Message::new(digits, convention, &map).to_string();
+        assert_eq!(message("*1234#", Convention::Ani), "ANI 1234");
+        assert_eq!(
+            message("123*456#", Convention::Page),
+            "Page to 123 from 456"
+        );
+        assert_eq!(message("*123", Convention::Page), "Page to 123");
+        assert_eq!(
+            message("12345#2", Convention::Selcall),
+            "Selcall 12345 status 2"
+        );
+        assert_eq!(message("54321", Convention::Selcall), "Selcall 54321");
+        assert_eq!(message("4869210", Convention::Data), "Data Hi!");
+
+        let digits = map.encode(b"\x00\xffOK");
+        assert_eq!(digits, "00##4#4B");
+        assert_eq!(map.decode(&digits), b"\x00\xffOK");
+        let custom: DigitMap = "123456789*0#abcd".parse().unwrap();
+        assert_eq!(custom.decode(&custom.encode(b"data")), b"data");
+        assert!("0123456789ABCD*".parse::<DigitMap>().is_err());
+        assert!("0123456789ABCD**".parse::<DigitMap>().is_err());
+    }
+}
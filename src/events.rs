@@ -0,0 +1,184 @@
+use crate::carrier::Detector;
+use crate::detect;
+use crate::standard::Channel;
+use std::io;
+
+/// Something worth telling a dashboard or logging stack about while decoding a stream
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<'a> {
+    /// Characters as they're decoded
+    Text(&'a str),
+    /// The carrier coming or going
+    Carrier(bool),
+    /// The channel's signal to noise ratio in dB over the last second
+    Snr(f64),
+    /// All the text a message carried, once it's complete
+    Message(&'a str),
+}
+
+/// Takes a decoded stream's events, such as the `--ws_listen` server
+pub trait Sink {
+    fn take(&mut self, event: &Event) -> io::Result<()>;
+}
+
+impl Event<'_> {
+    /// The event as a JSON object with its kind in `type`
+    pub fn to_json(&self) -> String {
+        match self {
+            Event::Text(text) => format!(r#"{{"type":"text","text":{}}}"#, json_string(text)),
+            Event::Carrier(present) => format!(r#"{{"type":"carrier","present":{}}}"#, present),
+            Event::Snr(db) if db.is_finite() => format!(r#"{{"type":"snr","db":{:.1}}}"#, db),
+            // Silence has no ratio, and JSON no infinity
+            Event::Snr(_) => r#"{"type":"snr","db":null}"#.to_string(),
+            Event::Message(text) => {
+                format!(r#"{{"type":"message","text":{}}}"#, json_string(text))
+            }
+        }
+    }
+}
+
+/// Quotes text as a JSON string
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c < ' ' || c == '\x7f' => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Gathers a stream's text into whole messages, each complete when its carrier goes
+#[derive(Default)]
+pub struct Messages {
+    text: String,
+}
+
+impl Messages {
+    /// Takes the next event, returning the message it completes, if it has any text
+    pub fn take(&mut self, event: &Event) -> Option<String> {
+        let message = match event {
+            Event::Text(text) => {
+                self.text.push_str(text);
+                return None;
+            }
+            Event::Carrier(false) => std::mem::take(&mut self.text),
+            Event::Message(text) => text.to_string(),
+            _ => return None,
+        };
+        Some(message).filter(|message| !message.trim().is_empty())
+    }
+}
+
+/// Follows a stream's signal, with an event when the carrier comes or goes and one for the SNR
+/// each second
+pub struct SignalWatch {
+    detector: Detector,
+    channel: Channel,
+    sampling_rate: f64,
+    second: Vec<i16>,
+    heard: bool,
+}
+
+impl SignalWatch {
+    pub fn new(channel: Channel, sampling_rate: f64) -> Self {
+        Self {
+            detector: Detector::new(channel, sampling_rate),
+            channel,
+            sampling_rate,
+            second: Vec::new(),
+            heard: false,
+        }
+    }
+
+    /// Takes the next block of audio, returning what's changed
+    pub fn push(&mut self, samples: &[i16]) -> Vec<Event<'static>> {
+        let mut events = Vec::new();
+        let heard = self.detector.push(samples);
+        if heard != self.heard {
+            self.heard = heard;
+            events.push(Event::Carrier(heard));
+        }
+        self.second.extend_from_slice(samples);
+        let second = self.sampling_rate.round() as usize;
+        while self.second.len() >= second {
+            let snr = detect::band_snr(&self.second[..second], self.channel, self.sampling_rate);
+            events.push(Event::Snr(snr));
+            self.second.drain(..second);
+        }
+        events
+    }
+
+    /// Ends the stream, with the carrier going if it was heard, so the message it carried is
+    /// complete
+    pub fn finish(&mut self) -> Vec<Event<'static>> {
+        if std::mem::take(&mut self.heard) {
+            vec![Event::Carrier(false)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+
+    #[test]
+    fn test_to_json() {
+        let text = Event::Text("say \"hi\"\r\n\x07").to_json();
+        assert_eq!(text, r#"{"type":"text","text":"say \"hi\"\r\n\u0007"}"#);
+        assert_eq!(
+            Event::Carrier(true).to_json(),
+            r#"{"type":"carrier","present":true}"#
+        );
+        assert_eq!(Event::Snr(12.34).to_json(), r#"{"type":"snr","db":12.3}"#);
+        assert_eq!(
+            Event::Message("ok").to_json(),
+            r#"{"type":"message","text":"ok"}"#
+        );
+        assert_eq!(
+            Event::Snr(f64::NEG_INFINITY).to_json(),
+            r#"{"type":"snr","db":null}"#
+        );
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut messages = Messages::default();
+        assert_eq!(messages.take(&Event::Text("o")), None);
+        assert_eq!(messages.take(&Event::Text("k")), None);
+        assert_eq!(
+            messages.take(&Event::Carrier(false)),
+            Some("ok".to_string())
+        );
+        assert_eq!(messages.take(&Event::Carrier(false)), None);
+        assert_eq!(messages.take(&Event::Message(" \n")), None);
+        assert_eq!(messages.take(&Event::Message("hi")), Some("hi".to_string()));
+    }
+
+    #[test]
+    fn test_signal_watch() {
+        let channel = Standard::Bell103.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        let mut carrier = Vec::new();
+        modulator.modulate(&[1; 300], &mut carrier);
+        let mut watch = SignalWatch::new(channel, 8_000.0);
+        let events = watch.push(&carrier);
+        assert_eq!(events[0], Event::Carrier(true));
+        assert!(matches!(events[1], Event::Snr(db) if db > 10.0));
+        assert_eq!(watch.push(&vec![0; 4_000]), [Event::Carrier(false)]);
+        assert_eq!(watch.finish(), []);
+        watch.push(&carrier);
+        assert_eq!(watch.finish(), [Event::Carrier(false)]);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::events::{Event, Messages, Sink};
+use crate::ptt::shell;
+use std::io::{self, Write};
+use std::process::Stdio;
+use std::thread::{self, JoinHandle};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Runs a command for each complete message, with `{}` in it replaced by the quoted text. The
+/// text is also on its stdin and in `BELL103_TEXT`, with `BELL103_SEQUENCE` counting messages
+/// from 1, `BELL103_TIME` the Unix time it completed and `BELL103_SNR` the last SNR heard in
+/// dB, when a stream has one. Commands run alongside decoding, so a slow one doesn't hold it
+/// up, and a failure is reported without stopping it.
+pub struct Exec {
+    command: String,
+    messages: Messages,
+    sequence: u64,
+    snr: Option<f64>,
+    running: Vec<JoinHandle<()>>,
+}
+
+impl Exec {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            messages: Messages::default(),
+            sequence: 0,
+            snr: None,
+            running: Vec::new(),
+        }
+    }
+
+    fn run(&mut self, message: String) -> io::Result<()> {
+        self.sequence += 1;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut command = shell(&self.command.replace("{}", &quote(&message)));
+        command
+            .env("BELL103_TEXT", &message)
+            .env("BELL103_SEQUENCE", self.sequence.to_string())
+            .env("BELL103_TIME", time.to_string())
+            .stdin(Stdio::piped());
+        match self.snr.filter(|snr| snr.is_finite()) {
+            Some(snr) => command.env("BELL103_SNR", format!("{:.1}", snr)),
+            None => command.env_remove("BELL103_SNR"),
+        };
+        let mut child = command.spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let shown = self.command.clone();
+        self.running.retain(|running| !running.is_finished());
+        self.running.push(thread::spawn(move || {
+            // A command needn't read its stdin
+            let _ = stdin.write_all(message.as_bytes());
+            drop(stdin);
+            match child.wait() {
+                Ok(status) if status.success() => {}
+                Ok(status) => eprintln!("`{}` failed with {}", shown, status),
+                Err(e) => eprintln!("Couldn't wait for `{}`: {}", shown, e),
+            }
+        }));
+        Ok(())
+    }
+}
+
+impl Sink for Exec {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        if let Event::Snr(db) = event {
+            self.snr = Some(*db);
+        }
+        if let Some(message) = self.messages.take(event) {
+            if let Err(e) = self.run(message) {
+                eprintln!("Couldn't run `{}`: {}", self.command, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Waits for the commands still running, e.g. when a recording has been decoded
+impl Drop for Exec {
+    fn drop(&mut self) {
+        for running in self.running.drain(..) {
+            let _ = running.join();
+        }
+    }
+}
+
+/// Quotes text as one word for the platform shell
+fn quote(text: &str) -> String {
+    if cfg!(windows) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        if cfg!(windows) {
+            assert_eq!(quote(r#"say "hi""#), r#""say ""hi""""#);
+        } else {
+            assert_eq!(quote("it's $HOME"), r"'it'\''s $HOME'");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exec() {
+        let path = std::env::temp_dir().join(format!("exec-{}.txt", std::process::id()));
+        let command = format!(
+            r#"printf '%s|%s|%s|%s|' {{}} "$BELL103_SEQUENCE" "$BELL103_SNR" "$BELL103_TEXT" >> {}; cat >> {}; test $BELL103_TIME -gt 0"#,
+            path.display(),
+            path.display()
+        );
+        let mut exec = Exec::new(&command);
+        exec.take(&Event::Text("it's $HOME")).unwrap();
+        exec.take(&Event::Snr(12.34)).unwrap();
+        exec.take(&Event::Carrier(false)).unwrap();
+        // Let the first finish, so they're written in order
+        exec.running.pop().unwrap().join().unwrap();
+        exec.take(&Event::Message("login:")).unwrap();
+        drop(exec);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            "it's $HOME|1|12.3|it's $HOME|it's $HOMElogin:|2|12.3|login:|login:"
+        );
+    }
+}
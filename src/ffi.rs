@@ -0,0 +1,237 @@
+//! A C interface for embedding the demodulator, declared in `include/bell103.h`. A decoder
+//! is created for a standard and channel, fed mono samples as they arrive and read for the
+//! bytes decoded from them. Built for WebAssembly the same functions are the module's
+//! exports, which `wasm/bell103.js` wraps for JavaScript.
+
+use crate::backend::Goertzel;
+use crate::framing::{self, Framing, StreamReceiver};
+use crate::standard::Standard;
+use std::collections::VecDeque;
+use std::slice;
+
+/// Standards to pass to `bell103_create`
+pub const BELL103_STANDARD_BELL103: u32 = 0;
+pub const BELL103_STANDARD_BELL202: u32 = 1;
+pub const BELL103_STANDARD_V21: u32 = 2;
+pub const BELL103_STANDARD_V23: u32 = 3;
+
+/// A streaming decoder, opaque to C
+pub struct Bell103Decoder {
+    receiver: StreamReceiver,
+    decoded: VecDeque<u8>,
+}
+
+/// Creates a decoder for samples at `sampling_rate`, on the originating channel of
+/// `standard` when `origin` is true and the answering one otherwise. Returns null for an
+/// unknown standard or a sampling rate outside 8 to 384 kHz.
+#[no_mangle]
+pub extern "C" fn bell103_create(
+    sampling_rate: f64,
+    standard: u32,
+    origin: bool,
+) -> *mut Bell103Decoder {
+    let standard = match standard {
+        BELL103_STANDARD_BELL103 => Standard::Bell103,
+        BELL103_STANDARD_BELL202 => Standard::Bell202,
+        BELL103_STANDARD_V21 => Standard::V21,
+        BELL103_STANDARD_V23 => Standard::V23,
+        _ => return std::ptr::null_mut(),
+    };
+    if !framing::SAMPLING_RATES.contains(&sampling_rate) {
+        return std::ptr::null_mut();
+    }
+    let channel = standard.channel(origin);
+    match StreamReceiver::with_backend(channel, sampling_rate, Framing::new(8), &Goertzel) {
+        Ok(receiver) => Box::into_raw(Box::new(Bell103Decoder {
+            receiver,
+            decoded: VecDeque::new(),
+        })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Demodulates the next `len` samples, returning how many decoded bytes are waiting to be
+/// read
+///
+/// # Safety
+///
+/// `decoder` must come from `bell103_create` and not have been destroyed, and `samples`
+/// must point to `len` samples, or be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn bell103_feed(
+    decoder: *mut Bell103Decoder,
+    samples: *const i16,
+    len: usize,
+) -> usize {
+    let Some(decoder) = decoder.as_mut() else {
+        return 0;
+    };
+    if !samples.is_null() && len > 0 {
+        decoder.feed(slice::from_raw_parts(samples, len));
+    }
+    decoder.decoded.len()
+}
+
+/// Demodulates the next `len` samples between -1.0 and 1.0, as WebAudio and many SDR
+/// libraries produce them, returning how many decoded bytes are waiting to be read
+///
+/// # Safety
+///
+/// `decoder` must come from `bell103_create` and not have been destroyed, and `samples`
+/// must point to `len` samples, or be null if `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn bell103_feed_f32(
+    decoder: *mut Bell103Decoder,
+    samples: *const f32,
+    len: usize,
+) -> usize {
+    let Some(decoder) = decoder.as_mut() else {
+        return 0;
+    };
+    if !samples.is_null() && len > 0 {
+        let samples: Vec<i16> = slice::from_raw_parts(samples, len)
+            .iter()
+            .map(|&sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)
+            .collect();
+        decoder.feed(&samples);
+    }
+    decoder.decoded.len()
+}
+
+impl Bell103Decoder {
+    fn feed(&mut self, samples: &[i16]) {
+        let received = self.receiver.push(samples);
+        self.decoded.extend(received.iter().map(|r| r.byte));
+    }
+}
+
+/// Moves up to `capacity` decoded bytes into `buffer`, returning how many it took
+///
+/// # Safety
+///
+/// `decoder` must come from `bell103_create` and not have been destroyed, and `buffer`
+/// must have room for `capacity` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bell103_read(
+    decoder: *mut Bell103Decoder,
+    buffer: *mut u8,
+    capacity: usize,
+) -> usize {
+    let Some(decoder) = decoder.as_mut() else {
+        return 0;
+    };
+    if buffer.is_null() {
+        return 0;
+    }
+    let count = capacity.min(decoder.decoded.len());
+    let buffer = slice::from_raw_parts_mut(buffer, count);
+    for (slot, byte) in buffer.iter_mut().zip(decoder.decoded.drain(..count)) {
+        *slot = byte;
+    }
+    count
+}
+
+/// Frees a decoder, doing nothing for null
+///
+/// # Safety
+///
+/// `decoder` must come from `bell103_create` and not have been destroyed already.
+#[no_mangle]
+pub unsafe extern "C" fn bell103_destroy(decoder: *mut Bell103Decoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
+
+/// Allocates `len` bytes of the module's memory, aligned for samples, for JavaScript to
+/// write samples to and read decoded bytes from. Returns null if there's no room.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub extern "C" fn bell103_alloc(len: usize) -> *mut u8 {
+    match std::alloc::Layout::from_size_align(len.max(1), 4) {
+        // Safe as the layout is never zero sized
+        Ok(layout) => unsafe { std::alloc::alloc(layout) },
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Frees memory from `bell103_alloc`
+///
+/// # Safety
+///
+/// `buffer` must come from `bell103_alloc(len)` and not have been freed already.
+#[cfg(target_arch = "wasm32")]
+#[no_mangle]
+pub unsafe extern "C" fn bell103_free(buffer: *mut u8, len: usize) {
+    if !buffer.is_null() {
+        let layout = std::alloc::Layout::from_size_align_unchecked(len.max(1), 4);
+        std::alloc::dealloc(buffer, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing;
+    use crate::modulator::FskModulator;
+
+    #[test]
+    fn test_decoder() {
+        assert!(bell103_create(48_000.0, 9, false).is_null());
+        assert!(bell103_create(0.0, BELL103_STANDARD_V21, false).is_null());
+        // Too few samples a second for a bit to have one of its own, or too many to make room for
+        assert!(bell103_create(100.0, BELL103_STANDARD_BELL103, false).is_null());
+        assert!(bell103_create(1e15, BELL103_STANDARD_BELL103, false).is_null());
+
+        let channel = Standard::Bell103.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 48_000.0);
+        let mut samples = Vec::new();
+        modulator.hold(1, 20.0, &mut samples);
+        modulator.modulate(&framing::frame(b"hello"), &mut samples);
+        modulator.hold(1, 20.0, &mut samples);
+
+        let decoder = bell103_create(48_000.0, BELL103_STANDARD_BELL103, false);
+        let mut decoded = Vec::new();
+        unsafe {
+            for chunk in samples.chunks(1000) {
+                if bell103_feed(decoder, chunk.as_ptr(), chunk.len()) > 0 {
+                    let mut buffer = [0; 2];
+                    let count = bell103_read(decoder, buffer.as_mut_ptr(), buffer.len());
+                    decoded.extend_from_slice(&buffer[..count]);
+                }
+            }
+            let mut buffer = [0; 16];
+            let count = bell103_read(decoder, buffer.as_mut_ptr(), buffer.len());
+            decoded.extend_from_slice(&buffer[..count]);
+            assert_eq!(bell103_feed(std::ptr::null_mut(), samples.as_ptr(), 1), 0);
+            bell103_destroy(decoder);
+        }
+        assert_eq!(decoded, b"hello");
+    }
+
+    #[test]
+    fn test_float_samples() {
+        let channel = Standard::V23.originate();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 44_100.0);
+        let mut samples = Vec::new();
+        modulator.hold(1, 20.0, &mut samples);
+        modulator.modulate(&framing::frame(b"ok"), &mut samples);
+        modulator.hold(1, 20.0, &mut samples);
+        let samples: Vec<f32> = samples.iter().map(|&s| f32::from(s) / 32768.0).collect();
+
+        let decoder = bell103_create(44_100.0, BELL103_STANDARD_V23, true);
+        let mut decoded = [0; 4];
+        unsafe {
+            assert_eq!(
+                bell103_feed_f32(decoder, samples.as_ptr(), samples.len()),
+                2
+            );
+            assert_eq!(
+                bell103_read(decoder, decoded.as_mut_ptr(), decoded.len()),
+                2
+            );
+            bell103_destroy(decoder);
+        }
+        assert_eq!(&decoded[..2], b"ok");
+    }
+}
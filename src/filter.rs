@@ -0,0 +1,163 @@
+use crate::standard::Channel;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+/// Second order IIR section using the RBJ audio EQ cookbook formulas
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    /// Band-pass with unity gain at `center`
+    pub fn band_pass(center: f64, q: f64, sampling_rate: f64) -> Self {
+        let omega = 2.0 * PI * center / sampling_rate;
+        let alpha = omega.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        Self {
+            b0: alpha / a0,
+            b1: 0.0,
+            b2: -alpha / a0,
+            a1: -2.0 * omega.cos() / a0,
+            a2: (1.0 - alpha) / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Windowed-sinc FIR low-pass, for band limiting before a sampling rate is lowered
+#[derive(Debug, Clone)]
+pub struct LowPass {
+    taps: Vec<f64>,
+    /// The most recent inputs, newest last, as long as `taps`
+    history: VecDeque<f64>,
+}
+
+impl LowPass {
+    /// Passes up to `cutoff` Hz with `length` taps, or everything if that's above Nyquist
+    pub fn new(cutoff: f64, length: usize, sampling_rate: f64) -> Self {
+        let fraction = cutoff / sampling_rate;
+        let taps = if fraction >= 0.5 {
+            vec![1.0]
+        } else {
+            let middle = (length - 1) as f64 / 2.0;
+            let taps: Vec<f64> = (0..length)
+                .map(|i| {
+                    let x = i as f64 - middle;
+                    let sinc = if x == 0.0 {
+                        2.0 * fraction
+                    } else {
+                        (2.0 * PI * fraction * x).sin() / (PI * x)
+                    };
+                    let blackman = 0.42 - 0.5 * (2.0 * PI * i as f64 / (length - 1) as f64).cos()
+                        + 0.08 * (4.0 * PI * i as f64 / (length - 1) as f64).cos();
+                    sinc * blackman
+                })
+                .collect();
+            // Unity gain at DC
+            let sum: f64 = taps.iter().sum();
+            taps.iter().map(|tap| tap / sum).collect()
+        };
+        Self {
+            history: vec![0.0; taps.len()].into(),
+            taps,
+        }
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        self.history.pop_front();
+        self.history.push_back(x);
+        self.history
+            .iter()
+            .rev()
+            .zip(&self.taps)
+            .map(|(x, tap)| x * tap)
+            .sum()
+    }
+}
+
+/// Band-passes `samples` around a channel's mark and space tones, attenuating the opposite
+/// direction of a duplex recording before it leaks into the short Goertzel blocks. The output
+/// is advanced by the filter's group delay so bits stay aligned with the input.
+pub fn isolate_channel(samples: &[i16], channel: Channel, sampling_rate: f64) -> Vec<i16> {
+    const STAGES: usize = 2;
+    let center = (channel.mark * channel.space).sqrt();
+    let q = center / (2.0 * (channel.mark - channel.space).abs());
+    // Two cascaded sections give steeper skirts than one
+    let mut stages = vec![Biquad::band_pass(center, q, sampling_rate); STAGES];
+    let delay = (STAGES as f64 * q / (PI * center) * sampling_rate).round() as usize;
+    samples
+        .iter()
+        .copied()
+        .chain(std::iter::repeat_n(0, delay))
+        .map(|sample| {
+            let y = stages
+                .iter_mut()
+                .fold(f64::from(sample), |x, stage| stage.process(x));
+            y.round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16
+        })
+        .skip(delay)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::Oscillator;
+
+    fn rms_after_filter(frequency: f64) -> f64 {
+        let mut oscillator = Oscillator::new(frequency, 48_000.0);
+        let mut filter = Biquad::band_pass(1_000.0, 2.0, 48_000.0);
+        let output: Vec<f64> = (0..48_000)
+            .map(|_| filter.process(oscillator.next_sample()))
+            .skip(4_800)
+            .collect();
+        (output.iter().map(|y| y * y).sum::<f64>() / output.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_band_pass() {
+        // A unit sine has an RMS of 1/sqrt(2)
+        assert!((rms_after_filter(1_000.0) - 0.5f64.sqrt()).abs() < 0.01);
+        assert!(rms_after_filter(250.0) < 0.1);
+        assert!(rms_after_filter(4_000.0) < 0.1);
+    }
+
+    #[test]
+    fn test_low_pass() {
+        let rms = |frequency: f64| {
+            let mut oscillator = Oscillator::new(frequency, 48_000.0);
+            let mut filter = LowPass::new(4_000.0, 63, 48_000.0);
+            let output: Vec<f64> = (0..4_800)
+                .map(|_| filter.process(oscillator.next_sample()))
+                .skip(63)
+                .collect();
+            (output.iter().map(|y| y * y).sum::<f64>() / output.len() as f64).sqrt()
+        };
+        assert!((rms(1_000.0) - 0.5f64.sqrt()).abs() < 0.01);
+        assert!(rms(8_000.0) < 0.01);
+        assert_eq!(LowPass::new(30_000.0, 63, 48_000.0).process(0.5), 0.5);
+    }
+}
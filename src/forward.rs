@@ -0,0 +1,124 @@
+use crate::events::{Event, Messages, Sink};
+use std::io;
+use std::net::UdpSocket;
+use std::str::FromStr;
+
+const SYSLOG_PORT: u16 = 514;
+/// The user facility (1) times eight plus informational severity (6)
+const SYSLOG_PRIORITY: u8 = 14;
+
+/// How decoded messages are sent to a collector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The bare text in a datagram
+    Udp,
+    /// An RFC 5424 syslog entry, leaving the timestamp and hostname for the collector to add
+    Syslog,
+}
+
+/// A collector to forward messages to, written `udp://host:port` or `syslog://host[:port]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forward {
+    pub format: Format,
+    pub address: String,
+}
+
+impl FromStr for Forward {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, host) = match s.split_once("://") {
+            Some(("udp", host)) => (Format::Udp, host),
+            Some(("syslog", host)) => (Format::Syslog, host),
+            _ => {
+                return Err(format!(
+                    "unknown collector `{}`, expected udp://host:port or syslog://host[:port]",
+                    s
+                ))
+            }
+        };
+        let address = match (format, host.contains(':')) {
+            (_, true) => host.to_string(),
+            (Format::Syslog, false) => format!("{}:{}", host, SYSLOG_PORT),
+            (Format::Udp, false) => return Err(format!("`{}` needs a port", s)),
+        };
+        Ok(Self { format, address })
+    }
+}
+
+/// Sends each complete message to a collector in its own datagram
+pub struct Forwarder {
+    socket: UdpSocket,
+    forward: Forward,
+    messages: Messages,
+}
+
+impl Forwarder {
+    pub fn open(forward: &Forward) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(&forward.address)?;
+        Ok(Self {
+            socket,
+            forward: forward.clone(),
+            messages: Messages::default(),
+        })
+    }
+}
+
+impl Sink for Forwarder {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        if let Some(message) = self.messages.take(event) {
+            let datagram = match self.forward.format {
+                Format::Udp => message,
+                Format::Syslog => syslog_entry(&message, std::process::id()),
+            };
+            self.socket.send(datagram.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn syslog_entry(message: &str, pid: u32) -> String {
+    format!(
+        "<{}>1 - - bell103_demodulator {} - - {}",
+        SYSLOG_PRIORITY, pid, message
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward() {
+        let forward: Forward = "syslog://logs.local".parse().unwrap();
+        assert_eq!(forward.format, Format::Syslog);
+        assert_eq!(forward.address, "logs.local:514");
+        let forward: Forward = "udp://10.0.0.2:9000".parse().unwrap();
+        assert_eq!(forward.format, Format::Udp);
+        assert_eq!(forward.address, "10.0.0.2:9000");
+        assert!("udp://10.0.0.2".parse::<Forward>().is_err());
+        assert!("tcp://10.0.0.2:9000".parse::<Forward>().is_err());
+        assert_eq!(
+            syslog_entry("ok", 42),
+            "<14>1 - - bell103_demodulator 42 - - ok"
+        );
+    }
+
+    #[test]
+    fn test_forwarder() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let forward = Forward {
+            format: Format::Udp,
+            address,
+        };
+        let mut forwarder = Forwarder::open(&forward).unwrap();
+        for event in [Event::Text("o"), Event::Text("k"), Event::Carrier(false)] {
+            forwarder.take(&event).unwrap();
+        }
+        let mut datagram = [0; 16];
+        let len = collector.recv(&mut datagram).unwrap();
+        assert_eq!(&datagram[..len], b"ok");
+    }
+}
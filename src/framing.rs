@@ -0,0 +1,675 @@
+use crate::assembly::MessageWriter;
+use crate::backend::{Backend, Goertzel};
+use crate::demodulator::{Demodulator, TICKS_PER_BIT};
+use crate::standard::Channel;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+/// Frames bytes as 8N1: a space start bit, eight data bits LSB first, then a mark stop bit
+pub fn frame(bytes: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(bytes.len() * 10);
+    for byte in bytes {
+        bits.push(0);
+        bits.extend((0..8).map(|i| (byte >> i) & 1));
+        bits.push(1);
+    }
+    bits
+}
+
+/// Decodes tightly packed 10 bit frames into bytes, skipping any frame without a valid start
+/// and stop bit
+pub fn deframe(bits: &[u8]) -> Vec<u8> {
+    let mut deframer = Deframer::default();
+    bits.iter().filter_map(|&bit| deframer.push(bit)).collect()
+}
+
+/// Decodes tightly packed 10 bit frames a bit at a time as they're decided, the way
+/// [`deframe`] does a whole message's
+#[derive(Debug, Default)]
+pub struct Deframer {
+    /// The bits of the frame so far, the first in the lowest bit
+    frame: u16,
+    bits: u32,
+}
+
+impl Deframer {
+    /// Takes the next bit, returning the byte of the frame it ends if the frame is valid
+    pub fn push(&mut self, bit: u8) -> Option<u8> {
+        self.frame |= u16::from(bit & 1) << self.bits;
+        self.bits += 1;
+        if self.bits < 10 {
+            return None;
+        }
+        let frame = std::mem::take(&mut self.frame);
+        self.bits = 0;
+        // A space start bit, then the data bits and a mark stop bit
+        (frame & 1 == 0 && frame >> 9 == 1).then_some((frame >> 1) as u8)
+    }
+}
+
+/// How the parity bit following the data bits is set, if there is one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+    Mark,
+    Space,
+}
+
+/// Character format of an asynchronous serial line, written like `8N1` or `7E2`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Framing {
+    pub data_bits: u32,
+    pub parity: Parity,
+    pub stop_bits: u32,
+}
+
+impl Framing {
+    /// Data bits with no parity and one stop bit
+    pub fn new(data_bits: u32) -> Self {
+        Self {
+            data_bits,
+            parity: Parity::None,
+            stop_bits: 1,
+        }
+    }
+
+    fn parity_bits(self) -> u32 {
+        if self.parity == Parity::None {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Whether the parity bit received after `data` is the one that should have been sent
+    fn parity_ok(self, data: u16, bit: bool) -> bool {
+        let odd = data.count_ones() % 2 == 1;
+        match self.parity {
+            Parity::None => true,
+            Parity::Even => bit == odd,
+            Parity::Odd => bit != odd,
+            Parity::Mark => bit,
+            Parity::Space => !bit,
+        }
+    }
+}
+
+impl FromStr for Framing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid framing `{}`, expected data bits 5 to 8, parity N, E, O, M or S and \
+                 1 or 2 stop bits, e.g. 8N1",
+                s
+            )
+        };
+        let chars: Vec<char> = s.chars().collect();
+        if chars.len() != 3 {
+            return Err(invalid());
+        }
+        let data_bits = chars[0].to_digit(10).filter(|bits| (5..=8).contains(bits));
+        let parity = match chars[1].to_ascii_uppercase() {
+            'N' => Some(Parity::None),
+            'E' => Some(Parity::Even),
+            'O' => Some(Parity::Odd),
+            'M' => Some(Parity::Mark),
+            'S' => Some(Parity::Space),
+            _ => None,
+        };
+        let stop_bits = chars[2].to_digit(10).filter(|bits| (1..=2).contains(bits));
+        match (data_bits, parity, stop_bits) {
+            (Some(data_bits), Some(parity), Some(stop_bits)) => Ok(Self {
+                data_bits,
+                parity,
+                stop_bits,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A character recovered by a [`UartReceiver`], with the decision tick its start bit began on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Received {
+    pub byte: u8,
+    pub tick: usize,
+}
+
+#[derive(Debug)]
+enum UartState {
+    Idle { mark_seen: bool },
+    Receiving { edge: usize, bit: u32, value: u16 },
+}
+
+/// Asynchronous serial receiver working from mark/space decisions taken several times per
+/// bit. It waits for the mark to space edge of a start bit, then samples the middle of each
+/// following bit, so characters are found wherever they start.
+#[derive(Debug)]
+pub struct UartReceiver {
+    ticks_per_bit: f64,
+    framing: Framing,
+    tick: usize,
+    state: UartState,
+    errors: u64,
+}
+
+impl UartReceiver {
+    pub fn new(ticks_per_bit: f64, data_bits: u32) -> Self {
+        Self::with_framing(ticks_per_bit, Framing::new(data_bits))
+    }
+
+    /// A receiver that also checks parity and every stop bit
+    pub fn with_framing(ticks_per_bit: f64, framing: Framing) -> Self {
+        Self {
+            ticks_per_bit,
+            framing,
+            tick: 0,
+            state: UartState::Idle { mark_seen: false },
+            errors: 0,
+        }
+    }
+
+    pub fn ticks_per_bit(&self) -> f64 {
+        self.ticks_per_bit
+    }
+
+    /// How many characters have been dropped for a bad parity or stop bit
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    /// Feeds the next soft decision, positive for mark, returning a character once its
+    /// parity and stop bits have been checked
+    pub fn push(&mut self, soft: f64) -> Option<Received> {
+        let mark = soft >= 0.0;
+        let tick = self.tick;
+        self.tick += 1;
+        match self.state {
+            UartState::Idle { mark_seen } => {
+                self.state = if !mark && mark_seen {
+                    UartState::Receiving {
+                        edge: tick,
+                        bit: 0,
+                        value: 0,
+                    }
+                } else {
+                    UartState::Idle {
+                        mark_seen: mark_seen || mark,
+                    }
+                };
+                None
+            }
+            UartState::Receiving { edge, bit, value } => {
+                let center = edge as f64 + self.ticks_per_bit * (f64::from(bit) + 0.5);
+                if (tick as f64) < center {
+                    return None;
+                }
+                if bit == 0 && mark {
+                    // Too short to be a start bit
+                    self.state = UartState::Idle { mark_seen: true };
+                    return None;
+                }
+                let framing = self.framing;
+                let character_bits = framing.data_bits + framing.parity_bits();
+                let last = character_bits + framing.stop_bits;
+                if bit <= character_bits {
+                    let value = if bit > 0 && mark {
+                        value | (1 << (bit - 1))
+                    } else {
+                        value
+                    };
+                    self.state = UartState::Receiving {
+                        edge,
+                        bit: bit + 1,
+                        value,
+                    };
+                    None
+                } else if mark && bit < last {
+                    self.state = UartState::Receiving {
+                        edge,
+                        bit: bit + 1,
+                        value,
+                    };
+                    None
+                } else {
+                    self.state = UartState::Idle { mark_seen: mark };
+                    let data = value & ((1 << framing.data_bits) - 1);
+                    let parity = value >> framing.data_bits & 1 == 1;
+                    if mark && framing.parity_ok(data, parity) {
+                        Some(Received {
+                            byte: data as u8,
+                            tick: edge,
+                        })
+                    } else {
+                        self.errors += 1;
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs a sliding demodulator and UART receiver over a recording, returning the characters
+/// found and the number of decision ticks per bit
+pub fn receive(
+    samples: &[i16],
+    channel: Channel,
+    sampling_rate: f64,
+    data_bits: u32,
+) -> (Vec<Received>, f64) {
+    receive_framed(samples, channel, sampling_rate, Framing::new(data_bits))
+}
+
+/// Like [`receive`] for any character format, dropping characters with bad parity
+pub fn receive_framed(
+    samples: &[i16],
+    channel: Channel,
+    sampling_rate: f64,
+    framing: Framing,
+) -> (Vec<Received>, f64) {
+    let mut stream = StreamReceiver::new(channel, sampling_rate, framing);
+    let received = stream.push(samples);
+    (received, stream.ticks_per_bit())
+}
+
+/// Checks that `sampling_rate` gives each bit of `channel` a sample of its own, as a
+/// [`StreamReceiver`] needs to be made
+pub fn check_sampling_rate(channel: Channel, sampling_rate: f64) -> Result<(), String> {
+    let samples_per_bit = sampling_rate / channel.baud;
+    if samples_per_bit.round() as usize == 0 || !samples_per_bit.is_finite() {
+        return Err(format!(
+            "A {} Hz sampling rate doesn't give a {} baud bit a sample of its own",
+            sampling_rate, channel.baud
+        ));
+    }
+    Ok(())
+}
+
+/// Sampling rates the decoders other programs make accept, from telephone audio to the
+/// fastest sound cards, so a caller can't have one's buffers sized by an absurd rate
+pub const SAMPLING_RATES: RangeInclusive<f64> = 8_000.0..=384_000.0;
+
+/// Samples a [`StreamReceiver`] takes in at a time, unless made with another capacity
+pub const STREAM_CAPACITY: usize = 4096;
+
+/// The sliding demodulator and UART receiver of [`receive`] fed audio as it arrives, e.g.
+/// from a sound card, keeping back the samples the next window still needs
+#[derive(Debug)]
+pub struct StreamReceiver {
+    demodulator: Demodulator,
+    receiver: UartReceiver,
+    step: usize,
+    /// The window's samples, allocated once and never grown: chunks longer than it has room
+    /// for are taken in a piece at a time
+    pending: Vec<i16>,
+    capacity: usize,
+}
+
+impl StreamReceiver {
+    /// Panics unless [`check_sampling_rate`] passes the rate, which
+    /// [`StreamReceiver::with_backend`] returns as an error instead
+    pub fn new(channel: Channel, sampling_rate: f64, framing: Framing) -> Self {
+        Self::with_backend(channel, sampling_rate, framing, &Goertzel).unwrap()
+    }
+
+    /// Like [`StreamReceiver::new`], demodulating with another backend's detector
+    pub fn with_backend(
+        channel: Channel,
+        sampling_rate: f64,
+        framing: Framing,
+        backend: &dyn Backend,
+    ) -> Result<Self, String> {
+        Self::with_capacity(channel, sampling_rate, framing, backend, STREAM_CAPACITY)
+    }
+
+    /// Like [`StreamReceiver::with_backend`], taking in up to `capacity` samples at a time.
+    /// Everything the receiver needs is allocated here, so [`StreamReceiver::push_to`] never
+    /// allocates, and a sound card's callback can feed it without waiting on the heap; a
+    /// capacity of its period takes each period in one go.
+    pub fn with_capacity(
+        channel: Channel,
+        sampling_rate: f64,
+        framing: Framing,
+        backend: &dyn Backend,
+        capacity: usize,
+    ) -> Result<Self, String> {
+        check_sampling_rate(channel, sampling_rate)?;
+        let samples_per_bit = sampling_rate / channel.baud;
+        let block_size = samples_per_bit.round() as usize;
+        let step = (block_size / TICKS_PER_BIT).max(1);
+        let detector = backend.detector(channel, block_size, sampling_rate)?;
+        // Less than a block is left over from each piece, so there's always room for more
+        let capacity = block_size + capacity.max(1);
+        Ok(Self {
+            demodulator: Demodulator::with_detector(block_size, detector),
+            receiver: UartReceiver::with_framing(samples_per_bit / step as f64, framing),
+            step,
+            pending: Vec::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    /// The receiver's decision ticks per bit
+    pub fn ticks_per_bit(&self) -> f64 {
+        self.receiver.ticks_per_bit()
+    }
+
+    /// How many characters have been dropped for a bad parity or stop bit
+    pub fn errors(&self) -> u64 {
+        self.receiver.errors()
+    }
+
+    /// Feeds the next samples, returning the characters completed by them
+    pub fn push(&mut self, samples: &[i16]) -> Vec<Received> {
+        let mut received = Vec::new();
+        self.each(samples, |character| received.push(character));
+        received
+    }
+
+    /// Like [`StreamReceiver::push`], writing the characters rather than collecting them, so
+    /// nothing is allocated unless the writer does
+    pub fn push_to<W: MessageWriter>(&mut self, samples: &[i16], writer: &mut W) -> usize {
+        let mut written = 0;
+        self.each(samples, |character| {
+            writer.write_byte(character.byte);
+            written += 1;
+        });
+        written
+    }
+
+    fn each<F: FnMut(Received)>(&mut self, mut samples: &[i16], mut take: F) {
+        let block_size = self.demodulator.block_size();
+        while !samples.is_empty() {
+            let room = self.capacity - self.pending.len();
+            let (piece, rest) = samples.split_at(room.min(samples.len()));
+            self.pending.extend_from_slice(piece);
+            samples = rest;
+            let mut start = 0;
+            while start + block_size <= self.pending.len() {
+                let soft = self
+                    .demodulator
+                    .soft_bit(&self.pending[start..start + block_size]);
+                if let Some(character) = self.receiver.push(soft) {
+                    take(character);
+                }
+                start += self.step;
+            }
+            self.pending.drain(..start);
+        }
+    }
+}
+
+/// One direction of a duplex call for [`interleave`]: its label, the characters received
+/// and how many seconds each of their ticks lasts
+pub struct Side<'a> {
+    pub label: &'a str,
+    pub received: &'a [Received],
+    pub seconds_per_tick: f64,
+}
+
+/// Merges what each side of a call sent into one transcript in the order it was sent, as
+/// 7-bit ASCII with each turn headed by the side and when it began
+pub fn interleave(sides: &[Side]) -> String {
+    let mut characters: Vec<(f64, usize, u8)> = sides
+        .iter()
+        .enumerate()
+        .flat_map(|(i, side)| {
+            side.received.iter().map(move |received| {
+                let time = received.tick as f64 * side.seconds_per_tick;
+                (time, i, received.byte & 0x7f)
+            })
+        })
+        .collect();
+    characters.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut transcript = String::new();
+    let mut turn = None;
+    for (time, side, byte) in characters {
+        if turn != Some(side) {
+            if turn.is_some() {
+                transcript.push('\n');
+            }
+            transcript.push_str(&format!("[{} {:.2} s]\n", sides[side].label, time));
+            turn = Some(side);
+        }
+        transcript.push(char::from(byte));
+    }
+    transcript
+}
+
+/// A byte pattern sent ahead of a message so the receiver can tell where the data starts,
+/// written as hex with an optional repeat count, e.g. `55*16` or `2dd4`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preamble {
+    unit: Vec<u8>,
+    count: usize,
+}
+
+impl Preamble {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.unit.repeat(self.count)
+    }
+
+    /// Returns the data following the first occurrence of the preamble. Any further
+    /// repetitions of the pattern are skipped too, so a receiver can require fewer
+    /// repetitions than were sent and tolerate corruption at the start of a long run.
+    pub fn strip<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        let pattern = self.bytes();
+        let start = data
+            .windows(pattern.len())
+            .position(|window| window == &pattern[..])?;
+        let mut rest = &data[start + pattern.len()..];
+        while rest.starts_with(&self.unit) {
+            rest = &rest[self.unit.len()..];
+        }
+        Some(rest)
+    }
+}
+
+impl FromStr for Preamble {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hex, count) = match s.find('*') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, "1"),
+        };
+        let hex = hex.trim_start_matches("0x");
+        if hex.is_empty() || hex.len() % 2 != 0 {
+            return Err(format!("preamble `{}` must be whole hex bytes", s));
+        }
+        let unit = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("preamble `{}` is not valid hex", s))?;
+        let count = match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(format!("preamble repeat count `{}` is invalid", count)),
+        };
+        Ok(Self { unit, count })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_deframe() {
+        let bits = frame(b"Hi");
+        assert_eq!(bits.len(), 20);
+        assert_eq!(&bits[..10], &[0, 0, 0, 0, 1, 0, 0, 1, 0, 1]);
+        assert_eq!(deframe(&bits), b"Hi");
+    }
+
+    #[test]
+    fn test_interleave() {
+        let received = |tick, byte| Received { byte, tick };
+        let originate = [received(10, b'h'), received(20, b'i'), received(90, b'?')];
+        let answer = [received(40, b'o' | 0x80), received(50, b'k')];
+        let transcript = interleave(&[
+            Side {
+                label: "originate",
+                received: &originate,
+                seconds_per_tick: 0.01,
+            },
+            Side {
+                label: "answer",
+                received: &answer,
+                seconds_per_tick: 0.01,
+            },
+        ]);
+        assert_eq!(
+            transcript,
+            "[originate 0.10 s]\nhi\n[answer 0.40 s]\nok\n[originate 0.90 s]\n?"
+        );
+    }
+
+    #[test]
+    fn test_deframe_skips_invalid_frames() {
+        let mut bits = frame(b"a");
+        bits.extend(&[1; 10]);
+        bits.extend(frame(&[0xd4]));
+        bits.extend(&[0; 4]);
+        assert_eq!(deframe(&bits), [b'a', 0xd4]);
+    }
+
+    #[test]
+    fn test_deframer_carries_frames_between_pushes() {
+        let bits = frame(b"Welcome");
+        let mut deframer = Deframer::default();
+        let mut bytes = Vec::new();
+        // Split unevenly, as bits are decided across chunks of a recording
+        for chunk in bits.chunks(7) {
+            bytes.extend(chunk.iter().filter_map(|&bit| deframer.push(bit)));
+        }
+        assert_eq!(bytes, b"Welcome");
+        assert_eq!(deframer.push(0), None);
+    }
+
+    #[test]
+    fn test_uart_receiver_finds_unaligned_characters() {
+        use crate::modulator::FskModulator;
+
+        let mut modulator = FskModulator::new(1200.0, 2200.0, 1200.0, 48_000.0);
+        let mut samples = vec![0; 17];
+        modulator.modulate(&[1; 13], &mut samples);
+        modulator.modulate(&frame(b"ok"), &mut samples);
+        modulator.modulate(&[1; 3], &mut samples);
+        modulator.modulate(&frame(b"!"), &mut samples);
+        modulator.modulate(&[1; 2], &mut samples);
+
+        let step = 40 / TICKS_PER_BIT;
+        let mut demodulator = Demodulator::new(40, 1200.0, 2200.0, 48_000.0);
+        let mut receiver = UartReceiver::new(40.0 / step as f64, 8);
+        let received: Vec<Received> = demodulator
+            .sliding_soft_bits(&samples, step)
+            .into_iter()
+            .filter_map(|soft| receiver.push(soft))
+            .collect();
+        let bytes: Vec<u8> = received.iter().map(|r| r.byte).collect();
+        assert_eq!(bytes, b"ok!");
+        // The third character starts three idle bits after the second ends
+        let gap = (received[2].tick - received[1].tick) as f64 / receiver.ticks_per_bit();
+        assert!((gap - 13.0).abs() < 0.5, "{}", gap);
+    }
+
+    #[test]
+    fn test_uart_receiver_checks_parity_and_stop_bits() {
+        use crate::modulator::FskModulator;
+
+        // 7E2 characters, the second with its parity bit flipped and the third missing its
+        // second stop bit
+        let mut bits = vec![1; 4];
+        for (i, &c) in b"abc".iter().enumerate() {
+            bits.push(0);
+            bits.extend((0..7).map(|bit| (c >> bit) & 1));
+            let parity = (c.count_ones() % 2) as u8;
+            bits.push(if i == 1 { parity ^ 1 } else { parity });
+            bits.extend(if i == 2 { [1, 0] } else { [1, 1] });
+            bits.extend(&[1; 3]);
+        }
+        bits.extend(&[0, 0, 0, 1, 1, 0, 1, 1, 0, 1, 1, 1, 1, 1]);
+        let mut modulator = FskModulator::new(1200.0, 2200.0, 1200.0, 48_000.0);
+        let mut samples = Vec::new();
+        modulator.modulate(&bits, &mut samples);
+
+        let framing: Framing = "7e2".parse().unwrap();
+        assert_eq!(framing.parity, Parity::Even);
+        let channel = Channel {
+            mark: 1200.0,
+            space: 2200.0,
+            baud: 1200.0,
+        };
+        let (received, _) = receive_framed(&samples, channel, 48_000.0, framing);
+        let bytes: Vec<u8> = received.iter().map(|r| r.byte).collect();
+        assert_eq!(bytes, b"al");
+        // The two bad characters are counted
+        let mut stream = StreamReceiver::new(channel, 48_000.0, framing);
+        stream.push(&samples);
+        assert_eq!(stream.errors(), 2);
+
+        assert_eq!("8N1".parse(), Ok(Framing::new(8)));
+        assert!("9N1".parse::<Framing>().is_err());
+        assert!("8X1".parse::<Framing>().is_err());
+        assert!("8N3".parse::<Framing>().is_err());
+    }
+
+    #[test]
+    fn test_stream_receiver_matches_receive() {
+        use crate::modulator::FskModulator;
+        use crate::standard::Standard;
+
+        let channel = Standard::Bell103.answer();
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 48_000.0);
+        let mut samples = Vec::new();
+        modulator.modulate(&[1; 20], &mut samples);
+        modulator.modulate(&frame(b"streamed in pieces"), &mut samples);
+        modulator.modulate(&[1; 10], &mut samples);
+
+        let (expected, _) = receive(&samples, channel, 48_000.0, 8);
+        let mut stream = StreamReceiver::new(channel, 48_000.0, Framing::new(8));
+        let mut received = Vec::new();
+        for chunk in samples.chunks(1234) {
+            received.extend(stream.push(chunk));
+        }
+        assert_eq!(received, expected);
+        let bytes: Vec<u8> = received.iter().map(|r| r.byte).collect();
+        assert_eq!(bytes, b"streamed in pieces");
+
+        // However little room the window has, longer chunks are taken in pieces just the same
+        for capacity in [1, 7, 160, 100_000] {
+            let framing = Framing::new(8);
+            let mut stream =
+                StreamReceiver::with_capacity(channel, 48_000.0, framing, &Goertzel, capacity)
+                    .unwrap();
+            assert_eq!(stream.push(&samples), expected);
+            assert_eq!(stream.pending.capacity(), 160 + capacity);
+        }
+        // A rate too low for a bit to have a sample of its own is refused
+        let low = StreamReceiver::with_backend(channel, 100.0, Framing::new(8), &Goertzel);
+        assert!(low.is_err());
+    }
+
+    #[test]
+    fn test_preamble() {
+        let preamble: Preamble = "0x55*4".parse().unwrap();
+        assert_eq!(preamble.bytes(), [0x55; 4]);
+        let mut data = vec![0x12, 0x55, 0x17];
+        data.extend(&[0x55; 6]);
+        data.extend(b"data");
+        assert_eq!(preamble.strip(&data), Some(&b"data"[..]));
+        assert_eq!(preamble.strip(b"no sync"), None);
+
+        let sync: Preamble = "2dd4".parse().unwrap();
+        assert_eq!(sync.strip(&[0x00, 0x2d, 0xd4, 0x41]), Some(&[0x41][..]));
+        assert!("2d4".parse::<Preamble>().is_err());
+        assert!("zz".parse::<Preamble>().is_err());
+        assert!("55*0".parse::<Preamble>().is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use std::f64::consts::PI;
+use std::thread;
+
+/// Filters a [`GoertzelBank`] updates together with each sample read
+const LANES: usize = 4;
+/// Sample updates, filters times samples, worth sharing a [`GoertzelBank`]'s filters between
+/// threads for
+const PARALLEL_WORK: usize = 1 << 22;
+/// Blocks quieter than this mean square level are silence
+const MIN_LEVEL: f64 = 1e4;
+
+/// Whether a block's too quiet for the tone detectors to measure, as an empty one is
+pub fn is_silent(block: &[i16]) -> bool {
+    let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+    block.is_empty() || energy / (block.len() as f64) < MIN_LEVEL
+}
+
+#[derive(Debug)]
+pub struct GoertzelFilter {
+    #[allow(unused)]
+    k: u32,
+    #[allow(unused)]
+    n: usize,
+    coeff: f64,
+    q1: f64,
+    q2: f64,
+    sin: f64,
+    cos: f64,
+}
+
+impl GoertzelFilter {
+    pub fn new(block_size: usize, target_freq: f64, sampling_rate: f64) -> Self {
+        let k = (block_size as f64 * target_freq) / sampling_rate;
+        let omega = (2.0 * PI * k) / block_size as f64;
+        let cos = omega.cos();
+        Self {
+            k: k as u32,
+            n: block_size,
+            coeff: 2.0 * cos,
+            q1: 0.0,
+            q2: 0.0,
+            sin: omega.sin(),
+            cos,
+        }
+    }
+
+    pub fn process(&mut self, samples: &[i16]) {
+        for v in samples {
+            let q0 = self.coeff * self.q1 - self.q2 + f64::from(*v);
+            self.q2 = self.q1;
+            self.q1 = q0;
+        }
+    }
+
+    #[allow(unused)]
+    pub fn get_real_imag(&self) -> (f64, f64) {
+        let real = self.q1 - self.q2 * self.cos;
+        let imag = self.q2 * self.sin;
+        (real, imag)
+    }
+
+    pub fn get_mag_sq(&self) -> f64 {
+        self.q1 * self.q1 + self.q2 * self.q2 - self.q1 * self.q2 * self.coeff
+    }
+
+    pub fn reset(&mut self) {
+        self.q2 = 0.0;
+        self.q1 = 0.0;
+    }
+}
+
+/// Two Goertzel filters run together, such as a channel's mark and space tones, updating both
+/// with each sample so a block is read once rather than once for each of them
+#[derive(Debug, Clone, Copy)]
+pub struct GoertzelPair {
+    coeffs: [f64; 2],
+}
+
+impl GoertzelPair {
+    pub fn new(block_size: usize, frequencies: [f64; 2], sampling_rate: f64) -> Self {
+        let coeff = |frequency| GoertzelFilter::new(block_size, frequency, sampling_rate).coeff;
+        Self {
+            coeffs: [coeff(frequencies[0]), coeff(frequencies[1])],
+        }
+    }
+
+    /// Returns the magnitude squared at each tone over a single block, as two
+    /// [`GoertzelFilter`]s would
+    pub fn energies(&self, block: &[i16]) -> [f64; 2] {
+        let [a, b] = self.coeffs;
+        let (mut a1, mut a2, mut b1, mut b2) = (0.0, 0.0, 0.0, 0.0);
+        for &sample in block {
+            let sample = f64::from(sample);
+            let a0 = a * a1 - a2 + sample;
+            let b0 = b * b1 - b2 + sample;
+            a2 = a1;
+            a1 = a0;
+            b2 = b1;
+            b1 = b0;
+        }
+        [
+            a1 * a1 + a2 * a2 - a1 * a2 * a,
+            b1 * b1 + b2 * b2 - b1 * b2 * b,
+        ]
+    }
+}
+
+/// [`GoertzelPair`] in single precision, for processors that run `f32` arithmetic faster than
+/// `f64`. The coefficients are worked out in double precision and rounded, and the filters'
+/// state kept in single, which a block of a bit's samples is short enough not to suffer from.
+#[derive(Debug, Clone, Copy)]
+pub struct GoertzelPair32 {
+    coeffs: [f32; 2],
+}
+
+impl GoertzelPair32 {
+    pub fn new(block_size: usize, frequencies: [f64; 2], sampling_rate: f64) -> Self {
+        let [a, b] = GoertzelPair::new(block_size, frequencies, sampling_rate).coeffs;
+        Self {
+            coeffs: [a as f32, b as f32],
+        }
+    }
+
+    /// Returns the magnitude squared at each tone over a single block, as
+    /// [`GoertzelPair::energies`] does
+    pub fn energies(&self, block: &[i16]) -> [f32; 2] {
+        let [a, b] = self.coeffs;
+        let (mut a1, mut a2, mut b1, mut b2) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+        for &sample in block {
+            let sample = f32::from(sample);
+            let a0 = a * a1 - a2 + sample;
+            let b0 = b * b1 - b2 + sample;
+            a2 = a1;
+            a1 = a0;
+            b2 = b1;
+            b1 = b0;
+        }
+        [
+            a1 * a1 + a2 * a2 - a1 * a2 * a,
+            b1 * b1 + b2 * b2 - b1 * b2 * b,
+        ]
+    }
+}
+
+/// Goertzel filters at many frequencies over the same blocks of a recording, for searching a
+/// band for its tones. Each block's read once for every few filters rather than once for each,
+/// and when there's enough work the filters are shared out between threads, so thousands of
+/// frequencies over seconds of audio take a fraction of the time one filter at a time would.
+#[derive(Debug, Clone)]
+pub struct GoertzelBank {
+    frequencies: Vec<f64>,
+    sampling_rate: f64,
+}
+
+impl GoertzelBank {
+    pub fn new(frequencies: Vec<f64>, sampling_rate: f64) -> Self {
+        Self {
+            frequencies,
+            sampling_rate,
+        }
+    }
+
+    pub fn frequencies(&self) -> &[f64] {
+        &self.frequencies
+    }
+
+    /// Returns the magnitude squared at each frequency summed over the `(start, end)` blocks of
+    /// `samples`, exactly as a [`GoertzelFilter`] for each frequency and block would
+    pub fn energies(&self, samples: &[i16], blocks: &[(usize, usize)]) -> Vec<f64> {
+        let mut energies = vec![0.0; self.frequencies.len()];
+        let work =
+            self.frequencies.len() * blocks.iter().map(|(start, end)| end - start).sum::<usize>();
+        let threads = thread::available_parallelism().map_or(1, usize::from);
+        let threads = threads
+            .min(self.frequencies.len().div_ceil(LANES))
+            .min(work / PARALLEL_WORK + 1);
+        if threads <= 1 {
+            self.fill(samples, blocks, &self.frequencies, &mut energies);
+            return energies;
+        }
+        let share = self
+            .frequencies
+            .len()
+            .div_ceil(threads)
+            .next_multiple_of(LANES);
+        thread::scope(|scope| {
+            for (frequencies, energies) in self
+                .frequencies
+                .chunks(share)
+                .zip(energies.chunks_mut(share))
+            {
+                scope.spawn(move || self.fill(samples, blocks, frequencies, energies));
+            }
+        });
+        energies
+    }
+
+    /// Writes the magnitude squared at each frequency over a single block into `energies`, as
+    /// [`GoertzelBank::energies`] would for that block alone, without allocating, for decoders
+    /// that read tones block by block
+    pub fn energies_into(&self, block: &[i16], energies: &mut [f64]) {
+        energies.iter_mut().for_each(|energy| *energy = 0.0);
+        self.add(block, &self.frequencies, energies);
+    }
+
+    /// Sums the energies at `frequencies` over each of `blocks` into `energies`
+    fn fill(
+        &self,
+        samples: &[i16],
+        blocks: &[(usize, usize)],
+        frequencies: &[f64],
+        energies: &mut [f64],
+    ) {
+        for &(start, end) in blocks {
+            self.add(&samples[start..end], frequencies, energies);
+        }
+    }
+
+    /// Adds the energies at `frequencies` over one block to `energies`, [`LANES`] filters at a
+    /// time
+    fn add(&self, block: &[i16], frequencies: &[f64], energies: &mut [f64]) {
+        for (frequencies, energies) in frequencies.chunks(LANES).zip(energies.chunks_mut(LANES)) {
+            let mut coeffs = [0.0; LANES];
+            for (coeff, &frequency) in coeffs.iter_mut().zip(frequencies) {
+                *coeff = GoertzelFilter::new(block.len(), frequency, self.sampling_rate).coeff;
+            }
+            let (mut q1, mut q2) = ([0.0; LANES], [0.0; LANES]);
+            for &sample in block {
+                let sample = f64::from(sample);
+                for lane in 0..LANES {
+                    let q0 = coeffs[lane] * q1[lane] - q2[lane] + sample;
+                    q2[lane] = q1[lane];
+                    q1[lane] = q0;
+                }
+            }
+            for (lane, energy) in energies.iter_mut().enumerate() {
+                *energy +=
+                    q1[lane] * q1[lane] + q2[lane] * q2[lane] - q1[lane] * q2[lane] * coeffs[lane];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLING_RATE: f64 = 8_000.0; // 8 kHz
+    const BLOCK_SIZE: usize = 205;
+    const TARGET_FREQUENCY: f64 = 941.0; // 941 Hz
+
+    fn generate_test_samples(frequency: f64) -> Vec<u8> {
+        let step = frequency * 2.0 * PI / SAMPLING_RATE;
+        let mut samples = vec![0u8; BLOCK_SIZE];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = (100.0 * (i as f64 * step).sin() + 100.0) as u8;
+        }
+        samples
+    }
+
+    fn run_test(filter: &mut GoertzelFilter, frequency: f64) {
+        eprintln!("For test frequency {:.6}:", frequency);
+
+        let samples = generate_test_samples(frequency);
+        let samples: Vec<i16> = samples.iter().map(|s| *s as i16).collect();
+        filter.process(&samples);
+
+        let (real, imag) = filter.get_real_imag();
+        eprintln!("real = {:.6} imag = {:.6}", real, imag);
+
+        let mag_sq = real * real + imag * imag;
+        eprintln!("Relative magnitude squared = {:.6}", mag_sq);
+        eprintln!("Relative magnitude = {:.6}", mag_sq.sqrt());
+
+        eprintln!("Relative magnitude squared = {:.6}", filter.get_mag_sq());
+        eprintln!("Relative magnitude = {:.6}\n", filter.get_mag_sq().sqrt());
+    }
+
+    #[test]
+    fn test_goertzel_filter_target() {
+        let mut filter = GoertzelFilter::new(BLOCK_SIZE, TARGET_FREQUENCY, SAMPLING_RATE);
+        eprint!("\nFor SAMPLING_RATE = {:.6}", SAMPLING_RATE);
+        eprint!(" N = {}", BLOCK_SIZE);
+        eprintln!(" and FREQUENCY = {:.6},", TARGET_FREQUENCY);
+        eprintln!("k = {} and coeff = {:.6}\n", filter.k, filter.coeff);
+
+        run_test(&mut filter, TARGET_FREQUENCY - 250.0);
+        let (real, imag) = filter.get_real_imag();
+        assert_eq!(real.floor(), -316.0);
+        assert_eq!(imag.floor(), -187.0);
+        assert_eq!(filter.get_mag_sq().floor(), 134338.0);
+        filter.reset();
+
+        run_test(&mut filter, TARGET_FREQUENCY);
+        let (real, imag) = filter.get_real_imag();
+        assert_eq!(real.floor(), -191.0);
+        assert_eq!(imag.floor(), -10196.0);
+        assert_eq!(filter.get_mag_sq().floor(), 103981719.0);
+        filter.reset();
+
+        run_test(&mut filter, TARGET_FREQUENCY + 250.0);
+        let (real, imag) = filter.get_real_imag();
+        assert_eq!(real.floor(), 596.0);
+        assert_eq!(imag.floor(), -177.0);
+        assert_eq!(filter.get_mag_sq().floor(), 387565.0);
+        filter.reset();
+    }
+
+    #[test]
+    fn test_goertzel_filter_sweep() {
+        let mut filter = GoertzelFilter::new(BLOCK_SIZE, TARGET_FREQUENCY, SAMPLING_RATE);
+        let mut freq = TARGET_FREQUENCY - 300.0;
+        let end = TARGET_FREQUENCY + 300.0;
+        while freq <= end {
+            eprint!("Freq={:7.1}   ", freq);
+
+            let samples = generate_test_samples(freq);
+            let samples: Vec<i16> = samples.iter().map(|s| *s as i16).collect();
+            filter.process(&samples);
+
+            let (real, imag) = filter.get_real_imag();
+            let mag_sq = real * real + imag * imag;
+            eprint!("rel mag^2={:16.5}   ", mag_sq);
+            eprintln!("rel mag={:12.5}", mag_sq.sqrt());
+
+            freq += 15.0;
+            filter.reset();
+        }
+    }
+
+    #[test]
+    fn test_goertzel_pair() {
+        let samples = generate_test_samples(TARGET_FREQUENCY);
+        let samples: Vec<i16> = samples.iter().map(|s| *s as i16).collect();
+        let frequencies = [TARGET_FREQUENCY, TARGET_FREQUENCY + 250.0];
+        let pair = GoertzelPair::new(BLOCK_SIZE, frequencies, SAMPLING_RATE);
+        let separate = frequencies.map(|frequency| {
+            let mut filter = GoertzelFilter::new(BLOCK_SIZE, frequency, SAMPLING_RATE);
+            filter.process(&samples);
+            filter.get_mag_sq()
+        });
+        assert_eq!(pair.energies(&samples), separate);
+        // Each block starts afresh
+        assert_eq!(pair.energies(&samples), separate);
+    }
+
+    #[test]
+    fn test_goertzel_pair_32() {
+        let samples = generate_test_samples(TARGET_FREQUENCY);
+        let samples: Vec<i16> = samples.iter().map(|s| *s as i16 * 100).collect();
+        let frequencies = [TARGET_FREQUENCY, TARGET_FREQUENCY + 250.0];
+        let double = GoertzelPair::new(BLOCK_SIZE, frequencies, SAMPLING_RATE).energies(&samples);
+        let single = GoertzelPair32::new(BLOCK_SIZE, frequencies, SAMPLING_RATE).energies(&samples);
+        for (double, single) in double.iter().zip(single) {
+            assert!((f64::from(single) / double - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_is_silent() {
+        assert!(is_silent(&[]));
+        assert!(is_silent(&[0; 160]));
+        assert!(is_silent(&[99; 160]));
+        assert!(!is_silent(&[100; 160]));
+        assert!(!is_silent(&[-8_000, 8_000]));
+    }
+
+    #[test]
+    fn test_goertzel_bank() {
+        let samples: Vec<i16> = (0..48_000)
+            .map(|i| (8_000.0 * (i as f64 * 1270.0 * 2.0 * PI / 48_000.0).sin()) as i16)
+            .collect();
+        let blocks = [(0, 160), (1_000, 1_331), (2_000, 48_000)];
+        let one_at_a_time = |frequency| -> f64 {
+            blocks
+                .iter()
+                .map(|&(start, end)| {
+                    let mut filter = GoertzelFilter::new(end - start, frequency, 48_000.0);
+                    filter.process(&samples[start..end]);
+                    filter.get_mag_sq()
+                })
+                .sum()
+        };
+        // Few enough filters to run on one thread, and a leftover short of a whole set
+        let few = GoertzelBank::new(vec![1070.0, 1270.0, 2025.0, 2225.0, 1185.5], 48_000.0);
+        let energies = few.energies(&samples, &blocks);
+        for (&frequency, &energy) in few.frequencies().iter().zip(&energies) {
+            assert_eq!(energy, one_at_a_time(frequency));
+        }
+        // and enough that they're shared between threads
+        let many = GoertzelBank::new(
+            (0..1_001).map(|i| 300.0 + i as f64 * 3.0).collect(),
+            48_000.0,
+        );
+        let energies = many.energies(&samples, &blocks);
+        assert_eq!(energies.len(), 1_001);
+        for &i in &[0, 3, 4, 323, 998, 1_000] {
+            assert_eq!(energies[i], one_at_a_time(many.frequencies()[i]));
+        }
+        // One block at a time, into a buffer that's reused
+        let mut block = vec![f64::NAN; 5];
+        for &(start, end) in &blocks {
+            few.energies_into(&samples[start..end], &mut block);
+            assert_eq!(block, few.energies(&samples, &[(start, end)]));
+        }
+        let loudest = (0..energies.len()).max_by(|&a, &b| energies[a].total_cmp(&energies[b]));
+        assert_eq!(many.frequencies()[loudest.unwrap()], 1269.0);
+    }
+}
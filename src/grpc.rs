@@ -0,0 +1,598 @@
+//! A gRPC server over HTTP/2 without TLS, which clients connect to with prior knowledge,
+//! as `grpcurl -plaintext` and channels made without credentials do. There's just enough
+//! HTTP/2 for gRPC calls: a connection's streams are served on its own thread as their
+//! frames arrive, and messages can't be compressed.
+
+use crate::hpack;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+/// What a client sends first, before its settings
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+/// Frame flags, ACK being on SETTINGS and PING
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+/// Settings a client can change
+const INITIAL_WINDOW_SIZE: u16 = 0x4;
+const MAX_FRAME_SIZE: u16 = 0x5;
+/// Error codes, for RST_STREAM and GOAWAY
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+/// The largest frame and flow control window each side starts with
+const FRAME_SIZE: usize = 16_384;
+const WINDOW: i64 = 65_535;
+/// Bigger messages are refused, as gRPC's own servers do by default
+const MAX_MESSAGE: usize = 4 << 20;
+/// Header blocks bigger than this close the connection
+const MAX_HEADERS: usize = 64 << 10;
+
+/// How a call ended, sent in its trailers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub code: u32,
+    pub message: String,
+}
+
+impl Status {
+    pub const OK: u32 = 0;
+    pub const INVALID_ARGUMENT: u32 = 3;
+    pub const RESOURCE_EXHAUSTED: u32 = 8;
+    pub const UNIMPLEMENTED: u32 = 12;
+    pub const INTERNAL: u32 = 13;
+
+    pub fn new(code: u32, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A call in progress, answering the messages a client streams to it with its own
+pub trait Call {
+    /// Takes the next message, returning those to answer with, or the status ending the
+    /// call early
+    fn message(&mut self, message: &[u8]) -> Result<Vec<Vec<u8>>, Status>;
+
+    /// Ends the call once the client has sent everything, returning any last messages
+    fn finish(&mut self) -> Result<Vec<Vec<u8>>, Status> {
+        Ok(Vec::new())
+    }
+}
+
+/// Starts calls to a method from its path, such as `/package.Service/Method`
+type Service = dyn Fn(&str) -> Result<Box<dyn Call>, Status> + Send + Sync;
+
+/// A gRPC server, accepting connections in the background
+pub struct Server {
+    address: SocketAddr,
+}
+
+impl Server {
+    /// Listens on `address`, starting each call with `service`
+    pub fn listen<F>(address: &str, service: F) -> io::Result<Self>
+    where
+        F: Fn(&str) -> Result<Box<dyn Call>, Status> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(address)?;
+        let server = Self {
+            address: listener.local_addr()?,
+        };
+        let service: Arc<Service> = Arc::new(service);
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let service = Arc::clone(&service);
+                thread::spawn(move || {
+                    let mut connection = Connection::new(client, service);
+                    if let Err(e) = connection.serve() {
+                        if e.kind() == io::ErrorKind::InvalidData {
+                            let _ = connection.go_away(PROTOCOL_ERROR);
+                        }
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+/// A call's stream, from the headers starting it until its trailers are sent
+struct Stream {
+    /// The call, until it ends
+    call: Option<Box<dyn Call>>,
+    /// What's arrived of the next message
+    received: Vec<u8>,
+    /// Messages waiting for the client to have room for them
+    output: Vec<u8>,
+    /// How the call ended, to send once the output has gone
+    status: Option<Status>,
+    /// How much more the client will take on the stream
+    window: i64,
+    /// Whether the client has sent everything
+    closed: bool,
+}
+
+impl Stream {
+    fn receive(&mut self, data: &[u8]) {
+        self.received.extend_from_slice(data);
+        while self.call.is_some() && self.received.len() >= 5 {
+            let length = u32::from_be_bytes(self.received[1..5].try_into().unwrap()) as usize;
+            if self.received[0] != 0 {
+                let status = Status::new(Status::UNIMPLEMENTED, "messages can't be compressed");
+                return self.answer(Err(status));
+            } else if length > MAX_MESSAGE {
+                let message = format!("messages can't be over {} bytes", MAX_MESSAGE);
+                return self.answer(Err(Status::new(Status::RESOURCE_EXHAUSTED, &message)));
+            } else if self.received.len() < 5 + length {
+                break;
+            }
+            let message: Vec<u8> = self.received.drain(..5 + length).skip(5).collect();
+            let answers = self.call.as_mut().unwrap().message(&message);
+            self.answer(answers);
+        }
+    }
+
+    /// Ends the call once the client has sent everything
+    fn finish(&mut self) {
+        self.closed = true;
+        let Some(call) = self.call.as_mut() else {
+            return;
+        };
+        let answers = match self.received.is_empty() {
+            true => call.finish(),
+            false => Err(Status::new(
+                Status::INTERNAL,
+                "the stream ended mid-message",
+            )),
+        };
+        self.answer(answers);
+        self.status
+            .get_or_insert_with(|| Status::new(Status::OK, ""));
+        self.call = None;
+    }
+
+    fn answer(&mut self, answers: Result<Vec<Vec<u8>>, Status>) {
+        match answers {
+            Ok(messages) => {
+                for message in messages {
+                    self.output.push(0);
+                    self.output
+                        .extend_from_slice(&(message.len() as u32).to_be_bytes());
+                    self.output.extend_from_slice(&message);
+                }
+            }
+            Err(status) => {
+                self.status = Some(status);
+                self.call = None;
+            }
+        }
+    }
+}
+
+struct Connection {
+    socket: TcpStream,
+    service: Arc<Service>,
+    headers: hpack::Decoder,
+    streams: HashMap<u32, Stream>,
+    /// The last stream the client opened, as closed streams can't be opened again
+    last_stream: u32,
+    /// How much more the client will take across the connection, and on a new stream
+    window: i64,
+    stream_window: i64,
+    frame_size: usize,
+}
+
+impl Connection {
+    fn new(socket: TcpStream, service: Arc<Service>) -> Self {
+        Self {
+            socket,
+            service,
+            headers: hpack::Decoder::default(),
+            streams: HashMap::new(),
+            last_stream: 0,
+            window: WINDOW,
+            stream_window: WINDOW,
+            frame_size: FRAME_SIZE,
+        }
+    }
+
+    /// Serves calls until the client goes away
+    fn serve(&mut self) -> io::Result<()> {
+        let mut preface = [0; PREFACE.len()];
+        self.socket.read_exact(&mut preface)?;
+        if preface != PREFACE {
+            // Probably HTTP/1.1, which can't be answered with a GOAWAY
+            return Err(io::Error::other("expected HTTP/2 with prior knowledge"));
+        }
+        write_frame(&mut self.socket, SETTINGS, 0, 0, &[])?;
+        loop {
+            let (kind, flags, id, payload) = read_frame(&mut self.socket)?;
+            match kind {
+                DATA => self.data(id, flags, &payload)?,
+                HEADERS => self.start(id, flags, &payload)?,
+                RST_STREAM => drop(self.streams.remove(&id)),
+                SETTINGS if flags & ACK == 0 => self.settings(&payload)?,
+                PING if flags & ACK == 0 => write_frame(&mut self.socket, PING, ACK, 0, &payload)?,
+                GOAWAY => return Ok(()),
+                WINDOW_UPDATE => self.window_update(id, &payload)?,
+                CONTINUATION => return Err(invalid("CONTINUATION without HEADERS")),
+                _ => {}
+            }
+            self.flush()?;
+        }
+    }
+
+    /// Starts a call from the headers opening a stream, or ends one with the client's
+    /// trailers
+    fn start(&mut self, id: u32, flags: u8, payload: &[u8]) -> io::Result<()> {
+        let mut block = unpad(flags, payload)?;
+        if flags & PRIORITY != 0 {
+            block = block
+                .get(5..)
+                .ok_or_else(|| invalid("a HEADERS frame is too short"))?;
+        }
+        let mut block = block.to_vec();
+        let mut ended = flags & END_HEADERS != 0;
+        while !ended {
+            let (kind, flags, continued, payload) = read_frame(&mut self.socket)?;
+            if kind != CONTINUATION || continued != id {
+                return Err(invalid("expected CONTINUATION"));
+            } else if block.len() + payload.len() > MAX_HEADERS {
+                return Err(invalid("the headers are too big"));
+            }
+            block.extend_from_slice(&payload);
+            ended = flags & END_HEADERS != 0;
+        }
+        let headers = self.headers.decode(&block).map_err(|e| invalid(&e))?;
+        let end_stream = flags & END_STREAM != 0;
+
+        if let Some(stream) = self.streams.get_mut(&id) {
+            if end_stream {
+                stream.finish();
+            }
+            return Ok(());
+        } else if id & 1 == 0 {
+            return Err(invalid("clients open odd numbered streams"));
+        } else if id <= self.last_stream {
+            // Trailers for a call that's already ended
+            return Ok(());
+        }
+        self.last_stream = id;
+
+        let header = |name: &str| {
+            let mut values = headers.iter().filter(|(key, _)| key == name);
+            values.next().map(|(_, value)| value.as_str())
+        };
+        if !header("content-type").is_some_and(|kind| kind.starts_with("application/grpc")) {
+            let block = hpack::encode(&[(":status", "415")]);
+            return write_frame(
+                &mut self.socket,
+                HEADERS,
+                END_HEADERS | END_STREAM,
+                id,
+                &block,
+            );
+        }
+        let (call, status) = match (self.service)(header(":path").unwrap_or_default()) {
+            Ok(call) => (Some(call), None),
+            Err(status) => (None, Some(status)),
+        };
+        let block = hpack::encode(&[(":status", "200"), ("content-type", "application/grpc")]);
+        write_frame(&mut self.socket, HEADERS, END_HEADERS, id, &block)?;
+        let mut stream = Stream {
+            call,
+            received: Vec::new(),
+            output: Vec::new(),
+            status,
+            window: self.stream_window,
+            closed: false,
+        };
+        if end_stream {
+            stream.finish();
+        }
+        self.streams.insert(id, stream);
+        Ok(())
+    }
+
+    fn data(&mut self, id: u32, flags: u8, payload: &[u8]) -> io::Result<()> {
+        let end_stream = flags & END_STREAM != 0;
+        // Give back the room the frame took as soon as it's read
+        if !payload.is_empty() {
+            let increment = (payload.len() as u32).to_be_bytes();
+            write_frame(&mut self.socket, WINDOW_UPDATE, 0, 0, &increment)?;
+            if !end_stream && self.streams.contains_key(&id) {
+                write_frame(&mut self.socket, WINDOW_UPDATE, 0, id, &increment)?;
+            }
+        }
+        let data = unpad(flags, payload)?;
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.receive(data);
+            if end_stream {
+                stream.finish();
+            }
+        }
+        Ok(())
+    }
+
+    fn settings(&mut self, payload: &[u8]) -> io::Result<()> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(invalid("a SETTINGS frame has a partial setting"));
+        }
+        for setting in payload.chunks_exact(6) {
+            let value = u32::from_be_bytes(setting[2..].try_into().unwrap());
+            match u16::from_be_bytes([setting[0], setting[1]]) {
+                INITIAL_WINDOW_SIZE if value > i32::MAX as u32 => {
+                    return Err(invalid("the initial window is too big"));
+                }
+                INITIAL_WINDOW_SIZE => {
+                    // Changing it moves the windows of streams already open too
+                    let change = i64::from(value) - self.stream_window;
+                    for stream in self.streams.values_mut() {
+                        stream.window += change;
+                    }
+                    self.stream_window = i64::from(value);
+                }
+                MAX_FRAME_SIZE if !(FRAME_SIZE as u32..1 << 24).contains(&value) => {
+                    return Err(invalid("the maximum frame size is out of range"));
+                }
+                MAX_FRAME_SIZE => self.frame_size = value as usize,
+                _ => {}
+            }
+        }
+        write_frame(&mut self.socket, SETTINGS, ACK, 0, &[])
+    }
+
+    fn window_update(&mut self, id: u32, payload: &[u8]) -> io::Result<()> {
+        let increment: [u8; 4] = payload
+            .try_into()
+            .map_err(|_| invalid("a WINDOW_UPDATE frame isn't 4 bytes"))?;
+        let increment = i64::from(u32::from_be_bytes(increment) & 0x7fff_ffff);
+        match id {
+            0 => self.window += increment,
+            id => {
+                if let Some(stream) = self.streams.get_mut(&id) {
+                    stream.window += increment;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends as much output as the client has room for, and the trailers of calls that
+    /// have ended once all their output has gone
+    fn flush(&mut self) -> io::Result<()> {
+        let mut ids: Vec<u32> = self.streams.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            let stream = self.streams.get_mut(&id).unwrap();
+            while !stream.output.is_empty() && self.window > 0 && stream.window > 0 {
+                let room = self.window.min(stream.window) as usize;
+                let size = stream.output.len().min(self.frame_size).min(room);
+                let chunk: Vec<u8> = stream.output.drain(..size).collect();
+                self.window -= size as i64;
+                stream.window -= size as i64;
+                write_frame(&mut self.socket, DATA, 0, id, &chunk)?;
+            }
+            if !stream.output.is_empty() {
+                continue;
+            }
+            let Some(status) = stream.status.take() else {
+                continue;
+            };
+            let code = status.code.to_string();
+            let mut trailers = vec![("grpc-status", code.as_str())];
+            let message = percent_encode(&status.message);
+            if !message.is_empty() {
+                trailers.push(("grpc-message", &message));
+            }
+            let block = hpack::encode(&trailers);
+            write_frame(
+                &mut self.socket,
+                HEADERS,
+                END_HEADERS | END_STREAM,
+                id,
+                &block,
+            )?;
+            // A client still sending is told to stop
+            if !stream.closed {
+                write_frame(&mut self.socket, RST_STREAM, 0, id, &NO_ERROR.to_be_bytes())?;
+            }
+            self.streams.remove(&id);
+        }
+        Ok(())
+    }
+
+    fn go_away(&mut self, error: u32) -> io::Result<()> {
+        let mut payload = self.last_stream.to_be_bytes().to_vec();
+        payload.extend_from_slice(&error.to_be_bytes());
+        write_frame(&mut self.socket, GOAWAY, 0, 0, &payload)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads a frame as its type, flags, stream and payload
+fn read_frame<R: Read>(reader: &mut R) -> io::Result<(u8, u8, u32, Vec<u8>)> {
+    let mut header = [0; 9];
+    reader.read_exact(&mut header)?;
+    let length = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+    if length > FRAME_SIZE {
+        return Err(invalid("a frame is bigger than the maximum frame size"));
+    }
+    let id = u32::from_be_bytes(header[5..].try_into().unwrap()) & 0x7fff_ffff;
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+    Ok((header[3], header[4], id, payload))
+}
+
+fn write_frame<W: Write>(
+    writer: &mut W,
+    kind: u8,
+    flags: u8,
+    id: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+    frame.extend_from_slice(&[kind, flags]);
+    frame.extend_from_slice(&id.to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)
+}
+
+/// A padded frame's payload without its padding
+fn unpad(flags: u8, payload: &[u8]) -> io::Result<&[u8]> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    match payload.split_first() {
+        Some((&padding, rest)) if usize::from(padding) <= rest.len() => {
+            Ok(&rest[..rest.len() - usize::from(padding)])
+        }
+        _ => Err(invalid("a frame has more padding than payload")),
+    }
+}
+
+/// Percent encodes a status message, as `grpc-message` holds only printable ASCII
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::new();
+    for byte in message.bytes() {
+        match byte {
+            b' '..=b'~' if byte != b'%' => encoded.push(char::from(byte)),
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers each message with it reversed, and ends with its count
+    struct Reverse(u8);
+
+    impl Call for Reverse {
+        fn message(&mut self, message: &[u8]) -> Result<Vec<Vec<u8>>, Status> {
+            self.0 += 1;
+            Ok(vec![message.iter().rev().copied().collect()])
+        }
+
+        fn finish(&mut self) -> Result<Vec<Vec<u8>>, Status> {
+            Ok(vec![vec![self.0]])
+        }
+    }
+
+    fn message(message: &[u8]) -> Vec<u8> {
+        let mut framed = vec![0];
+        framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        framed.extend_from_slice(message);
+        framed
+    }
+
+    /// Makes a call, returning the response's headers, its body and its trailers
+    fn call(
+        client: &mut TcpStream,
+        id: u32,
+        path: &str,
+        body: &[u8],
+    ) -> Vec<Vec<(String, String)>> {
+        let headers = hpack::encode(&[
+            (":method", "POST"),
+            (":scheme", "http"),
+            (":path", path),
+            ("content-type", "application/grpc"),
+        ]);
+        write_frame(client, HEADERS, END_HEADERS, id, &headers).unwrap();
+        let (first, rest) = body.split_at(body.len() / 3);
+        write_frame(client, DATA, 0, id, first).unwrap();
+        write_frame(client, DATA, END_STREAM, id, rest).unwrap();
+
+        let mut decoder = hpack::Decoder::default();
+        let mut blocks = Vec::new();
+        let mut body = Vec::new();
+        loop {
+            let (kind, flags, stream, payload) = read_frame(client).unwrap();
+            match kind {
+                DATA if stream == id => {
+                    body.extend_from_slice(&payload);
+                    let increment = (payload.len() as u32).to_be_bytes();
+                    write_frame(client, WINDOW_UPDATE, 0, id, &increment).unwrap();
+                }
+                HEADERS if stream == id => {
+                    blocks.push(decoder.decode(&payload).unwrap());
+                    if flags & END_STREAM != 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let body = String::from_utf8_lossy(&body).into_owned();
+        blocks.insert(1, vec![("body".to_string(), body)]);
+        blocks
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::listen("127.0.0.1:0", |path| match path {
+            "/test.Reverse/Reverse" => Ok(Box::new(Reverse(0)) as Box<dyn Call>),
+            _ => Err(Status::new(Status::UNIMPLEMENTED, "no such 100% method")),
+        })
+        .unwrap();
+        let mut client = TcpStream::connect(server.local_address()).unwrap();
+        client.write_all(PREFACE).unwrap();
+        write_frame(&mut client, SETTINGS, 0, 0, &[0, 4, 0, 0, 0, 8]).unwrap();
+
+        let body = [message(b"olleh"), message(b"dlrow")].concat();
+        let reversed = call(&mut client, 1, "/test.Reverse/Reverse", &body);
+        let header = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+        assert_eq!(
+            reversed[0],
+            [
+                header(":status", "200"),
+                header("content-type", "application/grpc")
+            ]
+            .concat()
+        );
+        // The client's window of 8 bytes splits the answers up, an answer needing 10
+        let body =
+            String::from_utf8([message(b"hello"), message(b"world"), message(&[2])].concat())
+                .unwrap();
+        assert_eq!(reversed[1], header("body", &body));
+        assert_eq!(reversed[2], header("grpc-status", "0"));
+
+        let missing = call(&mut client, 3, "/test.Reverse/Missing", &[]);
+        assert_eq!(missing[1], header("body", ""));
+        assert_eq!(
+            missing[2],
+            [
+                header("grpc-status", "12"),
+                header("grpc-message", "no such 100%25 method")
+            ]
+            .concat()
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use crate::audio;
+use crate::detect::{self, Identified};
+use crate::events;
+use crate::framing;
+use crate::goertzel::GoertzelPair;
+use crate::standard::{Channel, Selection, Standard};
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+/// The page `bell103_gui` serves, which a recording is dropped on to decode it
+pub const PAGE: &str = include_str!("gui.html");
+/// The most points plotted for each tone, however long the recording
+const MAX_POINTS: usize = 2000;
+/// Where the plot's levels bottom out, in dBFS
+const FLOOR_DB: f64 = -90.0;
+
+/// A recording decoded for the GUI, with the level of each tone over it to plot
+#[derive(Debug)]
+pub struct Decoded {
+    pub standard: Standard,
+    pub origin: bool,
+    pub text: String,
+    /// Seconds between the points of the plot
+    pub interval: f64,
+    /// Mark and space levels in dBFS
+    pub mark: Vec<f64>,
+    pub space: Vec<f64>,
+}
+
+impl Decoded {
+    pub fn json(&self) -> String {
+        let levels = |levels: &[f64]| {
+            let mut json = String::from("[");
+            for (i, level) in levels.iter().enumerate() {
+                let _ = write!(json, "{}{:.1}", if i > 0 { "," } else { "" }, level);
+            }
+            json + "]"
+        };
+        format!(
+            r#"{{"standard":"{}","origin":{},"text":{},"interval":{},"mark":{},"space":{}}}"#,
+            format!("{:?}", self.standard).to_lowercase(),
+            self.origin,
+            events::json_string(&self.text),
+            self.interval,
+            levels(&self.mark),
+            levels(&self.space)
+        )
+    }
+}
+
+/// Decodes a WAV recording with the preset picked, a standard and channel or `auto` to
+/// identify them from the recording
+pub fn decode(
+    wav: &[u8],
+    selection: Selection,
+    origin: bool,
+    sampling_rate: f64,
+) -> Result<Decoded, String> {
+    let samples = audio::read_wav(Cursor::new(wav), sampling_rate)
+        .map_err(|e| format!("couldn't read the recording: {}", e))?;
+    let (standard, origin) = match selection {
+        Selection::Fixed(standard) => (standard, origin),
+        Selection::Auto => match detect::identify(&samples, sampling_rate) {
+            Some(detection) => match detection.identified {
+                Identified::Modem { standard, origin } => (standard, origin),
+                identified => return Err(format!("found {}, not a modem standard", identified)),
+            },
+            None => {
+                return Err(
+                    "couldn't identify the standard, the recording starts with silence".to_string(),
+                )
+            }
+        },
+    };
+    let channel = standard.channel(origin);
+    let (received, _) = framing::receive(&samples, channel, sampling_rate, 8);
+    let text = received.iter().map(|r| char::from(r.byte & 0x7f)).collect();
+    let (block, mark, space) = levels(&samples, channel, sampling_rate);
+    Ok(Decoded {
+        standard,
+        origin,
+        text,
+        interval: block as f64 / sampling_rate,
+        mark,
+        space,
+    })
+}
+
+/// The level of the mark and space tones over blocks of a bit period or longer, so there are
+/// at most [`MAX_POINTS`] of them, returning the block length with them
+fn levels(samples: &[i16], channel: Channel, sampling_rate: f64) -> (usize, Vec<f64>, Vec<f64>) {
+    let bit = (sampling_rate / channel.baud).round().max(1.0) as usize;
+    let block = bit.max(samples.len().div_ceil(MAX_POINTS));
+    let filters = GoertzelPair::new(block, [channel.mark, channel.space], sampling_rate);
+    let (mut mark, mut space) = (Vec::new(), Vec::new());
+    for chunk in samples.chunks_exact(block) {
+        // A full scale tone's magnitude is half the block length times full scale
+        let db = |energy: f64| {
+            let amplitude = 2.0 * energy.sqrt() / block as f64 / f64::from(i16::MAX);
+            (20.0 * amplitude.log10()).max(FLOOR_DB)
+        };
+        let [mark_energy, space_energy] = filters.energies(chunk);
+        mark.push(db(mark_energy));
+        space.push(db(space_energy));
+    }
+    (block, mark, space)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::PcmFormat;
+    use crate::framing::frame;
+    use crate::modulator::FskModulator;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    fn recording(channel: Channel) -> Vec<u8> {
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+        let mut bits = vec![1; 60];
+        bits.extend(frame(b"Welcome, login:"));
+        bits.extend(vec![1; 30]);
+        let mut samples = Vec::new();
+        modulator.modulate(&bits, &mut samples);
+        let mut wav = Vec::new();
+        audio::write_samples(&mut wav, &samples, PcmFormat::Wav, SAMPLING_RATE as u32).unwrap();
+        wav
+    }
+
+    #[test]
+    fn test_decode() {
+        let wav = recording(Standard::V21.originate());
+        let decoded = decode(&wav, Selection::Auto, false, SAMPLING_RATE).unwrap();
+        assert_eq!(decoded.standard, Standard::V21);
+        assert!(decoded.origin);
+        assert_eq!(decoded.text, "Welcome, login:");
+        assert_eq!(decoded.interval, 160.0 / SAMPLING_RATE);
+        assert_eq!(decoded.mark.len(), decoded.space.len());
+        // The modulator's idle mark at the start is at half of full scale, and a bit period's
+        // filter only hears some of it at the space tone
+        assert!((decoded.mark[10] + 6.0).abs() < 1.0);
+        assert!(decoded.space[10] < decoded.mark[10] - 5.0);
+        assert!(decoded.json().starts_with(
+            r#"{"standard":"v21","origin":true,"text":"Welcome, login:","interval":"#
+        ));
+
+        let wrong = decode(
+            &wav,
+            Selection::Fixed(Standard::Bell202),
+            false,
+            SAMPLING_RATE,
+        );
+        assert_ne!(wrong.unwrap().text, "Welcome, login:");
+        assert!(decode(b"not a recording", Selection::Auto, false, SAMPLING_RATE).is_err());
+    }
+
+    #[test]
+    fn test_levels() {
+        let samples = vec![0; MAX_POINTS * 1000 + 1];
+        let (block, mark, space) = levels(&samples, Standard::Bell103.answer(), SAMPLING_RATE);
+        assert_eq!(block, 1001);
+        assert_eq!(mark.len(), samples.len() / block);
+        assert!(mark.len() <= MAX_POINTS);
+        assert!(space.iter().all(|&db| db == FLOOR_DB));
+    }
+}
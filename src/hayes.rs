@@ -0,0 +1,332 @@
+use std::time::Duration;
+
+/// S-registers S0 to S12 as a Hayes modem starts with them. Of these S2 (the escape
+/// character), S3 to S5 (carriage return, line feed and backspace), S7 (seconds to wait for
+/// carrier) and S12 (the escape guard time in fiftieths of a second) are honoured, the rest
+/// are only stored for software that sets them.
+const DEFAULT_REGISTERS: [u8; 13] = [0, 0, b'+', b'\r', b'\n', 8, 2, 50, 2, 6, 14, 95, 50];
+
+/// What the modem asks of its link in response to what a program types
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Bytes for the program, result codes and echoed commands
+    Reply(Vec<u8>),
+    /// A byte to send across the link while online
+    Send(u8),
+    /// Start calling, the number being a `host:port` for a TCP connection
+    Dial(String),
+    /// Start answering a call
+    Answer,
+    /// Drop the link
+    Hangup,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// On hook, no call
+    Idle,
+    /// Dialing or answering, since this long into the session
+    Waiting(Duration),
+    Connected,
+}
+
+/// Interprets Hayes AT commands from a program driving the modem: ATD, ATA, ATH, ATO, ATE,
+/// ATZ and ATSn=v or ATSn?. Once connected, typed bytes pass through to the link until the
+/// escape sequence, `+++` after a guard time, returns to commands.
+pub struct Hayes {
+    baud: u32,
+    echo: bool,
+    registers: [u8; 13],
+    line: Vec<u8>,
+    state: State,
+    online: bool,
+    last_typed: Option<Duration>,
+    escapes: usize,
+}
+
+impl Hayes {
+    /// A modem on hook that reports connecting at `baud`
+    pub fn new(baud: u32) -> Self {
+        Self {
+            baud,
+            echo: true,
+            registers: DEFAULT_REGISTERS,
+            line: Vec::new(),
+            state: State::Idle,
+            online: false,
+            last_typed: None,
+            escapes: 0,
+        }
+    }
+
+    /// Whether typed bytes go across the link, rather than being taken as commands
+    pub fn is_online(&self) -> bool {
+        self.online
+    }
+
+    /// How long to wait for carrier after dialing or answering, S7
+    pub fn wait_for_carrier(&self) -> Duration {
+        Duration::from_secs(u64::from(self.registers[7]))
+    }
+
+    /// Takes a byte typed by the program `at` this long into the session
+    pub fn typed(&mut self, byte: u8, at: Duration) -> Vec<Action> {
+        let guard = Duration::from_millis(20 * u64::from(self.registers[12]));
+        let guarded = self.last_typed.is_none_or(|last| at - last >= guard);
+        self.last_typed = Some(at);
+        if self.online {
+            self.typed_online(byte, guarded)
+        } else {
+            self.typed_command(byte, at)
+        }
+    }
+
+    /// Follows the audio link's carrier, connecting when it's heard after dialing or
+    /// answering and hanging up when it's lost or never comes
+    pub fn carrier(&mut self, heard: bool, at: Duration) -> Vec<Action> {
+        match self.state {
+            State::Waiting(_) if heard => self.connected(),
+            State::Waiting(since) if at - since >= self.wait_for_carrier() => self.lost(),
+            State::Connected if !heard => self.lost(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Reports the call dialed or answered as connected
+    pub fn connected(&mut self) -> Vec<Action> {
+        if !matches!(self.state, State::Waiting(_)) {
+            return Vec::new();
+        }
+        self.state = State::Connected;
+        self.online = true;
+        vec![self.result(&format!("CONNECT {}", self.baud))]
+    }
+
+    /// Reports the call as lost or failed, hanging up
+    pub fn lost(&mut self) -> Vec<Action> {
+        if self.state == State::Idle {
+            return Vec::new();
+        }
+        self.state = State::Idle;
+        self.online = false;
+        vec![self.result("NO CARRIER"), Action::Hangup]
+    }
+
+    fn typed_online(&mut self, byte: u8, guarded: bool) -> Vec<Action> {
+        // The first escape character needs a pause before it, the others follow it quickly
+        let escape = self.registers[2];
+        if byte == escape && guarded == (self.escapes == 0) {
+            self.escapes += 1;
+            if self.escapes < 3 {
+                return Vec::new();
+            }
+            self.escapes = 0;
+            self.online = false;
+            return vec![self.result("OK")];
+        }
+        let mut actions: Vec<Action> = (0..self.escapes).map(|_| Action::Send(escape)).collect();
+        self.escapes = 0;
+        if byte == escape && guarded {
+            self.escapes = 1;
+        } else {
+            actions.push(Action::Send(byte));
+        }
+        actions
+    }
+
+    fn typed_command(&mut self, byte: u8, at: Duration) -> Vec<Action> {
+        let mut actions = Vec::new();
+        if self.echo {
+            actions.push(Action::Reply(vec![byte]));
+        }
+        if byte == self.registers[3] {
+            let line = std::mem::take(&mut self.line);
+            actions.extend(self.execute(&line, at));
+        } else if byte == self.registers[5] {
+            self.line.pop();
+        } else if byte != self.registers[4] {
+            self.line.push(byte);
+        }
+        actions
+    }
+
+    fn execute(&mut self, line: &[u8], at: Duration) -> Vec<Action> {
+        let line = line.trim_ascii();
+        if line.len() < 2 || !line[..2].eq_ignore_ascii_case(b"AT") {
+            return Vec::new();
+        }
+        let commands = &line[2..];
+        let mut i = 0;
+        while i < commands.len() {
+            let command = commands[i].to_ascii_uppercase();
+            i += 1;
+            match command {
+                b' ' => {}
+                b'D' | b'A' if self.state != State::Idle => break,
+                b'D' => {
+                    let mut number = commands[i..].trim_ascii();
+                    if let [b'T' | b't' | b'P' | b'p', rest @ ..] = number {
+                        number = rest.trim_ascii_start();
+                    }
+                    self.state = State::Waiting(at);
+                    return vec![Action::Dial(String::from_utf8_lossy(number).into_owned())];
+                }
+                b'A' => {
+                    self.state = State::Waiting(at);
+                    return vec![Action::Answer];
+                }
+                b'O' if self.state == State::Connected => {
+                    self.online = true;
+                    return vec![self.result(&format!("CONNECT {}", self.baud))];
+                }
+                b'H' => {
+                    number(commands, &mut i);
+                    if self.state != State::Idle {
+                        self.state = State::Idle;
+                        return vec![Action::Hangup, self.result("OK")];
+                    }
+                }
+                b'E' => self.echo = number(commands, &mut i).unwrap_or(0) != 0,
+                b'Z' => {
+                    number(commands, &mut i);
+                    self.echo = true;
+                    self.registers = DEFAULT_REGISTERS;
+                    if self.state != State::Idle {
+                        self.state = State::Idle;
+                        return vec![Action::Hangup, self.result("OK")];
+                    }
+                }
+                b'S' => match (number(commands, &mut i), commands.get(i)) {
+                    (Some(register), Some(b'=')) if register < 13 => {
+                        i += 1;
+                        match number(commands, &mut i).filter(|&value| value < 256) {
+                            Some(value) => self.registers[register as usize] = value as u8,
+                            None => break,
+                        }
+                    }
+                    (Some(register), Some(b'?')) if register < 13 => {
+                        return vec![
+                            self.result(&format!("{:03}", self.registers[register as usize])),
+                            self.result("OK"),
+                        ];
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+            if i == commands.len() {
+                return vec![self.result("OK")];
+            }
+        }
+        if commands.is_empty() {
+            vec![self.result("OK")]
+        } else {
+            vec![self.result("ERROR")]
+        }
+    }
+
+    /// A result code on a line of its own, with the line ending in S3 and S4
+    fn result(&self, text: &str) -> Action {
+        let end = [self.registers[3], self.registers[4]];
+        let mut reply = end.to_vec();
+        reply.extend_from_slice(text.as_bytes());
+        reply.extend_from_slice(&end);
+        Action::Reply(reply)
+    }
+}
+
+/// Reads the decimal number a command may take, moving past it
+fn number(commands: &[u8], i: &mut usize) -> Option<u32> {
+    let digits = commands[*i..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let value = std::str::from_utf8(&commands[*i..*i + digits]).unwrap();
+    *i += digits;
+    value.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_line(modem: &mut Hayes, line: &str, at: Duration) -> Vec<Action> {
+        let mut actions = Vec::new();
+        for &byte in line.as_bytes() {
+            actions.extend(modem.typed(byte, at));
+        }
+        actions
+    }
+
+    fn reply(text: &str) -> Action {
+        Action::Reply(format!("\r\n{}\r\n", text).into_bytes())
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut modem = Hayes::new(300);
+        let at = Duration::from_secs(0);
+        let actions = type_line(&mut modem, "AT\r", at);
+        assert_eq!(actions.last(), Some(&reply("OK")));
+        assert_eq!(
+            type_line(&mut modem, "ATE0\r", at).last(),
+            Some(&reply("OK"))
+        );
+        assert_eq!(
+            type_line(&mut modem, "ATS7=30S12?\r", at),
+            [reply("050"), reply("OK")]
+        );
+        assert_eq!(modem.wait_for_carrier(), Duration::from_secs(30));
+        assert_eq!(type_line(&mut modem, "ATX\r", at), [reply("ERROR")]);
+        assert_eq!(type_line(&mut modem, "ATS7=300\r", at), [reply("ERROR")]);
+        assert_eq!(type_line(&mut modem, "ATQ\x08E0\r", at), [reply("OK")]);
+        assert!(type_line(&mut modem, "hello\r", at).is_empty());
+
+        let dial = type_line(&mut modem, "ATDT bbs.example.com:23\r", at);
+        assert_eq!(dial, [Action::Dial("bbs.example.com:23".to_string())]);
+        assert_eq!(type_line(&mut modem, "ATA\r", at), [reply("ERROR")]);
+        assert_eq!(modem.connected(), [reply("CONNECT 300")]);
+        assert!(modem.is_online());
+        assert_eq!(modem.lost(), [reply("NO CARRIER"), Action::Hangup]);
+        assert!(modem.lost().is_empty());
+        type_line(&mut modem, "ATD5551234\r", at);
+        assert_eq!(
+            type_line(&mut modem, "ATH0\r", at),
+            [Action::Hangup, reply("OK")]
+        );
+    }
+
+    #[test]
+    fn test_escape() {
+        let mut modem = Hayes::new(300);
+        let second = |n: u64| Duration::from_secs(n);
+        type_line(&mut modem, "ATE0A\r", second(0));
+        assert_eq!(modem.carrier(true, second(1)), [reply("CONNECT 300")]);
+
+        // Pluses typed without a pause before them are data
+        let quick = Duration::from_millis(1_100);
+        assert_eq!(modem.typed(b'x', quick), [Action::Send(b'x')]);
+        assert_eq!(modem.typed(b'+', quick), [Action::Send(b'+')]);
+        // And so are ones after a pause that don't make up the whole sequence
+        let sent = type_line(&mut modem, "++y", second(3));
+        assert_eq!(sent, [b'+', b'+', b'y'].map(Action::Send));
+        assert_eq!(type_line(&mut modem, "+++", second(5)), [reply("OK")]);
+        assert!(!modem.is_online());
+        assert_eq!(
+            type_line(&mut modem, "ATO\r", second(6)),
+            [reply("CONNECT 300")]
+        );
+        assert_eq!(modem.carrier(true, second(7)), []);
+        assert_eq!(
+            modem.carrier(false, second(8)),
+            [reply("NO CARRIER"), Action::Hangup]
+        );
+
+        type_line(&mut modem, "ATD\r", second(10));
+        assert!(modem.carrier(false, second(59)).is_empty());
+        assert_eq!(
+            modem.carrier(false, second(60)),
+            [reply("NO CARRIER"), Action::Hangup]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+/// Frames shorter than two addresses, a control byte and the FCS are noise
+const MIN_FRAME_BYTES: usize = 17;
+const MAX_FRAME_BYTES: usize = 512;
+
+/// The CRC-16 used by HDLC and AX.25 (CCITT polynomial, bit reversed, inverted)
+pub fn fcs(data: &[u8]) -> u16 {
+    let crc = data.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            }
+        })
+    });
+    !crc
+}
+
+/// Recovers HDLC frames from NRZI coded tones: a change of tone is a 0 and no change is a 1.
+/// A flag of six 1s ends each frame, a 0 after five 1s is stuffing and seven 1s aborts.
+#[derive(Debug, Default)]
+pub struct HdlcDecoder {
+    last_tone: u8,
+    ones: u32,
+    bits: Vec<u8>,
+    in_frame: bool,
+}
+
+impl HdlcDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a frame's contents, without the FCS, when a flag ends a frame with a valid FCS
+    pub fn push(&mut self, tone: u8) -> Option<Vec<u8>> {
+        let bit = u8::from(tone == self.last_tone);
+        self.last_tone = tone;
+        if bit == 1 {
+            self.ones += 1;
+            if self.ones > 6 {
+                self.in_frame = false;
+                self.bits.clear();
+            } else {
+                self.bits.push(1);
+            }
+            return None;
+        }
+
+        let ones = self.ones;
+        self.ones = 0;
+        match ones {
+            6 => {
+                // Drop the flag's leading 0 and 1s already collected
+                self.bits.truncate(self.bits.len().saturating_sub(7));
+                let frame = if self.in_frame {
+                    self.take_frame()
+                } else {
+                    None
+                };
+                self.bits.clear();
+                self.in_frame = true;
+                frame
+            }
+            5 => None,
+            _ => {
+                self.bits.push(0);
+                if self.bits.len() > MAX_FRAME_BYTES * 8 {
+                    self.in_frame = false;
+                    self.bits.clear();
+                }
+                None
+            }
+        }
+    }
+
+    fn take_frame(&self) -> Option<Vec<u8>> {
+        if !self.bits.len().is_multiple_of(8) || self.bits.len() < MIN_FRAME_BYTES * 8 {
+            return None;
+        }
+        let bytes: Vec<u8> = self
+            .bits
+            .chunks_exact(8)
+            .map(|byte| byte.iter().rev().fold(0, |value, &bit| value << 1 | bit))
+            .collect();
+        let (data, check) = bytes.split_at(bytes.len() - 2);
+        if fcs(data) == u16::from_le_bytes([check[0], check[1]]) {
+            Some(data.to_vec())
+        } else {
+            None
+        }
+    }
+}
+
+/// Encodes a frame as NRZI tones with its FCS, bit stuffing and `flags` flags either side
+#[allow(unused)]
+pub fn encode(data: &[u8], flags: usize) -> Vec<u8> {
+    let flag = [0u8, 1, 1, 1, 1, 1, 1, 0];
+    let mut bits: Vec<u8> = flag.iter().cycle().take(8 * flags).copied().collect();
+    let mut ones = 0;
+    let fcs = fcs(data).to_le_bytes();
+    for &byte in data.iter().chain(&fcs) {
+        for i in 0..8 {
+            let bit = (byte >> i) & 1;
+            bits.push(bit);
+            ones = if bit == 1 { ones + 1 } else { 0 };
+            if ones == 5 {
+                bits.push(0);
+                ones = 0;
+            }
+        }
+    }
+    bits.extend(flag.iter().cycle().take(8 * flags));
+
+    let mut tone = 0;
+    bits.iter()
+        .map(|&bit| {
+            if bit == 0 {
+                tone ^= 1;
+            }
+            tone
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fcs() {
+        assert_eq!(fcs(b"123456789"), 0x906e);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Runs of 1s that need stuffing
+        let data: Vec<u8> = (0..40)
+            .map(|i| if i % 3 == 0 { 0xff } else { 0x7e })
+            .collect();
+        let mut decoder = HdlcDecoder::new();
+        let frames: Vec<Vec<u8>> = encode(&data, 3)
+            .into_iter()
+            .filter_map(|tone| decoder.push(tone))
+            .collect();
+        assert_eq!(frames, [data]);
+    }
+
+    #[test]
+    fn test_rejects_bad_fcs() {
+        let mut tones = encode(&[0x55; 20], 2);
+        tones[40] ^= 1;
+        let mut decoder = HdlcDecoder::new();
+        assert!(tones.into_iter().all(|tone| decoder.push(tone).is_none()));
+    }
+}
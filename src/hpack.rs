@@ -0,0 +1,382 @@
+//! HPACK header compression for HTTP/2, as described in RFC 7541. Headers sent are
+//! encoded as plain literals, which any peer can decode, so only decoding needs the
+//! tables and Huffman code.
+
+use std::collections::VecDeque;
+
+/// The dynamic table's size until the encoder changes it, which is HTTP/2's default
+const TABLE_SIZE: usize = 4096;
+/// What each entry costs in the table besides its name and value
+const ENTRY_OVERHEAD: usize = 32;
+/// The end of string symbol, which mustn't appear in a string
+const EOS: usize = 256;
+
+/// The static table, which indices 1 to 61 refer to
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// The length in bits of each symbol's Huffman code, the codes being canonical so these
+/// are all it takes to rebuild them
+const CODE_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+const LONGEST_CODE: usize = 30;
+
+/// Encodes headers as literals that aren't indexed, so they never touch the peer's table
+pub fn encode(headers: &[(&str, &str)]) -> Vec<u8> {
+    let mut block = Vec::new();
+    for (name, value) in headers {
+        block.push(0);
+        for string in [name, value] {
+            encode_integer(&mut block, 0, 7, string.len());
+            block.extend_from_slice(string.as_bytes());
+        }
+    }
+    block
+}
+
+/// Writes `value` with an `N`-bit prefix, the rest of its first byte being `flags`
+fn encode_integer(block: &mut Vec<u8>, flags: u8, prefix: u32, value: usize) {
+    let limit = (1 << prefix) - 1;
+    if value < limit {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | limit as u8);
+    let mut rest = value - limit;
+    while rest >= 0x80 {
+        block.push(rest as u8 | 0x80);
+        rest >>= 7;
+    }
+    block.push(rest as u8);
+}
+
+/// Decodes the header blocks a peer sends, keeping the dynamic table they build up
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    huffman: Huffman,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: TABLE_SIZE,
+            huffman: Huffman::new(),
+        }
+    }
+}
+
+impl Decoder {
+    /// The headers in a whole header block, in order
+    pub fn decode(&mut self, mut block: &[u8]) -> Result<Vec<(String, String)>, String> {
+        let mut headers = Vec::new();
+        while let Some(&first) = block.first() {
+            if first & 0x80 != 0 {
+                // Indexed
+                let index = decode_integer(&mut block, 7)?;
+                headers.push(self.entry(index)?);
+            } else if first & 0xe0 == 0x20 {
+                // A dynamic table size update
+                let size = decode_integer(&mut block, 5)?;
+                if size > TABLE_SIZE {
+                    return Err(format!("a table of {} bytes is too big", size));
+                }
+                self.max_size = size;
+                self.evict(0);
+            } else {
+                // A literal, with incremental indexing when 01 leads it
+                let indexing = first & 0xc0 == 0x40;
+                let index = decode_integer(&mut block, if indexing { 6 } else { 4 })?;
+                let name = match index {
+                    0 => self.string(&mut block)?,
+                    index => self.entry(index)?.0,
+                };
+                let value = self.string(&mut block)?;
+                if indexing {
+                    self.insert(name.clone(), value.clone());
+                }
+                headers.push((name, value));
+            }
+        }
+        Ok(headers)
+    }
+
+    fn entry(&self, index: usize) -> Result<(String, String), String> {
+        let entry = match index.checked_sub(1) {
+            Some(index) if index < STATIC_TABLE.len() => {
+                let (name, value) = STATIC_TABLE[index];
+                Some((name.to_string(), value.to_string()))
+            }
+            Some(index) => self.table.get(index - STATIC_TABLE.len()).cloned(),
+            None => None,
+        };
+        entry.ok_or_else(|| format!("no header at index {}", index))
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let size = name.len() + value.len() + ENTRY_OVERHEAD;
+        self.evict(size);
+        // An entry bigger than the whole table just empties it
+        if size <= self.max_size {
+            self.table.push_front((name, value));
+            self.size += size;
+        }
+    }
+
+    /// Evicts the oldest entries until there's room for `size` more bytes
+    fn evict(&mut self, size: usize) {
+        while self.size + size > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                break;
+            };
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+
+    fn string(&self, block: &mut &[u8]) -> Result<String, String> {
+        let huffman = block.first().is_some_and(|first| first & 0x80 != 0);
+        let length = decode_integer(block, 7)?;
+        if length > block.len() {
+            return Err("a header string runs past the block".to_string());
+        }
+        let (bytes, rest) = block.split_at(length);
+        *block = rest;
+        let bytes = match huffman {
+            true => self.huffman.decode(bytes)?,
+            false => bytes.to_vec(),
+        };
+        String::from_utf8(bytes).map_err(|_| "a header isn't UTF-8".to_string())
+    }
+}
+
+/// Reads an integer with an `N`-bit prefix from the block's first byte
+fn decode_integer(block: &mut &[u8], prefix: u32) -> Result<usize, String> {
+    let truncated = || "a header block ends in an integer".to_string();
+    let (&first, mut rest) = block.split_first().ok_or_else(truncated)?;
+    let limit = (1 << prefix) - 1;
+    let mut value = usize::from(first) & limit;
+    if value == limit {
+        let mut shift = 0;
+        loop {
+            let (&byte, after) = rest.split_first().ok_or_else(truncated)?;
+            rest = after;
+            if shift > 28 {
+                return Err("a header block has an integer too big".to_string());
+            }
+            value += usize::from(byte & 0x7f) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    *block = rest;
+    Ok(value)
+}
+
+/// The canonical Huffman code, as how many codes there are of each length and the symbols
+/// in code order
+struct Huffman {
+    counts: [u32; LONGEST_CODE + 1],
+    symbols: Vec<usize>,
+}
+
+impl Huffman {
+    fn new() -> Self {
+        let mut counts = [0; LONGEST_CODE + 1];
+        for &length in &CODE_LENGTHS {
+            counts[usize::from(length)] += 1;
+        }
+        let mut symbols: Vec<usize> = (0..CODE_LENGTHS.len()).collect();
+        symbols.sort_by_key(|&symbol| CODE_LENGTHS[symbol]);
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>, String> {
+        let mut decoded = Vec::new();
+        // The code so far, the first code of its length and where that length's symbols
+        // start
+        let (mut code, mut first, mut index, mut length) = (0, 0, 0, 0);
+        let mut ones = true;
+        for byte in bytes {
+            for shift in (0..8).rev() {
+                let bit = u32::from(byte >> shift & 1);
+                code |= bit;
+                ones &= bit == 1;
+                length += 1;
+                let count = self.counts[length];
+                if code < first + count {
+                    match self.symbols[index + (code - first) as usize] {
+                        EOS => return Err("a header string holds EOS".to_string()),
+                        symbol => decoded.push(symbol as u8),
+                    }
+                    (code, first, index, length, ones) = (0, 0, 0, 0, true);
+                } else if length == LONGEST_CODE {
+                    return Err("a header string has an invalid code".to_string());
+                } else {
+                    index += count as usize;
+                    first = (first + count) << 1;
+                    code <<= 1;
+                }
+            }
+        }
+        // Strings are padded out with the start of EOS, which is all ones
+        if length > 7 || !ones {
+            return Err("a header string is badly padded".to_string());
+        }
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(u8::is_ascii_hexdigit).collect();
+        let digits = std::str::from_utf8(&digits).unwrap();
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn pairs(headers: &[(String, String)]) -> Vec<(&str, &str)> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_requests() {
+        // RFC 7541's examples C.4, requests on one connection with Huffman coding
+        let mut decoder = Decoder::default();
+        let first = decoder
+            .decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+            .unwrap();
+        assert_eq!(
+            pairs(&first),
+            [
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]
+        );
+        let second = decoder
+            .decode(&hex("8286 84be 5886 a8eb 1064 9cbf"))
+            .unwrap();
+        assert_eq!(
+            pairs(&second)[3..],
+            [
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache")
+            ]
+        );
+        let third = decoder
+            .decode(&hex(
+                "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf",
+            ))
+            .unwrap();
+        assert_eq!(
+            pairs(&third),
+            [
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ]
+        );
+        assert_eq!(decoder.size, 164);
+
+        assert!(decoder.decode(&hex("80")).is_err());
+        assert!(decoder.decode(&hex("c5")).is_err());
+        assert!(decoder.decode(&hex("4088 25a8")).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let long = "x".repeat(200);
+        let headers = [(":status", "200"), ("grpc-message", long.as_str())];
+        let block = encode(&headers);
+        assert_eq!(&block[..13], b"\0\x07:status\x03200");
+        let decoded = Decoder::default().decode(&block).unwrap();
+        assert_eq!(pairs(&decoded), headers);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::events::{Event, Sink};
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Uploads bigger than this are turned away, about ten minutes of 48 kHz stereo
+const MAX_BODY: usize = 128 << 20;
+/// Longer request and header lines are turned away
+const MAX_LINE: usize = 8 << 10;
+/// Nor may the request line and headers together come to more than this
+const MAX_HEADERS: usize = 64 << 10;
+/// A client that takes longer than this to read an event is dropped
+const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// An HTTP/1.1 request, read whole
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// The query string's `name=value` pairs, as they're written
+    pub query: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        let mut values = self.query.iter().filter(|(key, _)| key == name);
+        values.next().map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads a request and its body, which needs a `Content-Length`
+pub fn read_request<R: BufRead>(mut reader: R) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut line = String::new();
+    let mut headers = 0;
+    // Reads a line of at most MAX_LINE bytes into `line`, returning its length
+    let mut read_line = |reader: &mut R, line: &mut String| {
+        line.clear();
+        let read = reader.take(MAX_LINE as u64).read_line(line)?;
+        headers += read;
+        if read == MAX_LINE && !line.ends_with('\n') {
+            return Err(invalid("a header line is too long"));
+        }
+        if headers > MAX_HEADERS {
+            return Err(invalid("the headers are too big"));
+        }
+        Ok(read)
+    };
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let mut length = 0;
+    loop {
+        if read_line(&mut reader, &mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| invalid("bad Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(invalid("the body is too big"));
+    }
+    // Grown as the body arrives, so a Content-Length alone doesn't take the memory
+    let mut body = Vec::new();
+    reader.take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        body,
+    })
+}
+
+/// Writes a whole response, closing the connection after it
+pub fn respond<W: Write>(
+    mut writer: W,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()
+}
+
+/// Server-sent events to every client subscribed, each a JSON event on a `data:` line
+#[derive(Clone, Default)]
+pub struct EventStream {
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl EventStream {
+    /// Starts the response to a client subscribing, which then gets every event sent
+    pub fn subscribe(&self, mut client: TcpStream) -> io::Result<()> {
+        client.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n",
+        )?;
+        client.set_write_timeout(Some(SEND_TIMEOUT))?;
+        self.clients.lock().unwrap().push(client);
+        Ok(())
+    }
+}
+
+impl Sink for EventStream {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        let message = format!("data: {}\n\n", event.to_json());
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(message.as_bytes()).is_ok());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_read_request() {
+        let request = b"POST /decode?standard=v21&origin HTTP/1.1\r\nHost: x\r\n\
+                        content-length: 3\r\n\r\nabcdef";
+        let request = read_request(&request[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/decode");
+        assert_eq!(request.parameter("standard"), Some("v21"));
+        assert_eq!(request.parameter("origin"), Some(""));
+        assert_eq!(request.parameter("rate"), None);
+        assert_eq!(request.body, b"abc");
+        assert!(read_request(&b"\r\n"[..]).is_err());
+        // A body cut short, and lines and headers too long to be worth reading
+        assert!(read_request(&b"POST / HTTP/1.1\r\nContent-Length: 9\r\n\r\nabc"[..]).is_err());
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE));
+        assert!(read_request(long.as_bytes()).is_err());
+        let many = format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            "X-A: b\r\n".repeat(MAX_HEADERS / 8)
+        );
+        assert!(read_request(many.as_bytes()).is_err());
+
+        let mut response = Vec::new();
+        respond(&mut response, "404 Not Found", "text/plain", b"no").unwrap();
+        assert_eq!(
+            response,
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\
+              Connection: close\r\n\r\nno"
+        );
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut events = EventStream::default();
+        events.subscribe(listener.accept().unwrap().0).unwrap();
+        events.take(&Event::Carrier(true)).unwrap();
+        drop(events);
+
+        let mut received = String::new();
+        client.read_to_string(&mut received).unwrap();
+        assert!(received.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n"));
+        assert!(received.ends_with("\r\n\r\ndata: {\"type\":\"carrier\",\"present\":true}\n\n"));
+    }
+}
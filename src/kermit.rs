@@ -0,0 +1,502 @@
+use std::fmt;
+
+/// Starts every packet
+const MARK: u8 = 0x01;
+/// Smallest LEN of a normal packet, just SEQ, TYPE and a one character check
+const MIN_LEN: u8 = 3;
+
+/// Kermit keeps packets printable by sending small numbers offset into the printable range
+fn tochar(x: u8) -> u8 {
+    x + 32
+}
+
+fn unchar(c: u8) -> u8 {
+    c.wrapping_sub(32)
+}
+
+/// Characters usable as a prefix, i.e. printable but not a digit, letter or space
+fn is_prefix(c: u8) -> bool {
+    (33..=62).contains(&c) || (96..=126).contains(&c)
+}
+
+/// The block check of type 1, 2 or 3 over the packet from LEN up to the check itself
+fn block_check(data: &[u8], check: u8) -> Vec<u8> {
+    let sum: u32 = data.iter().map(|&byte| u32::from(byte)).sum();
+    match check {
+        1 => vec![tochar(((sum + ((sum & 192) >> 6)) & 63) as u8)],
+        2 => vec![tochar((sum >> 6 & 63) as u8), tochar((sum & 63) as u8)],
+        _ => {
+            let crc = crc16(data);
+            vec![
+                tochar((crc >> 12 & 15) as u8),
+                tochar((crc >> 6 & 63) as u8),
+                tochar((crc & 63) as u8),
+            ]
+        }
+    }
+}
+
+/// The reflected CRC-CCITT used by block check 3
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= u16::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// How the sender asked for data to be encoded in its Send-Init packet
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Params {
+    /// Prefixes control characters, sent with bit 6 flipped
+    qctl: u8,
+    /// Prefixes bytes with the eighth bit set, for 7 bit lines
+    qbin: Option<u8>,
+    /// Prefixes a repeat count for the following character
+    rept: Option<u8>,
+    /// Block check type used after the Send-Init exchange
+    check: u8,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            qctl: b'#',
+            qbin: None,
+            rept: None,
+            check: 1,
+        }
+    }
+}
+
+impl Params {
+    /// Reads the fields after MAXL, TIME, NPAD, PADC and EOL. Whether the receiver agreed
+    /// is on the other channel, so the sender's requests are taken as given.
+    fn from_init(data: &[u8]) -> Self {
+        let field = |i: usize| data.get(i).copied().filter(|&c| c != b' ');
+        let defaults = Self::default();
+        Self {
+            qctl: field(5).unwrap_or(defaults.qctl),
+            qbin: field(6).filter(|&c| is_prefix(c)),
+            rept: field(8).filter(|&c| is_prefix(c)),
+            check: match field(7) {
+                Some(c @ b'1'..=b'3') => c - b'0',
+                _ => defaults.check,
+            },
+        }
+    }
+
+    /// Undoes the repeat, eighth bit and control prefixes of a data field
+    fn decode(&self, data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        let mut bytes = data.iter().copied();
+        while let Some(mut c) = bytes.next() {
+            let mut count = 1;
+            if Some(c) == self.rept {
+                count = unchar(bytes.next().unwrap_or(b' '));
+                c = bytes.next().unwrap_or(0);
+            }
+            let mut high = 0;
+            if Some(c) == self.qbin {
+                high = 0x80;
+                c = bytes.next().unwrap_or(0);
+            }
+            if c == self.qctl {
+                c = bytes.next().unwrap_or(0);
+                // Only control characters are flipped, quoted prefixes are sent as they are
+                if (63..=95).contains(&(c & 0x7f)) {
+                    c ^= 64;
+                }
+            }
+            decoded.extend(std::iter::repeat_n(c | high, usize::from(count)));
+        }
+        decoded
+    }
+}
+
+#[derive(Debug)]
+struct Packet<'a> {
+    seq: u8,
+    kind: u8,
+    data: &'a [u8],
+}
+
+/// Parses the packet starting with MARK at `start`, trying the session's block check and
+/// then the others, returning the packet and where it ends. Noise that only looks like a
+/// MARK fails without a problem to report.
+fn parse(bytes: &[u8], start: usize, check: u8) -> Result<(Packet<'_>, usize), Option<Problem>> {
+    let header = bytes
+        .get(start + 1..start + 4)
+        .ok_or(Some(Problem::Truncated))?;
+    let (len, seq, kind) = (unchar(header[0]), unchar(header[1]), header[2]);
+    if !(kind.is_ascii_uppercase() && seq < 64 && (len == 0 || len >= MIN_LEN)) {
+        return Err(None);
+    }
+
+    // Long packets put the length after TYPE, with a check of their own
+    let (data_start, end) = if len == 0 {
+        let extended = bytes
+            .get(start + 1..start + 7)
+            .ok_or(Some(Problem::Truncated))?;
+        if block_check(&extended[..5], 1)[0] != extended[5] {
+            return Err(Some(Problem::BadCheck));
+        }
+        let len = usize::from(unchar(extended[3])) * 95 + usize::from(unchar(extended[4]));
+        (start + 7, start + 7 + len)
+    } else {
+        (start + 4, start + 2 + usize::from(len))
+    };
+    if end > bytes.len() {
+        return Err(Some(Problem::Truncated));
+    }
+
+    let mut checks = vec![check];
+    checks.extend((1..=3).filter(|&other| other != check));
+    for check in checks {
+        let split = end - usize::from(check);
+        if split >= data_start && block_check(&bytes[start + 1..split], check) == bytes[split..end]
+        {
+            let data = &bytes[data_start..split];
+            return Ok((Packet { seq, kind, data }, end));
+        }
+    }
+    Err(Some(Problem::BadCheck))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem {
+    /// The packet failed its block check, so the receiver would have asked for it again
+    BadCheck,
+    /// Packets were skipped, e.g. when a MARK was lost to noise
+    OutOfSequence { expected: u8 },
+    /// The recording ended partway through the packet
+    Truncated,
+}
+
+/// A packet that couldn't be accepted, at its offset into the byte stream
+#[derive(Debug, PartialEq)]
+pub struct PacketError {
+    pub offset: usize,
+    pub problem: Problem,
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Packet at byte {}: ", self.offset)?;
+        match self.problem {
+            Problem::BadCheck => write!(f, "check failed"),
+            Problem::OutOfSequence { expected } => {
+                write!(f, "out of sequence, expected packet {}", expected)
+            }
+            Problem::Truncated => write!(f, "truncated"),
+        }
+    }
+}
+
+/// A file sent during a Kermit session
+#[derive(Debug, Default, PartialEq)]
+pub struct File {
+    pub name: String,
+    pub data: Vec<u8>,
+    /// Whether the sender finished the file with an EOF packet
+    pub complete: bool,
+    /// Whether the EOF packet told the receiver to throw the file away
+    pub discarded: bool,
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "File \"{}\" ({} bytes)", self.name, self.data.len())?;
+        if self.discarded {
+            write!(f, ", discarded by the sender")
+        } else if !self.complete {
+            write!(f, ", incomplete")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The files reassembled from the sending side of a Kermit session
+#[derive(Debug, Default, PartialEq)]
+pub struct Session {
+    pub files: Vec<File>,
+    /// Packets accepted
+    pub packets: usize,
+    /// Copies of packets already accepted, resent because an ACK was lost
+    pub duplicates: usize,
+    pub errors: Vec<PacketError>,
+    /// Whether the sender ended the session with a Break packet
+    pub complete: bool,
+    /// The message of an Error packet that aborted the session
+    pub aborted: Option<String>,
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Kermit: {} files, {} packets, {} duplicates, {} packet errors",
+            self.files.len(),
+            self.packets,
+            self.duplicates,
+            self.errors.len()
+        )?;
+        match &self.aborted {
+            Some(message) => write!(f, ", aborted: {}", message),
+            None if self.complete => write!(f, ", ended with Break"),
+            None => write!(f, ", incomplete without Break"),
+        }
+    }
+}
+
+/// Reassembles the files from the bytes sent by a Kermit sender. Kermit is often run over
+/// 7 bit lines with parity, so if nothing checks out as 8 bit data the eighth bit is
+/// stripped and the bytes are read again.
+pub fn receive(bytes: &[u8]) -> Session {
+    let session = receive_bytes(bytes);
+    if session.packets > 0 {
+        return session;
+    }
+    let stripped: Vec<u8> = bytes.iter().map(|byte| byte & 0x7f).collect();
+    let stripped_session = receive_bytes(&stripped);
+    if stripped_session.packets > 0 {
+        stripped_session
+    } else {
+        session
+    }
+}
+
+fn receive_bytes(bytes: &[u8]) -> Session {
+    let mut session = Session::default();
+    let mut params = Params::default();
+    let mut expected = 0;
+    let mut last = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != MARK {
+            i += 1;
+            continue;
+        }
+        let (packet, end) = match parse(bytes, i, params.check) {
+            Ok(parsed) => parsed,
+            Err(None) => {
+                i += 1;
+                continue;
+            }
+            Err(Some(problem)) => {
+                session.errors.push(PacketError { offset: i, problem });
+                if problem == Problem::Truncated {
+                    break;
+                }
+                // The length may be what was corrupted, so look for the next MARK
+                i += 1;
+                continue;
+            }
+        };
+
+        // ACKs and NAKs belong to the receiver, on the other channel of a clean recording
+        if packet.kind == b'Y' || packet.kind == b'N' {
+            i = end;
+            continue;
+        }
+        if last == Some(packet.seq) {
+            session.duplicates += 1;
+            i = end;
+            continue;
+        }
+        if packet.seq != expected {
+            session.errors.push(PacketError {
+                offset: i,
+                problem: Problem::OutOfSequence { expected },
+            });
+        }
+        session.packets += 1;
+        last = Some(packet.seq);
+        expected = (packet.seq + 1) % 64;
+        i = end;
+
+        match packet.kind {
+            b'S' => params = Params::from_init(packet.data),
+            b'F' => session.files.push(File {
+                name: String::from_utf8_lossy(&params.decode(packet.data)).into_owned(),
+                ..File::default()
+            }),
+            b'D' => {
+                if session.files.is_empty() {
+                    session.files.push(File::default());
+                }
+                let file = session.files.last_mut().unwrap();
+                file.data.extend(params.decode(packet.data));
+            }
+            b'Z' => {
+                if let Some(file) = session.files.last_mut() {
+                    file.complete = true;
+                    file.discarded = packet.data == b"D";
+                }
+            }
+            b'B' => {
+                session.complete = true;
+                break;
+            }
+            b'E' => {
+                let message = params.decode(packet.data);
+                session.aborted = Some(String::from_utf8_lossy(&message).into_owned());
+                break;
+            }
+            // Attributes, text headers and server commands don't carry file data
+            _ => {}
+        }
+    }
+    session
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(seq: u8, kind: u8, data: &[u8], check: u8) -> Vec<u8> {
+        let mut packet = vec![
+            MARK,
+            tochar(data.len() as u8 + 2 + check),
+            tochar(seq),
+            kind,
+        ];
+        packet.extend(data);
+        let check = block_check(&packet[1..], check);
+        packet.extend(check);
+        packet.push(b'\r');
+        packet
+    }
+
+    fn long_packet(seq: u8, kind: u8, data: &[u8], check: u8) -> Vec<u8> {
+        let len = data.len() + usize::from(check);
+        let mut packet = vec![
+            MARK,
+            tochar(0),
+            tochar(seq),
+            kind,
+            tochar((len / 95) as u8),
+            tochar((len % 95) as u8),
+        ];
+        packet.extend(block_check(&packet[1..], 1));
+        packet.extend(data);
+        let check = block_check(&packet[1..], check);
+        packet.extend(check);
+        packet
+    }
+
+    /// Control and eighth bit prefixing, without repeat counts
+    fn encode(data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        for &byte in data {
+            let mut c = byte & 0x7f;
+            if byte & 0x80 != 0 {
+                encoded.push(b'&');
+            }
+            if c < 32 || c == 127 {
+                encoded.push(b'#');
+                c ^= 64;
+            } else if c == b'#' || c == b'&' || c == b'~' {
+                encoded.push(b'#');
+            }
+            encoded.push(c);
+        }
+        encoded
+    }
+
+    #[test]
+    fn test_block_checks() {
+        // A NAK for packet 0, as in the Kermit protocol manual
+        assert_eq!(block_check(b"# N", 1), b"3");
+        assert_eq!(block_check(b"# N", 2), b"\"1");
+        assert_eq!(crc16(b"123456789"), 0x2189);
+    }
+
+    #[test]
+    fn test_receive_session() {
+        let text = b"Hello\r\nKermit # & ~ AAAAAAAA\r\n".repeat(10);
+        let binary: Vec<u8> = (0..=255).collect();
+
+        let mut bytes = b"garbage\x01\x7f".to_vec();
+        bytes.extend(packet(0, b'S', b"~* @-#&3~", 1));
+        bytes.extend(packet(1, b'F', b"README.TXT", 3));
+        bytes.extend(long_packet(2, b'D', &encode(&text), 3));
+        bytes.extend(packet(3, b'Z', b"", 3));
+        bytes.extend(packet(4, b'F', b"DATA.BIN", 3));
+        let mut seq = 5;
+        for chunk in binary.chunks(20) {
+            bytes.extend(packet(seq, b'D', &encode(chunk), 3));
+            seq += 1;
+        }
+        // A repeat count of 10 for the final byte
+        bytes.extend(packet(seq, b'D', b"~*&#?", 3));
+        bytes.extend(packet(seq + 1, b'Z', b"", 3));
+        bytes.extend(packet(seq + 2, b'B', b"", 3));
+
+        let session = receive(&bytes);
+        assert!(session.complete, "{}", session);
+        assert!(session.errors.is_empty(), "{:?}", session.errors);
+        assert_eq!(session.files.len(), 2);
+        assert_eq!(session.files[0].name, "README.TXT");
+        assert_eq!(session.files[0].data, text);
+        let mut expected = binary;
+        expected.extend(vec![0xff; 10]);
+        assert_eq!(session.files[1].data, expected);
+        assert_eq!(
+            session.to_string(),
+            "Kermit: 2 files, 21 packets, 0 duplicates, 0 packet errors, ended with Break"
+        );
+        assert_eq!(
+            session.files[1].to_string(),
+            "File \"DATA.BIN\" (266 bytes)"
+        );
+
+        // The same session sent with even parity on a 7 bit line
+        let parity: Vec<u8> = bytes
+            .iter()
+            .map(|&byte| byte | ((byte.count_ones() as u8 & 1) << 7))
+            .collect();
+        assert_eq!(receive(&parity).files, session.files);
+    }
+
+    #[test]
+    fn test_receive_with_errors() {
+        let mut bytes = packet(0, b'S', b"~* @-#", 1);
+        bytes.extend(packet(1, b'F', b"LOG", 1));
+        let mut corrupted = packet(2, b'D', b"first ", 1);
+        corrupted[6] ^= 1;
+        bytes.extend(corrupted);
+        bytes.extend(packet(2, b'D', b"first ", 1));
+        bytes.extend(packet(2, b'D', b"first ", 1));
+        // Packet 3 is lost entirely
+        bytes.extend(packet(4, b'D', b"third", 1));
+        bytes.extend(packet(5, b'E', b"Disk full", 1));
+
+        let session = receive(&bytes);
+        assert_eq!(session.packets, 5);
+        assert_eq!(session.duplicates, 1);
+        let problems: Vec<Problem> = session.errors.iter().map(|error| error.problem).collect();
+        assert_eq!(
+            problems,
+            vec![Problem::BadCheck, Problem::OutOfSequence { expected: 3 }]
+        );
+        assert_eq!(
+            session.errors[0].to_string(),
+            "Packet at byte 21: check failed"
+        );
+        assert_eq!(session.files[0].data, b"first third");
+        assert_eq!(
+            session.files[0].to_string(),
+            "File \"LOG\" (11 bytes), incomplete"
+        );
+        assert_eq!(session.aborted.as_deref(), Some("Disk full"));
+    }
+}
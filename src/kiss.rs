@@ -0,0 +1,183 @@
+use std::io::{self, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Frame end, which also starts a frame
+const FEND: u8 = 0xc0;
+/// Frame escape, so FEND and FESC can appear in a frame
+const FESC: u8 = 0xdb;
+/// Transposed frame end and escape, which follow FESC
+const TFEND: u8 = 0xdc;
+const TFESC: u8 = 0xdd;
+/// Command numbers, in a frame's first byte below its port number
+const DATA: u8 = 0x00;
+const TX_DELAY: u8 = 0x01;
+/// KISS frames needn't be bigger than an AX.25 frame, so longer ones are dropped
+const MAX_FRAME: usize = 1024;
+
+/// What a KISS client asks of the TNC. Persistence, slot time and the rest are for radios
+/// sharing a channel, and are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// An AX.25 frame to transmit, without its FCS
+    Data(Vec<u8>),
+    /// How long to key the transmitter before sending data, in 10 ms units
+    TxDelay(u8),
+}
+
+/// A data frame on port 0 holding an AX.25 frame
+pub fn encode(frame: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![FEND, DATA];
+    for &byte in frame {
+        match byte {
+            FEND => encoded.extend_from_slice(&[FESC, TFEND]),
+            FESC => encoded.extend_from_slice(&[FESC, TFESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(FEND);
+    encoded
+}
+
+/// Unescapes frames from the bytes a client sends
+#[derive(Debug, Default)]
+pub struct Decoder {
+    frame: Vec<u8>,
+    escaped: bool,
+}
+
+impl Decoder {
+    /// Takes the next byte, returning the command a frame holds once it ends
+    pub fn push(&mut self, byte: u8) -> Option<Command> {
+        match (byte, self.escaped) {
+            (FEND, _) => {
+                self.escaped = false;
+                let frame = std::mem::take(&mut self.frame);
+                return parse(&frame);
+            }
+            (FESC, false) => self.escaped = true,
+            (byte, escaped) => {
+                self.escaped = false;
+                self.frame.push(match (byte, escaped) {
+                    (TFEND, true) => FEND,
+                    (TFESC, true) => FESC,
+                    (byte, _) => byte,
+                });
+                if self.frame.len() > MAX_FRAME {
+                    self.frame.clear();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The command of a whole unescaped frame, for any port as the TNC has only one
+fn parse(frame: &[u8]) -> Option<Command> {
+    let (&kind, rest) = frame.split_first()?;
+    match (kind & 0x0f, rest) {
+        (DATA, frame) if !frame.is_empty() => Some(Command::Data(frame.to_vec())),
+        (TX_DELAY, &[delay]) => Some(Command::TxDelay(delay)),
+        _ => None,
+    }
+}
+
+/// A KISS TNC over TCP, sending each frame received to every client connected and passing
+/// on what they send
+#[derive(Clone)]
+pub struct Server {
+    address: SocketAddr,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Server {
+    /// Listens on `host:port`, or all interfaces for `:port`, accepting clients in the
+    /// background and handing their commands to `commands`
+    pub fn listen(address: &str, commands: Sender<Command>) -> io::Result<Self> {
+        let address = match address.strip_prefix(':') {
+            Some(port) => format!("0.0.0.0:{}", port),
+            None => address.to_string(),
+        };
+        let listener = TcpListener::bind(address)?;
+        let server = Self {
+            address: listener.local_addr()?,
+            clients: Arc::default(),
+        };
+        let clients = Arc::clone(&server.clients);
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let Ok(reader) = client.try_clone() else {
+                    continue;
+                };
+                clients.lock().unwrap().push(client);
+                let commands = commands.clone();
+                thread::spawn(move || {
+                    let mut decoder = Decoder::default();
+                    for byte in BufReader::new(reader).bytes() {
+                        let Ok(byte) = byte else { break };
+                        if let Some(command) = decoder.push(byte) {
+                            let _ = commands.send(command);
+                        }
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends a frame received to the clients, dropping the ones that have gone
+    pub fn send(&self, frame: &[u8]) {
+        let encoded = encode(frame);
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(&encoded).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frames() {
+        let frame = [0x82, FEND, 0x40, FESC];
+        let encoded = encode(&frame);
+        assert_eq!(
+            encoded,
+            [FEND, 0, 0x82, FESC, TFEND, 0x40, FESC, TFESC, FEND]
+        );
+
+        let mut decoder = Decoder::default();
+        let commands: Vec<_> = [FEND, 0x01, 30, FEND, 0x05, 1, FEND]
+            .iter()
+            .chain(&encoded)
+            .filter_map(|&byte| decoder.push(byte))
+            .collect();
+        assert_eq!(
+            commands,
+            [Command::TxDelay(30), Command::Data(frame.to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_server() {
+        let (sender, commands) = mpsc::channel();
+        let server = Server::listen("127.0.0.1:0", sender).unwrap();
+        let mut client = TcpStream::connect(server.local_address()).unwrap();
+        client.write_all(&encode(b"out")).unwrap();
+        let sent = commands.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(sent, Command::Data(b"out".to_vec()));
+
+        server.send(b"in");
+        let mut received = [0; 5];
+        client.read_exact(&mut received).unwrap();
+        assert_eq!(received, [FEND, 0, b'i', b'n', FEND]);
+    }
+}
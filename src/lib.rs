@@ -0,0 +1,78 @@
+pub mod analyze;
+pub mod assembly;
+pub mod audio;
+pub mod ax25;
+pub mod backend;
+pub mod baudot;
+pub mod calibrate;
+pub mod callerid;
+pub mod carrier;
+pub mod cassette;
+pub mod clock;
+pub mod control;
+pub mod cw;
+pub mod daemon;
+pub mod dbus;
+pub mod demodulator;
+pub mod detect;
+pub mod dpsk;
+pub mod dtmf;
+pub mod dtmfdata;
+pub mod events;
+pub mod exec;
+pub mod ffi;
+pub mod filter;
+pub mod forward;
+pub mod framing;
+pub mod goertzel;
+pub mod grpc;
+pub mod gui;
+pub mod hayes;
+pub mod hdlc;
+pub mod hpack;
+pub mod http;
+pub mod kermit;
+pub mod kiss;
+#[cfg(feature = "live")]
+pub mod live;
+pub mod metrics;
+pub mod mfsk;
+pub mod minimodem;
+pub mod modulator;
+pub mod mqtt;
+pub mod navtex;
+pub mod net;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod notify;
+pub mod paging;
+pub mod pipeline;
+pub mod png;
+pub mod pocsag;
+pub mod progress;
+pub mod protobuf;
+pub mod ptt;
+#[cfg(unix)]
+pub mod pty;
+pub mod punter;
+pub mod resample;
+pub mod rigctld;
+pub mod ring;
+pub mod rtltcp;
+pub mod rtty;
+pub mod same;
+pub mod sdr;
+pub mod selcall;
+pub mod serial;
+pub mod slip;
+pub mod spectrogram;
+pub mod spectrum;
+pub mod standard;
+pub mod tdd;
+pub mod timeline;
+pub mod tui;
+pub mod tun;
+pub mod ukhas;
+pub mod verify;
+pub mod websocket;
+pub mod xmodem;
@@ -0,0 +1,462 @@
+use crate::resample::Resampler;
+use crate::ring::{self, RingReader, RingWriter};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{Device, SampleFormat, SampleRate, Stream, StreamConfig, SupportedStreamConfig};
+use std::cell::Cell;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// Playback waits this long after the last sample is queued for the device's buffer to drain
+const DRAIN: Duration = Duration::from_millis(250);
+
+/// Seconds of captured audio held for decoding before what doesn't fit is dropped, rather than
+/// keeping the sound card waiting
+const CAPTURE_SECONDS: f64 = 4.0;
+/// Most silence put in for audio that was dropped, in seconds, enough to end a character at
+/// slow RTTY rates but not so much that catching up takes long
+const GAP_FILL: f64 = 0.25;
+/// Seconds of audio the capture callback's buffers have room for, more than any sound card's
+/// period, so they're never grown once capture's started
+const SCRATCH_SECONDS: f64 = 1.0;
+/// How long decoding waits for more audio when it's taken all there is
+const POLL: Duration = Duration::from_millis(5);
+
+/// Most audio a [`Monitor`] holds back, in seconds, beyond which what's handed to it is
+/// dropped until playback catches up, as it must with an output clock running slower than
+/// the input's
+const MONITOR_LATENCY: f64 = 0.5;
+/// Samples an output callback takes from its ring at a time, in a buffer allocated before
+/// playback starts
+const PLAYBACK_BUFFER: usize = 1024;
+
+/// Lists the input and output devices, marking the defaults
+pub fn describe_devices() -> Result<String, String> {
+    let host = cpal::default_host();
+    let name = |device: Option<Device>| device.and_then(|device| device.name().ok());
+    let mut description = String::new();
+    for (heading, devices, default) in [
+        (
+            "Input devices",
+            host.input_devices(),
+            name(host.default_input_device()),
+        ),
+        (
+            "Output devices",
+            host.output_devices(),
+            name(host.default_output_device()),
+        ),
+    ] {
+        description.push_str(heading);
+        description.push_str(":\n");
+        for device in devices.map_err(|e| format!("Couldn't list audio devices: {}", e))? {
+            let device = name(Some(device)).unwrap_or_default();
+            let marker = if Some(&device) == default.as_ref() {
+                " (default)"
+            } else {
+                ""
+            };
+            description.push_str(&format!("  {}{}\n", device, marker));
+        }
+    }
+    Ok(description)
+}
+
+/// Finds a device by name, or the default one
+fn find_device(name: Option<&str>, input: bool) -> Result<Device, String> {
+    let host = cpal::default_host();
+    let kind = if input { "input" } else { "output" };
+    let devices = if input {
+        host.input_devices()
+    } else {
+        host.output_devices()
+    };
+    let device = match name {
+        None if input => host.default_input_device(),
+        None => host.default_output_device(),
+        Some(name) => devices
+            .map_err(|e| format!("Couldn't list audio devices: {}", e))?
+            .find(|device| device.name().ok().as_deref() == Some(name)),
+    };
+    device.ok_or_else(|| match name {
+        Some(name) => format!(
+            "No audio {} device named `{}`, see --list_devices",
+            kind, name
+        ),
+        None => format!("No audio {} device found", kind),
+    })
+}
+
+/// Picks 16-bit or float samples at `sampling_rate` if the device supports it, otherwise
+/// its default rate, to be resampled
+fn negotiate(
+    device: &Device,
+    sampling_rate: u32,
+    input: bool,
+) -> Result<SupportedStreamConfig, String> {
+    let name = device.name().unwrap_or_else(|_| "the audio device".into());
+    let failed = |e: &dyn std::fmt::Display| format!("Couldn't query {}: {}", name, e);
+    let (ranges, default) = if input {
+        (
+            device
+                .supported_input_configs()
+                .map_err(|e| failed(&e))?
+                .collect::<Vec<_>>(),
+            device.default_input_config().map_err(|e| failed(&e))?,
+        )
+    } else {
+        (
+            device
+                .supported_output_configs()
+                .map_err(|e| failed(&e))?
+                .collect::<Vec<_>>(),
+            device.default_output_config().map_err(|e| failed(&e))?,
+        )
+    };
+    let usable = |format: SampleFormat| [SampleFormat::I16, SampleFormat::F32].contains(&format);
+    [SampleFormat::I16, SampleFormat::F32]
+        .iter()
+        .find_map(|&format| {
+            ranges.iter().find(|range| {
+                range.sample_format() == format
+                    && (range.min_sample_rate().0..=range.max_sample_rate().0)
+                        .contains(&sampling_rate)
+            })
+        })
+        .map(|range| range.with_sample_rate(SampleRate(sampling_rate)))
+        .or_else(|| Some(default.clone()).filter(|config| usable(config.sample_format())))
+        .or_else(|| {
+            ranges
+                .iter()
+                .find(|range| usable(range.sample_format()))
+                .map(|range| {
+                    let rate = default.sample_rate();
+                    range.with_sample_rate(
+                        rate.clamp(range.min_sample_rate(), range.max_sample_rate()),
+                    )
+                })
+        })
+        .ok_or_else(|| format!("{} doesn't take 16-bit or float samples", name))
+}
+
+/// Audio being captured from an input device. Capture stops when this is dropped.
+pub struct Capture {
+    _stream: Stream,
+    samples: RingReader,
+    sampling_rate: u32,
+    dropped: Cell<u64>,
+}
+
+impl Capture {
+    /// Blocks of mono samples at the rate asked for, in the order they were recorded, as they
+    /// arrive. Where decoding fell so far behind that audio was dropped, silence as long as
+    /// what was lost is put in, up to a quarter of a second, so a character it cut short ends
+    /// there instead of taking its bits from whatever was heard next.
+    pub fn blocks(&self) -> impl Iterator<Item = Vec<i16>> + '_ {
+        let fill = (f64::from(self.sampling_rate) * GAP_FILL) as u64;
+        let mut gap = None;
+        std::iter::from_fn(move || loop {
+            if let Some(dropped) = gap.take() {
+                return Some(vec![0; fill.min(dropped) as usize]);
+            }
+            let closed = self.samples.closed();
+            let mut block = Vec::new();
+            let taken = self.samples.take(&mut block);
+            if taken.dropped > 0 {
+                self.dropped.set(self.dropped.get() + taken.dropped);
+                gap = Some(taken.dropped);
+            }
+            if !block.is_empty() {
+                return Some(block);
+            } else if gap.is_none() {
+                if closed {
+                    return None;
+                }
+                thread::sleep(POLL);
+            }
+        })
+    }
+
+    /// How many samples have been dropped because decoding fell behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.get()
+    }
+
+    pub fn sampling_rate(&self) -> u32 {
+        self.sampling_rate
+    }
+}
+
+/// Starts capturing from the named input device, or the default one, at `sampling_rate`,
+/// keeping only the first channel of a multi-channel device
+pub fn capture(device: Option<&str>, sampling_rate: u32) -> Result<Capture, String> {
+    let device = find_device(device, true)?;
+    let supported = negotiate(&device, sampling_rate, true)?;
+    record(device, supported, sampling_rate)
+}
+
+/// Starts capturing what the system is playing, from the monitor of the default output or
+/// the named PulseAudio or PipeWire source
+#[cfg(target_os = "linux")]
+pub fn capture_output(source: Option<&str>, sampling_rate: u32) -> Result<Capture, String> {
+    // The PulseAudio plugin for ALSA records from whichever source PULSE_SOURCE names, and
+    // both PulseAudio and PipeWire call the monitor of the default output @DEFAULT_MONITOR@
+    std::env::set_var("PULSE_SOURCE", source.unwrap_or("@DEFAULT_MONITOR@"));
+    let device = find_device(Some("pulse"), true).map_err(|_| {
+        "Capturing the system output needs PulseAudio or PipeWire, and there's no `pulse` \
+         audio device"
+            .to_string()
+    })?;
+    let supported = negotiate(&device, sampling_rate, true)?;
+    record(device, supported, sampling_rate)
+}
+
+/// Starts capturing what the named output device, or the default one, is playing
+#[cfg(target_os = "windows")]
+pub fn capture_output(device: Option<&str>, sampling_rate: u32) -> Result<Capture, String> {
+    // WASAPI records what an output device plays when it's opened for input
+    let device = find_device(device, false)?;
+    let supported = negotiate(&device, sampling_rate, false)?;
+    record(device, supported, sampling_rate)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+pub fn capture_output(_: Option<&str>, _: u32) -> Result<Capture, String> {
+    Err(
+        "There's no way to capture the system output here without a loopback device, such as \
+         BlackHole on macOS, so pick one with --device instead of --loopback"
+            .into(),
+    )
+}
+
+/// What a capture callback does with each period: takes the first channel, resamples it and
+/// writes it to the ring, in buffers allocated before capture starts
+struct ToRing {
+    writer: RingWriter,
+    resampler: Resampler,
+    mono: Vec<i16>,
+    resampled: Vec<i16>,
+}
+
+impl ToRing {
+    fn write(&mut self, samples: &mut dyn Iterator<Item = i16>) {
+        self.mono.clear();
+        self.mono.extend(samples);
+        self.resampler.process_into(&self.mono, &mut self.resampled);
+        self.writer.write(&self.resampled);
+    }
+}
+
+/// Starts capturing from a device already negotiated
+fn record(
+    device: Device,
+    supported: SupportedStreamConfig,
+    sampling_rate: u32,
+) -> Result<Capture, String> {
+    let name = device.name().unwrap_or_else(|_| "the input device".into());
+    let format = supported.sample_format();
+    let config: StreamConfig = supported.into();
+    if config.sample_rate.0 != sampling_rate {
+        eprintln!(
+            "{} records at {} Hz, resampling to {} Hz",
+            name, config.sample_rate.0, sampling_rate
+        );
+    }
+    let channels = usize::from(config.channels);
+    let resampler = Resampler::new(f64::from(config.sample_rate.0), f64::from(sampling_rate));
+
+    let (writer, samples) = ring::ring((f64::from(sampling_rate) * CAPTURE_SECONDS) as usize);
+    let mut to_ring = ToRing {
+        writer,
+        resampler,
+        mono: Vec::with_capacity((f64::from(config.sample_rate.0) * SCRATCH_SECONDS) as usize),
+        resampled: Vec::with_capacity((f64::from(sampling_rate) * SCRATCH_SECONDS) as usize + 1),
+    };
+    let mut send = move |samples: &mut dyn Iterator<Item = i16>| to_ring.write(samples);
+    let error = |e| eprintln!("Audio input error: {}", e);
+    let stream = match format {
+        SampleFormat::I16 => device.build_input_stream(
+            &config,
+            move |data: &[i16], _: &_| send(&mut data.iter().step_by(channels).copied()),
+            error,
+            None,
+        ),
+        _ => device.build_input_stream(
+            &config,
+            move |data: &[f32], _: &_| {
+                let samples = data.iter().step_by(channels);
+                send(&mut samples.map(|&s| (s * 32_767.0) as i16))
+            },
+            error,
+            None,
+        ),
+    }
+    .map_err(|e| format!("Couldn't open {}: {}", name, e))?;
+    stream
+        .play()
+        .map_err(|e| format!("Couldn't start {}: {}", name, e))?;
+    Ok(Capture {
+        _stream: stream,
+        samples,
+        sampling_rate,
+        dropped: Cell::new(0),
+    })
+}
+
+/// An output device opened at the rate asked for, or its own rate when it won't run at that
+pub struct Output {
+    device: Device,
+    name: String,
+    config: StreamConfig,
+    format: SampleFormat,
+}
+
+impl Output {
+    /// Opens the named output device, or the default one
+    pub fn open(device: Option<&str>, sampling_rate: u32) -> Result<Self, String> {
+        let device = find_device(device, false)?;
+        let name = device.name().unwrap_or_else(|_| "the output device".into());
+        let supported = negotiate(&device, sampling_rate, false)?;
+        Ok(Self {
+            device,
+            name,
+            format: supported.sample_format(),
+            config: supported.into(),
+        })
+    }
+
+    pub fn sampling_rate(&self) -> u32 {
+        self.config.sample_rate.0
+    }
+
+    /// Starts playing mono samples pulled from `next` on every channel, until the returned
+    /// stream is dropped
+    pub fn start<F>(self, mut next: F) -> Result<Stream, String>
+    where
+        F: FnMut() -> i16 + Send + 'static,
+    {
+        let channels = usize::from(self.config.channels);
+        let error = |e| eprintln!("Audio output error: {}", e);
+        let stream = match self.format {
+            SampleFormat::I16 => self.device.build_output_stream(
+                &self.config,
+                move |data: &mut [i16], _: &_| {
+                    for frame in data.chunks_mut(channels) {
+                        let sample = next();
+                        frame.iter_mut().for_each(|s| *s = sample);
+                    }
+                },
+                error,
+                None,
+            ),
+            _ => self.device.build_output_stream(
+                &self.config,
+                move |data: &mut [f32], _: &_| {
+                    for frame in data.chunks_mut(channels) {
+                        let sample = f32::from(next()) / 32_768.0;
+                        frame.iter_mut().for_each(|s| *s = sample);
+                    }
+                },
+                error,
+                None,
+            ),
+        }
+        .map_err(|e| format!("Couldn't open {}: {}", self.name, e))?;
+        stream
+            .play()
+            .map_err(|e| format!("Couldn't start {}: {}", self.name, e))?;
+        Ok(stream)
+    }
+
+    /// Starts playing what's written to the returned ring, which holds up to `capacity`
+    /// samples, and silence whenever it's empty. The callback takes samples from the ring
+    /// into a buffer made here, so it neither locks nor allocates.
+    pub fn start_ring(self, capacity: usize) -> Result<(Stream, RingWriter), String> {
+        let (writer, reader) = ring::ring(capacity);
+        let mut from_ring = FromRing {
+            reader,
+            buffer: vec![0; PLAYBACK_BUFFER].into_boxed_slice(),
+            next: 0,
+            len: 0,
+        };
+        let stream = self.start(move || from_ring.next())?;
+        Ok((stream, writer))
+    }
+}
+
+/// What an output callback plays from a ring: what's been written, a buffer at a time, or
+/// silence when there's nothing waiting
+struct FromRing {
+    reader: RingReader,
+    buffer: Box<[i16]>,
+    next: usize,
+    len: usize,
+}
+
+impl FromRing {
+    fn next(&mut self) -> i16 {
+        if self.next == self.len {
+            self.len = self.reader.take_into(&mut self.buffer).samples;
+            self.next = 0;
+            if self.len == 0 {
+                return 0;
+            }
+        }
+        self.next += 1;
+        self.buffer[self.next - 1]
+    }
+}
+
+/// Plays mono samples through the named output device, or the default one, returning once
+/// they've been heard
+pub fn play(device: Option<&str>, samples: &[i16], sampling_rate: u32) -> Result<(), String> {
+    let output = Output::open(device, sampling_rate)?;
+    let name = output.name.clone();
+    let mut samples = Resampler::new(f64::from(sampling_rate), f64::from(output.sampling_rate()))
+        .process(samples)
+        .into_iter();
+    let (done, finished) = mpsc::channel();
+    // Silence follows once every sample has been queued
+    let _stream = output.start(move || {
+        samples.next().unwrap_or_else(|| {
+            let _ = done.send(());
+            0
+        })
+    })?;
+    finished
+        .recv()
+        .map_err(|_| format!("{} stopped playing", name))?;
+    thread::sleep(DRAIN);
+    Ok(())
+}
+
+/// Plays audio through an output device as it's handed over, so what's being decoded can be
+/// heard. Silence fills in when nothing's waiting, and playback stops when this is dropped.
+pub struct Monitor {
+    _stream: Stream,
+    playing: RingWriter,
+    resampler: Resampler,
+    resampled: Vec<i16>,
+}
+
+/// Opens the named output device, or the default one, to monitor audio at `sampling_rate`
+pub fn monitor(device: Option<&str>, sampling_rate: u32) -> Result<Monitor, String> {
+    let output = Output::open(device, sampling_rate)?;
+    let rate = output.sampling_rate();
+    let (stream, playing) = output.start_ring((f64::from(rate) * MONITOR_LATENCY) as usize)?;
+    Ok(Monitor {
+        _stream: stream,
+        playing,
+        resampler: Resampler::new(f64::from(sampling_rate), f64::from(rate)),
+        resampled: Vec::new(),
+    })
+}
+
+impl Monitor {
+    /// Queues samples at the rate the monitor was opened for, dropping them if playback's
+    /// fallen too far behind
+    pub fn play(&mut self, samples: &[i16]) {
+        self.resampler.process_into(samples, &mut self.resampled);
+        self.playing.write(&self.resampled);
+    }
+}
@@ -1,23 +1,684 @@
-use hound;
-use std::f64::consts::PI;
+use assembly::{Assembler, MessageWriter};
+use audio::{ChannelPick, Endian, InputGain, Layout, Mixdown, PcmFormat, RawSpec};
+use backend::{Backend, Goertzel, Registry};
+#[cfg(all(feature = "live", unix))]
+use bell103_demodulator::pty;
+use bell103_demodulator::{
+    analyze, assembly, audio, ax25, backend, calibrate, callerid, carrier, cassette, control, cw,
+    daemon, dbus, demodulator, detect, dpsk, dtmf, dtmfdata, events, exec, filter, forward,
+    framing, grpc, http, kermit, metrics, mfsk, minimodem, modulator, mqtt, navtex, net, notify,
+    paging, pipeline, pocsag, progress, protobuf, ptt, punter, rigctld, rtltcp, rtty, same, sdr,
+    selcall, serial, spectrogram, standard, tdd, timeline, tui, ukhas, verify, websocket, xmodem,
+};
+#[cfg(feature = "live")]
+use bell103_demodulator::{hayes, kiss, live, slip, tun};
+use cassette::TapeFormat;
+use control::Control;
+use cw::CwGenerator;
+use daemon::Signal;
+use demodulator::Demodulator;
+use detect::Identified;
+use dtmf::{DtmfDetector, DtmfGenerator};
+use dtmfdata::{Convention, DigitMap, Message};
+use events::{Event, SignalWatch, Sink};
+use exec::Exec;
+use forward::{Forward, Forwarder};
+use framing::{Framing, Preamble, StreamReceiver};
+#[cfg(feature = "live")]
+use hayes::Hayes;
+use http::EventStream;
+use metrics::Metrics;
+use mfsk::{MfskDemodulator, ToneGrid};
+use minimodem::Mode;
+use modulator::FskModulator;
+use mqtt::MqttTarget;
+use net::{Listen, Transport};
+use notify::{Notifier, Notify};
+use paging::{PageDetector, TonePlan};
+use progress::ToneDetector;
+use ptt::{CommandPtt, GpioLine, GpioPtt, Ptt, SerialLine, SerialPtt};
+use sdr::{FmReceiver, IqFormat};
+use selcall::SelcallDecoder;
+#[cfg(feature = "live")]
+use serial::Bridge;
+use serial::{SerialTarget, Tee};
+use standard::{Channel, Selection, Standard};
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, Cursor, Read, Write};
+#[cfg(feature = "live")]
+use std::net::ToSocketAddrs;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+#[cfg(feature = "live")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "live")]
+use std::time::Instant;
+use structopt::clap::{Error, ErrorKind};
 use structopt::StructOpt;
+use timeline::Timeline;
+use tui::Tui;
+#[cfg(feature = "live")]
+use tun::Tun;
 
-const ORIG_MARK_FREQUENCY: f64 = 1270.0;
-const ORIG_SPACE_FREQUENCY: f64 = 1070.0;
-const ANS_MARK_FREQUENCY: f64 = 2225.0;
-const ANS_SPACE_FREQUENCY: f64 = 2025.0;
+/// Speed and pitch of the identifier appended by `encode --cw_id`
+const CW_ID_WPM: f64 = 20.0;
+const CW_ID_TONE: f64 = 700.0;
+/// Where `--notify` emails come from unless `--notify_from` says
+const NOTIFY_FROM: &str = "bell103_demodulator@localhost";
+/// Frames decoded at a time from `--listen`, small so characters print soon after they're sent
+const LISTEN_FRAMES: usize = 1024;
+/// IQ samples demodulated at a time for `--iq`, a few milliseconds at SDR rates
+const IQ_FRAMES: usize = 65_536;
+/// Chunks of a recording read ahead of decoding, so a slow disk and the decoder overlap
+const READ_AHEAD: usize = 4;
+/// Pieces of decoded text waiting to be written, so slow output doesn't hold up decoding
+/// until this many are behind
+const OUTPUT_QUEUE: usize = 1024;
+/// How often a daemon waiting for audio looks for a signal to reload or shut down
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long `aprs --kiss` keys up before a packet until a client sets it, 300 ms in 10 ms units
+#[cfg(feature = "live")]
+const KISS_TX_DELAY: u8 = 30;
+/// Seconds of modulated audio the terminal keeps ready for the sound card, so what's typed
+/// waits behind no more than this
+#[cfg(feature = "live")]
+const TERMINAL_LEAD: f64 = 0.05;
+/// Seconds of audio the ring to the terminal's sound card holds, room for a character on top
+/// of the lead at the slowest rates
+#[cfg(feature = "live")]
+const TERMINAL_RING: f64 = 1.0;
+/// How often the terminal looks for room to modulate more into
+#[cfg(feature = "live")]
+const TERMINAL_POLL: Duration = Duration::from_millis(5);
 
 #[derive(StructOpt, Debug)]
 #[structopt(
     name = "bell103_demodulator",
-    about = "Decodes messages made using the Bell 103 modem protocol using a Goertzel filter",
+    about = "Decodes messages made using the Bell 103 and related FSK modem protocols using a Goertzel filter",
     version = "0.1.0",
-    author = "Luke Petherbridge <me@lukeworks.tech>"
+    author = "Luke Petherbridge <me@lukeworks.tech>",
+    raw(setting = "structopt::clap::AppSettings::SubcommandsNegateReqs"),
+    raw(
+        usage = r#""bell103_demodulator [FLAGS] [OPTIONS] <file> [output]\n    bell103_demodulator <SUBCOMMAND>""#
+    )
 )]
 struct Opt {
+    #[structopt(subcommand)]
+    command: Option<Command>,
+    #[structopt(
+        parse(from_os_str),
+        help = "The PCM WAV file to be decoded, - for stdin"
+    )]
+    file: Option<PathBuf>,
+    #[structopt(parse(from_os_str), help = "The output file to store the message")]
+    output: Option<PathBuf>,
+    #[structopt(
+        long = "follow",
+        help = "Write characters to the output as they're decoded, e.g. to a FIFO, rather than once the message is",
+        raw(
+            requires = r#""output""#,
+            conflicts_with_all = r#"&["both", "verify", "segment", "split", "preamble"]"#
+        )
+    )]
+    follow: bool,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate to decode at, recordings made at another are resampled to it",
+        raw(alias = r#""rate""#),
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "l",
+        long = "filter_length",
+        help = "Goertzel filter length N [default: one bit period, 160 for Bell 103 at 48 kHz]"
+    )]
+    filter_length: Option<usize>,
+    #[structopt(
+        long = "calibrate",
+        help = "Try filter lengths around a bit period on a synthetic recording and decode with the one telling mark from space apart best",
+        raw(conflicts_with = r#""filter_length""#)
+    )]
+    calibrate: bool,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "Use the originating channel, e.g. V.21 channel 1 (default uses the answering channel)"
+    )]
+    origin: bool,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard: bell103, bell202, v21 or v23, or auto to identify it"
+    )]
+    standard: Selection,
+    #[structopt(
+        long = "backend",
+        default_value = "goertzel",
+        help = "Demodulator backend to decode with, goertzel, goertzel32 or one from a --plugin, see --list_backends"
+    )]
+    backend: String,
+    #[structopt(
+        long = "plugin",
+        parse(from_os_str),
+        help = "Load demodulator backends from this shared library, see include/bell103_plugin.h",
+        raw(number_of_values = "1")
+    )]
+    plugin: Vec<PathBuf>,
+    #[structopt(
+        long = "list_backends",
+        help = "List the demodulator backends, including those from any --plugin",
+        raw(conflicts_with_all = r#"&["file", "output", "live"]"#)
+    )]
+    list_backends: bool,
+    #[structopt(
+        long = "both",
+        help = "Decode both directions of a duplex recording, e.g. V.23 forward and back channels"
+    )]
+    both: bool,
+    #[structopt(
+        long = "verify",
+        help = "Re-modulate the decoded message and report how well it matches the recording"
+    )]
+    verify: bool,
+    #[structopt(
+        long = "plot",
+        parse(from_os_str),
+        help = "Plot the mark and space levels, carrier detect and bit decisions over the recording as an SVG image",
+        raw(conflicts_with_all = r#"&["split", "follow", "live", "listen", "rtl_tcp"]"#)
+    )]
+    plot: Option<PathBuf>,
+    #[structopt(
+        long = "split",
+        help = "Decode a line tap recording with the originating side on the left channel and the answering side on the right, as one transcript of each side's turns",
+        raw(
+            conflicts_with_all = r#"&["origin", "both", "verify", "preamble", "live", "listen", "input_channel", "iq", "rtl_tcp"]"#
+        )
+    )]
+    split: bool,
+    #[structopt(
+        long = "segment",
+        help = "Find each message by where the carrier comes and goes, decoding them one at a time under the time each starts",
+        raw(conflicts_with_all = r#"&["both", "split", "live", "listen", "rtl_tcp"]"#)
+    )]
+    segment: bool,
+    #[structopt(
+        long = "clips",
+        parse(from_os_str),
+        help = "Save the audio of each --segment message to this directory as a WAV file named by when it starts",
+        raw(requires = r#""segment""#)
+    )]
+    clips: Option<PathBuf>,
+    #[structopt(
+        long = "preamble",
+        help = "Only accept data following this hex byte pattern, e.g. 55*4 or 2dd4"
+    )]
+    preamble: Option<Preamble>,
+    #[structopt(
+        long = "live",
+        help = "Decode from the default audio input as it arrives, printing characters as they come",
+        raw(conflicts_with_all = r#"&["file", "output", "both", "verify", "preamble"]"#)
+    )]
+    live: bool,
+    #[structopt(
+        long = "device",
+        help = "Audio input device for --live, see --list_devices [default: the system default]",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    device: Option<String>,
+    #[structopt(
+        long = "loopback",
+        help = "Decode what this computer is playing with --live, from the PulseAudio or PipeWire monitor, where --device names another source, or WASAPI loopback of --device",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    loopback: bool,
+    #[structopt(
+        long = "monitor",
+        help = "Play what --live hears through an output device while decoding it, to judge tuning and levels by ear",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    monitor: bool,
+    #[structopt(
+        long = "monitor_device",
+        help = "Output device for --monitor, see --list_devices [default: the system default]",
+        raw(requires = r#""monitor""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    monitor_device: Option<String>,
+    #[structopt(
+        long = "list_devices",
+        help = "List the audio input and output devices",
+        raw(conflicts_with_all = r#"&["file", "output", "live"]"#)
+    )]
+    list_devices: bool,
+    #[structopt(
+        long = "mmap",
+        help = "Memory map the file and decode it in place, for very large recordings",
+        raw(conflicts_with = r#""live""#)
+    )]
+    mmap: bool,
+    #[structopt(
+        long = "raw",
+        help = "Read headerless PCM, e.g. from arecord or an SDR pipeline, instead of a WAV file",
+        raw(conflicts_with_all = r#"&["live", "mmap"]"#)
+    )]
+    raw: bool,
+    #[structopt(
+        short = "f",
+        long = "format",
+        help = "Sample format of --raw input: s16le, u8 or f32le [default: s16le]",
+        raw(requires = r#""raw""#)
+    )]
+    format: Option<PcmFormat>,
+    #[structopt(
+        long = "channels",
+        help = "Channels of --raw input [default: 1]",
+        raw(requires = r#""raw""#)
+    )]
+    channels: Option<usize>,
+    #[structopt(
+        long = "endian",
+        help = "Byte order of --raw samples: little or big [default: little]",
+        raw(requires = r#""raw""#)
+    )]
+    endian: Option<Endian>,
+    #[structopt(
+        long = "layout",
+        help = "Channel layout of --raw input: interleaved, or planar for one channel after another [default: interleaved]",
+        raw(requires = r#""raw""#)
+    )]
+    layout: Option<Layout>,
+    #[structopt(
+        long = "input_channel",
+        help = "Channel of a multi-channel recording to decode: mix to average them, best for the strongest signal in the decoded band, or a number from 1 [default: mix]",
+        raw(conflicts_with = r#""live""#)
+    )]
+    input_channel: Option<ChannelPick>,
+    #[structopt(
+        long = "gain",
+        help = "Gain in dB applied to the input before decoding, negative to attenuate, warning if it clips [default: 0]",
+        raw(allow_hyphen_values = "true")
+    )]
+    gain: Option<f64>,
+    #[structopt(
+        long = "record",
+        parse(from_os_str),
+        help = "Archive the audio --live, --listen or --rtl_tcp decodes to this WAV file as it arrives, to decode again later with other settings",
+        raw(conflicts_with_all = r#"&["file", "output", "both", "verify", "preamble", "split"]"#)
+    )]
+    record: Option<PathBuf>,
+    #[structopt(
+        long = "serial",
+        help = "Send the decoded bytes to a serial port too, written port:baud, e.g. /dev/ttyUSB0:300 or COM3 [default baud: the modem's]"
+    )]
+    serial: Option<SerialTarget>,
+    #[structopt(
+        long = "ws_listen",
+        help = "Serve decoded characters, carrier and SNR events over WebSocket on host:port or :port as they're produced",
+        raw(conflicts_with_all = r#"&["file"]"#)
+    )]
+    ws_listen: Option<String>,
+    #[structopt(
+        long = "metrics",
+        help = "Serve Prometheus counters and gauges of the stream at /metrics on host:port or :port, e.g. characters decoded, framing errors, carrier, input level and SNR",
+        raw(conflicts_with_all = r#"&["file"]"#)
+    )]
+    metrics: Option<String>,
+    #[structopt(
+        long = "tui",
+        help = "Show the stream full screen, a waterfall of the band around its tones with the carrier and SNR over it and the decoded text below",
+        raw(conflicts_with_all = r#"&["file", "output"]"#)
+    )]
+    tui: bool,
+    #[structopt(
+        long = "mqtt",
+        help = "Publish each decoded message as JSON to an MQTT broker, written mqtt://host[:port]/topic"
+    )]
+    mqtt: Option<MqttTarget>,
+    #[structopt(
+        long = "mqtt_chars",
+        help = "Publish each character to --mqtt as it's decoded from a stream, as well as whole messages",
+        raw(requires = r#""mqtt""#)
+    )]
+    mqtt_chars: bool,
+    #[structopt(
+        long = "forward",
+        help = "Send each decoded message to a collector, as a datagram with udp://host:port or a syslog entry with syslog://host[:port]"
+    )]
+    forward: Option<Forward>,
+    #[structopt(
+        long = "notify",
+        help = "Tell someone of each decoded message, by email with smtp://host[:port]/recipient through a mail server or a JSON POST to a webhook with http://host[:port]/path"
+    )]
+    notify: Option<Notify>,
+    #[structopt(
+        long = "notify_from",
+        help = "Address --notify emails come from [default: bell103_demodulator@localhost]",
+        raw(requires = r#""notify""#)
+    )]
+    notify_from: Option<String>,
+    #[structopt(
+        long = "exec",
+        help = "Run a shell command for each decoded message, e.g. 'script.sh {}' with {} replaced by its quoted text, which is on the command's stdin and in $BELL103_TEXT too"
+    )]
+    exec: Option<String>,
+    #[structopt(
+        long = "control",
+        help = "Take commands on a Unix socket at this path while decoding a stream, to start and stop it, change --standard and --origin, and subscribe to its events",
+        raw(conflicts_with_all = r#"&["file"]"#)
+    )]
+    control: Option<PathBuf>,
+    #[structopt(
+        long = "dbus",
+        help = "Offer control of a stream on D-Bus, on the session or system bus: Start and Stop methods, Running, Standard and Origin properties, and a MessageDecoded signal",
+        raw(conflicts_with_all = r#"&["file"]"#)
+    )]
+    dbus: Option<dbus::Bus>,
+    #[structopt(
+        long = "listen",
+        help = "Decode audio sent over the network as it arrives: udp://host:port for datagrams of headerless PCM, as --raw describes, or rtp://host:port for RTP L16",
+        raw(conflicts_with_all = r#"&["file", "live", "mmap", "both", "verify", "preamble"]"#)
+    )]
+    listen: Option<Listen>,
+    #[structopt(
+        long = "iq",
+        help = "Read complex IQ from an SDR, cf32, cs16 or cu8, and demodulate narrowband FM from it before decoding",
+        raw(
+            conflicts_with_all = r#"&["live", "mmap", "raw", "listen", "input_channel"]"#,
+            group = r#""sdr""#
+        )
+    )]
+    iq: Option<IqFormat>,
+    #[structopt(
+        long = "rtl_tcp",
+        help = "Tune an RTL-SDR through an rtl_tcp server at host or host:port to --tune, and decode narrowband FM from the air as it arrives",
+        raw(
+            conflicts_with_all = r#"&["file", "live", "mmap", "raw", "listen", "both", "verify", "preamble"]"#,
+            group = r#""sdr""#
+        )
+    )]
+    rtl_tcp: Option<String>,
+    #[structopt(
+        long = "tuner_gain",
+        help = "Tuner gain for --rtl_tcp in dB [default: automatic]",
+        raw(requires = r#""rtl_tcp""#)
+    )]
+    tuner_gain: Option<f64>,
+    #[structopt(
+        long = "ppm",
+        help = "Frequency correction for the --rtl_tcp dongle's crystal in parts per million [default: 0]",
+        raw(requires = r#""rtl_tcp""#, allow_hyphen_values = "true")
+    )]
+    ppm: Option<i32>,
+    #[structopt(
+        long = "iq_rate",
+        help = "Sampling rate of --iq input or to set for --rtl_tcp in Hz [default: 2400000]",
+        raw(requires = r#""sdr""#)
+    )]
+    iq_rate: Option<f64>,
+    #[structopt(
+        long = "center",
+        help = "Frequency the --iq recording is centred on, or to centre --rtl_tcp on, in Hz, e.g. 144800000 [default: 0, or a quarter of --iq_rate above --tune for --rtl_tcp, clear of the dongle's DC spike]",
+        raw(requires = r#""sdr""#)
+    )]
+    center: Option<f64>,
+    #[structopt(
+        long = "tune",
+        help = "Frequency of the FM signal to demodulate from --iq or --rtl_tcp in Hz [default: the --center frequency]",
+        raw(requires = r#""sdr""#)
+    )]
+    tune: Option<f64>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    #[structopt(name = "encode", about = "Modulates a message into FSK modem audio")]
+    Encode(EncodeOpt),
+    #[structopt(
+        name = "transmit",
+        about = "Keys a transmitter and plays a modulated message through it"
+    )]
+    Transmit(TransmitOpt),
+    #[structopt(
+        name = "callerid",
+        about = "Decodes Bell 202 caller ID messages from a phone line recording"
+    )]
+    CallerId(CallerIdOpt),
+    #[structopt(name = "v22", about = "Decodes 1200 bps Bell 212A and V.22 DPSK")]
+    V22(V22Opt),
+    #[structopt(name = "rtty", about = "Decodes Baudot RTTY")]
+    Rtty(RttyOpt),
+    #[structopt(name = "ukhas", about = "Decodes high altitude balloon RTTY telemetry")]
+    Ukhas(UkhasOpt),
+    #[structopt(name = "tdd", about = "Decodes US TDD/TTY conversations")]
+    Tdd(TddOpt),
+    #[structopt(name = "navtex", about = "Decodes NAVTEX and other SITOR-B broadcasts")]
+    Navtex(NavtexOpt),
+    #[structopt(
+        name = "aprs",
+        about = "Decodes AFSK1200 AX.25 packets such as APRS in TNC2 monitor format"
+    )]
+    Aprs(AprsOpt),
+    #[structopt(name = "pocsag", about = "Decodes POCSAG pages")]
+    Pocsag(PocsagOpt),
+    #[structopt(name = "same", about = "Decodes EAS SAME alert headers")]
+    Same(SameOpt),
+    #[structopt(name = "cassette", about = "Decodes vintage computer cassette tapes")]
+    Cassette(CassetteOpt),
+    #[structopt(
+        name = "uart",
+        about = "Decodes asynchronous serial data sent with any pair of FSK tones"
+    )]
+    Uart(UartOpt),
+    #[structopt(
+        name = "analyze",
+        about = "Estimates the tones and baud rate of an unknown FSK signal"
+    )]
+    Analyze(AnalyzeOpt),
+    #[structopt(
+        name = "tones",
+        about = "Annotates call progress, SIT and modem answer tones in a phone line recording"
+    )]
+    Tones(TonesOpt),
+    #[structopt(
+        name = "xmodem-tx",
+        about = "Modulates a file sent with XMODEM or YMODEM, as heard from the sending modem"
+    )]
+    XmodemTx(XmodemTxOpt),
+    #[structopt(
+        name = "xmodem-rx",
+        about = "Recovers a file from a recording of an XMODEM or YMODEM transfer"
+    )]
+    XmodemRx(XmodemRxOpt),
+    #[structopt(
+        name = "kermit",
+        about = "Recovers the files from a recording of a Kermit session"
+    )]
+    Kermit(KermitOpt),
+    #[structopt(
+        name = "punter",
+        about = "Recovers a file from a recording of a Commodore Punter C1 transfer"
+    )]
+    Punter(PunterOpt),
+    #[structopt(name = "dtmf", about = "Works with DTMF tone sequences")]
+    Dtmf {
+        #[structopt(subcommand)]
+        command: DtmfCommand,
+    },
+    #[structopt(name = "cw", about = "Works with Morse code (CW)")]
+    Cw {
+        #[structopt(subcommand)]
+        command: CwCommand,
+    },
+    #[structopt(name = "mfsk", about = "Works with multi-tone (M-ary) FSK")]
+    Mfsk {
+        #[structopt(subcommand)]
+        command: MfskCommand,
+    },
+    #[structopt(
+        name = "selcall",
+        about = "Works with ZVEI, CCIR and EEA five-tone selective calls"
+    )]
+    Selcall {
+        #[structopt(subcommand)]
+        command: SelcallCommand,
+    },
+    #[structopt(
+        name = "terminal",
+        about = "Runs a full duplex soft modem, sending stdin and printing what's received"
+    )]
+    Terminal(TerminalOpt),
+    #[structopt(
+        name = "serve",
+        about = "Serves an HTTP API decoding uploaded recordings and streaming live decodes"
+    )]
+    Serve(ServeOpt),
+    #[structopt(
+        name = "daemon",
+        about = "Decodes a stream set up by a configuration file until stopped, reloading it on SIGHUP"
+    )]
+    Daemon(DaemonOpt),
+    #[structopt(
+        name = "paging",
+        about = "Finds two-tone sequential pages such as Quick Call II in a dispatch recording"
+    )]
+    Paging(PagingOpt),
+}
+
+#[derive(StructOpt, Debug)]
+enum CwCommand {
+    #[structopt(name = "encode", about = "Keys text as Morse code audio")]
+    Encode(CwEncodeOpt),
+}
+
+#[derive(StructOpt, Debug)]
+struct CwEncodeOpt {
+    #[structopt(help = "The text to send")]
+    text: String,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        short = "w",
+        long = "wpm",
+        default_value = "20",
+        help = "Sending speed in words per minute"
+    )]
+    wpm: f64,
+    #[structopt(
+        short = "t",
+        long = "tone",
+        default_value = "700",
+        help = "Tone frequency in Hz"
+    )]
+    tone: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct CallerIdOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the messages")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct PagingOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the pages found")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "plan",
+        parse(from_os_str),
+        help = "A tone plan with a code and frequency in Hz on each line, to name the tones by"
+    )]
+    plan: Option<PathBuf>,
+    #[structopt(
+        long = "first_length",
+        default_value = "1000",
+        help = "Nominal length of the first (A) tone in milliseconds"
+    )]
+    first_length: f64,
+    #[structopt(
+        long = "second_length",
+        default_value = "3000",
+        help = "Nominal length of the second (B) tone in milliseconds"
+    )]
+    second_length: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct V22Opt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the message")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "Use the originating modem's 1200 Hz carrier (default uses the answering 2400 Hz)"
+    )]
+    origin: bool,
+    #[structopt(long = "both", help = "Decode both directions and label each")]
+    both: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct RttyOpt {
     #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
     file: PathBuf,
     #[structopt(parse(from_os_str), help = "The output file to store the message")]
@@ -26,217 +687,4180 @@ struct Opt {
         short = "s",
         long = "sampling_rate",
         default_value = "48000",
-        help = "Audio sampling rate"
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        default_value = "45.45",
+        help = "Signalling rate, usually 45.45, 50 or 75"
+    )]
+    baud: f64,
+    #[structopt(
+        long = "mark",
+        default_value = "2125",
+        help = "Audio frequency of the mark tone in Hz"
+    )]
+    mark: f64,
+    #[structopt(
+        long = "shift",
+        default_value = "170",
+        help = "Shift between mark and space in Hz, usually 170, 450 or 850"
+    )]
+    shift: f64,
+    #[structopt(
+        short = "r",
+        long = "reverse",
+        help = "Put space below mark instead of above it"
+    )]
+    reverse: bool,
+    #[structopt(
+        long = "no_unshift_on_space",
+        help = "Stay in figures after a space instead of returning to letters"
+    )]
+    no_unshift_on_space: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct UkhasOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the telemetry")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        default_value = "50",
+        help = "Signalling rate, usually 50 or 300"
+    )]
+    baud: f64,
+    #[structopt(
+        long = "data_bits",
+        default_value = "8",
+        help = "ASCII bits per character, 7 or 8"
+    )]
+    data_bits: u32,
+    #[structopt(
+        short = "r",
+        long = "reverse",
+        help = "Treat the lower tone as mark instead of the higher one"
     )]
+    reverse: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct TddOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the conversation")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        default_value = "45.45",
+        help = "Signalling rate, 45.45 or 50 for some international devices"
+    )]
+    baud: f64,
+    #[structopt(
+        long = "raw",
+        help = "Print the text as received instead of splitting it into turns"
+    )]
+    raw: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct NavtexOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the message")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "center",
+        default_value = "1000",
+        help = "Audio frequency halfway between the tones in Hz"
+    )]
+    center: f64,
+    #[structopt(
+        long = "shift",
+        default_value = "170",
+        help = "Shift between the tones in Hz"
+    )]
+    shift: f64,
+    #[structopt(
+        short = "r",
+        long = "reverse",
+        help = "Treat the lower tone as mark instead of the higher one"
+    )]
+    reverse: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct AprsOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "The PCM WAV file to be decoded",
+        raw(required_unless = r#""live""#)
+    )]
+    file: Option<PathBuf>,
+    #[structopt(parse(from_os_str), help = "The output file to store the packets")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "live",
+        help = "Decode packets from the default audio input as they're heard",
+        raw(conflicts_with_all = r#"&["file", "output"]"#)
+    )]
+    live: bool,
+    #[structopt(
+        long = "device",
+        help = "Audio input device for --live, see --list_devices [default: the system default]",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    device: Option<String>,
+    #[structopt(
+        long = "kiss",
+        help = "Act as a KISS TNC on host:port or :port, e.g. :8001, sending clients the packets heard and transmitting the ones they send",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    kiss: Option<String>,
+    #[structopt(
+        long = "tx_device",
+        help = "Output device to transmit --kiss packets through, see --list_devices [default: the system default]",
+        raw(requires = r#""kiss""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    tx_device: Option<String>,
+    #[structopt(
+        long = "ptt_port",
+        help = "Serial port whose control line keys the transmitter for --kiss packets",
+        raw(requires = r#""kiss""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    ptt_port: Option<String>,
+    #[structopt(
+        long = "ptt_line",
+        default_value = "rts",
+        help = "Serial line used for PTT: rts or dtr"
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    ptt_line: SerialLine,
+    #[structopt(
+        long = "rigctld",
+        help = "Key the transmitter for --kiss packets through hamlib's rigctld at host or host:port",
+        raw(requires = r#""kiss""#, conflicts_with = r#""ptt_port""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    rigctld: Option<String>,
+    #[structopt(
+        long = "ptt_gpio",
+        help = "GPIO line that keys the transmitter for --kiss packets, e.g. 17 or gpiochip0:17, in builds with the gpio feature",
+        raw(
+            requires = r#""kiss""#,
+            conflicts_with_all = r#"&["ptt_port", "rigctld"]"#
+        )
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    ptt_gpio: Option<GpioLine>,
+    #[structopt(
+        long = "ptt_active_low",
+        help = "Drive the --ptt_gpio line low to key the transmitter, rather than high",
+        raw(requires = r#""ptt_gpio""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    ptt_active_low: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct PocsagOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "The discriminator audio WAV file, or bit file with --bits, to be decoded"
+    )]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the pages")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        help = "Bit rate: 512, 1200 or 2400 (default tries each)"
+    )]
+    baud: Option<f64>,
+    #[structopt(
+        long = "bits",
+        help = "Read raw NRZ bits, one 0 or 1 per byte or character, instead of audio"
+    )]
+    bits: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct SameOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the alerts")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct CassetteOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the data, stdout if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "kcs",
+        help = "Tape format: kcs, cuts, tarbell or msx"
+    )]
+    format: TapeFormat,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        help = "Bit rate (defaults to 300 for kcs, 1200 for cuts and msx, 1500 for tarbell)"
+    )]
+    baud: Option<f64>,
+}
+
+#[derive(StructOpt, Debug)]
+struct UartOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the data, stdout if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(long = "mark", help = "Audio frequency of the mark (1) tone in Hz")]
+    mark: f64,
+    #[structopt(long = "space", help = "Audio frequency of the space (0) tone in Hz")]
+    space: f64,
+    #[structopt(short = "b", long = "baud", help = "Bit rate")]
+    baud: f64,
+    #[structopt(
+        long = "framing",
+        default_value = "8N1",
+        help = "Data bits, parity (N, E, O, M or S) and stop bits"
+    )]
+    framing: Framing,
+    #[structopt(
+        long = "mmap",
+        help = "Memory map the file and decode it in place, for very large recordings"
+    )]
+    mmap: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct AnalyzeOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be analyzed")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the report, or the message with --apply"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "apply",
+        help = "Decode the recording with the estimated settings, reporting them on stderr"
+    )]
+    apply: bool,
+    #[structopt(
+        long = "spectrogram",
+        parse(from_os_str),
+        help = "Render a spectrogram of the recording as a PNG image, marking the tones and messages found"
+    )]
+    spectrogram: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct TonesOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(parse(from_os_str), help = "The output file to store the tones found")]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct XmodemTxOpt {
+    #[structopt(parse(from_os_str), help = "The file to send")]
+    input: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(
+        short = "p",
+        long = "protocol",
+        default_value = "xmodem",
+        help = "Protocol: xmodem, xmodem-crc, xmodem-1k or ymodem"
+    )]
+    protocol: xmodem::Protocol,
+    #[structopt(
+        long = "turnaround",
+        default_value = "100",
+        help = "Milliseconds the receiver takes to reply to each block, on top of sending the reply"
+    )]
+    turnaround: f64,
+    #[structopt(flatten)]
+    modulation: ModulationOpt,
+}
+
+#[derive(StructOpt, Debug)]
+struct XmodemRxOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the transferred file, stdout if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard: bell103, bell202, v21 or v23, or auto to identify it"
+    )]
+    standard: Selection,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "The sender used the originating channel (default uses the answering channel)"
+    )]
+    origin: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct KermitOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The directory to store the transferred files in, the current directory if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard: bell103, bell202, v21 or v23, or auto to identify it"
+    )]
+    standard: Selection,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "The sender used the originating channel (default uses the answering channel)"
+    )]
+    origin: bool,
+}
+
+#[derive(StructOpt, Debug)]
+struct PunterOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the transferred file, stdout if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard: bell103, bell202, v21 or v23, or auto to identify it"
+    )]
+    standard: Selection,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "The sender used the originating channel (default uses the answering channel)"
+    )]
+    origin: bool,
+}
+
+/// The options of minimodem, for scripts written against it
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "bell103_demodulator --minimodem",
+    about = "Sends and receives FSK text with the command line of minimodem",
+    version = "0.1.0",
+    author = "Luke Petherbridge <me@lukeworks.tech>",
+    raw(usage = r#""bell103_demodulator --minimodem [FLAGS] [OPTIONS] <baudmode>""#)
+)]
+struct MinimodemOpt {
+    #[structopt(short = "t", long = "tx", help = "Transmit the text read from stdin")]
+    tx: bool,
+    #[structopt(short = "r", long = "rx", help = "Receive text (the default)")]
+    rx: bool,
+    #[structopt(help = "Bit rate, e.g. 300 or 1200, or rtty or tdd")]
+    baudmode: String,
+    #[structopt(
+        short = "f",
+        long = "file",
+        parse(from_os_str),
+        help = "WAV file to read or write, instead of stdin or playing the audio"
+    )]
+    file: Option<PathBuf>,
+    #[structopt(short = "M", long = "mark", help = "Mark tone in Hz")]
+    mark: Option<f64>,
+    #[structopt(short = "S", long = "space", help = "Space tone in Hz")]
+    space: Option<f64>,
+    #[structopt(short = "8", long = "ascii", help = "8N1 ASCII characters")]
+    ascii: bool,
+    #[structopt(short = "7", help = "7N1 ASCII characters")]
+    seven_bit: bool,
+    #[structopt(
+        short = "5",
+        long = "baudot",
+        help = "Baudot characters with 1.5 stop bits"
+    )]
+    baudot: bool,
+    #[structopt(long = "stopbits", help = "Stop bits to transmit")]
+    stop_bits: Option<f64>,
+    #[structopt(
+        short = "R",
+        long = "samplerate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        short = "q",
+        long = "quiet",
+        help = "Don't report the carrier on stderr"
+    )]
+    quiet: bool,
+}
+
+#[derive(StructOpt, Debug)]
+enum MfskCommand {
+    #[structopt(name = "encode", about = "Modulates data as MFSK symbols")]
+    Encode(MfskEncodeOpt),
+    #[structopt(name = "decode", about = "Decodes MFSK symbols on a grid of tones")]
+    Decode(MfskDecodeOpt),
+}
+
+#[derive(StructOpt, Debug)]
+enum SelcallCommand {
+    #[structopt(name = "encode", about = "Synthesizes the tones of a selective call")]
+    Encode(SelcallEncodeOpt),
+    #[structopt(name = "decode", about = "Finds the selective calls in a recording")]
+    Decode(SelcallDecodeOpt),
+}
+
+#[derive(StructOpt, Debug)]
+struct SelcallEncodeOpt {
+    #[structopt(help = "The digits to call, 0-9")]
+    digits: String,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        long = "standard",
+        default_value = "ccir",
+        help = "Tone set and timing: zvei, ccir or eea"
+    )]
+    standard: selcall::Standard,
+}
+
+#[derive(StructOpt, Debug)]
+struct SelcallDecodeOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "standard",
+        help = "Tone set and timing: zvei, ccir or eea [default: all]"
+    )]
+    standard: Option<selcall::Standard>,
+    #[structopt(
+        long = "length",
+        default_value = "5",
+        help = "Digits in an address, later tones being reported as a status"
+    )]
+    length: usize,
+}
+
+/// Tones and symbol rate shared by MFSK encoding and decoding
+#[derive(StructOpt, Debug)]
+struct MfskGridOpt {
+    #[structopt(
+        short = "m",
+        long = "tones",
+        default_value = "4",
+        help = "Number of tones: 2, 4, 8, 16, 32 or 64"
+    )]
+    tones: usize,
+    #[structopt(
+        long = "lowest",
+        default_value = "1000",
+        help = "Lowest tone in Hz, sent for symbol 0"
+    )]
+    lowest: f64,
+    #[structopt(
+        long = "spacing",
+        help = "Hz between neighbouring tones [default: the baud rate]"
+    )]
+    spacing: Option<f64>,
+    #[structopt(
+        short = "b",
+        long = "baud",
+        default_value = "100",
+        help = "Symbols per second"
+    )]
+    baud: f64,
+    #[structopt(long = "gray", help = "Map symbols to tones with a Gray code")]
+    gray: bool,
+}
+
+impl MfskGridOpt {
+    fn grid(&self) -> ToneGrid {
+        if !self.tones.is_power_of_two() || !(2..=64).contains(&self.tones) {
+            Error::with_description(
+                &format!("--tones must be 2, 4, 8, 16, 32 or 64, not {}", self.tones),
+                ErrorKind::InvalidValue,
+            )
+            .exit()
+        }
+        ToneGrid {
+            lowest: self.lowest,
+            spacing: self.spacing.unwrap_or(self.baud),
+            tones: self.tones,
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+struct MfskEncodeOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "The file containing the data to encode, - or omitted for stdin"
+    )]
+    input: Option<PathBuf>,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(flatten)]
+    grid: MfskGridOpt,
+}
+
+#[derive(StructOpt, Debug)]
+struct MfskDecodeOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the data, stdout if omitted"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "symbols",
+        help = "List the symbol values instead of packing their bits into bytes"
+    )]
+    symbols: bool,
+    #[structopt(flatten)]
+    grid: MfskGridOpt,
+}
+
+#[derive(StructOpt, Debug)]
+enum DtmfCommand {
+    #[structopt(name = "encode", about = "Synthesizes a sequence of DTMF digits")]
+    Encode(DtmfEncodeOpt),
+    #[structopt(name = "decode", about = "Finds the DTMF digits dialed in a recording")]
+    Decode(DtmfDecodeOpt),
+    #[structopt(
+        name = "messages",
+        about = "Reads the digit sequences in a recording as ANI, pages, selcalls or data"
+    )]
+    Messages(DtmfMessagesOpt),
+}
+
+#[derive(StructOpt, Debug)]
+struct DtmfDecodeOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "min_length",
+        default_value = "40",
+        help = "Shortest tone accepted as a digit in milliseconds"
+    )]
+    min_length: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct DtmfMessagesOpt {
+    #[structopt(parse(from_os_str), help = "The PCM WAV file to be decoded")]
+    file: PathBuf,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the messages, or the bytes for data"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "min_length",
+        default_value = "40",
+        help = "Shortest tone accepted as a digit in milliseconds"
+    )]
+    min_length: f64,
+    #[structopt(
+        short = "c",
+        long = "convention",
+        default_value = "ani",
+        help = "How the digits are read: ani (*ID#), page (called*caller), selcall \
+                (unit#status) or data (two digits a byte)"
+    )]
+    convention: Convention,
+    #[structopt(
+        long = "max_gap",
+        default_value = "500",
+        help = "Longest pause between digits of one sequence in milliseconds"
+    )]
+    max_gap: f64,
+    #[structopt(
+        long = "map",
+        default_value = "0123456789ABCD*#",
+        help = "The digits for nibble values 0 to 15 when reading data"
+    )]
+    map: DigitMap,
+}
+
+#[derive(StructOpt, Debug)]
+struct DtmfEncodeOpt {
+    #[structopt(help = "The digits to dial: 0-9, A-D, * and #, with , for a pause")]
+    digits: String,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        long = "tone_length",
+        default_value = "100",
+        help = "Length of each digit's tone in milliseconds"
+    )]
+    tone_length: f64,
+    #[structopt(
+        long = "gap_length",
+        default_value = "100",
+        help = "Length of the silence after each digit in milliseconds"
+    )]
+    gap_length: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct EncodeOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "The file containing the message to encode, - or omitted for stdin"
+    )]
+    input: Option<PathBuf>,
+    #[structopt(
+        parse(from_os_str),
+        help = "The output file to store the audio, - or omitted for stdout"
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "format",
+        default_value = "wav",
+        help = "Output format: wav, or headerless s16le, u8 or f32le PCM"
+    )]
+    format: PcmFormat,
+    #[structopt(flatten)]
+    modulation: ModulationOpt,
+    #[structopt(
+        long = "play",
+        help = "Play the audio through an audio output device instead of writing it",
+        raw(conflicts_with = r#""output""#)
+    )]
+    play: bool,
+    #[structopt(
+        long = "device",
+        help = "Output device for --play, see --list_devices [default: the system default]",
+        raw(requires = r#""play""#)
+    )]
+    device: Option<String>,
+    #[structopt(
+        long = "level",
+        default_value = "0",
+        help = "Gain in dB, e.g. -20 to drive a microphone input",
+        raw(allow_hyphen_values = "true")
+    )]
+    level: f64,
+}
+
+#[derive(StructOpt, Debug)]
+struct TransmitOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "The file containing the message to send, - or omitted for stdin"
+    )]
+    input: Option<PathBuf>,
+    #[structopt(flatten)]
+    modulation: ModulationOpt,
+    #[structopt(
+        long = "ptt_port",
+        help = "Serial port whose control line keys the transmitter"
+    )]
+    ptt_port: Option<String>,
+    #[structopt(
+        long = "ptt_line",
+        default_value = "rts",
+        help = "Serial line used for PTT: rts or dtr"
+    )]
+    ptt_line: SerialLine,
+    #[structopt(
+        long = "ptt_on",
+        help = "Command to run to key the transmitter, instead of a serial line"
+    )]
+    ptt_on: Option<String>,
+    #[structopt(long = "ptt_off", help = "Command to run to unkey the transmitter")]
+    ptt_off: Option<String>,
+    #[structopt(
+        long = "rigctld",
+        help = "Key the transmitter through hamlib's rigctld at host or host:port, instead of a serial line or commands",
+        raw(conflicts_with_all = r#"&["ptt_port", "ptt_on", "ptt_off"]"#)
+    )]
+    rigctld: Option<String>,
+    #[structopt(
+        long = "ptt_gpio",
+        help = "GPIO line that keys the transmitter, e.g. 17 or gpiochip0:17, in builds with the gpio feature",
+        raw(conflicts_with_all = r#"&["ptt_port", "ptt_on", "ptt_off", "rigctld"]"#)
+    )]
+    ptt_gpio: Option<GpioLine>,
+    #[structopt(
+        long = "ptt_active_low",
+        help = "Drive the --ptt_gpio line low to key the transmitter, rather than high",
+        raw(requires = r#""ptt_gpio""#)
+    )]
+    ptt_active_low: bool,
+    #[structopt(
+        long = "frequency",
+        help = "Dial frequency in Hz to tune the transceiver to through --rigctld before transmitting",
+        raw(requires = r#""rigctld""#)
+    )]
+    frequency: Option<f64>,
+    #[structopt(
+        long = "key_up_delay",
+        default_value = "200",
+        help = "Milliseconds to wait after keying before sending audio"
+    )]
+    key_up_delay: u64,
+    #[structopt(
+        long = "player",
+        default_value = "aplay -q",
+        help = "Command that plays WAV audio from its stdin"
+    )]
+    player: String,
+    #[structopt(
+        long = "device",
+        help = "Play through this audio output device instead of --player, see --list_devices",
+        raw(conflicts_with = r#""player""#)
+    )]
+    device: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+#[cfg_attr(not(feature = "live"), allow(dead_code))]
+struct TerminalOpt {
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "Send on the originating channel and receive on the answering one (default answers)"
+    )]
+    origin: bool,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Full duplex modem standard: bell103, v21 or v23"
+    )]
+    standard: Standard,
+    #[structopt(
+        long = "input_device",
+        help = "Audio input to receive from, see --list_devices [default: the system default]"
+    )]
+    input_device: Option<String>,
+    #[structopt(
+        long = "output_device",
+        help = "Audio output to send on, see --list_devices [default: the system default]"
+    )]
+    output_device: Option<String>,
+    #[structopt(
+        long = "level",
+        default_value = "0",
+        help = "Gain in dB for the sent audio",
+        raw(allow_hyphen_values = "true")
+    )]
+    level: f64,
+    #[structopt(
+        long = "pty",
+        parse(from_os_str),
+        help = "Be a modem on a pseudo-terminal linked here, e.g. /tmp/bell103, for programs such as minicom to open instead of using the keyboard"
+    )]
+    pty: Option<PathBuf>,
+    #[structopt(
+        long = "com",
+        help = "Be a modem on a serial port, e.g. COM5 of a com0com pair whose COM6 DOS software in an emulator opens, written port:baud [default baud: the modem's]",
+        raw(conflicts_with_all = r#"&["pty", "tcp_connect", "tcp_listen"]"#)
+    )]
+    com: Option<SerialTarget>,
+    #[structopt(
+        long = "tun",
+        help = "Carry IP packets over the link as SLIP through a TUN interface of this name, e.g. bell0, in Linux builds with the tun feature",
+        raw(conflicts_with_all = r#"&["pty", "com", "hayes", "tcp_connect", "tcp_listen"]"#)
+    )]
+    tun: Option<String>,
+    #[structopt(
+        long = "hayes",
+        help = "Take Hayes AT commands on --pty or --com, e.g. ATD to dial the audio link or ATDT host:port for TCP"
+    )]
+    hayes: bool,
+    #[structopt(
+        long = "tcp_connect",
+        help = "Bridge the modem to a TCP connection to host:port, e.g. a telnet BBS, instead of using the keyboard",
+        raw(conflicts_with_all = r#"&["pty", "tcp_listen"]"#)
+    )]
+    tcp_connect: Option<String>,
+    #[structopt(
+        long = "tcp_listen",
+        help = "Bridge the modem to the first TCP connection made to this port instead of using the keyboard",
+        raw(conflicts_with_all = r#"&["pty"]"#)
+    )]
+    tcp_listen: Option<u16>,
+}
+
+#[derive(StructOpt, Debug)]
+struct ServeOpt {
+    #[structopt(
+        long = "listen",
+        default_value = "127.0.0.1:8000",
+        help = "Address to serve the HTTP API on"
+    )]
+    listen: String,
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate to decode at, uploads made at another are resampled to it",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: f64,
+    #[structopt(
+        long = "live",
+        help = "Decode the sound card as well, streaming what's heard from GET /events"
+    )]
+    live: bool,
+    #[structopt(
+        long = "device",
+        help = "Audio input device for --live, see --list_devices [default: the system default]",
+        raw(requires = r#""live""#)
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    device: Option<String>,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard for --live: bell103, bell202, v21 or v23"
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    standard: Standard,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "Decode --live on the originating channel (default decodes the answering one)"
+    )]
+    #[cfg_attr(not(feature = "live"), allow(dead_code))]
+    origin: bool,
+    #[structopt(
+        long = "grpc",
+        help = "Address to serve the gRPC Demodulator service on as well, see proto/bell103.proto"
+    )]
+    grpc: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+struct DaemonOpt {
+    #[structopt(
+        parse(from_os_str),
+        help = "Configuration file of the stream to decode, a long option on each line, e.g. listen = udp://0.0.0.0:7355"
+    )]
+    config: PathBuf,
+}
+
+/// Options shared by everything that modulates a message
+#[derive(StructOpt, Debug)]
+struct ModulationOpt {
+    #[structopt(
+        short = "s",
+        long = "sampling_rate",
+        default_value = "48000",
+        help = "Audio sampling rate",
+        raw(validator = "valid_sampling_rate")
+    )]
+    sampling_rate: u32,
+    #[structopt(
+        short = "o",
+        long = "origin",
+        help = "Use the originating channel, e.g. V.21 channel 1 (default uses the answering channel)"
+    )]
+    origin: bool,
+    #[structopt(
+        long = "standard",
+        default_value = "bell103",
+        help = "Modem standard: bell103, bell202, v21 or v23"
+    )]
+    standard: Standard,
+    #[structopt(
+        long = "preamble",
+        help = "Send this hex byte pattern ahead of the message, e.g. 55*16 or 2dd4"
+    )]
+    preamble: Option<Preamble>,
+    #[structopt(
+        long = "cw_id",
+        help = "Append a Morse code identifier, e.g. a callsign, after the message"
+    )]
+    cw_id: Option<String>,
+}
+
+fn main() {
+    // Take minimodem's arguments when asked to, or when installed under its name
+    let mut args: Vec<_> = env::args_os().collect();
+    let invoked_as = Path::new(&args[0]).file_stem().map(OsStr::to_os_string);
+    if args.get(1).is_some_and(|arg| arg == "--minimodem") {
+        args.remove(1);
+        return run_minimodem(MinimodemOpt::from_iter(args));
+    } else if invoked_as.is_some_and(|name| name == "minimodem") {
+        return run_minimodem(MinimodemOpt::from_iter(args));
+    }
+
+    let mut opt = Opt::from_args();
+    match opt.command.take() {
+        Some(Command::Encode(encode)) => encode_message(encode),
+        Some(Command::Transmit(transmit)) => transmit_message(transmit),
+        Some(Command::CallerId(callerid)) => decode_caller_id(callerid),
+        Some(Command::V22(v22)) => decode_v22(v22),
+        Some(Command::Rtty(rtty)) => decode_rtty(rtty),
+        Some(Command::Ukhas(ukhas)) => decode_ukhas(ukhas),
+        Some(Command::Tdd(tdd)) => decode_tdd(tdd),
+        Some(Command::Navtex(navtex)) => decode_navtex(navtex),
+        Some(Command::Aprs(aprs)) => decode_aprs(aprs),
+        Some(Command::Pocsag(pocsag)) => decode_pocsag(pocsag),
+        Some(Command::Same(same)) => decode_same(same),
+        Some(Command::Cassette(cassette)) => decode_cassette(cassette),
+        Some(Command::Uart(uart)) => decode_uart(uart),
+        Some(Command::Analyze(analyze)) => analyze_signal(analyze),
+        Some(Command::Tones(tones)) => decode_tones(tones),
+        Some(Command::Paging(paging)) => decode_paging(paging),
+        Some(Command::Terminal(terminal)) => run_terminal(terminal),
+        Some(Command::Serve(serve)) => run_serve(serve),
+        Some(Command::Daemon(daemon)) => run_daemon(daemon),
+        Some(Command::XmodemTx(xmodem)) => send_xmodem(xmodem),
+        Some(Command::XmodemRx(xmodem)) => receive_xmodem(xmodem),
+        Some(Command::Kermit(kermit)) => receive_kermit(kermit),
+        Some(Command::Punter(punter)) => receive_punter(punter),
+        Some(Command::Dtmf {
+            command: DtmfCommand::Encode(encode),
+        }) => encode_dtmf(encode),
+        Some(Command::Dtmf {
+            command: DtmfCommand::Decode(decode),
+        }) => decode_dtmf(decode),
+        Some(Command::Dtmf {
+            command: DtmfCommand::Messages(messages),
+        }) => decode_dtmf_messages(messages),
+        Some(Command::Cw {
+            command: CwCommand::Encode(encode),
+        }) => encode_cw(encode),
+        Some(Command::Mfsk {
+            command: MfskCommand::Encode(encode),
+        }) => encode_mfsk(encode),
+        Some(Command::Mfsk {
+            command: MfskCommand::Decode(decode),
+        }) => decode_mfsk(decode),
+        Some(Command::Selcall {
+            command: SelcallCommand::Encode(encode),
+        }) => encode_selcall(encode),
+        Some(Command::Selcall {
+            command: SelcallCommand::Decode(decode),
+        }) => decode_selcall(decode),
+        None => decode_file(opt),
+    }
+}
+
+/// Checks a sampling rate given with `-s`, the same for every command, so nothing is made
+/// for a rate too low to carry its tones or so high its buffers couldn't be allocated
+fn valid_sampling_rate(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(rate) if framing::SAMPLING_RATES.contains(&rate) => Ok(()),
+        _ => Err(format!(
+            "must be between {} and {} Hz",
+            framing::SAMPLING_RATES.start(),
+            framing::SAMPLING_RATES.end()
+        )),
+    }
+}
+
+/// Treats a missing path or `-` as stdin/stdout
+fn is_std_stream(path: &Option<PathBuf>) -> bool {
+    path.as_ref().is_none_or(|path| path == Path::new("-"))
+}
+
+fn read_message(input: &Option<PathBuf>) -> Vec<u8> {
+    let mut message = Vec::new();
+    if is_std_stream(input) {
+        io::stdin().read_to_end(&mut message).unwrap();
+    } else {
+        File::open(input.as_ref().unwrap())
+            .unwrap()
+            .read_to_end(&mut message)
+            .unwrap();
+    }
+    message
+}
+
+fn modulate_message(opt: &ModulationOpt, mut message: Vec<u8>) -> Vec<i16> {
+    if let Some(preamble) = &opt.preamble {
+        message.splice(0..0, preamble.bytes());
+    }
+    modulate_bits(opt, &framing::frame(&message))
+}
+
+fn modulate_bits(opt: &ModulationOpt, bits: &[u8]) -> Vec<i16> {
+    let channel = opt.standard.channel(opt.origin);
+    let sampling_rate = f64::from(opt.sampling_rate);
+    let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+    let mut samples = Vec::new();
+    modulator.modulate(bits, &mut samples);
+
+    if let Some(id) = &opt.cw_id {
+        let generator = CwGenerator::new(CW_ID_WPM, CW_ID_TONE, sampling_rate);
+        // Leave a word gap between the data and the identifier
+        samples.extend(std::iter::repeat_n(
+            0,
+            (sampling_rate * 7.0 * 1.2 / CW_ID_WPM) as usize,
+        ));
+        if let Err(c) = generator.generate(id, &mut samples) {
+            invalid_morse("--cw_id", c);
+        }
+    }
+    samples
+}
+
+fn encode_message(opt: EncodeOpt) {
+    let mut samples = modulate_message(&opt.modulation, read_message(&opt.input));
+    audio::apply_gain(&mut samples, opt.level);
+    if opt.play {
+        play_on_device(
+            opt.device.as_deref(),
+            &samples,
+            opt.modulation.sampling_rate,
+        )
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+        return;
+    }
+    write_audio(
+        &opt.output,
+        &samples,
+        opt.format,
+        opt.modulation.sampling_rate,
+    );
+}
+
+fn transmit_message(opt: TransmitOpt) {
+    let ptt = (
+        &opt.ptt_gpio,
+        &opt.rigctld,
+        &opt.ptt_port,
+        &opt.ptt_on,
+        &opt.ptt_off,
+    );
+    let mut ptt: Box<dyn Ptt> = match ptt {
+        (Some(gpio), None, None, None, None) => Box::new(open_gpio(gpio, opt.ptt_active_low)),
+        (None, Some(address), None, None, None) => {
+            let mut rig = connect_rig(address);
+            let tuned = match opt.frequency {
+                Some(hz) => rig.set_frequency(hz).and_then(|_| rig.frequency()),
+                None => rig.frequency(),
+            };
+            let hz = tuned.unwrap_or_else(|e| rig_failed(address, e));
+            eprintln!("Transmitting on a dial frequency of {} Hz", hz);
+            Box::new(rig)
+        }
+        (None, None, Some(port), None, None) => {
+            Box::new(SerialPtt::open(port, opt.ptt_line).unwrap())
+        }
+        (None, None, None, Some(on), Some(off)) => Box::new(CommandPtt {
+            on: on.clone(),
+            off: off.clone(),
+        }),
+        _ => Error::with_description(
+            "Either --ptt_gpio, --rigctld, --ptt_port or both --ptt_on and --ptt_off must be provided",
+            ErrorKind::ArgumentConflict,
+        )
+        .exit(),
+    };
+    let samples = modulate_message(&opt.modulation, read_message(&opt.input));
+
+    ptt.set(true).unwrap();
+    thread::sleep(Duration::from_millis(opt.key_up_delay));
+    let played = match &opt.device {
+        Some(device) => play_on_device(Some(device), &samples, opt.modulation.sampling_rate),
+        None => audio::play_with_command(&opt.player, &samples, opt.modulation.sampling_rate)
+            .map_err(|e| e.to_string()),
+    };
+    // Always release PTT, even if playback failed, so the transmitter isn't left keyed
+    ptt.set(false).unwrap();
+    played.unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+}
+
+fn open_gpio(gpio: &GpioLine, active_low: bool) -> GpioPtt {
+    GpioPtt::open(gpio, active_low).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!(
+                "Couldn't key PTT with line {} of {}: {}",
+                gpio.line,
+                gpio.chip.display(),
+                e
+            ),
+            ErrorKind::Io,
+        )
+        .exit()
+    })
+}
+
+fn connect_rig(address: &str) -> rigctld::Rigctld {
+    rigctld::connect(address).unwrap_or_else(|e| rig_failed(address, e))
+}
+
+fn rig_failed(address: &str, e: io::Error) -> ! {
+    Error::with_description(
+        &format!(
+            "Couldn't control the rig through rigctld at {}: {}",
+            address, e
+        ),
+        ErrorKind::Io,
+    )
+    .exit()
+}
+
+fn encode_dtmf(opt: DtmfEncodeOpt) {
+    let generator = DtmfGenerator::new(
+        f64::from(opt.sampling_rate),
+        opt.tone_length,
+        opt.gap_length,
+    );
+    let mut samples = Vec::new();
+    if let Err(digit) = generator.generate(&opt.digits, &mut samples) {
+        Error::with_description(
+            &format!(
+                "Invalid value for '<digits>': `{}` is not a DTMF digit",
+                digit
+            ),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    write_audio(&opt.output, &samples, opt.format, opt.sampling_rate);
+}
+
+fn decode_dtmf(opt: DtmfDecodeOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let detections = DtmfDetector::new(opt.sampling_rate, opt.min_length).detect(&samples);
+    if detections.is_empty() {
+        eprintln!("No DTMF digits found");
+        return;
+    }
+    for detection in &detections {
+        println!("{}", detection);
+    }
+    let digits: String = detections.iter().map(|d| d.digit).collect();
+    println!("Digits: {}", digits);
+}
+
+fn decode_dtmf_messages(opt: DtmfMessagesOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let detections = DtmfDetector::new(opt.sampling_rate, opt.min_length).detect(&samples);
+    let sequences = dtmfdata::sequences(&detections, opt.max_gap / 1000.0);
+    if sequences.is_empty() {
+        eprintln!("No DTMF digits found");
+        return;
+    }
+    let messages = sequences
+        .iter()
+        .map(|sequence| Message::new(&sequence.digits, opt.convention, &opt.map));
+    if opt.convention == Convention::Data {
+        let mut data = Vec::new();
+        for message in messages {
+            if let Message::Data(bytes) = message {
+                data.extend(bytes);
+            }
+        }
+        if let Some(file) = &opt.output {
+            File::create(file).unwrap().write_all(&data).unwrap();
+        } else {
+            io::stdout().write_all(&data).unwrap();
+        }
+    } else {
+        let lines: Vec<String> = sequences
+            .iter()
+            .zip(messages)
+            .map(|(sequence, message)| format!("{:.3}s {}", sequence.start, message))
+            .collect();
+        write_message(&opt.output, &lines.join("\n"));
+    }
+}
+
+fn encode_mfsk(opt: MfskEncodeOpt) {
+    let grid = opt.grid.grid();
+    let symbols = mfsk::symbols(&read_message(&opt.input), grid.bits_per_symbol());
+    let samples = mfsk::modulate(
+        &symbols,
+        grid,
+        opt.grid.baud,
+        opt.grid.gray,
+        f64::from(opt.sampling_rate),
+    );
+    write_audio(&opt.output, &samples, opt.format, opt.sampling_rate);
+}
+
+fn decode_mfsk(opt: MfskDecodeOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let grid = opt.grid.grid();
+    let mut demodulator =
+        MfskDemodulator::new(grid, opt.grid.baud, opt.grid.gray, opt.sampling_rate);
+    let symbols = demodulator.symbols(&samples);
+    if symbols.is_empty() {
+        eprintln!("No MFSK symbols found");
+    }
+    if opt.symbols {
+        let values: Vec<String> = symbols.iter().map(ToString::to_string).collect();
+        write_message(&opt.output, &values.join(" "));
+        return;
+    }
+    let data = mfsk::bytes(&symbols, grid.bits_per_symbol());
+    if let Some(file) = &opt.output {
+        File::create(file).unwrap().write_all(&data).unwrap();
+    } else {
+        io::stdout().write_all(&data).unwrap();
+    }
+}
+
+fn encode_selcall(opt: SelcallEncodeOpt) {
+    let samples = selcall::encode(&opt.digits, opt.standard, f64::from(opt.sampling_rate))
+        .unwrap_or_else(|digit| {
+            Error::with_description(
+                &format!(
+                    "Invalid value for '<digits>': `{}` is not a selcall digit",
+                    digit
+                ),
+                ErrorKind::InvalidValue,
+            )
+            .exit()
+        });
+    write_audio(&opt.output, &samples, opt.format, opt.sampling_rate);
+}
+
+fn decode_selcall(opt: SelcallDecodeOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let standards = match opt.standard {
+        Some(standard) => vec![standard],
+        None => selcall::STANDARDS.to_vec(),
+    };
+    let mut calls = Vec::new();
+    for standard in standards {
+        calls.extend(SelcallDecoder::new(standard, opt.length, opt.sampling_rate).decode(&samples));
+    }
+    if calls.is_empty() {
+        eprintln!("No selective calls found");
+        return;
+    }
+    calls.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    for call in &calls {
+        println!("{}", call);
+    }
+}
+
+fn encode_cw(opt: CwEncodeOpt) {
+    let generator = CwGenerator::new(opt.wpm, opt.tone, f64::from(opt.sampling_rate));
+    let mut samples = Vec::new();
+    if let Err(c) = generator.generate(&opt.text, &mut samples) {
+        invalid_morse("<text>", c);
+    }
+    write_audio(&opt.output, &samples, opt.format, opt.sampling_rate);
+}
+
+fn invalid_morse(arg: &str, c: char) -> ! {
+    Error::with_description(
+        &format!("Invalid value for '{}': `{}` has no Morse code", arg, c),
+        ErrorKind::InvalidValue,
+    )
+    .exit()
+}
+
+fn write_audio(output: &Option<PathBuf>, samples: &[i16], format: PcmFormat, sampling_rate: u32) {
+    if is_std_stream(output) {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        audio::write_samples(&mut stdout, samples, format, sampling_rate).unwrap();
+        stdout.flush().unwrap();
+    } else {
+        let mut file = File::create(output.as_ref().unwrap()).unwrap();
+        audio::write_samples(&mut file, samples, format, sampling_rate).unwrap();
+    }
+}
+
+fn decode_caller_id(opt: CallerIdOpt) {
+    check_channel(Standard::Bell202.answer(), opt.sampling_rate);
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let messages: String = callerid::decode(&samples, opt.sampling_rate)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(messages.as_bytes())
+            .unwrap();
+    } else if messages.is_empty() {
+        eprintln!("No caller ID messages found");
+    } else {
+        print!("{}", messages);
+    }
+}
+
+fn decode_v22(opt: V22Opt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let decode = |carrier| -> String {
+        dpsk::decode(&samples, opt.sampling_rate, carrier)
+            .iter()
+            .map(|&b| char::from(b & 0x7f))
+            .collect()
+    };
+    let message = if opt.both {
+        let originate = decode(dpsk::ORIGINATE_CARRIER);
+        let answer = decode(dpsk::ANSWER_CARRIER);
+        format!("[originate]\n{}\n[answer]\n{}", originate, answer)
+    } else if opt.origin {
+        decode(dpsk::ORIGINATE_CARRIER)
+    } else {
+        decode(dpsk::ANSWER_CARRIER)
+    };
+    write_message(&opt.output, &message);
+}
+
+fn decode_rtty(opt: RttyOpt) {
+    let shift = if opt.reverse { -opt.shift } else { opt.shift };
+    let channel = Channel {
+        mark: opt.mark,
+        space: opt.mark + shift,
+        baud: opt.baud,
+    };
+    check_channel(channel, opt.sampling_rate);
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let message = rtty::decode(
+        &samples,
+        channel,
+        opt.sampling_rate,
+        !opt.no_unshift_on_space,
+    );
+    write_message(&opt.output, &message);
+}
+
+fn decode_ukhas(opt: UkhasOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let text = ukhas::decode_text(
+        &samples,
+        opt.sampling_rate,
+        opt.baud,
+        opt.data_bits,
+        opt.reverse,
+    );
+    let sentences: String = ukhas::sentences(&text)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(sentences.as_bytes())
+            .unwrap();
+    } else if sentences.is_empty() {
+        eprintln!("No telemetry sentences found");
+    } else {
+        print!("{}", sentences);
+    }
+}
+
+fn decode_tdd(opt: TddOpt) {
+    let channel = Channel {
+        baud: opt.baud,
+        ..tdd::CHANNEL
+    };
+    check_channel(channel, opt.sampling_rate);
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let text = rtty::decode(&samples, channel, opt.sampling_rate, false);
+    let message = if opt.raw {
+        text
+    } else {
+        let turns: Vec<String> = tdd::turns(&text).iter().map(ToString::to_string).collect();
+        turns.join("\n")
+    };
+    write_message(&opt.output, &message);
+}
+
+fn decode_navtex(opt: NavtexOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let shift = if opt.reverse { -opt.shift } else { opt.shift };
+    let channel = Channel {
+        mark: opt.center + shift / 2.0,
+        space: opt.center - shift / 2.0,
+        baud: navtex::BAUD,
+    };
+    let (message, stats) = navtex::decode(&samples, channel, opt.sampling_rate);
+    eprintln!(
+        "NAVTEX: {} characters, {} corrected from the repeat, {} lost",
+        stats.characters, stats.corrected, stats.errors
+    );
+    write_message(&opt.output, &message);
+}
+
+fn decode_aprs(opt: AprsOpt) {
+    let file = match &opt.file {
+        Some(file) if !opt.live => file,
+        _ => return decode_aprs_live(&opt),
+    };
+    let samples = audio::read_wav(File::open(file).unwrap(), opt.sampling_rate).unwrap();
+    let frames = ax25::decode(&samples, opt.sampling_rate);
+    if frames.is_empty() {
+        eprintln!("No packets found");
+    }
+    let packets: String = frames.iter().map(|frame| format!("{}\n", frame)).collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(packets.as_bytes())
+            .unwrap();
+    } else {
+        print!("{}", packets);
+    }
+}
+
+fn decode_pocsag(opt: PocsagOpt) {
+    let rates = opt
+        .baud
+        .map_or(pocsag::BAUD_RATES.to_vec(), |baud| vec![baud]);
+    let mut pages = Vec::new();
+    if opt.bits {
+        let mut bytes = Vec::new();
+        File::open(&opt.file)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let bits: Vec<u8> = bytes
+            .iter()
+            .filter(|b| !b.is_ascii_whitespace())
+            .map(|b| b & 1)
+            .collect();
+        for &baud in &rates {
+            pages.extend(pocsag::decode_bits(&bits, baud));
+        }
+    } else {
+        let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+        for &baud in &rates {
+            pages.extend(pocsag::decode_audio(&samples, opt.sampling_rate, baud));
+        }
+    }
+    pages.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+    if pages.is_empty() {
+        eprintln!("No pages found");
+    }
+    let pages: String = pages.iter().map(|page| format!("{}\n", page)).collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(pages.as_bytes())
+            .unwrap();
+    } else {
+        print!("{}", pages);
+    }
+}
+
+fn decode_same(opt: SameOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let alerts: String = same::decode(&samples, opt.sampling_rate)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(alerts.as_bytes())
+            .unwrap();
+    } else if alerts.is_empty() {
+        eprintln!("No SAME headers found");
+    } else {
+        print!("{}", alerts);
+    }
+}
+
+fn decode_cassette(opt: CassetteOpt) {
+    let baud = opt.baud.unwrap_or_else(|| opt.format.default_baud());
+    if let Some(channel) = opt.format.channel(baud) {
+        check_channel(channel, opt.sampling_rate);
+    }
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let blocks = cassette::decode(&samples, opt.sampling_rate, opt.format, baud);
+    if blocks.is_empty() {
+        eprintln!("No blocks found");
+    }
+    let mut data = Vec::new();
+    for block in &blocks {
+        eprintln!("{}", block);
+        // Headers describe the file rather than being part of it
+        if block.msx_header().is_none() {
+            data.extend(&block.data);
+        }
+    }
+    if let Some(file) = &opt.output {
+        File::create(file).unwrap().write_all(&data).unwrap();
+    } else {
+        io::stdout().write_all(&data).unwrap();
+    }
+}
+
+fn send_xmodem(opt: XmodemTxOpt) {
+    let mut file = Vec::new();
+    File::open(&opt.input)
+        .unwrap()
+        .read_to_end(&mut file)
+        .unwrap();
+    let name = opt
+        .input
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let modulation = &opt.modulation;
+    let channel = modulation.standard.channel(modulation.origin);
+    let reply_baud = modulation.standard.channel(!modulation.origin).baud;
+    let mut bits = match &modulation.preamble {
+        Some(preamble) => framing::frame(&preamble.bytes()),
+        None => Vec::new(),
+    };
+    for exchange in xmodem::send(&name, &file, opt.protocol) {
+        bits.extend(framing::frame(&exchange.packet));
+        // Hold mark while the receiver replies on the other channel, which also gives the
+        // receiving instance the idle line it needs ahead of the first block
+        let reply_secs = (exchange.reply.len() * 10) as f64 / reply_baud;
+        let idle_secs = reply_secs + opt.turnaround / 1000.0;
+        bits.extend(std::iter::repeat_n(
+            1,
+            (idle_secs * channel.baud).ceil() as usize,
+        ));
+    }
+
+    let samples = modulate_bits(modulation, &bits);
+    write_audio(&opt.output, &samples, opt.format, modulation.sampling_rate);
+}
+
+fn receive_xmodem(opt: XmodemRxOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let (standard, origin) = match opt.standard {
+        Selection::Fixed(standard) => (standard, opt.origin),
+        Selection::Auto => identify_standard(&samples, opt.sampling_rate),
+    };
+    check_channel(standard.channel(origin), opt.sampling_rate);
+    let (received, _) = framing::receive(&samples, standard.channel(origin), opt.sampling_rate, 8);
+    let bytes: Vec<u8> = received.iter().map(|received| received.byte).collect();
+    let transfer = xmodem::receive(&bytes);
+    if transfer.blocks == 0 && transfer.errors.is_empty() {
+        eprintln!("No XMODEM blocks found");
+    } else {
+        for error in &transfer.errors {
+            eprintln!("{}", error);
+        }
+        eprintln!("{}", transfer);
+    }
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(&transfer.data)
+            .unwrap();
+    } else {
+        io::stdout().write_all(&transfer.data).unwrap();
+    }
+}
+
+fn receive_kermit(opt: KermitOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let (standard, origin) = match opt.standard {
+        Selection::Fixed(standard) => (standard, opt.origin),
+        Selection::Auto => identify_standard(&samples, opt.sampling_rate),
+    };
+    check_channel(standard.channel(origin), opt.sampling_rate);
+    let (received, _) = framing::receive(&samples, standard.channel(origin), opt.sampling_rate, 8);
+    let bytes: Vec<u8> = received.iter().map(|received| received.byte).collect();
+    let session = kermit::receive(&bytes);
+    if session.packets == 0 && session.errors.is_empty() {
+        eprintln!("No Kermit packets found");
+        return;
+    }
+    for error in &session.errors {
+        eprintln!("{}", error);
+    }
+
+    let directory = opt.output.unwrap_or_default();
+    for (i, file) in session.files.iter().enumerate() {
+        eprintln!("{}", file);
+        if file.discarded {
+            continue;
+        }
+        // Only keep the last component so a name can't write outside the directory
+        let name = match Path::new(&file.name).file_name() {
+            Some(name) => PathBuf::from(name),
+            None => PathBuf::from(format!("kermit{}.bin", i + 1)),
+        };
+        File::create(directory.join(name))
+            .unwrap()
+            .write_all(&file.data)
+            .unwrap();
+    }
+    eprintln!("{}", session);
+}
+
+fn receive_punter(opt: PunterOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let (standard, origin) = match opt.standard {
+        Selection::Fixed(standard) => (standard, opt.origin),
+        Selection::Auto => identify_standard(&samples, opt.sampling_rate),
+    };
+    check_channel(standard.channel(origin), opt.sampling_rate);
+    let (received, _) = framing::receive(&samples, standard.channel(origin), opt.sampling_rate, 8);
+    let bytes: Vec<u8> = received.iter().map(|received| received.byte).collect();
+    let transfer = punter::receive(&bytes);
+    if transfer.blocks == 0 {
+        eprintln!("No Punter blocks found");
+    } else {
+        for error in &transfer.errors {
+            eprintln!("{}", error);
+        }
+        eprintln!("{}", transfer);
+    }
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(&transfer.data)
+            .unwrap();
+    } else {
+        io::stdout().write_all(&transfer.data).unwrap();
+    }
+}
+
+fn decode_tones(opt: TonesOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let events = ToneDetector::new(opt.sampling_rate).detect(&samples);
+    if events.is_empty() {
+        eprintln!("No call progress tones found");
+    }
+    let lines: String = events.iter().map(|event| format!("{}\n", event)).collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(lines.as_bytes())
+            .unwrap();
+    } else {
+        print!("{}", lines);
+    }
+}
+
+fn decode_paging(opt: PagingOpt) {
+    let plan = opt.plan.as_ref().map(|path| {
+        let text = std::fs::read_to_string(path).unwrap();
+        text.parse::<TonePlan>().unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Invalid tone plan {}: {}", path.display(), e),
+                ErrorKind::InvalidValue,
+            )
+            .exit()
+        })
+    });
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let detector = PageDetector::new(opt.sampling_rate, opt.first_length, opt.second_length);
+    let pages = detector.detect(&samples, plan.as_ref());
+    if pages.is_empty() {
+        eprintln!("No pages found");
+    }
+    let lines: String = pages.iter().map(|page| format!("{}\n", page)).collect();
+    if let Some(file) = &opt.output {
+        File::create(file)
+            .unwrap()
+            .write_all(lines.as_bytes())
+            .unwrap();
+    } else {
+        print!("{}", lines);
+    }
+}
+
+fn decode_uart(opt: UartOpt) {
+    let channel = Channel {
+        mark: opt.mark,
+        space: opt.space,
+        baud: opt.baud,
+    };
+    let mut receiver =
+        StreamReceiver::with_backend(channel, opt.sampling_rate, opt.framing, &Goertzel)
+            .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+    let received: Vec<_> = if opt.mmap {
+        let mapped = map_wav(&opt.file).resample_to(opt.sampling_rate);
+        let chunks = read_or_exit(mapped.chunks(audio::CHUNK_FRAMES));
+        chunks.flat_map(|chunk| receiver.push(&chunk)).collect()
+    } else {
+        let chunks = wav_chunks(&opt.file, opt.sampling_rate);
+        chunks.flat_map(|chunk| receiver.push(&chunk)).collect()
+    };
+    let data: Vec<u8> = received.iter().map(|received| received.byte).collect();
+    if data.is_empty() {
+        eprintln!("No characters found");
+    }
+    if let Some(file) = &opt.output {
+        File::create(file).unwrap().write_all(&data).unwrap();
+    } else {
+        io::stdout().write_all(&data).unwrap();
+    }
+}
+
+fn analyze_signal(opt: AnalyzeOpt) {
+    let samples = audio::read_wav(File::open(&opt.file).unwrap(), opt.sampling_rate).unwrap();
+    let estimate = analyze::estimate(&samples, opt.sampling_rate);
+    if let Some(path) = &opt.spectrogram {
+        // Drawn even when nothing is found, it's then all there is to go on
+        let channel = estimate.as_ref().map(|estimate| match estimate.standard() {
+            Some(Identified::Modem { standard, origin }) => standard.channel(origin),
+            _ => estimate.channel(),
+        });
+        let messages = channel
+            .map(|channel| carrier::segments(&samples, channel, opt.sampling_rate))
+            .unwrap_or_default();
+        let image = spectrogram::render(&samples, opt.sampling_rate, channel, &messages);
+        std::fs::write(path, image).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't write {}: {}", path.display(), e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+    }
+    let estimate = estimate.unwrap_or_else(|| {
+        Error::with_description(
+            "No signal alternating between two tones was found",
+            ErrorKind::ValueValidation,
+        )
+        .exit()
+    });
+
+    // Suggest the closest decoder, falling back on decoding 8N1 at the estimated tones
+    let file = opt.file.display();
+    let baudot = estimate.baud <= analyze::MAX_BAUDOT_BAUD;
+    let (matches, suggestion, channel) = match estimate.standard() {
+        Some(identified @ Identified::Modem { standard, origin }) => (
+            format!("\nMatches: {}", identified),
+            format!(
+                "bell103_demodulator --standard {}{} {}",
+                format!("{:?}", standard).to_lowercase(),
+                if origin { " --origin" } else { "" },
+                file
+            ),
+            standard.channel(origin),
+        ),
+        _ if baudot => (
+            String::new(),
+            format!(
+                "bell103_demodulator rtty --baud {} --mark {:.0} --shift {:.0}{} {}",
+                estimate.baud,
+                estimate.mark,
+                estimate.shift(),
+                if estimate.space < estimate.mark {
+                    " --reverse"
+                } else {
+                    ""
+                },
+                file
+            ),
+            estimate.channel(),
+        ),
+        _ => (
+            String::new(),
+            format!(
+                "bell103_demodulator uart --mark {:.0} --space {:.0} --baud {} {}",
+                estimate.mark, estimate.space, estimate.baud, file
+            ),
+            estimate.channel(),
+        ),
+    };
+    let report = format!("{}{}\nSuggested: {}", estimate, matches, suggestion);
+    if !opt.apply {
+        write_message(&opt.output, &report);
+        return;
+    }
+
+    eprintln!("{}", report);
+    check_channel(channel, opt.sampling_rate);
+    let message = if baudot && estimate.standard().is_none() {
+        rtty::decode(&samples, channel, opt.sampling_rate, true)
+    } else {
+        let (received, _) = framing::receive(&samples, channel, opt.sampling_rate, 8);
+        received
+            .iter()
+            .map(|received| char::from(received.byte & 0x7f))
+            .collect()
+    };
+    write_message(&opt.output, &message);
+}
+
+fn run_minimodem(opt: MinimodemOpt) {
+    if opt.tx && opt.rx {
+        Error::with_description(
+            "--tx and --rx can't be used together",
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    let mut mode = Mode::new(&opt.baudmode, opt.mark, opt.space)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+    if opt.baudot {
+        mode.data_bits = 5;
+        mode.stop_bits = 1.5;
+    } else if opt.ascii || opt.seven_bit {
+        mode.data_bits = if opt.seven_bit { 7 } else { 8 };
+        mode.stop_bits = 1.0;
+    }
+    if let Some(stop_bits) = opt.stop_bits {
+        mode.stop_bits = stop_bits;
+    }
+    let sampling_rate = f64::from(opt.sampling_rate);
+
+    if opt.tx {
+        let samples = mode.modulate(&read_message(&None), sampling_rate);
+        match &opt.file {
+            Some(_) => write_audio(&opt.file, &samples, PcmFormat::Wav, opt.sampling_rate),
+            None => audio::play_with_command("aplay -q", &samples, opt.sampling_rate).unwrap(),
+        }
+        return;
+    }
+
+    check_channel(mode.channel, sampling_rate);
+    let samples = match &opt.file {
+        Some(file) => audio::read_wav(File::open(file).unwrap(), sampling_rate),
+        None => audio::read_wav(io::stdin(), sampling_rate),
+    }
+    .unwrap();
+    let text = mode.demodulate(&samples, sampling_rate);
+    // Mirror minimodem's carrier reports around the decoded text
+    let channel = mode.channel;
+    if !opt.quiet && !text.is_empty() {
+        eprintln!("### CARRIER {} @ {:.1} Hz ###", channel.baud, channel.mark);
+    }
+    io::stdout().write_all(&text).unwrap();
+    io::stdout().flush().unwrap();
+    if !opt.quiet && !text.is_empty() {
+        if text.last() != Some(&b'\n') {
+            eprintln!();
+        }
+        eprintln!("### NOCARRIER ndata={} ###", text.len());
+    }
+}
+
+fn write_message(output: &Option<PathBuf>, message: &str) {
+    if let Some(file) = output {
+        File::create(file)
+            .unwrap()
+            .write_all(message.as_bytes())
+            .unwrap();
+    } else {
+        println!("{}", message);
+    }
+}
+
+fn decode_file(opt: Opt) {
+    if opt.list_devices {
+        return list_devices();
+    }
+    if opt.list_backends {
+        for backend in backends(&opt).iter() {
+            println!("{}: {}", backend.name(), backend.description());
+        }
+        return;
+    }
+    // Refuse a filter or bit with no samples before anything's read, as decoding would never
+    // move past it
+    let standards = match opt.standard {
+        Selection::Fixed(standard) => vec![standard],
+        Selection::Auto => Standard::ALL.to_vec(),
+    };
+    for standard in standards {
+        filter_length(&opt, standard.originate());
+        filter_length(&opt, standard.answer());
+    }
+    if decode_stream(&opt, |blocks, standard| {
+        print_stream(&opt, blocks, standard)
+    }) {
+        return;
+    }
+    let path = opt.file.as_ref().unwrap_or_else(|| {
+        Error::with_description(
+            "The following required arguments were not provided:\n    <file>",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    });
+    if opt.format == Some(PcmFormat::Wav) || opt.channels == Some(0) {
+        Error::with_description(
+            "--raw needs --format s16le, u8 or f32le and at least one channel",
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    if opt.split {
+        return decode_split(&opt, path);
+    }
+    let mapped = if opt.mmap {
+        Some(
+            map_wav(path)
+                .mixdown(mixdown(&opt))
+                .resample_to(opt.sampling_rate),
+        )
+    } else {
+        None
+    };
+    // Recordings are decoded a chunk at a time, unless the whole of one is needed to identify
+    // the standard, separate both directions, verify the message, find each message or plot it
+    let whole = opt.both || opt.verify || opt.segment || opt.plot.is_some();
+    if opt.follow && opt.standard == Selection::Auto {
+        Error::with_description(
+            "--follow can't take --standard auto, which needs the whole recording",
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    if let (Selection::Fixed(standard), false) = (opt.standard, whole) {
+        let channel = backend_channel(&opt, standard, opt.origin);
+        // The recording's read and the followed text written on threads of their own
+        let bytes = thread::scope(|scope| {
+            let (mut follow, writing) = follow_output(&opt, scope);
+            let bytes = match &mapped {
+                // Gain has to copy the samples out of the mapping
+                Some(mapped) if opt.gain.is_some() => {
+                    let chunks = read_or_exit(mapped.chunks(audio::CHUNK_FRAMES));
+                    let chunks = chunks.map(Cow::into_owned);
+                    demodulate_chunks(&opt, with_gain(&opt, chunks), channel, &mut follow)
+                }
+                Some(mapped) => {
+                    let chunks = read_or_exit(mapped.chunks(audio::CHUNK_FRAMES));
+                    demodulate_chunks(&opt, chunks, channel, &mut follow)
+                }
+                None => {
+                    // Opened here so a missing file is reported as it'd be otherwise
+                    let input = open_input(path);
+                    let read = || {
+                        let chunks = read_chunks(&opt, input, mixdown(&opt));
+                        with_gain(&opt, read_or_exit(chunks))
+                    };
+                    let chunks = pipeline::source(scope, READ_AHEAD, read);
+                    demodulate_chunks(&opt, chunks, channel, &mut follow)
+                }
+            };
+            drop(follow);
+            if let Some(Err(e)) = writing.map(|writing| writing.join().unwrap()) {
+                let path = opt.output.as_ref().unwrap();
+                Error::with_description(
+                    &format!("Couldn't write {}: {}", path.display(), e),
+                    ErrorKind::Io,
+                )
+                .exit()
+            }
+            bytes
+        });
+        let message = message_text(&opt, &bytes);
+        return send_message(&opt, &message, channel.baud);
+    }
+
+    // Read our sample data, unless it's mapped
+    let samples = match &mapped {
+        Some(mapped) => {
+            let mut samples = mapped.samples().unwrap();
+            if let Some(mut gain) = opt.gain.map(InputGain::new) {
+                gain.process(samples.to_mut());
+            }
+            samples
+        }
+        None => Cow::Owned(input_chunks(&opt, path).flatten().collect()),
+    };
+
+    let (standard, origin) = match opt.standard {
+        Selection::Fixed(standard) => (standard, opt.origin),
+        Selection::Auto => identify_standard(&samples, opt.sampling_rate),
+    };
+    if opt.both && standard.originate() == standard.answer() {
+        Error::with_description(
+            &format!(
+                "--both needs a full duplex standard, {:?} only has one channel",
+                standard
+            ),
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+
+    // Print and save our message, labelling each direction when decoding both
+    let channel = backend_channel(&opt, standard, origin);
+    if let Some(path) = &opt.plot {
+        plot_timeline(&opt, path, &samples, channel);
+    }
+    let message = if opt.segment {
+        decode_segments(&opt, &samples, channel)
+    } else if opt.both {
+        let originate = decode_channel(&opt, &samples, backend_channel(&opt, standard, true));
+        let answer = decode_channel(&opt, &samples, backend_channel(&opt, standard, false));
+        format!("[originate]\n{}\n[answer]\n{}", originate, answer)
+    } else {
+        decode_channel(&opt, &samples, channel)
+    };
+    send_message(&opt, &message, channel.baud);
+}
+
+/// The backends `--backend` chooses from, loading any `--plugin` the first time
+fn backends(opt: &Opt) -> &'static Registry {
+    static BACKENDS: OnceLock<Registry> = OnceLock::new();
+    BACKENDS.get_or_init(|| {
+        let mut registry = Registry::default();
+        for path in &opt.plugin {
+            registry.load(path).unwrap_or_else(|e| {
+                Error::with_description(
+                    &format!("Couldn't load the plugin {}: {}", path.display(), e),
+                    ErrorKind::InvalidValue,
+                )
+                .exit()
+            });
+        }
+        registry
+    })
+}
+
+fn backend(opt: &Opt) -> &'static dyn Backend {
+    let registry = backends(opt);
+    registry.find(&opt.backend).unwrap_or_else(|| {
+        let names: Vec<_> = registry.iter().map(Backend::name).collect();
+        Error::with_description(
+            &format!(
+                "Invalid value for '--backend <backend>': unknown backend `{}`, expected {}",
+                opt.backend,
+                names.join(", ")
+            ),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    })
+}
+
+/// The channel of `standard` to decode, unless the backend brings its own
+fn backend_channel(opt: &Opt, standard: Standard, origin: bool) -> Channel {
+    backend(opt)
+        .channel(origin)
+        .unwrap_or_else(|| standard.channel(origin))
+}
+
+/// A detector from the backend, exiting when it can't decode the channel
+fn detector(opt: &Opt, channel: Channel, window: usize) -> Box<dyn demodulator::Detector> {
+    backend(opt)
+        .detector(channel, window, opt.sampling_rate)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit())
+}
+
+/// Prints or saves a decoded message, sending it to `--serial` too at `baud` unless the
+/// port gives its own rate, and passing it on to any `--mqtt` broker or `--forward` collector
+fn send_message(opt: &Opt, message: &str, baud: f64) {
+    // --follow has written it already, as it was decoded
+    if !opt.follow {
+        write_message(&opt.output, message);
+    }
+    if let Some(target) = &opt.serial {
+        let sent = open_serial(target, baud).write_all(message.as_bytes());
+        sent.unwrap_or_else(|e| serial_failed(target, e));
+    }
+    for mut sink in message_sinks(opt) {
+        let sent = sink.take(&Event::Message(message));
+        sent.unwrap_or_else(|e| sink_failed(e));
+    }
+}
+
+/// What characters arriving as they're decoded are written to, stdout and any `--serial`
+/// port
+fn received_output(opt: &Opt, baud: f64) -> Box<dyn Write + Send> {
+    // Text goes in the --tui's pane instead of stdout
+    match (&opt.serial, opt.tui) {
+        (Some(target), false) => Box::new(Tee {
+            port: open_serial(target, baud),
+            stdout: io::stdout(),
+        }),
+        (Some(target), true) => Box::new(open_serial(target, baud)),
+        (None, false) => Box::new(io::stdout()),
+        (None, true) => Box::new(io::sink()),
+    }
+}
+
+fn open_serial(target: &SerialTarget, baud: f64) -> Box<dyn serialport::SerialPort> {
+    target
+        .open(baud.round() as u32)
+        .unwrap_or_else(|e| serial_failed(target, e))
+}
+
+fn serial_failed(target: &SerialTarget, e: impl std::fmt::Display) -> ! {
+    Error::with_description(
+        &format!("Couldn't send to serial port {}: {}", target.path, e),
+        ErrorKind::Io,
+    )
+    .exit()
+}
+
+/// Decodes each message of a `--segment` recording on its own, headed by where its carrier
+/// starts, cutting each out to a `--clips` file too
+fn decode_segments(opt: &Opt, samples: &[i16], channel: Channel) -> String {
+    let segments = carrier::segments(samples, channel, opt.sampling_rate);
+    if segments.is_empty() {
+        eprintln!("No carrier found");
+    }
+    if let Some(clips) = &opt.clips {
+        std::fs::create_dir_all(clips).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't create {}: {}", clips.display(), e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+    }
+    let mut messages = Vec::new();
+    for segment in &segments {
+        let seconds = segment.start as f64 / opt.sampling_rate;
+        let clip = carrier::with_margin(segment, channel, opt.sampling_rate, samples.len());
+        let text = decode_channel(opt, &samples[clip.clone()], channel);
+        if let Some(clips) = &opt.clips {
+            let path = clips.join(format!("{}.wav", clip_name(seconds)));
+            let audio = &samples[clip];
+            write_audio(&Some(path), audio, PcmFormat::Wav, opt.sampling_rate as u32);
+        }
+        messages.push(format!("[{:.2} s]\n{}", seconds, text));
+    }
+    messages.join("\n")
+}
+
+/// Names a clip by where it starts in the recording, as hours, minutes and seconds that sort
+/// in order
+fn clip_name(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}-{:02}-{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Decodes the two sides of a `--split` recording from their own channels, each with its own
+/// tones, and prints them as one transcript
+fn decode_split(opt: &Opt, path: &Path) {
+    let sides = split_channels(opt, path).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("--split needs a stereo recording: {}", e),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    });
+    let standard = match opt.standard {
+        Selection::Fixed(standard) => standard,
+        Selection::Auto => identify_standard(&sides[0], opt.sampling_rate).0,
+    };
+    if standard.originate() == standard.answer() {
+        Error::with_description(
+            &format!(
+                "--split needs a full duplex standard, {:?} only has one channel",
+                standard
+            ),
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    let receive = |samples: &[i16], channel: Channel| {
+        let framing = Framing::new(8);
+        let mut stream =
+            StreamReceiver::with_backend(channel, opt.sampling_rate, framing, backend(opt))
+                .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+        let received = stream.push(samples);
+        (received, 1.0 / (stream.ticks_per_bit() * channel.baud))
+    };
+    let originate_channel = backend_channel(opt, standard, true);
+    let (originate, originate_tick) = receive(&sides[0], originate_channel);
+    let (answer, answer_tick) = receive(&sides[1], backend_channel(opt, standard, false));
+    let transcript = framing::interleave(&[
+        framing::Side {
+            label: "originate",
+            received: &originate,
+            seconds_per_tick: originate_tick,
+        },
+        framing::Side {
+            label: "answer",
+            received: &answer,
+            seconds_per_tick: answer_tick,
+        },
+    ]);
+    send_message(opt, &transcript, originate_channel.baud);
+}
+
+/// Reads the left and right channels of a recording on their own, from the mapping for
+/// `--mmap` or having read the input once otherwise
+fn split_channels(opt: &Opt, path: &Path) -> io::Result<Vec<Vec<i16>>> {
+    let mut gain = opt.gain.map(InputGain::new);
+    let mut bytes = Vec::new();
+    if !opt.mmap {
+        open_input(path).read_to_end(&mut bytes)?;
+    }
+    (0..2)
+        .map(|channel| {
+            let mut samples = if opt.mmap {
+                let mapped = map_wav(path).mixdown(Mixdown::Only(channel));
+                mapped
+                    .resample_to(opt.sampling_rate)
+                    .samples()?
+                    .into_owned()
+            } else {
+                let reader = Box::new(Cursor::new(&bytes[..]));
+                let chunks = read_chunks(opt, reader, Mixdown::Only(channel));
+                chunks.collect::<io::Result<Vec<_>>>()?.concat()
+            };
+            if let Some(gain) = &mut gain {
+                gain.process(&mut samples);
+            }
+            Ok(samples)
+        })
+        .collect()
+}
+
+/// Decodes the stream `opt` has, from `--live`, `--listen` or `--rtl_tcp`, with `decode`,
+/// returning false when it has none
+fn decode_stream<F>(opt: &Opt, decode: F) -> bool
+where
+    F: FnOnce(&mut dyn Iterator<Item = Vec<i16>>, Standard),
+{
+    if opt.live {
+        decode_live(opt, decode);
+    } else if let Some(listen) = opt.listen {
+        decode_listen(opt, listen, decode);
+    } else if let Some(address) = &opt.rtl_tcp {
+        decode_rtl_tcp(opt, address, decode);
+    } else {
+        return false;
+    }
+    true
+}
+
+#[cfg(feature = "live")]
+fn decode_live<F>(opt: &Opt, decode: F)
+where
+    F: FnOnce(&mut dyn Iterator<Item = Vec<i16>>, Standard),
+{
+    let standard = stream_standard(opt, "--live");
+    let channel = backend_channel(opt, standard, opt.origin);
+    let capture = if opt.loopback {
+        live::capture_output(opt.device.as_deref(), opt.sampling_rate as u32)
+    } else {
+        live::capture(opt.device.as_deref(), opt.sampling_rate as u32)
+    };
+    let capture = capture.unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    // Heard after --gain, so it can be judged by ear too
+    let mut monitor = if opt.monitor {
+        let monitor = live::monitor(opt.monitor_device.as_deref(), opt.sampling_rate as u32);
+        Some(monitor.unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit()))
+    } else {
+        None
+    };
+    eprintln!(
+        "Listening for {:?} on {}/{} Hz, press Ctrl-C to stop",
+        standard, channel.mark, channel.space
+    );
+    let mut blocks = with_gain(opt, with_recording(opt, captured(&capture))).inspect(|block| {
+        if let Some(monitor) = &mut monitor {
+            monitor.play(block);
+        }
+    });
+    decode(&mut blocks, standard);
+}
+
+/// The blocks of audio a sound card's captured, with a warning whenever some have been
+/// dropped for decoding falling behind
+#[cfg(feature = "live")]
+fn captured(capture: &live::Capture) -> impl Iterator<Item = Vec<i16>> + '_ {
+    let mut reported = 0;
+    capture.blocks().inspect(move |_| {
+        let dropped = capture.dropped();
+        if dropped > reported {
+            eprintln!(
+                "Decoding fell behind the sound card, {:.2} s of audio dropped so far",
+                dropped as f64 / f64::from(capture.sampling_rate())
+            );
+            reported = dropped;
+        }
+    })
+}
+
+/// Decodes audio arriving at a network address for `--listen`, until interrupted
+fn decode_listen<F>(opt: &Opt, listen: Listen, decode: F)
+where
+    F: FnOnce(&mut dyn Iterator<Item = Vec<i16>>, Standard),
+{
+    let standard = stream_standard(opt, "--listen");
+    let channel = backend_channel(opt, standard, opt.origin);
+    if opt.layout == Some(Layout::Planar) {
+        Error::with_description(
+            "--listen can't take --layout planar, which needs the whole capture",
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    let bound = listen
+        .bind()
+        .and_then(|incoming| Ok((incoming.local_address()?, incoming)));
+    let (address, incoming) = bound.unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't listen on {}: {}", listen, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    let spec = match listen.transport {
+        Transport::Udp => raw_spec(opt),
+        // RTP payloads are mixed down to mono as they arrive
+        Transport::Rtp => RawSpec {
+            format: PcmFormat::S16le,
+            endian: Endian::Big,
+            channels: 1,
+            layout: Layout::Interleaved,
+        },
+    };
+    eprintln!(
+        "Listening for {:?} on {}/{} Hz at {}, press Ctrl-C to stop",
+        standard, channel.mark, channel.space, address
+    );
+    let chunks = audio::read_raw_chunks(incoming, spec, LISTEN_FRAMES)
+        .unwrap()
+        .mixdown(mixdown(opt));
+    let mut blocks = with_gain(opt, with_recording(opt, read_or_exit(chunks)));
+    decode(&mut blocks, standard);
+}
+
+/// Tunes an RTL-SDR through rtl_tcp for `--rtl_tcp` and decodes FM from it, until interrupted
+fn decode_rtl_tcp<F>(opt: &Opt, address: &str, decode: F)
+where
+    F: FnOnce(&mut dyn Iterator<Item = Vec<i16>>, Standard),
+{
+    let standard = stream_standard(opt, "--rtl_tcp");
+    let channel = backend_channel(opt, standard, opt.origin);
+    let tune = opt.tune.unwrap_or_else(|| {
+        Error::with_description(
+            "--rtl_tcp needs --tune, the frequency to receive",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    });
+    let center = sdr_center(opt);
+    let mut client = rtltcp::connect(address).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't connect to rtl_tcp at {}: {}", address, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    let tuned = client
+        .set_sample_rate(sdr_rate(opt) as u32)
+        .and_then(|_| client.set_frequency(center as u32))
+        .and_then(|_| client.set_gain(opt.tuner_gain))
+        .and_then(|_| client.set_frequency_correction(opt.ppm.unwrap_or(0)));
+    if let Err(e) = tuned {
+        Error::with_description(
+            &format!("Couldn't tune through rtl_tcp at {}: {}", address, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    }
+    eprintln!(
+        "Receiving {} Hz on an {} tuner with {} gains centred on {} Hz, listening for {:?} on {}/{} Hz, press Ctrl-C to stop",
+        tune,
+        client.tuner_name(),
+        client.gains,
+        center,
+        standard,
+        channel.mark,
+        channel.space
+    );
+    let receiver = fm_receiver(opt);
+    let chunks = sdr::read_iq_chunks(client, IqFormat::Cu8, receiver, IQ_FRAMES);
+    let mut blocks = with_gain(opt, with_recording(opt, read_or_exit(chunks)));
+    decode(&mut blocks, standard);
+}
+
+/// Decoding as audio arrives has to know what it's listening for before any of it has
+fn stream_standard(opt: &Opt, flag: &str) -> Standard {
+    match opt.standard {
+        Selection::Fixed(standard) => standard,
+        Selection::Auto => Error::with_description(
+            &format!(
+                "{} needs a fixed --standard, auto identifies it from a whole recording",
+                flag
+            ),
+            ErrorKind::ArgumentConflict,
+        )
+        .exit(),
+    }
+}
+
+/// Prints characters from a stream's blocks of audio as they arrive, passing them and
+/// events about the signal to any `--ws_listen` clients or `--mqtt` broker too, and
+/// following the commands of a `--control` socket and `--dbus` callers
+fn print_stream<I>(opt: &Opt, blocks: I, standard: Standard)
+where
+    I: IntoIterator<Item = Vec<i16>>,
+{
+    let channel = backend_channel(opt, standard, opt.origin);
+    let mut output = received_output(opt, channel.baud);
+    let mut sinks = event_sinks(opt);
+    let tui = opt
+        .tui
+        .then(|| Tui::new(&format!("{:?}", standard), channel, opt.sampling_rate));
+    if let Some(tui) = &tui {
+        sinks.push(Box::new(tui.clone()));
+    }
+    let blocks = blocks.into_iter().inspect(move |block| {
+        if let Some(tui) = &tui {
+            tui.observe(block);
+        }
+    });
+    let settings = Arc::new(Mutex::new(control::Settings {
+        running: true,
+        standard,
+        origin: opt.origin,
+    }));
+    let control = control_socket(opt, &settings);
+    if let Some(service) = dbus_service(opt, &settings) {
+        sinks.push(service);
+    }
+    let metrics = opt.metrics.as_ref().map(|address| serve_metrics(address));
+    if let Some(metrics) = &metrics {
+        sinks.push(Box::new(metrics.clone()));
+    }
+    if sinks.is_empty() && control.is_none() {
+        let rate = opt.sampling_rate;
+        return print_received(blocks, channel, rate, backend(opt), 0x7f, output);
+    }
+    if let Some(control) = control {
+        sinks.push(Box::new(control));
+    }
+    let tuning = || {
+        let settings = settings.lock().unwrap();
+        let own = backend(opt).channel(settings.origin);
+        settings.tuning().map(|channel| own.unwrap_or(channel))
+    };
+    let (rate, backend) = (opt.sampling_rate, backend(opt));
+    stream_events(blocks, tuning, rate, backend, metrics.as_ref(), |events| {
+        take_events(events, &mut output, &mut sinks)
+    });
+}
+
+/// Prints a block's events' text and passes them all on to `sinks`
+fn take_events(events: &[Event], output: &mut dyn Write, sinks: &mut [Box<dyn Sink>]) {
+    for event in events {
+        if let Event::Text(text) = event {
+            write!(output, "{}", text).unwrap();
+            output.flush().unwrap();
+        }
+        for sink in sinks.iter_mut() {
+            sink.take(event).unwrap_or_else(|e| sink_failed(e));
+        }
+    }
+}
+
+/// Decodes a stream's blocks of audio as they arrive, handing `each` the events of a block
+/// with its text last. The channel comes from `tuning` for each block, starting afresh when
+/// it changes and skipping the block when there's none. Each block decoded is observed by
+/// `metrics`. When the blocks end, so does any message the carrier was still carrying.
+fn stream_events<I, T, F>(
+    blocks: I,
+    mut tuning: T,
     sampling_rate: f64,
-    #[structopt(
-        short = "l",
-        long = "filter_length",
-        default_value = "160",
-        help = "Goertzel filter length N"
-    )]
-    filter_length: usize,
-    #[structopt(
-        short = "o",
-        long = "origin",
-        help = "Use originating mark/space frequencies (default uses answering frequencies"
-    )]
-    origin: bool,
+    backend: &dyn Backend,
+    metrics: Option<&Metrics>,
+    mut each: F,
+) where
+    I: IntoIterator<Item = Vec<i16>>,
+    T: FnMut() -> Option<Channel>,
+    F: FnMut(&[Event]),
+{
+    let mut decoding = None;
+    for block in blocks {
+        let channel = tuning();
+        if decoding.as_ref().map(|(tuned, _)| *tuned) != channel {
+            decoding = channel.map(|channel| {
+                let decoding =
+                    StreamDecoding::new(channel, sampling_rate, backend).unwrap_or_else(|e| {
+                        Error::with_description(&e, ErrorKind::InvalidValue).exit()
+                    });
+                (channel, decoding)
+            });
+        }
+        let Some((_, decoding)) = &mut decoding else {
+            continue;
+        };
+        let (mut events, bytes) = decoding.push(&block);
+        if let Some(metrics) = metrics {
+            metrics.observe(&block, decoding.framing_errors());
+        }
+        let text: String = bytes.iter().map(|&byte| char::from(byte & 0x7f)).collect();
+        if !text.is_empty() {
+            events.push(Event::Text(&text));
+        }
+        each(&events);
+    }
+    if let Some((_, decoding)) = &mut decoding {
+        each(&decoding.watch.finish());
+    }
 }
 
-fn main() {
-    let opt = Opt::from_args();
-    decode_file(opt);
+/// A stream's signal and what's decoded from it, followed a block at a time
+struct StreamDecoding {
+    watch: SignalWatch,
+    receiver: StreamReceiver,
+    /// Framing errors already counted by `framing_errors`
+    errors: u64,
 }
 
-fn decode_file(opt: Opt) {
-    let (mark_frequency, space_frequency) = if opt.origin {
-        (ORIG_MARK_FREQUENCY, ORIG_SPACE_FREQUENCY)
-    } else {
-        (ANS_MARK_FREQUENCY, ANS_SPACE_FREQUENCY)
-    };
-    // Create two filters for mark and space frequencies
-    let mut mark = GoertzelFilter::new(opt.filter_length, mark_frequency, opt.sampling_rate);
-    let mut space = GoertzelFilter::new(opt.filter_length, space_frequency, opt.sampling_rate);
-
-    // Read our sample data
-    let file = File::open(opt.file).unwrap();
-    let mut reader = hound::WavReader::new(file).unwrap();
-    let samples: Vec<i16> = reader.samples::<i16>().map(Result::unwrap).collect();
-
-    // Loop in chunks over our sample, applying our filters and building a list of bits
-    let mut bits: Vec<u8> = Vec::with_capacity(samples.len() / opt.filter_length);
-    for chunk in samples.chunks(opt.filter_length) {
-        mark.process(chunk);
-        space.process(chunk);
-        let bit = if mark.get_mag_sq() >= space.get_mag_sq() {
-            1
-        } else {
-            0
+impl StreamDecoding {
+    fn new(channel: Channel, sampling_rate: f64, backend: &dyn Backend) -> Result<Self, String> {
+        Ok(Self {
+            watch: SignalWatch::new(channel, sampling_rate),
+            receiver: StreamReceiver::with_backend(
+                channel,
+                sampling_rate,
+                Framing::new(8),
+                backend,
+            )?,
+            errors: 0,
+        })
+    }
+
+    /// How many characters have been dropped for bad framing since this was last asked
+    fn framing_errors(&mut self) -> u64 {
+        let errors = self.receiver.errors();
+        let new = errors - self.errors;
+        self.errors = errors;
+        new
+    }
+
+    /// The signal's events over the next block, and the bytes decoded from it
+    fn push(&mut self, block: &[i16]) -> (Vec<Event<'static>>, Vec<u8>) {
+        let events = self.watch.push(block);
+        let received = self.receiver.push(block);
+        (events, received.iter().map(|r| r.byte).collect())
+    }
+}
+
+/// Where a stream's events go, the `--ws_listen` server and everything taking messages
+fn event_sinks(opt: &Opt) -> Vec<Box<dyn Sink>> {
+    let mut sinks = message_sinks(opt);
+    sinks.extend(websocket_server(opt));
+    sinks
+}
+
+/// The `--ws_listen` server
+fn websocket_server(opt: &Opt) -> Option<Box<dyn Sink>> {
+    let address = opt.ws_listen.as_ref()?;
+    let server = websocket::Server::listen(address).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't serve WebSocket on {}: {}", address, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    eprintln!(
+        "Serving decoded text over WebSocket on {}",
+        server.local_address()
+    );
+    Some(Box::new(server))
+}
+
+/// Where decoded messages go, the `--mqtt` broker and the `--forward` collector
+fn message_sinks(opt: &Opt) -> Vec<Box<dyn Sink>> {
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(target) = &opt.mqtt {
+        sinks.push(Box::new(mqtt_publisher(opt, target)));
+    }
+    if let Some(forward) = &opt.forward {
+        let forwarder = Forwarder::open(forward).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't forward to {}: {}", forward.address, e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+        sinks.push(Box::new(forwarder));
+    }
+    if let Some(notify) = &opt.notify {
+        let from = opt.notify_from.as_deref();
+        let notifier = Notifier::new(notify.clone(), from.unwrap_or(NOTIFY_FROM));
+        sinks.push(Box::new(notifier));
+    }
+    if let Some(command) = &opt.exec {
+        sinks.push(Box::new(Exec::new(command)));
+    }
+    sinks
+}
+
+/// Serves `--metrics` on `address`
+fn serve_metrics(address: &str) -> Metrics {
+    let metrics = Metrics::default();
+    let local = metrics.serve(address).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't serve metrics on {}: {}", address, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    eprintln!("Serving metrics on http://{}/metrics", local);
+    metrics
+}
+
+/// The `--control` socket, changing the stream's `settings`
+fn control_socket(opt: &Opt, settings: &Arc<Mutex<control::Settings>>) -> Option<Control> {
+    let path = opt.control.as_ref()?;
+    let control = Control::listen(path, Arc::clone(settings)).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't take commands on {}: {}", path.display(), e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    eprintln!("Taking commands on {}", path.display());
+    Some(control)
+}
+
+/// The `--dbus` service, changing the stream's `settings` and signalling its messages
+fn dbus_service(opt: &Opt, settings: &Arc<Mutex<control::Settings>>) -> Option<Box<dyn Sink>> {
+    let bus = opt.dbus?;
+    let service = dbus::Service::connect(bus, Arc::clone(settings)).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't offer {} on D-Bus: {}", dbus::NAME, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    eprintln!("Offering {} at {} on D-Bus", dbus::NAME, dbus::PATH);
+    Some(Box::new(service))
+}
+
+fn mqtt_publisher(opt: &Opt, target: &MqttTarget) -> mqtt::Publisher {
+    let client_id = format!("bell103_demodulator-{}", std::process::id());
+    let client = mqtt::Client::connect(&target.address, &client_id).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!(
+                "Couldn't connect to the MQTT broker at {}: {}",
+                target.address, e
+            ),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    mqtt::Publisher::new(client, target.topic.clone(), opt.mqtt_chars)
+}
+
+fn sink_failed(e: io::Error) -> ! {
+    Error::with_description(&format!("Couldn't pass on an event: {}", e), ErrorKind::Io).exit()
+}
+
+/// Writes characters from blocks of audio to `output` as they arrive, until they stop, with
+/// `mask` clearing the top bit of 7-bit text. They're written on a thread of their own, so
+/// output that's slow to take them doesn't hold up decoding.
+fn print_received<I, W>(
+    blocks: I,
+    channel: Channel,
+    sampling_rate: f64,
+    backend: &dyn Backend,
+    mask: u8,
+    mut output: W,
+) where
+    I: IntoIterator<Item = Vec<i16>>,
+    W: Write + Send,
+{
+    let framing = Framing::new(8);
+    let mut receiver = StreamReceiver::with_backend(channel, sampling_rate, framing, backend)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+    thread::scope(|scope| {
+        let (written, writing) = pipeline::sink(scope, OUTPUT_QUEUE, |bytes: Vec<u8>| {
+            output.write_all(&bytes).and_then(|_| output.flush())
+        });
+        for block in blocks {
+            let mut bytes = Vec::new();
+            receiver.push_to(&block, &mut bytes);
+            if !bytes.is_empty() {
+                bytes.iter_mut().for_each(|byte| *byte &= mask);
+                // Writing's failed, its error reported below
+                if written.send(bytes).is_err() {
+                    break;
+                }
+            }
+        }
+        drop(written);
+        if let Err(e) = writing.join().unwrap() {
+            Error::with_description(
+                &format!("Couldn't write what was received: {}", e),
+                ErrorKind::Io,
+            )
+            .exit()
+        }
+    });
+}
+
+#[cfg(feature = "live")]
+fn run_terminal(opt: TerminalOpt) {
+    let standard = opt.standard;
+    if standard.originate() == standard.answer() {
+        Error::with_description(
+            &format!(
+                "The terminal needs a full duplex standard, {:?} only has one channel",
+                standard
+            ),
+            ErrorKind::ArgumentConflict,
+        )
+        .exit()
+    }
+    if opt.hayes && opt.pty.is_none() && opt.com.is_none() {
+        Error::with_description(
+            "--hayes needs --pty or --com for a program to send AT commands on",
+            ErrorKind::MissingRequiredArgument,
+        )
+        .exit()
+    }
+    let (sending, receiving) = (standard.channel(opt.origin), standard.channel(!opt.origin));
+    let capture = live::capture(opt.input_device.as_deref(), opt.sampling_rate)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    let output = live::Output::open(opt.output_device.as_deref(), opt.sampling_rate)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+
+    let (input, received, _pty) = terminal_ends(&opt);
+
+    // Send bytes as they're typed, holding mark in between like a modem's idle line. A Hayes
+    // modem is silent until it dials or answers.
+    let (keyboard, typed) = std::sync::mpsc::channel();
+    let carrier = Arc::new(AtomicBool::new(!opt.hayes));
+    let (session, received) = if opt.hayes {
+        let session = HayesSession {
+            modem: Hayes::new(receiving.baud as u32),
+            program: received,
+            carrier: Arc::clone(&carrier),
+            sending: keyboard.clone(),
+            tcp: None,
+            calls: 0,
+            start: Instant::now(),
         };
-        bits.push(bit);
-        mark.reset();
-        space.reset();
+        (Some(Arc::new(Mutex::new(session))), None)
+    } else {
+        (None, Some(received))
+    };
+    let typing = session.clone();
+    let bridged = opt.tcp_connect.is_some() || opt.tcp_listen.is_some();
+    thread::spawn(move || {
+        for byte in io::BufReader::new(input).bytes() {
+            // A reset connection ends the stream as a closed one does
+            let Ok(byte) = byte else { break };
+            match &typing {
+                Some(session) => {
+                    let mut modem = session.lock().unwrap();
+                    let at = modem.start.elapsed();
+                    let actions = modem.modem.typed(byte, at);
+                    modem.perform(session, actions);
+                }
+                None => {
+                    if keyboard.send(byte).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        if bridged {
+            eprintln!("The TCP connection closed");
+            std::process::exit(0)
+        }
+    });
+    let mut modulator = FskModulator::new(
+        sending.mark,
+        sending.space,
+        sending.baud,
+        f64::from(output.sampling_rate()),
+    );
+    let level = opt.level;
+    let rate = f64::from(output.sampling_rate());
+    let (_playback, mut playing) = output
+        .start_ring((rate * TERMINAL_RING) as usize)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    // Modulated here rather than in the sound card's callback, which only takes what's ready
+    // from the ring, kept just full enough that what's typed is heard soon after
+    let lead = (rate * TERMINAL_LEAD) as usize;
+    thread::spawn(move || {
+        let mut bits = Vec::new();
+        loop {
+            if !carrier.load(Ordering::Relaxed) || playing.unread() >= lead {
+                thread::sleep(TERMINAL_POLL);
+                continue;
+            }
+            let frame = match typed.try_recv() {
+                Ok(byte) => framing::frame(&[byte]),
+                Err(_) => vec![1],
+            };
+            bits.clear();
+            modulator.modulate(&frame, &mut bits);
+            audio::apply_gain(&mut bits, level);
+            playing.write(&bits);
+        }
+    });
+    eprintln!(
+        "Sending on {}/{} Hz and receiving on {}/{} Hz, press Ctrl-C to stop",
+        sending.mark, sending.space, receiving.mark, receiving.space
+    );
+    match session {
+        Some(session) => hayes_received(captured(&capture), receiving, opt.sampling_rate, &session),
+        None => print_received(
+            captured(&capture),
+            receiving,
+            f64::from(opt.sampling_rate),
+            &Goertzel,
+            // SLIP needs all eight bits of each byte
+            if opt.tun.is_some() { 0xff } else { 0x7f },
+            received.unwrap(),
+        ),
+    }
+}
+
+/// A Hayes modem on `--pty` or `--com` with the links it drives, shared by the threads feeding it
+#[cfg(feature = "live")]
+struct HayesSession {
+    modem: Hayes,
+    /// The program driving the modem, getting result codes and what's received online
+    program: Box<dyn Write + Send>,
+    /// Whether the audio link is sending
+    carrier: Arc<AtomicBool>,
+    sending: std::sync::mpsc::Sender<u8>,
+    /// The connection dialed with `ATDT host:port`, carrying the call instead of the audio
+    tcp: Option<TcpStream>,
+    /// Counts calls so a connection's reader can tell it's been hung up on
+    calls: usize,
+    start: Instant,
+}
+
+#[cfg(feature = "live")]
+impl HayesSession {
+    fn perform(&mut self, shared: &Arc<Mutex<Self>>, actions: Vec<hayes::Action>) {
+        for action in actions {
+            match action {
+                hayes::Action::Reply(bytes) => self.tell_program(&bytes),
+                hayes::Action::Send(byte) => match &mut self.tcp {
+                    Some(tcp) => {
+                        if tcp.write_all(&[byte]).is_err() {
+                            let lost = self.modem.lost();
+                            self.perform(shared, lost);
+                        }
+                    }
+                    None => self.sending.send(byte).unwrap(),
+                },
+                hayes::Action::Dial(address) if address.contains(':') => {
+                    let result = match self.dial(shared, &address) {
+                        Ok(()) => self.modem.connected(),
+                        Err(_) => self.modem.lost(),
+                    };
+                    self.perform(shared, result);
+                }
+                hayes::Action::Dial(_) | hayes::Action::Answer => {
+                    self.carrier.store(true, Ordering::Relaxed)
+                }
+                hayes::Action::Hangup => {
+                    self.carrier.store(false, Ordering::Relaxed);
+                    if let Some(tcp) = self.tcp.take() {
+                        let _ = tcp.shutdown(std::net::Shutdown::Both);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Connects to `address`, passing what it sends to the program while online
+    fn dial(&mut self, shared: &Arc<Mutex<Self>>, address: &str) -> io::Result<()> {
+        let address = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let tcp = TcpStream::connect_timeout(&address, self.modem.wait_for_carrier())?;
+        let mut incoming = tcp.try_clone()?;
+        self.tcp = Some(tcp);
+        self.calls += 1;
+        let (call, shared) = (self.calls, Arc::clone(shared));
+        thread::spawn(move || {
+            let mut buffer = [0; 512];
+            loop {
+                let read = incoming.read(&mut buffer).unwrap_or(0);
+                let mut session = shared.lock().unwrap();
+                if session.calls != call || session.tcp.is_none() {
+                    break;
+                }
+                if read == 0 {
+                    let lost = session.modem.lost();
+                    session.perform(&shared, lost);
+                    break;
+                }
+                if session.modem.is_online() {
+                    session.tell_program(&buffer[..read]);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn tell_program(&mut self, bytes: &[u8]) {
+        self.program.write_all(bytes).unwrap();
+        self.program.flush().unwrap();
     }
+}
 
-    // Loop over chunks of 10 bits to create char bytes for our decoded message
-    let mut message = String::new();
-    for chunk in bits.chunks(10) {
-        if chunk[0] == 0 && chunk[9] == 1 {
-            let int = chunk[1..8]
-                .iter()
-                .rev()
-                .fold(0, |acc, &b| (acc << 1) | u32::from(b));
-            let char = std::char::from_u32(int).unwrap();
-            message.push(char);
+/// Passes what's received over the audio link to a Hayes modem's program while it's online,
+/// following the carrier to connect and hang up
+#[cfg(feature = "live")]
+fn hayes_received<I>(
+    blocks: I,
+    channel: Channel,
+    sampling_rate: u32,
+    shared: &Arc<Mutex<HayesSession>>,
+) where
+    I: IntoIterator<Item = Vec<i16>>,
+{
+    let sampling_rate = f64::from(sampling_rate);
+    let mut receiver = StreamReceiver::new(channel, sampling_rate, Framing::new(8));
+    let mut detector = carrier::Detector::new(channel, sampling_rate);
+    for block in blocks {
+        let heard = detector.push(&block);
+        let received: Vec<u8> = receiver.push(&block).iter().map(|r| r.byte).collect();
+        let mut session = shared.lock().unwrap();
+        if session.tcp.is_some() {
+            continue;
         }
+        let at = session.start.elapsed();
+        let actions = session.modem.carrier(heard, at);
+        session.perform(shared, actions);
+        if session.modem.is_online() && !received.is_empty() {
+            session.tell_program(&received);
+        }
+    }
+}
+
+/// Where the terminal's typed bytes come from and received ones go, stdin and stdout, a TCP
+/// connection, a `--com` port, a `--tun` interface or a program on `--pty`, which is linked
+/// for as long as it's held
+#[cfg(all(feature = "live", unix))]
+fn terminal_ends(
+    opt: &TerminalOpt,
+) -> (
+    Box<dyn Read + Send>,
+    Box<dyn Write + Send>,
+    Option<pty::Pty>,
+) {
+    if let Some((input, output)) = tcp_ends(opt)
+        .or_else(|| com_ends(opt))
+        .or_else(|| tun_ends(opt))
+    {
+        return (input, output, None);
+    }
+    let link = match &opt.pty {
+        Some(link) => link,
+        None => return (Box::new(io::stdin()), Box::new(io::stdout()), None),
+    };
+    let pty = pty::Pty::open(link)
+        .and_then(|pty| Ok((pty.master.try_clone()?, pty.master.try_clone()?, pty)))
+        .unwrap_or_else(|e| {
+            Error::with_description(
+                &format!(
+                    "Couldn't create a pseudo-terminal at {}: {}",
+                    link.display(),
+                    e
+                ),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+    eprintln!("Programs can open the modem at {}", link.display());
+    (Box::new(pty.0), Box::new(pty.1), Some(pty.2))
+}
+
+#[cfg(all(feature = "live", not(unix)))]
+fn terminal_ends(opt: &TerminalOpt) -> (Box<dyn Read + Send>, Box<dyn Write + Send>, Option<()>) {
+    if let Some((input, output)) = tcp_ends(opt)
+        .or_else(|| com_ends(opt))
+        .or_else(|| tun_ends(opt))
+    {
+        return (input, output, None);
     }
+    if opt.pty.is_some() {
+        Error::with_description(
+            "--pty needs pseudo-terminals, which only Unix has",
+            ErrorKind::InvalidValue,
+        )
+        .exit()
+    }
+    (Box::new(io::stdin()), Box::new(io::stdout()), None)
+}
+
+/// SLIP frames of the packets sent through the `--tun` interface, and where those received go
+#[cfg(feature = "live")]
+fn tun_ends(opt: &TerminalOpt) -> Option<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    let name = opt.tun.as_ref()?;
+    let tun = Tun::open(name)
+        .and_then(|tun| Ok((tun.try_clone()?, tun)))
+        .unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't create TUN interface {}: {}", name, e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+    eprintln!(
+        "Carrying IP over SLIP on {}, which needs an address and to be brought up",
+        tun.1.name
+    );
+    let (input, output) = (slip::Framed::new(tun.0), slip::Unframed::new(tun.1));
+    Some((Box::new(input), Box::new(output)))
+}
+
+/// The port for `--com`, opened at the baud rate received unless it gives one
+#[cfg(feature = "live")]
+fn com_ends(opt: &TerminalOpt) -> Option<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    let target = opt.com.as_ref()?;
+    let baud = opt.standard.channel(!opt.origin).baud.round() as u32;
+    let port = Bridge::open(target, baud)
+        .and_then(|port| Ok((port.try_clone()?, port)))
+        .unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't open serial port {}: {}", target.path, e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+    eprintln!(
+        "Programs can use the modem on the other end of {}",
+        target.path
+    );
+    Some((Box::new(port.0), Box::new(port.1)))
+}
 
-    // Print and save our message
-    if let Some(file) = opt.output {
-        let mut file = std::fs::File::create(file).unwrap();
-        file.write_all(message.as_bytes()).unwrap();
+/// The socket for `--tcp_connect` or `--tcp_listen`, once the connection's made
+#[cfg(feature = "live")]
+fn tcp_ends(opt: &TerminalOpt) -> Option<(Box<dyn Read + Send>, Box<dyn Write + Send>)> {
+    let connection = if let Some(address) = &opt.tcp_connect {
+        TcpStream::connect(address).map_err(|e| format!("Couldn't connect to {}: {}", address, e))
+    } else if let Some(port) = opt.tcp_listen {
+        TcpListener::bind(("0.0.0.0", port))
+            .and_then(|listener| {
+                eprintln!("Waiting for a connection on port {}", port);
+                listener.accept()
+            })
+            .map(|(connection, peer)| {
+                eprintln!("Connected to {}", peer);
+                connection
+            })
+            .map_err(|e| format!("Couldn't listen on port {}: {}", port, e))
     } else {
-        println!("{}", message);
+        return None;
+    };
+    let connection = connection
+        .and_then(|connection| {
+            Ok((
+                connection.try_clone().map_err(|e| e.to_string())?,
+                connection,
+            ))
+        })
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    Some((Box::new(connection.0), Box::new(connection.1)))
+}
+
+/// Serves the HTTP API for `serve`, until interrupted
+fn run_serve(opt: ServeOpt) {
+    let listener = TcpListener::bind(&opt.listen).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't serve on {}: {}", opt.listen, e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+    eprintln!(
+        "Serving the HTTP API on {}, press Ctrl-C to stop",
+        listener.local_addr().unwrap()
+    );
+    let _grpc = opt.grpc.as_ref().map(|address| {
+        let sampling_rate = opt.sampling_rate;
+        let server = grpc::Server::listen(address, move |path| match path {
+            "/bell103.Demodulator/Decode" => Ok(Box::new(DecodeCall::new(sampling_rate)) as _),
+            _ => Err(grpc::Status::new(
+                grpc::Status::UNIMPLEMENTED,
+                &format!("no method {}", path),
+            )),
+        })
+        .unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't serve gRPC on {}: {}", address, e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+        eprintln!("Serving gRPC on {}", server.local_address());
+        server
+    });
+    let events = opt.live.then(EventStream::default);
+    let (sampling_rate, clients) = (opt.sampling_rate, events.clone());
+    let server = thread::spawn(move || {
+        for client in listener.incoming().flatten() {
+            let events = clients.clone();
+            thread::spawn(move || serve_client(client, sampling_rate, events));
+        }
+    });
+    if let Some(events) = events {
+        serve_live(&opt, events);
     }
+    server.join().unwrap();
+}
+
+/// Answers one request: `POST /decode` with a recording, or `GET /events` to follow `--live`
+fn serve_client(mut client: TcpStream, sampling_rate: f64, events: Option<EventStream>) {
+    let request = client
+        .try_clone()
+        .and_then(|reader| http::read_request(io::BufReader::new(reader)));
+    let (status, body) = match request {
+        Err(e) => ("400 Bad Request", json_error(&e.to_string())),
+        Ok(request) => match (request.method.as_str(), request.path.as_str(), events) {
+            ("POST", "/decode", _) => match decode_upload(&request, sampling_rate) {
+                Ok(decoded) => ("200 OK", decoded),
+                Err(e) => ("400 Bad Request", json_error(&e)),
+            },
+            ("GET", "/events", Some(events)) => {
+                let _ = events.subscribe(client);
+                return;
+            }
+            ("GET", "/events", None) => ("404 Not Found", json_error("/events needs serve --live")),
+            _ => (
+                "404 Not Found",
+                json_error("expected POST /decode or GET /events"),
+            ),
+        },
+    };
+    let _ = http::respond(&mut client, status, "application/json", body.as_bytes());
 }
 
-#[derive(Debug)]
-struct GoertzelFilter {
-    k: u32,
-    n: usize,
-    coeff: f64,
-    q1: f64,
-    q2: f64,
-    sin: f64,
-    cos: f64,
+/// Decodes a recording uploaded to `POST /decode` as JSON, with the standard from
+/// `?standard=` and `?origin` or identified from the recording
+fn decode_upload(request: &http::Request, sampling_rate: f64) -> Result<String, String> {
+    let samples = audio::read_wav(Cursor::new(&request.body), sampling_rate)
+        .map_err(|e| format!("couldn't read the recording: {}", e))?;
+    let origin = matches!(request.parameter("origin"), Some("" | "1" | "true"));
+    let (standard, origin) = match request.parameter("standard").unwrap_or("auto").parse()? {
+        Selection::Fixed(standard) => (standard, origin),
+        Selection::Auto => match detect::identify(&samples, sampling_rate) {
+            Some(detection) => match detection.identified {
+                Identified::Modem { standard, origin } => (standard, origin),
+                identified => return Err(format!("found {}, not a modem standard", identified)),
+            },
+            None => {
+                return Err(
+                    "couldn't identify the standard, the recording starts with silence".to_string(),
+                )
+            }
+        },
+    };
+    framing::check_sampling_rate(standard.channel(origin), sampling_rate)?;
+    let (received, _) = framing::receive(&samples, standard.channel(origin), sampling_rate, 8);
+    let text: String = received.iter().map(|r| char::from(r.byte & 0x7f)).collect();
+    Ok(format!(
+        r#"{{"standard":"{}","origin":{},"text":{}}}"#,
+        format!("{:?}", standard).to_lowercase(),
+        origin,
+        events::json_string(&text)
+    ))
+}
+
+/// A call to the gRPC `Decode` method, demodulating the PCM a client streams to it. The
+/// first message picks the standard, channel and sampling rate, and each is answered with
+/// the events and bytes its audio brought.
+struct DecodeCall {
+    sampling_rate: f64,
+    decoding: Option<StreamDecoding>,
 }
 
-impl GoertzelFilter {
-    fn new(block_size: usize, target_freq: f64, sampling_rate: f64) -> Self {
-        let k = (block_size as f64 * target_freq) / sampling_rate;
-        let omega = (2.0 * PI * k as f64) / block_size as f64;
-        let cos = omega.cos();
+impl DecodeCall {
+    fn new(sampling_rate: f64) -> Self {
         Self {
-            k: k as u32,
-            n: block_size,
-            coeff: 2.0 * cos,
-            q1: 0.0,
-            q2: 0.0,
-            sin: omega.sin(),
-            cos,
+            sampling_rate,
+            decoding: None,
         }
     }
+}
 
-    fn process(&mut self, samples: &[i16]) {
-        for v in samples {
-            let q0 = self.coeff * self.q1 - self.q2 + f64::from(*v);
-            self.q2 = self.q1;
-            self.q1 = q0;
+impl grpc::Call for DecodeCall {
+    fn message(&mut self, message: &[u8]) -> Result<Vec<Vec<u8>>, grpc::Status> {
+        let invalid = |e: String| grpc::Status::new(grpc::Status::INVALID_ARGUMENT, &e);
+        let fields = protobuf::parse(message).map_err(invalid)?;
+        // The last of a field repeated wins, as Protocol Buffers has it
+        let field = |number| {
+            let mut values = fields.iter().rev().filter(|(field, _)| *field == number);
+            values.next().map(|(_, value)| *value)
+        };
+        let decoding = match &mut self.decoding {
+            Some(decoding) => decoding,
+            None => {
+                let standard = match field(3).and_then(protobuf::Value::as_str) {
+                    Some(standard) if !standard.is_empty() => standard.parse().map_err(invalid)?,
+                    _ => Standard::Bell103,
+                };
+                let origin = field(4).and_then(protobuf::Value::as_bool) == Some(true);
+                let sampling_rate = match field(2).and_then(protobuf::Value::as_double) {
+                    Some(rate) if rate != 0.0 => rate,
+                    _ => self.sampling_rate,
+                };
+                if !framing::SAMPLING_RATES.contains(&sampling_rate) {
+                    return Err(invalid(format!(
+                        "sampling_rate must be between {} and {} Hz",
+                        framing::SAMPLING_RATES.start(),
+                        framing::SAMPLING_RATES.end()
+                    )));
+                }
+                let channel = standard.channel(origin);
+                let decoding = StreamDecoding::new(channel, sampling_rate, &Goertzel);
+                self.decoding.insert(decoding.map_err(invalid)?)
+            }
+        };
+        let pcm = field(1)
+            .and_then(protobuf::Value::as_bytes)
+            .unwrap_or_default();
+        let samples: Vec<i16> = pcm
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let (events, bytes) = decoding.push(&samples);
+        let mut answers = Vec::new();
+        for event in events {
+            let mut answer = Vec::new();
+            match event {
+                Event::Carrier(present) => protobuf::put_bool(&mut answer, 2, present),
+                Event::Snr(db) => protobuf::put_double(&mut answer, 3, db),
+                _ => continue,
+            }
+            answers.push(answer);
+        }
+        if !bytes.is_empty() {
+            let mut answer = Vec::new();
+            protobuf::put_bytes(&mut answer, 1, &bytes);
+            answers.push(answer);
         }
+        Ok(answers)
     }
+}
 
-    #[allow(unused)]
-    fn get_real_imag(&self) -> (f64, f64) {
-        let real = self.q1 - self.q2 * self.cos;
-        let imag = self.q2 * self.sin;
-        (real, imag)
+/// Decodes the stream a configuration file sets up until SIGTERM or SIGINT, finishing the
+/// message being decoded before exiting. SIGHUP reads the file again and carries on with
+/// what it now says, apart from where the audio comes from and the servers answering
+/// clients, which are kept until a restart.
+fn run_daemon(opt: DaemonOpt) {
+    daemon::catch_signals().unwrap_or_else(|e| {
+        Error::with_description(&format!("Couldn't catch signals: {}", e), ErrorKind::Io).exit()
+    });
+    let (options, mut config) = daemon_config(&opt.config)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+    let audio = stream_source(options);
+    let settings = Arc::new(Mutex::new(control::Settings {
+        running: true,
+        standard: stream_standard(&config, "daemon"),
+        origin: config.origin,
+    }));
+    let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(daemon::StateLog::new(io::stderr()))];
+    sinks.extend(websocket_server(&config));
+    let metrics = config
+        .metrics
+        .as_ref()
+        .map(|address| serve_metrics(address));
+    if let Some(metrics) = &metrics {
+        sinks.push(Box::new(metrics.clone()));
     }
-
-    fn get_mag_sq(&self) -> f64 {
-        self.q1 * self.q1 + self.q2 * self.q2 - self.q1 * self.q2 * self.coeff
+    if let Some(service) = dbus_service(&config, &settings) {
+        sinks.push(service);
+    }
+    if let Some(control) = control_socket(&config, &settings) {
+        sinks.push(Box::new(control));
+    }
+    // The rest are replaced whenever the configuration is reloaded
+    let kept = sinks.len();
+    eprintln!(
+        "Running from {}, pid {}",
+        opt.config.display(),
+        std::process::id()
+    );
+    loop {
+        let standard = settings.lock().unwrap().standard;
+        let channel = backend_channel(&config, standard, config.origin);
+        let mut output = received_output(&config, channel.baud);
+        sinks.extend(message_sinks(&config));
+        let mut signal = None;
+        let blocks = std::iter::from_fn(|| loop {
+            if let Some(caught) = daemon::signal() {
+                signal = Some(caught);
+                return None;
+            }
+            match audio.recv_timeout(SIGNAL_INTERVAL) {
+                Ok(block) => return Some(block),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        });
+        let tuning = || {
+            let settings = settings.lock().unwrap();
+            let own = backend(&config).channel(settings.origin);
+            settings.tuning().map(|channel| own.unwrap_or(channel))
+        };
+        let (rate, backend) = (config.sampling_rate, backend(&config));
+        stream_events(blocks, tuning, rate, backend, metrics.as_ref(), |events| {
+            take_events(events, &mut output, &mut sinks)
+        });
+        sinks.truncate(kept);
+        match signal {
+            Some(Signal::Reload) => match daemon_config(&opt.config) {
+                Ok((_, reloaded)) => {
+                    let mut settings = settings.lock().unwrap();
+                    settings.standard = stream_standard(&reloaded, "daemon");
+                    settings.origin = reloaded.origin;
+                    config = reloaded;
+                    eprintln!("Reloaded {}", opt.config.display());
+                }
+                Err(e) => eprintln!("Kept the running configuration: {}", e),
+            },
+            Some(Signal::Shutdown) => {
+                eprintln!("Shutting down");
+                break;
+            }
+            None => {
+                eprintln!("The stream has ended");
+                break;
+            }
+        }
     }
+}
 
-    fn reset(&mut self) {
-        self.q2 = 0.0;
-        self.q1 = 0.0;
+/// Reads the daemon's configuration file into the options of the stream it sets up, both as
+/// they'd be given on the command line and parsed
+fn daemon_config(path: &Path) -> Result<(Vec<String>, Opt), String> {
+    let shown = path.display();
+    let config =
+        std::fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {}", shown, e))?;
+    let options = daemon::options(&config).map_err(|e| format!("{} {}", shown, e))?;
+    let arguments = std::iter::once("bell103_demodulator".to_string()).chain(options.clone());
+    let opt = Opt::from_iter_safe(arguments).map_err(|e| {
+        let error = e.message.lines().next().unwrap_or_default();
+        format!("{}: {}", shown, error.trim_start_matches("error: "))
+    })?;
+    if !(opt.live || opt.listen.is_some() || opt.rtl_tcp.is_some()) {
+        return Err(format!(
+            "{} needs live, listen or rtl_tcp for the audio to decode",
+            shown
+        ));
+    }
+    if opt.standard == Selection::Auto {
+        return Err(format!(
+            "{} needs a fixed standard, auto identifies it from a whole recording",
+            shown
+        ));
     }
+    Ok((options, opt))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Reads the audio of the stream `options` set up on a thread of its own, so the daemon can
+/// see to signals while it waits for more
+fn stream_source(options: Vec<String>) -> Receiver<Vec<i16>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let opt = Opt::from_iter(std::iter::once("bell103_demodulator".to_string()).chain(options));
+        decode_stream(&opt, |blocks, _| {
+            for block in blocks {
+                if sender.send(block).is_err() {
+                    return;
+                }
+            }
+        });
+    });
+    receiver
+}
+
+fn json_error(message: &str) -> String {
+    format!(r#"{{"error":{}}}"#, events::json_string(message))
+}
 
-    const SAMPLING_RATE: f64 = 8_000.0; // 8 kHz
-    const BLOCK_SIZE: usize = 205;
-    const TARGET_FREQUENCY: f64 = 941.0; // 941 Hz
+/// Decodes the sound card for `serve --live`, sending what's heard to `/events` subscribers
+#[cfg(feature = "live")]
+fn serve_live(opt: &ServeOpt, mut events: EventStream) {
+    let capture = live::capture(opt.device.as_deref(), opt.sampling_rate as u32)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    let channel = opt.standard.channel(opt.origin);
+    eprintln!(
+        "Listening for {:?} on {}/{} Hz for GET /events",
+        opt.standard, channel.mark, channel.space
+    );
+    let tuning = || Some(channel);
+    stream_events(
+        captured(&capture),
+        tuning,
+        opt.sampling_rate,
+        &Goertzel,
+        None,
+        |block| {
+            for event in block {
+                events.take(event).unwrap();
+            }
+        },
+    );
+}
 
-    fn generate_test_samples(frequency: f64) -> Vec<u8> {
-        let step = frequency * 2.0 * PI / SAMPLING_RATE;
-        let mut samples = vec![0u8; BLOCK_SIZE];
-        for i in 0..BLOCK_SIZE {
-            samples[i] = (100.0 * (i as f64 * step).sin() + 100.0) as u8;
+/// Prints packets from the sound card as they're heard for `aprs --live`, acting as a TNC
+/// for `--kiss` clients
+#[cfg(feature = "live")]
+fn decode_aprs_live(opt: &AprsOpt) {
+    let capture = live::capture(opt.device.as_deref(), opt.sampling_rate as u32)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    let server = opt.kiss.as_ref().map(|address| {
+        let (commands, requested) = std::sync::mpsc::channel();
+        let server = kiss::Server::listen(address, commands).unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't serve KISS on {}: {}", address, e),
+                ErrorKind::Io,
+            )
+            .exit()
+        });
+        eprintln!("Serving KISS on {}", server.local_address());
+        let ptt = (&opt.ptt_gpio, &opt.rigctld, &opt.ptt_port);
+        let mut ptt: Option<Box<dyn Ptt + Send>> = match ptt {
+            (Some(gpio), _, _) => Some(Box::new(open_gpio(gpio, opt.ptt_active_low))),
+            (None, Some(address), _) => Some(Box::new(connect_rig(address))),
+            (None, None, Some(port)) => Some(Box::new(
+                SerialPtt::open(port, opt.ptt_line).unwrap_or_else(|e| {
+                    Error::with_description(
+                        &format!("Couldn't open {} for PTT: {}", port, e),
+                        ErrorKind::Io,
+                    )
+                    .exit()
+                }),
+            )),
+            (None, None, None) => None,
+        };
+        let (device, sampling_rate) = (opt.tx_device.clone(), opt.sampling_rate);
+        thread::spawn(move || {
+            let mut flags = kiss_flags(KISS_TX_DELAY);
+            for command in requested {
+                let frame = match command {
+                    kiss::Command::TxDelay(delay) => {
+                        flags = kiss_flags(delay);
+                        continue;
+                    }
+                    kiss::Command::Data(frame) => frame,
+                };
+                let samples = ax25::modulate(&frame, flags, sampling_rate);
+                if let Some(ptt) = &mut ptt {
+                    ptt.set(true).unwrap();
+                }
+                let played = live::play(device.as_deref(), &samples, sampling_rate as u32);
+                // Always release PTT, so the transmitter isn't left keyed
+                if let Some(ptt) = &mut ptt {
+                    ptt.set(false).unwrap();
+                }
+                if let Err(e) = played {
+                    eprintln!("Couldn't transmit a packet: {}", e);
+                }
+            }
+        });
+        server
+    });
+    eprintln!("Listening for AFSK1200 packets, press Ctrl-C to stop");
+    let mut decoder = ax25::StreamDecoder::new(opt.sampling_rate);
+    for block in captured(&capture) {
+        for frame in decoder.push(&block) {
+            if let Some(packet) = ax25::Frame::parse(&frame) {
+                println!("{}", packet);
+            }
+            if let Some(server) = &server {
+                server.send(&frame);
+            }
         }
+    }
+}
+
+/// The flags to send ahead of a packet to key up for a KISS TX delay. KISS counts in 10 ms,
+/// and a flag is 8 bits at 1200 bps.
+#[cfg(feature = "live")]
+fn kiss_flags(delay: u8) -> usize {
+    (usize::from(delay) * 10 * 1200 / 8 / 1000).max(1)
+}
+
+#[cfg(feature = "live")]
+fn list_devices() {
+    let devices = live::describe_devices()
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::Io).exit());
+    print!("{}", devices);
+}
+
+#[cfg(feature = "live")]
+fn play_on_device(device: Option<&str>, samples: &[i16], sampling_rate: u32) -> Result<(), String> {
+    live::play(device, samples, sampling_rate)
+}
+
+#[cfg(not(feature = "live"))]
+fn decode_live<F>(_: &Opt, _: F) {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn run_terminal(_: TerminalOpt) {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn serve_live(_: &ServeOpt, _: EventStream) {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn decode_aprs_live(_: &AprsOpt) {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn list_devices() {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn play_on_device(_: Option<&str>, _: &[i16], _: u32) -> Result<(), String> {
+    without_audio_devices()
+}
+
+#[cfg(not(feature = "live"))]
+fn without_audio_devices() -> ! {
+    Error::with_description(
+        "This build can't use audio devices, rebuild with `cargo build --features live`",
+        ErrorKind::InvalidValue,
+    )
+    .exit()
+}
+
+/// Picks the standard for `--standard auto`, reporting what was found
+fn identify_standard(samples: &[i16], sampling_rate: f64) -> (Standard, bool) {
+    let detection = detect::identify(samples, sampling_rate).unwrap_or_else(|| {
+        Error::with_description(
+            "Could not identify the standard, the recording starts with silence",
+            ErrorKind::ValueValidation,
+        )
+        .exit()
+    });
+    eprintln!(
+        "Detected {} with {:.0}% confidence",
+        detection.identified,
+        100.0 * detection.confidence
+    );
+    match detection.identified {
+        Identified::Modem { standard, origin } => (standard, origin),
+        Identified::Rtty { low, shift } => Error::with_description(
+            &format!(
+                "RTTY can't be decoded as a modem standard, try `rtty --mark {:.0} --shift {:.0}`",
+                low, shift
+            ),
+            ErrorKind::ValueValidation,
+        )
+        .exit(),
+    }
+}
+
+fn decode_channel(opt: &Opt, samples: &[i16], channel: Channel) -> String {
+    // Separate this direction from the other when both share the recording
+    let isolated;
+    let samples = if opt.both {
+        isolated = filter::isolate_channel(samples, channel, opt.sampling_rate);
+        &isolated
+    } else {
         samples
+    };
+    let bytes = demodulate_chunks(opt, std::iter::once(samples), channel, |_| true);
+
+    // Re-modulate the message and compare it against the recording
+    if opt.verify {
+        let filter_length = filter_length(opt, channel);
+        let baud = opt.sampling_rate / filter_length as f64;
+        let mut demodulator = Demodulator::new(
+            filter_length,
+            channel.mark,
+            channel.space,
+            opt.sampling_rate,
+        );
+        let mut modulator = FskModulator::new(channel.mark, channel.space, baud, opt.sampling_rate);
+        let mut regenerated = Vec::new();
+        modulator.modulate(&framing::frame(&bytes), &mut regenerated);
+        let (score, lag) = verify::cross_correlate(
+            &demodulator.soft_bits(samples),
+            &demodulator.soft_bits(&regenerated),
+        );
+        eprintln!(
+            "Verify: match score {:.3} at an offset of {} bits ({}/{} Hz)",
+            score, lag, channel.mark, channel.space
+        );
+    }
+    message_text(opt, &bytes)
+}
+
+/// Writes the `--plot` of how `channel` was decoded from the recording
+fn plot_timeline(opt: &Opt, path: &Path, samples: &[i16], channel: Channel) {
+    let filter_length = filter_length(opt, channel);
+    let detector = detector(opt, channel, filter_length);
+    let mut demodulator = Demodulator::with_detector(filter_length, detector);
+    let timeline = Timeline::measure(
+        samples,
+        channel,
+        opt.sampling_rate,
+        &mut demodulator,
+        filter_length,
+    );
+    std::fs::write(path, timeline.svg()).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't write {}: {}", path.display(), e),
+            ErrorKind::Io,
+        )
+        .exit()
+    });
+}
+
+/// Each filter block covers exactly one bit unless a length is given. Neither may be empty,
+/// which a sampling rate under about half the baud would make a bit.
+fn filter_length(opt: &Opt, channel: Channel) -> usize {
+    let bit_length = (opt.sampling_rate / channel.baud).round() as usize;
+    let description = match opt.filter_length {
+        Some(0) => "--filter_length must be at least one sample".to_string(),
+        Some(length) => return length,
+        None if bit_length == 0 => format!(
+            "A {} Hz sampling rate is too low to give a {} baud bit a sample",
+            opt.sampling_rate, channel.baud
+        ),
+        None => return bit_length,
+    };
+    Error::with_description(&description, ErrorKind::InvalidValue).exit()
+}
+
+/// Exits when `sampling_rate` is too low for the UART receiver to give a bit of `channel` a
+/// sample
+fn check_channel(channel: Channel, sampling_rate: f64) {
+    framing::check_sampling_rate(channel, sampling_rate)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+}
+
+/// The filter length each bit is decided over: the bit's own length, unless `--calibrate`
+/// finds one telling the tones apart better. Each channel is calibrated once, when it's first
+/// decoded, with the table of how each length did shown then.
+fn filter_window(opt: &Opt, channel: Channel, bit_length: usize) -> usize {
+    static CALIBRATED: Mutex<Vec<(Channel, usize)>> = Mutex::new(Vec::new());
+    if !opt.calibrate {
+        return bit_length;
+    }
+    let mut calibrated = CALIBRATED.lock().unwrap();
+    if let Some(&(_, window)) = calibrated.iter().find(|(tuned, _)| *tuned == channel) {
+        return window;
+    }
+    let calibrations = calibrate::calibrate(channel, opt.sampling_rate, bit_length, |window| {
+        Demodulator::with_detector(window, detector(opt, channel, window))
+    });
+    eprintln!(
+        "Calibrating {}/{} Hz at {} baud, {} samples a bit:",
+        channel.mark, channel.space, channel.baud, bit_length
+    );
+    eprintln!("  window  overlap  margin  errors");
+    for calibration in &calibrations {
+        eprintln!(
+            "  {:>6}  {:>+6.0}%  {:>6.3}  {:>6}",
+            calibration.window,
+            calibration.overlap() * 100.0,
+            calibration.margin,
+            calibration.errors
+        );
+    }
+    let window = calibrations[0].window;
+    eprintln!("Decoding with a {} sample filter", window);
+    calibrated.push((channel, window));
+    window
+}
+
+/// Applies our filters to each bit-length block and decodes the resulting frames, taking the
+/// recording in chunks. Each block's bit is deframed as it's decided by an [`Assembler`], so
+/// only the samples a bit still to be decided needs are kept, and blocks fall where they would
+/// if it were all read at once. The characters of each chunk are handed to `decoded`, and
+/// decoding stops there if it returns false.
+fn demodulate_chunks<I, F>(opt: &Opt, chunks: I, channel: Channel, mut decoded: F) -> Vec<u8>
+where
+    I: IntoIterator,
+    I::Item: AsRef<[i16]>,
+    F: FnMut(&[u8]) -> bool,
+{
+    let bit_length = filter_length(opt, channel);
+    let window = filter_window(opt, channel, bit_length);
+    let demodulator = Demodulator::with_detector(window, detector(opt, channel, window));
+    let mut assembler = Assembler::with_bit_length(demodulator, bit_length)
+        .unwrap_or_else(|e| Error::with_description(&e, ErrorKind::InvalidValue).exit());
+    let mut bytes = Vec::new();
+    for chunk in chunks {
+        let start = bytes.len();
+        assembler.push(chunk.as_ref(), &mut bytes);
+        if !decoded(&bytes[start..]) {
+            return bytes;
+        }
     }
+    let start = bytes.len();
+    assembler.finish(&mut bytes);
+    decoded(&bytes[start..]);
+    bytes
+}
+
+/// A thread writing output, joining to the error that stopped it if it failed
+type Writing<'scope> = thread::ScopedJoinHandle<'scope, io::Result<()>>;
 
-    fn run_test(filter: &mut GoertzelFilter, frequency: f64) {
-        eprintln!("For test frequency {:.6}:", frequency);
+/// Writes the characters of each chunk to the output as it's decoded for `--follow`, which is
+/// opened once at the start so a FIFO's reader sees one continuous stream. They're written on
+/// a thread of their own in `scope`, so a reader slow to take them doesn't hold up decoding.
+/// The closure returned says whether to go on decoding, which once writing's failed, e.g.
+/// because the FIFO's reader has gone, there's no point in, and the handle joins to the error.
+fn follow_output<'scope>(
+    opt: &Opt,
+    scope: &'scope thread::Scope<'scope, '_>,
+) -> (impl FnMut(&[u8]) -> bool + 'scope, Option<Writing<'scope>>) {
+    let (written, writing) = match opt.output.as_ref().filter(|_| opt.follow) {
+        Some(path) => {
+            let mut file = File::create(path).unwrap_or_else(|e| {
+                Error::with_description(
+                    &format!("Couldn't open {}: {}", path.display(), e),
+                    ErrorKind::Io,
+                )
+                .exit()
+            });
+            let (written, writing) = pipeline::sink(scope, OUTPUT_QUEUE, move |text: Vec<u8>| {
+                file.write_all(&text).and_then(|_| file.flush())
+            });
+            (Some(written), Some(writing))
+        }
+        None => (None, None),
+    };
+    let follow = move |bytes: &[u8]| match &written {
+        Some(written) if !bytes.is_empty() => written
+            .send(bytes.iter().map(|&b| b & 0x7f).collect())
+            .is_ok(),
+        _ => true,
+    };
+    (follow, writing)
+}
 
-        let samples = generate_test_samples(frequency);
-        let samples: Vec<i16> = samples.iter().map(|s| *s as i16).collect();
-        filter.process(&samples);
+/// Drops anything received before the preamble and reads the rest as 7-bit ASCII
+fn message_text(opt: &Opt, bytes: &[u8]) -> String {
+    let data = match &opt.preamble {
+        Some(preamble) => preamble.strip(bytes).unwrap_or_else(|| {
+            eprintln!("Preamble not found, no data accepted");
+            &[]
+        }),
+        None => bytes,
+    };
+    let mut text = String::with_capacity(data.len());
+    text.write_bytes(data);
+    text
+}
 
-        let (real, imag) = filter.get_real_imag();
-        eprintln!("real = {:.6} imag = {:.6}", real, imag);
+/// Maps a recording into memory for `--mmap`
+fn map_wav(path: &Path) -> audio::MappedWav {
+    let file = File::open(path).unwrap();
+    audio::MappedWav::open(&file).unwrap_or_else(|e| {
+        Error::with_description(
+            &format!("Couldn't map {}: {}", path.display(), e),
+            ErrorKind::Io,
+        )
+        .exit()
+    })
+}
 
-        let mag_sq = real * real + imag * imag;
-        eprintln!("Relative magnitude squared = {:.6}", mag_sq);
-        eprintln!("Relative magnitude = {:.6}", mag_sq.sqrt());
+/// Reads the recording to decode a chunk at a time, from stdin for `-`, as a WAV file,
+/// headerless with `--raw` or demodulated from IQ with `--iq`
+fn input_chunks(opt: &Opt, path: &Path) -> Box<dyn Iterator<Item = Vec<i16>>> {
+    let chunks = read_chunks(opt, open_input(path), mixdown(opt));
+    Box::new(with_gain(opt, read_or_exit(chunks)))
+}
 
-        eprintln!("Relative magnitude squared = {:.6}", filter.get_mag_sq());
-        eprintln!("Relative magnitude = {:.6}\n", filter.get_mag_sq().sqrt());
+fn open_input(path: &Path) -> Box<dyn Read + Send> {
+    if path == Path::new("-") {
+        Box::new(io::stdin())
+    } else {
+        Box::new(io::BufReader::new(File::open(path).unwrap()))
     }
+}
 
-    #[test]
-    fn test_goertzel_filter_target() {
-        let mut filter = GoertzelFilter::new(BLOCK_SIZE, TARGET_FREQUENCY, SAMPLING_RATE);
-        eprint!("\nFor SAMPLING_RATE = {:.6}", SAMPLING_RATE);
-        eprint!(" N = {}", BLOCK_SIZE);
-        eprintln!(" and FREQUENCY = {:.6},", TARGET_FREQUENCY);
-        eprintln!("k = {} and coeff = {:.6}\n", filter.k, filter.coeff);
+/// Mono samples from `reader` as [`input_chunks`] takes them, made mono as `mixdown` says
+fn read_chunks<'a>(
+    opt: &Opt,
+    reader: Box<dyn Read + 'a>,
+    mixdown: Mixdown,
+) -> Box<dyn Iterator<Item = io::Result<Vec<i16>>> + 'a> {
+    if let Some(format) = opt.iq {
+        Box::new(sdr::read_iq_chunks(
+            reader,
+            format,
+            fm_receiver(opt),
+            IQ_FRAMES,
+        ))
+    } else if opt.raw {
+        let chunks = audio::read_raw_chunks(reader, raw_spec(opt), audio::CHUNK_FRAMES);
+        Box::new(chunks.unwrap().mixdown(mixdown))
+    } else {
+        let chunks = audio::read_wav_chunks(reader, audio::CHUNK_FRAMES).unwrap();
+        Box::new(chunks.mixdown(mixdown).resample_to(opt.sampling_rate))
+    }
+}
 
-        run_test(&mut filter, TARGET_FREQUENCY - 250.0);
-        let (real, imag) = filter.get_real_imag();
-        assert_eq!(real.floor(), -316.0);
-        assert_eq!(imag.floor(), -187.0);
-        assert_eq!(filter.get_mag_sq().floor(), 134338.0);
-        filter.reset();
+/// Archives blocks of incoming samples to the `--record` file as they pass, before any
+/// `--gain`, so they can be decoded again with other settings
+fn with_recording<I>(opt: &Opt, blocks: I) -> impl Iterator<Item = Vec<i16>>
+where
+    I: IntoIterator<Item = Vec<i16>>,
+{
+    let failed = |path: &Path, e: io::Error| -> ! {
+        Error::with_description(
+            &format!("Couldn't record to {}: {}", path.display(), e),
+            ErrorKind::Io,
+        )
+        .exit()
+    };
+    let path = opt.record.clone();
+    let mut recorder = path.as_ref().map(|path| {
+        audio::WavRecorder::create(path, opt.sampling_rate as u32)
+            .unwrap_or_else(|e| failed(path, e))
+    });
+    blocks.into_iter().inspect(move |block| {
+        if let (Some(recorder), Some(path)) = (&mut recorder, &path) {
+            recorder.write(block).unwrap_or_else(|e| failed(path, e));
+        }
+    })
+}
 
-        run_test(&mut filter, TARGET_FREQUENCY);
-        let (real, imag) = filter.get_real_imag();
-        assert_eq!(real.floor(), -191.0);
-        assert_eq!(imag.floor(), -10196.0);
-        assert_eq!(filter.get_mag_sq().floor(), 103981719.0);
-        filter.reset();
+/// Applies `--gain` to blocks of incoming samples, if it's given
+fn with_gain<I>(opt: &Opt, blocks: I) -> impl Iterator<Item = Vec<i16>>
+where
+    I: IntoIterator<Item = Vec<i16>>,
+{
+    let mut gain = opt.gain.map(InputGain::new);
+    blocks.into_iter().map(move |mut block| {
+        if let Some(gain) = &mut gain {
+            gain.process(&mut block);
+        }
+        block
+    })
+}
 
-        run_test(&mut filter, TARGET_FREQUENCY + 250.0);
-        let (real, imag) = filter.get_real_imag();
-        assert_eq!(real.floor(), 596.0);
-        assert_eq!(imag.floor(), -177.0);
-        assert_eq!(filter.get_mag_sq().floor(), 387565.0);
-        filter.reset();
+/// An FM receiver for `--iq` tuned to `--tune`, which has to be within the recorded bandwidth
+fn fm_receiver(opt: &Opt) -> FmReceiver {
+    let iq_rate = sdr_rate(opt);
+    let center = sdr_center(opt);
+    let offset = opt.tune.unwrap_or(center) - center;
+    if offset.abs() >= iq_rate / 2.0 {
+        Error::with_description(
+            &format!(
+                "--tune is {} Hz from --center, outside the {} Hz an IQ recording at --iq_rate {} holds either side",
+                offset,
+                iq_rate / 2.0,
+                iq_rate
+            ),
+            ErrorKind::InvalidValue,
+        )
+        .exit()
     }
+    FmReceiver::new(iq_rate, offset, opt.sampling_rate)
+}
 
-    #[test]
-    fn test_goertzel_filter_sweep() {
-        let mut filter = GoertzelFilter::new(BLOCK_SIZE, TARGET_FREQUENCY, SAMPLING_RATE);
-        let mut freq = TARGET_FREQUENCY - 300.0;
-        let end = TARGET_FREQUENCY + 300.0;
-        while freq <= end {
-            eprint!("Freq={:7.1}   ", freq);
+fn sdr_rate(opt: &Opt) -> f64 {
+    opt.iq_rate.unwrap_or(2_400_000.0)
+}
 
-            let samples = generate_test_samples(freq);
-            let samples: Vec<i16> = samples.iter().map(|s| *s as i16).collect();
-            filter.process(&samples);
+/// Where `--center` puts the SDR, tuning rtl_tcp off the signal so it stays clear of the
+/// spike dongles have at DC
+fn sdr_center(opt: &Opt) -> f64 {
+    match (opt.center, opt.tune) {
+        (Some(center), _) => center,
+        (None, Some(tune)) if opt.rtl_tcp.is_some() => tune + sdr_rate(opt) / 4.0,
+        (None, _) => 0.0,
+    }
+}
 
-            let (real, imag) = filter.get_real_imag();
-            let mag_sq = real * real + imag * imag;
-            eprint!("rel mag^2={:16.5}   ", mag_sq);
-            eprintln!("rel mag={:12.5}", mag_sq.sqrt());
+/// What `--raw` and its options say headerless samples are
+fn raw_spec(opt: &Opt) -> RawSpec {
+    RawSpec {
+        format: opt.format.unwrap_or(PcmFormat::S16le),
+        endian: opt.endian.unwrap_or(Endian::Little),
+        channels: opt.channels.unwrap_or(1),
+        layout: opt.layout.unwrap_or(Layout::Interleaved),
+    }
+}
 
-            freq += 15.0;
-            filter.reset();
+/// How `--input_channel` makes a multi-channel recording mono, scoring channels for `best`
+/// against whichever bands might be decoded
+fn mixdown(opt: &Opt) -> Mixdown {
+    match opt.input_channel.unwrap_or(ChannelPick::Mix) {
+        ChannelPick::Mix => Mixdown::Mix,
+        ChannelPick::Number(number) => Mixdown::Only(number - 1),
+        ChannelPick::Best => {
+            let bands = match opt.standard {
+                Selection::Fixed(standard) if opt.both => {
+                    vec![standard.originate(), standard.answer()]
+                }
+                Selection::Fixed(standard) => vec![standard.channel(opt.origin)],
+                Selection::Auto => Standard::ALL
+                    .iter()
+                    .flat_map(|standard| vec![standard.originate(), standard.answer()])
+                    .collect(),
+            };
+            Mixdown::Best {
+                bands,
+                sampling_rate: opt.sampling_rate,
+            }
         }
     }
 }
+
+/// Reads a recording a chunk at a time, resampled to `sampling_rate` if it was made at another
+fn wav_chunks(path: &Path, sampling_rate: f64) -> impl Iterator<Item = Vec<i16>> {
+    let file = io::BufReader::new(File::open(path).unwrap());
+    let chunks = audio::read_wav_chunks(file, audio::CHUNK_FRAMES).unwrap();
+    read_or_exit(chunks.resample_to(sampling_rate))
+}
+
+/// The samples of each chunk as it's read, exiting with the error if one can't be
+fn read_or_exit<T>(chunks: impl Iterator<Item = io::Result<T>>) -> impl Iterator<Item = T> {
+    chunks.map(|chunk| {
+        chunk.unwrap_or_else(|e| {
+            Error::with_description(
+                &format!("Couldn't read the recording: {}", e),
+                ErrorKind::Io,
+            )
+            .exit()
+        })
+    })
+}
@@ -0,0 +1,208 @@
+use crate::events::{Event, Messages, Sink};
+use crate::http;
+use std::fmt::Write as _;
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// What a stream has decoded so far
+#[derive(Default)]
+struct Counts {
+    samples: u64,
+    characters: u64,
+    framing_errors: u64,
+    messages: u64,
+    carrier: bool,
+    snr: Option<f64>,
+    level: Option<f64>,
+    gathered: Messages,
+}
+
+/// Counters and gauges of a decoded stream, in Prometheus' text format at `/metrics`. The
+/// decoder observes each block of audio, and the events decoded from it are taken as a
+/// [`Sink`].
+#[derive(Clone, Default)]
+pub struct Metrics {
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Metrics {
+    /// Serves `/metrics` on `address`, written `host:port` or `:port` for every interface
+    pub fn serve(&self, address: &str) -> io::Result<SocketAddr> {
+        let address = match address.strip_prefix(':') {
+            Some(port) => format!("0.0.0.0:{}", port),
+            None => address.to_string(),
+        };
+        let listener = TcpListener::bind(address)?;
+        let local = listener.local_addr()?;
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for client in listener.incoming().flatten() {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    let request = BufReader::new(&client);
+                    let _ = match http::read_request(request) {
+                        Ok(request) if request.method == "GET" && request.path == "/metrics" => {
+                            let text = metrics.render();
+                            let content_type = "text/plain; version=0.0.4";
+                            http::respond(&client, "200 OK", content_type, text.as_bytes())
+                        }
+                        Ok(_) => http::respond(&client, "404 Not Found", "text/plain", b""),
+                        Err(_) => http::respond(&client, "400 Bad Request", "text/plain", b""),
+                    };
+                });
+            }
+        });
+        Ok(local)
+    }
+
+    /// Notes a block of audio that's been decoded, with how many characters were dropped for
+    /// bad framing in it
+    pub fn observe(&self, block: &[i16], framing_errors: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.samples += block.len() as u64;
+        counts.framing_errors += framing_errors;
+        if !block.is_empty() {
+            let power = block.iter().map(|&s| f64::from(s).powi(2)).sum::<f64>();
+            let rms = (power / block.len() as f64).sqrt();
+            counts.level = Some(20.0 * (rms / 32768.0).log10());
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let counts = self.counts.lock().unwrap();
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = write!(
+                text,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n",
+                name = name,
+                kind = kind,
+                help = help,
+                value = value
+            );
+        };
+        metric(
+            "bell103_samples_total",
+            "counter",
+            "Audio samples decoded",
+            counts.samples.to_string(),
+        );
+        metric(
+            "bell103_characters_total",
+            "counter",
+            "Characters decoded",
+            counts.characters.to_string(),
+        );
+        metric(
+            "bell103_framing_errors_total",
+            "counter",
+            "Characters dropped for a bad parity or stop bit",
+            counts.framing_errors.to_string(),
+        );
+        metric(
+            "bell103_messages_total",
+            "counter",
+            "Messages completed when the carrier went",
+            counts.messages.to_string(),
+        );
+        metric(
+            "bell103_carrier",
+            "gauge",
+            "Whether a carrier is heard",
+            u8::from(counts.carrier).to_string(),
+        );
+        metric(
+            "bell103_snr_db",
+            "gauge",
+            "SNR in the channel's band over the last second",
+            value(counts.snr),
+        );
+        metric(
+            "bell103_input_level_dbfs",
+            "gauge",
+            "RMS level of the last block of audio relative to full scale",
+            value(counts.level),
+        );
+        text
+    }
+}
+
+/// A gauge's value as Prometheus writes it, NaN before there's been one
+fn value(value: Option<f64>) -> String {
+    match value {
+        None => "NaN".to_string(),
+        Some(value) if value == f64::INFINITY => "+Inf".to_string(),
+        Some(value) if value == f64::NEG_INFINITY => "-Inf".to_string(),
+        Some(value) => format!("{:.1}", value),
+    }
+}
+
+impl Sink for Metrics {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        let mut counts = self.counts.lock().unwrap();
+        match event {
+            Event::Text(text) => counts.characters += text.chars().count() as u64,
+            Event::Carrier(present) => counts.carrier = *present,
+            Event::Snr(db) => counts.snr = Some(*db),
+            Event::Message(_) => {}
+        }
+        if counts.gathered.take(event).is_some() {
+            counts.messages += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_metrics() {
+        let mut metrics = Metrics::default();
+        let address = metrics.serve("127.0.0.1:0").unwrap();
+        assert!(metrics.render().contains("\nbell103_snr_db NaN\n"));
+
+        metrics.observe(&[16384, -16384], 0);
+        metrics.observe(&[0; 4], 2);
+        assert!(metrics
+            .render()
+            .contains("\nbell103_input_level_dbfs -Inf\n"));
+        metrics.observe(&[16384, -16384], 1);
+        for event in &[
+            Event::Carrier(true),
+            Event::Snr(21.34),
+            Event::Text("login:"),
+            Event::Carrier(false),
+        ] {
+            metrics.take(event).unwrap();
+        }
+
+        let get = |path: &str| {
+            let mut client = TcpStream::connect(address).unwrap();
+            write!(client, "GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        for line in &[
+            "# TYPE bell103_characters_total counter",
+            "bell103_samples_total 8",
+            "bell103_characters_total 6",
+            "bell103_framing_errors_total 3",
+            "bell103_messages_total 1",
+            "bell103_carrier 0",
+            "bell103_snr_db 21.3",
+            "bell103_input_level_dbfs -6.0",
+        ] {
+            assert!(response.contains(&format!("\n{}\n", line)), "{}", line);
+        }
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::demodulator::TICKS_PER_BIT;
+use crate::goertzel::GoertzelBank;
+use crate::modulator::{Oscillator, AMPLITUDE};
+
+/// Blocks quieter than this fraction of the loudest are taken as silence around the signal
+const SILENCE: f64 = 0.01;
+
+/// Evenly spaced tones, one per symbol value, from the lowest up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneGrid {
+    pub lowest: f64,
+    pub spacing: f64,
+    /// Number of tones, a power of two so each symbol carries whole bits
+    pub tones: usize,
+}
+
+impl ToneGrid {
+    pub fn frequency(&self, tone: usize) -> f64 {
+        self.lowest + self.spacing * tone as f64
+    }
+
+    pub fn frequencies(&self) -> Vec<f64> {
+        (0..self.tones).map(|tone| self.frequency(tone)).collect()
+    }
+
+    /// Bits per symbol, e.g. di-bits for 4-FSK and tri-bits for 8-FSK
+    pub fn bits_per_symbol(&self) -> u32 {
+        self.tones.trailing_zeros()
+    }
+}
+
+/// Gray coding puts neighbouring tones one bit apart, so mistaking a tone for the next costs
+/// only one bit
+fn gray(value: usize) -> usize {
+    value ^ (value >> 1)
+}
+
+fn from_gray(mut code: usize) -> usize {
+    let mut value = code;
+    while code > 0 {
+        code >>= 1;
+        value ^= code;
+    }
+    value
+}
+
+/// Splits bytes into symbol values, most significant bits first, padding the last symbol
+/// with zeros
+pub fn symbols(bytes: &[u8], bits_per_symbol: u32) -> Vec<usize> {
+    let bits: Vec<usize> = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| usize::from(byte >> i & 1)))
+        .collect();
+    bits.chunks(bits_per_symbol as usize)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0, |value, &bit| value << 1 | bit);
+            value << (bits_per_symbol as usize - chunk.len())
+        })
+        .collect()
+}
+
+/// Packs symbol values back into bytes, dropping any bits left over at the end
+pub fn bytes(symbols: &[usize], bits_per_symbol: u32) -> Vec<u8> {
+    let bits: Vec<u8> = symbols
+        .iter()
+        .flat_map(|&symbol| {
+            (0..bits_per_symbol)
+                .rev()
+                .map(move |i| (symbol >> i & 1) as u8)
+        })
+        .collect();
+    bits.chunks_exact(8)
+        .map(|chunk| chunk.iter().fold(0, |byte, &bit| byte << 1 | bit))
+        .collect()
+}
+
+/// Generates phase-continuous MFSK audio, one tone per symbol
+pub fn modulate(
+    symbols: &[usize],
+    grid: ToneGrid,
+    baud: f64,
+    gray_coded: bool,
+    sampling_rate: f64,
+) -> Vec<i16> {
+    let samples_per_symbol = sampling_rate / baud;
+    let mut oscillator = Oscillator::new(grid.frequency(0), sampling_rate);
+    let mut samples = Vec::new();
+    for (i, &symbol) in symbols.iter().enumerate() {
+        let tone = if gray_coded { gray(symbol) } else { symbol };
+        oscillator.set_frequency(grid.frequency(tone));
+        let end = ((i + 1) as f64 * samples_per_symbol).round() as usize;
+        while samples.len() < end {
+            samples.push((oscillator.next_sample() * AMPLITUDE) as i16);
+        }
+    }
+    samples
+}
+
+/// The strongest tone over one symbol period
+#[derive(Debug, Clone, Copy)]
+struct Slice {
+    tone: usize,
+    /// Fraction of the energy at all tones that's at the strongest
+    share: f64,
+    energy: f64,
+}
+
+/// How cleanly the slices separate into single tones, ignoring silence around the signal
+fn clarity(slices: &[Slice]) -> f64 {
+    let loudest = slices.iter().map(|slice| slice.energy).fold(0.0, f64::max);
+    let signal: Vec<f64> = slices
+        .iter()
+        .filter(|slice| slice.energy >= SILENCE * loudest)
+        .map(|slice| slice.share)
+        .collect();
+    signal.iter().sum::<f64>() / signal.len().max(1) as f64
+}
+
+/// Decides each symbol as the strongest tone of the grid over one symbol period
+#[derive(Debug)]
+pub struct MfskDemodulator {
+    bank: GoertzelBank,
+    /// The energy at each tone over the last symbol
+    energies: Vec<f64>,
+    samples_per_symbol: f64,
+    block_size: usize,
+    gray_coded: bool,
+}
+
+impl MfskDemodulator {
+    pub fn new(grid: ToneGrid, baud: f64, gray_coded: bool, sampling_rate: f64) -> Self {
+        let samples_per_symbol = sampling_rate / baud;
+        let block_size = samples_per_symbol.round() as usize;
+        Self {
+            bank: GoertzelBank::new(grid.frequencies(), sampling_rate),
+            energies: vec![0.0; grid.tones],
+            samples_per_symbol,
+            block_size,
+            gray_coded,
+        }
+    }
+
+    /// Slices every symbol period starting `offset` samples in, as long as at least half of
+    /// the period was recorded
+    fn slice(&mut self, samples: &[i16], offset: usize) -> Vec<Slice> {
+        let mut slices = Vec::new();
+        loop {
+            let start = offset + (slices.len() as f64 * self.samples_per_symbol).round() as usize;
+            // The last symbol may be cut short by the offset or the end of the recording
+            if start + self.block_size / 2 > samples.len() {
+                return slices;
+            }
+            let end = (start + self.block_size).min(samples.len());
+            self.bank
+                .energies_into(&samples[start..end], &mut self.energies);
+            let energy: f64 = self.energies.iter().sum();
+            let (tone, strongest) =
+                self.energies
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .fold((0, 0.0), |best, (tone, energy)| {
+                        if energy > best.1 {
+                            (tone, energy)
+                        } else {
+                            best
+                        }
+                    });
+            let share = if energy > 0.0 {
+                strongest / energy
+            } else {
+                0.0
+            };
+            slices.push(Slice {
+                tone,
+                share,
+                energy,
+            });
+        }
+    }
+
+    /// Slices at whichever of `offsets` gives the clearest tones
+    fn clearest(
+        &mut self,
+        samples: &[i16],
+        offsets: impl Iterator<Item = usize>,
+    ) -> (usize, Vec<Slice>) {
+        let mut best: Option<(f64, usize, Vec<Slice>)> = None;
+        for offset in offsets {
+            let slices = self.slice(samples, offset);
+            let clarity = clarity(&slices);
+            if best.as_ref().is_none_or(|best| clarity > best.0) {
+                best = Some((clarity, offset, slices));
+            }
+        }
+        best.map(|(_, offset, slices)| (offset, slices))
+            .unwrap_or_default()
+    }
+
+    /// Returns the symbol values in a recording. A block straddling two symbols splits its
+    /// energy between their tones, so symbol timing is found by trying offsets across one
+    /// symbol period, then to the sample around the best, and keeping the one where the
+    /// strongest tones stand out most.
+    pub fn symbols(&mut self, samples: &[i16]) -> Vec<usize> {
+        let step = (self.block_size / TICKS_PER_BIT).max(1);
+        let (coarse, _) = self.clearest(samples, (0..self.block_size).step_by(step));
+        let fine = coarse.saturating_sub(step)..coarse + step;
+        let (_, slices) = self.clearest(samples, fine);
+
+        let loudest = slices.iter().map(|slice| slice.energy).fold(0.0, f64::max);
+        let audible = |slice: &Slice| slice.energy >= SILENCE * loudest;
+        let start = slices.iter().position(audible);
+        let end = slices.iter().rposition(audible);
+        match (start, end) {
+            (Some(start), Some(end)) => slices[start..=end]
+                .iter()
+                .map(|slice| {
+                    if self.gray_coded {
+                        from_gray(slice.tone)
+                    } else {
+                        slice.tone
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_and_bytes() {
+        assert_eq!(symbols(&[0b1101_0010], 2), vec![3, 1, 0, 2]);
+        assert_eq!(symbols(&[0b1101_0010], 3), vec![6, 4, 4]);
+        assert_eq!(bytes(&[6, 4, 4], 3), vec![0b1101_0010]);
+        for value in 0..16 {
+            assert_eq!(from_gray(gray(value)), value);
+            assert_eq!((gray(value) ^ gray(value + 1)).count_ones(), 1);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let message = b"MFSK telemetry 0123456789";
+        for &(tones, spacing, baud) in &[(4, 100.0, 100.0), (8, 62.5, 31.25), (16, 50.0, 50.0)] {
+            let grid = ToneGrid {
+                lowest: 1000.0,
+                spacing,
+                tones,
+            };
+            let sent = symbols(message, grid.bits_per_symbol());
+            let mut samples = vec![0; 1234];
+            samples.extend(modulate(&sent, grid, baud, true, 8_000.0));
+            samples.extend(vec![0; 777]);
+
+            let mut demodulator = MfskDemodulator::new(grid, baud, true, 8_000.0);
+            let received = demodulator.symbols(&samples);
+            assert_eq!(received, sent, "{} tones", tones);
+            assert_eq!(bytes(&received, grid.bits_per_symbol()), message);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::baudot;
+use crate::framing;
+use crate::modulator::FskModulator;
+use crate::rtty;
+use crate::standard::Channel;
+use crate::tdd;
+
+/// Mark sent before and after a transmission, so a receiver sees the idle line first
+const LEADER_BITS: f64 = 10.0;
+
+/// Tones and character format for one of minimodem's baud modes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mode {
+    pub channel: Channel,
+    pub data_bits: u32,
+    pub stop_bits: f64,
+    /// Whether a space returns Baudot to letters, which TDDs don't do
+    pub unshift_on_space: bool,
+}
+
+impl Mode {
+    /// Picks the settings for a baud mode, either a bit rate or `rtty` or `tdd`, the way
+    /// minimodem does. Missing tones default by rate to Bell 202 from 400 baud, Bell 103
+    /// from 100 baud and 170 Hz shift RTTY below that, keeping the usual shift when only
+    /// one tone is given.
+    pub fn new(baudmode: &str, mark: Option<f64>, space: Option<f64>) -> Result<Self, String> {
+        let (baud, data_bits, stop_bits) = match baudmode {
+            "rtty" => (45.45, 5, 1.5),
+            "tdd" => (tdd::CHANNEL.baud, 5, 2.0),
+            _ => match baudmode.parse::<f64>() {
+                Ok(baud) if baud > 0.0 => (baud, 8, 1.0),
+                _ => {
+                    return Err(format!(
+                        "unknown baud mode `{}`, expected a bit rate, rtty or tdd",
+                        baudmode
+                    ))
+                }
+            },
+        };
+        let (default_mark, shift) = if baudmode == "tdd" {
+            (tdd::CHANNEL.mark, tdd::CHANNEL.mark - tdd::CHANNEL.space)
+        } else if baud >= 400.0 {
+            (baud / 2.0 + 600.0, -baud * 5.0 / 6.0)
+        } else if baud >= 100.0 {
+            (1270.0, 200.0)
+        } else {
+            (1585.0, 170.0)
+        };
+        let mark = mark.unwrap_or(default_mark);
+        Ok(Self {
+            channel: Channel {
+                mark,
+                space: space.unwrap_or(mark - shift),
+                baud,
+            },
+            data_bits,
+            stop_bits,
+            unshift_on_space: baudmode != "tdd",
+        })
+    }
+
+    /// Modulates text, sending Baudot when there are 5 data bits
+    pub fn modulate(&self, text: &[u8], sampling_rate: f64) -> Vec<i16> {
+        let codes = if self.data_bits == 5 {
+            baudot::encode(&String::from_utf8_lossy(text))
+        } else {
+            text.to_vec()
+        };
+        let channel = self.channel;
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, sampling_rate);
+        let mut samples = Vec::new();
+        modulator.hold(1, LEADER_BITS, &mut samples);
+        for code in codes {
+            modulator.hold(0, 1.0, &mut samples);
+            for i in 0..self.data_bits {
+                modulator.hold((code >> i) & 1, 1.0, &mut samples);
+            }
+            modulator.hold(1, self.stop_bits, &mut samples);
+        }
+        modulator.hold(1, LEADER_BITS, &mut samples);
+        samples
+    }
+
+    /// Recovers the text from a recording, reading 5 data bits as Baudot
+    pub fn demodulate(&self, samples: &[i16], sampling_rate: f64) -> Vec<u8> {
+        if self.data_bits == 5 {
+            rtty::decode(samples, self.channel, sampling_rate, self.unshift_on_space).into_bytes()
+        } else {
+            let (received, _) =
+                framing::receive(samples, self.channel, sampling_rate, self.data_bits);
+            received.iter().map(|received| received.byte).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_defaults() {
+        let mode = |baudmode, mark, space| Mode::new(baudmode, mark, space).unwrap().channel;
+        let tones = |channel: Channel| (channel.mark, channel.space, channel.baud);
+        assert_eq!(tones(mode("300", None, None)), (1270.0, 1070.0, 300.0));
+        assert_eq!(tones(mode("1200", None, None)), (1200.0, 2200.0, 1200.0));
+        assert_eq!(tones(mode("rtty", None, None)), (1585.0, 1415.0, 45.45));
+        assert_eq!(tones(mode("tdd", None, None)), (1400.0, 1800.0, 45.45));
+        assert_eq!(
+            tones(mode("300", Some(2225.0), None)),
+            (2225.0, 2025.0, 300.0)
+        );
+        assert_eq!(
+            tones(mode("110", Some(1270.0), Some(1070.0))),
+            (1270.0, 1070.0, 110.0)
+        );
+        assert!(Mode::new("fast", None, None).is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &baudmode in &["300", "1200", "rtty", "tdd"] {
+            let mut mode = Mode::new(baudmode, None, None).unwrap();
+            let text: &[u8] = if mode.data_bits == 5 {
+                b"CQ CQ DE N0CALL 599\n"
+            } else {
+                b"Hello, world!\n"
+            };
+            let samples = mode.modulate(text, 48_000.0);
+            assert_eq!(mode.demodulate(&samples, 48_000.0), text, "{}", baudmode);
+
+            mode.data_bits = 7;
+            mode.stop_bits = 2.0;
+            let samples = mode.modulate(b"7 bit", 48_000.0);
+            assert_eq!(
+                mode.demodulate(&samples, 48_000.0),
+                b"7 bit",
+                "{}",
+                baudmode
+            );
+        }
+    }
+}
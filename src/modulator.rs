@@ -0,0 +1,107 @@
+use std::f64::consts::PI;
+
+/// Peak amplitude of generated tones, about -6 dBFS to leave some headroom
+pub const AMPLITUDE: f64 = 16_384.0;
+
+/// Phase accumulating sine oscillator shared by the tone generators. Changing frequency keeps
+/// the phase continuous so there are no clicks at tone boundaries.
+#[derive(Debug)]
+pub struct Oscillator {
+    sampling_rate: f64,
+    phase: f64,
+    step: f64,
+}
+
+impl Oscillator {
+    pub fn new(frequency: f64, sampling_rate: f64) -> Self {
+        let mut oscillator = Self {
+            sampling_rate,
+            phase: 0.0,
+            step: 0.0,
+        };
+        oscillator.set_frequency(frequency);
+        oscillator
+    }
+
+    pub fn set_frequency(&mut self, frequency: f64) {
+        self.step = 2.0 * PI * frequency / self.sampling_rate;
+    }
+
+    /// Returns the next sample in [-1.0, 1.0]
+    pub fn next_sample(&mut self) -> f64 {
+        let sample = self.phase.sin();
+        self.phase = (self.phase + self.step) % (2.0 * PI);
+        sample
+    }
+}
+
+/// Generates phase-continuous FSK audio from a sequence of bits
+#[derive(Debug)]
+pub struct FskModulator {
+    oscillator: Oscillator,
+    mark_frequency: f64,
+    space_frequency: f64,
+    samples_per_bit: f64,
+    clock: f64,
+}
+
+impl FskModulator {
+    pub fn new(mark_frequency: f64, space_frequency: f64, baud: f64, sampling_rate: f64) -> Self {
+        Self {
+            oscillator: Oscillator::new(mark_frequency, sampling_rate),
+            mark_frequency,
+            space_frequency,
+            samples_per_bit: sampling_rate / baud,
+            clock: 0.0,
+        }
+    }
+
+    /// Appends the audio for `bits` to `samples`. Phase and fractional bit timing carry over
+    /// between calls so a message can be modulated in pieces.
+    pub fn modulate(&mut self, bits: &[u8], samples: &mut Vec<i16>) {
+        for &bit in bits {
+            self.hold(bit, 1.0, samples);
+        }
+    }
+
+    /// Appends a tone for `bit` lasting a possibly fractional number of bit periods, e.g.
+    /// the 1.5 stop bits of Baudot framing
+    pub fn hold(&mut self, bit: u8, periods: f64, samples: &mut Vec<i16>) {
+        self.oscillator.set_frequency(if bit == 1 {
+            self.mark_frequency
+        } else {
+            self.space_frequency
+        });
+        self.clock += self.samples_per_bit * periods;
+        while self.clock >= 1.0 {
+            samples.push((AMPLITUDE * self.oscillator.next_sample()) as i16);
+            self.clock -= 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demodulator::Demodulator;
+
+    #[test]
+    fn test_modulate_demodulate() {
+        let bits = [0, 1, 1, 0, 1, 0, 0, 0, 1, 1];
+        let mut modulator = FskModulator::new(2225.0, 2025.0, 300.0, 48_000.0);
+        let mut samples = Vec::new();
+        modulator.modulate(&bits, &mut samples);
+        assert_eq!(samples.len(), bits.len() * 160);
+
+        let mut demodulator = Demodulator::new(160, 2225.0, 2025.0, 48_000.0);
+        assert_eq!(demodulator.bits(&samples), bits);
+    }
+
+    #[test]
+    fn test_fractional_bit_period() {
+        let mut modulator = FskModulator::new(1400.0, 1800.0, 45.45, 8_000.0);
+        let mut samples = Vec::new();
+        modulator.modulate(&[1; 100], &mut samples);
+        assert_eq!(samples.len(), (100.0 * 8_000.0 / 45.45) as usize);
+    }
+}
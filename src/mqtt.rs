@@ -0,0 +1,191 @@
+use crate::events::{Event, Messages, Sink};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+
+const DEFAULT_PORT: u16 = 1883;
+/// Packet types, in the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+
+/// A broker and topic to publish to, written `mqtt://host[:port]/topic`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MqttTarget {
+    pub address: String,
+    pub topic: String,
+}
+
+impl FromStr for MqttTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("mqtt://")
+            .ok_or_else(|| format!("unknown broker `{}`, expected mqtt://host[:port]/topic", s))?;
+        let (host, topic) = rest
+            .split_once('/')
+            .filter(|(host, topic)| !host.is_empty() && !topic.is_empty())
+            .ok_or_else(|| format!("`{}` needs a host and topic, mqtt://host[:port]/topic", s))?;
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, DEFAULT_PORT)
+        };
+        Ok(Self {
+            address,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+/// An MQTT 3.1.1 connection publishing at QoS 0, with no keep alive so an idle line can stay
+/// connected
+pub struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    pub fn connect(address: &str, client_id: &str) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.write_all(&connect_packet(client_id))?;
+        let mut connack = [0; 4];
+        stream.read_exact(&mut connack)?;
+        match connack {
+            [CONNACK, 2, _, 0] => Ok(Self { stream }),
+            [CONNACK, 2, _, code] => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("the broker refused the connection with code {}", code),
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the broker didn't acknowledge the connection",
+            )),
+        }
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        self.stream.write_all(&publish_packet(topic, payload))
+    }
+}
+
+/// Publishes each complete message, and optionally each character as it's decoded, as JSON
+/// to a topic
+pub struct Publisher {
+    client: Client,
+    topic: String,
+    characters: bool,
+    messages: Messages,
+}
+
+impl Publisher {
+    pub fn new(client: Client, topic: String, characters: bool) -> Self {
+        Self {
+            client,
+            topic,
+            characters,
+            messages: Messages::default(),
+        }
+    }
+}
+
+impl Sink for Publisher {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        if self.characters && matches!(event, Event::Text(_)) {
+            self.client
+                .publish(&self.topic, event.to_json().as_bytes())?;
+        }
+        if let Some(message) = self.messages.take(event) {
+            let message = Event::Message(&message).to_json();
+            self.client.publish(&self.topic, message.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn connect_packet(client_id: &str) -> Vec<u8> {
+    let mut body = string(b"MQTT");
+    // Protocol level 4 is 3.1.1, starting a clean session with keep alive off
+    body.extend_from_slice(&[4, 0x02, 0, 0]);
+    body.extend(string(client_id.as_bytes()));
+    packet(CONNECT, body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = string(topic.as_bytes());
+    body.extend_from_slice(payload);
+    packet(PUBLISH, body)
+}
+
+/// A packet with its remaining length in base 128, low digits first
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut len = body.len();
+    loop {
+        let digit = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(digit);
+            break;
+        }
+        packet.push(digit | 0x80);
+    }
+    packet.extend(body);
+    packet
+}
+
+/// A length-prefixed UTF-8 string
+fn string(bytes: &[u8]) -> Vec<u8> {
+    let mut string = (bytes.len() as u16).to_be_bytes().to_vec();
+    string.extend_from_slice(bytes);
+    string
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_mqtt_target() {
+        let target: MqttTarget = "mqtt://broker/line/bell103".parse().unwrap();
+        assert_eq!(target.address, "broker:1883");
+        assert_eq!(target.topic, "line/bell103");
+        let target: MqttTarget = "mqtt://10.0.0.2:1884/modem".parse().unwrap();
+        assert_eq!(target.address, "10.0.0.2:1884");
+        assert!("mqtt://broker".parse::<MqttTarget>().is_err());
+        assert!("tcp://broker/topic".parse::<MqttTarget>().is_err());
+    }
+
+    #[test]
+    fn test_packets() {
+        let connect = connect_packet("id");
+        assert_eq!(connect, b"\x10\x0e\x00\x04MQTT\x04\x02\x00\x00\x00\x02id");
+        assert_eq!(publish_packet("t", b"hi"), b"\x30\x05\x00\x01thi");
+        assert_eq!(packet(PUBLISH, vec![0; 200])[..3], [PUBLISH, 0xc8, 0x01]);
+    }
+
+    #[test]
+    fn test_publisher() {
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = broker.local_addr().unwrap().to_string();
+        let client = std::thread::spawn(move || {
+            let client = Client::connect(&address, "test").unwrap();
+            let mut publisher = Publisher::new(client, "t".to_string(), false);
+            for event in [Event::Carrier(true), Event::Text("o"), Event::Text("k")] {
+                publisher.take(&event).unwrap();
+            }
+            publisher.take(&Event::Carrier(false)).unwrap();
+            publisher.take(&Event::Carrier(false)).unwrap();
+        });
+        let (mut connection, _) = broker.accept().unwrap();
+        let mut connect = vec![0; connect_packet("test").len()];
+        connection.read_exact(&mut connect).unwrap();
+        connection.write_all(&[CONNACK, 2, 0, 0]).unwrap();
+        client.join().unwrap();
+        let mut published = Vec::new();
+        connection.read_to_end(&mut published).unwrap();
+        let expected = publish_packet("t", br#"{"type":"message","text":"ok"}"#);
+        assert_eq!(published, expected);
+    }
+}
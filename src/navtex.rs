@@ -0,0 +1,256 @@
+use crate::baudot::{self, BaudotDecoder};
+use crate::clock;
+use crate::demodulator::{Demodulator, TICKS_PER_BIT};
+use crate::standard::Channel;
+
+pub const BAUD: f64 = 100.0;
+/// Phasing signal sent in the DX position before a message
+pub const ALPHA: u8 = 0x0f;
+/// Phasing signal sent in the RX position before a message
+pub const RQ: u8 = 0x66;
+/// A character's repeat follows it this many positions later
+pub const REPEAT_DISTANCE: usize = 5;
+/// Printed in place of a character lost in both positions
+pub const ERROR_CHAR: char = '*';
+const BITS_PER_CHAR: usize = 7;
+/// Characters checked when searching for character alignment
+const ALIGN_WINDOW: usize = 14;
+/// Recent invalid characters, less valid ones, before searching for alignment again
+const RESYNC_ERRORS: usize = 6;
+
+/// CCIR 476 codes and the ITA2 codes they carry. Every valid code has exactly four 1 bits,
+/// which is what lets a receiver detect a corrupted character.
+const CODES: [(u8, u8); 32] = [
+    (0x6a, 0x00),
+    (0x56, 0x01),
+    (0x6c, 0x02),
+    (0x47, 0x03),
+    (0x5c, 0x04),
+    (0x4b, 0x05),
+    (0x4d, 0x06),
+    (0x4e, 0x07),
+    (0x78, 0x08),
+    (0x53, 0x09),
+    (0x55, 0x0a),
+    (0x17, 0x0b),
+    (0x59, 0x0c),
+    (0x1b, 0x0d),
+    (0x1d, 0x0e),
+    (0x1e, 0x0f),
+    (0x74, 0x10),
+    (0x63, 0x11),
+    (0x65, 0x12),
+    (0x27, 0x13),
+    (0x69, 0x14),
+    (0x2b, 0x15),
+    (0x2d, 0x16),
+    (0x2e, 0x17),
+    (0x71, 0x18),
+    (0x72, 0x19),
+    (0x35, 0x1a),
+    (0x5a, baudot::FIGS),
+    (0x39, 0x1c),
+    (0x3a, 0x1d),
+    (0x3c, 0x1e),
+    (0x36, baudot::LTRS),
+];
+
+/// Counts from decoding a broadcast
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub characters: usize,
+    /// Characters corrupted in the DX position but recovered from the RX repeat
+    pub corrected: usize,
+    pub errors: usize,
+}
+
+pub fn is_valid(code: u8) -> bool {
+    code.count_ones() == 4
+}
+
+/// The ITA2 code carried by a CCIR 476 code, if it carries one
+pub fn to_ita2(code: u8) -> Option<u8> {
+    CODES
+        .iter()
+        .find(|&&(c, _)| c == code)
+        .map(|&(_, ita2)| ita2)
+}
+
+/// Decodes a SITOR-B broadcast such as NAVTEX. Bits are sent synchronously, first bit most
+/// significant, so both the bit clock and the character alignment are recovered from the
+/// signal before pairing each character with its repeat.
+pub fn decode(samples: &[i16], channel: Channel, sampling_rate: f64) -> (String, Stats) {
+    let samples_per_bit = sampling_rate / channel.baud;
+    let block_size = samples_per_bit.round() as usize;
+    let step = (block_size / TICKS_PER_BIT).max(1);
+    let mut demodulator = Demodulator::new(block_size, channel.mark, channel.space, sampling_rate);
+    let soft = demodulator.sliding_soft_bits(samples, step);
+    let bits = clock::recover_bits(&soft, samples_per_bit / step as f64);
+    decode_codes(&split_characters(&bits))
+}
+
+fn char_at(bits: &[u8], pos: usize) -> u8 {
+    bits[pos..pos + BITS_PER_CHAR]
+        .iter()
+        .fold(0, |code, &bit| code << 1 | bit)
+}
+
+fn alignment_score(bits: &[u8], pos: usize) -> usize {
+    (0..ALIGN_WINDOW)
+        .map(|i| pos + i * BITS_PER_CHAR)
+        .take_while(|&p| p + BITS_PER_CHAR <= bits.len())
+        .filter(|&p| is_valid(char_at(bits, p)))
+        .count()
+}
+
+/// Splits a bit stream into characters, realigning after a run of invalid characters
+fn split_characters(bits: &[u8]) -> Vec<u8> {
+    let best = |candidates: &mut dyn Iterator<Item = usize>| {
+        candidates
+            .max_by_key(|&pos| alignment_score(bits, pos))
+            .unwrap_or(0)
+    };
+    let mut pos = best(&mut (0..BITS_PER_CHAR));
+    let mut errors = 0usize;
+    let mut codes = Vec::new();
+    while pos + BITS_PER_CHAR <= bits.len() {
+        let code = char_at(bits, pos);
+        codes.push(code);
+        pos += BITS_PER_CHAR;
+        if is_valid(code) {
+            errors = errors.saturating_sub(1);
+        } else {
+            errors += 2;
+        }
+        if errors >= RESYNC_ERRORS {
+            errors = 0;
+            let low = pos.saturating_sub(BITS_PER_CHAR / 2);
+            pos = best(&mut (low..=pos + BITS_PER_CHAR / 2));
+        }
+    }
+    codes
+}
+
+/// Pairs each DX character with its RX repeat and decodes the result
+fn decode_codes(codes: &[u8]) -> (String, Stats) {
+    // DX positions are the ones carrying the first phasing signal, or whose characters
+    // match the character repeated after them
+    let repeats = |parity: usize| {
+        (parity..codes.len().saturating_sub(REPEAT_DISTANCE))
+            .step_by(2)
+            .filter(|&i| {
+                let phasing = codes[i] == ALPHA && codes[i + 1] == RQ;
+                phasing || is_valid(codes[i]) && codes[i] == codes[i + REPEAT_DISTANCE]
+            })
+            .count()
+    };
+    let parity = if repeats(1) > repeats(0) { 1 } else { 0 };
+
+    let mut stats = Stats::default();
+    let mut decoder = BaudotDecoder::international(false);
+    let mut text = String::new();
+    for i in (parity..codes.len()).step_by(2) {
+        let dx = codes[i];
+        let rx = codes.get(i + REPEAT_DISTANCE).copied().unwrap_or(0);
+        let code = if is_valid(dx) {
+            dx
+        } else if is_valid(rx) {
+            stats.corrected += 1;
+            rx
+        } else {
+            stats.errors += 1;
+            text.push(ERROR_CHAR);
+            continue;
+        };
+        if let Some(c) = to_ita2(code).and_then(|ita2| decoder.decode(ita2)) {
+            stats.characters += 1;
+            if c != '\r' {
+                text.push(c);
+            }
+        }
+    }
+    (text, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::FskModulator;
+
+    const BETA: u8 = 0x33;
+
+    fn from_ita2(ita2: u8) -> u8 {
+        CODES.iter().find(|&&(_, i)| i == ita2).unwrap().0
+    }
+
+    /// Builds the character stream for a message: DX positions carry phasing then the
+    /// message, RX positions carry RQ then the message delayed by the repeat distance
+    fn broadcast(text: &str) -> Vec<u8> {
+        let message: Vec<u8> = baudot::encode(text).into_iter().map(from_ita2).collect();
+        let phasing = 8;
+        let dx: Vec<u8> = std::iter::repeat_n(ALPHA, phasing)
+            .chain(message.iter().copied())
+            .chain(std::iter::repeat_n(BETA, 4))
+            .collect();
+        let delay = REPEAT_DISTANCE / 2;
+        let rx: Vec<u8> = std::iter::repeat_n(RQ, phasing + delay)
+            .chain(message.iter().copied())
+            .chain(std::iter::repeat_n(BETA, 4 - delay))
+            .collect();
+        dx.iter().zip(&rx).flat_map(|(&d, &r)| vec![d, r]).collect()
+    }
+
+    fn to_bits(codes: &[u8]) -> Vec<u8> {
+        codes
+            .iter()
+            .flat_map(|&code| (0..BITS_PER_CHAR).rev().map(move |i| (code >> i) & 1))
+            .collect()
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(CODES.iter().filter(|&&(c, _)| is_valid(c)).count(), 32);
+        for &special in &[ALPHA, RQ, BETA] {
+            assert!(is_valid(special));
+            assert_eq!(to_ita2(special), None);
+        }
+    }
+
+    #[test]
+    fn test_repeat_corrects_errors() {
+        let mut codes = broadcast("ZCZC FA01\r\nGALE 8\r\nNNNN");
+        // Corrupt the DX copy of 'F', and both copies of 'G'
+        let f = 2 * (8 + 6);
+        codes[f] ^= 0x01;
+        let g = 2 * (8 + 14);
+        codes[g] ^= 0x01;
+        codes[g + REPEAT_DISTANCE] ^= 0x01;
+
+        let (text, stats) = decode_codes(&codes);
+        assert_eq!(text, "ZCZC FA01\n*ALE 8\nNNNN");
+        assert_eq!(stats.corrected, 1);
+        assert_eq!(stats.errors, 1);
+    }
+
+    #[test]
+    fn test_decode() {
+        let channel = Channel {
+            mark: 1085.0,
+            space: 915.0,
+            baud: BAUD,
+        };
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        let mut samples = Vec::new();
+        // Start part way through a bit with an unaligned character
+        modulator.hold(1, 0.4, &mut samples);
+        modulator.modulate(&[0, 1, 1], &mut samples);
+        modulator.modulate(
+            &to_bits(&broadcast("ZCZC OA12\r\nTEST 123\r\nNNNN")),
+            &mut samples,
+        );
+
+        let (text, stats) = decode(&samples, channel, 8_000.0);
+        assert_eq!(text, "ZCZC OA12\nTEST 123\nNNNN");
+        assert_eq!(stats.errors, 0);
+    }
+}
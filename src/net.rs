@@ -0,0 +1,241 @@
+use std::fmt;
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+
+/// RTP payload types for L16 audio at 44.1 kHz, and where the dynamic ones start
+const L16_STEREO: u8 = 10;
+const L16_MONO: u8 = 11;
+const DYNAMIC: u8 = 96;
+/// A jump in sequence numbers bigger than this is a restarted sender rather than lost packets
+const MAX_GAP: u16 = 1000;
+
+/// How audio arrives at a `--listen` address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transport {
+    /// Datagrams of headerless PCM
+    Udp,
+    /// RTP packets of big-endian L16, one or two channels
+    Rtp,
+}
+
+/// An address to receive audio on, written `udp://host:port` or `rtp://host:port`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listen {
+    pub transport: Transport,
+    pub address: SocketAddr,
+}
+
+impl FromStr for Listen {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (transport, address) = match s.split_once("://") {
+            Some(("udp", address)) => (Transport::Udp, address),
+            Some(("rtp", address)) => (Transport::Rtp, address),
+            _ => {
+                return Err(format!(
+                    "unknown address `{}`, expected udp://host:port or rtp://host:port",
+                    s
+                ))
+            }
+        };
+        let address = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or_else(|| format!("can't resolve `{}` to listen on", address))?;
+        Ok(Self { transport, address })
+    }
+}
+
+impl fmt::Display for Listen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scheme = match self.transport {
+            Transport::Udp => "udp",
+            Transport::Rtp => "rtp",
+        };
+        write!(f, "{}://{}", scheme, self.address)
+    }
+}
+
+impl Listen {
+    pub fn bind(self) -> io::Result<Incoming> {
+        Ok(Incoming {
+            socket: UdpSocket::bind(self.address)?,
+            transport: self.transport,
+            pending: Vec::new(),
+            position: 0,
+            sequence: None,
+            last_length: 0,
+            ignored: None,
+        })
+    }
+}
+
+/// The audio arriving at a `--listen` address, read as a stream of bytes that never ends. RTP
+/// payloads are mixed down to mono big-endian samples, in sequence, with silence in place of
+/// lost packets so bits on either side stay the right distance apart.
+pub struct Incoming {
+    socket: UdpSocket,
+    transport: Transport,
+    /// Bytes of the last datagram not yet read
+    pending: Vec<u8>,
+    position: usize,
+    /// Sequence number expected next from RTP
+    sequence: Option<u16>,
+    /// Bytes in the last RTP payload, for the silence filling in after it
+    last_length: usize,
+    /// An RTP payload type already warned about
+    ignored: Option<u8>,
+}
+
+impl Incoming {
+    /// The address actually bound, with its port filled in when asked for port 0
+    pub fn local_address(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Turns an RTP packet into the bytes to be read, or none if it's late or not L16
+    fn receive_rtp(&mut self, packet: &[u8]) -> Vec<u8> {
+        let (sequence, payload_type, payload) = match rtp_payload(packet) {
+            Some(parts) => parts,
+            None => return Vec::new(),
+        };
+        let lost = match self.sequence {
+            Some(expected) => sequence.wrapping_sub(expected),
+            None => 0,
+        };
+        // Duplicates and packets overtaken by later ones are too late to use
+        if lost > u16::MAX / 2 {
+            return Vec::new();
+        }
+        self.sequence = Some(sequence.wrapping_add(1));
+        let stereo = payload_type == L16_STEREO;
+        if !stereo && payload_type != L16_MONO && payload_type < DYNAMIC {
+            if self.ignored != Some(payload_type) {
+                eprintln!(
+                    "Ignoring RTP payload type {}, only L16 audio is decoded",
+                    payload_type
+                );
+                self.ignored = Some(payload_type);
+            }
+            return Vec::new();
+        }
+        let silence = if lost < MAX_GAP {
+            usize::from(lost) * self.last_length
+        } else {
+            0
+        };
+        let mut bytes = vec![0; silence];
+        if stereo {
+            for frame in payload.chunks_exact(4) {
+                let left = i32::from(i16::from_be_bytes([frame[0], frame[1]]));
+                let right = i32::from(i16::from_be_bytes([frame[2], frame[3]]));
+                bytes.extend((((left + right) / 2) as i16).to_be_bytes());
+            }
+        } else {
+            bytes.extend(&payload[..payload.len() / 2 * 2]);
+        }
+        self.last_length = bytes.len() - silence;
+        bytes
+    }
+}
+
+impl Read for Incoming {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            let mut packet = [0; 65_536];
+            let length = self.socket.recv(&mut packet)?;
+            self.pending = match self.transport {
+                Transport::Udp => packet[..length].to_vec(),
+                Transport::Rtp => self.receive_rtp(&packet[..length]),
+            };
+            self.position = 0;
+        }
+        let length = buf.len().min(self.pending.len() - self.position);
+        buf[..length].copy_from_slice(&self.pending[self.position..self.position + length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// The sequence number, payload type and payload of an RTP packet, skipping any CSRCs,
+/// header extension and padding
+fn rtp_payload(packet: &[u8]) -> Option<(u16, u8, &[u8])> {
+    if packet.len() < 12 || packet[0] >> 6 != 2 {
+        return None;
+    }
+    let mut start = 12 + 4 * usize::from(packet[0] & 0x0f);
+    if packet[0] & 0x10 != 0 {
+        let words = packet.get(start + 2..start + 4)?;
+        start += 4 + 4 * usize::from(u16::from_be_bytes([words[0], words[1]]));
+    }
+    let padding = if packet[0] & 0x20 != 0 {
+        usize::from(*packet.last()?)
+    } else {
+        0
+    };
+    let end = packet.len().checked_sub(padding)?;
+    let payload = packet.get(start..end)?;
+    let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+    Some((sequence, packet[1] & 0x7f, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtp(sequence: u16, payload_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, payload_type];
+        packet.extend(sequence.to_be_bytes());
+        packet.extend([0; 8]);
+        packet.extend(payload);
+        packet
+    }
+
+    #[test]
+    fn test_parse_listen() {
+        let listen: Listen = "rtp://127.0.0.1:5004".parse().unwrap();
+        assert_eq!(listen.transport, Transport::Rtp);
+        assert_eq!(listen.address.port(), 5004);
+        assert_eq!(listen.to_string(), "rtp://127.0.0.1:5004");
+        assert!("tcp://127.0.0.1:5004".parse::<Listen>().is_err());
+        assert!("udp://127.0.0.1".parse::<Listen>().is_err());
+    }
+
+    #[test]
+    fn test_rtp_payload() {
+        assert_eq!(
+            rtp_payload(&rtp(7, 11, &[1, 2])),
+            Some((7, 11, &[1, 2][..]))
+        );
+        // One CSRC, a one-word extension and two bytes of padding
+        let mut packet = vec![0xb1, 0x8b, 0, 9];
+        packet.extend([0; 8]);
+        packet.extend([0; 4]);
+        packet.extend([0xbe, 0xde, 0, 1, 0, 0, 0, 0]);
+        packet.extend([3, 4, 0, 2]);
+        assert_eq!(rtp_payload(&packet), Some((9, 11, &[3, 4][..])));
+        assert_eq!(rtp_payload(&[0x80, 11, 0, 0]), None);
+        assert_eq!(rtp_payload(&[0u8; 12]), None);
+    }
+
+    #[test]
+    fn test_receive() {
+        let listen: Listen = "rtp://127.0.0.1:0".parse().unwrap();
+        let mut incoming = listen.bind().unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender.connect(incoming.local_address().unwrap()).unwrap();
+        sender.send(&rtp(1, 11, &[0, 1, 0, 2])).unwrap();
+        // One packet lost, then a late one and a stereo one
+        sender.send(&rtp(3, 96, &[0, 5, 0, 6])).unwrap();
+        sender.send(&rtp(2, 11, &[9, 9, 9, 9])).unwrap();
+        sender.send(&rtp(4, 10, &[0, 2, 0, 4])).unwrap();
+        sender.send(&rtp(5, 0, &[0xff])).unwrap();
+        sender.send(&rtp(6, 11, &[0, 7])).unwrap();
+        let mut bytes = vec![0; 16];
+        incoming.read_exact(&mut bytes).unwrap();
+        assert_eq!(bytes, [0, 1, 0, 2, 0, 0, 0, 0, 0, 5, 0, 6, 0, 3, 0, 7]);
+    }
+}
@@ -0,0 +1,249 @@
+//! A Node.js addon built on Node-API, which `node/index.js` wraps as an event emitter. It
+//! exports `create(sampleRate, standard, origin)`, making a decoder, and `push(decoder,
+//! samples)`, demodulating an `Int16Array`, a `Float32Array` or a `Buffer` of 16-bit little
+//! endian samples and returning a `Buffer` of the bytes decoded from them.
+
+use crate::backend::Goertzel;
+use crate::framing::{self, Framing, StreamReceiver};
+use crate::standard::Standard;
+use std::ffi::{c_char, c_void, CString};
+use std::{ptr, slice};
+
+#[repr(C)]
+pub struct Env {
+    _private: [u8; 0],
+}
+#[repr(C)]
+pub struct Value {
+    _private: [u8; 0],
+}
+#[repr(C)]
+pub struct CallbackInfo {
+    _private: [u8; 0],
+}
+
+type NapiEnv = *mut Env;
+type NapiValue = *mut Value;
+type Callback = unsafe extern "C" fn(NapiEnv, *mut CallbackInfo) -> NapiValue;
+type Finalize = unsafe extern "C" fn(NapiEnv, *mut c_void, *mut c_void);
+
+/// `napi_ok`, the other statuses being errors
+const OK: i32 = 0;
+/// Typed array types, as `napi_typedarray_type` numbers them
+const UINT8_ARRAY: i32 = 1;
+const INT16_ARRAY: i32 = 3;
+const FLOAT32_ARRAY: i32 = 7;
+
+extern "C" {
+    fn napi_create_function(
+        env: NapiEnv,
+        name: *const c_char,
+        length: usize,
+        callback: Callback,
+        data: *mut c_void,
+        result: *mut NapiValue,
+    ) -> i32;
+    fn napi_set_named_property(
+        env: NapiEnv,
+        object: NapiValue,
+        name: *const c_char,
+        value: NapiValue,
+    ) -> i32;
+    fn napi_get_cb_info(
+        env: NapiEnv,
+        info: *mut CallbackInfo,
+        argc: *mut usize,
+        argv: *mut NapiValue,
+        this: *mut NapiValue,
+        data: *mut *mut c_void,
+    ) -> i32;
+    fn napi_get_value_double(env: NapiEnv, value: NapiValue, result: *mut f64) -> i32;
+    fn napi_get_value_bool(env: NapiEnv, value: NapiValue, result: *mut bool) -> i32;
+    fn napi_get_value_string_utf8(
+        env: NapiEnv,
+        value: NapiValue,
+        buffer: *mut c_char,
+        size: usize,
+        result: *mut usize,
+    ) -> i32;
+    fn napi_create_external(
+        env: NapiEnv,
+        data: *mut c_void,
+        finalize: Finalize,
+        hint: *mut c_void,
+        result: *mut NapiValue,
+    ) -> i32;
+    fn napi_get_value_external(env: NapiEnv, value: NapiValue, result: *mut *mut c_void) -> i32;
+    fn napi_get_typedarray_info(
+        env: NapiEnv,
+        array: NapiValue,
+        kind: *mut i32,
+        length: *mut usize,
+        data: *mut *mut c_void,
+        buffer: *mut NapiValue,
+        offset: *mut usize,
+    ) -> i32;
+    fn napi_create_buffer_copy(
+        env: NapiEnv,
+        length: usize,
+        data: *const c_void,
+        result_data: *mut *mut c_void,
+        result: *mut NapiValue,
+    ) -> i32;
+    fn napi_throw_type_error(env: NapiEnv, code: *const c_char, message: *const c_char) -> i32;
+}
+
+/// Registers the addon's functions when Node loads it
+///
+/// # Safety
+///
+/// Only Node calls this, with its own environment and the module's exports object.
+#[no_mangle]
+pub unsafe extern "C" fn napi_register_module_v1(env: NapiEnv, exports: NapiValue) -> NapiValue {
+    let functions: [(&[u8], Callback); 2] = [(b"create\0", create), (b"push\0", push)];
+    for (name, callback) in functions {
+        let mut function = ptr::null_mut();
+        let name = name.as_ptr().cast();
+        if napi_create_function(
+            env,
+            name,
+            usize::MAX,
+            callback,
+            ptr::null_mut(),
+            &mut function,
+        ) != OK
+            || napi_set_named_property(env, exports, name, function) != OK
+        {
+            return ptr::null_mut();
+        }
+    }
+    exports
+}
+
+/// `create(sampleRate, standard, origin)`, a decoder freed once it's garbage collected
+unsafe extern "C" fn create(env: NapiEnv, info: *mut CallbackInfo) -> NapiValue {
+    let made = arguments::<3>(env, info).and_then(|[rate, standard, origin]| {
+        let mut sampling_rate = 0.0;
+        let mut origin_channel = false;
+        if napi_get_value_double(env, rate, &mut sampling_rate) != OK
+            || !framing::SAMPLING_RATES.contains(&sampling_rate)
+        {
+            return Err("sampleRate must be between 8000 and 384000".to_string());
+        }
+        if napi_get_value_bool(env, origin, &mut origin_channel) != OK {
+            return Err("origin must be a boolean".to_string());
+        }
+        let standard: Standard = string(env, standard)?.parse()?;
+        let channel = standard.channel(origin_channel);
+        let receiver =
+            StreamReceiver::with_backend(channel, sampling_rate, Framing::new(8), &Goertzel)?;
+        let receiver = Box::into_raw(Box::new(receiver)).cast();
+        let mut external = ptr::null_mut();
+        if napi_create_external(env, receiver, release, ptr::null_mut(), &mut external) != OK {
+            release(env, receiver, ptr::null_mut());
+            return Err("couldn't make a decoder".to_string());
+        }
+        Ok(external)
+    });
+    made.unwrap_or_else(|e| throw(env, &e))
+}
+
+/// `push(decoder, samples)`, the bytes decoded from the samples
+unsafe extern "C" fn push(env: NapiEnv, info: *mut CallbackInfo) -> NapiValue {
+    let decoded = arguments::<2>(env, info).and_then(|[decoder, samples]| {
+        let mut receiver = ptr::null_mut();
+        if napi_get_value_external(env, decoder, &mut receiver) != OK || receiver.is_null() {
+            return Err("expected a decoder from create()".to_string());
+        }
+        let receiver = &mut *receiver.cast::<StreamReceiver>();
+        let (mut kind, mut length, mut data) = (0, 0, ptr::null_mut());
+        let (mut buffer, mut offset) = (ptr::null_mut(), 0);
+        let typed = napi_get_typedarray_info(
+            env,
+            samples,
+            &mut kind,
+            &mut length,
+            &mut data,
+            &mut buffer,
+            &mut offset,
+        );
+        // An empty array may have no data at all
+        let samples: Vec<i16> = match kind {
+            _ if typed != OK => return Err("samples must be a typed array or Buffer".to_string()),
+            _ if length == 0 || data.is_null() => Vec::new(),
+            INT16_ARRAY => slice::from_raw_parts(data.cast::<i16>(), length).to_vec(),
+            FLOAT32_ARRAY => slice::from_raw_parts(data.cast::<f32>(), length)
+                .iter()
+                .map(|&sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16)
+                .collect(),
+            UINT8_ARRAY => slice::from_raw_parts(data.cast::<u8>(), length)
+                .chunks_exact(2)
+                .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+                .collect(),
+            _ => return Err("samples must be an Int16Array, Float32Array or Buffer".to_string()),
+        };
+        let bytes: Vec<u8> = receiver.push(&samples).iter().map(|r| r.byte).collect();
+        let mut result = ptr::null_mut();
+        let copied = napi_create_buffer_copy(
+            env,
+            bytes.len(),
+            bytes.as_ptr().cast(),
+            ptr::null_mut(),
+            &mut result,
+        );
+        if copied != OK {
+            return Err("couldn't return the bytes decoded".to_string());
+        }
+        Ok(result)
+    });
+    decoded.unwrap_or_else(|e| throw(env, &e))
+}
+
+unsafe extern "C" fn release(_: NapiEnv, receiver: *mut c_void, _: *mut c_void) {
+    drop(Box::from_raw(receiver.cast::<StreamReceiver>()));
+}
+
+/// The first `N` arguments of a call, which must all be given
+unsafe fn arguments<const N: usize>(
+    env: NapiEnv,
+    info: *mut CallbackInfo,
+) -> Result<[NapiValue; N], String> {
+    let mut values = [ptr::null_mut(); N];
+    let mut count = N;
+    let got = napi_get_cb_info(
+        env,
+        info,
+        &mut count,
+        values.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    if got != OK || count < N {
+        return Err(format!("expected {} arguments", N));
+    }
+    Ok(values)
+}
+
+unsafe fn string(env: NapiEnv, value: NapiValue) -> Result<String, String> {
+    let mut length = 0;
+    if napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut length) != OK {
+        return Err("standard must be a string".to_string());
+    }
+    let mut bytes = vec![0u8; length + 1];
+    napi_get_value_string_utf8(
+        env,
+        value,
+        bytes.as_mut_ptr().cast(),
+        bytes.len(),
+        &mut length,
+    );
+    bytes.truncate(length);
+    String::from_utf8(bytes).map_err(|e| e.to_string())
+}
+
+/// Throws a `TypeError`, returning undefined for the call
+unsafe fn throw(env: NapiEnv, message: &str) -> NapiValue {
+    let message = CString::new(message).unwrap_or_default();
+    napi_throw_type_error(env, ptr::null(), message.as_ptr());
+    ptr::null_mut()
+}
@@ -0,0 +1,323 @@
+use crate::events::{Event, Messages, Sink};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::Duration;
+
+const SMTP_PORT: u16 = 25;
+const HTTP_PORT: u16 = 80;
+/// How long a mail server or webhook has to answer before a notification is given up on
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// Characters of a message's first line put in an email's subject
+const SUBJECT_LENGTH: usize = 60;
+
+/// Where someone is told of each decoded message, written `smtp://host[:port]/recipient` to
+/// email them through a mail server or `http://host[:port]/path` to post to a webhook
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notify {
+    Email { address: String, to: String },
+    Webhook { host: String, path: String },
+}
+
+impl FromStr for Notify {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s.split_once("://").unwrap_or(("", s));
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            return Err(format!("`{}` needs a host", s));
+        }
+        match scheme {
+            "smtp" if path.contains('@') => Ok(Notify::Email {
+                address: with_port(host, SMTP_PORT),
+                to: path.to_string(),
+            }),
+            "smtp" => Err(format!(
+                "`{}` needs a recipient, smtp://host[:port]/user@example.com",
+                s
+            )),
+            "http" => Ok(Notify::Webhook {
+                host: host.to_string(),
+                path: format!("/{}", path),
+            }),
+            _ => Err(format!(
+                "unknown notification `{}`, expected smtp://host[:port]/recipient or http://host[:port]/path",
+                s
+            )),
+        }
+    }
+}
+
+fn with_port(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Emails or posts each complete message. One that can't be sent is reported and skipped, so
+/// an unattended decoder carries on through a mail server or webhook being down.
+pub struct Notifier {
+    notify: Notify,
+    from: String,
+    messages: Messages,
+}
+
+impl Notifier {
+    /// Notifies `notify`, with emails coming from the address `from`
+    pub fn new(notify: Notify, from: &str) -> Self {
+        Self {
+            notify,
+            from: from.to_string(),
+            messages: Messages::default(),
+        }
+    }
+
+    fn send(&self, message: &str) -> io::Result<()> {
+        match &self.notify {
+            Notify::Email { address, to } => {
+                let stream = connect(address)?;
+                send_email(stream, &self.from, to, message)
+            }
+            Notify::Webhook { host, path } => {
+                let stream = connect(&with_port(host, HTTP_PORT))?;
+                post(stream, host, path, &Event::Message(message).to_json())
+            }
+        }
+    }
+}
+
+impl Sink for Notifier {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        if let Some(message) = self.messages.take(event) {
+            if let Err(e) = self.send(&message) {
+                eprintln!("Couldn't send a notification of a message: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn connect(address: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Hands a message to a mail server over plain SMTP, for a local MTA or relay to deliver
+fn send_email(stream: TcpStream, from: &str, to: &str, message: &str) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    // Sends a command, labelled for errors, and checks the server accepts it
+    let mut exchange = |label: &str, command: Option<String>| -> io::Result<()> {
+        if let Some(command) = &command {
+            writer.write_all(command.as_bytes())?;
+            writer.write_all(b"\r\n")?;
+        }
+        let reply = smtp_reply(&mut reader)?;
+        if reply.starts_with('2') || reply.starts_with('3') {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "the mail server answered {} with `{}`",
+                label, reply
+            )))
+        }
+    };
+    exchange("the connection", None)?;
+    exchange("EHLO", Some("EHLO localhost".to_string()))?;
+    exchange("MAIL", Some(format!("MAIL FROM:<{}>", from)))?;
+    exchange("RCPT", Some(format!("RCPT TO:<{}>", to)))?;
+    exchange("DATA", Some("DATA".to_string()))?;
+    exchange("the message", Some(email(from, to, message)))?;
+    exchange("QUIT", Some("QUIT".to_string()))
+}
+
+/// Reads a reply, which runs over lines starting `250-` up to one starting `250 `
+fn smtp_reply(reader: &mut impl BufRead) -> io::Result<String> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok(line.trim_end().to_string());
+        }
+    }
+}
+
+/// The email's headers and text, with lines starting with a dot doubled and the line of a
+/// lone dot that ends it. The server adds the date.
+fn email(from: &str, to: &str, message: &str) -> String {
+    let text: String = message
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .chars()
+        .filter(|&c| c == '\n' || c == '\t' || !c.is_control())
+        .collect();
+    let first = text.trim().lines().next().unwrap_or_default();
+    let mut subject: String = first.chars().take(SUBJECT_LENGTH).collect();
+    if first.chars().count() > SUBJECT_LENGTH {
+        subject.push_str("...");
+    }
+    let mut email = format!(
+        "From: <{}>\r\nTo: <{}>\r\nSubject: Decoded: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        from, to, subject
+    );
+    for line in text.lines() {
+        if line.starts_with('.') {
+            email.push('.');
+        }
+        email.push_str(line);
+        email.push_str("\r\n");
+    }
+    email.push('.');
+    email
+}
+
+/// Posts JSON to a webhook, failing unless it answers with a 2xx status
+fn post(stream: TcpStream, host: &str, path: &str, json: &str) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    write!(
+        writer,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        json.len(),
+        json
+    )?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    let status = status.trim_end();
+    match status.split(' ').nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "the webhook answered `{}`",
+            status
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_notify() {
+        let email: Notify = "smtp://mail/ops@example.com".parse().unwrap();
+        assert_eq!(
+            email,
+            Notify::Email {
+                address: "mail:25".to_string(),
+                to: "ops@example.com".to_string()
+            }
+        );
+        let webhook: Notify = "http://10.0.0.2:8080/hooks/modem".parse().unwrap();
+        assert_eq!(
+            webhook,
+            Notify::Webhook {
+                host: "10.0.0.2:8080".to_string(),
+                path: "/hooks/modem".to_string()
+            }
+        );
+        assert!("smtp://mail".parse::<Notify>().is_err());
+        assert!("https://example.com/hook".parse::<Notify>().is_err());
+        assert!("http:///hook".parse::<Notify>().is_err());
+    }
+
+    #[test]
+    fn test_email() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"220 mail ESMTP\r\n").unwrap();
+            let mut lines = Vec::new();
+            let mut data = false;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end_matches("\r\n").to_string();
+                let reply: &[u8] = match line.as_str() {
+                    _ if data && line == "." => {
+                        data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if data => b"",
+                    "EHLO localhost" => b"250-mail\r\n250-8BITMIME\r\n250 SIZE\r\n",
+                    "DATA" => {
+                        data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                writer.write_all(reply).unwrap();
+                lines.push(line);
+                if lines.last().unwrap() == "QUIT" {
+                    return lines;
+                }
+            }
+        });
+        let mut notifier = Notifier::new(
+            Notify::Email {
+                address,
+                to: "ops@example.com".to_string(),
+            },
+            "modem@example.com",
+        );
+        notifier.take(&Event::Text("Welcome\r\n.login:")).unwrap();
+        notifier.take(&Event::Carrier(false)).unwrap();
+        let lines = server.join().unwrap();
+        assert_eq!(
+            lines[..4],
+            [
+                "EHLO localhost",
+                "MAIL FROM:<modem@example.com>",
+                "RCPT TO:<ops@example.com>",
+                "DATA"
+            ]
+        );
+        assert!(lines.contains(&"Subject: Decoded: Welcome".to_string()));
+        assert_eq!(
+            lines[lines.len() - 4..],
+            ["Welcome", "..login:", ".", "QUIT"]
+        );
+    }
+
+    #[test]
+    fn test_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in &["204 No Content", "500 Internal Server Error"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                write!(stream, "HTTP/1.1 {}\r\n\r\n", status).unwrap();
+                let mut request = String::new();
+                stream.read_to_string(&mut request).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        let notify = Notify::Webhook {
+            host: host.clone(),
+            path: "/hook".to_string(),
+        };
+        let notifier = Notifier::new(notify, "");
+        notifier.send("login:").unwrap();
+        let failed = notifier.send("login:").unwrap_err();
+        assert!(failed.to_string().contains("500"));
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with(&format!("POST /hook HTTP/1.1\r\nHost: {}\r\n", host)));
+        assert!(requests[0].ends_with("\r\n\r\n{\"type\":\"message\",\"text\":\"login:\"}"));
+    }
+}
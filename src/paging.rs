@@ -0,0 +1,271 @@
+use crate::goertzel;
+use crate::spectrum;
+use std::fmt;
+use std::str::FromStr;
+
+/// Tones are measured over blocks of about 100 ms, the largest power of two samples that
+/// fits, advancing by half a block
+const BLOCK_SECS: f64 = 0.1;
+/// The peak of the spectrum must hold this much of a block's energy
+const MIN_TONE_ENERGY: f64 = 0.6;
+/// Bins either side of the peak counted as the tone, covering the Hann window's main lobe
+const PEAK_BINS: usize = 2;
+/// Blocks within this fraction of a burst's frequency hold the same tone
+const MAX_DRIFT: f64 = 0.01;
+/// The second tone must follow the first within this, allowing for the blocks that straddle
+/// the change
+const MAX_GAP_SECS: f64 = 0.25;
+/// Measured tones match a tone in the plan within this fraction of its frequency
+const PLAN_TOLERANCE: f64 = 0.01;
+
+/// One tone frequency of a paging tone plan and the code it stands for
+#[derive(Debug, Clone, PartialEq)]
+struct PlanTone {
+    code: String,
+    frequency: f64,
+}
+
+/// The frequencies a paging system assigns to tone codes, read from lines of a code and a
+/// frequency in Hz separated by spaces or a comma, with `#` starting a comment
+#[derive(Debug, Clone, PartialEq)]
+pub struct TonePlan {
+    tones: Vec<PlanTone>,
+}
+
+impl TonePlan {
+    /// The code of the plan's nearest tone to `frequency`, if one is close enough
+    pub fn code(&self, frequency: f64) -> Option<&str> {
+        self.tones
+            .iter()
+            .filter(|tone| (tone.frequency - frequency).abs() <= PLAN_TOLERANCE * tone.frequency)
+            .min_by(|a, b| {
+                let distance = |tone: &PlanTone| (tone.frequency - frequency).abs();
+                distance(a).partial_cmp(&distance(b)).unwrap()
+            })
+            .map(|tone| tone.code.as_str())
+    }
+}
+
+impl FromStr for TonePlan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tones = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .collect();
+            match fields.as_slice() {
+                [code, frequency] => match frequency.parse::<f64>() {
+                    Ok(frequency) if frequency > 0.0 => tones.push(PlanTone {
+                        code: code.to_string(),
+                        frequency,
+                    }),
+                    _ => return Err(format!("line {}: `{}` isn't a frequency", i + 1, frequency)),
+                },
+                _ => return Err(format!("line {}: expected a code and a frequency", i + 1)),
+            }
+        }
+        if tones.is_empty() {
+            return Err("the tone plan has no tones".into());
+        }
+        Ok(Self { tones })
+    }
+}
+
+/// A steady tone found in a recording, timed in seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tone {
+    pub frequency: f64,
+    pub start: f64,
+    pub duration: f64,
+    /// The tone's code in the plan, if one was given and matched
+    pub code: Option<String>,
+}
+
+impl fmt::Display for Tone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(code) = &self.code {
+            write!(f, "{} ", code)?;
+        }
+        write!(f, "{:.1} Hz ({:.1} s)", self.frequency, self.duration)
+    }
+}
+
+/// A page: the first (A) tone followed straight away by the second (B) tone
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub first: Tone,
+    pub second: Tone,
+}
+
+impl fmt::Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.3}s {} + {}",
+            self.first.start, self.first, self.second
+        )
+    }
+}
+
+/// Finds two-tone sequential pages, such as Motorola Quick Call II where a 1 s A tone is
+/// followed by a 3 s B tone, or a longer B tone for a group call
+#[derive(Debug)]
+pub struct PageDetector {
+    sampling_rate: f64,
+    block_size: usize,
+    /// Nominal tone lengths in seconds
+    first_secs: f64,
+    second_secs: f64,
+}
+
+impl PageDetector {
+    /// Tone lengths are in milliseconds
+    pub fn new(sampling_rate: f64, first_ms: f64, second_ms: f64) -> Self {
+        let block_size = ((sampling_rate * BLOCK_SECS) as usize + 1).next_power_of_two() / 2;
+        Self {
+            sampling_rate,
+            block_size,
+            first_secs: first_ms / 1000.0,
+            second_secs: second_ms / 1000.0,
+        }
+    }
+
+    /// The frequency of a block's single tone, if it's loud and clean enough
+    fn frequency(&self, block: &[i16]) -> Option<f64> {
+        if goertzel::is_silent(block) {
+            return None;
+        }
+        let spectrum = spectrum::power_spectrum(block);
+        let peak = (1..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+            .unwrap();
+        let lobe = peak.saturating_sub(PEAK_BINS)..(peak + PEAK_BINS + 1).min(spectrum.len());
+        let tone: f64 = spectrum[lobe].iter().sum();
+        if tone < MIN_TONE_ENERGY * spectrum.iter().sum::<f64>() {
+            return None;
+        }
+        Some(spectrum::interpolate_peak(&spectrum, peak) * self.sampling_rate / block.len() as f64)
+    }
+
+    /// Splits a recording into bursts of steady tone
+    fn tones(&self, samples: &[i16]) -> Vec<Tone> {
+        let hop = self.block_size / 2;
+        let seconds = |samples: usize| samples as f64 / self.sampling_rate;
+        let mut tones = Vec::new();
+        // The current burst's first block, block count and summed frequency
+        let mut burst: Option<(usize, usize, f64)> = None;
+        let blocks = samples.len().saturating_sub(self.block_size) / hop + 1;
+        for i in 0..=blocks {
+            let frequency = if i < blocks && samples.len() >= self.block_size {
+                self.frequency(&samples[i * hop..i * hop + self.block_size])
+            } else {
+                None
+            };
+            if let (Some((_, count, sum)), Some(frequency)) = (&mut burst, frequency) {
+                let mean = *sum / *count as f64;
+                if (frequency - mean).abs() <= MAX_DRIFT * mean {
+                    *count += 1;
+                    *sum += frequency;
+                    continue;
+                }
+            }
+            if let Some((first, count, sum)) = burst.take() {
+                tones.push(Tone {
+                    frequency: sum / count as f64,
+                    start: seconds(first * hop),
+                    duration: seconds((count - 1) * hop + self.block_size),
+                    code: None,
+                });
+            }
+            burst = frequency.map(|frequency| (i, 1, frequency));
+        }
+        tones
+    }
+
+    /// Returns the pages in a recording. The first tone must last within half of its
+    /// nominal length either way and the second at least half of its own, as group calls
+    /// hold it for longer. Tones are named by their codes when a plan is given.
+    pub fn detect(&self, samples: &[i16], plan: Option<&TonePlan>) -> Vec<Page> {
+        let mut tones = self.tones(samples);
+        if let Some(plan) = plan {
+            for tone in &mut tones {
+                tone.code = plan.code(tone.frequency).map(Into::into);
+            }
+        }
+        tones
+            .windows(2)
+            .filter(|pair| {
+                let (first, second) = (&pair[0], &pair[1]);
+                (first.duration - self.first_secs).abs() <= 0.5 * self.first_secs
+                    && second.duration >= 0.5 * self.second_secs
+                    && second.start - (first.start + first.duration) <= MAX_GAP_SECS
+            })
+            .map(|pair| Page {
+                first: pair[0].clone(),
+                second: pair[1].clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{Oscillator, AMPLITUDE};
+
+    fn tone(frequency: f64, secs: f64, samples: &mut Vec<i16>) {
+        let mut oscillator = Oscillator::new(frequency, 8_000.0);
+        for _ in 0..(secs * 8_000.0) as usize {
+            samples.push((AMPLITUDE * oscillator.next_sample()) as i16);
+        }
+    }
+
+    #[test]
+    fn test_tone_plan() {
+        let plan: TonePlan = "# code, Hz\n101, 349.0\n102 368.5\n\n1A 433.7  # comment\n"
+            .parse()
+            .unwrap();
+        assert_eq!(plan.code(350.5), Some("101"));
+        assert_eq!(plan.code(366.0), Some("102"));
+        assert_eq!(plan.code(400.0), None);
+        assert_eq!(plan.code(431.0), Some("1A"));
+        assert!("101 fast".parse::<TonePlan>().is_err());
+        assert!("101".parse::<TonePlan>().is_err());
+        assert!("# nothing\n".parse::<TonePlan>().is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut samples = vec![0; 1234];
+        tone(349.0, 1.0, &mut samples);
+        tone(433.7, 3.0, &mut samples);
+        samples.extend(vec![0; 4000]);
+        // A group call holds the B tone for 8 s
+        tone(1153.4, 1.0, &mut samples);
+        tone(707.3, 8.0, &mut samples);
+        samples.extend(vec![0; 4000]);
+        // Tones too short for a page
+        tone(600.0, 0.3, &mut samples);
+        tone(900.0, 0.5, &mut samples);
+
+        let detector = PageDetector::new(8_000.0, 1000.0, 3000.0);
+        let plan: TonePlan = "101 349.0\n123 433.7\n".parse().unwrap();
+        let pages = detector.detect(&samples, Some(&plan));
+        assert_eq!(pages.len(), 2);
+        assert_eq!(
+            pages[0].to_string(),
+            "0.128s 101 349.2 Hz (1.0 s) + 123 433.5 Hz (3.0 s)"
+        );
+        assert!((pages[1].first.frequency - 1153.4).abs() < 1.0);
+        assert!((pages[1].second.frequency - 707.3).abs() < 1.0);
+        assert!((pages[1].second.duration - 8.0).abs() < 0.1);
+        assert_eq!(pages[1].first.code, None);
+    }
+}
@@ -0,0 +1,84 @@
+//! Bounded queues between the stages of a streaming decode, each stage on a thread of its own,
+//! so one that stalls, such as a slow disk being read or output that isn't being taken, only
+//! holds up the others once the queue between them fills. A full queue waits for room,
+//! pushing back on the stage feeding it, so nothing sent is lost.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread::{Scope, ScopedJoinHandle};
+
+/// Runs the iterator `make` returns on a thread of its own in `scope`, queueing up to `depth`
+/// of the items it yields for the stage after. It's made on that thread, so it needn't be sent
+/// there, and stops early if the receiving end goes.
+pub fn source<'scope, T, I, F>(
+    scope: &'scope Scope<'scope, '_>,
+    depth: usize,
+    make: F,
+) -> Receiver<T>
+where
+    T: Send + 'scope,
+    I: IntoIterator<Item = T>,
+    F: FnOnce() -> I + Send + 'scope,
+{
+    let (sender, receiver) = mpsc::sync_channel(depth);
+    scope.spawn(move || {
+        for item in make() {
+            if sender.send(item).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Hands what's queued, up to `depth` items, to `take` on a thread of its own in `scope`,
+/// until every sender's gone or `take` fails. Once it has, sending fails too, so the stage
+/// feeding it knows to stop, and the returned handle joins to its error.
+pub fn sink<'scope, T, E, F>(
+    scope: &'scope Scope<'scope, '_>,
+    depth: usize,
+    take: F,
+) -> (SyncSender<T>, ScopedJoinHandle<'scope, Result<(), E>>)
+where
+    T: Send + 'scope,
+    E: Send + 'scope,
+    F: FnMut(T) -> Result<(), E> + Send + 'scope,
+{
+    let (sender, receiver) = mpsc::sync_channel(depth);
+    let taking = scope.spawn(move || receiver.into_iter().try_for_each(take));
+    (sender, taking)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_stages() {
+        let taken = thread::scope(|scope| {
+            let numbers = source(scope, 1, || 0..100);
+            let mut taken = Vec::new();
+            let (sender, results) = mpsc::sync_channel(100);
+            let (doubled, doubling) = sink(scope, 1, move |n: i32| sender.send(n * 2));
+            for n in numbers {
+                doubled.send(n).unwrap();
+            }
+            drop(doubled);
+            assert!(doubling.join().unwrap().is_ok());
+            taken.extend(results);
+            taken
+        });
+        assert_eq!(taken, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_failed_sink_stops_its_senders() {
+        thread::scope(|scope| {
+            let (sender, taking) = sink(scope, 1, |n: i32| if n < 3 { Ok(()) } else { Err(n) });
+            let sent = (0..100).take_while(|&n| sender.send(n).is_ok()).count();
+            assert!(sent < 100);
+            drop(sender);
+            assert_eq!(taking.join().unwrap(), Err(3));
+        });
+    }
+}
@@ -0,0 +1,339 @@
+use crate::clock::ClockRecovery;
+use std::fmt;
+
+pub const BAUD_RATES: [f64; 3] = [512.0, 1200.0, 2400.0];
+/// Starts every batch of 16 codewords
+const SYNC: u32 = 0x7cd2_15d8;
+/// Fills unused codeword slots
+const IDLE: u32 = 0x7a89_c197;
+/// Generator of the BCH(31,21) code protecting each codeword
+const BCH_POLY: u32 = 0x769;
+const CODEWORDS_PER_BATCH: usize = 16;
+/// Bit errors tolerated in the sync codeword when following a transmission
+const MAX_SYNC_ERRORS: u32 = 2;
+const NUMERIC_CHARS: &[u8; 16] = b"0123456789*U -)(";
+
+#[derive(Debug, PartialEq)]
+pub enum Content {
+    /// An address codeword with no message, which just alerts the pager
+    Tone,
+    Numeric(String),
+    Alpha(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Page {
+    pub baud: f64,
+    /// Seconds from the start of the recording
+    pub time: f64,
+    /// The 21-bit pager address, from the address codeword and its frame
+    pub address: u32,
+    pub function: u8,
+    pub content: Content,
+    /// Bit errors corrected across the page's codewords
+    pub corrected: u32,
+    /// Message codewords that had too many errors to correct and were dropped
+    pub lost: usize,
+}
+
+impl fmt::Display for Page {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "POCSAG{} {:.3}s: Address: {:7} Function: {}",
+            self.baud, self.time, self.address, self.function
+        )?;
+        match &self.content {
+            Content::Tone => write!(f, " Tone")?,
+            Content::Numeric(text) => write!(f, " Numeric: {}", text)?,
+            Content::Alpha(text) => write!(f, " Alpha: {}", text)?,
+        }
+        if self.corrected > 0 {
+            write!(f, " ({} bits corrected)", self.corrected)?;
+        }
+        if self.lost > 0 {
+            write!(f, " ({} codewords lost)", self.lost)?;
+        }
+        Ok(())
+    }
+}
+
+fn syndrome(codeword: u32) -> u32 {
+    let mut remainder = codeword >> 1;
+    for i in (10..31).rev() {
+        if remainder & (1 << i) != 0 {
+            remainder ^= BCH_POLY << (i - 10);
+        }
+    }
+    remainder
+}
+
+fn is_valid(codeword: u32) -> bool {
+    syndrome(codeword) == 0 && codeword.count_ones().is_multiple_of(2)
+}
+
+/// Corrects up to two bit errors, returning the codeword and the number of bits flipped
+pub fn correct(codeword: u32) -> Option<(u32, u32)> {
+    if is_valid(codeword) {
+        return Some((codeword, 0));
+    }
+    for i in 0..32 {
+        let single = codeword ^ (1 << i);
+        if is_valid(single) {
+            return Some((single, 1));
+        }
+    }
+    for i in 0..32 {
+        for j in i + 1..32 {
+            let double = codeword ^ (1 << i) ^ (1 << j);
+            if is_valid(double) {
+                return Some((double, 2));
+            }
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+struct PageBuilder {
+    time: f64,
+    address: u32,
+    function: u8,
+    data: Vec<u8>,
+    message_codewords: usize,
+    corrected: u32,
+    lost: usize,
+}
+
+impl PageBuilder {
+    /// Function 0 is conventionally numeric and the others alphanumeric
+    fn finish(self, baud: f64) -> Page {
+        // Characters are sent least significant bit first
+        let chars = |width: usize| {
+            self.data
+                .chunks_exact(width)
+                .map(|bits| bits.iter().rev().fold(0u8, |value, &bit| value << 1 | bit))
+        };
+        let content = if self.message_codewords == 0 {
+            Content::Tone
+        } else if self.function == 0 {
+            let text: String = chars(4)
+                .map(|d| char::from(NUMERIC_CHARS[usize::from(d)]))
+                .collect();
+            Content::Numeric(text.trim_end().to_string())
+        } else {
+            let text: String = chars(7)
+                .map(char::from)
+                .filter(|&c| c != '\0' && c != '\x03' && c != '\x04')
+                .collect();
+            Content::Alpha(text)
+        };
+        Page {
+            baud,
+            time: self.time,
+            address: self.address,
+            function: self.function,
+            content,
+            corrected: self.corrected,
+            lost: self.lost,
+        }
+    }
+}
+
+/// Decodes pages from a bit stream, following batches from each sync codeword in either
+/// polarity since discriminator outputs differ
+pub fn decode_bits(bits: &[u8], baud: f64) -> Vec<Page> {
+    let word = |pos: usize| {
+        bits.get(pos..pos + 32).map(|w| {
+            w.iter()
+                .fold(0u32, |value, &bit| value << 1 | u32::from(bit))
+        })
+    };
+    let mut pages = Vec::new();
+    let mut pos = 0;
+    while let Some(sync) = word(pos) {
+        let invert = if sync == SYNC {
+            0
+        } else if sync == !SYNC {
+            !0
+        } else {
+            pos += 1;
+            continue;
+        };
+        pos += 32;
+        let mut page: Option<PageBuilder> = None;
+        loop {
+            for slot in 0..CODEWORDS_PER_BATCH {
+                let codeword = match word(pos) {
+                    Some(codeword) => codeword ^ invert,
+                    None => break,
+                };
+                let time = pos as f64 / baud;
+                pos += 32;
+                let (codeword, flipped) = match correct(codeword) {
+                    Some(corrected) => corrected,
+                    None => {
+                        if let Some(page) = &mut page {
+                            page.lost += 1;
+                        }
+                        continue;
+                    }
+                };
+                if codeword == IDLE || codeword & 0x8000_0000 == 0 {
+                    pages.extend(page.take().map(|p| p.finish(baud)));
+                }
+                if codeword == IDLE {
+                    continue;
+                }
+                if codeword & 0x8000_0000 == 0 {
+                    // Each address is only sent in the frame matching its low three bits
+                    page = Some(PageBuilder {
+                        time,
+                        address: (codeword >> 13 & 0x3ffff) << 3 | (slot / 2) as u32,
+                        function: (codeword >> 11 & 3) as u8,
+                        data: Vec::new(),
+                        message_codewords: 0,
+                        corrected: flipped,
+                        lost: 0,
+                    });
+                } else if let Some(page) = &mut page {
+                    page.data
+                        .extend((11..31).rev().map(|i| (codeword >> i & 1) as u8));
+                    page.message_codewords += 1;
+                    page.corrected += flipped;
+                }
+            }
+            // A page can continue into the next batch
+            match word(pos) {
+                Some(sync) if (sync ^ invert ^ SYNC).count_ones() <= MAX_SYNC_ERRORS => pos += 32,
+                _ => break,
+            }
+        }
+        pages.extend(page.take().map(|p| p.finish(baud)));
+    }
+    pages
+}
+
+/// Decodes discriminator audio, where the NRZ signal is the audio itself. The DC offset left
+/// by any tuning error is removed and the signal is smoothed over half a bit before
+/// recovering the bit clock.
+pub fn decode_audio(samples: &[i16], sampling_rate: f64, baud: f64) -> Vec<Page> {
+    let samples_per_bit = sampling_rate / baud;
+    let smoothing = (samples_per_bit / 2.0).max(1.0) as usize;
+    let dc_rate = 1.0 / (32.0 * samples_per_bit);
+    let mut dc = 0.0;
+    let mut sum = 0.0;
+    let mut clock = ClockRecovery::new(samples_per_bit);
+    let bits: Vec<u8> = samples
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &sample)| {
+            sum += f64::from(sample);
+            if i >= smoothing {
+                sum -= f64::from(samples[i - smoothing]);
+            }
+            let smoothed = sum / smoothing as f64;
+            dc += dc_rate * (smoothed - dc);
+            clock.push(smoothed - dc)
+        })
+        .map(|soft| u8::from(soft > 0.0))
+        .collect();
+    decode_bits(&bits, baud)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::AMPLITUDE;
+
+    /// Builds a codeword from its 21 leading bits
+    fn codeword(data: u32) -> u32 {
+        let mut codeword = data << 11;
+        let mut remainder = codeword >> 1;
+        for i in (10..31).rev() {
+            if remainder & (1 << i) != 0 {
+                remainder ^= BCH_POLY << (i - 10);
+            }
+        }
+        codeword |= remainder << 1;
+        codeword | (codeword.count_ones() % 2)
+    }
+
+    fn address_codeword(address: u32, function: u32) -> u32 {
+        codeword((address >> 3) << 2 | function)
+    }
+
+    fn message_codewords(text: &str) -> Vec<u32> {
+        let mut bits: Vec<u32> = text
+            .bytes()
+            .flat_map(|b| (0..7).map(move |i| u32::from(b >> i & 1)))
+            .collect();
+        while !bits.len().is_multiple_of(20) {
+            bits.push(0);
+        }
+        bits.chunks(20)
+            .map(|chunk| codeword(1 << 20 | chunk.iter().fold(0, |v, &b| v << 1 | b)))
+            .collect()
+    }
+
+    /// One batch with an alpha page to address 1234569 (frame 1) and a tone page
+    fn transmission() -> Vec<u8> {
+        let mut codewords = vec![IDLE; 2];
+        codewords.push(address_codeword(1_234_569, 3));
+        codewords.extend(message_codewords("HELLO WORLD"));
+        codewords.push(IDLE);
+        codewords.push(address_codeword(1_234_564, 0));
+        while codewords.len() < CODEWORDS_PER_BATCH {
+            codewords.push(IDLE);
+        }
+        let mut bits: Vec<u8> = (0..576).map(|i| (i % 2) as u8 ^ 1).collect();
+        for word in std::iter::once(SYNC).chain(codewords) {
+            bits.extend((0..32).rev().map(|i| (word >> i & 1) as u8));
+        }
+        bits
+    }
+
+    #[test]
+    fn test_correct() {
+        let word = address_codeword(1_234_567, 2);
+        assert_eq!(correct(word), Some((word, 0)));
+        assert_eq!(correct(word ^ 0x0010_0001), Some((word, 2)));
+        assert_eq!(correct(word ^ 0x0000_0007), None);
+    }
+
+    #[test]
+    fn test_decode_bits() {
+        let mut bits = transmission();
+        // Two errors in the first message codeword
+        bits[576 + 32 * 4 + 5] ^= 1;
+        bits[576 + 32 * 4 + 20] ^= 1;
+        let pages = decode_bits(&bits, 1200.0);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(
+            pages[0].to_string(),
+            "POCSAG1200 0.560s: Address: 1234569 Function: 3 Alpha: HELLO WORLD (2 bits corrected)"
+        );
+        assert_eq!(pages[1].content, Content::Tone);
+        assert_eq!(pages[1].address, 1_234_564);
+    }
+
+    #[test]
+    fn test_decode_inverted_audio() {
+        let bits = transmission();
+        // Discriminator output with a DC offset, slow edges and inverted polarity
+        let samples_per_bit = 48_000.0 / 2400.0;
+        let mut level = 0.0;
+        let samples: Vec<i16> = (0..(bits.len() as f64 * samples_per_bit) as usize)
+            .map(|i| {
+                let bit = bits[(i as f64 / samples_per_bit) as usize];
+                let target = if bit == 1 { -0.4 } else { 0.4 };
+                level += 0.3 * (target - level);
+                (AMPLITUDE * (level + 0.1)) as i16
+            })
+            .collect();
+        let pages = decode_audio(&samples, 48_000.0, 2400.0);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].content, Content::Alpha("HELLO WORLD".to_string()));
+        assert_eq!(decode_audio(&samples, 48_000.0, 1200.0), []);
+    }
+}
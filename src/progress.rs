@@ -0,0 +1,537 @@
+use crate::goertzel::{self, GoertzelFilter};
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Tones are classified over blocks of 50 ms, fine enough to separate the 40 Hz between
+/// the ringback and busy tones, advancing by half a block
+const BLOCK_SECS: f64 = 0.05;
+/// The tones of a signal must hold this much of a block's energy
+const MIN_TONE_ENERGY: f64 = 0.6;
+/// The tones of a pair may differ by up to 10 dB
+const MIN_PAIR_RATIO: f64 = 0.1;
+/// Tones that drop out for no longer than this are still one burst, e.g. across a phase
+/// reversal
+const MAX_DROPOUT_SECS: f64 = 0.1;
+/// Bursts of the same tone closer than this are one signal, covering ringback's 4 s off
+const MAX_CADENCE_GAP: f64 = 4.5;
+/// Busy signals with shorter bursts than this are the fast reorder cadence
+const MAX_REORDER_ON: f64 = 0.375;
+/// Filters are spread this far apart across the tolerance of a tone
+const TOLERANCE_STEP: f64 = 10.0;
+/// The low and high frequencies of the first and second SIT segments, and the third
+/// segment's frequency
+const SIT_FIRST: [f64; 2] = [913.8, 985.2];
+const SIT_SECOND: [f64; 2] = [1370.6, 1428.5];
+const SIT_THIRD: f64 = 1776.7;
+const SIT_TOLERANCE: f64 = 15.0;
+/// SIT segments are either short, 276 ms, or long, 380 ms
+const SIT_LONG_SECS: f64 = 0.328;
+
+/// Call progress and modem handshake signals
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tone {
+    /// 350 + 440 Hz continuous
+    Dial,
+    /// 440 + 480 Hz, 2 s on 4 s off
+    Ringback,
+    /// 480 + 620 Hz, 0.5 s on 0.5 s off
+    Busy,
+    /// 480 + 620 Hz, 0.25 s on 0.25 s off
+    Reorder,
+    /// 1100 Hz fax and modem calling tone, 0.5 s on 3 s off
+    Cng,
+    /// 2100 Hz answer tone, with phase reversals every 450 ms when echo cancellers must be
+    /// disabled
+    Ans,
+    /// Special information tone ahead of a network announcement
+    Sit(SitCode),
+}
+
+impl Tone {
+    fn name(self) -> &'static str {
+        match self {
+            Tone::Dial => "Dial tone",
+            Tone::Ringback => "Ringback",
+            Tone::Busy => "Busy",
+            Tone::Reorder => "Reorder",
+            Tone::Cng => "CNG calling tone",
+            Tone::Ans => "ANS answer tone",
+            Tone::Sit(code) => code.name(),
+        }
+    }
+
+    /// What each burst of a cadenced signal is called
+    fn burst_name(self) -> Option<&'static str> {
+        match self {
+            Tone::Ringback => Some("rings"),
+            Tone::Busy | Tone::Reorder => Some("cycles"),
+            Tone::Cng => Some("beeps"),
+            Tone::Dial | Tone::Ans | Tone::Sit(_) => None,
+        }
+    }
+}
+
+/// The reason given by a special information tone, from the frequency and length of its
+/// first two segments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SitCode {
+    /// Intercept: the number has changed or been disconnected
+    Ic,
+    /// Vacant code: an unassigned prefix or service code
+    Vc,
+    /// No circuit found, within the local network
+    Nc,
+    /// No circuit found, on the long distance network
+    NcInter,
+    /// Reorder, the local network is busy
+    Ro,
+    /// Reorder, the long distance network is busy
+    RoInter,
+    /// Ineffective other: misdialling, coin deposit required or another failure
+    Io,
+    /// A combination not assigned a meaning
+    Unassigned,
+}
+
+impl SitCode {
+    /// Looks up a code from whether each of the first two segments was the high frequency
+    /// and whether it was long
+    pub fn from_segments(first: (bool, bool), second: (bool, bool)) -> Self {
+        match (first, second) {
+            ((false, false), (false, false)) => SitCode::Ic,
+            ((true, true), (false, false)) => SitCode::Vc,
+            ((false, true), (false, true)) => SitCode::Nc,
+            ((true, true), (true, true)) => SitCode::NcInter,
+            ((false, false), (true, true)) => SitCode::Ro,
+            ((true, false), (false, true)) => SitCode::RoInter,
+            ((false, true), (true, false)) => SitCode::Io,
+            _ => SitCode::Unassigned,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SitCode::Ic => "SIT IC, number changed or disconnected",
+            SitCode::Vc => "SIT VC, vacant code",
+            SitCode::Nc => "SIT NC, no circuit found",
+            SitCode::NcInter => "SIT NC', no long distance circuit found",
+            SitCode::Ro => "SIT RO, reorder",
+            SitCode::RoInter => "SIT RO', long distance reorder",
+            SitCode::Io => "SIT IO, ineffective other",
+            SitCode::Unassigned => "SIT with an unassigned code",
+        }
+    }
+}
+
+/// What a block holds: one of the signals, or one segment of a SIT as its index and whether
+/// it is the high frequency
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Signal(Tone),
+    SitSegment(usize, bool),
+}
+
+/// The frequencies making up each class of block, and how far off in Hz each may be
+const CLASSES: [(Class, &[f64], f64); 10] = [
+    (Class::Signal(Tone::Dial), &[350.0, 440.0], 0.0),
+    (Class::Signal(Tone::Ringback), &[440.0, 480.0], 0.0),
+    (Class::Signal(Tone::Busy), &[480.0, 620.0], 0.0),
+    (Class::Signal(Tone::Cng), &[1100.0], 38.0),
+    (Class::Signal(Tone::Ans), &[2100.0], 15.0),
+    (Class::SitSegment(0, false), &[SIT_FIRST[0]], SIT_TOLERANCE),
+    (Class::SitSegment(0, true), &[SIT_FIRST[1]], SIT_TOLERANCE),
+    (Class::SitSegment(1, false), &[SIT_SECOND[0]], SIT_TOLERANCE),
+    (Class::SitSegment(1, true), &[SIT_SECOND[1]], SIT_TOLERANCE),
+    (Class::SitSegment(2, false), &[SIT_THIRD], SIT_TOLERANCE),
+];
+
+/// A signal found in a recording, timed in seconds
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    pub tone: Tone,
+    pub start: f64,
+    pub duration: f64,
+    /// The number of bursts in a cadenced signal
+    pub bursts: usize,
+    /// Phase reversals in an answer tone
+    pub reversals: usize,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}s {}", self.start, self.tone.name())?;
+        if self.reversals > 0 {
+            write!(f, " with phase reversals")?;
+        }
+        write!(f, " ({:.2} s", self.duration)?;
+        if let Some(name) = self.tone.burst_name() {
+            write!(f, ", {} {}", self.bursts, name)?;
+        }
+        if self.reversals > 0 {
+            write!(f, ", {} reversals", self.reversals)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A stretch of blocks holding one tone
+#[derive(Debug)]
+struct Burst {
+    class: Class,
+    first: usize,
+    last: usize,
+    /// The answer tone's phase in each block, for finding reversals
+    phases: Vec<(usize, f64)>,
+}
+
+/// Finds call progress tones with Goertzel filters for each frequency
+#[derive(Debug)]
+pub struct ToneDetector {
+    sampling_rate: f64,
+    block_size: usize,
+}
+
+impl ToneDetector {
+    pub fn new(sampling_rate: f64) -> Self {
+        Self {
+            sampling_rate,
+            block_size: (sampling_rate * BLOCK_SECS).round() as usize,
+        }
+    }
+
+    pub fn detect(&self, samples: &[i16]) -> Vec<Event> {
+        let hop = self.block_size / 2;
+        let max_dropout = (MAX_DROPOUT_SECS * self.sampling_rate / hop as f64).round() as usize;
+        let blocks = (samples.len() + hop).saturating_sub(self.block_size) / hop;
+        let mut bursts: Vec<Burst> = Vec::new();
+        for i in 0..blocks {
+            let block = &samples[i * hop..i * hop + self.block_size];
+            let class = match self.classify(block) {
+                Some(class) => class,
+                None => continue,
+            };
+            let phase = if class == Class::Signal(Tone::Ans) {
+                Some((i, self.phase(block, i * hop)))
+            } else {
+                None
+            };
+            match bursts.last_mut() {
+                Some(burst) if burst.class == class && i - burst.last <= max_dropout + 1 => {
+                    burst.last = i;
+                    burst.phases.extend(phase);
+                }
+                _ => bursts.push(Burst {
+                    class,
+                    first: i,
+                    last: i,
+                    phases: phase.into_iter().collect(),
+                }),
+            }
+        }
+
+        // Group the bursts of each cadence into one signal, and each run of three SIT
+        // segments into one SIT
+        let time = |block: usize| (block * hop) as f64 / self.sampling_rate;
+        let block_secs = self.block_size as f64 / self.sampling_rate;
+        let mut events: Vec<Event> = Vec::new();
+        let mut on_time = 0.0;
+        let mut i = 0;
+        while i < bursts.len() {
+            let burst = &bursts[i];
+            let tone = match burst.class {
+                Class::Signal(tone) => tone,
+                Class::SitSegment(..) => {
+                    let sit = self.sit(&bursts[i..], max_dropout);
+                    i += if sit.is_some() { 3 } else { 1 };
+                    events.extend(sit);
+                    continue;
+                }
+            };
+            i += 1;
+            let start = time(burst.first);
+            let end = time(burst.last) + block_secs;
+            let reversals = count_reversals(&burst.phases);
+            match events.last_mut() {
+                Some(event)
+                    if (event.tone == tone
+                        || (event.tone == Tone::Reorder && tone == Tone::Busy))
+                        && start - (event.start + event.duration) <= MAX_CADENCE_GAP =>
+                {
+                    event.duration = end - event.start;
+                    event.bursts += 1;
+                    event.reversals += reversals;
+                }
+                _ => {
+                    on_time = 0.0;
+                    events.push(Event {
+                        tone,
+                        start,
+                        duration: end - start,
+                        bursts: 1,
+                        reversals,
+                    });
+                }
+            }
+            on_time += end - start;
+            let event = events.last_mut().unwrap();
+            if event.tone == Tone::Busy || event.tone == Tone::Reorder {
+                let fast = event.bursts > 1 && on_time / (event.bursts as f64) < MAX_REORDER_ON;
+                event.tone = if fast { Tone::Reorder } else { Tone::Busy };
+            }
+        }
+        events
+    }
+
+    /// Reads a SIT from three back to back bursts holding its segments in order. Each
+    /// segment is timed by the number of blocks holding it, since the blocks straddling a
+    /// change of frequency fall to one side or neither.
+    fn sit(&self, bursts: &[Burst], max_dropout: usize) -> Option<Event> {
+        let segments = bursts.get(..3)?;
+        let mut high = [false; 3];
+        for (i, burst) in segments.iter().enumerate() {
+            match burst.class {
+                Class::SitSegment(segment, is_high) if segment == i => high[i] = is_high,
+                _ => return None,
+            }
+        }
+        let adjacent = segments
+            .windows(2)
+            .all(|pair| pair[1].first - pair[0].last <= max_dropout + 1);
+        if !adjacent {
+            return None;
+        }
+        let hop = (self.block_size / 2) as f64 / self.sampling_rate;
+        let long = |burst: &Burst| (burst.last - burst.first + 1) as f64 * hop >= SIT_LONG_SECS;
+        let code =
+            SitCode::from_segments((high[0], long(&segments[0])), (high[1], long(&segments[1])));
+        let start = segments[0].first as f64 * hop;
+        let end = segments[2].last as f64 * hop + self.block_size as f64 / self.sampling_rate;
+        Some(Event {
+            tone: Tone::Sit(code),
+            start,
+            duration: end - start,
+            bursts: 1,
+            reversals: 0,
+        })
+    }
+
+    /// Checks a block's level and how much of it each class's tones account for, returning
+    /// the best class if it passes
+    fn classify(&self, block: &[i16]) -> Option<Class> {
+        if goertzel::is_silent(block) {
+            return None;
+        }
+        let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+        // Scale Goertzel magnitudes to the energy of the tone in the block, taking the best
+        // of the filters spread across a tone's tolerance
+        let power = |frequency: f64, tolerance: f64| {
+            let steps = (tolerance / TOLERANCE_STEP).ceil() as i32;
+            (-steps..=steps)
+                .map(|step| {
+                    let offset = f64::from(step) * TOLERANCE_STEP;
+                    let mut filter =
+                        GoertzelFilter::new(block.len(), frequency + offset, self.sampling_rate);
+                    filter.process(block);
+                    2.0 * filter.get_mag_sq() / block.len() as f64
+                })
+                .fold(0.0, f64::max)
+        };
+        CLASSES
+            .iter()
+            .filter_map(|&(class, frequencies, tolerance)| {
+                let powers: Vec<f64> = frequencies
+                    .iter()
+                    .map(|&frequency| power(frequency, tolerance))
+                    .collect();
+                let total: f64 = powers.iter().sum();
+                let strongest = powers.iter().cloned().fold(0.0, f64::max);
+                let balanced = powers.iter().all(|&p| p >= MIN_PAIR_RATIO * strongest);
+                Some((class, total)).filter(|_| balanced && total >= MIN_TONE_ENERGY * energy)
+            })
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(class, _)| class)
+    }
+
+    /// The answer tone's phase in a block, relative to the start of the recording
+    fn phase(&self, block: &[i16], offset: usize) -> f64 {
+        let omega = 2.0 * PI * 2100.0 / self.sampling_rate;
+        let (mut re, mut im) = (0.0, 0.0);
+        for (n, &sample) in block.iter().enumerate() {
+            let angle = omega * (offset + n) as f64;
+            re += f64::from(sample) * angle.cos();
+            im -= f64::from(sample) * angle.sin();
+        }
+        im.atan2(re)
+    }
+}
+
+/// Wraps an angle into (-π, π]
+fn wrap(angle: f64) -> f64 {
+    let wrapped = angle.rem_euclid(2.0 * PI);
+    if wrapped > PI {
+        wrapped - 2.0 * PI
+    } else {
+        wrapped
+    }
+}
+
+/// Counts the half turns in the answer tone's phase between blocks. A tone slightly off
+/// 2100 Hz drifts in phase from block to block, which is measured over neighbouring blocks
+/// and taken out first. Reversals are rare enough not to upset that average.
+fn count_reversals(phases: &[(usize, f64)]) -> usize {
+    let (mut re, mut im) = (0.0, 0.0);
+    for pair in phases.windows(2) {
+        if pair[1].0 == pair[0].0 + 1 {
+            let change = pair[1].1 - pair[0].1;
+            re += change.cos();
+            im += change.sin();
+        }
+    }
+    let drift = im.atan2(re);
+    phases
+        .windows(2)
+        .filter(|pair| {
+            let blocks = (pair[1].0 - pair[0].0) as f64;
+            wrap(pair[1].1 - pair[0].1 - blocks * drift).abs() > PI / 2.0
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{Oscillator, AMPLITUDE};
+
+    const SAMPLING_RATE: f64 = 8_000.0;
+
+    /// Appends `secs` of the tones, inverting the phase every `reversal` seconds if given
+    fn tone(frequencies: &[f64], secs: f64, reversal: Option<f64>, samples: &mut Vec<i16>) {
+        let mut oscillators: Vec<Oscillator> = frequencies
+            .iter()
+            .map(|&frequency| Oscillator::new(frequency, SAMPLING_RATE))
+            .collect();
+        for n in 0..(secs * SAMPLING_RATE) as usize {
+            let sum: f64 = oscillators.iter_mut().map(|o| o.next_sample()).sum();
+            let flips = reversal.map_or(0, |every| (n as f64 / SAMPLING_RATE / every) as usize);
+            let sign = if flips.is_multiple_of(2) { 1.0 } else { -1.0 };
+            let sample = sign * AMPLITUDE * sum / frequencies.len() as f64;
+            samples.push(sample as i16);
+        }
+    }
+
+    fn silence(secs: f64, samples: &mut Vec<i16>) {
+        samples.extend(std::iter::repeat_n(0, (secs * SAMPLING_RATE) as usize));
+    }
+
+    fn kinds(events: &[Event]) -> Vec<(Tone, usize, usize)> {
+        events
+            .iter()
+            .map(|event| (event.tone, event.bursts, event.reversals))
+            .collect()
+    }
+
+    #[test]
+    fn test_call_progress() {
+        let mut samples = Vec::new();
+        tone(&[350.0, 440.0], 2.0, None, &mut samples);
+        silence(1.0, &mut samples);
+        for _ in 0..2 {
+            tone(&[440.0, 480.0], 2.0, None, &mut samples);
+            silence(4.0, &mut samples);
+        }
+        for _ in 0..3 {
+            tone(&[480.0, 620.0], 0.5, None, &mut samples);
+            silence(0.5, &mut samples);
+        }
+        silence(5.0, &mut samples);
+        for _ in 0..4 {
+            tone(&[480.0, 620.0], 0.25, None, &mut samples);
+            silence(0.25, &mut samples);
+        }
+
+        let events = ToneDetector::new(SAMPLING_RATE).detect(&samples);
+        assert_eq!(
+            kinds(&events),
+            vec![
+                (Tone::Dial, 1, 0),
+                (Tone::Ringback, 2, 0),
+                (Tone::Busy, 3, 0),
+                (Tone::Reorder, 4, 0),
+            ]
+        );
+        assert_eq!(events[0].start, 0.0);
+        assert!((events[0].duration - 2.0).abs() < 0.06);
+        assert!((events[1].start - 3.0).abs() < 0.06);
+        assert!((events[1].duration - 8.0).abs() < 0.06);
+    }
+
+    #[test]
+    fn test_modem_handshake() {
+        let mut samples = Vec::new();
+        for _ in 0..2 {
+            tone(&[1100.0], 0.5, None, &mut samples);
+            silence(3.0, &mut samples);
+        }
+        // An answer tone 10 Hz off with reversals, then a plain one after a pause
+        tone(&[2110.0], 2.5, Some(0.45), &mut samples);
+        silence(5.0, &mut samples);
+        tone(&[2100.0], 2.0, None, &mut samples);
+
+        let events = ToneDetector::new(SAMPLING_RATE).detect(&samples);
+        assert_eq!(
+            kinds(&events),
+            vec![(Tone::Cng, 2, 0), (Tone::Ans, 1, 5), (Tone::Ans, 1, 0)]
+        );
+        assert_eq!(
+            events[1].to_string(),
+            "7.000s ANS answer tone with phase reversals (2.50 s, 5 reversals)"
+        );
+        assert_eq!(
+            events[0].to_string(),
+            "0.000s CNG calling tone (4.00 s, 2 beeps)"
+        );
+    }
+
+    #[test]
+    fn test_sit_codes() {
+        let codes = [
+            ((false, false), (false, false), SitCode::Ic),
+            ((true, true), (false, false), SitCode::Vc),
+            ((false, true), (false, true), SitCode::Nc),
+            ((true, true), (true, true), SitCode::NcInter),
+            ((false, false), (true, true), SitCode::Ro),
+            ((true, false), (false, true), SitCode::RoInter),
+            ((false, true), (true, false), SitCode::Io),
+            ((true, false), (true, false), SitCode::Unassigned),
+        ];
+        let segment = |frequencies: &[f64; 2], (high, long): (bool, bool)| {
+            (frequencies[high as usize], if long { 0.380 } else { 0.276 })
+        };
+        let mut samples = Vec::new();
+        for &(first, second, _) in &codes {
+            let (frequency, secs) = segment(&SIT_FIRST, first);
+            tone(&[frequency], secs, None, &mut samples);
+            let (frequency, secs) = segment(&SIT_SECOND, second);
+            tone(&[frequency], secs, None, &mut samples);
+            tone(&[SIT_THIRD], 0.380, None, &mut samples);
+            silence(2.0, &mut samples);
+        }
+
+        let events = ToneDetector::new(SAMPLING_RATE).detect(&samples);
+        let found: Vec<Tone> = events.iter().map(|event| event.tone).collect();
+        let expected: Vec<Tone> = codes.iter().map(|&(_, _, code)| Tone::Sit(code)).collect();
+        assert_eq!(found, expected);
+        assert_eq!(
+            events[0].to_string(),
+            "0.000s SIT IC, number changed or disconnected (0.95 s)"
+        );
+    }
+
+    #[test]
+    fn test_ignores_other_tones() {
+        let mut samples = Vec::new();
+        tone(&[1000.0], 1.0, None, &mut samples);
+        tone(&[697.0, 1209.0], 1.0, None, &mut samples);
+        assert!(ToneDetector::new(SAMPLING_RATE).detect(&samples).is_empty());
+    }
+}
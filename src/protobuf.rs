@@ -0,0 +1,145 @@
+//! Enough of the Protocol Buffers wire format for the gRPC service's messages, in
+//! `proto/bell103.proto`: fields are read out of a message by number, and written one at a
+//! time.
+
+use std::convert::{TryFrom, TryInto};
+
+/// A field's value as it's encoded, which its type in the schema says how to read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> Value<'a> {
+    pub fn as_bool(self) -> Option<bool> {
+        match self {
+            Value::Varint(value) => Some(value != 0),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(self) -> Option<f64> {
+        match self {
+            Value::Fixed64(bits) => Some(f64::from_bits(bits)),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(self) -> Option<&'a [u8]> {
+        match self {
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> Option<&'a str> {
+        self.as_bytes()
+            .and_then(|bytes| std::str::from_utf8(bytes).ok())
+    }
+}
+
+/// Every field in a message, as its number and value
+pub fn parse(mut message: &[u8]) -> Result<Vec<(u32, Value<'_>)>, String> {
+    let mut fields = Vec::new();
+    while !message.is_empty() {
+        let key = varint(&mut message)?;
+        let number = u32::try_from(key >> 3).map_err(|_| "a field number is too big")?;
+        let value = match key & 7 {
+            0 => Value::Varint(varint(&mut message)?),
+            1 => Value::Fixed64(u64::from_le_bytes(
+                take(&mut message, 8)?.try_into().unwrap(),
+            )),
+            2 => {
+                let length = varint(&mut message)?;
+                let length = usize::try_from(length).map_err(|_| "a field is too long")?;
+                Value::Bytes(take(&mut message, length)?)
+            }
+            5 => Value::Fixed32(u32::from_le_bytes(
+                take(&mut message, 4)?.try_into().unwrap(),
+            )),
+            kind => return Err(format!("field {} has unknown wire type {}", number, kind)),
+        };
+        fields.push((number, value));
+    }
+    Ok(fields)
+}
+
+fn varint(message: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = message.split_first().ok_or("a message ends in a varint")?;
+        *message = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("a varint is too long".to_string())
+}
+
+fn take<'a>(message: &mut &'a [u8], length: usize) -> Result<&'a [u8], String> {
+    if length > message.len() {
+        return Err("a field runs past the message".to_string());
+    }
+    let (value, rest) = message.split_at(length);
+    *message = rest;
+    Ok(value)
+}
+
+fn put_varint(message: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        message.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    message.push(value as u8);
+}
+
+pub fn put_bool(message: &mut Vec<u8>, number: u32, value: bool) {
+    put_varint(message, u64::from(number) << 3);
+    put_varint(message, u64::from(value));
+}
+
+pub fn put_double(message: &mut Vec<u8>, number: u32, value: f64) {
+    put_varint(message, u64::from(number) << 3 | 1);
+    message.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_bytes(message: &mut Vec<u8>, number: u32, value: &[u8]) {
+    put_varint(message, u64::from(number) << 3 | 2);
+    put_varint(message, value.len() as u64);
+    message.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let mut message = Vec::new();
+        put_bytes(&mut message, 1, &[7; 300]);
+        put_double(&mut message, 2, 44_100.0);
+        put_bytes(&mut message, 3, b"v21");
+        put_bool(&mut message, 4, true);
+        // An int32 of -1, which takes ten bytes, and a fixed32
+        message.extend_from_slice(&[0x28, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        message.extend_from_slice(&[0x01, 0x35, 1, 0, 0, 0]);
+        assert_eq!(&message[..3], [0x0a, 0xac, 0x02]);
+
+        let fields = parse(&message).unwrap();
+        assert_eq!(fields.len(), 6);
+        assert_eq!(fields[0], (1, Value::Bytes(&[7; 300])));
+        assert_eq!(fields[1].1.as_double(), Some(44_100.0));
+        assert_eq!(fields[2].1.as_str(), Some("v21"));
+        assert_eq!(fields[3].1.as_bool(), Some(true));
+        assert_eq!(fields[4], (5, Value::Varint(u64::MAX)));
+        assert_eq!(fields[5], (6, Value::Fixed32(1)));
+        assert_eq!(fields[2].1.as_bool(), None);
+
+        assert!(parse(&message[..message.len() - 1]).is_err());
+        assert!(parse(&[0x0b]).is_err());
+    }
+}
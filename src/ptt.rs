@@ -0,0 +1,313 @@
+use serialport::SerialPort;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+/// Keys a transmitter on and off around a transmission
+pub trait Ptt {
+    fn set(&mut self, keyed: bool) -> io::Result<()>;
+}
+
+/// Serial control line wired to a transmitter's PTT input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SerialLine {
+    Rts,
+    Dtr,
+}
+
+impl FromStr for SerialLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rts" => Ok(SerialLine::Rts),
+            "dtr" => Ok(SerialLine::Dtr),
+            _ => Err(format!("unknown serial line `{}`, expected rts or dtr", s)),
+        }
+    }
+}
+
+/// Keys PTT with the RTS or DTR line of a serial port, as most radio interface cables expect
+pub struct SerialPtt {
+    port: Box<dyn SerialPort>,
+    line: SerialLine,
+}
+
+impl SerialPtt {
+    pub fn open(path: &str, line: SerialLine) -> io::Result<Self> {
+        let port = serialport::new(path, 9600).open()?;
+        let mut ptt = Self { port, line };
+        // Opening a port can assert its control lines, so make sure we start unkeyed
+        ptt.set(false)?;
+        Ok(ptt)
+    }
+}
+
+impl Ptt for SerialPtt {
+    fn set(&mut self, keyed: bool) -> io::Result<()> {
+        match self.line {
+            SerialLine::Rts => self.port.write_request_to_send(keyed)?,
+            SerialLine::Dtr => self.port.write_data_terminal_ready(keyed)?,
+        }
+        Ok(())
+    }
+}
+
+/// Runs user supplied shell commands to key and unkey the transmitter
+#[derive(Debug)]
+pub struct CommandPtt {
+    pub on: String,
+    pub off: String,
+}
+
+impl Ptt for CommandPtt {
+    fn set(&mut self, keyed: bool) -> io::Result<()> {
+        let command = if keyed { &self.on } else { &self.off };
+        let status = shell(command).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "`{}` failed with {}",
+                command, status
+            )))
+        }
+    }
+}
+
+/// A GPIO line keying the transmitter, written `chip:line` or just the line number on
+/// `gpiochip0`, e.g. `gpiochip0:17` for GPIO17 of a Raspberry Pi
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpioLine {
+    pub chip: PathBuf,
+    pub line: u32,
+}
+
+impl FromStr for GpioLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chip, line) = s.rsplit_once(':').unwrap_or(("gpiochip0", s));
+        let line = line.parse().map_err(|_| {
+            format!(
+                "`{}` isn't a GPIO line, expected e.g. 17 or gpiochip0:17",
+                s
+            )
+        })?;
+        // Chips are found under /dev unless given as a path
+        let chip = if chip.contains('/') {
+            PathBuf::from(chip)
+        } else {
+            Path::new("/dev").join(chip)
+        };
+        Ok(GpioLine { chip, line })
+    }
+}
+
+/// Keys PTT with a GPIO line through the Linux GPIO character device, e.g. driving the
+/// transistor of a Raspberry Pi radio interface. The line is held until this is dropped.
+#[derive(Debug)]
+pub struct GpioPtt {
+    #[cfg(all(feature = "gpio", target_os = "linux"))]
+    line: std::fs::File,
+}
+
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+impl GpioPtt {
+    /// Takes `gpio` as an output, driving it low to key unless `active_low`
+    pub fn open(gpio: &GpioLine, active_low: bool) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let chip = std::fs::File::open(&gpio.chip)?;
+        let mut request = gpio::LineRequest::output(gpio.line, active_low);
+        gpio::ioctl(&chip, gpio::GET_LINE, &mut request)?;
+        let line = unsafe { std::fs::File::from_raw_fd(request.fd) };
+        let mut ptt = Self { line };
+        ptt.set(false)?;
+        Ok(ptt)
+    }
+}
+
+#[cfg(not(all(feature = "gpio", target_os = "linux")))]
+impl GpioPtt {
+    pub fn open(_: &GpioLine, _: bool) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "This build can't key PTT with GPIO, rebuild on Linux with `cargo build --features gpio`",
+        ))
+    }
+}
+
+impl Ptt for GpioPtt {
+    #[cfg(all(feature = "gpio", target_os = "linux"))]
+    fn set(&mut self, keyed: bool) -> io::Result<()> {
+        let mut values = gpio::LineValues {
+            bits: u64::from(keyed),
+            mask: 1,
+        };
+        gpio::ioctl(&self.line, gpio::SET_VALUES, &mut values)
+    }
+
+    #[cfg(not(all(feature = "gpio", target_os = "linux")))]
+    fn set(&mut self, _: bool) -> io::Result<()> {
+        unreachable!("a GpioPtt can't be opened without the gpio feature")
+    }
+}
+
+/// The parts of the kernel's GPIO v2 interface, `linux/gpio.h`, that request one output
+#[cfg(all(feature = "gpio", target_os = "linux"))]
+mod gpio {
+    use std::fs::File;
+    use std::io;
+    use std::mem::size_of;
+    use std::os::unix::io::AsRawFd;
+
+    const LINES_MAX: usize = 64;
+    const NUM_ATTRS_MAX: usize = 10;
+    const FLAG_ACTIVE_LOW: u64 = 1 << 1;
+    const FLAG_OUTPUT: u64 = 1 << 3;
+
+    #[repr(C, align(8))]
+    #[derive(Clone, Copy)]
+    struct ConfigAttribute {
+        id: u32,
+        padding: u32,
+        value: u64,
+        mask: u64,
+    }
+
+    #[repr(C, align(8))]
+    struct LineConfig {
+        flags: u64,
+        num_attrs: u32,
+        padding: [u32; 5],
+        attrs: [ConfigAttribute; NUM_ATTRS_MAX],
+    }
+
+    #[repr(C, align(8))]
+    pub struct LineRequest {
+        offsets: [u32; LINES_MAX],
+        consumer: [u8; 32],
+        config: LineConfig,
+        num_lines: u32,
+        event_buffer_size: u32,
+        padding: [u32; 5],
+        pub fd: i32,
+    }
+
+    #[repr(C, align(8))]
+    pub struct LineValues {
+        pub bits: u64,
+        pub mask: u64,
+    }
+
+    // The sizes the kernel's ioctl numbers are made from
+    const _: () = assert!(size_of::<LineRequest>() == 592 && size_of::<LineValues>() == 16);
+
+    /// `_IOWR(0xB4, nr, size)`
+    const fn iowr(nr: u32, size: usize) -> u32 {
+        3 << 30 | (size as u32) << 16 | 0xb4 << 8 | nr
+    }
+
+    pub const GET_LINE: u32 = iowr(0x07, size_of::<LineRequest>());
+    pub const SET_VALUES: u32 = iowr(0x0f, size_of::<LineValues>());
+
+    impl LineRequest {
+        pub fn output(line: u32, active_low: bool) -> Self {
+            let mut offsets = [0; LINES_MAX];
+            offsets[0] = line;
+            let mut consumer = [0; 32];
+            consumer[..19].copy_from_slice(b"bell103_demodulator");
+            let flags = if active_low {
+                FLAG_OUTPUT | FLAG_ACTIVE_LOW
+            } else {
+                FLAG_OUTPUT
+            };
+            let attribute = ConfigAttribute {
+                id: 0,
+                padding: 0,
+                value: 0,
+                mask: 0,
+            };
+            Self {
+                offsets,
+                consumer,
+                config: LineConfig {
+                    flags,
+                    num_attrs: 0,
+                    padding: [0; 5],
+                    attrs: [attribute; NUM_ATTRS_MAX],
+                },
+                num_lines: 1,
+                event_buffer_size: 0,
+                padding: [0; 5],
+                fd: -1,
+            }
+        }
+    }
+
+    pub fn ioctl<T>(file: &File, request: u32, argument: &mut T) -> io::Result<()> {
+        // The request is a c_ulong on glibc and a c_int on musl, with the same bits
+        if unsafe { libc::ioctl(file.as_raw_fd(), request as _, argument as *mut T) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Builds a command that runs `command` through the platform shell
+pub fn shell(command: &str) -> Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut process = Command::new(shell);
+    process.arg(flag).arg(command);
+    process
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_line() {
+        assert_eq!("dtr".parse(), Ok(SerialLine::Dtr));
+        assert!("cts".parse::<SerialLine>().is_err());
+    }
+
+    #[test]
+    fn test_gpio_line() {
+        let gpio: GpioLine = "17".parse().unwrap();
+        assert_eq!(gpio.chip, Path::new("/dev/gpiochip0"));
+        assert_eq!(gpio.line, 17);
+        let gpio: GpioLine = "/dev/gpiochip4:26".parse().unwrap();
+        assert_eq!(
+            (gpio.chip.as_path(), gpio.line),
+            (Path::new("/dev/gpiochip4"), 26)
+        );
+        assert!("gpiochip0:".parse::<GpioLine>().is_err());
+        assert!("gpio17".parse::<GpioLine>().is_err());
+    }
+
+    #[cfg(all(feature = "gpio", target_os = "linux"))]
+    #[test]
+    fn test_gpio_ioctls() {
+        // GPIO_V2_GET_LINE_IOCTL and GPIO_V2_LINE_SET_VALUES_IOCTL from linux/gpio.h
+        assert_eq!(gpio::GET_LINE, 0xc250_b407);
+        assert_eq!(gpio::SET_VALUES, 0xc010_b40f);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_ptt() {
+        let mut ptt = CommandPtt {
+            on: "true".to_string(),
+            off: "exit 3".to_string(),
+        };
+        assert!(ptt.set(true).is_ok());
+        assert!(ptt.set(false).is_err());
+    }
+}
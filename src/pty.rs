@@ -0,0 +1,97 @@
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::symlink;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+
+/// A pseudo-terminal for programs such as minicom to open as if it were a modem's serial
+/// port, what they write is read from `master` and what's written there they read back
+pub struct Pty {
+    pub master: File,
+    /// Kept open so reading `master` waits for a program instead of failing between them
+    _slave: File,
+    link: PathBuf,
+}
+
+impl Pty {
+    /// Creates a raw pseudo-terminal and links it at `link`, replacing a link left there
+    /// by an earlier run
+    pub fn open(link: &Path) -> io::Result<Self> {
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        if master < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = unsafe { File::from_raw_fd(master) };
+        let fd = master.as_raw_fd();
+        if unsafe { libc::grantpt(fd) } != 0 || unsafe { libc::unlockpt(fd) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let name = unsafe { libc::ptsname(fd) };
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let name = PathBuf::from(unsafe { CStr::from_ptr(name) }.to_string_lossy().as_ref());
+        let slave = fs::OpenOptions::new().read(true).write(true).open(&name)?;
+        make_raw(&slave)?;
+
+        if fs::symlink_metadata(link).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            fs::remove_file(link)?;
+        }
+        symlink(&name, link)?;
+        Ok(Self {
+            master,
+            _slave: slave,
+            link: link.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for Pty {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.link);
+    }
+}
+
+/// Passes bytes through unchanged, without echoing them or translating line endings, as a
+/// serial port does
+fn make_raw(terminal: &File) -> io::Result<()> {
+    let mut termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(terminal.as_raw_fd(), &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::cfmakeraw(&mut termios) };
+    if unsafe { libc::tcsetattr(terminal.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_pty() {
+        let link = std::env::temp_dir().join(format!("bell103-test-{}", std::process::id()));
+        let mut pty = Pty::open(&link).unwrap();
+        let mut program = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&link)
+            .unwrap();
+
+        program.write_all(b"ATDT\r").unwrap();
+        let mut typed = [0; 5];
+        pty.master.read_exact(&mut typed).unwrap();
+        assert_eq!(&typed, b"ATDT\r");
+        pty.master.write_all(b"hi\n").unwrap();
+        let mut received = [0; 3];
+        program.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hi\n");
+
+        drop(pty);
+        assert!(fs::symlink_metadata(&link).is_err());
+    }
+}
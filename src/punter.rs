@@ -0,0 +1,296 @@
+use std::fmt;
+
+/// Additive checksum, cyclic checksum, next block size and block number
+const HEADER_LEN: usize = 7;
+/// Block numbers from here on mark the final block of a transfer
+const FINAL_BLOCK: u16 = 0xff00;
+
+/// The cyclic checksum: each byte is XORed in, then the 16 bits are rotated left by one
+fn clc(data: &[u8]) -> u16 {
+    data.iter()
+        .fold(0, |clc, &byte| (clc ^ u16::from(byte)).rotate_left(1))
+}
+
+/// A block accepted from the byte stream
+#[derive(Debug, PartialEq)]
+struct Block {
+    number: u16,
+    /// Length of the following block, header included
+    next_size: usize,
+    data: std::ops::Range<usize>,
+    end: usize,
+}
+
+/// Finds sums of any run of bytes in constant time, so every block size can be tried at
+/// each offset until the stream is in step
+struct Stream<'a> {
+    bytes: &'a [u8],
+    sums: Vec<u32>,
+}
+
+impl<'a> Stream<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        let mut sums = vec![0];
+        for &byte in bytes {
+            sums.push(sums[sums.len() - 1] + u32::from(byte));
+        }
+        Self { bytes, sums }
+    }
+
+    /// Checks for a block of `size` bytes at `start`. Both checksums cover everything from
+    /// the size byte on.
+    fn block(&self, start: usize, size: usize) -> Option<Block> {
+        let end = start + size;
+        if size < HEADER_LEN || end > self.bytes.len() {
+            return None;
+        }
+        let header = &self.bytes[start..start + HEADER_LEN];
+        let sum = (self.sums[end] - self.sums[start + 4]) as u16;
+        if sum != u16::from_le_bytes([header[0], header[1]]) {
+            return None;
+        }
+        if clc(&self.bytes[start + 4..end]) != u16::from_le_bytes([header[2], header[3]]) {
+            return None;
+        }
+        let number = u16::from_le_bytes([header[5], header[6]]);
+        let next_size = usize::from(header[4]);
+        // Runs of NULs checksum to zero, but only the final block can end the chain
+        if next_size < HEADER_LEN && number < FINAL_BLOCK {
+            return None;
+        }
+        Some(Block {
+            number,
+            next_size,
+            data: start + HEADER_LEN..end,
+            end,
+        })
+    }
+
+    /// Tries the size the previous block gave first, then every other size
+    fn find(&self, start: usize, expected: Option<usize>) -> Option<Block> {
+        expected
+            .and_then(|size| self.block(start, size))
+            .or_else(|| (HEADER_LEN..=255).find_map(|size| self.block(start, size)))
+    }
+}
+
+/// The type sent ahead of the file, to tell the receiver how to save it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileType {
+    Prg,
+    Seq,
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileType::Prg => write!(f, "PRG"),
+            FileType::Seq => write!(f, "SEQ"),
+        }
+    }
+}
+
+/// A block that couldn't be accepted, at its offset into the byte stream
+#[derive(Debug, PartialEq)]
+pub struct BlockError {
+    pub offset: usize,
+    pub number: u16,
+    pub expected: u16,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Block {} at byte {}: out of sequence, expected block {}",
+            self.number & !FINAL_BLOCK,
+            self.offset,
+            self.expected
+        )
+    }
+}
+
+/// A file reassembled from the sending side of a Punter C1 transfer
+#[derive(Debug, Default, PartialEq)]
+pub struct Transfer {
+    pub data: Vec<u8>,
+    pub file_type: Option<FileType>,
+    /// Blocks accepted into the file
+    pub blocks: usize,
+    /// Copies of blocks already accepted, resent because the receiver answered BAD
+    pub duplicates: usize,
+    pub errors: Vec<BlockError>,
+    /// Whether the final block of the file was received
+    pub complete: bool,
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Punter")?;
+        if let Some(file_type) = self.file_type {
+            write!(f, " {}", file_type)?;
+        }
+        write!(
+            f,
+            ": {} blocks ({} bytes), {} duplicates, {} block errors, {}",
+            self.blocks,
+            self.data.len(),
+            self.duplicates,
+            self.errors.len(),
+            if self.complete {
+                "ended with the final block"
+            } else {
+                "incomplete"
+            }
+        )
+    }
+}
+
+/// Reassembles a file from the bytes sent by a Punter C1 sender. A file goes as two
+/// transfers, first one carrying just the file type and then the file itself, each a chain
+/// of blocks where every block gives the size of the next. The three letter handshake
+/// codes in between, and blocks whose checksums fail, are skipped over.
+pub fn receive(bytes: &[u8]) -> Transfer {
+    let stream = Stream::new(bytes);
+    let mut transfer = Transfer::default();
+    let mut data = Vec::new();
+    let mut blocks = 0;
+    let mut expected_number = 0u16;
+    let mut expected_size = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        let block = match stream.find(i, expected_size) {
+            Some(block) => block,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        i = block.end;
+        let number = block.number & !FINAL_BLOCK;
+        if blocks > 0 && number == expected_number.wrapping_sub(1) {
+            transfer.duplicates += 1;
+            continue;
+        }
+        if number != expected_number {
+            transfer.errors.push(BlockError {
+                offset: block.data.start - HEADER_LEN,
+                number: block.number,
+                expected: expected_number,
+            });
+        }
+        data.extend(&bytes[block.data]);
+        blocks += 1;
+        expected_number = number.wrapping_add(1);
+        expected_size = Some(block.next_size);
+
+        if block.number >= FINAL_BLOCK {
+            // A lone type byte ahead of the file is the file type transfer
+            match (transfer.file_type, data.as_slice()) {
+                (None, [1]) => transfer.file_type = Some(FileType::Prg),
+                (None, [2]) => transfer.file_type = Some(FileType::Seq),
+                _ => {
+                    transfer.complete = true;
+                    break;
+                }
+            }
+            data.clear();
+            blocks = 0;
+            expected_number = 0;
+            expected_size = None;
+        }
+    }
+    transfer.data = data;
+    transfer.blocks = blocks;
+    transfer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Chains `data` into blocks of up to `size` bytes, as a sender would
+    fn transfer(data: &[u8], size: usize) -> Vec<Vec<u8>> {
+        let chunks: Vec<&[u8]> = data.chunks(size - HEADER_LEN).collect();
+        chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let next_size = chunks.get(i + 1).map_or(0, |next| next.len() + HEADER_LEN);
+                let number = if i + 1 == chunks.len() {
+                    FINAL_BLOCK | i as u16
+                } else {
+                    i as u16
+                };
+                let mut checked = vec![next_size as u8];
+                checked.extend(&number.to_le_bytes());
+                checked.extend(*chunk);
+                let sum = checked.iter().map(|&byte| u16::from(byte)).sum::<u16>();
+                let mut block = sum.to_le_bytes().to_vec();
+                block.extend(&clc(&checked).to_le_bytes());
+                block.extend(checked);
+                block
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_receive_file() {
+        let file: Vec<u8> = (0..1000).map(|i| (i * 13 % 256) as u8).collect();
+        let mut bytes = b"GOO".to_vec();
+        for block in transfer(&[1], 255) {
+            bytes.extend(block);
+            bytes.extend(b"ACKS/B");
+        }
+        for block in transfer(&file, 255) {
+            bytes.extend(block);
+            bytes.extend(b"ACK");
+        }
+        bytes.extend(b"SYN");
+
+        let transfer = receive(&bytes);
+        assert_eq!(transfer.data, file);
+        assert_eq!(transfer.file_type, Some(FileType::Prg));
+        assert_eq!(transfer.blocks, 5);
+        assert!(transfer.errors.is_empty());
+        assert_eq!(
+            transfer.to_string(),
+            "Punter PRG: 5 blocks (1000 bytes), 0 duplicates, 0 block errors, \
+             ended with the final block"
+        );
+    }
+
+    #[test]
+    fn test_receive_with_errors() {
+        let file: Vec<u8> = (0..500).map(|i| (i % 200) as u8).collect();
+        let blocks = transfer(&file, 100);
+        let mut bytes = vec![];
+        bytes.extend(&blocks[0]);
+        // A corrupted copy, then the resend and a duplicate from a lost GOO
+        let mut corrupted = blocks[1].clone();
+        corrupted[20] ^= 0x40;
+        bytes.extend(corrupted);
+        bytes.extend(&blocks[1]);
+        bytes.extend(&blocks[1]);
+        // Block 2 is lost entirely
+        for block in &blocks[3..] {
+            bytes.extend(block);
+        }
+
+        let transfer = receive(&bytes);
+        assert_eq!(transfer.file_type, None);
+        assert_eq!(transfer.duplicates, 1);
+        assert_eq!(transfer.errors.len(), 1);
+        assert_eq!(
+            transfer.errors[0].to_string(),
+            format!(
+                "Block 3 at byte {}: out of sequence, expected block 2",
+                blocks[0].len() + blocks[1].len() * 3
+            )
+        );
+        let mut expected = file[..186].to_vec();
+        expected.extend(&file[279..]);
+        assert_eq!(transfer.data, expected);
+        assert!(transfer.complete);
+    }
+}
@@ -0,0 +1,87 @@
+/// Converts a stream of samples between sampling rates by linear interpolation, for devices
+/// that won't open at the rate asked for and audio demodulated from radio
+#[derive(Debug)]
+pub struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Position of the next output sample, where 0 is the last sample of the previous input
+    position: f64,
+    last: f64,
+}
+
+impl Resampler {
+    pub fn new(from: f64, to: f64) -> Self {
+        Self {
+            step: from / to,
+            position: 1.0,
+            last: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, input: &[i16]) -> Vec<i16> {
+        let mut output = Vec::new();
+        self.process_into(input, &mut output);
+        output
+    }
+
+    /// Like [`Resampler::process`], putting the output in `output` in place of what it held,
+    /// so a buffer with room for it can be used again without allocating
+    pub fn process_into(&mut self, input: &[i16], output: &mut Vec<i16>) {
+        output.clear();
+        if (self.step - 1.0).abs() < f64::EPSILON {
+            output.extend_from_slice(input);
+            return;
+        }
+        let last = self.last;
+        let sample = |i: usize| match i {
+            0 => last,
+            _ => f64::from(input[i - 1]),
+        };
+        while self.position <= input.len() as f64 {
+            let i = self.position.floor() as usize;
+            let fraction = self.position - i as f64;
+            let next = if fraction > 0.0 { sample(i + 1) } else { 0.0 };
+            output.push((sample(i) * (1.0 - fraction) + next * fraction).round() as i16);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        if let Some(&last) = input.last() {
+            self.last = f64::from(last);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampler() {
+        let input: Vec<i16> = (0..100).map(|i| i * 100).collect();
+        assert_eq!(Resampler::new(8_000.0, 8_000.0).process(&input), input);
+
+        // Upsampling in pieces follows the same line as all at once
+        let mut resampler = Resampler::new(8_000.0, 16_000.0);
+        let mut output = Vec::new();
+        for chunk in input.chunks(7) {
+            output.extend(resampler.process(chunk));
+        }
+        assert_eq!(output.len(), 199);
+        let expected: Vec<i16> = (0..199).map(|i| i * 50).collect();
+        assert_eq!(output, expected);
+
+        let mut resampler = Resampler::new(48_000.0, 8_000.0);
+        let output = resampler.process(&input);
+        assert_eq!(output, (0..17).map(|i| i * 600).collect::<Vec<i16>>());
+
+        // Into a buffer already holding something, replacing it
+        let mut resampler = Resampler::new(8_000.0, 16_000.0);
+        let mut reused = Vec::with_capacity(256);
+        for chunk in input.chunks(7) {
+            resampler.process_into(chunk, &mut reused);
+            assert!(reused.len() <= 14);
+        }
+        assert_eq!(reused, expected[expected.len() - reused.len()..]);
+        assert_eq!(reused.capacity(), 256);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::ptt::Ptt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+/// rigctld's own port, used when an address doesn't give one
+pub const DEFAULT_PORT: u16 = 4532;
+
+/// A connection to hamlib's rigctld, which keys and tunes a transceiver through commands of
+/// one line each
+pub struct Rigctld {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+/// Connects to rigctld at `address`, written `host` or `host:port`
+pub fn connect(address: &str) -> io::Result<Rigctld> {
+    let stream = if address.contains(':') {
+        TcpStream::connect(address)?
+    } else {
+        TcpStream::connect((address, DEFAULT_PORT))?
+    };
+    Ok(Rigctld {
+        reader: BufReader::new(stream.try_clone()?),
+        stream,
+    })
+}
+
+impl Rigctld {
+    /// Sends a command and reads the first line of its answer, which is a value for one that
+    /// gets and `RPRT 0` for one that sets, unless it's a `RPRT` error
+    fn command(&mut self, command: &str) -> io::Result<String> {
+        writeln!(self.stream, "{}", command)?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end().to_string();
+        match line.strip_prefix("RPRT ") {
+            Some("0") => Ok(line),
+            Some(code) => Err(io::Error::other(format!(
+                "rigctld answered `{}` with error {}",
+                command, code
+            ))),
+            None => Ok(line),
+        }
+    }
+
+    /// The transceiver's dial frequency in Hz
+    pub fn frequency(&mut self) -> io::Result<f64> {
+        let answer = self.command("f")?;
+        answer.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("rigctld gave `{}` for the frequency", answer),
+            )
+        })
+    }
+
+    pub fn set_frequency(&mut self, hz: f64) -> io::Result<()> {
+        self.command(&format!("F {}", hz.round())).map(drop)
+    }
+}
+
+impl Ptt for Rigctld {
+    fn set(&mut self, keyed: bool) -> io::Result<()> {
+        self.command(if keyed { "T 1" } else { "T 0" }).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_rigctld() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut commands = Vec::new();
+            for line in BufReader::new(stream).lines() {
+                let line = line.unwrap();
+                let answer = match line.as_str() {
+                    "f" => "14070000",
+                    "T 0" => "RPRT -9",
+                    _ => "RPRT 0",
+                };
+                writeln!(writer, "{}", answer).unwrap();
+                commands.push(line);
+            }
+            commands
+        });
+        let mut rig = connect(&address).unwrap();
+        rig.set_frequency(144_390_000.4).unwrap();
+        assert_eq!(rig.frequency().unwrap(), 14_070_000.0);
+        rig.set(true).unwrap();
+        assert!(rig.set(false).is_err());
+        drop(rig);
+        assert_eq!(server.join().unwrap(), ["F 144390000", "f", "T 1", "T 0"]);
+    }
+}
@@ -0,0 +1,248 @@
+//! A lock-free ring buffer of samples from one thread to another, for a sound card's callback
+//! to hand audio to the thread decoding it without ever waiting on a lock or the allocator.
+//! When decoding falls so far behind that the ring fills, what the callback writes is dropped
+//! until the reader's taken everything written before it, then the reader's told how many
+//! samples are missing there, so the gap can be accounted for rather than the audio either
+//! side of it being joined up as if nothing were lost.
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug)]
+struct Shared {
+    samples: Box<[AtomicI16]>,
+    /// Samples written and read since the ring was made, each stored by one side only
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Samples dropped since the reader last took what came before them, writing stopping
+    /// until it has
+    overrun: AtomicU64,
+    closed: AtomicBool,
+}
+
+/// The writing end of a [`ring`]
+#[derive(Debug)]
+pub struct RingWriter {
+    shared: Arc<Shared>,
+}
+
+/// The reading end of a [`ring`], which can be sent to another thread but not shared
+#[derive(Debug)]
+pub struct RingReader {
+    shared: Arc<Shared>,
+    read: Cell<usize>,
+}
+
+/// What a [`RingReader::take`] found
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Taken {
+    /// Samples moved out of the ring
+    pub samples: usize,
+    /// Samples dropped straight after them, when the ring overran
+    pub dropped: u64,
+}
+
+/// A ring holding up to `capacity` samples
+pub fn ring(capacity: usize) -> (RingWriter, RingReader) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity.max(1)).map(|_| AtomicI16::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        overrun: AtomicU64::new(0),
+        closed: AtomicBool::new(false),
+    });
+    let reader = RingReader {
+        shared: Arc::clone(&shared),
+        read: Cell::new(0),
+    };
+    (RingWriter { shared }, reader)
+}
+
+impl RingWriter {
+    /// Writes all of `samples`, or none of them if they don't fit or the ring's overrun and
+    /// the reader hasn't caught up yet. Returns whether they were written.
+    pub fn write(&mut self, samples: &[i16]) -> bool {
+        let shared = &*self.shared;
+        let dropped = samples.len() as u64;
+        if shared.overrun.load(Ordering::Acquire) > 0 {
+            shared.overrun.fetch_add(dropped, Ordering::AcqRel);
+            return false;
+        }
+        let written = shared.written.load(Ordering::Relaxed);
+        let free = shared.samples.len() - (written - shared.read.load(Ordering::Acquire));
+        if samples.len() > free {
+            shared.overrun.fetch_add(dropped, Ordering::AcqRel);
+            return false;
+        }
+        for (i, &sample) in samples.iter().enumerate() {
+            shared.samples[(written + i) % shared.samples.len()].store(sample, Ordering::Relaxed);
+        }
+        shared
+            .written
+            .store(written + samples.len(), Ordering::Release);
+        true
+    }
+
+    /// How many samples written are still waiting to be read, for a writer keeping the ring
+    /// only so full
+    pub fn unread(&self) -> usize {
+        let shared = &*self.shared;
+        shared.written.load(Ordering::Relaxed) - shared.read.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+impl RingReader {
+    /// Moves the samples written since the last take onto the end of `out`. After an overrun,
+    /// the samples written before it come first and the next take reports how many were
+    /// dropped, letting the writer start again.
+    pub fn take(&self, out: &mut Vec<i16>) -> Taken {
+        self.advance(usize::MAX, |_, sample| out.push(sample))
+    }
+
+    /// Like [`RingReader::take`], moving only as many samples as fit at the start of `out`, so
+    /// nothing's allocated, such as for a sound card's output callback. A drop's reported
+    /// once everything written before it has been taken.
+    pub fn take_into(&self, out: &mut [i16]) -> Taken {
+        let limit = out.len();
+        self.advance(limit, |i, sample| out[i] = sample)
+    }
+
+    /// Hands up to `limit` of the samples written since the last take to `put` with their
+    /// place among them
+    fn advance(&self, limit: usize, mut put: impl FnMut(usize, i16)) -> Taken {
+        let shared = &*self.shared;
+        // Nothing's written while there's an overrun, so the samples loaded after seeing one
+        // are exactly those that came before it
+        let overrun = shared.overrun.load(Ordering::Acquire);
+        let written = shared.written.load(Ordering::Acquire);
+        let read = self.read.get();
+        let samples = (written - read).min(limit);
+        for i in 0..samples {
+            put(
+                i,
+                shared.samples[(read + i) % shared.samples.len()].load(Ordering::Relaxed),
+            );
+        }
+        self.read.set(read + samples);
+        shared.read.store(read + samples, Ordering::Release);
+        let dropped = if overrun > 0 && read == written {
+            shared.overrun.swap(0, Ordering::AcqRel)
+        } else {
+            0
+        };
+        Taken { samples, dropped }
+    }
+
+    /// Whether the writer's gone, so nothing more will be written once the ring's empty
+    pub fn closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_ring_reports_overruns_where_they_happened() {
+        let (mut writer, reader) = ring(8);
+        let mut out = Vec::new();
+        assert!(writer.write(&[1, 2, 3]));
+        assert!(writer.write(&[4, 5, 6, 7, 8]));
+        assert!(!writer.write(&[9]));
+        // Nothing more's written until what came before the overrun's been taken
+        assert!(!writer.write(&[10, 11]));
+        assert_eq!(
+            reader.take(&mut out),
+            Taken {
+                samples: 8,
+                dropped: 0
+            }
+        );
+        assert!(!writer.write(&[12]));
+        assert_eq!(
+            reader.take(&mut out),
+            Taken {
+                samples: 0,
+                dropped: 4
+            }
+        );
+        assert!(writer.write(&[13, 14, 15, 16, 17, 18, 19, 20]));
+        assert_eq!(reader.take(&mut out).samples, 8);
+        assert_eq!(
+            out,
+            [1, 2, 3, 4, 5, 6, 7, 8, 13, 14, 15, 16, 17, 18, 19, 20]
+        );
+        assert!(!reader.closed());
+        drop(writer);
+        assert!(reader.closed());
+    }
+
+    #[test]
+    fn test_ring_takes_into_slices() {
+        let (mut writer, reader) = ring(4);
+        let mut out = [0; 3];
+        assert!(writer.write(&[1, 2, 3, 4]));
+        assert_eq!(writer.unread(), 4);
+        assert!(!writer.write(&[5]));
+        assert_eq!(reader.take_into(&mut out).samples, 3);
+        assert_eq!(out, [1, 2, 3]);
+        // The drop's only reported once what came before it has all been taken
+        assert_eq!(
+            reader.take_into(&mut out),
+            Taken {
+                samples: 1,
+                dropped: 0
+            }
+        );
+        assert_eq!(out[0], 4);
+        assert_eq!(reader.take_into(&mut out).dropped, 1);
+        assert_eq!(writer.unread(), 0);
+        assert!(writer.write(&[6, 7]));
+        assert_eq!(reader.take_into(&mut out).samples, 2);
+        assert_eq!(out[..2], [6, 7]);
+    }
+
+    #[test]
+    fn test_ring_between_threads() {
+        let (mut writer, reader) = ring(64);
+        let writing = thread::spawn(move || {
+            let mut next = 0i16;
+            let mut dropped = 0;
+            for _ in 0..20_000 {
+                let block: Vec<i16> = (next..next + 5).collect();
+                if writer.write(&block) {
+                    next += 5;
+                } else {
+                    dropped += 5;
+                }
+                if next > 30_000 {
+                    break;
+                }
+            }
+            (next, dropped)
+        });
+        let mut out = Vec::new();
+        let mut dropped = 0;
+        loop {
+            let closed = reader.closed();
+            let taken = reader.take(&mut out);
+            dropped += taken.dropped;
+            if closed && taken.samples == 0 && taken.dropped == 0 {
+                break;
+            }
+        }
+        let (written, writer_dropped) = writing.join().unwrap();
+        // Every sample written arrives in order, whatever was dropped between them
+        assert_eq!(out, (0..written).collect::<Vec<_>>());
+        assert_eq!(dropped, writer_dropped);
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// What an rtl_tcp server sends first, before the tuner type and its number of gains
+const MAGIC: &[u8; 4] = b"RTL0";
+/// rtl_tcp's own port, used when an address doesn't give one
+pub const DEFAULT_PORT: u16 = 1234;
+
+const SET_FREQUENCY: u8 = 0x01;
+const SET_SAMPLE_RATE: u8 = 0x02;
+const SET_GAIN_MODE: u8 = 0x03;
+const SET_GAIN: u8 = 0x04;
+const SET_FREQUENCY_CORRECTION: u8 = 0x05;
+
+/// A connection to an rtl_tcp server, which streams unsigned 8-bit IQ from an RTL-SDR dongle
+/// and takes commands to tune it
+pub struct RtlTcp {
+    stream: TcpStream,
+    /// The dongle's tuner chip, as rtl_tcp numbers them
+    pub tuner: u32,
+    /// How many gain settings the tuner has
+    pub gains: u32,
+}
+
+/// Connects to rtl_tcp at `address`, written `host` or `host:port`
+pub fn connect(address: &str) -> io::Result<RtlTcp> {
+    let mut stream = if address.contains(':') {
+        TcpStream::connect(address)?
+    } else {
+        TcpStream::connect((address, DEFAULT_PORT))?
+    };
+    let mut header = [0; 12];
+    stream.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the server didn't answer like rtl_tcp",
+        ));
+    }
+    Ok(RtlTcp {
+        stream,
+        tuner: u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+        gains: u32::from_be_bytes([header[8], header[9], header[10], header[11]]),
+    })
+}
+
+impl RtlTcp {
+    /// The name of the tuner chip
+    pub fn tuner_name(&self) -> &'static str {
+        match self.tuner {
+            1 => "E4000",
+            2 => "FC0012",
+            3 => "FC0013",
+            4 => "FC2580",
+            5 => "R820T",
+            6 => "R828D",
+            _ => "unknown",
+        }
+    }
+
+    fn command(&mut self, command: u8, parameter: u32) -> io::Result<()> {
+        let mut bytes = [command, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(&parameter.to_be_bytes());
+        self.stream.write_all(&bytes)
+    }
+
+    pub fn set_frequency(&mut self, hz: u32) -> io::Result<()> {
+        self.command(SET_FREQUENCY, hz)
+    }
+
+    pub fn set_sample_rate(&mut self, hz: u32) -> io::Result<()> {
+        self.command(SET_SAMPLE_RATE, hz)
+    }
+
+    /// Fixes the gain in dB, or leaves it to the tuner's AGC with `None`
+    pub fn set_gain(&mut self, db: Option<f64>) -> io::Result<()> {
+        match db {
+            Some(db) => {
+                self.command(SET_GAIN_MODE, 1)?;
+                // Tenths of a dB
+                self.command(SET_GAIN, (db * 10.0).round() as i32 as u32)
+            }
+            None => self.command(SET_GAIN_MODE, 0),
+        }
+    }
+
+    /// Corrects for the dongle's crystal being `ppm` parts per million off
+    pub fn set_frequency_correction(&mut self, ppm: i32) -> io::Result<()> {
+        self.command(SET_FREQUENCY_CORRECTION, ppm as u32)
+    }
+}
+
+impl Read for RtlTcp {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_rtl_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"RTL0\0\0\0\x05\0\0\0\x1d").unwrap();
+            stream.write_all(&[127, 128, 0, 255]).unwrap();
+            let mut commands = [0; 20];
+            stream.read_exact(&mut commands).unwrap();
+            commands
+        });
+        let mut client = connect(&address).unwrap();
+        assert_eq!(client.tuner_name(), "R820T");
+        assert_eq!(client.gains, 29);
+        client.set_frequency(144_800_000).unwrap();
+        client.set_sample_rate(240_000).unwrap();
+        client.set_gain(Some(-1.0)).unwrap();
+        let mut iq = [0; 4];
+        client.read_exact(&mut iq).unwrap();
+        assert_eq!(iq, [127, 128, 0, 255]);
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                1, 0x08, 0xa1, 0x79, 0x00, 2, 0, 3, 0xa9, 0x80, 3, 0, 0, 0, 1, 4, 0xff, 0xff, 0xff,
+                0xf6
+            ]
+        );
+    }
+
+    #[test]
+    fn test_not_rtl_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"HTTP/1.1 400").unwrap();
+        });
+        assert!(connect(&address).is_err());
+        server.join().unwrap();
+    }
+}
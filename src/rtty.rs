@@ -0,0 +1,53 @@
+use crate::baudot::BaudotDecoder;
+use crate::framing;
+use crate::standard::Channel;
+
+/// Decodes ITA2 Baudot RTTY: 5 data bits with 1.5 stop bits. Only the first stop bit is
+/// checked, so 1, 1.5 and 2 stop bits are all accepted. Carriage returns are dropped since
+/// RTTY lines end in CR LF.
+pub fn decode(
+    samples: &[i16],
+    channel: Channel,
+    sampling_rate: f64,
+    unshift_on_space: bool,
+) -> String {
+    let (received, _) = framing::receive(samples, channel, sampling_rate, 5);
+    let mut decoder = BaudotDecoder::new(unshift_on_space);
+    received
+        .iter()
+        .filter_map(|r| decoder.decode(r.byte))
+        .filter(|&c| c != '\r')
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baudot;
+    use crate::modulator::FskModulator;
+
+    #[test]
+    fn test_decode_45_baud() {
+        let channel = Channel {
+            mark: 2125.0,
+            space: 2295.0,
+            baud: 45.45,
+        };
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        let mut samples = Vec::new();
+        modulator.hold(1, 3.3, &mut samples);
+        for code in baudot::encode("RYRY CQ DE N0CALL 599\r\n") {
+            modulator.hold(0, 1.0, &mut samples);
+            for i in 0..5 {
+                modulator.hold((code >> i) & 1, 1.0, &mut samples);
+            }
+            modulator.hold(1, 1.5, &mut samples);
+        }
+        modulator.hold(1, 2.0, &mut samples);
+
+        assert_eq!(
+            decode(&samples, channel, 8_000.0, true),
+            "RYRY CQ DE N0CALL 599\n"
+        );
+    }
+}
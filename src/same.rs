@@ -0,0 +1,324 @@
+use crate::clock;
+use crate::demodulator::{Demodulator, TICKS_PER_BIT};
+use crate::standard::Channel;
+use std::fmt;
+
+/// 520.83 baud with mark and space at 4 and 3 cycles per bit
+pub const CHANNEL: Channel = Channel {
+    mark: 2083.0 + 1.0 / 3.0,
+    space: 1562.5,
+    baud: 520.0 + 5.0 / 6.0,
+};
+const PREAMBLE: u8 = 0xab;
+/// Preamble bytes needed in a row to start a burst, out of the 16 sent
+const PREAMBLE_MATCH: usize = 3;
+const MAX_BURST_LEN: usize = 268;
+/// Each header and end of message is sent three times with a second of silence between,
+/// so bursts closer together than this are repeats
+const REPEAT_GAP_SECS: f64 = 1.5;
+
+/// Originators and events that are printed with a description
+const ORIGINATORS: [(&str, &str); 4] = [
+    ("EAS", "Broadcast station or cable system"),
+    ("CIV", "Civil authorities"),
+    ("WXR", "National Weather Service"),
+    ("PEP", "Primary Entry Point System"),
+];
+const EVENTS: [(&str, &str); 24] = [
+    ("EAN", "Emergency Action Notification"),
+    ("NPT", "National Periodic Test"),
+    ("RMT", "Required Monthly Test"),
+    ("RWT", "Required Weekly Test"),
+    ("ADR", "Administrative Message"),
+    ("BZW", "Blizzard Warning"),
+    ("CAE", "Child Abduction Emergency"),
+    ("CEM", "Civil Emergency Message"),
+    ("EQW", "Earthquake Warning"),
+    ("EVI", "Evacuation Immediate"),
+    ("FFW", "Flash Flood Warning"),
+    ("FFA", "Flash Flood Watch"),
+    ("FLW", "Flood Warning"),
+    ("FRW", "Fire Warning"),
+    ("HUW", "Hurricane Warning"),
+    ("HUA", "Hurricane Watch"),
+    ("SVR", "Severe Thunderstorm Warning"),
+    ("SVA", "Severe Thunderstorm Watch"),
+    ("SVS", "Severe Weather Statement"),
+    ("SPS", "Special Weather Statement"),
+    ("TOR", "Tornado Warning"),
+    ("TOA", "Tornado Watch"),
+    ("TSW", "Tsunami Warning"),
+    ("WSW", "Winter Storm Warning"),
+];
+
+/// The fields of a `ZCZC-ORG-EEE-PSSCCC+TTTT-JJJHHMM-LLLLLLLL-` header
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub originator: String,
+    pub event: String,
+    /// Location codes: part of a county, state and county FIPS codes
+    pub locations: Vec<String>,
+    /// How long the alert is valid for as HHMM
+    pub purge: String,
+    /// Day of the year, hour and minute of issue in UTC as JJJHHMM
+    pub issued: String,
+    pub station: String,
+}
+
+impl Header {
+    pub fn parse(text: &str) -> Option<Self> {
+        let digits = |s: &str, len| s.len() == len && s.bytes().all(|b| b.is_ascii_digit());
+        let (codes, times) = text.strip_prefix("ZCZC-")?.split_once('+')?;
+        let mut codes = codes.split('-');
+        let originator = codes.next()?.to_string();
+        let event = codes.next()?.to_string();
+        let locations: Vec<String> = codes.map(ToString::to_string).collect();
+        let mut times = times.split('-');
+        let purge = times.next()?.to_string();
+        let issued = times.next()?.to_string();
+        let station = times.next()?.to_string();
+        let valid = originator.len() == 3
+            && event.len() == 3
+            && !locations.is_empty()
+            && locations.iter().all(|l| digits(l, 6))
+            && digits(&purge, 4)
+            && digits(&issued, 7)
+            && !station.is_empty();
+        if !valid {
+            return None;
+        }
+        Some(Self {
+            originator,
+            event,
+            locations,
+            purge,
+            issued,
+            station,
+        })
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |table: &[(&str, &'static str)], code: &str| {
+            table.iter().find(|(c, _)| *c == code).map(|&(_, d)| d)
+        };
+        match describe(&EVENTS, &self.event) {
+            Some(event) => write!(f, "{} ({})", event, self.event)?,
+            None => write!(f, "Event {}", self.event)?,
+        }
+        match describe(&ORIGINATORS, &self.originator) {
+            Some(originator) => write!(f, " from {} ({})", originator, self.originator)?,
+            None => write!(f, " from {}", self.originator)?,
+        }
+        writeln!(f, ", station {}", self.station)?;
+        writeln!(f, "  Locations: {}", self.locations.join(", "))?;
+        write!(
+            f,
+            "  Issued: day {} at {}:{} UTC, valid for {}h{}m",
+            &self.issued[..3],
+            &self.issued[3..5],
+            &self.issued[5..],
+            &self.purge[..2],
+            &self.purge[2..]
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Content {
+    Header(Header),
+    EndOfMessage,
+    /// A burst that voting couldn't turn into either
+    Unknown,
+}
+
+/// A message and the bursts it was voted from
+#[derive(Debug)]
+pub struct Alert {
+    pub text: String,
+    pub bursts: usize,
+    pub content: Content,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ({} bursts)", self.text, self.bursts)?;
+        match &self.content {
+            Content::Header(header) => writeln!(f, "  {}", header),
+            Content::EndOfMessage => writeln!(f, "  End of message"),
+            Content::Unknown => writeln!(f, "  Not a valid header"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Burst {
+    text: Vec<u8>,
+    start: usize,
+    end: usize,
+}
+
+/// Decodes EAS SAME headers. Bits are sent synchronously, least significant first, so the
+/// bit clock is recovered from the signal and each burst is byte aligned by its preamble.
+pub fn decode(samples: &[i16], sampling_rate: f64) -> Vec<Alert> {
+    let samples_per_bit = sampling_rate / CHANNEL.baud;
+    let block_size = samples_per_bit.round() as usize;
+    let step = (block_size / TICKS_PER_BIT).max(1);
+    let mut demodulator = Demodulator::new(block_size, CHANNEL.mark, CHANNEL.space, sampling_rate);
+    let soft = demodulator.sliding_soft_bits(samples, step);
+    let bits = clock::recover_bits(&soft, samples_per_bit / step as f64);
+    let gap = (REPEAT_GAP_SECS * CHANNEL.baud) as usize;
+    group_bursts(&find_bursts(&bits), gap)
+        .iter()
+        .map(|group| vote(group))
+        .collect()
+}
+
+fn byte_at(bits: &[u8], pos: usize) -> u8 {
+    bits[pos..pos + 8]
+        .iter()
+        .rev()
+        .fold(0, |value, &bit| value << 1 | bit)
+}
+
+fn find_bursts(bits: &[u8]) -> Vec<Burst> {
+    let byte = |pos: usize| Some(byte_at(bits, pos)).filter(|_| pos + 8 <= bits.len());
+    let mut bursts = Vec::new();
+    let mut pos = 0;
+    while pos + 8 * PREAMBLE_MATCH <= bits.len() {
+        if (0..PREAMBLE_MATCH).any(|i| byte(pos + 8 * i) != Some(PREAMBLE)) {
+            pos += 1;
+            continue;
+        }
+        let start = pos;
+        while byte(pos) == Some(PREAMBLE) {
+            pos += 8;
+        }
+        let mut text = Vec::new();
+        while let Some(b) = byte(pos).filter(|b| (0x20..0x7f).contains(b)) {
+            if text.len() == MAX_BURST_LEN {
+                break;
+            }
+            text.push(b);
+            pos += 8;
+        }
+        if !text.is_empty() {
+            bursts.push(Burst {
+                text,
+                start,
+                end: pos,
+            });
+        }
+    }
+    bursts
+}
+
+/// Groups up to three bursts sent close together
+fn group_bursts(bursts: &[Burst], gap: usize) -> Vec<&[Burst]> {
+    let mut groups = Vec::new();
+    let mut first = 0;
+    for i in 1..=bursts.len() {
+        let repeat = i < bursts.len()
+            && i - first < 3
+            && bursts[i].start.saturating_sub(bursts[i - 1].end) < gap;
+        if !repeat {
+            groups.push(&bursts[first..i]);
+            first = i;
+        }
+    }
+    groups
+}
+
+/// Takes each character from the majority of the bursts, or the first burst when they all
+/// differ. A position is kept while most bursts are still long enough to reach it.
+fn vote(group: &[Burst]) -> Alert {
+    let needed = group.len() / 2 + 1;
+    let mut text = String::new();
+    for i in 0.. {
+        let chars: Vec<u8> = group
+            .iter()
+            .filter_map(|b| b.text.get(i).copied())
+            .collect();
+        if chars.len() < needed {
+            break;
+        }
+        let majority = chars
+            .iter()
+            .find(|&&c| chars.iter().filter(|&&d| d == c).count() >= needed)
+            .unwrap_or(&chars[0]);
+        text.push(char::from(*majority));
+    }
+    let content = if text.starts_with("NNNN") {
+        Content::EndOfMessage
+    } else {
+        Header::parse(&text).map_or(Content::Unknown, Content::Header)
+    };
+    if let Content::Header(header) = &content {
+        // Drop anything decoded from the noise after the header
+        let end = text.find(&format!("-{}-", header.station)).unwrap() + header.station.len() + 2;
+        text.truncate(end);
+    }
+    Alert {
+        text,
+        bursts: group.len(),
+        content,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::FskModulator;
+
+    const HEADER: &str = "ZCZC-WXR-TOR-024031-024033+0030-1051700-KLWX/NWS-";
+
+    fn burst(modulator: &mut FskModulator, text: &str, samples: &mut Vec<i16>) {
+        let bytes = std::iter::repeat_n(PREAMBLE, 16).chain(text.bytes());
+        let bits: Vec<u8> = bytes
+            .flat_map(|b| (0..8).map(move |i| (b >> i) & 1))
+            .collect();
+        modulator.modulate(&bits, samples);
+        samples.extend(std::iter::repeat_n(0, 30_000));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = Header::parse(HEADER).unwrap();
+        assert_eq!(header.event, "TOR");
+        assert_eq!(header.locations, ["024031", "024033"]);
+        assert_eq!(header.station, "KLWX/NWS");
+        assert_eq!(
+            header.to_string(),
+            "Tornado Warning (TOR) from National Weather Service (WXR), station KLWX/NWS\n  \
+             Locations: 024031, 024033\n  Issued: day 105 at 17:00 UTC, valid for 00h30m"
+        );
+        assert_eq!(
+            Header::parse("ZCZC-WXR-TOR-02403+0030-1051700-KLWX/NWS-"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_votes_bursts() {
+        let mut modulator = FskModulator::new(CHANNEL.mark, CHANNEL.space, CHANNEL.baud, 48_000.0);
+        let mut samples = vec![0; 10_000];
+        burst(&mut modulator, HEADER, &mut samples);
+        burst(&mut modulator, &HEADER.replace("TOR", "TQR"), &mut samples);
+        burst(&mut modulator, &HEADER.replace("1700", "1"), &mut samples);
+        samples.extend(std::iter::repeat_n(0, 100_000));
+        for _ in 0..3 {
+            burst(&mut modulator, "NNNN", &mut samples);
+        }
+
+        let alerts = decode(&samples, 48_000.0);
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].text, HEADER);
+        assert_eq!(alerts[0].bursts, 3);
+        assert_eq!(
+            alerts[0].content,
+            Content::Header(Header::parse(HEADER).unwrap())
+        );
+        assert_eq!(alerts[1].content, Content::EndOfMessage);
+    }
+}
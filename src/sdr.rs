@@ -0,0 +1,224 @@
+use crate::filter::LowPass;
+use crate::resample::Resampler;
+use std::f64::consts::PI;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// Narrowband FM channels are filtered to this either side of the carrier
+const CHANNEL_HZ: f64 = 8_000.0;
+/// IQ is averaged down to at least this rate, wide enough for the channel, before filtering
+const CHANNEL_RATE: f64 = 32_000.0;
+const FILTER_TAPS: usize = 63;
+
+/// Sample format of complex baseband from an SDR, I then Q
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IqFormat {
+    /// 32-bit little-endian floats, as GNU Radio and SDR++ write
+    Cf32,
+    /// Signed 16-bit little-endian integers
+    Cs16,
+    /// Unsigned 8-bit integers centred on 127.5, as rtl_sdr writes
+    Cu8,
+}
+
+impl FromStr for IqFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cf32" => Ok(IqFormat::Cf32),
+            "cs16" => Ok(IqFormat::Cs16),
+            "cu8" => Ok(IqFormat::Cu8),
+            _ => Err(format!(
+                "unknown IQ format `{}`, expected cf32, cs16 or cu8",
+                s
+            )),
+        }
+    }
+}
+
+impl IqFormat {
+    /// Bytes in each of I and Q
+    fn width(self) -> usize {
+        match self {
+            IqFormat::Cf32 => 4,
+            IqFormat::Cs16 => 2,
+            IqFormat::Cu8 => 1,
+        }
+    }
+
+    /// One of I or Q scaled to [-1.0, 1.0]
+    fn value(self, bytes: &[u8]) -> f64 {
+        match self {
+            IqFormat::Cf32 => {
+                f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            IqFormat::Cs16 => f64::from(i16::from_le_bytes([bytes[0], bytes[1]])) / 32_768.0,
+            IqFormat::Cu8 => (f64::from(bytes[0]) - 127.5) / 127.5,
+        }
+    }
+}
+
+/// Demodulates narrowband FM from complex baseband: tunes to a signal `offset` Hz from the
+/// centre, averages down towards [`CHANNEL_RATE`], filters out the channel, takes the phase
+/// step between samples and resamples that to the audio rate
+pub struct FmReceiver {
+    /// Turns the tuning phasor each IQ sample
+    rotation: (f64, f64),
+    phasor: (f64, f64),
+    decimation: usize,
+    sum: (f64, f64),
+    count: usize,
+    channel: [LowPass; 2],
+    previous: (f64, f64),
+    audio: LowPass,
+    resampler: Resampler,
+}
+
+impl FmReceiver {
+    pub fn new(iq_rate: f64, offset: f64, audio_rate: f64) -> Self {
+        let omega = -2.0 * PI * offset / iq_rate;
+        let decimation = ((iq_rate / CHANNEL_RATE.max(audio_rate)) as usize).max(1);
+        let channel_rate = iq_rate / decimation as f64;
+        let channel = LowPass::new(CHANNEL_HZ, FILTER_TAPS, channel_rate);
+        Self {
+            rotation: (omega.cos(), omega.sin()),
+            phasor: (1.0, 0.0),
+            decimation,
+            sum: (0.0, 0.0),
+            count: 0,
+            channel: [channel.clone(), channel],
+            previous: (0.0, 0.0),
+            // Keeps the discriminator's noise above the lower rate from aliasing into the tones
+            audio: LowPass::new(0.45 * audio_rate, FILTER_TAPS, channel_rate),
+            resampler: Resampler::new(channel_rate, audio_rate),
+        }
+    }
+
+    /// Demodulates interleaved I and Q into audio samples
+    pub fn process(&mut self, iq: &[(f64, f64)]) -> Vec<i16> {
+        let mut audio = Vec::with_capacity(iq.len() / self.decimation + 1);
+        for &sample in iq {
+            let mixed = multiply(sample, self.phasor);
+            self.phasor = multiply(self.phasor, self.rotation);
+            self.sum = (self.sum.0 + mixed.0, self.sum.1 + mixed.1);
+            self.count += 1;
+            if self.count < self.decimation {
+                continue;
+            }
+            let (i, q) = self.sum;
+            let filtered = (self.channel[0].process(i), self.channel[1].process(q));
+            self.sum = (0.0, 0.0);
+            self.count = 0;
+            // The phase turned through since the last sample is the instantaneous frequency
+            let (re, im) = multiply(filtered, (self.previous.0, -self.previous.1));
+            self.previous = filtered;
+            let step = self.audio.process(im.atan2(re));
+            audio.push((step / PI * 32_767.0) as i16);
+        }
+        // Rounding errors would otherwise grow the phasor
+        let length = self.phasor.0.hypot(self.phasor.1);
+        self.phasor = (self.phasor.0 / length, self.phasor.1 / length);
+        self.resampler.process(&audio)
+    }
+}
+
+fn multiply(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+/// Reads IQ samples `frames` at a time and demodulates them with `receiver`
+pub fn read_iq_chunks<R: Read>(
+    reader: R,
+    format: IqFormat,
+    receiver: FmReceiver,
+    frames: usize,
+) -> IqChunks<R> {
+    IqChunks {
+        reader,
+        format,
+        receiver,
+        frames,
+    }
+}
+
+/// Audio demodulated from an IQ recording a chunk at a time, see [`read_iq_chunks`]
+pub struct IqChunks<R: Read> {
+    reader: R,
+    format: IqFormat,
+    receiver: FmReceiver,
+    frames: usize,
+}
+
+impl<R: Read> Iterator for IqChunks<R> {
+    type Item = io::Result<Vec<i16>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let width = self.format.width();
+        let mut bytes = Vec::new();
+        let read = (&mut self.reader)
+            .take((self.frames * 2 * width) as u64)
+            .read_to_end(&mut bytes);
+        if let Err(err) = read {
+            return Some(Err(err));
+        }
+        if bytes.len() < 2 * width {
+            return None;
+        }
+        let iq: Vec<(f64, f64)> = bytes
+            .chunks_exact(2 * width)
+            .map(|pair| (self.format.value(pair), self.format.value(&pair[width..])))
+            .collect();
+        Some(Ok(self.receiver.process(&iq)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spectrum;
+
+    /// A 1 kHz tone frequency modulated with 3 kHz deviation, `offset` Hz from the centre
+    fn fm_tone(offset: f64, iq_rate: f64, seconds: f64) -> Vec<(f64, f64)> {
+        let mut phase = 0.0;
+        (0..(seconds * iq_rate) as usize)
+            .map(|i| {
+                let t = i as f64 / iq_rate;
+                let frequency = offset + 3_000.0 * (2.0 * PI * 1_000.0 * t).sin();
+                phase += 2.0 * PI * frequency / iq_rate;
+                (0.5 * phase.cos(), 0.5 * phase.sin())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fm_receiver() {
+        let iq = fm_tone(60_000.0, 240_000.0, 0.5);
+        let mut receiver = FmReceiver::new(240_000.0, 60_000.0, 8_000.0);
+        let audio: Vec<i16> = iq.chunks(1_000).flat_map(|c| receiver.process(c)).collect();
+        assert!((audio.len() as i64 - 4_000).abs() < 10, "{}", audio.len());
+        let spectrum = spectrum::power_spectrum(&audio[1_000..3_048]);
+        let peak = (1..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].total_cmp(&spectrum[b]))
+            .unwrap();
+        let frequency = spectrum::interpolate_peak(&spectrum, peak) * 8_000.0 / 2_048.0;
+        assert!((frequency - 1_000.0).abs() < 10.0, "{}", frequency);
+    }
+
+    #[test]
+    fn test_read_iq() {
+        let mut bytes = Vec::new();
+        for (i, q) in [(1.0f32, -0.5f32), (0.25, 0.0)] {
+            bytes.extend(i.to_le_bytes());
+            bytes.extend(q.to_le_bytes());
+        }
+        assert_eq!(IqFormat::Cf32.value(&bytes[4..]), -0.5);
+        assert_eq!(IqFormat::Cs16.value(&[0x00, 0xc0]), -0.5);
+        assert_eq!(IqFormat::Cu8.value(&[255]), 1.0);
+        assert_eq!("cu8".parse(), Ok(IqFormat::Cu8));
+
+        let receiver = FmReceiver::new(16_000.0, 0.0, 8_000.0);
+        let chunks = read_iq_chunks(&bytes[..], IqFormat::Cf32, receiver, 1);
+        assert_eq!(chunks.count(), 2);
+    }
+}
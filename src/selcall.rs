@@ -0,0 +1,282 @@
+use crate::goertzel::{self, GoertzelBank};
+use crate::modulator::{Oscillator, AMPLITUDE};
+use std::fmt;
+use std::str::FromStr;
+
+/// Detection blocks are a quarter of a tone, so every tone fills at least three whole blocks
+const BLOCKS_PER_TONE: f64 = 4.0;
+/// The strongest tone must hold this much of a block's energy
+const MIN_TONE_ENERGY: f64 = 0.6;
+/// and this much of the energy at all the tones of the standard
+const MIN_SHARE: f64 = 0.6;
+/// Tones may be up to half their nominal length shorter or longer
+const TIMING_TOLERANCE: f64 = 0.5;
+/// Index of the repeat tone, sent in place of a digit equal to the one before it
+const REPEAT: usize = 10;
+
+/// A sequential tone signalling system
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Standard {
+    Zvei,
+    Ccir,
+    Eea,
+}
+
+pub const STANDARDS: [Standard; 3] = [Standard::Zvei, Standard::Ccir, Standard::Eea];
+
+impl Standard {
+    /// Frequencies of digits 0 to 9, then the repeat tone
+    pub fn tones(&self) -> [f64; 11] {
+        match self {
+            Standard::Zvei => [
+                2400.0, 1060.0, 1160.0, 1270.0, 1400.0, 1530.0, 1670.0, 1830.0, 2000.0, 2200.0,
+                2600.0,
+            ],
+            // CCIR and EEA share tones and differ only in timing
+            Standard::Ccir | Standard::Eea => [
+                1981.0, 1124.0, 1197.0, 1275.0, 1358.0, 1446.0, 1540.0, 1640.0, 1747.0, 1860.0,
+                2110.0,
+            ],
+        }
+    }
+
+    /// Nominal length of each tone in milliseconds
+    pub fn tone_ms(&self) -> f64 {
+        match self {
+            Standard::Zvei => 70.0,
+            Standard::Ccir => 100.0,
+            Standard::Eea => 40.0,
+        }
+    }
+}
+
+impl FromStr for Standard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zvei" => Ok(Standard::Zvei),
+            "ccir" => Ok(Standard::Ccir),
+            "eea" => Ok(Standard::Eea),
+            _ => Err(format!(
+                "unknown selcall standard `{}`, expected zvei, ccir or eea",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Standard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Standard::Zvei => write!(f, "ZVEI"),
+            Standard::Ccir => write!(f, "CCIR"),
+            Standard::Eea => write!(f, "EEA"),
+        }
+    }
+}
+
+/// Generates the tones for a call, failing on the first character that isn't a digit. A
+/// digit equal to the one before is sent as the repeat tone, so every tone differs from
+/// the last.
+pub fn encode(digits: &str, standard: Standard, sampling_rate: f64) -> Result<Vec<i16>, char> {
+    let tone_samples = (sampling_rate * standard.tone_ms() / 1000.0).round() as usize;
+    let frequencies = standard.tones();
+    let mut oscillator = Oscillator::new(frequencies[0], sampling_rate);
+    let mut samples = Vec::new();
+    let mut previous = None;
+    for digit in digits.chars() {
+        let value = digit.to_digit(10).ok_or(digit)? as usize;
+        let tone = if previous == Some(value) {
+            REPEAT
+        } else {
+            value
+        };
+        previous = Some(tone);
+        oscillator.set_frequency(frequencies[tone]);
+        for _ in 0..tone_samples {
+            samples.push((AMPLITUDE * oscillator.next_sample()) as i16);
+        }
+    }
+    Ok(samples)
+}
+
+/// A call found in a recording, timed in seconds
+#[derive(Debug, PartialEq)]
+pub struct Call {
+    pub standard: Standard,
+    /// The identity of the called unit
+    pub address: String,
+    /// Any tones sent after the address, often a status or command
+    pub status: String,
+    pub start: f64,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.3}s {} {}", self.start, self.standard, self.address)?;
+        if !self.status.is_empty() {
+            write!(f, " status {}", self.status)?;
+        }
+        Ok(())
+    }
+}
+
+/// One tone held over consecutive blocks
+#[derive(Debug)]
+struct Run {
+    tone: usize,
+    start: usize,
+    blocks: usize,
+}
+
+/// Finds the calls of one standard with a Goertzel filter for each of its tones
+#[derive(Debug)]
+pub struct SelcallDecoder {
+    standard: Standard,
+    bank: GoertzelBank,
+    /// The energy at each tone over the last block, then the power of each
+    powers: Vec<f64>,
+    block_size: usize,
+    /// Digits in an address, the rest of a sequence being its status
+    length: usize,
+    sampling_rate: f64,
+}
+
+impl SelcallDecoder {
+    pub fn new(standard: Standard, length: usize, sampling_rate: f64) -> Self {
+        let block_size =
+            (sampling_rate * standard.tone_ms() / 1000.0 / BLOCKS_PER_TONE).round() as usize;
+        Self {
+            standard,
+            bank: GoertzelBank::new(standard.tones().to_vec(), sampling_rate),
+            powers: vec![0.0; standard.tones().len()],
+            block_size,
+            length,
+            sampling_rate,
+        }
+    }
+
+    /// Checks a block's level and tone purity, returning its tone if it passes. Blocks
+    /// straddling two tones split their energy and fail.
+    fn classify(&mut self, block: &[i16]) -> Option<usize> {
+        if goertzel::is_silent(block) {
+            return None;
+        }
+        let energy: f64 = block.iter().map(|&s| f64::from(s).powi(2)).sum();
+        // Scale Goertzel magnitudes to the energy of the tone in the block
+        self.bank.energies_into(block, &mut self.powers);
+        for power in self.powers.iter_mut() {
+            *power = 2.0 * *power / block.len() as f64;
+        }
+        let powers = &self.powers;
+        let tone = (0..powers.len())
+            .max_by(|&a, &b| powers[a].partial_cmp(&powers[b]).unwrap())
+            .unwrap();
+        let pure = powers[tone] >= MIN_TONE_ENERGY * energy;
+        let clear = powers[tone] >= MIN_SHARE * powers.iter().sum::<f64>();
+        Some(tone).filter(|_| pure && clear)
+    }
+
+    fn runs(&mut self, samples: &[i16]) -> Vec<Run> {
+        let mut runs: Vec<Run> = Vec::new();
+        for (i, block) in samples.chunks_exact(self.block_size).enumerate() {
+            let tone = match self.classify(block) {
+                Some(tone) => tone,
+                None => continue,
+            };
+            match runs.last_mut() {
+                Some(run) if run.tone == tone && run.start + run.blocks == i => run.blocks += 1,
+                _ => runs.push(Run {
+                    tone,
+                    start: i,
+                    blocks: 1,
+                }),
+            }
+        }
+        runs
+    }
+
+    /// Returns the calls in a recording. Each tone must last its nominal length, within
+    /// the timing tolerance, and follow the last with at most one block of transition in
+    /// between. Sequences shorter than an address are ignored.
+    pub fn decode(&mut self, samples: &[i16]) -> Vec<Call> {
+        let runs = self.runs(samples);
+        let mut calls = Vec::new();
+        let mut sequence: Vec<&Run> = Vec::new();
+        for run in &runs {
+            let timed =
+                (run.blocks as f64 - BLOCKS_PER_TONE).abs() <= TIMING_TOLERANCE * BLOCKS_PER_TONE;
+            let follows = sequence
+                .last()
+                .is_some_and(|last| run.start <= last.start + last.blocks + 1);
+            if !(timed && follows) {
+                calls.extend(self.call(&sequence));
+                sequence.clear();
+            }
+            // A call can't start with a repeat
+            if timed && !(sequence.is_empty() && run.tone == REPEAT) {
+                sequence.push(run);
+            }
+        }
+        calls.extend(self.call(&sequence));
+        calls
+    }
+
+    fn call(&self, sequence: &[&Run]) -> Option<Call> {
+        if sequence.len() < self.length {
+            return None;
+        }
+        let mut digits = String::new();
+        for run in sequence {
+            let digit = match run.tone {
+                REPEAT => digits.chars().last().unwrap(),
+                tone => std::char::from_digit(tone as u32, 10).unwrap(),
+            };
+            digits.push(digit);
+        }
+        Some(Call {
+            standard: self.standard,
+            status: digits.split_off(self.length),
+            address: digits,
+            start: (sequence[0].start * self.block_size) as f64 / self.sampling_rate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let samples = encode("1123", Standard::Eea, 8_000.0).unwrap();
+        assert_eq!(samples.len(), 4 * 320);
+        let mut decoder = SelcallDecoder::new(Standard::Eea, 5, 8_000.0);
+        let tones: Vec<usize> = decoder.runs(&samples).iter().map(|run| run.tone).collect();
+        assert_eq!(tones, vec![1, REPEAT, 2, 3]);
+        assert_eq!(encode("12a", Standard::Eea, 8_000.0), Err('a'));
+    }
+
+    #[test]
+    fn test_decode() {
+        let silence = |ms: usize| vec![0; ms * 8];
+        let mut samples = silence(123);
+        samples.extend(encode("12334", Standard::Ccir, 8_000.0).unwrap());
+        samples.extend(silence(500));
+        samples.extend(encode("5550009", Standard::Zvei, 8_000.0).unwrap());
+        samples.extend(silence(300));
+        samples.extend(encode("97531", Standard::Eea, 8_000.0).unwrap());
+        // Too short to be an address
+        samples.extend(silence(300));
+        samples.extend(encode("864", Standard::Ccir, 8_000.0).unwrap());
+
+        let calls = |standard| {
+            let calls = SelcallDecoder::new(standard, 5, 8_000.0).decode(&samples);
+            calls.iter().map(Call::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(calls(Standard::Ccir), vec!["0.125s CCIR 12334"]);
+        assert_eq!(calls(Standard::Zvei), vec!["1.120s ZVEI 55500 status 09"]);
+        assert_eq!(calls(Standard::Eea), vec!["1.910s EEA 97531"]);
+    }
+}
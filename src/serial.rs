@@ -0,0 +1,150 @@
+use serialport::SerialPort;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long a read of a bridged port waits before trying again, letting writes in between
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A serial port to send decoded bytes to, written `port:baud` with the rate optional, e.g.
+/// `/dev/ttyUSB0:300` or `COM3`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerialTarget {
+    pub path: String,
+    pub baud: Option<u32>,
+}
+
+impl FromStr for SerialTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, baud) = match s.rsplit_once(':') {
+            Some((path, baud)) => {
+                let baud = baud
+                    .parse()
+                    .ok()
+                    .filter(|&baud| baud > 0)
+                    .ok_or_else(|| format!("serial baud rate `{}` is invalid", baud))?;
+                (path, Some(baud))
+            }
+            None => (s, None),
+        };
+        if path.is_empty() {
+            return Err(format!("serial port `{}` has no device", s));
+        }
+        Ok(Self {
+            path: path.to_string(),
+            baud,
+        })
+    }
+}
+
+impl SerialTarget {
+    /// Opens the port as 8N1 at its baud rate, or `default_baud` when it doesn't give one
+    pub fn open(&self, default_baud: u32) -> io::Result<Box<dyn SerialPort>> {
+        let port = serialport::new(&self.path, self.baud.unwrap_or(default_baud)).open()?;
+        Ok(port)
+    }
+}
+
+/// A serial port bridged to the modem, such as one end of a com0com virtual pair for software
+/// on the other end to use as its modem. Reads wait for bytes instead of timing out.
+pub struct Bridge(pub Box<dyn SerialPort>);
+
+impl Bridge {
+    /// Opens the port as [`SerialTarget::open`] does
+    pub fn open(target: &SerialTarget, default_baud: u32) -> io::Result<Self> {
+        let mut port = target.open(default_baud)?;
+        port.set_timeout(READ_TIMEOUT)?;
+        Ok(Self(port))
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self(self.0.try_clone()?))
+    }
+}
+
+impl Read for Bridge {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.0.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => continue,
+                read => return read,
+            }
+        }
+    }
+}
+
+impl Write for Bridge {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Writes to stdout and copies everything written to a serial port as well
+pub struct Tee {
+    pub port: Box<dyn SerialPort>,
+    pub stdout: io::Stdout,
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdout.write_all(buf)?;
+        self.port.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()?;
+        self.port.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_target() {
+        let target = |path: &str, baud| SerialTarget {
+            path: path.to_string(),
+            baud,
+        };
+        assert_eq!(
+            "/dev/ttyUSB0:300".parse(),
+            Ok(target("/dev/ttyUSB0", Some(300)))
+        );
+        assert_eq!("COM3".parse(), Ok(target("COM3", None)));
+        assert!("/dev/ttyS0:fast".parse::<SerialTarget>().is_err());
+        assert!(":9600".parse::<SerialTarget>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_bridge() {
+        let link = std::env::temp_dir().join(format!("bell103-bridge-{}", std::process::id()));
+        let mut pty = crate::pty::Pty::open(&link).unwrap();
+        let target = SerialTarget {
+            path: link.to_string_lossy().into_owned(),
+            baud: None,
+        };
+        let mut bridge = Bridge::open(&target, 300).unwrap();
+        let writer = std::thread::spawn(move || {
+            // Past a read timeout or two
+            std::thread::sleep(READ_TIMEOUT * 3);
+            pty.master.write_all(b"ATDT").unwrap();
+            pty
+        });
+        let mut read = [0; 4];
+        bridge.read_exact(&mut read).unwrap();
+        assert_eq!(&read, b"ATDT");
+        let mut pty = writer.join().unwrap();
+        bridge.try_clone().unwrap().write_all(b"OK").unwrap();
+        pty.master.read_exact(&mut read[..2]).unwrap();
+        assert_eq!(&read[..2], b"OK");
+    }
+}
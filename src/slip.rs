@@ -0,0 +1,186 @@
+use std::io::{self, Read, Write};
+
+/// Packet end, sent before a packet as well to flush out line noise
+const END: u8 = 0xc0;
+/// Escape, so END and ESC can appear in a packet
+const ESC: u8 = 0xdb;
+/// Transposed end and escape, which follow ESC
+const ESC_END: u8 = 0xdc;
+const ESC_ESC: u8 = 0xdd;
+/// The biggest packet taken or passed on, a TUN interface's default MTU
+pub const MTU: usize = 1500;
+
+/// A packet framed for a serial line as RFC 1055 does
+pub fn encode(packet: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![END];
+    for &byte in packet {
+        match byte {
+            END => encoded.extend_from_slice(&[ESC, ESC_END]),
+            ESC => encoded.extend_from_slice(&[ESC, ESC_ESC]),
+            byte => encoded.push(byte),
+        }
+    }
+    encoded.push(END);
+    encoded
+}
+
+/// Unescapes packets from the bytes of a serial line
+#[derive(Debug, Default)]
+pub struct Decoder {
+    packet: Vec<u8>,
+    escaped: bool,
+    /// Set once a packet is too big, so the rest of it is dropped too
+    overrun: bool,
+}
+
+impl Decoder {
+    /// Takes the next byte, returning the packet it ends. Empty packets, between back to back
+    /// ENDs, and ones bigger than the MTU are dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Vec<u8>> {
+        match (byte, self.escaped) {
+            (END, _) => {
+                self.escaped = false;
+                let packet = std::mem::take(&mut self.packet);
+                let overrun = std::mem::take(&mut self.overrun);
+                return Some(packet).filter(|packet| !packet.is_empty() && !overrun);
+            }
+            (ESC, false) => self.escaped = true,
+            (byte, escaped) => {
+                self.escaped = false;
+                if self.packet.len() == MTU {
+                    self.overrun = true;
+                    self.packet.clear();
+                }
+                self.packet.push(match (byte, escaped) {
+                    (ESC_END, true) => END,
+                    (ESC_ESC, true) => ESC,
+                    (byte, _) => byte,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Reads packets from a device that gives one a read, such as a TUN interface, as the SLIP
+/// frames to send for them
+pub struct Framed<R> {
+    device: R,
+    pending: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Framed<R> {
+    pub fn new(device: R) -> Self {
+        Self {
+            device,
+            pending: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Framed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.pending.len() {
+            let mut packet = [0; MTU];
+            let length = self.device.read(&mut packet)?;
+            if length == 0 {
+                return Ok(0);
+            }
+            self.pending = encode(&packet[..length]);
+            self.position = 0;
+        }
+        let length = buf.len().min(self.pending.len() - self.position);
+        buf[..length].copy_from_slice(&self.pending[self.position..][..length]);
+        self.position += length;
+        Ok(length)
+    }
+}
+
+/// Writes the packets of SLIP frames written to it to a device taking one a write, such as a
+/// TUN interface
+pub struct Unframed<W> {
+    device: W,
+    decoder: Decoder,
+}
+
+impl<W: Write> Unframed<W> {
+    pub fn new(device: W) -> Self {
+        Self {
+            device,
+            decoder: Decoder::default(),
+        }
+    }
+}
+
+impl<W: Write> Write for Unframed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if let Some(packet) = self.decoder.push(byte) {
+                // A packet that's been garbled on the way is the kernel's to drop
+                match self.device.write(&packet) {
+                    Err(e) if e.kind() == io::ErrorKind::InvalidInput => {}
+                    written => {
+                        written?;
+                    }
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.device.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slip() {
+        let packet = [0x45, END, 0x00, ESC, ESC_END];
+        let encoded = encode(&packet);
+        assert_eq!(
+            encoded,
+            [END, 0x45, ESC, ESC_END, 0x00, ESC, ESC_ESC, ESC_END, END]
+        );
+        let mut decoder = Decoder::default();
+        let decoded: Vec<_> = encoded.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(decoded, [packet.to_vec()]);
+
+        let mut long = vec![END];
+        long.extend(std::iter::repeat_n(0x45, MTU + 1));
+        long.extend_from_slice(&[END, 0x01, END]);
+        let decoded: Vec<_> = long.iter().filter_map(|&b| decoder.push(b)).collect();
+        assert_eq!(decoded, [vec![0x01]]);
+    }
+
+    #[test]
+    fn test_framing() {
+        // Each read of the device gives one packet, as a TUN interface's does
+        struct Packets(Vec<Vec<u8>>);
+        impl Read for Packets {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let Some(packet) = self.0.pop() else {
+                    return Ok(0);
+                };
+                buf[..packet.len()].copy_from_slice(&packet);
+                Ok(packet.len())
+            }
+        }
+        let mut framed = Framed::new(Packets(vec![vec![2, END], vec![1]]));
+        let mut line = Vec::new();
+        framed.read_to_end(&mut line).unwrap();
+        assert_eq!(line, [END, 1, END, END, 2, ESC, ESC_END, END]);
+
+        let mut unframed = Unframed::new(Vec::new());
+        unframed.write_all(&[0x55]).unwrap();
+        for byte in &line {
+            unframed.write_all(&[*byte]).unwrap();
+        }
+        assert_eq!(unframed.device, [0x55, 1, 2, END]);
+    }
+}
@@ -0,0 +1,360 @@
+use crate::detect::MAX_TONE;
+use crate::png;
+use crate::spectrum;
+use crate::standard::Channel;
+use std::ops::Range;
+
+/// Spectra taken each second of audio, before a long recording is narrowed to fit
+const SPECTRA_PER_SECOND: f64 = 50.0;
+/// The widest the plot gets, in columns of one spectrum each
+const MAX_COLUMNS: usize = 1600;
+/// The plot's height in pixels, from 0 Hz up to [`MAX_TONE`]
+const PLOT_HEIGHT: usize = 400;
+/// How many dB below the loudest bin the plot fades to black
+const RANGE_DB: f64 = 70.0;
+/// Frequency gridlines, every this many Hz
+const FREQUENCY_STEP: f64 = 500.0;
+/// Time scale intervals to pick from, in seconds, the first whose labels fit
+pub const TIME_STEPS: [f64; 13] = [
+    0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0,
+];
+
+/// Room around the plot for the scales, the message strip over it and the tones' labels
+const LEFT: usize = 44;
+const RIGHT: usize = 14;
+const TOP: usize = 16;
+const BOTTOM: usize = 16;
+/// Text is drawn in glyphs 3 by 5 pixels scaled up this much, a pixel apart
+const TEXT_SCALE: usize = 2;
+const ADVANCE: usize = 4 * TEXT_SCALE;
+
+const BACKGROUND: [u8; 3] = [24, 24, 24];
+const FOREGROUND: [u8; 3] = [220, 220, 220];
+const GRID: [u8; 3] = [70, 70, 70];
+const MESSAGE: [u8; 3] = [40, 200, 80];
+const MARK: [u8; 3] = [255, 255, 255];
+const SPACE: [u8; 3] = [255, 90, 255];
+/// Colours from quiet to loud: black, blue, cyan, green, yellow and red
+const HEAT: [[u8; 3]; 6] = [
+    [0, 0, 0],
+    [0, 0, 160],
+    [0, 160, 255],
+    [0, 220, 80],
+    [255, 230, 0],
+    [255, 40, 0],
+];
+
+/// Renders a recording's spectrogram as a PNG image, time across and the band up to 4 kHz up
+/// it. `channel`'s mark and space tones are marked with dashed lines, and each message found
+/// in it as a numbered bar across the top with lines where it starts and ends.
+pub fn render(
+    samples: &[i16],
+    sampling_rate: f64,
+    channel: Option<Channel>,
+    messages: &[Range<usize>],
+) -> Vec<u8> {
+    let fft_len = ((sampling_rate / SPECTRA_PER_SECOND) as usize).next_power_of_two();
+    let hop = ((sampling_rate / SPECTRA_PER_SECOND) as usize)
+        .max(samples.len().div_ceil(MAX_COLUMNS))
+        .max(1);
+    let columns = samples.len().div_ceil(hop).max(1);
+    let top_hz = MAX_TONE.min(sampling_rate / 2.0);
+    let bin_hz = sampling_rate / fft_len as f64;
+    let row_hz = |y: usize| top_hz * (1.0 - (y as f64 + 0.5) / PLOT_HEIGHT as f64);
+    let row_of = |hz: f64| ((1.0 - hz / top_hz) * PLOT_HEIGHT as f64) as usize;
+
+    let mut window = vec![0; fft_len];
+    let levels: Vec<Vec<f64>> = (0..columns)
+        .map(|column| {
+            // Each spectrum is centred on its column's time, silence past either end
+            let centre = column * hop;
+            window.iter_mut().enumerate().for_each(|(i, sample)| {
+                *sample = (centre + i)
+                    .checked_sub(fft_len / 2)
+                    .and_then(|i| samples.get(i))
+                    .copied()
+                    .unwrap_or(0);
+            });
+            let spectrum = spectrum::power_spectrum(&window);
+            (0..PLOT_HEIGHT)
+                .map(|y| {
+                    let bin = row_hz(y) / bin_hz;
+                    let (below, fraction) = (bin.floor() as usize, bin.fract());
+                    let db = |bin: usize| 10.0 * spectrum[bin.min(spectrum.len() - 1)].log10();
+                    db(below) * (1.0 - fraction) + db(below + 1) * fraction
+                })
+                .collect()
+        })
+        .collect();
+    let peak = levels
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|db| db.is_finite())
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    let mut canvas = Canvas::new(LEFT + columns + RIGHT, TOP + PLOT_HEIGHT + BOTTOM);
+    for (x, column) in levels.iter().enumerate() {
+        for (y, &db) in column.iter().enumerate() {
+            let level = ((db - peak + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0);
+            canvas.set(
+                LEFT + x,
+                TOP + y,
+                heat(if level.is_nan() { 0.0 } else { level }),
+            );
+        }
+    }
+
+    // The frequency scale, with gridlines dotted over the plot
+    canvas.text(LEFT - 3 * ADVANCE, 2, "Hz", FOREGROUND);
+    let mut hz = 0.0;
+    while hz <= top_hz {
+        let y = TOP + row_of(hz).min(PLOT_HEIGHT - 1);
+        let label = format!("{:.0}", hz);
+        let label_y = (y + 1).saturating_sub(5 * TEXT_SCALE / 2).max(TOP);
+        canvas.text(
+            LEFT - 4 - label.len() * ADVANCE,
+            label_y,
+            &label,
+            FOREGROUND,
+        );
+        for x in (LEFT - 3..LEFT + columns).step_by(4) {
+            canvas.set(x, y, GRID);
+        }
+        hz += FREQUENCY_STEP;
+    }
+    // The time scale, with a tick at each label
+    let seconds = samples.len() as f64 / sampling_rate;
+    let time_label = |time: f64, step: f64| {
+        if step < 1.0 {
+            format!("{:.1}s", time)
+        } else {
+            format!("{:.0}s", time)
+        }
+    };
+    let step = TIME_STEPS
+        .iter()
+        .copied()
+        .find(|&step| {
+            let label = time_label(seconds, step).len() + 1;
+            step * sampling_rate / hop as f64 >= (label * ADVANCE) as f64
+        })
+        .unwrap_or(TIME_STEPS[TIME_STEPS.len() - 1]);
+    let mut tick = 0;
+    loop {
+        let time = tick as f64 * step;
+        let x = LEFT + (time * sampling_rate / hop as f64).round() as usize;
+        if x >= LEFT + columns {
+            break;
+        }
+        for y in TOP + PLOT_HEIGHT..TOP + PLOT_HEIGHT + 3 {
+            canvas.set(x, y, FOREGROUND);
+        }
+        canvas.text(
+            x,
+            TOP + PLOT_HEIGHT + 5,
+            &time_label(time, step),
+            FOREGROUND,
+        );
+        tick += 1;
+    }
+
+    if let Some(channel) = channel {
+        for &(hz, colour, label) in &[(channel.mark, MARK, "M"), (channel.space, SPACE, "S")] {
+            if hz >= top_hz {
+                continue;
+            }
+            let y = TOP + row_of(hz);
+            for x in (LEFT..LEFT + columns).filter(|x| (x - LEFT) % 8 < 4) {
+                canvas.set(x, y, colour);
+            }
+            let label_y = (y + 1).saturating_sub(5 * TEXT_SCALE / 2);
+            canvas.text(LEFT + columns + 3, label_y, label, colour);
+        }
+    }
+    for (number, message) in messages.iter().enumerate() {
+        let start = LEFT + message.start / hop;
+        let end = (LEFT + message.end.div_ceil(hop)).min(LEFT + columns);
+        for x in start..end {
+            for y in 1..TOP - 3 {
+                canvas.set(x, y, MESSAGE);
+            }
+        }
+        for y in (TOP..TOP + PLOT_HEIGHT).filter(|y| y % 6 < 3) {
+            canvas.set(start, y, MESSAGE);
+            canvas.set(end.saturating_sub(1), y, MESSAGE);
+        }
+        let label = (number + 1).to_string();
+        if end - start >= label.len() * ADVANCE + 2 {
+            canvas.text(start + 2, 2, &label, BACKGROUND);
+        }
+    }
+    canvas.png()
+}
+
+/// The colour of a level between 0.0 (quiet) and 1.0 (loud) along [`HEAT`]
+fn heat(level: f64) -> [u8; 3] {
+    let position = level * (HEAT.len() - 1) as f64;
+    let below = (position as usize).min(HEAT.len() - 2);
+    let fraction = position - below as f64;
+    let mut colour = [0; 3];
+    for (i, channel) in colour.iter_mut().enumerate() {
+        let (from, to) = (f64::from(HEAT[below][i]), f64::from(HEAT[below + 1][i]));
+        *channel = (from + (to - from) * fraction).round() as u8;
+    }
+    colour
+}
+
+/// An RGB image being drawn, which clips what's drawn outside it
+struct Canvas {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgb: BACKGROUND.repeat(width * height),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, colour: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let i = (y * self.width + x) * 3;
+            self.rgb[i..i + 3].copy_from_slice(&colour);
+        }
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y`. Characters [`glyph`] has no shape
+    /// for are left blank.
+    fn text(&mut self, x: usize, y: usize, text: &str, colour: [u8; 3]) {
+        for (i, c) in text.chars().enumerate() {
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..3 {
+                    if bits >> (2 - column) & 1 == 0 {
+                        continue;
+                    }
+                    for dy in 0..TEXT_SCALE {
+                        for dx in 0..TEXT_SCALE {
+                            let px = x + i * ADVANCE + column * TEXT_SCALE + dx;
+                            self.set(px, y + row * TEXT_SCALE + dy, colour);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn png(&self) -> Vec<u8> {
+        png::encode(self.width as u32, self.height as u32, &self.rgb)
+    }
+}
+
+/// The rows of a character's 3 by 5 pixel shape, the high bit of each on the left
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        's' => [0b000, 0b011, 0b110, 0b011, 0b110],
+        'z' => [0b000, 0b111, 0b011, 0b110, 0b111],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        _ => [0; 5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::frame;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+    use std::convert::TryInto;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    /// The pixels of a PNG made by [`png::encode`], which stores them uncompressed
+    fn pixels(png: &[u8]) -> (usize, usize, Vec<u8>) {
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(png[20..24].try_into().unwrap()) as usize;
+        let mut zlib = &png[33 + 8..][2..];
+        let mut rows = Vec::new();
+        loop {
+            let length = usize::from(u16::from_le_bytes([zlib[1], zlib[2]]));
+            rows.extend_from_slice(&zlib[5..5 + length]);
+            if zlib[0] == 1 {
+                break;
+            }
+            zlib = &zlib[5 + length..];
+        }
+        let rgb = rows
+            .chunks(width * 3 + 1)
+            .flat_map(|row| row[1..].to_vec())
+            .collect();
+        (width, height, rgb)
+    }
+
+    #[test]
+    fn test_render() {
+        let channel = Standard::Bell103.answer();
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+        let mut samples = vec![0; 24_000];
+        modulator.hold(1, 150.0, &mut samples);
+        modulator.modulate(&frame(b"Welcome, login:"), &mut samples);
+        samples.extend(vec![0; 24_000]);
+        let start = 24_000;
+        let message = start..samples.len() - 24_000;
+        let png = render(
+            &samples,
+            SAMPLING_RATE,
+            Some(channel),
+            std::slice::from_ref(&message),
+        );
+        let (width, height, rgb) = pixels(&png);
+
+        let hop = 960;
+        assert_eq!(width, LEFT + samples.len().div_ceil(hop) + RIGHT);
+        assert_eq!(height, TOP + PLOT_HEIGHT + BOTTOM);
+        let pixel = |x: usize, y: usize| {
+            let i = (y * width + x) * 3;
+            [rgb[i], rgb[i + 1], rgb[i + 2]]
+        };
+        let row = |hz: f64| TOP + ((1.0 - hz / MAX_TONE) * PLOT_HEIGHT as f64) as usize;
+        let idle = LEFT + (start + 12_000) / hop;
+        let middle = LEFT + (message.start + message.end) / 2 / hop;
+        // The tones are loud while the message is sent, the idle mark the loudest, and silent
+        // either side of it, with little far from them
+        assert_eq!(pixel(idle, row(channel.mark) + 1)[0], 255);
+        assert_ne!(pixel(middle, row(channel.space) + 1), HEAT[0]);
+        assert_eq!(pixel(LEFT + 5, row(channel.mark) + 1), HEAT[0]);
+        assert_eq!(pixel(middle, row(3500.0))[..2], [0, 0]);
+        // With dashes along them
+        assert_eq!(pixel(LEFT, row(channel.mark)), MARK);
+        assert_eq!(pixel(LEFT, row(channel.space)), SPACE);
+        assert_eq!(pixel(LEFT + 4, row(channel.space)), HEAT[0]);
+        // And the message's bar over the plot
+        assert_eq!(pixel(middle, TOP - 4), MESSAGE);
+        assert_eq!(pixel(LEFT + 5, TOP - 4), BACKGROUND);
+    }
+
+    #[test]
+    fn test_heat() {
+        assert_eq!(heat(0.0), HEAT[0]);
+        assert_eq!(heat(1.0), HEAT[5]);
+        assert_eq!(heat(0.1), [0, 0, 80]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::f64::consts::PI;
+
+/// In place radix-2 FFT. Both slices must have the same power of two length.
+pub fn fft(re: &mut [f64], im: &mut [f64]) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Power in each frequency bin up to half the sampling rate, with a Hann window. The block
+/// length must be a power of two; bin `i` is centred on `i * sampling_rate / len`.
+pub fn power_spectrum(samples: &[i16]) -> Vec<f64> {
+    let n = samples.len();
+    let mut re: Vec<f64> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, &s)| f64::from(s) * (0.5 - 0.5 * (2.0 * PI * i as f64 / n as f64).cos()))
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    re.iter()
+        .zip(&im)
+        .take(n / 2)
+        .map(|(r, i)| r * r + i * i)
+        .collect()
+}
+
+/// Refines a peak bin to a fractional bin by fitting a parabola to its log power
+pub fn interpolate_peak(spectrum: &[f64], bin: usize) -> f64 {
+    if bin == 0 || bin + 1 >= spectrum.len() {
+        return bin as f64;
+    }
+    let (a, b, c) = (
+        spectrum[bin - 1].max(1e-12).ln(),
+        spectrum[bin].max(1e-12).ln(),
+        spectrum[bin + 1].max(1e-12).ln(),
+    );
+    let denominator = a - 2.0 * b + c;
+    if denominator.abs() < 1e-12 {
+        bin as f64
+    } else {
+        bin as f64 + 0.5 * (a - c) / denominator
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tone_peak() {
+        let samples: Vec<i16> = (0..1024)
+            .map(|i| (10_000.0 * (2.0 * PI * 1234.0 * i as f64 / 8_000.0).sin()) as i16)
+            .collect();
+        let spectrum = power_spectrum(&samples);
+        let peak = (0..spectrum.len())
+            .max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+            .unwrap();
+        let frequency = interpolate_peak(&spectrum, peak) * 8_000.0 / 1024.0;
+        assert!((frequency - 1234.0).abs() < 2.0, "{}", frequency);
+    }
+}
@@ -0,0 +1,212 @@
+use std::str::FromStr;
+
+/// Tone frequencies and signalling rate for one direction of an FSK modem link, laid out as
+/// `Bell103Channel` for plugins
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    pub mark: f64,
+    pub space: f64,
+    pub baud: f64,
+}
+
+/// Supported modem standards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Standard {
+    /// Bell 103, 300 baud full duplex
+    Bell103,
+    /// Bell 202, 1200 baud half duplex
+    Bell202,
+    /// ITU-T V.21, 300 baud full duplex. Channel 1 is the originating channel and channel 2 the
+    /// answering channel.
+    V21,
+    /// ITU-T V.23 mode 2, 1200 baud forward channel with a 75 baud back channel
+    V23,
+}
+
+impl Standard {
+    pub const ALL: [Standard; 4] = [
+        Standard::Bell103,
+        Standard::Bell202,
+        Standard::V21,
+        Standard::V23,
+    ];
+
+    /// Channel transmitted by the originating (calling) side
+    pub fn originate(self) -> Channel {
+        match self {
+            Standard::Bell103 => Channel {
+                mark: 1270.0,
+                space: 1070.0,
+                baud: 300.0,
+            },
+            Standard::Bell202 => Channel {
+                mark: 1200.0,
+                space: 2200.0,
+                baud: 1200.0,
+            },
+            Standard::V21 => Channel {
+                mark: 980.0,
+                space: 1180.0,
+                baud: 300.0,
+            },
+            Standard::V23 => Channel {
+                mark: 390.0,
+                space: 450.0,
+                baud: 75.0,
+            },
+        }
+    }
+
+    /// Channel transmitted by the answering side
+    pub fn answer(self) -> Channel {
+        match self {
+            Standard::Bell103 => Channel {
+                mark: 2225.0,
+                space: 2025.0,
+                baud: 300.0,
+            },
+            Standard::Bell202 => self.originate(),
+            Standard::V21 => Channel {
+                mark: 1650.0,
+                space: 1850.0,
+                baud: 300.0,
+            },
+            Standard::V23 => Channel {
+                mark: 1300.0,
+                space: 2100.0,
+                baud: 1200.0,
+            },
+        }
+    }
+
+    pub fn channel(self, origin: bool) -> Channel {
+        if origin {
+            self.originate()
+        } else {
+            self.answer()
+        }
+    }
+}
+
+/// A standard chosen on the command line, where `auto` identifies it from the recording
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Auto,
+    Fixed(Standard),
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Selection::Auto),
+            _ => s.parse().map(Selection::Fixed).map_err(|_| {
+                format!(
+                    "unknown standard `{}`, expected auto, bell103, bell202, v21 or v23",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+impl FromStr for Standard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bell103" => Ok(Standard::Bell103),
+            "bell202" => Ok(Standard::Bell202),
+            "v21" => Ok(Standard::V21),
+            "v23" => Ok(Standard::V23),
+            _ => Err(format!(
+                "unknown standard `{}`, expected bell103, bell202, v21 or v23",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demodulator::Demodulator;
+    use crate::filter::isolate_channel;
+    use crate::goertzel::GoertzelFilter;
+    use crate::modulator::FskModulator;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    #[test]
+    fn test_round_trip_all_channels() {
+        let bits = [0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1];
+        for standard in &["bell103", "bell202", "v21", "v23"] {
+            let standard: Standard = standard.parse().unwrap();
+            for &origin in &[true, false] {
+                let channel = standard.channel(origin);
+                let mut modulator =
+                    FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+                let mut samples = Vec::new();
+                modulator.modulate(&bits, &mut samples);
+
+                let block_size = (SAMPLING_RATE / channel.baud) as usize;
+                let mut demodulator =
+                    Demodulator::new(block_size, channel.mark, channel.space, SAMPLING_RATE);
+                assert_eq!(demodulator.bits(&samples), bits, "{:?}", channel);
+            }
+        }
+    }
+
+    #[test]
+    fn test_v21_channels_are_isolated() {
+        let standard: Standard = "v21".parse().unwrap();
+        let (channel1, channel2) = (standard.originate(), standard.answer());
+        assert_eq!((channel1.mark, channel1.space), (980.0, 1180.0));
+        assert_eq!((channel2.mark, channel2.space), (1650.0, 1850.0));
+
+        let bits = [0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1];
+        let mut modulator =
+            FskModulator::new(channel2.mark, channel2.space, channel2.baud, SAMPLING_RATE);
+        let mut samples = Vec::new();
+        modulator.modulate(&bits, &mut samples);
+
+        // Channel 1's filters hear very little of the opposite direction's band
+        let energy = |frequency: f64| {
+            let mut filter = GoertzelFilter::new(samples.len(), frequency, SAMPLING_RATE);
+            filter.process(&samples);
+            filter.get_mag_sq()
+        };
+        let leakage = energy(channel1.mark).max(energy(channel1.space));
+        assert!(energy(channel2.mark) > 100.0 * leakage);
+        assert!(energy(channel2.space) > 100.0 * leakage);
+    }
+
+    #[test]
+    fn test_v23_directions_from_summed_audio() {
+        let (back, forward) = (Standard::V23.originate(), Standard::V23.answer());
+        let back_bits = [0, 1, 1, 0];
+        let forward_bits: Vec<u8> = (0..64).map(|i| (i * 7 % 3 == 0) as u8).collect();
+
+        let mut back_audio = Vec::new();
+        FskModulator::new(back.mark, back.space, back.baud, SAMPLING_RATE)
+            .modulate(&back_bits, &mut back_audio);
+        let mut forward_audio = Vec::new();
+        FskModulator::new(forward.mark, forward.space, forward.baud, SAMPLING_RATE)
+            .modulate(&forward_bits, &mut forward_audio);
+        assert_eq!(back_audio.len(), forward_audio.len());
+        let summed: Vec<i16> = back_audio
+            .iter()
+            .zip(&forward_audio)
+            .map(|(a, b)| a / 2 + b / 2)
+            .collect();
+
+        let mut demodulator = Demodulator::new(640, back.mark, back.space, SAMPLING_RATE);
+        let isolated = isolate_channel(&summed, back, SAMPLING_RATE);
+        assert_eq!(demodulator.bits(&isolated), back_bits);
+        let mut demodulator = Demodulator::new(40, forward.mark, forward.space, SAMPLING_RATE);
+        let isolated = isolate_channel(&summed, forward, SAMPLING_RATE);
+        assert_eq!(demodulator.bits(&isolated), forward_bits);
+    }
+}
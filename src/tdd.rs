@@ -0,0 +1,115 @@
+use crate::standard::Channel;
+use std::fmt;
+
+/// US TDDs send 45.45 baud Baudot, marking with 1400 Hz and spacing with 1800 Hz
+pub const CHANNEL: Channel = Channel {
+    mark: 1400.0,
+    space: 1800.0,
+    baud: 45.45,
+};
+/// Typed to hand the conversation to the other party
+const GO_AHEAD: &str = "GA";
+/// Typed to end the conversation, often as SKSK
+const STOP_KEYING: [&str; 2] = ["SK", "SKSK"];
+
+/// One party's turn in a half-duplex conversation
+#[derive(Debug, PartialEq)]
+pub struct Turn {
+    pub text: String,
+    pub ending: Ending,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Ending {
+    GoAhead,
+    StopKeying,
+    /// The recording ended mid-turn
+    None,
+}
+
+impl fmt::Display for Turn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ending = match self.ending {
+            Ending::GoAhead => "(go ahead)",
+            Ending::StopKeying => "(stop keying)",
+            Ending::None => return write!(f, "{}", self.text),
+        };
+        if self.text.is_empty() {
+            write!(f, "{}", ending)
+        } else {
+            write!(f, "{} {}", self.text, ending)
+        }
+    }
+}
+
+/// Splits a transcript into turns at each GA and SK. Line breaks within a turn are only the
+/// sender's display wrapping, so words are rejoined with single spaces.
+pub fn turns(text: &str) -> Vec<Turn> {
+    let mut turns = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+    for word in text.split_whitespace() {
+        let ending = if word == GO_AHEAD {
+            Ending::GoAhead
+        } else if STOP_KEYING.contains(&word) {
+            Ending::StopKeying
+        } else {
+            words.push(word);
+            continue;
+        };
+        turns.push(Turn {
+            text: words.join(" "),
+            ending,
+        });
+        words.clear();
+    }
+    if !words.is_empty() {
+        turns.push(Turn {
+            text: words.join(" "),
+            ending: Ending::None,
+        });
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baudot;
+    use crate::modulator::FskModulator;
+    use crate::rtty;
+
+    #[test]
+    fn test_turns() {
+        let turns = turns("HELLO THIS IS\nJOHN GA HI JOHN  GA\n\nBYE GA OR SK SKSK");
+        let lines: Vec<String> = turns.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "HELLO THIS IS JOHN (go ahead)",
+                "HI JOHN (go ahead)",
+                "BYE (go ahead)",
+                "OR (stop keying)",
+                "(stop keying)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        // TDDs drop the carrier between characters
+        let mut modulator = FskModulator::new(CHANNEL.mark, CHANNEL.space, CHANNEL.baud, 8_000.0);
+        let mut samples = Vec::new();
+        for code in baudot::encode("HI GA") {
+            modulator.hold(1, 2.0, &mut samples);
+            modulator.hold(0, 1.0, &mut samples);
+            for i in 0..5 {
+                modulator.hold((code >> i) & 1, 1.0, &mut samples);
+            }
+            modulator.hold(1, 1.5, &mut samples);
+            samples.extend(std::iter::repeat_n(0, 500));
+        }
+
+        let text = rtty::decode(&samples, CHANNEL, 8_000.0, false);
+        assert_eq!(turns(&text)[0].to_string(), "HI (go ahead)");
+    }
+}
@@ -0,0 +1,293 @@
+use crate::carrier;
+use crate::demodulator::Demodulator;
+use crate::goertzel::GoertzelPair;
+use crate::spectrogram::TIME_STEPS;
+use crate::standard::Channel;
+use std::fmt::Write as _;
+
+/// How wide each decision is drawn, so single bits can be told apart
+const BIT_WIDTH: f64 = 4.0;
+/// Room to the left of the lanes for their labels and scales
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 10.0;
+/// Short recordings are drawn this wide anyway, for the title to fit
+const MIN_WIDTH: f64 = 520.0;
+/// The height of each lane from the top: tone levels, carrier detect, soft decisions and bits
+const LANES: [(&str, f64); 4] = [
+    ("dBFS", 200.0),
+    ("Carrier", 24.0),
+    ("Soft", 100.0),
+    ("Bits", 32.0),
+];
+const TITLE: f64 = 30.0;
+const GAP: f64 = 14.0;
+const AXIS: f64 = 24.0;
+/// Where the level lane bottoms out, in dBFS
+const FLOOR_DB: f64 = -90.0;
+/// Time scale labels are at least this far apart
+const MIN_LABEL_SPACING: f64 = 60.0;
+
+const MARK: &str = "#1f6fd1";
+const SPACE: &str = "#e07b00";
+const CARRIER: &str = "#2ca02c";
+const DECISION: &str = "#444";
+const GRID: &str = "#ddd";
+const TICK: &str = "#999";
+
+/// What the decoder made of each block of a recording, for plotting where a decode went wrong:
+/// the level of each tone, whether the carrier was heard, and the decision taken
+#[derive(Debug)]
+pub struct Timeline {
+    channel: Channel,
+    /// Seconds each block lasts
+    period: f64,
+    /// Mark and space levels in dBFS
+    mark: Vec<f64>,
+    space: Vec<f64>,
+    carrier: Vec<bool>,
+    /// Soft decisions between -1.0 (space) and 1.0 (mark), a bit being a mark from 0.0 up
+    soft: Vec<f64>,
+}
+
+impl Timeline {
+    /// Measures `samples` a `block_size` block at a time, as `demodulator` decides them
+    pub fn measure(
+        samples: &[i16],
+        channel: Channel,
+        sampling_rate: f64,
+        demodulator: &mut Demodulator,
+        block_size: usize,
+    ) -> Self {
+        let filters = GoertzelPair::new(block_size, [channel.mark, channel.space], sampling_rate);
+        let mut detector = carrier::Detector::new(channel, sampling_rate);
+        let mut timeline = Self {
+            channel,
+            period: block_size as f64 / sampling_rate,
+            mark: Vec::new(),
+            space: Vec::new(),
+            carrier: Vec::new(),
+            soft: demodulator.soft_bits(samples),
+        };
+        for block in samples.chunks(block_size) {
+            // A full scale tone's magnitude is half the block length times full scale
+            let db = |energy: f64| {
+                let amplitude = 2.0 * energy.sqrt() / block.len() as f64 / f64::from(i16::MAX);
+                (20.0 * amplitude.log10()).max(FLOOR_DB)
+            };
+            let [mark, space] = filters.energies(block);
+            timeline.mark.push(db(mark));
+            timeline.space.push(db(space));
+            timeline.carrier.push(detector.push(block));
+        }
+        timeline
+    }
+
+    /// Draws the timeline as an SVG image, each lane under the last and time across
+    pub fn svg(&self) -> String {
+        let blocks = self.soft.len();
+        let plot_width = blocks as f64 * BIT_WIDTH;
+        let width = (LEFT + plot_width + RIGHT).max(MIN_WIDTH);
+        let lanes_height: f64 = LANES.iter().map(|(_, height)| height + GAP).sum();
+        let height = TITLE + lanes_height + AXIS;
+        let x = |block: usize| LEFT + block as f64 * BIT_WIDTH;
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w:.0}" height="{h:.0}" viewBox="0 0 {w:.0} {h:.0}" font-family="sans-serif" font-size="11">"#,
+            w = width,
+            h = height
+        );
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" fill="white"/><text x="4" y="18" font-size="13"><tspan fill="{}">mark {} Hz</tspan>, <tspan fill="{}">space {} Hz</tspan>, {} baud, {} decisions of {:.2} ms</text>"#,
+            MARK,
+            self.channel.mark,
+            SPACE,
+            self.channel.space,
+            self.channel.baud,
+            blocks,
+            self.period * 1000.0
+        );
+
+        let mut top = TITLE;
+        for &(label, lane) in &LANES {
+            let _ = writeln!(
+                svg,
+                r#"<text x="4" y="{:.1}">{}</text><rect x="{}" y="{}" width="{:.0}" height="{}" fill="none" stroke="{}"/>"#,
+                top + lane / 2.0 + 4.0,
+                label,
+                LEFT,
+                top,
+                plot_width,
+                lane,
+                GRID
+            );
+            match label {
+                "dBFS" => {
+                    let y = |db: f64| top + lane * db.max(FLOOR_DB) / FLOOR_DB;
+                    for db in [-30.0, -60.0] {
+                        let _ = writeln!(
+                            svg,
+                            r#"<line x1="{}" y1="{y:.1}" x2="{:.0}" y2="{y:.1}" stroke="{}"/><text x="{}" y="{:.1}" text-anchor="end">{}</text>"#,
+                            LEFT,
+                            LEFT + plot_width,
+                            GRID,
+                            LEFT - 4.0,
+                            y(db) + 4.0,
+                            db,
+                            y = y(db)
+                        );
+                    }
+                    for (levels, colour) in [(&self.space, SPACE), (&self.mark, MARK)] {
+                        let points = levels
+                            .iter()
+                            .enumerate()
+                            .map(|(i, &db)| format!("{:.1},{:.1}", x(i) + BIT_WIDTH / 2.0, y(db)));
+                        polyline(&mut svg, points, colour);
+                    }
+                }
+                "Carrier" => {
+                    let mut i = 0;
+                    while i < blocks.min(self.carrier.len()) {
+                        let run = self.carrier[i..]
+                            .iter()
+                            .take_while(|&&c| c == self.carrier[i]);
+                        let length = run.count();
+                        if self.carrier[i] {
+                            let _ = writeln!(
+                                svg,
+                                r#"<rect x="{:.1}" y="{}" width="{:.1}" height="{}" fill="{}"/>"#,
+                                x(i),
+                                top + 2.0,
+                                length as f64 * BIT_WIDTH,
+                                lane - 4.0,
+                                CARRIER
+                            );
+                        }
+                        i += length;
+                    }
+                }
+                "Soft" => {
+                    let y = |soft: f64| top + lane * (1.0 - soft.clamp(-1.0, 1.0)) / 2.0;
+                    let _ = writeln!(
+                        svg,
+                        r#"<line x1="{}" y1="{y:.1}" x2="{:.0}" y2="{y:.1}" stroke="{}"/>"#,
+                        LEFT,
+                        LEFT + plot_width,
+                        GRID,
+                        y = y(0.0)
+                    );
+                    let points =
+                        self.soft.iter().enumerate().map(|(i, &soft)| {
+                            format!("{:.1},{:.1}", x(i) + BIT_WIDTH / 2.0, y(soft))
+                        });
+                    polyline(&mut svg, points, DECISION);
+                }
+                _ => {
+                    // Each decision held across its block, mark high and space low
+                    let points = self.soft.iter().enumerate().flat_map(|(i, &soft)| {
+                        let y = if soft >= 0.0 {
+                            top + 4.0
+                        } else {
+                            top + lane - 4.0
+                        };
+                        vec![
+                            format!("{:.1},{:.1}", x(i), y),
+                            format!("{:.1},{:.1}", x(i + 1), y),
+                        ]
+                    });
+                    polyline(&mut svg, points, DECISION);
+                }
+            }
+            top += lane + GAP;
+        }
+
+        // The time scale under the lanes
+        let step = TIME_STEPS
+            .iter()
+            .copied()
+            .find(|step| step / self.period * BIT_WIDTH >= MIN_LABEL_SPACING)
+            .unwrap_or(TIME_STEPS[TIME_STEPS.len() - 1]);
+        let mut tick = 0;
+        loop {
+            let time = tick as f64 * step;
+            let tick_x = LEFT + time / self.period * BIT_WIDTH;
+            if tick_x > LEFT + plot_width {
+                break;
+            }
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{x:.1}" y1="{}" x2="{x:.1}" y2="{}" stroke="{}"/><text x="{x:.1}" y="{:.1}" text-anchor="middle">{}s</text>"#,
+                TITLE,
+                top,
+                TICK,
+                top + 12.0,
+                if step < 1.0 {
+                    format!("{:.1}", time)
+                } else {
+                    format!("{:.0}", time)
+                },
+                x = tick_x
+            );
+            tick += 1;
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn polyline<I: Iterator<Item = String>>(svg: &mut String, points: I, colour: &str) {
+    let points: Vec<String> = points.collect();
+    let _ = writeln!(
+        svg,
+        r#"<polyline fill="none" stroke="{}" stroke-width="1" points="{}"/>"#,
+        colour,
+        points.join(" ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framing::frame;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+
+    const SAMPLING_RATE: f64 = 48_000.0;
+
+    #[test]
+    fn test_timeline() {
+        let channel = Standard::Bell103.answer();
+        let mut modulator =
+            FskModulator::new(channel.mark, channel.space, channel.baud, SAMPLING_RATE);
+        let mut samples = vec![0; 160 * 40];
+        modulator.hold(1, 40.0, &mut samples);
+        modulator.modulate(&frame(b"U"), &mut samples);
+        modulator.hold(1, 40.0, &mut samples);
+        samples.extend(vec![0; 160 * 40]);
+        let mut demodulator = Demodulator::new(160, channel.mark, channel.space, SAMPLING_RATE);
+        let timeline = Timeline::measure(&samples, channel, SAMPLING_RATE, &mut demodulator, 160);
+
+        assert_eq!(timeline.period, 160.0 / SAMPLING_RATE);
+        assert_eq!(timeline.soft.len(), 170);
+        assert_eq!(timeline.mark.len(), 170);
+        // Silence at the floor, then the idle mark well over the space tone
+        assert_eq!(timeline.mark[0], FLOOR_DB);
+        assert!(timeline.mark[60] > timeline.space[60] + 5.0);
+        // The carrier is heard a while into the idle mark and lost a while into the silence
+        assert!(!timeline.carrier[45] && timeline.carrier[70] && timeline.carrier[130]);
+        assert!(!timeline.carrier[169]);
+        // U is 0x55, sent least significant bit first between its start and stop bits
+        let bits: Vec<u8> = timeline.soft[80..90]
+            .iter()
+            .map(|&soft| u8::from(soft >= 0.0))
+            .collect();
+        assert_eq!(bits, [0, 1, 0, 1, 0, 1, 0, 1, 0, 1]);
+
+        let svg = timeline.svg();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="760" "#));
+        assert!(svg.contains("mark 2225 Hz"));
+        assert_eq!(svg.matches("<polyline").count(), 4);
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
@@ -0,0 +1,352 @@
+use crate::events::{Event, Sink};
+use crate::spectrum;
+use crate::standard::Channel;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Waterfall rows drawn each second, each of them the spectrum of the latest audio
+const ROWS_PER_SECOND: f64 = 10.0;
+/// Rows kept for a tall terminal
+const MAX_ROWS: usize = 200;
+/// Decoded text kept for the text pane, in characters
+const MAX_TEXT: usize = 16_384;
+/// How many dB below the loudest recent bin the waterfall fades to black
+const RANGE_DB: f64 = 50.0;
+/// How fast the loudest level the waterfall is scaled to falls back, in dB a row
+const PEAK_DECAY_DB: f64 = 0.5;
+/// 256-colour palette from quiet to loud: black, blues, cyan, green, yellow and red
+const PALETTE: [u8; 16] = [
+    16, 17, 18, 19, 20, 21, 27, 33, 39, 45, 51, 48, 82, 226, 208, 196,
+];
+/// Terminal size used when it can't be asked
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+/// A full screen view of a stream for tuning and troubleshooting it, a waterfall of the band
+/// around the channel's tones with the carrier and SNR over it and the decoded text below.
+/// It's drawn with ANSI escapes as the decoder observes each block of audio, and the events
+/// decoded from it are taken as a [`Sink`].
+#[derive(Clone)]
+pub struct Tui {
+    screen: Arc<Mutex<Screen>>,
+}
+
+struct Screen {
+    title: String,
+    channel: Channel,
+    sampling_rate: f64,
+    /// Lowest and highest frequency shown
+    band: (f64, f64),
+    /// The latest audio, one spectrum's worth
+    recent: VecDeque<i16>,
+    fft_len: usize,
+    /// Samples until the next row
+    until_row: usize,
+    /// Rows of power in dB of each bin in the band, the newest first
+    rows: VecDeque<Vec<f64>>,
+    peak: f64,
+    carrier: bool,
+    snr: Option<f64>,
+    characters: u64,
+    text: String,
+}
+
+impl Tui {
+    /// A view of `channel` sampled at `sampling_rate`, headed by `title`
+    pub fn new(title: &str, channel: Channel, sampling_rate: f64) -> Self {
+        let low = channel.mark.min(channel.space);
+        let high = channel.mark.max(channel.space);
+        let margin = (high - low).max(channel.baud);
+        let band = (
+            (low - margin).max(0.0),
+            (high + margin).min(sampling_rate / 2.0),
+        );
+        let fft_len = ((sampling_rate / ROWS_PER_SECOND) as usize).next_power_of_two();
+        Self {
+            screen: Arc::new(Mutex::new(Screen {
+                title: title.to_string(),
+                channel,
+                sampling_rate,
+                band,
+                recent: VecDeque::with_capacity(fft_len),
+                fft_len,
+                until_row: fft_len,
+                rows: VecDeque::new(),
+                peak: f64::NEG_INFINITY,
+                carrier: false,
+                snr: None,
+                characters: 0,
+                text: String::new(),
+            })),
+        }
+    }
+
+    /// Takes the next block of audio, redrawing the terminal once there's a new row
+    pub fn observe(&self, block: &[i16]) {
+        let mut screen = self.screen.lock().unwrap();
+        if screen.push(block) {
+            let (width, height) = size();
+            let frame = screen.render(width, height);
+            let mut stdout = io::stdout().lock();
+            // A terminal that's gone away just isn't drawn on
+            let _ = stdout
+                .write_all(frame.as_bytes())
+                .and_then(|_| stdout.flush());
+        }
+    }
+}
+
+impl Screen {
+    /// Takes audio, returning whether it's made a new row
+    fn push(&mut self, block: &[i16]) -> bool {
+        let mut added = false;
+        for &sample in block {
+            if self.recent.len() == self.fft_len {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(sample);
+            self.until_row -= 1;
+            if self.until_row == 0 {
+                self.until_row = (self.sampling_rate / ROWS_PER_SECOND).round() as usize;
+                self.add_row();
+                added = true;
+            }
+        }
+        added
+    }
+
+    fn add_row(&mut self) {
+        let samples: Vec<i16> = self.recent.iter().copied().collect();
+        let spectrum = spectrum::power_spectrum(&samples);
+        let (low, high) = self.bins();
+        let row: Vec<f64> = spectrum[low..=high]
+            .iter()
+            .map(|&power| 10.0 * power.log10())
+            .collect();
+        let loudest = row.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        self.peak = loudest.max(self.peak - PEAK_DECAY_DB);
+        self.rows.push_front(row);
+        self.rows.truncate(MAX_ROWS);
+    }
+
+    /// The spectrum bins of the band's edges
+    fn bins(&self) -> (usize, usize) {
+        let bin_hz = self.sampling_rate / self.fft_len as f64;
+        let high = ((self.band.1 / bin_hz).round() as usize).min(self.fft_len / 2 - 1);
+        ((self.band.0 / bin_hz).round() as usize, high)
+    }
+
+    /// The column of `width` showing `hz`
+    fn column(&self, hz: f64, width: usize) -> usize {
+        let (low, high) = self.band;
+        let column = (hz - low) / (high - low) * (width - 1) as f64;
+        (column.round().max(0.0) as usize).min(width - 1)
+    }
+
+    /// A frame filling a terminal of `width` columns and `height` lines, from its top left
+    fn render(&self, width: usize, height: usize) -> String {
+        let (width, height) = (width.max(20), height.max(8));
+        let mut frame = String::from("\x1b[H");
+        let snr = match self.snr {
+            Some(snr) => format!("{:.1} dB", snr),
+            None => "-".to_string(),
+        };
+        // A lamp for the carrier, then as much of the rest as fits
+        frame.push_str(if self.carrier {
+            "\x1b[1;32m*\x1b[0m "
+        } else {
+            "- "
+        });
+        let status = format!(
+            "{} | {}/{} Hz | Carrier {} | SNR {} | {} characters",
+            self.title,
+            self.channel.mark,
+            self.channel.space,
+            if self.carrier { "on" } else { "off" },
+            snr,
+            self.characters
+        );
+        line(
+            &mut frame,
+            &status.chars().take(width - 2).collect::<String>(),
+        );
+
+        // The scale, with the mark and space tones under M and S
+        let mut scale = vec![' '; width];
+        let low = format!("{:.0} Hz", self.band.0);
+        let high = format!("{:.0} Hz", self.band.1);
+        for (i, c) in low.chars().enumerate() {
+            scale[i] = c;
+        }
+        for (i, c) in high.chars().enumerate() {
+            scale[width - high.len() + i] = c;
+        }
+        scale[self.column(self.channel.mark, width)] = 'M';
+        scale[self.column(self.channel.space, width)] = 'S';
+        line(&mut frame, &scale.iter().collect::<String>());
+
+        // The waterfall takes half of what's left and the text the rest beneath a rule
+        let waterfall = (height - 3) / 2;
+        for row in 0..waterfall {
+            match self.rows.get(row) {
+                Some(powers) => {
+                    // Each column is the loudest of the bins nearer it than its neighbours, as
+                    // the scale puts a tone in the column nearest it
+                    let per_column = (powers.len() - 1) as f64 / (width - 1) as f64;
+                    let bin = |edge: f64| (edge * per_column).round().max(0.0) as usize;
+                    for column in 0..width {
+                        let first = bin(column as f64 - 0.5);
+                        let last = bin(column as f64 + 0.5).clamp(first, powers.len() - 1);
+                        let spanned = &powers[first..=last];
+                        let db = spanned.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                        let level = ((db - self.peak + RANGE_DB) / RANGE_DB).clamp(0.0, 1.0);
+                        let shade = PALETTE[(level * (PALETTE.len() - 1) as f64).round() as usize];
+                        let _ = write!(frame, "\x1b[48;5;{}m ", shade);
+                    }
+                    frame.push_str("\x1b[0m\r\n");
+                }
+                None => line(&mut frame, ""),
+            }
+        }
+        line(&mut frame, &"-".repeat(width));
+        let pane = height - 3 - waterfall;
+        let lines = wrap(&self.text, width);
+        let shown = &lines[lines.len().saturating_sub(pane)..];
+        for row in 0..pane {
+            let text = shown.get(row).map_or("", String::as_str);
+            frame.push_str(text);
+            frame.push_str("\x1b[K");
+            if row + 1 < pane {
+                frame.push_str("\r\n");
+            }
+        }
+        // Clearing whatever the terminal showed below, before the first frame or a resize
+        frame.push_str("\x1b[J");
+        frame
+    }
+}
+
+/// Adds a line to a frame, clearing what was left of the last frame's
+fn line(frame: &mut String, text: &str) {
+    frame.push_str(text);
+    frame.push_str("\x1b[K\r\n");
+}
+
+/// Decoded text as lines of at most `width` characters, with line endings of any kind and no
+/// other control characters
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let characters: Vec<char> = paragraph.chars().filter(|c| !c.is_control()).collect();
+        if characters.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in characters.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
+
+impl Sink for Tui {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        let mut screen = self.screen.lock().unwrap();
+        match event {
+            Event::Text(text) => {
+                screen.characters += text.chars().count() as u64;
+                screen.text.push_str(text);
+                let excess = screen.text.chars().count().saturating_sub(MAX_TEXT);
+                if excess > 0 {
+                    let cut = screen.text.char_indices().nth(excess).unwrap().0;
+                    screen.text.drain(..cut);
+                }
+            }
+            Event::Carrier(present) => screen.carrier = *present,
+            Event::Snr(db) => screen.snr = Some(*db).filter(|db| db.is_finite()),
+            Event::Message(_) => {}
+        }
+        Ok(())
+    }
+}
+
+/// The terminal's columns and lines
+#[cfg(unix)]
+fn size() -> (usize, usize) {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+        && size.ws_col > 0
+    {
+        (usize::from(size.ws_col), usize::from(size.ws_row))
+    } else {
+        DEFAULT_SIZE
+    }
+}
+
+#[cfg(not(unix))]
+fn size() -> (usize, usize) {
+    let variable = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+    match (variable("COLUMNS"), variable("LINES")) {
+        (Some(columns), Some(lines)) => (columns, lines),
+        _ => DEFAULT_SIZE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::FskModulator;
+    use crate::standard::Standard;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(
+            wrap("Welcome\r\nlogin:\x07 abcdef", 5),
+            ["Welco", "me", "login", ": abc", "def"]
+        );
+        assert_eq!(wrap("\r\n", 5), ["", ""]);
+    }
+
+    #[test]
+    fn test_render() {
+        let channel = Standard::Bell103.answer();
+        let mut tui = Tui::new("Bell103", channel, 8_000.0);
+        let mut modulator = FskModulator::new(channel.mark, channel.space, channel.baud, 8_000.0);
+        let mut mark = Vec::new();
+        modulator.modulate(&[1; 150], &mut mark);
+        for event in &[
+            Event::Carrier(true),
+            Event::Snr(16.94),
+            Event::Text("Welcome\r\nlogin:"),
+        ] {
+            tui.take(event).unwrap();
+        }
+        let mut screen = tui.screen.lock().unwrap();
+        assert!(screen.push(&mark));
+        assert_eq!(screen.rows.len(), 4);
+
+        let frame = screen.render(40, 12);
+        let lines: Vec<&str> = frame.split("\r\n").collect();
+        assert_eq!(lines.len(), 12);
+        assert_eq!(
+            lines[0],
+            "\x1b[H\x1b[1;32m*\x1b[0m Bell103 | 2225/2025 Hz | Carrier on | \x1b[K"
+        );
+        assert!(screen
+            .render(80, 12)
+            .contains("| SNR 16.9 dB | 15 characters\x1b[K"));
+        // Bell 103's answer tones are 200 Hz apart, with 300 Hz either side of them
+        assert!(lines[1].starts_with("1725 Hz"));
+        assert!(lines[1].ends_with("2525 Hz\x1b[K"));
+        let (space, mark) = (lines[1].find('S').unwrap(), lines[1].find('M').unwrap());
+        assert_eq!((space, mark), (15, 24));
+        // The newest row is loudest at the mark tone
+        let row: Vec<&str> = lines[2].split("\x1b[48;5;").skip(1).collect();
+        assert_eq!(row.len(), 40);
+        assert_eq!(row[mark], "196m ");
+        assert_eq!(row[0], "16m ");
+        assert_eq!(lines[6], format!("{}\x1b[K", "-".repeat(40)));
+        assert_eq!(&lines[7..10], ["Welcome\x1b[K", "login:\x1b[K", "\x1b[K"]);
+    }
+}
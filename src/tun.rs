@@ -0,0 +1,133 @@
+use std::io::{self, Read, Write};
+
+/// A TUN interface, whose reads are the IP packets the kernel routes to it and whose writes
+/// deliver packets to the kernel as if they'd arrived on it. It's removed when the last handle
+/// to it is dropped.
+#[derive(Debug)]
+pub struct Tun {
+    #[cfg(all(feature = "tun", target_os = "linux"))]
+    file: std::fs::File,
+    pub name: String,
+}
+
+#[cfg(all(feature = "tun", target_os = "linux"))]
+impl Tun {
+    /// Creates the interface `name`, which can have `%d` in it for the kernel to number,
+    /// e.g. `bell%d`. It still needs an address and to be brought up, like any other.
+    pub fn open(name: &str) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        if name.len() >= sys::IFNAMSIZ {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` is longer than an interface name can be", name),
+            ));
+        }
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")?;
+        let mut request = sys::IfReq {
+            name: [0; sys::IFNAMSIZ],
+            flags: sys::IFF_TUN | sys::IFF_NO_PI,
+            padding: [0; 22],
+        };
+        request.name[..name.len()].copy_from_slice(name.as_bytes());
+        if unsafe { libc::ioctl(file.as_raw_fd(), sys::TUNSETIFF as _, &mut request) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let length = request.name.iter().position(|&b| b == 0).unwrap_or(0);
+        Ok(Self {
+            file,
+            name: String::from_utf8_lossy(&request.name[..length]).into_owned(),
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+            name: self.name.clone(),
+        })
+    }
+}
+
+#[cfg(not(all(feature = "tun", target_os = "linux")))]
+impl Tun {
+    pub fn open(_: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "This build can't create TUN interfaces, rebuild on Linux with `cargo build --features tun`",
+        ))
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        unreachable!("a Tun can't be opened without the tun feature")
+    }
+}
+
+#[cfg(all(feature = "tun", target_os = "linux"))]
+impl Read for Tun {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+#[cfg(all(feature = "tun", target_os = "linux"))]
+impl Write for Tun {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(not(all(feature = "tun", target_os = "linux")))]
+impl Read for Tun {
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        unreachable!("a Tun can't be opened without the tun feature")
+    }
+}
+
+#[cfg(not(all(feature = "tun", target_os = "linux")))]
+impl Write for Tun {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        unreachable!("a Tun can't be opened without the tun feature")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        unreachable!("a Tun can't be opened without the tun feature")
+    }
+}
+
+/// The parts of `linux/if_tun.h` and `net/if.h` that create an interface
+#[cfg(all(feature = "tun", target_os = "linux"))]
+mod sys {
+    pub const IFNAMSIZ: usize = 16;
+    pub const IFF_TUN: i16 = 0x0001;
+    pub const IFF_NO_PI: i16 = 0x1000;
+    /// `_IOW('T', 202, int)`
+    pub const TUNSETIFF: u32 = 1 << 30 | 4 << 16 | (b'T' as u32) << 8 | 202;
+
+    /// `struct ifreq` with the flags of its union
+    #[repr(C)]
+    pub struct IfReq {
+        pub name: [u8; IFNAMSIZ],
+        pub flags: i16,
+        pub padding: [u8; 22],
+    }
+
+    const _: () = assert!(std::mem::size_of::<IfReq>() == 40);
+}
+
+#[cfg(all(test, feature = "tun", target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tunsetiff() {
+        assert_eq!(sys::TUNSETIFF, 0x4004_54ca);
+        assert!(Tun::open("a_name_too_long_for_linux").is_err());
+    }
+}
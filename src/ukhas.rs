@@ -0,0 +1,259 @@
+use crate::demodulator::{Demodulator, TICKS_PER_BIT};
+use crate::framing::UartReceiver;
+use crate::spectrum;
+use std::fmt;
+
+/// Tones are tracked over windows of about this long, each overlapping the last by half
+const TRACK_WINDOW_SECS: f64 = 0.2;
+const MIN_TONE: f64 = 100.0;
+const MAX_TONE: f64 = 4000.0;
+/// Balloon transmitters shift anywhere from a few hundred Hz to around a kHz
+const MIN_SHIFT: f64 = 100.0;
+const MAX_SHIFT: f64 = 1500.0;
+/// The weaker tone must have at least this fraction of the stronger one's power
+const MIN_TONE_RATIO: f64 = 0.05;
+/// Windows whose centre estimates are smoothed together
+const CENTER_SMOOTHING: usize = 5;
+
+/// The CRC-16-CCITT used by UKHAS sentences: polynomial 0x1021, starting at 0xffff
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// A `$$CALLSIGN,id,time,latitude,longitude,altitude,...*CRC` telemetry sentence
+#[derive(Debug, PartialEq)]
+pub struct Sentence {
+    /// The text between the `$$` and the `*`, which the CRC covers
+    pub body: String,
+    pub crc_ok: bool,
+}
+
+impl Sentence {
+    /// Parses a sentence from the text after its `$$`
+    pub fn parse(text: &str) -> Option<Self> {
+        let (body, rest) = text.split_once('*')?;
+        let crc = rest
+            .get(..4)
+            .and_then(|hex| u16::from_str_radix(hex, 16).ok())?;
+        Some(Self {
+            body: body.to_string(),
+            crc_ok: crc16(body.as_bytes()) == crc,
+        })
+    }
+
+    pub fn fields(&self) -> Vec<&str> {
+        self.body.split(',').collect()
+    }
+}
+
+impl fmt::Display for Sentence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "$${}*{:04X} (CRC {})",
+            self.body,
+            crc16(self.body.as_bytes()),
+            if self.crc_ok { "OK" } else { "FAILED" }
+        )?;
+        let fields = self.fields();
+        let names = [
+            "Payload",
+            "Sentence",
+            "Time",
+            "Latitude",
+            "Longitude",
+            "Altitude",
+        ];
+        for (name, value) in names.iter().zip(&fields) {
+            writeln!(f, "  {}: {}", name, value)?;
+        }
+        if fields.len() > names.len() {
+            writeln!(f, "  Other: {}", fields[names.len()..].join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the mark and space tones in each window, returning `(low, high)` frequencies per
+/// half window. The shift is taken as the median over the recording since it stays fixed
+/// while the carrier drifts, and the centre is smoothed across neighbouring windows.
+fn track_tones(samples: &[i16], sampling_rate: f64, window: usize) -> Vec<Option<(f64, f64)>> {
+    let hop = window / 2;
+    let bin_hz = sampling_rate / window as f64;
+    let bins = |hz: f64| (hz / bin_hz) as usize;
+    let pairs: Vec<Option<(f64, f64)>> = (0..samples.len().saturating_sub(window) / hop + 1)
+        .map(|i| {
+            let block = samples.get(i * hop..i * hop + window)?;
+            let spectrum = spectrum::power_spectrum(block);
+            let range = bins(MIN_TONE)..bins(MAX_TONE.min(sampling_rate / 2.0)).min(spectrum.len());
+            let strongest = |candidates: &mut dyn Iterator<Item = usize>| {
+                candidates.max_by(|&a, &b| spectrum[a].partial_cmp(&spectrum[b]).unwrap())
+            };
+            let first = strongest(&mut range.clone())?;
+            let second = strongest(&mut range.filter(|&b| {
+                let distance = (b as f64 - first as f64).abs();
+                distance >= MIN_SHIFT / bin_hz && distance <= MAX_SHIFT / bin_hz
+            }))?;
+            if spectrum[second] < MIN_TONE_RATIO * spectrum[first] || spectrum[first] == 0.0 {
+                return None;
+            }
+            let first = spectrum::interpolate_peak(&spectrum, first) * bin_hz;
+            let second = spectrum::interpolate_peak(&spectrum, second) * bin_hz;
+            Some((first.min(second), first.max(second)))
+        })
+        .collect();
+
+    let mut shifts: Vec<f64> = pairs
+        .iter()
+        .flatten()
+        .map(|(low, high)| high - low)
+        .collect();
+    if shifts.is_empty() {
+        return pairs;
+    }
+    shifts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let shift = shifts[shifts.len() / 2];
+    let centers: Vec<Option<f64>> = pairs
+        .iter()
+        .map(|pair| {
+            pair.filter(|(low, high)| (high - low - shift).abs() <= 3.0 * bin_hz)
+                .map(|(low, high)| (low + high) / 2.0)
+        })
+        .collect();
+    (0..centers.len())
+        .map(|i| {
+            let low = i.saturating_sub(CENTER_SMOOTHING / 2);
+            let high = (i + CENTER_SMOOTHING / 2 + 1).min(centers.len());
+            let mut nearby: Vec<f64> = centers[low..high].iter().flatten().copied().collect();
+            if nearby.is_empty() {
+                return None;
+            }
+            nearby.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let center = nearby[nearby.len() / 2];
+            Some((center - shift / 2.0, center + shift / 2.0))
+        })
+        .collect()
+}
+
+/// Decodes balloon RTTY, tracking the tones as they drift. Each stretch of the recording is
+/// demodulated at the tones found there, with the higher tone as mark unless `reverse`.
+pub fn decode_text(
+    samples: &[i16],
+    sampling_rate: f64,
+    baud: f64,
+    data_bits: u32,
+    reverse: bool,
+) -> String {
+    let samples_per_bit = sampling_rate / baud;
+    let block_size = samples_per_bit.round() as usize;
+    let step = (block_size / TICKS_PER_BIT).max(1);
+    let window = ((TRACK_WINDOW_SECS * sampling_rate) as usize)
+        .next_power_of_two()
+        .max(2 * step);
+    // Keep each segment a whole number of decision steps
+    let hop = window / 2 / step * step;
+    let tones = track_tones(samples, sampling_rate, window);
+
+    let mut receiver = UartReceiver::new(samples_per_bit / step as f64, data_bits);
+    let mut text = Vec::new();
+    for (i, pair) in tones.iter().enumerate() {
+        // Segments are centred on their tracking window
+        let start = if i == 0 { 0 } else { i * hop + hop / 2 };
+        let end = if i + 1 == tones.len() {
+            samples.len()
+        } else {
+            (i + 1) * hop + hop / 2
+        };
+        let decisions = end.saturating_sub(start) / step;
+        let soft = match pair {
+            Some((low, high)) => {
+                let (mark, space) = if reverse {
+                    (*low, *high)
+                } else {
+                    (*high, *low)
+                };
+                let mut demodulator = Demodulator::new(block_size, mark, space, sampling_rate);
+                let segment =
+                    &samples[start.min(samples.len())..(end + block_size).min(samples.len())];
+                demodulator.sliding_soft_bits(segment, step)
+            }
+            None => Vec::new(),
+        };
+        let soft = soft
+            .into_iter()
+            .chain(std::iter::repeat(0.0))
+            .take(decisions);
+        text.extend(soft.filter_map(|s| receiver.push(s)).map(|r| r.byte));
+    }
+    text.iter().map(|&b| char::from(b & 0x7f)).collect()
+}
+
+/// Finds the sentences in decoded text
+pub fn sentences(text: &str) -> Vec<Sentence> {
+    text.split("$$")
+        .skip(1)
+        .filter_map(|s| Sentence::parse(s.trim_start_matches('$')))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulator::{Oscillator, AMPLITUDE};
+
+    const BODY: &str = "HABTEST,42,12:34:56,51.49826,-0.17830,31337,8,-12.5";
+
+    #[test]
+    fn test_sentence() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        let crc = crc16(BODY.as_bytes());
+        let sentence = Sentence::parse(&format!("{}*{:04X}\n", BODY, crc)).unwrap();
+        assert!(sentence.crc_ok);
+        assert_eq!(sentence.fields()[5], "31337");
+        assert!(sentence.to_string().contains("  Other: 8, -12.5\n"));
+        let corrupted = Sentence::parse(&format!("{}*{:04X}", BODY.replace('4', "5"), crc));
+        assert!(!corrupted.unwrap().crc_ok);
+    }
+
+    #[test]
+    fn test_decode_drifting() {
+        let sampling_rate = 8_000.0;
+        let baud = 50.0;
+        let line = format!("$$$${}*{:04X}\n", BODY, crc16(BODY.as_bytes()));
+        let mut bits = vec![1; 25];
+        for _ in 0..2 {
+            for byte in line.bytes() {
+                bits.push(0);
+                bits.extend((0..7).map(|i| (byte >> i) & 1));
+                bits.extend(&[1, 1]);
+            }
+        }
+        bits.extend(vec![1; 25]);
+
+        // The carrier drifts 300 Hz over the transmission with a 425 Hz shift
+        let total = (bits.len() as f64 * sampling_rate / baud) as usize;
+        let mut oscillator = Oscillator::new(1000.0, sampling_rate);
+        let samples: Vec<i16> = (0..total)
+            .map(|i| {
+                let bit = bits[(i as f64 * baud / sampling_rate) as usize];
+                let space = 1000.0 + 300.0 * i as f64 / total as f64;
+                let frequency = if bit == 1 { space + 425.0 } else { space };
+                oscillator.set_frequency(frequency);
+                (0.5 * AMPLITUDE * oscillator.next_sample()) as i16
+            })
+            .collect();
+
+        let text = decode_text(&samples, sampling_rate, baud, 7, false);
+        let found = sentences(&text);
+        assert_eq!(found.len(), 2, "{:?}", text);
+        assert!(found.iter().all(|s| s.crc_ok && s.body == BODY));
+    }
+}
@@ -0,0 +1,52 @@
+/// Finds the offset of `regenerated` within `original` with the highest normalized
+/// cross-correlation, returning the score (1.0 is a perfect match) and the lag in bits
+pub fn cross_correlate(original: &[f64], regenerated: &[f64]) -> (f64, usize) {
+    let energy: f64 = regenerated.iter().map(|v| v * v).sum();
+    if regenerated.is_empty() || original.len() < regenerated.len() || energy == 0.0 {
+        return (0.0, 0);
+    }
+
+    // Running energy of the original over the window currently being compared
+    let mut window: f64 = original[..regenerated.len()].iter().map(|v| v * v).sum();
+    let mut best = (0.0, 0);
+    for lag in 0..=original.len() - regenerated.len() {
+        if lag > 0 {
+            let (out, next) = (original[lag - 1], original[lag + regenerated.len() - 1]);
+            window += next * next - out * out;
+        }
+        if window > 0.0 {
+            let dot: f64 = original[lag..]
+                .iter()
+                .zip(regenerated)
+                .map(|(a, b)| a * b)
+                .sum();
+            let score = dot / (window * energy).sqrt();
+            if score > best.0 {
+                best = (score, lag);
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cross_correlate() {
+        let regenerated = [1.0, -1.0, -1.0, 1.0, -1.0, 1.0];
+        let mut original = vec![0.1, -0.2, 0.0];
+        original.extend(&regenerated);
+        original.push(1.0);
+
+        let (score, lag) = cross_correlate(&original, &regenerated);
+        assert!((score - 1.0).abs() < 1e-9);
+        assert_eq!(lag, 3);
+    }
+
+    #[test]
+    fn test_cross_correlate_empty() {
+        assert_eq!(cross_correlate(&[1.0, -1.0], &[]), (0.0, 0));
+    }
+}
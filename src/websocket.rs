@@ -0,0 +1,236 @@
+use crate::events::{Event, Sink};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Appended to a client's key before hashing it to accept the connection, from RFC 6455
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// A client that takes longer than this to read what's sent is dropped, rather than holding up
+/// the decoding
+const SEND_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A WebSocket server sending text messages to every client connected, with nothing read back
+#[derive(Clone)]
+pub struct Server {
+    address: SocketAddr,
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+impl Server {
+    /// Listens on `host:port`, or all interfaces for `:port`, accepting clients in the
+    /// background
+    pub fn listen(address: &str) -> io::Result<Self> {
+        let address = match address.strip_prefix(':') {
+            Some(port) => format!("0.0.0.0:{}", port),
+            None => address.to_string(),
+        };
+        let listener = TcpListener::bind(address)?;
+        let server = Self {
+            address: listener.local_addr()?,
+            clients: Arc::default(),
+        };
+        let clients = Arc::clone(&server.clients);
+        thread::spawn(move || {
+            for mut client in listener.incoming().flatten() {
+                let clients = Arc::clone(&clients);
+                thread::spawn(move || {
+                    if handshake(&mut client).is_ok() {
+                        client.set_write_timeout(Some(SEND_TIMEOUT)).unwrap();
+                        clients.lock().unwrap().push(client);
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends a text message to the clients, dropping the ones that have gone
+    pub fn send(&self, text: &str) {
+        let frame = frame(text);
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(&frame).is_ok());
+    }
+}
+
+impl Sink for Server {
+    fn take(&mut self, event: &Event) -> io::Result<()> {
+        self.send(&event.to_json());
+        Ok(())
+    }
+}
+
+/// Reads a client's upgrade request and accepts it, or turns away anything else
+fn handshake(client: &mut TcpStream) -> io::Result<()> {
+    client.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut key = None;
+    for line in BufReader::new(&*client).lines() {
+        let line = line?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    match key {
+        Some(key) => write!(
+            client,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(&key)
+        ),
+        None => {
+            client.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a WebSocket request",
+            ))
+        }
+    }
+}
+
+/// The `Sec-WebSocket-Accept` answering a client's `Sec-WebSocket-Key`
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+/// A single unmasked text frame, as a server sends them
+fn frame(text: &str) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match text.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= 0xffff => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let next = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = next;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(h) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | u32::from(byte) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_accept_key() {
+        // The example handshake from RFC 6455
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+        assert_eq!(base64(b"hi"), "aGk=");
+        assert_eq!(frame("hi"), [0x81, 2, b'h', b'i']);
+        assert_eq!(frame(&"x".repeat(300))[..4], [0x81, 126, 1, 44]);
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::listen("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(server.local_address()).unwrap();
+        client
+            .write_all(
+                b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                  Connection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let expected = "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                        Connection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+        let mut response = vec![0; expected.len()];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(String::from_utf8(response).unwrap(), expected);
+
+        while server.clients.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        server.send("hi");
+        let mut message = [0; 4];
+        client.read_exact(&mut message).unwrap();
+        assert_eq!(message, [0x81, 2, b'h', b'i']);
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Starts a 128 byte block
+pub const SOH: u8 = 0x01;
+/// Starts a 1024 byte block, from XMODEM-1K
+pub const STX: u8 = 0x02;
+/// Ends the transfer
+pub const EOT: u8 = 0x04;
+/// Acknowledges a good block
+pub const ACK: u8 = 0x06;
+/// Asks for a block again, and starts a checksum transfer
+pub const NAK: u8 = 0x15;
+/// Two of these in a row cancel the transfer
+pub const CAN: u8 = 0x18;
+/// Pads out the final block
+pub const SUB: u8 = 0x1a;
+/// Starts a CRC transfer
+pub const CRC_REQUEST: u8 = b'C';
+
+/// The CRC-16 used by XMODEM-CRC: polynomial 0x1021, starting at 0
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// The original XMODEM check byte, the sum of the data bytes
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// How each block is checked, as agreed when the receiver starts the transfer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Check {
+    Checksum,
+    Crc,
+}
+
+impl Check {
+    fn len(self) -> usize {
+        match self {
+            Check::Checksum => 1,
+            Check::Crc => 2,
+        }
+    }
+
+    fn verify(self, data: &[u8], check: &[u8]) -> bool {
+        match self {
+            Check::Checksum => check == [checksum(data)],
+            Check::Crc => check == crc16(data).to_be_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Problem {
+    /// The block failed its checksum or CRC, so the receiver would have asked for it again
+    BadCheck,
+    /// Blocks were skipped, e.g. when a header was lost to noise
+    OutOfSequence { expected: u8 },
+    /// The recording ended partway through the block
+    Truncated,
+}
+
+/// A block that couldn't be accepted, at its offset into the byte stream
+#[derive(Debug, PartialEq)]
+pub struct BlockError {
+    pub offset: usize,
+    pub number: u8,
+    pub problem: Problem,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Block {} at byte {}: ", self.number, self.offset)?;
+        match self.problem {
+            Problem::BadCheck => write!(f, "check failed"),
+            Problem::OutOfSequence { expected } => {
+                write!(f, "out of sequence, expected block {}", expected)
+            }
+            Problem::Truncated => write!(f, "truncated"),
+        }
+    }
+}
+
+/// A file reassembled from the sending side of an XMODEM transfer
+#[derive(Debug, Default, PartialEq)]
+pub struct Transfer {
+    pub data: Vec<u8>,
+    pub check: Option<Check>,
+    /// Blocks accepted into the file
+    pub blocks: usize,
+    /// Copies of blocks already accepted, resent because an ACK was lost
+    pub duplicates: usize,
+    pub errors: Vec<BlockError>,
+    /// Whether the sender finished with EOT
+    pub complete: bool,
+    pub cancelled: bool,
+    /// File name and length from a YMODEM header block
+    pub name: Option<String>,
+    pub size: Option<usize>,
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.name, self.check) {
+            (Some(name), _) => write!(f, "YMODEM \"{}\"", name)?,
+            (None, Some(Check::Crc)) => write!(f, "XMODEM-CRC")?,
+            (None, _) => write!(f, "XMODEM")?,
+        }
+        write!(
+            f,
+            ": {} blocks ({} bytes), {} duplicates, {} block errors",
+            self.blocks,
+            self.data.len(),
+            self.duplicates,
+            self.errors.len()
+        )?;
+        if self.cancelled {
+            write!(f, ", cancelled")
+        } else if self.complete {
+            write!(f, ", ended with EOT")
+        } else {
+            write!(f, ", incomplete without EOT")
+        }
+    }
+}
+
+/// Reassembles a file from the bytes sent by an XMODEM sender. Bytes outside blocks, such
+/// as line noise while waiting for an ACK, are skipped, and a block only counts once its
+/// number and complement agree. The check mode is worked out from the first good block
+/// since the receiver's request for it is on the other channel. The padding of the final
+/// block is removed, using the length from the header block of a YMODEM transfer.
+pub fn receive(bytes: &[u8]) -> Transfer {
+    let mut transfer = Transfer::default();
+    let mut expected = 1u8;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            SOH | STX if i + 2 < bytes.len() && bytes[i + 1] == !bytes[i + 2] => {
+                let size = if bytes[i] == SOH { 128 } else { 1024 };
+                let number = bytes[i + 1];
+                let data = (i + 3)..(i + 3 + size);
+
+                // Try the agreed mode first, then either mode before one is agreed
+                let modes = match transfer.check {
+                    Some(check) => vec![check],
+                    None => vec![Check::Crc, Check::Checksum],
+                };
+                let passed = modes.iter().copied().find(|check| {
+                    let end = data.end + check.len();
+                    end <= bytes.len() && check.verify(&bytes[data.clone()], &bytes[data.end..end])
+                });
+                let check = passed.unwrap_or(modes[0]);
+                let end = data.end + check.len();
+                let error = |problem| BlockError {
+                    offset: i,
+                    number,
+                    problem,
+                };
+                if end > bytes.len() {
+                    transfer.errors.push(error(Problem::Truncated));
+                    break;
+                }
+                if passed.is_none() {
+                    transfer.errors.push(error(Problem::BadCheck));
+                    i = end;
+                    continue;
+                }
+
+                transfer.check = Some(check);
+                if number == 0 && transfer.blocks == 0 {
+                    // A YMODEM header ahead of the first data block
+                    if transfer.name.is_some() {
+                        transfer.duplicates += 1;
+                    } else {
+                        read_header(&bytes[data], &mut transfer);
+                    }
+                } else if number == expected.wrapping_sub(1) && transfer.blocks > 0 {
+                    transfer.duplicates += 1;
+                } else {
+                    if number != expected {
+                        transfer
+                            .errors
+                            .push(error(Problem::OutOfSequence { expected }));
+                    }
+                    transfer.data.extend(&bytes[data]);
+                    transfer.blocks += 1;
+                    expected = number.wrapping_add(1);
+                }
+                i = end;
+            }
+            EOT => {
+                transfer.complete = true;
+                break;
+            }
+            CAN if bytes.get(i + 1) == Some(&CAN) => {
+                transfer.cancelled = true;
+                break;
+            }
+            _ => i += 1,
+        }
+    }
+    match transfer.size {
+        Some(size) if size <= transfer.data.len() => transfer.data.truncate(size),
+        _ => {
+            while transfer.data.last() == Some(&SUB) {
+                transfer.data.pop();
+            }
+        }
+    }
+    transfer
+}
+
+/// Reads the NUL terminated file name and length, sent in decimal, from a YMODEM header
+fn read_header(block: &[u8], transfer: &mut Transfer) {
+    let mut fields = block.splitn(3, |&byte| byte == 0);
+    let name = fields.next().unwrap_or_default();
+    transfer.name = Some(String::from_utf8_lossy(name).into_owned());
+    transfer.size = fields
+        .next()
+        .and_then(|field| field.split(|&byte| byte == b' ').next())
+        .and_then(|size| std::str::from_utf8(size).ok()?.parse().ok());
+}
+
+/// The variant of XMODEM a sender uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Protocol {
+    /// 128 byte blocks with a checksum
+    Xmodem,
+    /// 128 byte blocks with a CRC
+    XmodemCrc,
+    /// 1024 byte blocks with a CRC
+    Xmodem1k,
+    /// XMODEM-1K with a header block giving the file name and length
+    Ymodem,
+}
+
+impl Protocol {
+    fn check(self) -> Check {
+        match self {
+            Protocol::Xmodem => Check::Checksum,
+            _ => Check::Crc,
+        }
+    }
+
+    fn block_size(self) -> usize {
+        match self {
+            Protocol::Xmodem | Protocol::XmodemCrc => 128,
+            Protocol::Xmodem1k | Protocol::Ymodem => 1024,
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xmodem" => Ok(Protocol::Xmodem),
+            "xmodem-crc" => Ok(Protocol::XmodemCrc),
+            "xmodem-1k" => Ok(Protocol::Xmodem1k),
+            "ymodem" => Ok(Protocol::Ymodem),
+            _ => Err(format!(
+                "unknown protocol `{}`, expected xmodem, xmodem-crc, xmodem-1k or ymodem",
+                s
+            )),
+        }
+    }
+}
+
+/// One turn of a transfer: what the sender transmits, then what the receiver replies
+#[derive(Debug, PartialEq)]
+pub struct Exchange {
+    pub packet: Vec<u8>,
+    pub reply: Vec<u8>,
+}
+
+/// Lays out the sending of `data` as the exchanges of a transfer where every block is
+/// acknowledged first time. The first exchange is the receiver asking for the transfer to
+/// start, with nothing sent ahead of it.
+pub fn send(name: &str, data: &[u8], protocol: Protocol) -> Vec<Exchange> {
+    let check = protocol.check();
+    let start = match check {
+        Check::Checksum => NAK,
+        Check::Crc => CRC_REQUEST,
+    };
+    let exchange = |packet, reply: &[u8]| Exchange {
+        packet,
+        reply: reply.to_vec(),
+    };
+    let mut exchanges = vec![exchange(vec![], &[start])];
+
+    if protocol == Protocol::Ymodem {
+        let mut header = name.as_bytes().to_vec();
+        header.push(0);
+        header.extend(data.len().to_string().as_bytes());
+        let size = if header.len() < 128 { 128 } else { 1024 };
+        let header = encode_block(0, &header, size, 0, check);
+        exchanges.push(exchange(header, &[ACK, CRC_REQUEST]));
+    }
+
+    for (i, chunk) in data.chunks(protocol.block_size()).enumerate() {
+        let number = (i + 1) as u8;
+        // A short final chunk goes in a 128 byte block rather than padding out a large one
+        let size = if chunk.len() > 128 { 1024 } else { 128 };
+        exchanges.push(exchange(
+            encode_block(number, chunk, size, SUB, check),
+            &[ACK],
+        ));
+    }
+
+    if protocol == Protocol::Ymodem {
+        // The first EOT is refused to be sure it wasn't noise, then an empty header ends
+        // the batch
+        exchanges.push(exchange(vec![EOT], &[NAK]));
+        exchanges.push(exchange(vec![EOT], &[ACK, CRC_REQUEST]));
+        exchanges.push(exchange(encode_block(0, &[], 128, 0, check), &[ACK]));
+    } else {
+        exchanges.push(exchange(vec![EOT], &[ACK]));
+    }
+    exchanges
+}
+
+/// Builds a block of `size` bytes, 128 or 1024, filling out short data with `padding`
+fn encode_block(number: u8, data: &[u8], size: usize, padding: u8, check: Check) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.resize(size, padding);
+    let mut block = vec![if size == 128 { SOH } else { STX }, number, !number];
+    block.extend(&padded);
+    match check {
+        Check::Checksum => block.push(checksum(&padded)),
+        Check::Crc => block.extend(&crc16(&padded).to_be_bytes()),
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(number: u8, data: &[u8], check: Check) -> Vec<u8> {
+        encode_block(number, data, 128, SUB, check)
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        let file: Vec<u8> = (0..2100).map(|i| (i * 7 % 256) as u8).collect();
+        for &protocol in &[
+            Protocol::Xmodem,
+            Protocol::XmodemCrc,
+            Protocol::Xmodem1k,
+            Protocol::Ymodem,
+        ] {
+            let exchanges = send("data.bin", &file, protocol);
+            let bytes: Vec<u8> = exchanges
+                .iter()
+                .flat_map(|exchange| exchange.packet.clone())
+                .collect();
+            let transfer = receive(&bytes);
+            assert_eq!(transfer.data, file, "{:?}", protocol);
+            assert!(transfer.errors.is_empty(), "{:?}", protocol);
+            assert!(transfer.complete, "{:?}", protocol);
+            assert_eq!(transfer.check, Some(protocol.check()), "{:?}", protocol);
+        }
+
+        let exchanges = send("data.bin", &file, Protocol::Ymodem);
+        assert_eq!(exchanges[0].reply, vec![CRC_REQUEST]);
+        assert_eq!(&exchanges[1].packet[3..16], b"data.bin\x002100");
+        // Two 1K blocks and a short one, then the EOT handshake and the empty header
+        let sizes: Vec<usize> = exchanges[2..]
+            .iter()
+            .map(|exchange| exchange.packet.len())
+            .collect();
+        assert_eq!(sizes, vec![1029, 1029, 133, 1, 1, 133]);
+        assert_eq!(
+            receive(&exchanges[1].packet).to_string(),
+            "YMODEM \"data.bin\": 0 blocks (0 bytes), 0 duplicates, 0 block errors, \
+             incomplete without EOT"
+        );
+    }
+
+    #[test]
+    fn test_receive_checksum_transfer() {
+        let file: Vec<u8> = (0..300).map(|i| (i % 251) as u8).collect();
+        let mut bytes = vec![0xff, 0x00];
+        for (i, chunk) in file.chunks(128).enumerate() {
+            bytes.extend(block(i as u8 + 1, chunk, Check::Checksum));
+            bytes.push(0x7f);
+        }
+        bytes.push(EOT);
+
+        let transfer = receive(&bytes);
+        assert_eq!(transfer.data, file);
+        assert_eq!(transfer.check, Some(Check::Checksum));
+        assert_eq!(transfer.blocks, 3);
+        assert!(transfer.errors.is_empty());
+        assert!(transfer.complete);
+        assert_eq!(
+            transfer.to_string(),
+            "XMODEM: 3 blocks (300 bytes), 0 duplicates, 0 block errors, ended with EOT"
+        );
+    }
+
+    #[test]
+    fn test_receive_with_errors() {
+        let mut bytes = block(1, b"first", Check::Crc);
+        // A corrupted copy is resent, then the ACK is lost and it comes again
+        let mut corrupted = block(2, b"second", Check::Crc);
+        corrupted[10] ^= 0x20;
+        bytes.extend(corrupted);
+        bytes.extend(block(2, b"second", Check::Crc));
+        bytes.extend(block(2, b"second", Check::Crc));
+        // Block 3 is lost entirely
+        bytes.extend(block(4, b"fourth", Check::Crc));
+        bytes.extend(&block(5, b"fifth", Check::Crc)[..50]);
+
+        let transfer = receive(&bytes);
+        assert_eq!(transfer.check, Some(Check::Crc));
+        assert_eq!(transfer.blocks, 3);
+        assert_eq!(transfer.duplicates, 1);
+        assert!(!transfer.complete);
+        let problems: Vec<(u8, Problem)> = transfer
+            .errors
+            .iter()
+            .map(|error| (error.number, error.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                (2, Problem::BadCheck),
+                (4, Problem::OutOfSequence { expected: 3 }),
+                (5, Problem::Truncated),
+            ]
+        );
+        assert_eq!(
+            transfer.errors[0].to_string(),
+            "Block 2 at byte 133: check failed"
+        );
+        let mut data = vec![];
+        for text in &[&b"first"[..], b"second", b"fourth"] {
+            let mut padded = text.to_vec();
+            padded.resize(128, SUB);
+            data.extend(padded);
+        }
+        while data.last() == Some(&SUB) {
+            data.pop();
+        }
+        assert_eq!(transfer.data, data);
+    }
+}
@@ -0,0 +1,81 @@
+// Decodes FSK modem audio in the browser with the demodulator built for WebAssembly, see
+// the README's WebAssembly section for building bell103_demodulator.wasm.
+
+/** Standards a decoder can be made for, as `bell103_create` numbers them */
+export const Standard = { bell103: 0, bell202: 1, v21: 2, v23: 3 };
+
+/** Loads the module, by default from beside this file */
+export async function load(url = new URL("bell103_demodulator.wasm", import.meta.url)) {
+  const { instance } = await WebAssembly.instantiateStreaming(fetch(url));
+  return new Bell103(instance.exports);
+}
+
+class Bell103 {
+  constructor(exports) {
+    this.exports = exports;
+  }
+
+  /**
+   * A decoder for samples at `sampleRate`, on the originating channel of `standard` when
+   * `origin` is true and the answering one otherwise
+   */
+  decoder({ sampleRate, standard = "bell103", origin = false }) {
+    return new Decoder(this.exports, sampleRate, standard, origin);
+  }
+}
+
+/** A streaming decoder, taking Float32Array chunks such as WebAudio's */
+export class Decoder {
+  constructor(exports, sampleRate, standard, origin) {
+    if (!(standard in Standard)) {
+      throw new Error(`unknown standard ${standard}, expected bell103, bell202, v21 or v23`);
+    }
+    this.exports = exports;
+    this.decoder = exports.bell103_create(sampleRate, Standard[standard], origin);
+    if (!this.decoder) {
+      throw new Error(`can't decode at ${sampleRate} Hz`);
+    }
+    this.buffer = 0;
+    this.capacity = 0;
+  }
+
+  /** Demodulates the next chunk of samples, returning the text decoded from it */
+  push(chunk) {
+    this.reserve(Math.max(chunk.length * 4, 256));
+    const { memory, bell103_feed_f32, bell103_read } = this.exports;
+    // Views are made afresh each time as growing the memory detaches the old ones
+    new Float32Array(memory.buffer, this.buffer, chunk.length).set(chunk);
+    let waiting = bell103_feed_f32(this.decoder, this.buffer, chunk.length);
+    let text = "";
+    while (waiting > 0) {
+      const count = bell103_read(this.decoder, this.buffer, Math.min(waiting, this.capacity));
+      for (const byte of new Uint8Array(memory.buffer, this.buffer, count)) {
+        text += String.fromCharCode(byte & 0x7f);
+      }
+      waiting -= count;
+    }
+    return text;
+  }
+
+  /** Frees the decoder's memory in the module, after which it can't be used */
+  free() {
+    this.exports.bell103_destroy(this.decoder);
+    this.exports.bell103_free(this.buffer, this.capacity);
+    this.decoder = 0;
+    this.buffer = 0;
+    this.capacity = 0;
+  }
+
+  /** Makes sure the buffer shared with the module holds at least `bytes` */
+  reserve(bytes) {
+    if (bytes <= this.capacity) {
+      return;
+    }
+    this.exports.bell103_free(this.buffer, this.capacity);
+    this.buffer = this.exports.bell103_alloc(bytes);
+    if (!this.buffer) {
+      throw new Error("the module is out of memory");
+    }
+    this.capacity = bytes;
+  }
+}